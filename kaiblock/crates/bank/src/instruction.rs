@@ -2,25 +2,53 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use crate::state::Pubkey;
 
 
+// Accounts are listed in the order each instruction expects them. Signers come
+// from the instruction's accounts, so an authority that has to sign is passed
+// after them.
 #[derive(Debug, BorshSerialize, BorshDeserialize, PartialEq, Clone)]
 pub enum BankInstruction{
-	// initialize a mint: decimals, mint_authority
+	// initialize a mint: decimals, mint_authority, freeze_authority
+	// accounts: mint
 	InitMint{
 		decimals: u8,
 		mint_authority: Option<Pubkey>,
+		freeze_authority: Option<Pubkey>,
 	},
 
 	// initialize token account: owner pubkey
+	// accounts: token_account, mint
 	InitAccount{owner: Pubkey},
 
 
-	// transfer smount from source_account to dest_account
+	// transfer amount from source_account to dest_account (owner signs)
+	// accounts: source, dest
 	Transfer{amount: u128},
 
 	// Mint tokens to a token account (only mint_authority)
+	// accounts: mint, dest
 	MintTo{amount: u128},
 
 	// Burn tokens from token account (owner or delegate)
-
+	// accounts: token_account, mint
 	Burn{amount: u128},
-}
\ No newline at end of file
+
+	// let `delegate` move up to amount from the account, replacing any earlier approval (owner signs)
+	// accounts: token_account
+	Approve{delegate: Pubkey, amount: u128},
+
+	// withdraw the account's approval (owner signs)
+	// accounts: token_account
+	Revoke,
+
+	// transfer amount from source to dest out of the allowance (delegate signs)
+	// accounts: source, dest
+	TransferFrom{amount: u128},
+
+	// stop a token account from sending, receiving or burning (freeze_authority signs)
+	// accounts: token_account, mint
+	FreezeAccount,
+
+	// undo FreezeAccount (freeze_authority signs)
+	// accounts: token_account, mint
+	ThawAccount,
+}
@@ -22,11 +22,20 @@ pub enum BankError{
 	Unauthorized,
 	#[error("bad mint")]
 	BadMint,
+	#[error("account already initialized")]
+	AlreadyInitialized,
+	#[error("account is frozen")]
+	AccountFrozen,
+	#[error("transfer exceeds the delegated amount")]
+	InsufficientAllowance,
 }
 
 
+// `keys` are the instruction's accounts in the order BankInstruction documents;
+// `accounts` holds their data
 pub fn process_instruction(
-	program_id: &[u8; 32],
+	_program_id: &[u8; 32],
+	keys: &[Pubkey],
 	accounts: &mut AccountStore,
 	instruction_data: &[u8],
 	signers: &[Pubkey] //a list of signers for this tx
@@ -35,159 +44,245 @@ pub fn process_instruction(
 	.map_err(|_| BankError::InvalidInstruction)?;
 
 	match instr{
-		BankInstruction::InitMint {decimals, mint_authority} =>{
+		BankInstruction::InitMint {decimals, mint_authority, freeze_authority} =>{
 
-			// account[0] is the mint account pubkey (key used in store)
-			// caller must ensure accounts contain a zeroed account to be initialized
-			let mint_key = program_id.to_vec(); //placeholder usage; expect caller to provide keys.
-			let key = mint_key;
-
-			if accounts.contains_key(&key) {
-				return Err(BankError::InvalidInstruction);
+			// the caller provides the mint account empty, or not at all
+			let mint_key = key(keys, 0)?;
+			if is_initialized(accounts, &mint_key) {
+				return Err(BankError::AlreadyInitialized);
 			}
 
-			let mint = Mint::new(decimals, mint_authority);
-
-			accounts.insert(key, mint.try_to_vec().unwrap());
-
+			let mint = Mint::new(decimals, mint_authority).with_freeze_authority(freeze_authority);
+			save(accounts, mint_key, &mint);
 			Ok(())
 
 		}
 
 
 		BankInstruction::InitAccount {owner} => {
-			let acct_key = program_id.to_vec();
-			if accounts.contains_key(&acct_key){
-				return Err(BankError::InvalidInstruction);
+			let acct_key = key(keys, 0)?;
+			let mint_key = key(keys, 1)?;
+			if is_initialized(accounts, &acct_key){
+				return Err(BankError::AlreadyInitialized);
 			}
+			load::<Mint>(accounts, &mint_key).map_err(|_| BankError::BadMint)?;
 
-			let mint_ref: Pubkey = *program_id;
-			let token_account = TokenAccount::new(owner, mint_ref);
-
-			accounts.insert(acct_key, token_account.try_to_vec().unwrap());
+			let token_account = TokenAccount::new(owner, keys[1]);
+			save(accounts, acct_key, &token_account);
 			Ok(())
 		}
 
 		BankInstruction::Transfer {amount} => {
-			// for this simple demo assume two keys in accounts: source, dest, with keys provided by runtime
-			if accounts.len() <2{
-				return Err(BankError::AccountNotFound);
-			}
+			let (source_key, dest_key) = (key(keys, 0)?, key(keys, 1)?);
+			let source_acct: TokenAccount = load(accounts, &source_key)?;
+			require_signer(signers, &source_acct.owner)?;
 
-			let mut iter = accounts.iter_mut();
-			let source_key = iter.next().unwrap().0.clone();
-			let dest_key = iter.next().unwrap().0.clone();
+			transfer(accounts, (source_key, source_acct), dest_key, amount)
+		}
 
-			let source_data = accounts.get_mut(&source_key).ok_or(BankError::AccountNotFound)?;
-			let dest_data = accounts.get_mut(&dest_key).ok_or(BankError::AccountNotFound)?;
+		BankInstruction::TransferFrom {amount} => {
+			let (source_key, dest_key) = (key(keys, 0)?, key(keys, 1)?);
+			let mut source_acct: TokenAccount = load(accounts, &source_key)?;
+			let delegate = source_acct.delegate.ok_or(BankError::Unauthorized)?;
+			require_signer(signers, &delegate)?;
+			spend_allowance(&mut source_acct, amount)?;
 
+			transfer(accounts, (source_key, source_acct), dest_key, amount)
+		}
 
-			let mut source_acct = TokenAccount::try_from_slice(source_data).map_err(|_| BankError::InvalidInstruction)?;
-			let dest_acct = TokenAccount::try_from_slice(dest_data).map_err(|_| BankError::InvalidInstruction)?;
 
-			if source_acct.amount < amount{
-				return Err(BankError::InsufficientFunds);
+		BankInstruction::MintTo{amount} => {
+
+			//accounts: mint_account, dest_token_account
+			let (mint_key, dest_key) = (key(keys, 0)?, key(keys, 1)?);
+			let mut mint: Mint = load(accounts, &mint_key)?;
+			let mut dest_acct: TokenAccount = load(accounts, &dest_key)?;
+
+
+			//check signer is mint_authority
+			let authority = mint.mint_authority.ok_or(BankError::Unauthorized)?;
+			require_signer(signers, &authority)?;
+			if dest_acct.mint != keys[0] {
+				return Err(BankError::BadMint);
+			}
+			if dest_acct.is_frozen {
+				return Err(BankError::AccountFrozen);
 			}
 
-			source_acct.amount = source_acct.amount.saturating_sub(amount);
+			mint.supply = mint.supply.checked_add(amount).ok_or(BankError::InvalidInstruction)?;
 			dest_acct.amount = dest_acct.amount.saturating_add(amount);
 
 
-			*source_data = source_acct.try_to_vec().unwrap();
-			*dest_data = dest_acct.try_to_vec().unwrap();
+			save(accounts, mint_key, &mint);
+			save(accounts, dest_key, &dest_acct);
 
 			Ok(())
 
-
 		}
 
+		BankInstruction::Burn{amount} => {
 
-		BankInstruction::MintTo{amount} => {
+			// accounts: token_account, mint_account
+			let (token_key, mint_key) = (key(keys, 0)?, key(keys, 1)?);
+			let mut token_acct: TokenAccount = load(accounts, &token_key)?;
+			let mut mint: Mint = load(accounts, &mint_key)?;
 
-			//accounts: mint_account, dest_token_account
+			if token_acct.mint != keys[1] {
+				return Err(BankError::BadMint);
+			}
+			if token_acct.is_frozen {
+				return Err(BankError::AccountFrozen);
+			}
+
+			// the owner burns freely; a delegate burns out of its allowance
+			if signers.iter().all(|s| s != &token_acct.owner) {
+				let delegate = token_acct.delegate.ok_or(BankError::Unauthorized)?;
+				require_signer(signers, &delegate)?;
+				spend_allowance(&mut token_acct, amount)?;
+			}
 
-			if accounts.len()<2 {
-				return Err(BankError::AccountNotFound);
+			if token_acct.amount < amount {
+				return Err(BankError::InsufficientFunds);
 			}
 
-			let mut iter = accounts.iter_mut();
-			let mint_key = iter.next().unwrap().0.clone();
-			let dest_key = iter.next().unwrap().0.clone();
+			token_acct.amount -= amount;
+			mint.supply = mint.supply.saturating_sub(amount);
 
-			let mint_data = accounts.get_mut(&mint_key).ok_or(BankError::AccountNotFound)?;
-			let dest_data = accounts.get_mut(&dest_key).ok_or(BankError::AccountNotFound)?;
 
-			let mut mint = Mint::try_from_slice(mint_data).map_err(|_| BankError::InvalidInstruction)?;
-			let mut dest_acct = TokenAccount::try_from_slice(dest_data).map_err(|_| BankError::InvalidInstruction)?;
+			save(accounts, token_key, &token_acct);
+			save(accounts, mint_key, &mint);
 
+			Ok(())
+		}
 
-			//check signer is mint_authority
-			let authority = mint.mint_authority.ok_or(BankError::Unauthorized)?;
-			if !signers.iter().any(|s| s==&authority){
-				return Err(BankError::Unauthorized);
+		BankInstruction::Approve{delegate, amount} => {
+			let token_key = key(keys, 0)?;
+			let mut token_acct: TokenAccount = load(accounts, &token_key)?;
+			require_signer(signers, &token_acct.owner)?;
+			if token_acct.is_frozen {
+				return Err(BankError::AccountFrozen);
 			}
 
-			mint.supply = mint.supply.saturating_add(amount);
+			token_acct.delegate = Some(delegate);
+			token_acct.delegated_amount = amount;
+			save(accounts, token_key, &token_acct);
+			Ok(())
+		}
+
+		BankInstruction::Revoke => {
+			let token_key = key(keys, 0)?;
+			let mut token_acct: TokenAccount = load(accounts, &token_key)?;
+			require_signer(signers, &token_acct.owner)?;
 
-			dest_acct.amount = dest_acct.amount.saturating_add(amount);
+			token_acct.delegate = None;
+			token_acct.delegated_amount = 0;
+			save(accounts, token_key, &token_acct);
+			Ok(())
+		}
 
+		BankInstruction::FreezeAccount => set_frozen(keys, accounts, signers, true),
 
-			*mint_data = mint.try_to_vec().unwrap();
+		BankInstruction::ThawAccount => set_frozen(keys, accounts, signers, false),
 
-			*dest_data = dest_acct.try_to_vec().unwrap();
 
-			Ok(())
+	}
+}
 
-		}
 
-		BankInstruction::Burn{amount} => {
+// move `amount` from the loaded source to `dest_key`; both must hold the same
+// mint and neither may be frozen
+fn transfer(
+	accounts: &mut AccountStore,
+	(source_key, mut source_acct): (Vec<u8>, TokenAccount),
+	dest_key: Vec<u8>,
+	amount: u128,
+	) ->Result<(), BankError> {
+	if source_acct.is_frozen {
+		return Err(BankError::AccountFrozen);
+	}
+	if source_acct.amount < amount{
+		return Err(BankError::InsufficientFunds);
+	}
 
-			// accounts: token_account, mint_account
+	// a transfer to itself only spends allowance; writing two copies would mint
+	if source_key == dest_key {
+		save(accounts, source_key, &source_acct);
+		return Ok(());
+	}
 
-			if accounts.len() <2{
-				return Err(BankError::AccountNotFound);
-			}
+	let mut dest_acct: TokenAccount = load(accounts, &dest_key)?;
+	if dest_acct.mint != source_acct.mint {
+		return Err(BankError::BadMint);
+	}
+	if dest_acct.is_frozen {
+		return Err(BankError::AccountFrozen);
+	}
 
-			let mut iter = accounts.iter_mut();
-			let token_key = iter.next().unwrap().0.clone();
-			let mint_key = iter.next().unwrap().0.clone();
+	source_acct.amount -= amount;
+	dest_acct.amount = dest_acct.amount.checked_add(amount).ok_or(BankError::InvalidInstruction)?;
 
-			let token_data = accounts.get_mut(&token_key).ok_or(BankError::AccountNotFound)?;
-			let mint_data = accounts.get_mut(&mint_key).ok_or(BankError::AccountNotFound)?;
+	save(accounts, source_key, &source_acct);
+	save(accounts, dest_key, &dest_acct);
+	Ok(())
+}
 
-			let mut token_acct = TokenAccount::try_from_slice(token_data).map_err(|_| BankError::InvalidInstruction)?;
-			let mut mint = Mint::try_from_slice(mint_data).map_err(|_| BankError::InvalidInstruction)?;
+// take `amount` out of what the delegate may still move
+fn spend_allowance(token_acct: &mut TokenAccount, amount: u128) ->Result<(), BankError> {
+	if token_acct.delegated_amount < amount {
+		return Err(BankError::InsufficientAllowance);
+	}
+	token_acct.delegated_amount -= amount;
+	if token_acct.delegated_amount == 0 {
+		token_acct.delegate = None;
+	}
+	Ok(())
+}
 
-			if token_acct.amount < amount {
-				return Err(BankError::InsufficientFunds);
-			}
+// accounts: token_account, mint; the mint's freeze authority signs
+fn set_frozen(keys: &[Pubkey], accounts: &mut AccountStore, signers: &[Pubkey], frozen: bool) ->Result<(), BankError> {
+	let (token_key, mint_key) = (key(keys, 0)?, key(keys, 1)?);
+	let mut token_acct: TokenAccount = load(accounts, &token_key)?;
+	let mint: Mint = load(accounts, &mint_key)?;
 
-            // for simplicity, check that signer is owner
-            // In a real runtime you'd pass signers and the token account owner to check.
-            // Here we require the first signer equals token owner
-            // (caller must provide signers param accordingly)
-            // That check is done by runtime; omitted here for brevity.
+	let authority = mint.freeze_authority.ok_or(BankError::Unauthorized)?;
+	require_signer(signers, &authority)?;
+	if token_acct.mint != keys[1] {
+		return Err(BankError::BadMint);
+	}
 
+	token_acct.is_frozen = frozen;
+	save(accounts, token_key, &token_acct);
+	Ok(())
+}
 
-            token_acct.amount = token_acct.amount.saturating_sub(amount);
-            mint.supply = mint.supply.saturating_sub(amount);
 
+fn key(keys: &[Pubkey], index: usize) ->Result<Vec<u8>, BankError> {
+	keys.get(index).map(|key| key.to_vec()).ok_or(BankError::AccountNotFound)
+}
 
-            *token_data = token_acct.try_to_vec().unwrap();
-            *mint_data = mint.try_to_vec().unwrap();
+fn is_initialized(accounts: &AccountStore, key: &[u8]) ->bool {
+	accounts.get(key).is_some_and(|data| !data.is_empty())
+}
 
-            Ok(())
-		}
+fn load<T: BorshDeserialize>(accounts: &AccountStore, key: &[u8]) ->Result<T, BankError> {
+	let data = accounts.get(key).ok_or(BankError::AccountNotFound)?;
+	T::try_from_slice(data).map_err(|_| BankError::InvalidInstruction)
+}
 
+fn save<T: BorshSerialize>(accounts: &mut AccountStore, key: Vec<u8>, value: &T) {
+	accounts.insert(key, value.try_to_vec().unwrap());
+}
 
+fn require_signer(signers: &[Pubkey], authority: &Pubkey) ->Result<(), BankError> {
+	if !signers.iter().any(|s| s == authority) {
+		return Err(BankError::Unauthorized);
 	}
+	Ok(())
 }
 
 
 // Notes & integration hints:
 
-// process_instruction is intentionally minimal and uses a simple AccountStore map keyed by bytes (in your runtime these will be real account pubkeys).
-
-// In your real runtime, the program_id and accounts will be provided by the execution engine; replace placeholder logic with reading account keys and owners from the runtime environment.
+// process_instruction uses a simple AccountStore map keyed by bytes (in your runtime these will be real account pubkeys), with `keys` giving the instruction's account order.
 
-// Authorization checks here are illustrative — your runtime should pass signers and validate signatures against account owners.
\ No newline at end of file
+// Authorization checks compare against the signers the runtime passes in; the runtime is responsible for validating their signatures.
@@ -19,6 +19,12 @@ impl Mint{
 	pub fn new(decimals: u8, mint_authority: Option<Pubkey>) ->Self{
 		Self{decimals, supply: 0, mint_authority, freeze_authority:None}
 	}
+
+	// let `authority` freeze and thaw this mint's token accounts
+	pub fn with_freeze_authority(mut self, authority: Option<Pubkey>) ->Self{
+		self.freeze_authority = authority;
+		self
+	}
 }
 
 #[derive(Debug, BorshSerialize, BorshDeserialize, PartialEq, Clone)]
@@ -26,10 +32,15 @@ pub struct TokenAccount{
 	pub owner: Pubkey,
 	pub amount: u128,
 	pub mint: Pubkey,
+	// may move up to `delegated_amount` on the owner's behalf
+	pub delegate: Option<Pubkey>,
+	pub delegated_amount: u128,
+	// a frozen account can't send, receive or burn until the freeze authority thaws it
+	pub is_frozen: bool,
 }
 
 impl TokenAccount{
 	pub fn new(owner: Pubkey, mint: Pubkey) ->Self{
-		Self{owner, amount: 0, mint}
+		Self{owner, amount: 0, mint, delegate: None, delegated_amount: 0, is_frozen: false}
 	}
 }
\ No newline at end of file
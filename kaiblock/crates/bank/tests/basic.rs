@@ -0,0 +1,177 @@
+use bank::instruction::BankInstruction;
+use bank::processor::{process_instruction, BankError};
+use bank::state::{Mint, Pubkey, TokenAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::collections::HashMap;
+
+fn rand_pubkey() -> Pubkey {
+    let mut p = [0u8; 32];
+    p[0] = rand::random::<u8>();
+    p
+}
+
+#[test]
+fn test_mint_and_transfer() {
+    let program_id = [1u8; 32];
+    let mint_key = vec![2u8; 32];
+    let alice_key = vec![3u8; 32];
+    let bob_key = vec![4u8; 32];
+
+    let mut store: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+
+    // create mint account and token accounts
+    let mint = Mint::new(6, Some([9u8; 32]));
+    store.insert(mint_key.clone(), mint.try_to_vec().unwrap());
+
+    let alice_acct = TokenAccount::new([11u8;32], [2u8; 32]);
+    store.insert(alice_key.clone(), alice_acct.try_to_vec().unwrap());
+
+    let bob_acct = TokenAccount::new([12u8;32], [2u8; 32]);
+    store.insert(bob_key.clone(), bob_acct.try_to_vec().unwrap());
+
+    // mint 1000 to alice
+    let instr = BankInstruction::MintTo { amount: 1000u128 }.try_to_vec().unwrap();
+    // For this test we will call process_instruction with accounts map containing mint then alice
+    let mut accounts_for_mint = HashMap::new();
+    accounts_for_mint.insert(mint_key.clone(), store.get(&mint_key).unwrap().clone());
+    accounts_for_mint.insert(alice_key.clone(), store.get(&alice_key).unwrap().clone());
+
+    let signers = vec![[9u8;32]]; // mint authority
+    process_instruction(&program_id, &[[2u8; 32], [3u8; 32]], &mut accounts_for_mint, &instr, &signers).unwrap();
+
+    // verify alice has 1000
+    let alice_after = TokenAccount::try_from_slice(accounts_for_mint.get(&alice_key).unwrap()).unwrap();
+    assert_eq!(alice_after.amount, 1000u128);
+
+    // Now transfer 200 from alice to bob
+    // put accounts in order source -> dest
+    let mut accounts_for_transfer = HashMap::new();
+    accounts_for_transfer.insert(alice_key.clone(), accounts_for_mint.get(&alice_key).unwrap().clone());
+    accounts_for_transfer.insert(bob_key.clone(), store.get(&bob_key).unwrap().clone());
+
+    let transfer_instr = BankInstruction::Transfer { amount: 200 }.try_to_vec().unwrap();
+    // only alice's owner can move her tokens
+    let keys = [[3u8; 32], [4u8; 32]];
+    assert!(process_instruction(&program_id, &keys, &mut accounts_for_transfer, &transfer_instr, &[]).is_err());
+    process_instruction(&program_id, &keys, &mut accounts_for_transfer, &transfer_instr, &[[11u8; 32]]).unwrap();
+
+    // check balances
+    let alice_after2 = TokenAccount::try_from_slice(accounts_for_transfer.get(&alice_key).unwrap()).unwrap();
+    let bob_after = TokenAccount::try_from_slice(accounts_for_transfer.get(&bob_key).unwrap()).unwrap();
+
+    assert_eq!(alice_after2.amount, 800u128);
+    assert_eq!(bob_after.amount, 200u128);
+}
+
+
+// a mint at [2; 32] with authority [9; 32] and freeze authority [10; 32], and
+// token accounts at [3; 32] (owner [11; 32], holding 1000) and [4; 32] (owner [12; 32])
+fn funded_store() -> HashMap<Vec<u8>, Vec<u8>> {
+    let mut store = HashMap::new();
+    let mut mint = Mint::new(6, Some([9u8; 32])).with_freeze_authority(Some([10u8; 32]));
+    mint.supply = 1000;
+    store.insert(vec![2u8; 32], mint.try_to_vec().unwrap());
+
+    let mut alice = TokenAccount::new([11u8; 32], [2u8; 32]);
+    alice.amount = 1000;
+    store.insert(vec![3u8; 32], alice.try_to_vec().unwrap());
+    store.insert(vec![4u8; 32], TokenAccount::new([12u8; 32], [2u8; 32]).try_to_vec().unwrap());
+    store
+}
+
+fn token_account(store: &HashMap<Vec<u8>, Vec<u8>>, key: u8) -> TokenAccount {
+    TokenAccount::try_from_slice(&store[&vec![key; 32]]).unwrap()
+}
+
+#[test]
+fn test_delegate_transfers_within_allowance() {
+    let program_id = [1u8; 32];
+    let (alice, bob, delegate) = ([3u8; 32], [4u8; 32], [13u8; 32]);
+    let mut store = funded_store();
+    let run = |store: &mut HashMap<Vec<u8>, Vec<u8>>, keys: &[Pubkey], instr: BankInstruction, signer: Pubkey| {
+        process_instruction(&program_id, keys, store, &instr.try_to_vec().unwrap(), &[signer])
+    };
+
+    // only the owner approves
+    let approve = BankInstruction::Approve { delegate, amount: 300 };
+    assert!(run(&mut store, &[alice], approve.clone(), delegate).is_err());
+    run(&mut store, &[alice], approve, [11u8; 32]).unwrap();
+
+    // only the delegate spends the allowance, and not beyond it
+    let transfer_from = |amount| BankInstruction::TransferFrom { amount };
+    assert!(run(&mut store, &[alice, bob], transfer_from(100), [12u8; 32]).is_err());
+    run(&mut store, &[alice, bob], transfer_from(200), delegate).unwrap();
+    assert!(matches!(
+        run(&mut store, &[alice, bob], transfer_from(200), delegate),
+        Err(BankError::InsufficientAllowance)
+    ));
+    assert_eq!(token_account(&store, 3).amount, 800);
+    assert_eq!(token_account(&store, 3).delegated_amount, 100);
+    assert_eq!(token_account(&store, 4).amount, 200);
+
+    // the delegate burns out of the same allowance, which then runs out
+    run(&mut store, &[alice, [2u8; 32]], BankInstruction::Burn { amount: 100 }, delegate).unwrap();
+    assert_eq!(token_account(&store, 3).delegate, None);
+    assert_eq!(Mint::try_from_slice(&store[&vec![2u8; 32]]).unwrap().supply, 900);
+    assert!(run(&mut store, &[alice, [2u8; 32]], BankInstruction::Burn { amount: 1 }, delegate).is_err());
+
+    // revoking ends the delegation
+    run(&mut store, &[alice], BankInstruction::Approve { delegate, amount: 50 }, [11u8; 32]).unwrap();
+    run(&mut store, &[alice], BankInstruction::Revoke, [11u8; 32]).unwrap();
+    assert!(run(&mut store, &[alice, bob], transfer_from(1), delegate).is_err());
+}
+
+#[test]
+fn test_frozen_accounts_neither_send_nor_receive() {
+    let program_id = [1u8; 32];
+    let (mint, alice, bob) = ([2u8; 32], [3u8; 32], [4u8; 32]);
+    let mut store = funded_store();
+    let run = |store: &mut HashMap<Vec<u8>, Vec<u8>>, keys: &[Pubkey], instr: BankInstruction, signer: Pubkey| {
+        process_instruction(&program_id, keys, store, &instr.try_to_vec().unwrap(), &[signer])
+    };
+
+    // only the freeze authority freezes
+    assert!(run(&mut store, &[bob, mint], BankInstruction::FreezeAccount, [9u8; 32]).is_err());
+    run(&mut store, &[bob, mint], BankInstruction::FreezeAccount, [10u8; 32]).unwrap();
+    assert!(token_account(&store, 4).is_frozen);
+
+    let transfer = |amount| BankInstruction::Transfer { amount };
+    assert!(matches!(run(&mut store, &[alice, bob], transfer(10), [11u8; 32]), Err(BankError::AccountFrozen)));
+    assert!(matches!(run(&mut store, &[bob, alice], transfer(0), [12u8; 32]), Err(BankError::AccountFrozen)));
+    assert!(run(&mut store, &[mint, bob], BankInstruction::MintTo { amount: 10 }, [9u8; 32]).is_err());
+
+    run(&mut store, &[bob, mint], BankInstruction::ThawAccount, [10u8; 32]).unwrap();
+    run(&mut store, &[alice, bob], transfer(10), [11u8; 32]).unwrap();
+    assert_eq!(token_account(&store, 4).amount, 10);
+
+    // a mint without a freeze authority can't freeze anything
+    let mut no_authority = Mint::new(6, Some([9u8; 32]));
+    no_authority.supply = 1000;
+    store.insert(mint.to_vec(), no_authority.try_to_vec().unwrap());
+    assert!(matches!(run(&mut store, &[bob, mint], BankInstruction::FreezeAccount, [10u8; 32]), Err(BankError::Unauthorized)));
+}
+
+#[test]
+fn test_only_the_owner_burns_without_a_delegation() {
+    let program_id = [1u8; 32];
+    let (mint, alice) = ([2u8; 32], [3u8; 32]);
+    let mut store = funded_store();
+    let run = |store: &mut HashMap<Vec<u8>, Vec<u8>>, keys: &[Pubkey], amount, signers: &[Pubkey]| {
+        let instr = BankInstruction::Burn { amount }.try_to_vec().unwrap();
+        process_instruction(&program_id, keys, store, &instr, signers)
+    };
+
+    // nobody signing, a stranger, another holder and the mint authority
+    for signers in [vec![], vec![rand_pubkey()], vec![[12u8; 32]], vec![[9u8; 32]], vec![[10u8; 32]]] {
+        assert!(matches!(run(&mut store, &[alice, mint], 100, &signers), Err(BankError::Unauthorized)));
+    }
+    assert_eq!(token_account(&store, 3).amount, 1000);
+
+    // the owner burns against the account's own mint, up to the balance
+    store.insert(vec![5u8; 32], Mint::new(6, Some([9u8; 32])).try_to_vec().unwrap());
+    assert!(matches!(run(&mut store, &[alice, [5u8; 32]], 100, &[[11u8; 32]]), Err(BankError::BadMint)));
+    assert!(matches!(run(&mut store, &[alice, mint], 1001, &[[11u8; 32]]), Err(BankError::InsufficientFunds)));
+    run(&mut store, &[alice, mint], 100, &[[11u8; 32]]).unwrap();
+    assert_eq!(token_account(&store, 3).amount, 900);
+    assert_eq!(Mint::try_from_slice(&store[&vec![2u8; 32]]).unwrap().supply, 900);
+}
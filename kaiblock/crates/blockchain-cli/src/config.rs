@@ -0,0 +1,415 @@
+// blockchain-cli/src/config.rs
+use blockchain_consensus::MinerConfig;
+use blockchain_core::{Address, ChainConfig};
+use blockchain_network::BandwidthConfig;
+use blockchain_storage::IndexerConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::node::NodeConfig;
+
+type ConfigResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Variables starting with this override config file settings, one per
+/// setting, with sections separated by `__`: `KAIBLOCK__NETWORK__MAX_PEERS=50`
+/// or `KAIBLOCK__CHAIN__CHAIN_ID=7`. Values are TOML (`true`, `8`,
+/// `["a", "b"]`); anything that doesn't parse is taken as a string.
+const ENV_PREFIX: &str = "KAIBLOCK__";
+
+
+/// Settings of a `--config node.toml` file.
+///
+/// Every section and setting is optional; what the file leaves out keeps its
+/// default, `[chain]` included, so a file only lists what it changes.
+/// Environment variables (see `ENV_PREFIX`) override the file, and command
+/// line flags override both.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    /// directory for chain data (in-memory if unset)
+    pub data_dir: Option<PathBuf>,
+    /// seconds to wait for in-flight work on shutdown before aborting
+    pub shutdown_timeout: u64,
+    pub network: NetworkSettings,
+    pub rpc: RpcSettings,
+    pub mining: MiningSettings,
+    pub indexer: IndexerConfig,
+    pub wallet: WalletSettings,
+    pub log: LogSettings,
+    pub chain: ChainConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkSettings {
+    /// address the P2P listener binds to
+    pub listen_addr: String,
+    /// peers to connect to and sync from on start
+    pub bootnodes: Vec<String>,
+    /// connections beyond this many are refused
+    pub max_peers: usize,
+    /// upload cap per peer in KiB/s
+    pub peer_upload_limit: Option<u64>,
+    /// download cap per peer in KiB/s
+    pub peer_download_limit: Option<u64>,
+    /// upload cap across all peers in KiB/s
+    pub upload_limit: Option<u64>,
+    /// download cap across all peers in KiB/s
+    pub download_limit: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcSettings {
+    pub enabled: bool,
+    /// address the JSON-RPC server (HTTP and WebSocket) binds to
+    pub listen_addr: SocketAddr,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MiningSettings {
+    /// mine on top of the node (needs `address`)
+    pub enabled: bool,
+    /// address that receives block rewards
+    pub address: Option<String>,
+    /// mining threads (defaults to the number of CPU cores)
+    pub threads: Option<usize>,
+    /// seconds between hash rate reports
+    pub report_interval: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletSettings {
+    /// addresses whose history the node keeps, saved in `data_dir` on shutdown
+    pub addresses: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    /// `RUST_LOG` style filter, e.g. `info` or `warn,blockchain_network=debug`
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// human readable lines
+    Text,
+    /// one JSON object per event, with the fields of the spans it is in
+    Json,
+}
+
+impl Default for FileConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: None,
+            shutdown_timeout: 30,
+            network: NetworkSettings::default(),
+            rpc: RpcSettings::default(),
+            mining: MiningSettings::default(),
+            indexer: IndexerConfig::default(),
+            wallet: WalletSettings::default(),
+            log: LogSettings::default(),
+            chain: ChainConfig::default(),
+        }
+    }
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        Self {
+            listen_addr: "0.0.0.0:8333".to_string(),
+            bootnodes: Vec::new(),
+            max_peers: 125,
+            peer_upload_limit: None,
+            peer_download_limit: None,
+            upload_limit: None,
+            download_limit: None,
+        }
+    }
+}
+
+impl Default for MiningSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            address: None,
+            threads: None,
+            report_interval: 10,
+        }
+    }
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+impl Default for RpcSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 8545)),
+        }
+    }
+}
+
+
+impl FileConfig {
+    /// Defaults, overridden by the file at `path` if given, then by the
+    /// environment; `RUST_LOG`, if set, replaces the log filter
+    pub fn load(path: Option<&Path>) -> ConfigResult<Self> {
+        // merge as JSON: the defaults have map keys (checkpoint heights) and
+        // large integers TOML can't represent, but every TOML value is JSON
+        let mut config = serde_json::to_value(Self::default())?;
+        if let Some(path) = path {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| format!("cannot read config {}: {}", path.display(), e))?;
+            let file: toml::Value = toml::from_str(&text)
+                .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+            merge(&mut config, serde_json::to_value(file)?);
+        }
+        for (name, raw) in std::env::vars() {
+            if let Some(setting) = name.strip_prefix(ENV_PREFIX) {
+                let keys: Vec<String> = setting.split("__").map(str::to_lowercase).collect();
+                merge(&mut config, nested(&keys, env_value(&raw)));
+            }
+        }
+        let mut config: Self = serde_json::from_value(config).map_err(|e| format!("invalid node config: {}", e))?;
+        if let Ok(filter) = std::env::var("RUST_LOG") {
+            config.log.filter = filter;
+        }
+        Ok(config)
+    }
+
+    /// Service settings for the node; fails if mining is enabled without a valid reward address
+    pub fn node_config(&self) -> ConfigResult<NodeConfig> {
+        let kib = |limit: Option<u64>| limit.map(|kib| kib * 1024);
+        let mining = match (self.mining.enabled, &self.mining.address) {
+            (false, _) => None,
+            (true, Some(address)) => {
+                let mut miner_config = MinerConfig::new(Address::from_string(address)?);
+                if let Some(threads) = self.mining.threads {
+                    miner_config.threads = threads;
+                }
+                Some(miner_config)
+            }
+            (true, None) => return Err("mining needs a reward address (--address or mining.address)".into()),
+        };
+        let wallet_addresses = self.wallet.addresses.iter()
+            .map(|address| Address::from_string(address))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(NodeConfig {
+            p2p_addr: self.network.listen_addr.clone(),
+            bootstrap_peers: self.network.bootnodes.clone(),
+            max_peers: Some(self.network.max_peers),
+            bandwidth: BandwidthConfig {
+                peer_upload: kib(self.network.peer_upload_limit),
+                peer_download: kib(self.network.peer_download_limit),
+                global_upload: kib(self.network.upload_limit),
+                global_download: kib(self.network.download_limit),
+            },
+            rpc_addr: self.rpc.enabled.then_some(self.rpc.listen_addr),
+            data_dir: self.data_dir.clone(),
+            shutdown_timeout: Duration::from_secs(self.shutdown_timeout),
+            indexer: self.indexer.clone(),
+            mining,
+            mining_report_interval: Duration::from_secs(self.mining.report_interval),
+            wallet_addresses,
+        })
+    }
+}
+
+
+// overlay `update` on `base`, table by table
+fn merge(base: &mut Value, update: Value) {
+    match (base, update) {
+        (Value::Object(base), Value::Object(update)) => {
+            for (key, value) in update {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, update) => *base = update,
+    }
+}
+
+// `value` under the tables named by `keys`
+fn nested(keys: &[String], value: Value) -> Value {
+    keys.iter().rev().fold(value, |value, key| {
+        let mut table = Map::new();
+        table.insert(key.clone(), value);
+        Value::Object(table)
+    })
+}
+
+fn env_value(raw: &str) -> Value {
+    toml::from_str::<toml::Table>(&format!("value = {}", raw))
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .and_then(|value| serde_json::to_value(value).ok())
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use blockchain_crypto::{address::public_key_to_address, signature::generate_keypair, AddressType};
+    use std::sync::Mutex;
+
+    // the environment is shared by every test in the binary
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Run `f` with exactly `vars` of the variables `load` reads set
+    pub(crate) fn with_env<T>(vars: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let clear = || {
+            for (name, _) in std::env::vars().filter(|(name, _)| name.starts_with(ENV_PREFIX) || name == "RUST_LOG") {
+                std::env::remove_var(name);
+            }
+        };
+        clear();
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+        let result = f();
+        clear();
+        result
+    }
+
+    pub(crate) fn write_config(dir: &tempfile::TempDir, text: &str) -> PathBuf {
+        let path = dir.path().join("node.toml");
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    const FILE: &str = r#"
+        data_dir = "/var/lib/kaiblock"
+
+        [network]
+        max_peers = 10
+        bootnodes = ["10.0.0.1:8333"]
+
+        [log]
+        format = "json"
+
+        [chain]
+        chain_id = 7
+    "#;
+
+    #[test]
+    fn test_file_overrides_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(&dir, FILE);
+        let config = with_env(&[], || FileConfig::load(Some(&path))).unwrap();
+
+        assert_eq!(config.data_dir, Some(PathBuf::from("/var/lib/kaiblock")));
+        assert_eq!((config.network.max_peers, config.network.bootnodes.as_slice()), (10, ["10.0.0.1:8333".to_string()].as_slice()));
+        assert_eq!(config.log.format, LogFormat::Json);
+        assert_eq!(config.chain.chain_id, 7);
+
+        // what the file leaves out keeps its default, inside a section too
+        let defaults = FileConfig::default();
+        assert_eq!(config.network.listen_addr, defaults.network.listen_addr);
+        assert_eq!(config.rpc.listen_addr, defaults.rpc.listen_addr);
+        assert_eq!(config.log.filter, defaults.log.filter);
+        assert_eq!(config.chain.genesis.genesis_reward, defaults.chain.genesis.genesis_reward);
+        assert_eq!(config.shutdown_timeout, defaults.shutdown_timeout);
+    }
+
+    #[test]
+    fn test_environment_overrides_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(&dir, FILE);
+        let vars = [
+            ("KAIBLOCK__NETWORK__MAX_PEERS", "50"),
+            ("KAIBLOCK__NETWORK__BOOTNODES", r#"["a:1", "b:2"]"#),
+            ("KAIBLOCK__CHAIN__CHAIN_ID", "9"),
+            ("KAIBLOCK__MINING__ENABLED", "true"),
+            // not TOML, so taken as a string
+            ("KAIBLOCK__LOG__FILTER", "warn,blockchain_network=debug"),
+        ];
+        let config = with_env(&vars, || FileConfig::load(Some(&path))).unwrap();
+
+        assert_eq!(config.network.max_peers, 50);
+        assert_eq!(config.network.bootnodes, vec!["a:1".to_string(), "b:2".to_string()]);
+        assert_eq!(config.chain.chain_id, 9);
+        assert!(config.mining.enabled);
+        assert_eq!(config.log.filter, "warn,blockchain_network=debug");
+        // untouched by the environment
+        assert_eq!(config.data_dir, Some(PathBuf::from("/var/lib/kaiblock")));
+        assert_eq!(config.log.format, LogFormat::Json);
+
+        // without a file the environment applies over the defaults
+        let config = with_env(&vars[..1], || FileConfig::load(None)).unwrap();
+        assert_eq!(config.network.max_peers, 50);
+        assert_eq!(config.chain.chain_id, ChainConfig::default().chain_id);
+    }
+
+    #[test]
+    fn test_rust_log_replaces_the_log_filter() {
+        let vars = [("KAIBLOCK__LOG__FILTER", "warn"), ("RUST_LOG", "debug,runtime=trace")];
+        let config = with_env(&vars, || FileConfig::load(None)).unwrap();
+        assert_eq!(config.log.filter, "debug,runtime=trace");
+    }
+
+    #[test]
+    fn test_bad_settings_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        for text in ["[network]\nmax_peer = 3", "[network]\nmax_peers = \"many\"", "[rpc\nenabled = true", "colour = \"blue\""] {
+            let path = write_config(&dir, text);
+            assert!(with_env(&[], || FileConfig::load(Some(&path))).is_err(), "{:?} was accepted", text);
+        }
+        assert!(with_env(&[], || FileConfig::load(Some(&dir.path().join("missing.toml")))).is_err());
+        assert!(with_env(&[("KAIBLOCK__NETWORK__MAX_PEERS", "lots")], || FileConfig::load(None)).is_err());
+    }
+
+    #[test]
+    fn test_node_config() {
+        let mut config = FileConfig::default();
+        config.network.peer_upload_limit = Some(64);
+        config.network.download_limit = Some(1024);
+        config.rpc.enabled = false;
+        config.shutdown_timeout = 5;
+
+        let node = config.node_config().unwrap();
+        assert_eq!((node.bandwidth.peer_upload, node.bandwidth.global_download), (Some(64 * 1024), Some(1024 * 1024)));
+        assert_eq!(node.bandwidth.peer_download, None);
+        assert_eq!(node.rpc_addr, None);
+        assert_eq!(node.shutdown_timeout, Duration::from_secs(5));
+        assert!(node.mining.is_none());
+
+        // mining needs somewhere to pay the rewards
+        config.mining.enabled = true;
+        assert!(config.node_config().is_err());
+        config.mining.address = Some("not an address".to_string());
+        assert!(config.node_config().is_err());
+
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        config.mining.address = Some(miner.to_string());
+        config.mining.threads = Some(2);
+        let mining = config.node_config().unwrap().mining.unwrap();
+        assert_eq!((mining.miner_address, mining.threads), (miner.clone(), 2));
+
+        config.wallet.addresses = vec![miner.to_string()];
+        assert_eq!(config.node_config().unwrap().wallet_addresses, vec![miner]);
+        config.wallet.addresses.push("not an address".to_string());
+        assert!(config.node_config().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+// blockchain-cli/src/devnet.rs
+use blockchain_consensus::MinerConfig;
+use blockchain_core::{ChainConfig, DevAccountsConfig, NetworkType};
+use futures_util::future::join_all;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::FileConfig;
+use crate::genesis;
+use crate::node::{termination_signal, Node, NodeConfig};
+
+type DevnetResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Genesis time of a devnet without a genesis file. Fixed so every node,
+/// and every run, builds the same genesis block.
+pub const DEVNET_GENESIS_TIMESTAMP: i64 = 1_700_000_000;
+
+/// Most nodes one devnet runs
+pub const MAX_DEVNET_NODES: usize = 32;
+
+
+/// Options for `blockchain-node devnet`
+pub struct DevnetOptions {
+    pub nodes: usize,
+    /// node `i` listens for peers on `p2p_port + i`
+    pub p2p_port: u16,
+    /// node `i` serves JSON-RPC on `rpc_port + i`
+    pub rpc_port: u16,
+    /// mine on every node, each paying a different dev account
+    pub mine: bool,
+    /// mining threads per node
+    pub threads: usize,
+    /// chain settings for every node instead of the devnet defaults
+    pub genesis: Option<PathBuf>,
+    /// node `i` keeps its chain in `data_dir/node-i`; in memory if None
+    pub data_dir: Option<PathBuf>,
+}
+
+
+/// Run `nodes` nodes in this process on one genesis block, each connected to
+/// every node started before it, until SIGINT or SIGTERM
+pub async fn run(config: FileConfig, options: DevnetOptions) -> DevnetResult<()> {
+    if options.nodes == 0 || options.nodes > MAX_DEVNET_NODES {
+        return Err(format!("a devnet runs 1 to {} nodes", MAX_DEVNET_NODES).into());
+    }
+    let last = options.nodes as u16 - 1;
+    if options.p2p_port.checked_add(last).is_none() || options.rpc_port.checked_add(last).is_none() {
+        return Err("ports of the last nodes are out of range".into());
+    }
+
+    let chain = devnet_chain(&config, options.genesis.as_deref())?;
+    let dev_accounts = chain.genesis.dev_accounts.clone().unwrap_or_default().derive()?;
+    if dev_accounts.is_empty() && options.mine {
+        return Err("mining needs dev accounts to pay".into());
+    }
+
+    let mut nodes: Vec<Arc<Node>> = Vec::with_capacity(options.nodes);
+    let mut peers = Vec::with_capacity(options.nodes);
+    for index in 0..options.nodes {
+        let p2p_addr = format!("127.0.0.1:{}", options.p2p_port + index as u16);
+        let rpc_addr = SocketAddr::from(([127, 0, 0, 1], options.rpc_port + index as u16));
+        let mining = options.mine.then(|| {
+            let mut miner_config = MinerConfig::new(dev_accounts[index % dev_accounts.len()].address.clone());
+            miner_config.threads = options.threads;
+            miner_config
+        });
+        let node_config = NodeConfig {
+            p2p_addr: p2p_addr.clone(),
+            bootstrap_peers: peers.clone(),
+            rpc_addr: Some(rpc_addr),
+            data_dir: options.data_dir.as_ref().map(|dir| dir.join(format!("node-{}", index))),
+            shutdown_timeout: Duration::from_secs(config.shutdown_timeout),
+            indexer: config.indexer.clone(),
+            mining_report_interval: Duration::from_secs(config.mining.report_interval),
+            mining: mining.clone(),
+            ..NodeConfig::default()
+        };
+        if let Some(data_dir) = &node_config.data_dir {
+            std::fs::create_dir_all(data_dir)
+                .map_err(|e| format!("cannot create {}: {}", data_dir.display(), e))?;
+        }
+
+        let node = Node::new(node_config, chain.clone())?;
+        node.start().await;
+        info!(
+            "node {}: p2p {}  rpc http://{}{}",
+            index,
+            p2p_addr,
+            rpc_addr,
+            mining.map(|miner| format!("  mining to {}", miner.miner_address)).unwrap_or_default(),
+        );
+        peers.push(p2p_addr);
+        nodes.push(node);
+    }
+
+    let genesis_id = nodes[0].blockchain().read().await.get_block_by_height(&0).map(|block| block.id());
+    if let Some(genesis_id) = genesis_id {
+        info!("Devnet of {} nodes on genesis {}; Ctrl-C to stop", nodes.len(), genesis_id);
+    }
+
+    termination_signal().await;
+    join_all(nodes.iter().map(|node| node.shutdown())).await;
+    Ok(())
+}
+
+
+// chain settings shared by every node: the genesis file if given, otherwise
+// the configured chain as a Devnet with a fixed genesis time
+fn devnet_chain(config: &FileConfig, genesis_path: Option<&Path>) -> DevnetResult<ChainConfig> {
+    let mut chain = config.chain.clone();
+    // each node keeps its chain under its own data directory
+    chain.storage_path = None;
+    if let Some(path) = genesis_path {
+        return Ok(genesis::load(path)?.chain_config(chain)?);
+    }
+
+    chain.network = NetworkType::Devnet;
+    chain.genesis.timestamp.get_or_insert(DEVNET_GENESIS_TIMESTAMP);
+    chain.genesis.dev_accounts.get_or_insert_with(DevAccountsConfig::default);
+    Ok(chain)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::Blockchain;
+
+    fn options(nodes: usize, p2p_port: u16) -> DevnetOptions {
+        DevnetOptions { nodes, p2p_port, rpc_port: 18_545, mine: false, threads: 1, genesis: None, data_dir: None }
+    }
+
+    #[test]
+    fn test_devnet_chain_defaults() {
+        let mut config = FileConfig::default();
+        config.chain.storage_path = Some(PathBuf::from("/shared"));
+        let chain = devnet_chain(&config, None).unwrap();
+
+        assert_eq!(chain.network, NetworkType::Devnet);
+        assert_eq!(chain.genesis.timestamp, Some(DEVNET_GENESIS_TIMESTAMP));
+        assert_eq!(chain.genesis.dev_accounts, Some(DevAccountsConfig::default()));
+        assert_eq!(chain.storage_path, None);
+
+        // a configured genesis time is kept
+        config.chain.genesis.timestamp = Some(1_600_000_000);
+        assert_eq!(devnet_chain(&config, None).unwrap().genesis.timestamp, Some(1_600_000_000));
+    }
+
+    #[test]
+    fn test_every_node_builds_the_same_genesis() {
+        let chain = devnet_chain(&FileConfig::default(), None).unwrap();
+        let genesis = || Blockchain::new(chain.clone()).unwrap().get_block_by_height(&0).unwrap().id();
+        assert_eq!(genesis(), genesis());
+    }
+
+    #[tokio::test]
+    async fn test_bad_options_are_refused_before_starting() {
+        for options in [options(0, 30_000), options(MAX_DEVNET_NODES + 1, 30_000), options(2, u16::MAX)] {
+            assert!(run(FileConfig::default(), options).await.is_err());
+        }
+
+        // mining pays dev accounts, so it needs some
+        let mut config = FileConfig::default();
+        config.chain.genesis.dev_accounts = Some(DevAccountsConfig { seed_phrase: String::new(), accounts: Vec::new() });
+        let options = DevnetOptions { mine: true, ..options(1, 30_000) };
+        assert!(run(config, options).await.unwrap_err().to_string().contains("dev accounts"));
+    }
+}
@@ -0,0 +1,67 @@
+// blockchain-cli/src/genesis.rs
+use blockchain_core::{genesis_block, ChainConfig, GenesisFile};
+use std::path::{Path, PathBuf};
+
+type GenesisResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Name of the genesis file `init` copies into the data directory; `start`
+/// and `mine` take the chain settings from it
+pub const GENESIS_FILE: &str = "genesis.json";
+
+
+/// Read a genesis file: TOML if the name ends in `.toml`, JSON otherwise
+pub fn load(path: &Path) -> GenesisResult<GenesisFile> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read genesis file {}: {}", path.display(), e))?;
+    let genesis = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str(&text)
+            .map_err(|e| format!("invalid genesis file {}: {}", path.display(), e))?,
+        _ => GenesisFile::from_json(&text)?,
+    };
+    Ok(genesis)
+}
+
+
+/// The genesis file `init` left in `data_dir`, if any
+pub fn load_initialized(data_dir: &Path) -> GenesisResult<Option<GenesisFile>> {
+    let path = data_dir.join(GENESIS_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(load(&path)?))
+}
+
+
+/// Create `data_dir` with the genesis block of `genesis` on top of the node's
+/// chain settings. Running it again with the same file is a no-op; a data
+/// directory holding another chain is refused.
+pub fn init(base: ChainConfig, genesis_path: &Path, data_dir: PathBuf) -> GenesisResult<()> {
+    let genesis = load(genesis_path)?;
+    if let Some(existing) = load_initialized(&data_dir)? {
+        if existing != genesis {
+            return Err(format!("{} is already initialized with a different genesis file", data_dir.display()).into());
+        }
+    }
+
+    let mut config = genesis.chain_config(base)?;
+    config.storage_path.get_or_insert_with(|| data_dir.clone());
+    let expected = genesis_block(&config)?.id();
+
+    std::fs::create_dir_all(&data_dir)
+        .map_err(|e| format!("cannot create {}: {}", data_dir.display(), e))?;
+    let blockchain = blockchain_storage::open_blockchain(config)?;
+    let genesis_id = blockchain.get_block_by_height(&0)
+        .map(|block| block.id())
+        .ok_or("data directory has no genesis block")?;
+    if genesis_id != expected {
+        return Err(format!("{} holds a chain with genesis {}, not {}", data_dir.display(), genesis_id, expected).into());
+    }
+    blockchain.flush()?;
+
+    std::fs::write(data_dir.join(GENESIS_FILE), genesis.to_json()?)
+        .map_err(|e| format!("cannot write {}: {}", data_dir.join(GENESIS_FILE).display(), e))?;
+
+    println!("Initialized {} (chain id {})", data_dir.display(), genesis.chain_id);
+    println!("Genesis hash: {}", genesis_id);
+    Ok(())
+}
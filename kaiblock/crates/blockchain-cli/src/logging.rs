@@ -0,0 +1,41 @@
+// blockchain-cli/src/logging.rs
+use crate::config::{LogFormat, LogSettings};
+use tracing_subscriber::EnvFilter;
+
+type LogResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+
+/// Install the global subscriber for node logs. The filter picks a level per
+/// crate or module (`warn,blockchain_network=debug,runtime::program=off`).
+/// JSON output carries the fields of the enclosing spans, such as the peer
+/// a message came from or the block being added, for log pipelines to index.
+pub fn init(settings: &LogSettings) -> LogResult<()> {
+    let filter = EnvFilter::try_new(&settings.filter)
+        .map_err(|e| format!("invalid log filter {:?}: {}", settings.filter, e))?;
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    let installed = match settings.format {
+        LogFormat::Text => subscriber.try_init(),
+        LogFormat::Json => subscriber.json().try_init(),
+    };
+    installed.map_err(|e| format!("cannot install logger: {}", e))?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one test, as only the first subscriber installed in a process takes
+    #[test]
+    fn test_init_checks_the_filter_and_installs_once() {
+        let settings = |filter: &str, format| LogSettings { filter: filter.to_string(), format };
+
+        let err = init(&settings("warn,blockchain_network=loud", LogFormat::Text)).unwrap_err();
+        assert!(err.to_string().contains("invalid log filter"));
+
+        init(&settings("warn,blockchain_network=debug,runtime::program=off", LogFormat::Json)).unwrap();
+        let err = init(&settings("info", LogFormat::Text)).unwrap_err();
+        assert!(err.to_string().contains("cannot install logger"));
+    }
+}
@@ -1,43 +1,297 @@
 // blockchain-cli/src/main.rs
-use blockchain_core::{Block, Transaction, Blockchain};
-use blockchain_crypto::{KeyPair, Signature};
-use blockchain_network::P2PNode;
 use clap::{Parser, Subcommand};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+mod config;
+mod devnet;
+mod genesis;
+mod logging;
+mod node;
+mod wallet;
+mod watch;
+
+use config::{FileConfig, LogFormat};
+use node::Node;
 
 #[derive(Parser)]
 #[command(name = "blockchain-node")]
 struct Cli {
+    /// Node settings file (TOML); flags and KAIBLOCK__* variables override it
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    /// Log filter, e.g. `info` or `warn,blockchain_network=debug` (overrides RUST_LOG)
+    #[arg(long, global = true)]
+    log: Option<String>,
+    /// Log output format
+    #[arg(long, global = true, value_enum)]
+    log_format: Option<LogFormat>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    Start { port: u16 },
-    Mine,
-    Wallet,
+    /// Run a node, mining too if the config enables it
+    Start {
+        /// P2P port
+        port: Option<u16>,
+        /// Peer to connect to and sync from (repeatable)
+        #[arg(long = "peer")]
+        peers: Vec<String>,
+        /// Port for the JSON-RPC server (HTTP and WebSocket)
+        #[arg(long)]
+        rpc_port: Option<u16>,
+        /// Directory for chain data (in-memory if omitted)
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+        /// Seconds to wait for in-flight work on shutdown before aborting
+        #[arg(long)]
+        shutdown_timeout: Option<u64>,
+        /// Most peers to connect to
+        #[arg(long)]
+        max_peers: Option<usize>,
+        /// Upload cap per peer in KiB/s
+        #[arg(long)]
+        peer_upload_limit: Option<u64>,
+        /// Download cap per peer in KiB/s
+        #[arg(long)]
+        peer_download_limit: Option<u64>,
+        /// Upload cap across all peers in KiB/s
+        #[arg(long)]
+        upload_limit: Option<u64>,
+        /// Download cap across all peers in KiB/s
+        #[arg(long)]
+        download_limit: Option<u64>,
+        /// Index transactions and UTXOs by address and blocks by time for RPC queries
+        #[arg(long)]
+        index: bool,
+        /// Keep transactions of only the last N blocks (at least 100), older blocks keep just their headers
+        #[arg(long, value_name = "N")]
+        prune: Option<u64>,
+    },
+    /// Run a node and mine blocks on top of it
+    Mine {
+        /// Address that receives block rewards
+        #[arg(long)]
+        address: Option<String>,
+        /// Mining threads (defaults to the number of CPU cores)
+        #[arg(long)]
+        threads: Option<usize>,
+        /// P2P port
+        #[arg(long)]
+        port: Option<u16>,
+        /// Peer to connect to and sync from (repeatable)
+        #[arg(long = "peer")]
+        peers: Vec<String>,
+        /// Directory for chain data (in-memory if omitted)
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+        /// Seconds between hash rate reports
+        #[arg(long)]
+        report_interval: Option<u64>,
+        /// JSON-RPC port
+        #[arg(long)]
+        rpc_port: Option<u16>,
+    },
+    /// Run several connected nodes in this process on one genesis block
+    Devnet {
+        /// Nodes to run
+        #[arg(long, default_value_t = 3)]
+        nodes: usize,
+        /// P2P port of the first node; node i listens on this plus i
+        #[arg(long, default_value_t = 30333)]
+        port: u16,
+        /// JSON-RPC port of the first node; node i serves on this plus i
+        #[arg(long, default_value_t = 8545)]
+        rpc_port: u16,
+        /// Mine on every node, each paying a different dev account
+        #[arg(long)]
+        mine: bool,
+        /// Mining threads per node
+        #[arg(long, default_value_t = 1)]
+        threads: usize,
+        /// Genesis file for every node (defaults to the configured chain as a Devnet)
+        #[arg(long)]
+        genesis: Option<PathBuf>,
+        /// Directory for chain data, one subdirectory per node (in-memory if omitted)
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+    },
+    /// Create a data directory and its genesis block from a genesis file
+    Init {
+        /// Genesis file (JSON, or TOML if it ends in .toml); nodes given the same file share a genesis hash
+        #[arg(long)]
+        genesis: PathBuf,
+        /// Directory for chain data (defaults to data_dir from the config)
+        #[arg(long)]
+        data_dir: Option<PathBuf>,
+    },
+    /// Manage keys in an encrypted keystore, label addresses and send payments
+    Wallet {
+        /// Keystore file
+        #[arg(long, default_value = "wallet.keystore")]
+        keystore: PathBuf,
+        /// Address book file, labelling addresses to pay by name
+        #[arg(long, default_value = "wallet.addressbook")]
+        address_book: PathBuf,
+        #[command(subcommand)]
+        command: wallet::WalletCommand,
+    },
+    /// Stream new blocks, mempool size and peer count from a running node
+    Watch {
+        /// Node event stream (ws:// for WebSocket, http:// for SSE)
+        #[arg(long, default_value = "ws://127.0.0.1:8545/events")]
+        url: String,
+        /// Only show blocks touching this address (repeatable)
+        #[arg(long = "address")]
+        addresses: Vec<String>,
+        /// Print line-delimited JSON events
+        #[arg(long)]
+        json: bool,
+    },
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let Cli { config: config_path, log, log_format, command } = Cli::parse();
     
-    match cli.command {
-        Commands::Start { port } => {
-            println!("Starting blockchain node on port {}", port);
-            // Use library crates to start the node
-            let blockchain = Blockchain::new();
-            let node = P2PNode::new(port, blockchain);
-            node.start().await?;
+    match command {
+        Commands::Start {
+            port, peers, rpc_port, data_dir, shutdown_timeout, max_peers,
+            peer_upload_limit, peer_download_limit, upload_limit, download_limit, index, prune,
+        } => {
+            let mut config = load_config(config_path.as_deref(), log, log_format)?;
+            set_node_flags(&mut config, port, peers, rpc_port, data_dir);
+            let network = &mut config.network;
+            network.max_peers = max_peers.unwrap_or(network.max_peers);
+            network.peer_upload_limit = peer_upload_limit.or(network.peer_upload_limit);
+            network.peer_download_limit = peer_download_limit.or(network.peer_download_limit);
+            network.upload_limit = upload_limit.or(network.upload_limit);
+            network.download_limit = download_limit.or(network.download_limit);
+            config.shutdown_timeout = shutdown_timeout.unwrap_or(config.shutdown_timeout);
+            config.indexer.enabled |= index;
+            config.chain.prune_depth = prune.or(config.chain.prune_depth);
+            run_node(config).await?;
+        }
+        Commands::Mine { address, threads, port, peers, data_dir, report_interval, rpc_port } => {
+            let mut config = load_config(config_path.as_deref(), log, log_format)?;
+            set_node_flags(&mut config, port, peers, rpc_port, data_dir);
+            let mining = &mut config.mining;
+            mining.enabled = true;
+            mining.address = address.or(mining.address.take());
+            mining.threads = threads.or(mining.threads);
+            mining.report_interval = report_interval.unwrap_or(mining.report_interval);
+            run_node(config).await?;
+        }
+        Commands::Devnet { nodes, port, rpc_port, mine, threads, genesis, data_dir } => {
+            let config = load_config(config_path.as_deref(), log, log_format)?;
+            logging::init(&config.log)?;
+            let options = devnet::DevnetOptions { nodes, p2p_port: port, rpc_port, mine, threads, genesis, data_dir };
+            devnet::run(config, options).await?;
         }
-        Commands::Mine => {
-            println!("Starting mining...");
-            // Use consensus crate for mining
+        Commands::Init { genesis, data_dir } => {
+            let config = load_config(config_path.as_deref(), log, log_format)?;
+            let data_dir = data_dir.or(config.data_dir).ok_or("init needs --data-dir or data_dir in the config")?;
+            genesis::init(config.chain, &genesis, data_dir)?;
         }
-        Commands::Wallet => {
-            println!("Opening wallet interface...");
-            // Use wallet crate
+        Commands::Wallet { keystore, address_book, command } => {
+            // addresses and labels are checked against the configured chain's network
+            let network = load_config(config_path.as_deref(), log, log_format)?.chain.network;
+            let files = wallet::WalletFiles { keystore, address_book };
+            wallet::run(&files, network, command).await?;
+        }
+        Commands::Watch { url, addresses, json } => {
+            watch::run(watch::WatchOptions { url, addresses, json }).await?;
         }
     }
     
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Settings from the config file and environment, with the global log flags applied
+fn load_config(path: Option<&Path>, log: Option<String>, log_format: Option<LogFormat>) -> Result<FileConfig, Box<dyn std::error::Error>> {
+    let mut config = FileConfig::load(path)?;
+    if let Some(filter) = log {
+        config.log.filter = filter;
+    }
+    if let Some(format) = log_format {
+        config.log.format = format;
+    }
+    Ok(config)
+}
+
+/// Apply the flags `start` and `mine` share over the loaded config
+fn set_node_flags(config: &mut FileConfig, port: Option<u16>, peers: Vec<String>, rpc_port: Option<u16>, data_dir: Option<PathBuf>) {
+    if let Some(port) = port {
+        config.network.listen_addr = format!("0.0.0.0:{}", port);
+    }
+    config.network.bootnodes.extend(peers);
+    if let Some(rpc_port) = rpc_port {
+        config.rpc.enabled = true;
+        config.rpc.listen_addr = SocketAddr::from(([127, 0, 0, 1], rpc_port));
+    }
+    config.data_dir = data_dir.or(config.data_dir.take());
+}
+
+/// Run a node until SIGINT or SIGTERM, mining on top of it if the config says so.
+/// A data directory set up by `init` supplies the chain settings of its genesis file.
+async fn run_node(mut config: FileConfig) -> Result<(), Box<dyn std::error::Error>> {
+    logging::init(&config.log)?;
+    if let Some(genesis) = config.data_dir.as_deref().map(genesis::load_initialized).transpose()?.flatten() {
+        config.chain = genesis.chain_config(config.chain)?;
+    }
+    tracing::info!("Starting blockchain node on {}", config.network.listen_addr);
+    let node = Node::new(config.node_config()?, config.chain.clone())?;
+    node.run().await;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::tests::{with_env, write_config};
+
+    #[test]
+    fn test_flags_override_environment_and_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(&dir, r#"
+            data_dir = "/from/file"
+
+            [network]
+            listen_addr = "0.0.0.0:9000"
+            bootnodes = ["file:1"]
+
+            [rpc]
+            enabled = false
+
+            [log]
+            filter = "warn"
+        "#);
+        let vars = [("KAIBLOCK__DATA_DIR", "/from/env"), ("KAIBLOCK__RPC__LISTEN_ADDR", "127.0.0.1:9999"), ("RUST_LOG", "info")];
+
+        let config = with_env(&vars, || load_config(Some(&path), None, None)).unwrap();
+        assert_eq!(config.data_dir, Some(PathBuf::from("/from/env")));
+        assert_eq!(config.rpc.listen_addr, SocketAddr::from(([127, 0, 0, 1], 9999)));
+        assert_eq!(config.log.filter, "info");
+        assert_eq!(config.log.format, LogFormat::Text);
+
+        let mut config = with_env(&vars, || load_config(Some(&path), Some("debug".to_string()), Some(LogFormat::Json))).unwrap();
+        assert_eq!((config.log.filter.as_str(), config.log.format), ("debug", LogFormat::Json));
+
+        set_node_flags(&mut config, Some(7000), vec!["flag:2".to_string()], Some(8000), Some(PathBuf::from("/from/flag")));
+        assert_eq!(config.network.listen_addr, "0.0.0.0:7000");
+        assert_eq!(config.network.bootnodes, vec!["file:1".to_string(), "flag:2".to_string()]);
+        assert!(config.rpc.enabled);
+        assert_eq!(config.rpc.listen_addr, SocketAddr::from(([127, 0, 0, 1], 8000)));
+        assert_eq!(config.data_dir, Some(PathBuf::from("/from/flag")));
+
+        // flags not given leave the loaded settings alone
+        let mut config = with_env(&vars, || load_config(Some(&path), None, None)).unwrap();
+        set_node_flags(&mut config, None, Vec::new(), None, None);
+        assert_eq!(config.network.listen_addr, "0.0.0.0:9000");
+        assert!(!config.rpc.enabled);
+        assert_eq!(config.data_dir, Some(PathBuf::from("/from/env")));
+    }
+}
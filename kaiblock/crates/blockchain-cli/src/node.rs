@@ -0,0 +1,949 @@
+// blockchain-cli/src/node.rs
+use blockchain_consensus::{Miner, MinerConfig, MinerReport};
+use blockchain_core::{Address, Block, BlockId, Blockchain, ChainConfig, ChainEvent, SyncStatus, Transaction, TxId};
+use blockchain_network::{BandwidthConfig, Network, SyncConfig, SyncManager};
+use blockchain_rpc::{EventBus, EventBusConfig, MiningStatus, NodeEvent, NodeMetrics, NodeStatus, NodeWrites, RpcHandler, RpcServer};
+use blockchain_storage::{ChainIndexer, IndexerConfig};
+use blockchain_wallet::Wallet;
+use async_trait::async_trait;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch, Mutex, Notify, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{timeout, Instant};
+use tracing::{info, warn};
+
+type NodeResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+const MEMPOOL_FILE: &str = "mempool.dat";
+
+/// History of the node's wallet addresses, next to the saved mempool
+const WALLET_FILE: &str = "wallet.dat";
+
+/// Indexer database, under the data directory
+const INDEX_DIR: &str = "index";
+
+/// Relayed blocks waiting for full validation; more are dropped at the network edge
+const BLOCK_QUEUE_CAPACITY: usize = 64;
+
+
+/// Node service configuration
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    /// address the P2P listener binds to
+    pub p2p_addr: String,
+    /// peers to connect to and sync from on start
+    pub bootstrap_peers: Vec<String>,
+    /// connections beyond this many are refused (None: no limit)
+    pub max_peers: Option<usize>,
+    /// per-peer and global upload/download caps
+    pub bandwidth: BandwidthConfig,
+    /// address the JSON-RPC server binds to (None disables RPC)
+    pub rpc_addr: Option<SocketAddr>,
+    /// directory for chain data and the saved mempool (None keeps everything in memory)
+    pub data_dir: Option<PathBuf>,
+    /// how long shutdown may take before remaining tasks are aborted
+    pub shutdown_timeout: Duration,
+    /// secondary indexes for address and time queries over RPC
+    pub indexer: IndexerConfig,
+    /// mine on top of the chain (None: don't mine)
+    pub mining: Option<MinerConfig>,
+    /// how often the miner's hash rate is printed and reported to getNodeStatus
+    pub mining_report_interval: Duration,
+    /// addresses whose history the node keeps; the wallet is saved on shutdown
+    pub wallet_addresses: Vec<Address>,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            p2p_addr: "0.0.0.0:8333".to_string(),
+            bootstrap_peers: Vec::new(),
+            max_peers: None,
+            bandwidth: BandwidthConfig::default(),
+            rpc_addr: Some(SocketAddr::from(([127, 0, 0, 1], 8545))),
+            data_dir: None,
+            shutdown_timeout: Duration::from_secs(30),
+            indexer: IndexerConfig::default(),
+            mining: None,
+            mining_report_interval: Duration::from_secs(10),
+            wallet_addresses: Vec::new(),
+        }
+    }
+}
+
+
+/// What happened during shutdown
+#[derive(Debug, Default)]
+pub struct ShutdownReport {
+    /// every in-flight operation finished before the deadline
+    pub drained: bool,
+    /// mempool transactions written to disk
+    pub saved_transactions: usize,
+    /// the wallet was written to disk
+    pub saved_wallet: bool,
+    /// peers that received a goodbye message
+    pub peers_notified: usize,
+    /// background tasks aborted after the deadline
+    pub aborted_tasks: usize,
+}
+
+
+/// Counts in-flight writes so shutdown can wait for them
+#[derive(Default)]
+struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+impl InFlight {
+    async fn wait_idle(&self) {
+        loop {
+            let notified = self.idle.notified();
+            if self.count.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// Held for the duration of one write; dropping it marks the write finished
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+
+/// A running node: chain, P2P network and background tasks
+pub struct Node {
+    config: NodeConfig,
+    blockchain: Arc<RwLock<Blockchain>>,
+    network: Arc<Network>,
+    sync_status: SyncStatus,
+    status: NodeStatus,
+    metrics: NodeMetrics,
+    events: Arc<EventBus>,
+    accepting_writes: Arc<AtomicBool>,
+    in_flight: Arc<InFlight>,
+    shutdown: watch::Sender<bool>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    /// pre-checked blocks from peers, taken by the import task on start
+    relayed_blocks: Mutex<Option<mpsc::Receiver<Block>>>,
+    /// secondary indexes, when enabled
+    indexer: Option<Arc<ChainIndexer>>,
+    /// the miner, when mining; shutdown lets its round wind down before flushing
+    mining: Mutex<Option<JoinHandle<()>>>,
+    /// history of `wallet_addresses`, when there are any
+    wallet: Option<Arc<Mutex<Wallet>>>,
+}
+
+impl Node {
+    pub fn new(config: NodeConfig, chain_config: ChainConfig) -> NodeResult<Arc<Self>> {
+        let mut chain_config = chain_config;
+        if chain_config.storage_path.is_none() {
+            chain_config.storage_path = config.data_dir.clone();
+        }
+
+        let blockchain = Arc::new(RwLock::new(blockchain_storage::open_blockchain(chain_config)?));
+        let (block_queue, relayed_blocks) = mpsc::channel(BLOCK_QUEUE_CAPACITY);
+        let mut network = Network::new()
+            .with_chain(blockchain.clone())
+            .with_bandwidth(config.bandwidth.clone())
+            .with_block_queue(block_queue);
+        if let Some(max_peers) = config.max_peers {
+            network = network.with_max_peers(max_peers);
+        }
+        let (shutdown, _) = watch::channel(false);
+        let status = NodeStatus::new().with_data_dir(config.data_dir.clone());
+        let indexer = match (&config.indexer, &config.data_dir) {
+            (indexer, _) if !indexer.enabled => None,
+            (indexer, Some(data_dir)) => Some(ChainIndexer::new(&data_dir.join(INDEX_DIR).to_string_lossy(), indexer)?),
+            (indexer, None) => Some(ChainIndexer::temporary(indexer)?),
+        };
+
+        let wallet = open_wallet(&config).map(|wallet| Arc::new(Mutex::new(wallet)));
+
+        let node = Arc::new(Self {
+            config,
+            blockchain,
+            network: Arc::new(network),
+            sync_status: SyncStatus::new(),
+            status,
+            metrics: NodeMetrics::new(),
+            events: Arc::new(EventBus::new(EventBusConfig::default())),
+            accepting_writes: Arc::new(AtomicBool::new(true)),
+            in_flight: Arc::new(InFlight::default()),
+            shutdown,
+            tasks: Mutex::new(Vec::new()),
+            relayed_blocks: Mutex::new(Some(relayed_blocks)),
+            indexer: indexer.map(Arc::new),
+            mining: Mutex::new(None),
+            wallet,
+        });
+
+        node.restore_mempool()?;
+        Ok(node)
+    }
+
+    pub fn blockchain(&self) -> Arc<RwLock<Blockchain>> {
+        self.blockchain.clone()
+    }
+
+    pub fn network(&self) -> Arc<Network> {
+        self.network.clone()
+    }
+
+    pub fn sync_status(&self) -> SyncStatus {
+        self.sync_status.clone()
+    }
+
+    /// Uptime, mining progress and recent errors reported by getNodeStatus
+    pub fn status(&self) -> NodeStatus {
+        self.status.clone()
+    }
+
+    /// Counters exported on `GET /metrics`
+    pub fn metrics(&self) -> NodeMetrics {
+        self.metrics.clone()
+    }
+
+    /// Events streamed to `GET /events` subscribers
+    pub fn events(&self) -> Arc<EventBus> {
+        self.events.clone()
+    }
+
+    /// Receiver that flips to `true` once shutdown starts
+    pub fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Start the node, run it until SIGINT (Ctrl-C) or SIGTERM, then shut it down
+    pub async fn run(self: &Arc<Self>) -> ShutdownReport {
+        self.run_until(termination_signal()).await
+    }
+
+    /// Start the node, run it until `stop` resolves, then shut it down
+    pub async fn run_until(self: &Arc<Self>, stop: impl Future<Output = ()>) -> ShutdownReport {
+        self.start().await;
+        stop.await;
+        self.shutdown().await
+    }
+
+    /// Start the P2P listener, block sync, the JSON-RPC server and the miner;
+    /// all stop when shutdown starts
+    pub async fn start(self: &Arc<Self>) {
+        let network = self.network.clone();
+        let addr = self.config.p2p_addr.clone();
+        let shutdown = self.shutdown_signal();
+        let status = self.status.clone();
+        self.spawn(async move {
+            tokio::select! {
+                result = network.start_listener(&addr) => {
+                    if let Err(e) = result {
+                        warn!("P2P listener stopped: {}", e);
+                        status.record_error("network");
+                    }
+                }
+                _ = wait_for_shutdown(shutdown) => {}
+            }
+        }).await;
+
+        for peer in &self.config.bootstrap_peers {
+            if let Err(e) = self.network.connect_to_peer(peer).await {
+                warn!("Failed to connect to peer {}: {}", peer, e);
+                self.status.record_error("network");
+            }
+        }
+
+        if let Some(mut relayed_blocks) = self.relayed_blocks.lock().await.take() {
+            let node = self.clone();
+            let shutdown = self.shutdown_signal();
+            self.spawn(async move {
+                let import = async {
+                    while let Some(block) = relayed_blocks.recv().await {
+                        if let Err(e) = node.import_block(block).await {
+                            warn!("Rejected relayed block: {}", e);
+                        }
+                    }
+                };
+                tokio::select! {
+                    _ = import => {}
+                    _ = wait_for_shutdown(shutdown) => {}
+                }
+            }).await;
+        }
+
+        let sync = SyncManager::new(
+            self.network.clone(),
+            self.blockchain.clone(),
+            self.sync_status.clone(),
+            SyncConfig::default(),
+        );
+        let shutdown = self.shutdown_signal();
+        self.spawn(async move {
+            tokio::select! {
+                _ = sync.run() => {}
+                _ = wait_for_shutdown(shutdown) => {}
+            }
+        }).await;
+
+        let events = self.events.clone();
+        let shutdown = self.shutdown_signal();
+        self.spawn(async move {
+            tokio::select! {
+                _ = events.run() => {}
+                _ = wait_for_shutdown(shutdown) => {}
+            }
+        }).await;
+
+        let node = self.clone();
+        let shutdown = self.shutdown_signal();
+        self.spawn(async move {
+            tokio::select! {
+                _ = node.publish_events() => {}
+                _ = wait_for_shutdown(shutdown) => {}
+            }
+        }).await;
+
+        if let Some(indexer) = self.indexer.clone() {
+            let node = self.clone();
+            let shutdown = self.shutdown_signal();
+            self.spawn(async move {
+                tokio::select! {
+                    _ = node.follow_chain(indexer) => {}
+                    _ = wait_for_shutdown(shutdown) => {}
+                }
+            }).await;
+        }
+
+        if let Some(wallet) = self.wallet.clone() {
+            let node = self.clone();
+            let shutdown = self.shutdown_signal();
+            self.spawn(async move {
+                tokio::select! {
+                    _ = node.follow_wallet(wallet) => {}
+                    _ = wait_for_shutdown(shutdown) => {}
+                }
+            }).await;
+        }
+
+        if let Some(rpc_addr) = self.config.rpc_addr {
+            let handler = RpcHandler::new(self.blockchain.clone())
+                .with_write_gate(self.accepting_writes.clone())
+                .with_sync_status(self.sync_status.clone())
+                .with_status(self.status.clone())
+                .with_event_bus(self.events.clone())
+                .with_metrics(self.metrics.clone())
+                .with_network(self.network.clone())
+                .with_node_writes(self.clone());
+            let handler = match &self.indexer {
+                Some(indexer) => handler.with_indexer(indexer.clone()),
+                None => handler,
+            };
+            let server = RpcServer::new(Arc::new(handler), rpc_addr);
+            let shutdown = self.shutdown_signal();
+            self.spawn(async move { server.start_until(wait_for_shutdown(shutdown)).await }).await;
+        }
+
+        if let Some(miner_config) = self.config.mining.clone() {
+            self.start_mining(miner_config).await;
+        }
+    }
+
+    /// Mine until shutdown, announcing the blocks found to peers and
+    /// reporting the hash rate every `mining_report_interval`
+    async fn start_mining(&self, miner_config: MinerConfig) {
+        let threads = miner_config.threads;
+        info!("Mining to {} on {} threads", miner_config.miner_address, threads);
+        let (found_blocks, mut mined) = mpsc::unbounded_channel();
+        let miner = Miner::new(self.blockchain.clone(), miner_config).with_found_blocks(found_blocks);
+
+        let network = self.network.clone();
+        let shutdown = self.shutdown_signal();
+        self.spawn(async move {
+            let announce = async {
+                while let Some(block) = mined.recv().await {
+                    if let Err(e) = network.broadcast_block(&block).await {
+                        warn!("Failed to announce mined block {}: {}", block.id(), e);
+                    }
+                }
+            };
+            tokio::select! {
+                _ = announce => {}
+                _ = wait_for_shutdown(shutdown) => {}
+            }
+        }).await;
+
+        let status = self.status.clone();
+        let report_interval = self.config.mining_report_interval.max(Duration::from_secs(1));
+        let shutdown = self.shutdown_signal();
+        let mining = tokio::spawn(async move {
+            let reporter = async {
+                let mut interval = tokio::time::interval(report_interval);
+                interval.tick().await;
+                loop {
+                    interval.tick().await;
+                    let report = miner.report();
+                    print_mining_report(&report);
+                    status.set_mining(Some(mining_status(&report, threads)));
+                }
+            };
+            tokio::select! {
+                _ = miner.run_until(wait_for_shutdown(shutdown)) => {}
+                _ = reporter => {}
+            }
+            status.set_mining(None);
+            print_mining_report(&miner.report());
+        });
+        *self.mining.lock().await = Some(mining);
+    }
+
+    /// Forward chain events to the event bus (and double-spend proofs to peers),
+    /// and publish mempool size and peer count when they change. Block import
+    /// and mempool admission never wait on event consumers: the chain's
+    /// broadcast channel drops events for a lagging receiver instead.
+    async fn publish_events(&self) {
+        let mut chain_events = self.blockchain.read().await.subscribe();
+        let mut interval = tokio::time::interval(self.events.config().batch_interval);
+        let mut mempool_size = None;
+        let mut peer_count = None;
+
+        loop {
+            tokio::select! {
+                event = chain_events.recv() => match event {
+                    Ok(event) => {
+                        self.metrics.record_chain_event(&event);
+                        // warn peers too, whether we saw the conflict or a peer proved it
+                        if let ChainEvent::DoubleSpend(proof) = &event {
+                            if let Err(e) = self.network.relay_double_spend(proof).await {
+                                warn!("Failed to relay double-spend proof: {}", e);
+                            }
+                        }
+                        for event in NodeEvent::from_chain_event(&event) {
+                            self.events.publish(event);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Event publisher fell behind the chain, {} events skipped", missed);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                _ = interval.tick() => {
+                    let size = self.blockchain.read().await.mempool().len();
+                    if mempool_size != Some(size) {
+                        mempool_size = Some(size);
+                        self.events.publish(NodeEvent::MempoolSize { size });
+                    }
+
+                    let peers = self.network.peer_info().await.len();
+                    if peer_count != Some(peers) {
+                        peer_count = Some(peers);
+                        self.events.publish(NodeEvent::PeerCount { count: peers });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keep the indexer on the main chain. It catches up from its own tip on
+    /// every block event, so reorgs and skipped events need no special case.
+    async fn follow_chain(&self, indexer: Arc<ChainIndexer>) {
+        let mut chain_events = self.blockchain.read().await.subscribe();
+        let mut stale = true;
+
+        loop {
+            if stale {
+                if let Err(e) = indexer.sync(&*self.blockchain.read().await) {
+                    warn!("Indexer failed to follow the chain: {}", e);
+                    self.status.record_error("indexer");
+                }
+            }
+
+            stale = match chain_events.recv().await {
+                Ok(ChainEvent::BlockConnected(_)) | Ok(ChainEvent::BlockDisconnected(_)) => true,
+                Ok(_) => false,
+                Err(broadcast::error::RecvError::Lagged(_)) => true,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+        }
+    }
+
+    /// Keep the wallet on the main chain: catch up from its saved tip, then
+    /// apply chain events. Blocks it already connected while catching up are skipped.
+    async fn follow_wallet(&self, wallet: Arc<Mutex<Wallet>>) {
+        let mut chain_events = self.blockchain.read().await.subscribe();
+        self.catch_up_wallet(&wallet).await;
+
+        loop {
+            match chain_events.recv().await {
+                Ok(ChainEvent::BlockConnected(block)) => {
+                    let mut wallet = wallet.lock().await;
+                    if wallet.tip().is_none_or(|tip| block.height() > tip) {
+                        wallet.connect_block(&block);
+                    }
+                }
+                Ok(event) => {
+                    wallet.lock().await.apply_event(&event);
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    warn!("Wallet fell behind the chain, {} events skipped", missed);
+                    self.catch_up_wallet(&wallet).await;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+
+    /// Connect the main chain blocks above the wallet's tip
+    async fn catch_up_wallet(&self, wallet: &Mutex<Wallet>) {
+        let blockchain = self.blockchain.read().await;
+        let mut wallet = wallet.lock().await;
+        let start = wallet.tip().map_or(0, |tip| tip + 1);
+        for height in start..=blockchain.height() {
+            match blockchain.get_block_by_height(&height) {
+                Some(block) => {
+                    wallet.connect_block(block);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Run a background task that shutdown waits for (and aborts past the deadline).
+    /// Long-running tasks should watch `shutdown_signal()` and return when it fires.
+    pub async fn spawn<F>(&self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.lock().await.push(tokio::spawn(task));
+    }
+
+    /// Accept a transaction from RPC; rejected once shutdown has started
+    pub async fn submit_transaction(&self, tx: Transaction) -> NodeResult<TxId> {
+        let _guard = self.begin_write()?;
+
+        let tx_id = self.blockchain.write().await.add_transaction(tx.clone())?;
+        self.network.broadcast_transaction(&tx).await;
+        Ok(tx_id)
+    }
+
+    /// Accept a block from RPC, e.g. an external miner's, and announce it to peers
+    pub async fn submit_block(&self, block: Block) -> NodeResult<BlockId> {
+        let block_id = self.import_block(block.clone()).await?;
+        // peers the announcement doesn't reach catch up through sync
+        if let Err(e) = self.network.broadcast_block(&block).await {
+            warn!("Failed to announce submitted block {}: {}", block_id, e);
+        }
+        Ok(block_id)
+    }
+
+    /// Import a block. A block is applied to the chain atomically, so an import
+    /// that has started always finishes; imports are refused once shutdown starts.
+    pub async fn import_block(&self, block: Block) -> NodeResult<BlockId> {
+        let _guard = self.begin_write()?;
+
+        let result = {
+            let mut blockchain = self.blockchain.write().await;
+            // time validation only, not waiting for the lock
+            let started = Instant::now();
+            let result = blockchain.add_block(block);
+            self.metrics.observe_block_validation(started.elapsed(), result.is_ok());
+            result
+        };
+        if result.is_err() {
+            self.status.record_error("chain");
+        }
+        Ok(result?)
+    }
+
+    fn begin_write(&self) -> NodeResult<InFlightGuard> {
+        // count first so shutdown can't observe zero between the check and the write
+        self.in_flight.count.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlightGuard(self.in_flight.clone());
+
+        if !self.accepting_writes.load(Ordering::SeqCst) {
+            return Err("node is shutting down".into());
+        }
+
+        Ok(guard)
+    }
+
+    /// Coordinated shutdown: stop writes, drain in-flight work, flush state,
+    /// say goodbye to peers, then stop background tasks. Anything still running
+    /// when `shutdown_timeout` expires is aborted.
+    pub async fn shutdown(&self) -> ShutdownReport {
+        let deadline = Instant::now() + self.config.shutdown_timeout;
+        let mut report = ShutdownReport::default();
+
+        info!("Shutting down node...");
+        self.accepting_writes.store(false, Ordering::SeqCst);
+        let _ = self.shutdown.send(true);
+
+        // 1. let in-flight imports and submissions reach their safe boundary,
+        //    and the miner cancel its round (a block it found is still added)
+        let mut mining = self.mining.lock().await.take();
+        report.drained = timeout(deadline.saturating_duration_since(Instant::now()), async {
+            self.in_flight.wait_idle().await;
+            if let Some(mining) = &mut mining {
+                let _ = mining.await;
+            }
+        }).await.is_ok();
+
+        if let Some(mining) = mining.filter(|mining| !mining.is_finished()) {
+            mining.abort();
+            report.aborted_tasks += 1;
+        }
+        if !report.drained {
+            warn!(
+                "Shutdown timeout: {} operations still in flight",
+                self.in_flight.count.load(Ordering::SeqCst)
+            );
+        }
+
+        // 2. flush state. If a write is stuck holding the chain lock we can't flush safely.
+        match timeout(deadline.saturating_duration_since(Instant::now()), self.flush()).await {
+            Ok(Ok((saved, saved_wallet))) => {
+                report.saved_transactions = saved;
+                report.saved_wallet = saved_wallet;
+            }
+            Ok(Err(e)) => {
+                warn!("Failed to flush node state: {}", e);
+                self.status.record_error("storage");
+            }
+            Err(_) => warn!("Shutdown timeout: skipped flushing node state"),
+        }
+
+        // 3. close peer connections
+        match timeout(
+            deadline.saturating_duration_since(Instant::now()),
+            self.network.disconnect_all("node shutting down"),
+        ).await {
+            Ok(Ok(notified)) => report.peers_notified = notified,
+            Ok(Err(e)) => warn!("Failed to disconnect peers: {}", e),
+            Err(_) => warn!("Shutdown timeout: skipped peer goodbyes"),
+        }
+
+        // 4. wait for background tasks, aborting whatever outlives the deadline
+        let tasks: Vec<JoinHandle<()>> = self.tasks.lock().await.drain(..).collect();
+        for mut task in tasks {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if timeout(remaining, &mut task).await.is_err() {
+                task.abort();
+                report.aborted_tasks += 1;
+            }
+        }
+
+        if report.aborted_tasks > 0 {
+            warn!("Shutdown timeout: aborted {} background tasks", report.aborted_tasks);
+        }
+
+        info!(
+            "Node stopped (drained: {}, saved {} mempool transactions, saved wallet: {}, notified {} peers)",
+            report.drained, report.saved_transactions, report.saved_wallet, report.peers_notified
+        );
+        report
+    }
+
+    /// Flush the chain store and save the mempool and the wallet; returns how
+    /// many transactions were saved and whether the wallet was
+    async fn flush(&self) -> NodeResult<(usize, bool)> {
+        let blockchain = self.blockchain.read().await;
+        blockchain.flush()?;
+
+        let dir = match &self.config.data_dir {
+            Some(dir) => dir,
+            None => return Ok((0, false)),
+        };
+
+        let transactions: Vec<&Transaction> = blockchain.mempool().get_pending_transactions();
+        save(&dir.join(MEMPOOL_FILE), &bincode::serialize(&transactions)?)?;
+
+        let saved_wallet = match &self.wallet {
+            Some(wallet) => {
+                save(&dir.join(WALLET_FILE), &bincode::serialize(&*wallet.lock().await)?)?;
+                true
+            }
+            None => false,
+        };
+
+        Ok((transactions.len(), saved_wallet))
+    }
+
+    /// Re-add transactions saved by the last shutdown
+    fn restore_mempool(&self) -> NodeResult<()> {
+        let path = match &self.config.data_dir {
+            Some(dir) => dir.join(MEMPOOL_FILE),
+            None => return Ok(()),
+        };
+
+        let data = match std::fs::read(&path) {
+            Ok(data) => data,
+            Err(_) => return Ok(()),
+        };
+
+        let transactions: Vec<Transaction> = match bincode::deserialize(&data) {
+            Ok(transactions) => transactions,
+            Err(e) => {
+                warn!("Ignoring unreadable mempool file {}: {}", path.display(), e);
+                return Ok(());
+            }
+        };
+
+        // only `new` calls this, before anything else can take the lock
+        let mut blockchain = self.blockchain.try_write()
+            .map_err(|_| "chain is locked while restoring the mempool")?;
+        let restored = transactions.into_iter()
+            .filter(|tx| blockchain.add_transaction(tx.clone()).is_ok())
+            .count();
+        info!("Restored {} mempool transactions", restored);
+        Ok(())
+    }
+}
+
+
+/// RPC writes take the node's own path: counted as in flight for shutdown
+/// and relayed to peers
+#[async_trait]
+impl NodeWrites for Node {
+    async fn submit_transaction(&self, tx: Transaction) -> Result<TxId, String> {
+        Node::submit_transaction(self, tx).await.map_err(|e| e.to_string())
+    }
+
+    async fn submit_block(&self, block: Block) -> Result<BlockId, String> {
+        Node::submit_block(self, block).await.map_err(|e| e.to_string())
+    }
+}
+
+
+/// The wallet of `wallet_addresses`, resumed from the one saved by the last
+/// shutdown if there is one; None without wallet addresses
+fn open_wallet(config: &NodeConfig) -> Option<Wallet> {
+    if config.wallet_addresses.is_empty() {
+        return None;
+    }
+
+    let saved = config.data_dir.as_ref()
+        .map(|dir| dir.join(WALLET_FILE))
+        .and_then(|path| match std::fs::read(&path) {
+            Ok(data) => match bincode::deserialize::<Wallet>(&data) {
+                Ok(wallet) => Some(wallet),
+                Err(e) => {
+                    warn!("Ignoring unreadable wallet file {}: {}", path.display(), e);
+                    None
+                }
+            },
+            Err(_) => None,
+        });
+
+    Some(match saved {
+        Some(mut wallet) => {
+            for address in &config.wallet_addresses {
+                wallet.watch(address.clone());
+            }
+            wallet
+        }
+        None => Wallet::new(config.wallet_addresses.iter().cloned()),
+    })
+}
+
+/// Write then rename so a crash mid-write keeps the previous file
+fn save(path: &std::path::Path, data: &[u8]) -> NodeResult<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+
+/// Resolve on SIGINT (Ctrl-C) or, on Unix, SIGTERM
+pub async fn termination_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("Cannot listen for SIGTERM, stop the node with Ctrl-C: {}", e),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Resolve once the node's shutdown signal fires
+pub async fn wait_for_shutdown(mut signal: watch::Receiver<bool>) {
+    while !*signal.borrow() {
+        if signal.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+
+fn mining_status(report: &MinerReport, threads: usize) -> MiningStatus {
+    MiningStatus {
+        threads,
+        hashes: report.hashes,
+        blocks_found: report.blocks_found,
+        hash_rate: report.hash_rate(),
+    }
+}
+
+fn print_mining_report(report: &MinerReport) {
+    info!(
+        "{:.2} kH/s, {} blocks found, {} hashes in {}s",
+        report.hash_rate() / 1000.0,
+        report.blocks_found,
+        report.hashes,
+        report.elapsed.as_secs(),
+    );
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{address::public_key_to_address, signature::generate_keypair, AddressType};
+    use blockchain_rpc::RpcError;
+
+    fn node(shutdown_timeout: Duration) -> Arc<Node> {
+        let config = NodeConfig { rpc_addr: None, shutdown_timeout, ..NodeConfig::default() };
+        Node::new(config, ChainConfig::default()).unwrap()
+    }
+
+    fn transaction() -> Transaction {
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        Transaction::new_coinbase(miner, 50, 1)
+    }
+
+    #[tokio::test]
+    async fn test_writes_rejected_once_shutdown_starts() {
+        let node = node(Duration::from_secs(5));
+        let report = node.shutdown().await;
+
+        assert!(report.drained);
+        assert!(node.is_shutting_down());
+        let err = node.submit_transaction(transaction()).await.unwrap_err();
+        assert!(err.to_string().contains("shutting down"));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_waits_for_in_flight_writes() {
+        let node = node(Duration::from_secs(5));
+        let write = node.begin_write().unwrap();
+
+        let shutdown = tokio::spawn({
+            let node = node.clone();
+            async move { node.shutdown().await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!shutdown.is_finished());
+
+        drop(write);
+        assert!(shutdown.await.unwrap().drained);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_gives_up_on_stuck_writes() {
+        let node = node(Duration::from_millis(50));
+        let _write = node.begin_write().unwrap();
+
+        let report = node.shutdown().await;
+        assert!(!report.drained);
+    }
+
+    #[tokio::test]
+    async fn test_rpc_writes_go_through_the_node() {
+        let node = node(Duration::from_secs(5));
+        // the handler's own write gate stays open; only the node's path knows about shutdown
+        let handler = RpcHandler::new(node.blockchain()).with_node_writes(node.clone());
+        node.shutdown().await;
+
+        let data = hex::encode(blockchain_core::codec::encode(&transaction()));
+        match handler.send_raw_transaction(&data, None).await {
+            Err(RpcError::TransactionRejected(reason)) => assert!(reason.contains("shutting down")),
+            other => panic!("expected a rejection, got {:?}", other),
+        }
+        assert_eq!(node.in_flight.count.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stop_signal_stops_a_mining_node() {
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let mut mining = MinerConfig::new(miner);
+        mining.threads = 1;
+        let config = NodeConfig {
+            p2p_addr: "127.0.0.1:0".to_string(),
+            rpc_addr: None,
+            shutdown_timeout: Duration::from_secs(10),
+            mining: Some(mining),
+            ..NodeConfig::default()
+        };
+        let node = Node::new(config, ChainConfig::default()).unwrap();
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let run = tokio::spawn({
+            let node = node.clone();
+            async move { node.run_until(async { let _ = stopped.await; }).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!run.is_finished());
+        stop.send(()).unwrap();
+
+        let report = timeout(Duration::from_secs(10), run).await
+            .expect("node ignored the stop signal")
+            .unwrap();
+        assert!(report.drained);
+        assert_eq!(report.aborted_tasks, 0);
+        assert!(node.is_shutting_down());
+        // the miner was stopped and waited for, not left running
+        assert!(node.mining.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wallet_is_saved_on_shutdown_and_resumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let owner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let config = NodeConfig {
+            p2p_addr: "127.0.0.1:0".to_string(),
+            rpc_addr: None,
+            data_dir: Some(dir.path().to_path_buf()),
+            shutdown_timeout: Duration::from_secs(5),
+            wallet_addresses: vec![owner.clone()],
+            ..NodeConfig::default()
+        };
+
+        let node = Node::new(config.clone(), ChainConfig::default()).unwrap();
+        node.start().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let report = node.shutdown().await;
+        assert!(report.saved_wallet);
+        let tip = node.wallet.as_ref().unwrap().lock().await.tip();
+        assert_eq!(tip, Some(node.blockchain.read().await.height()));
+        drop(node);
+
+        let resumed = open_wallet(&config).unwrap();
+        assert_eq!(resumed.tip(), tip);
+
+        // no addresses, no wallet
+        assert!(open_wallet(&NodeConfig { wallet_addresses: Vec::new(), ..config }).is_none());
+    }
+}
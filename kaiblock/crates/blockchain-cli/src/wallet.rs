@@ -0,0 +1,448 @@
+// blockchain-cli/src/wallet.rs
+use blockchain_core::{codec, Address, Block, NetworkType, SigningDomain, Transaction, UTXO};
+use blockchain_crypto::signature::{verify_message, Keypair};
+use blockchain_wallet::{
+    parse_sweep_key, plan_sweep, select_coins, AddressBook, Keystore, SelectionParams, SweepOptions, SweepPlan, SweepSource, Wallet,
+};
+use clap::Subcommand;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+
+type WalletResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+/// Files the wallet commands work on
+pub struct WalletFiles {
+    pub keystore: PathBuf,
+    pub address_book: PathBuf,
+}
+
+/// Read the passphrase from this variable instead of prompting (for scripts)
+const PASSPHRASE_ENV: &str = "KAIBLOCK_WALLET_PASSPHRASE";
+
+
+#[derive(Subcommand)]
+pub enum WalletCommand {
+    /// Create an empty keystore
+    Create,
+    /// Generate a new key in the keystore
+    NewKey,
+    /// Import a hex-encoded private key
+    Import {
+        private_key: String,
+    },
+    /// List the addresses in the keystore
+    List,
+    /// Sign a message with an address's key to prove you control it
+    SignMessage {
+        address: String,
+        message: String,
+    },
+    /// Check a message signature against an address or address book label
+    /// (no keystore needed)
+    VerifyMessage {
+        address: String,
+        signature: String,
+        message: String,
+    },
+    /// Move all funds controlled by a key into this wallet
+    Sweep {
+        /// Key to sweep: hex or base58 private key, or an older 64-byte keypair
+        /// export (prompted for when omitted)
+        #[arg(long)]
+        key: Option<String>,
+        /// Receiving address or address book label (defaults to the first
+        /// address in the keystore)
+        #[arg(long)]
+        to: Option<String>,
+        /// Node JSON-RPC endpoint
+        #[arg(long, default_value = "http://127.0.0.1:8545")]
+        rpc: String,
+        /// Fee per byte of the UTXO sweeps (defaults to the mempool average)
+        #[arg(long)]
+        fee_per_byte: Option<u64>,
+        /// Gas price of the account transfer
+        #[arg(long, default_value_t = 1)]
+        gas_price: u64,
+        /// Also store the swept key in the keystore
+        #[arg(long)]
+        import: bool,
+        /// Show what would be swept without broadcasting
+        #[arg(long)]
+        dry_run: bool,
+        /// Resubmitting with the same key broadcasts nothing new (defaults
+        /// to each transaction's id); a sweep of several transactions uses
+        /// `<key>-<n>` for the n-th
+        #[arg(long)]
+        idempotency_key: Option<String>,
+    },
+    /// Show balances and transaction history of the keystore's addresses
+    History {
+        /// Node JSON-RPC endpoint
+        #[arg(long, default_value = "http://127.0.0.1:8545")]
+        rpc: String,
+        /// First block to scan (the height the keystore's first key was made, if known)
+        #[arg(long, default_value_t = 0)]
+        from_height: u64,
+    },
+    /// Pay an address, or an address book label
+    Send {
+        /// Recipient address or address book label
+        #[arg(long)]
+        to: String,
+        amount: u64,
+        /// Paying address (defaults to the first address in the keystore)
+        #[arg(long)]
+        from: Option<String>,
+        /// Node JSON-RPC endpoint
+        #[arg(long, default_value = "http://127.0.0.1:8545")]
+        rpc: String,
+        /// Fee per byte (defaults to the mempool average)
+        #[arg(long)]
+        fee_per_byte: Option<u64>,
+        /// Show the payment without broadcasting it
+        #[arg(long)]
+        dry_run: bool,
+        /// Resubmitting with the same key returns the first payment instead
+        /// of paying again (defaults to the transaction id)
+        #[arg(long)]
+        idempotency_key: Option<String>,
+    },
+    /// Show an address to be paid at
+    Receive {
+        /// Generate a new key for it instead of showing the first one
+        #[arg(long)]
+        new: bool,
+        /// Also save the address in the address book under this label
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Label addresses so they can be paid by name
+    #[command(subcommand)]
+    AddressBook(AddressBookCommand),
+}
+
+
+#[derive(Subcommand)]
+pub enum AddressBookCommand {
+    /// Save an address under a label
+    Add {
+        label: String,
+        address: String,
+        /// Free-form note shown in the list
+        #[arg(long)]
+        note: Option<String>,
+    },
+    /// Forget a label
+    Remove {
+        label: String,
+    },
+    /// List the saved addresses
+    List,
+}
+
+
+pub async fn run(files: &WalletFiles, network: NetworkType, command: WalletCommand) -> WalletResult<()> {
+    let keystore_path = files.keystore.as_path();
+    match command {
+        WalletCommand::Create => {
+            let passphrase = read_passphrase("New passphrase: ")?;
+            let confirm = read_passphrase("Repeat passphrase: ")?;
+            if passphrase != confirm {
+                return Err("passphrases do not match".into());
+            }
+            Keystore::create(keystore_path, &passphrase)?;
+            println!("Created keystore {}", keystore_path.display());
+        }
+        WalletCommand::NewKey => {
+            let mut keystore = Keystore::unlock(keystore_path, &read_passphrase("Passphrase: ")?)?;
+            let address = keystore.generate_key()?;
+            println!("{}", address);
+        }
+        WalletCommand::Import { private_key } => {
+            let keypair = Keypair::from_private_hex(&private_key)?;
+            let mut keystore = Keystore::unlock(keystore_path, &read_passphrase("Passphrase: ")?)?;
+            let address = keystore.import_key(keypair)?;
+            println!("{}", address);
+        }
+        WalletCommand::List => {
+            // addresses are stored in the clear, no passphrase needed
+            for address in Keystore::open(keystore_path)?.addresses() {
+                println!("{}", address);
+            }
+        }
+        WalletCommand::SignMessage { address, message } => {
+            let keystore = Keystore::unlock(keystore_path, &read_passphrase("Passphrase: ")?)?;
+            println!("{}", keystore.sign_message(&address, message.as_bytes())?);
+        }
+        WalletCommand::VerifyMessage { address, signature, message } => {
+            let address = AddressBook::open(&files.address_book)?.resolve(&address, network)?;
+            if !verify_message(&address, message.as_bytes(), &signature) {
+                return Err("signature does not match the address and message".into());
+            }
+            println!("Signature is valid");
+        }
+        WalletCommand::Sweep { key, to, rpc, fee_per_byte, gas_price, import, dry_run, idempotency_key } => {
+            let key = match key {
+                Some(key) => key,
+                None => rpassword::prompt_password("Key to sweep: ")?,
+            };
+            let keypair = parse_sweep_key(&key)?;
+
+            let destination = match to {
+                Some(to) => AddressBook::open(&files.address_book)?.resolve(&to, network)?,
+                None => Address::from_string(&first_address(keystore_path)?)?,
+            };
+
+            let client = RpcClient::new(rpc);
+            let options = SweepOptions {
+                fee_per_byte: fee_rate(&client, fee_per_byte).await?,
+                max_transaction_size: client.call("getBlockLimits", json!([])).await?["maxTransactionSize"]
+                    .as_u64()
+                    .map(|size| size as usize)
+                    .unwrap_or(SweepOptions::default().max_transaction_size),
+                gas_price,
+                domain: signing_domain(&client).await?,
+            };
+
+            let source = sweep_source(&client, &keypair).await?;
+            let plan = plan_sweep(&keypair, destination, source, &options)?;
+            print_sweep_plan(&plan);
+
+            if dry_run {
+                println!("Dry run, nothing broadcast");
+                return Ok(());
+            }
+            if import {
+                let mut keystore = Keystore::unlock(keystore_path, &read_passphrase("Passphrase: ")?)?;
+                keystore.import_key(keypair)?;
+            }
+
+            let several = plan.transactions.len() > 1;
+            for (index, tx) in plan.transactions.iter().enumerate() {
+                let key = idempotency_key.as_ref()
+                    .map(|key| if several { format!("{}-{}", key, index) } else { key.clone() });
+                let tx_id = broadcast(&client, tx, key).await?;
+                println!("Broadcast {}", tx_id);
+            }
+        }
+        WalletCommand::History { rpc, from_height } => {
+            let addresses = Keystore::open(keystore_path)?.addresses().into_iter()
+                .map(|address| Address::from_string(&address))
+                .collect::<Result<Vec<_>, _>>()?;
+            let wallet = scan_chain(&RpcClient::new(rpc), Wallet::new(addresses.clone()), from_height).await?;
+            print_history(&wallet, &addresses, &AddressBook::open(&files.address_book)?, network);
+        }
+        WalletCommand::Send { to, amount, from, rpc, fee_per_byte, dry_run, idempotency_key } => {
+            let address_book = AddressBook::open(&files.address_book)?;
+            let recipient = address_book.resolve(&to, network)?;
+            let from = match from {
+                Some(from) => from,
+                None => first_address(keystore_path)?,
+            };
+
+            let keystore = Keystore::unlock(keystore_path, &read_passphrase("Passphrase: ")?)?;
+            let keypair = keystore.keypair(&from)?;
+            let client = RpcClient::new(rpc);
+            let utxos = client.call("getUtxos", json!([from])).await?;
+            let height = utxos["height"].as_u64().unwrap_or(0);
+            let coinbase_maturity = utxos["coinbaseMaturity"].as_u64().unwrap_or(0);
+            let spendable: Vec<UTXO> = serde_json::from_value::<Vec<UTXO>>(utxos["utxos"].clone())?
+                .into_iter()
+                .filter(|utxo| !utxo.is_coinbase || height.saturating_sub(utxo.block_height) >= coinbase_maturity)
+                .collect();
+
+            let params = SelectionParams::new(keypair, recipient.clone(), amount, fee_rate(&client, fee_per_byte).await?);
+            let selection = select_coins(&spendable, &params)?;
+            let name = address_book.label_of(&recipient, network)
+                .map_or_else(|| recipient.to_string(), |label| format!("{} ({})", label, recipient));
+            println!("Paying {} to {} from {}", amount, name, from);
+            println!("  {} input(s), fee {}, change {}", selection.inputs.len(), selection.fee, selection.change.unwrap_or(0));
+
+            let tx = selection.into_transaction(keypair, recipient, Address::from_string(&from)?, signing_domain(&client).await?)?;
+            if dry_run {
+                println!("Dry run, nothing broadcast");
+                return Ok(());
+            }
+            let tx_id = broadcast(&client, &tx, idempotency_key).await?;
+            println!("Broadcast {}", tx_id);
+        }
+        WalletCommand::Receive { new, label } => {
+            let address = if new {
+                let mut keystore = Keystore::unlock(keystore_path, &read_passphrase("Passphrase: ")?)?;
+                keystore.generate_key()?
+            } else {
+                Address::from_string(&first_address(keystore_path)?)?
+            };
+            if let Some(label) = label {
+                AddressBook::open(&files.address_book)?.insert(&label, &address, network, None)?;
+            }
+            println!("{}", address);
+        }
+        WalletCommand::AddressBook(command) => run_address_book(&files.address_book, network, command)?,
+    }
+    Ok(())
+}
+
+
+fn run_address_book(path: &Path, network: NetworkType, command: AddressBookCommand) -> WalletResult<()> {
+    let mut address_book = AddressBook::open(path)?;
+    match command {
+        AddressBookCommand::Add { label, address, note } => {
+            let address = Address::from_string(&address)?;
+            address_book.insert(&label, &address, network, note)?;
+            println!("Saved {} as {:?}", address, label);
+        }
+        AddressBookCommand::Remove { label } => {
+            let entry = address_book.remove(&label)?;
+            println!("Removed {:?} ({})", entry.label, entry.address);
+        }
+        AddressBookCommand::List => {
+            for entry in address_book.entries() {
+                // entries of other networks are listed too, but can't be paid here
+                let other_network = if entry.network == network { String::new() } else { format!("  [{:?}]", entry.network) };
+                let note = entry.note.as_deref().map(|note| format!("  {}", note)).unwrap_or_default();
+                println!("{}  {}{}{}", entry.label, entry.address, other_network, note);
+            }
+        }
+    }
+    Ok(())
+}
+
+
+/// First address in the keystore, where payments go by default
+fn first_address(keystore_path: &Path) -> WalletResult<String> {
+    Ok(Keystore::open(keystore_path)?.addresses().into_iter().next()
+        .ok_or("keystore has no addresses; create one with `wallet new-key` or pass an address")?)
+}
+
+/// `fee_per_byte`, or the mempool's average if not given
+async fn fee_rate(client: &RpcClient, fee_per_byte: Option<u64>) -> WalletResult<u64> {
+    match fee_per_byte {
+        Some(fee_per_byte) => Ok(fee_per_byte),
+        None => Ok(client.call("getMempoolInfo", json!([])).await?["avg_fee_per_byte"].as_u64().unwrap_or(0).max(1)),
+    }
+}
+
+/// Submit a signed transaction under an idempotency key, so a retried
+/// submission returns the first id instead of broadcasting again. Without
+/// one the transaction id is the key
+async fn broadcast(client: &RpcClient, tx: &Transaction, idempotency_key: Option<String>) -> WalletResult<String> {
+    let key = idempotency_key.unwrap_or_else(|| tx.id().to_string());
+    let tx_id = client.call("sendRawTransaction", json!([hex::encode(codec::encode(tx)), key])).await?;
+    Ok(tx_id.as_str().unwrap_or_default().to_string())
+}
+
+/// Signing domain of the chain the node follows
+async fn signing_domain(client: &RpcClient) -> WalletResult<SigningDomain> {
+    let chain_id = client.call("getChainId", json!([])).await?;
+    Ok(SigningDomain::new(
+        chain_id["chainId"].as_u64().ok_or("node did not report its chain id")? as u32,
+        chain_id["forkId"].as_u64().unwrap_or(0) as u32,
+    ))
+}
+
+
+/// Feed the wallet every main chain block from `from_height` to the node's tip
+async fn scan_chain(client: &RpcClient, mut wallet: Wallet, from_height: u64) -> WalletResult<Wallet> {
+    let height = client.call("getBlockHeight", json!([])).await?.as_u64().unwrap_or(0);
+    for block_height in from_height..=height {
+        let block: Block = serde_json::from_value(client.call("getBlockByHeight", json!([block_height])).await?)?;
+        wallet.connect_block(&block);
+    }
+    Ok(wallet)
+}
+
+
+fn print_history(wallet: &Wallet, addresses: &[Address], address_book: &AddressBook, network: NetworkType) {
+    for address in addresses {
+        let balance = wallet.balance(address);
+        match address_book.label_of(address, network) {
+            Some(label) => println!("{} ({})  {} confirmed", label, address, balance.confirmed),
+            None => println!("{}  {} confirmed", address, balance.confirmed),
+        }
+    }
+    for entry in wallet.history() {
+        let net = entry.received as i128 - entry.sent as i128;
+        match entry.block {
+            Some((height, _)) => println!("  {}  {:+}  block {} ({} confirmations)", entry.tx_id, net, height, entry.confirmations),
+            None => println!("  {}  {:+}  pending", entry.tx_id, net),
+        }
+    }
+}
+
+
+/// Everything the key controls, read from the node
+async fn sweep_source(client: &RpcClient, keypair: &Keypair) -> WalletResult<SweepSource> {
+    let address = Address::from_public_key(keypair.public_key(), blockchain_crypto::AddressType::Base58).to_string();
+
+    let utxos = client.call("getUtxos", json!([address])).await?;
+    let account = client.call("getAccount", json!([address])).await?;
+    Ok(SweepSource {
+        utxos: serde_json::from_value::<Vec<UTXO>>(utxos["utxos"].clone())?,
+        height: utxos["height"].as_u64().unwrap_or(0),
+        coinbase_maturity: utxos["coinbaseMaturity"].as_u64().unwrap_or(0),
+        account_balance: account["balance"].as_u64().unwrap_or(0),
+        account_nonce: account["nonce"].as_u64().unwrap_or(0),
+    })
+}
+
+
+fn print_sweep_plan(plan: &SweepPlan) {
+    println!("Sweeping {} -> {}", plan.source, plan.destination);
+    for utxo in &plan.spent {
+        println!("  utxo {}  {}", utxo.outpoint(), utxo.output.amount);
+    }
+    for utxo in &plan.immature {
+        println!("  utxo {}  {}  (immature coinbase, skipped)", utxo.outpoint(), utxo.output.amount);
+    }
+    for utxo in &plan.dust {
+        println!("  utxo {}  {}  (below spending fee, skipped)", utxo.outpoint(), utxo.output.amount);
+    }
+    if plan.account_balance > 0 {
+        println!("  account balance  {}", plan.account_balance);
+    }
+    println!(
+        "{} transaction(s), total fees {}, {} arriving at {}",
+        plan.transactions.len(), plan.total_fees(), plan.total_swept(), plan.destination
+    );
+}
+
+
+/// Minimal JSON-RPC client for talking to a node
+struct RpcClient {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl RpcClient {
+    fn new(url: String) -> Self {
+        Self { url, http: reqwest::Client::new() }
+    }
+
+    async fn call(&self, method: &str, params: Value) -> WalletResult<Value> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let body = self.http.post(&self.url)
+            .header("Content-Type", "application/json")
+            .body(request.to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let mut response: Value = serde_json::from_slice(&body)?;
+        if let Some(error) = response.get("error").filter(|error| !error.is_null()) {
+            let message = error["message"].as_str().unwrap_or("unknown error");
+            return Err(format!("{} failed: {}", method, message).into());
+        }
+        Ok(response["result"].take())
+    }
+}
+
+
+fn read_passphrase(prompt: &str) -> WalletResult<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+    Ok(rpassword::prompt_password(prompt)?)
+}
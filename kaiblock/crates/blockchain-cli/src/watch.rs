@@ -0,0 +1,225 @@
+// blockchain-cli/src/watch.rs
+use blockchain_rpc::events::{BlockSummary, NodeEvent};
+use futures_util::StreamExt;
+use tokio_tungstenite::tungstenite::Message;
+
+type WatchResult<T> = Result<T, Box<dyn std::error::Error>>;
+
+
+/// Options for `blockchain-cli watch`
+pub struct WatchOptions {
+    /// ws:// or wss:// for WebSocket, http:// or https:// for SSE
+    pub url: String,
+    /// only show blocks touching one of these addresses
+    pub addresses: Vec<String>,
+    /// print one JSON event per line instead of the live view
+    pub json: bool,
+}
+
+
+/// Connect to a node's event stream and render events until it closes
+pub async fn run(options: WatchOptions) -> WatchResult<()> {
+    let mut view = WatchView::default();
+
+    if options.url.starts_with("ws://") || options.url.starts_with("wss://") {
+        watch_websocket(&options, &mut view).await
+    } else {
+        watch_sse(&options, &mut view).await
+    }
+}
+
+
+async fn watch_websocket(options: &WatchOptions, view: &mut WatchView) -> WatchResult<()> {
+    let (mut stream, _) = tokio_tungstenite::connect_async(options.url.as_str()).await?;
+    eprintln!("Watching {} (WebSocket)", options.url);
+
+    while let Some(message) = stream.next().await {
+        match message? {
+            Message::Text(text) => handle_payload(&text, options, view)?,
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+
+async fn watch_sse(options: &WatchOptions, view: &mut WatchView) -> WatchResult<()> {
+    let response = reqwest::Client::new()
+        .get(&options.url)
+        .header("Accept", "text/event-stream")
+        .send()
+        .await?
+        .error_for_status()?;
+    eprintln!("Watching {} (SSE)", options.url);
+
+    let mut stream = response.bytes_stream();
+    let mut parser = SseParser::default();
+
+    while let Some(chunk) = stream.next().await {
+        for payload in parser.push(&chunk?) {
+            handle_payload(&payload, options, view)?;
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Splits an SSE stream into event payloads. Lines end in `\n`, `\r\n` or a
+/// lone `\r`, a blank line ends an event, and only `data:` lines matter here
+#[derive(Default)]
+struct SseParser {
+    /// bytes of the line still being received
+    buffer: Vec<u8>,
+    /// data lines of the event still being received
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feed the next chunk of the stream; returns the payloads of the events it completed
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\r' || byte == b'\n') {
+            let terminator = match (self.buffer[end], self.buffer.get(end + 1)) {
+                (b'\r', Some(b'\n')) => 2,
+                // a trailing \r may be the first half of a \r\n split across chunks
+                (b'\r', None) => break,
+                _ => 1,
+            };
+            let line = String::from_utf8_lossy(&self.buffer[..end]).into_owned();
+            self.buffer.drain(..end + terminator);
+
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+        }
+
+        events
+    }
+}
+
+
+fn handle_payload(payload: &str, options: &WatchOptions, view: &mut WatchView) -> WatchResult<()> {
+    let event: NodeEvent = match serde_json::from_str(payload) {
+        Ok(event) => event,
+        // unknown events from newer nodes are skipped
+        Err(_) => return Ok(()),
+    };
+
+    if let NodeEvent::NewBlock(block) = &event {
+        if !options.addresses.is_empty()
+            && !options.addresses.iter().any(|address| block.touches(address)) {
+            return Ok(());
+        }
+    }
+
+    if options.json {
+        println!("{}", serde_json::to_string(&event)?);
+    } else {
+        view.render(&event);
+    }
+
+    Ok(())
+}
+
+
+/// Last known node status shown alongside each block
+#[derive(Default)]
+struct WatchView {
+    mempool_size: Option<usize>,
+    peer_count: Option<usize>,
+}
+
+impl WatchView {
+    fn render(&mut self, event: &NodeEvent) {
+        match event {
+            NodeEvent::NewBlock(block) => println!("{}", self.block_line(block)),
+            NodeEvent::MempoolSize { size } => {
+                self.mempool_size = Some(*size);
+                println!("{}", self.status_line());
+            }
+            NodeEvent::PeerCount { count } => {
+                self.peer_count = Some(*count);
+                println!("{}", self.status_line());
+            }
+            NodeEvent::TransactionReplaced { replaced, replacement, old_fee_per_byte, new_fee_per_byte } => {
+                println!(
+                    "replaced {} with {} (fee/byte {} -> {})",
+                    replaced, replacement, old_fee_per_byte, new_fee_per_byte
+                );
+            }
+            NodeEvent::Lagged { missed } => println!("fell behind, {} events skipped", missed),
+        }
+    }
+
+    fn block_line(&self, block: &BlockSummary) -> String {
+        format!(
+            "block #{:<8} {}  txs: {:<5} fees: {}",
+            block.height,
+            short_hash(&block.hash),
+            block.tx_count,
+            block.total_fees,
+        )
+    }
+
+    fn status_line(&self) -> String {
+        let show = |value: Option<usize>| value.map(|v| v.to_string()).unwrap_or_else(|| "-".to_string());
+        format!("mempool: {} txs  peers: {}", show(self.mempool_size), show(self.peer_count))
+    }
+}
+
+
+fn short_hash(hash: &str) -> &str {
+    &hash[..hash.len().min(16)]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(chunks: &[&str]) -> Vec<String> {
+        let mut parser = SseParser::default();
+        chunks.iter().flat_map(|chunk| parser.push(chunk.as_bytes())).collect()
+    }
+
+    #[test]
+    fn test_sse_events_split_on_blank_lines() {
+        assert_eq!(parse(&["data: one\n\ndata: two\n\n"]), vec!["one", "two"]);
+        // an event isn't complete until its blank line arrives
+        assert!(parse(&["data: one\n"]).is_empty());
+    }
+
+    #[test]
+    fn test_sse_crlf_line_endings() {
+        assert_eq!(parse(&["data: one\r\n\r\ndata: two\r\n\r\n"]), vec!["one", "two"]);
+        assert_eq!(parse(&["data: one\r\rdata: two\r\n\n"]), vec!["one", "two"]);
+    }
+
+    #[test]
+    fn test_sse_event_split_across_chunks() {
+        assert_eq!(parse(&["data: o", "ne\r", "\n\r", "\n"]), vec!["one"]);
+        // a UTF-8 character split between chunks survives
+        let text = "data: \u{e9}t\u{e9}\n\n".as_bytes();
+        let mut parser = SseParser::default();
+        assert!(parser.push(&text[..7]).is_empty());
+        assert_eq!(parser.push(&text[7..]), vec!["\u{e9}t\u{e9}"]);
+    }
+
+    #[test]
+    fn test_sse_multiline_data_and_other_fields() {
+        let events = parse(&["event: block\nid: 7\n: comment\ndata: {\"a\":\ndata:1}\n\n"]);
+        assert_eq!(events, vec!["{\"a\":\n1}"]);
+        // an event without data is dropped
+        assert!(parse(&["event: ping\n\n"]).is_empty());
+    }
+}
@@ -0,0 +1,433 @@
+use crate::pos::PoSEngine;
+use crate::validator_keys::{ProposalSignature, ValidatorKey};
+use blockchain_core::{Address, Amount, Block, BlockHeight, BlockId, Blockchain, BlockchainError, ChainEvent, Result};
+use blockchain_crypto::signature::Keypair;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+
+/// Most attestations a proposer packs into one block
+pub const MAX_ATTESTATIONS_PER_BLOCK: usize = 256;
+
+
+/// A validator's vote that a main chain block is the one to build on. Signed
+/// like a proposal, so a threshold validator needs `threshold` co-signers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    pub block_id: BlockId,
+    pub height: BlockHeight,
+    pub validator: Address,
+    pub signature: ProposalSignature,
+}
+
+impl Attestation {
+    /// What validators sign to attest `block_id` at `height`
+    pub fn message(block_id: &BlockId, height: BlockHeight) -> Vec<u8> {
+        let mut message = b"kaiblock attestation".to_vec();
+        message.extend_from_slice(&height.to_be_bytes());
+        message.extend_from_slice(block_id.hash().as_bytes());
+        message
+    }
+
+    /// Attest `block` as `validator`. `keypair` must be enough to sign for
+    /// `key` on its own; co-signers of a threshold key add their shares to
+    /// `signature` with [`ProposalSignature::add`].
+    pub fn sign(block: &Block, validator: Address, key: &ValidatorKey, keypair: &Keypair) -> Result<Self> {
+        let (block_id, height) = (block.id(), block.height());
+        let message = Self::message(&block_id, height);
+        let mut signature = ProposalSignature::new();
+        signature.add(key, &message, &keypair.public_key(), keypair.sign(&message))?;
+        Ok(Self { block_id, height, validator, signature })
+    }
+
+    /// Check the signature against the validator's registered key
+    pub fn verify(&self, engine: &PoSEngine) -> Result<()> {
+        let key = engine.state().validator_key(&self.validator).ok_or_else(|| BlockchainError::ValidationError(
+            format!("validator {} has no registered key", self.validator.encoded())
+        ))?;
+        key.verify(&Self::message(&self.block_id, self.height), &self.signature)
+    }
+}
+
+
+/// What a proof-of-stake block carries in its header's `consensus_data`: the
+/// proposal signature, then the attestations aggregated into the block.
+///
+/// The two are encoded back to back, so a block without attestations is
+/// encoded exactly like a bare [`ProposalSignature`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConsensusData {
+    pub proposal: ProposalSignature,
+    pub attestations: Vec<Attestation>,
+}
+
+impl ConsensusData {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut bytes = self.proposal.encode()?;
+        if !self.attestations.is_empty() {
+            bytes.extend(bincode::serialize(&self.attestations)
+                .map_err(|e| BlockchainError::SerializationError(format!("attestations: {}", e)))?);
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let proposal = ProposalSignature::decode(bytes)?;
+        let proposal_len = bincode::serialized_size(&proposal)
+            .map_err(|e| BlockchainError::SerializationError(format!("proposal signature: {}", e)))? as usize;
+        let attestations = match bytes.get(proposal_len..) {
+            Some(rest) if !rest.is_empty() => bincode::deserialize(rest)
+                .map_err(|e| BlockchainError::ValidationError(format!("malformed attestations: {}", e)))?,
+            _ => Vec::new(),
+        };
+        Ok(Self { proposal, attestations })
+    }
+}
+
+
+/// Collects attestations and tracks finality.
+///
+/// Attestations gossiped by validators wait in a pool until a proposer packs
+/// them into a block. Only attestations carried by main chain blocks count
+/// towards finality, so every node replaying the chain reaches the same
+/// finalized height: a block is final once validators holding at least
+/// `required_quorum_pct` of the validator set's stake have attested it, and
+/// its ancestors are final with it.
+///
+/// Feed it the chain's `ChainEvent`s with `apply_event`, so a reorg takes
+/// back the votes of the blocks it disconnects.
+#[derive(Debug, Clone, Default)]
+pub struct Finality {
+    /// gossiped attestations not yet in a block, by attested block
+    pool: HashMap<BlockId, HashMap<Address, Attestation>>,
+    /// validators whose attestation of a block is on chain
+    votes: HashMap<BlockId, (BlockHeight, HashSet<Address>)>,
+    finalized: Option<(BlockHeight, BlockId)>,
+}
+
+impl Finality {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Highest finalized block
+    pub fn finalized(&self) -> Option<(BlockHeight, BlockId)> {
+        self.finalized
+    }
+
+    /// Stake whose attestation of `block_id` is on chain
+    pub fn attested_stake(&self, block_id: &BlockId, engine: &PoSEngine) -> Amount {
+        let Some((_, voters)) = self.votes.get(block_id) else { return 0 };
+        engine.validator_set().iter()
+            .filter(|(validator, _)| voters.contains(validator))
+            .map(|(_, stake)| stake)
+            .sum()
+    }
+
+    /// Pool a gossiped attestation for a main chain block above the finalized
+    /// height. Returns false if it was already known.
+    pub fn add_attestation(&mut self, attestation: Attestation, chain: &Blockchain, engine: &PoSEngine) -> Result<bool> {
+        self.check(&attestation, chain, engine)?;
+        let on_chain = self.votes.get(&attestation.block_id)
+            .is_some_and(|(_, voters)| voters.contains(&attestation.validator));
+        let pooled = self.pool.entry(attestation.block_id).or_default();
+        if on_chain || pooled.contains_key(&attestation.validator) {
+            return Ok(false);
+        }
+        pooled.insert(attestation.validator.clone(), attestation);
+        Ok(true)
+    }
+
+    /// Pooled attestations for the next block, oldest attested blocks first;
+    /// those for blocks no longer on the main chain are left out
+    pub fn attestations_for_block(&self, chain: &Blockchain, limit: usize) -> Vec<Attestation> {
+        let mut by_height: BTreeMap<BlockHeight, Vec<&Attestation>> = BTreeMap::new();
+        for (block_id, attestations) in &self.pool {
+            for attestation in attestations.values() {
+                if on_main_chain(chain, block_id, attestation.height) {
+                    by_height.entry(attestation.height).or_default().push(attestation);
+                }
+            }
+        }
+        by_height.into_values().flatten().take(limit).cloned().collect()
+    }
+
+    /// Follow the main chain: count the votes of connected blocks and take
+    /// back those of disconnected ones. Returns the new finalized height, if
+    /// it moved.
+    pub fn apply_event(&mut self, event: &ChainEvent, chain: &mut Blockchain, engine: &PoSEngine) -> Result<Option<BlockHeight>> {
+        match event {
+            ChainEvent::BlockConnected(block) => self.connect_block(block, chain, engine),
+            ChainEvent::BlockDisconnected(block) => {
+                self.disconnect_block(block)?;
+                Ok(None)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Count the attestations `block` carries as it joins the main chain and
+    /// finalize what reaches the quorum in `chain`. Fails if an attestation
+    /// is invalid, or a vote the block carries is already counted, in which
+    /// case the block should be rejected. Returns the new finalized height,
+    /// if it moved.
+    pub fn connect_block(&mut self, block: &Block, chain: &mut Blockchain, engine: &PoSEngine) -> Result<Option<BlockHeight>> {
+        let data = ConsensusData::decode(&block.header.consensus_data)?;
+        if data.attestations.len() > MAX_ATTESTATIONS_PER_BLOCK {
+            return Err(BlockchainError::InvalidBlock(
+                format!("block carries {} attestations, at most {} allowed", data.attestations.len(), MAX_ATTESTATIONS_PER_BLOCK)
+            ));
+        }
+
+        let mut seen = HashSet::new();
+        for attestation in &data.attestations {
+            if attestation.height >= block.height() {
+                return Err(BlockchainError::InvalidBlock(
+                    format!("attestation of height {} in a block at height {}", attestation.height, block.height())
+                ));
+            }
+            let counted = self.votes.get(&attestation.block_id)
+                .is_some_and(|(_, voters)| voters.contains(&attestation.validator));
+            if counted || !seen.insert((&attestation.validator, attestation.block_id)) {
+                return Err(BlockchainError::InvalidBlock(format!(
+                    "duplicate attestation of {} by {}", attestation.block_id, attestation.validator.encoded()
+                )));
+            }
+            self.check(attestation, chain, engine)
+                .map_err(|e| BlockchainError::InvalidBlock(format!("bad attestation: {}", e)))?;
+        }
+
+        for attestation in &data.attestations {
+            let (_, voters) = self.votes.entry(attestation.block_id).or_insert_with(|| (attestation.height, HashSet::new()));
+            voters.insert(attestation.validator.clone());
+            if let Some(pooled) = self.pool.get_mut(&attestation.block_id) {
+                pooled.remove(&attestation.validator);
+            }
+        }
+
+        let total_stake: Amount = engine.validator_set().iter().map(|(_, stake)| stake).sum();
+        if total_stake == 0 {
+            return Ok(None);
+        }
+        let quorum = u128::from(total_stake) * u128::from(engine.config().required_quorum_pct.min(100));
+        let newly_final = data.attestations.iter()
+            .map(|attestation| (attestation.height, attestation.block_id))
+            .filter(|(height, _)| self.finalized.is_none_or(|(finalized, _)| *height > finalized))
+            .filter(|(_, block_id)| u128::from(self.attested_stake(block_id, engine)) * 100 >= quorum)
+            .max_by_key(|(height, _)| *height);
+
+        let Some((height, block_id)) = newly_final else { return Ok(None) };
+        chain.finalize(height)?;
+        self.finalized = Some((height, block_id));
+        self.prune(height);
+        Ok(Some(height))
+    }
+
+    /// Take back the votes `block` carried as it leaves the main chain. They
+    /// go back to the pool, so a block of the new branch can carry them if
+    /// what they attest is still on the main chain.
+    pub fn disconnect_block(&mut self, block: &Block) -> Result<()> {
+        let data = ConsensusData::decode(&block.header.consensus_data)?;
+        for attestation in data.attestations {
+            // votes at or below the finalized height are already forgotten
+            if self.finalized.is_some_and(|(finalized, _)| attestation.height <= finalized) {
+                continue;
+            }
+            if let Some((_, voters)) = self.votes.get_mut(&attestation.block_id) {
+                voters.remove(&attestation.validator);
+                if voters.is_empty() {
+                    self.votes.remove(&attestation.block_id);
+                }
+            }
+            self.pool.entry(attestation.block_id).or_default()
+                .insert(attestation.validator.clone(), attestation);
+        }
+        // nothing can attest a block that is off the main chain
+        self.pool.remove(&block.id());
+        self.votes.remove(&block.id());
+        Ok(())
+    }
+
+    // an attestation must be for a main chain block above the finalized
+    // height, by a validator of the current epoch, with a valid signature
+    fn check(&self, attestation: &Attestation, chain: &Blockchain, engine: &PoSEngine) -> Result<()> {
+        if let Some((finalized, _)) = self.finalized.filter(|(finalized, _)| attestation.height <= *finalized) {
+            return Err(BlockchainError::ValidationError(
+                format!("attestation of height {} at or below the finalized height {}", attestation.height, finalized)
+            ));
+        }
+        if !on_main_chain(chain, &attestation.block_id, attestation.height) {
+            return Err(BlockchainError::ValidationError(
+                format!("attested block {} is not on the main chain at height {}", attestation.block_id, attestation.height)
+            ));
+        }
+        if !engine.validator_set().iter().any(|(validator, _)| *validator == attestation.validator) {
+            return Err(BlockchainError::ValidationError(
+                format!("{} is not a validator in epoch {}", attestation.validator.encoded(), engine.state().epoch())
+            ));
+        }
+        attestation.verify(engine)
+    }
+
+    // forget votes and pooled attestations at or below the finalized height
+    fn prune(&mut self, finalized: BlockHeight) {
+        self.votes.retain(|_, (height, _)| *height > finalized);
+        self.pool.retain(|_, attestations| attestations.values().any(|attestation| attestation.height > finalized));
+    }
+}
+
+
+fn on_main_chain(chain: &Blockchain, block_id: &BlockId, height: BlockHeight) -> bool {
+    chain.get_block_by_height(&height).is_some_and(|block| block.id() == *block_id)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pos::{PoSConfig, StakingState};
+    use blockchain_core::{ChainConfig, GenesisValidator, Transaction};
+    use blockchain_crypto::{address::public_key_to_address, AddressType};
+    use std::sync::Arc;
+
+    // the default minimum stake
+    const STAKE: Amount = 1_000_000;
+
+    struct Setup {
+        chain: Blockchain,
+        engine: PoSEngine,
+        validators: Vec<(Address, Keypair)>,
+    }
+
+    impl Setup {
+        // a chain of `height` blocks and four validators with equal stake
+        fn new(height: BlockHeight) -> Self {
+            let validators: Vec<(Address, Keypair)> = (0..4)
+                .map(|_| {
+                    let keypair = Keypair::generate();
+                    (public_key_to_address(&keypair.public_key(), AddressType::Base58), keypair)
+                })
+                .collect();
+            let genesis: Vec<GenesisValidator> = validators.iter()
+                .map(|(address, keypair)| GenesisValidator { address: address.clone(), stake: STAKE, public_key: keypair.public_key() })
+                .collect();
+            let engine = PoSEngine::with_state(PoSConfig::default(), StakingState::genesis(&genesis));
+
+            let mut chain = Blockchain::new(ChainConfig::default()).unwrap();
+            for _ in 0..height {
+                chain.mine_block(validators[0].0.clone()).unwrap();
+            }
+            Setup { chain, engine, validators }
+        }
+
+        fn attest(&self, validator: usize, height: BlockHeight) -> Attestation {
+            let (address, keypair) = &self.validators[validator];
+            let block = self.chain.get_block_by_height(&height).unwrap();
+            Attestation::sign(block, address.clone(), &ValidatorKey::Single(keypair.public_key()), keypair).unwrap()
+        }
+
+        // a block at `height` carrying `attestations`; `nonce` tells apart blocks at one height
+        fn carrier(&self, height: BlockHeight, nonce: u64, attestations: Vec<Attestation>) -> Block {
+            let coinbase = Transaction::new_coinbase(self.validators[0].0.clone(), 50, height);
+            let mut block = Block::new(BlockId::genesis(), vec![coinbase], 0, height, 1).unwrap();
+            block.header.nonce = nonce;
+            block.header.consensus_data = ConsensusData { proposal: ProposalSignature::new(), attestations }.encode().unwrap();
+            block
+        }
+
+        fn connect(&mut self, finality: &mut Finality, block: &Block) -> Result<Option<BlockHeight>> {
+            finality.connect_block(block, &mut self.chain, &self.engine)
+        }
+    }
+
+    #[test]
+    fn test_consensus_data_round_trip() {
+        let setup = Setup::new(1);
+        let data = ConsensusData { proposal: ProposalSignature::new(), attestations: vec![setup.attest(1, 1)] };
+        assert_eq!(ConsensusData::decode(&data.encode().unwrap()).unwrap(), data);
+
+        let bare = ConsensusData::default();
+        assert_eq!(bare.encode().unwrap(), ProposalSignature::new().encode().unwrap());
+    }
+
+    #[test]
+    fn test_quorum_finalizes_the_block_and_prunes() {
+        let mut setup = Setup::new(4);
+        let mut finality = Finality::new();
+        let block2 = setup.chain.get_block_by_height(&2).unwrap().id();
+
+        // half the stake is not enough
+        let first = setup.carrier(5, 0, vec![setup.attest(0, 2), setup.attest(1, 2)]);
+        assert_eq!(setup.connect(&mut finality, &first).unwrap(), None);
+        assert_eq!(finality.attested_stake(&block2, &setup.engine), 2 * STAKE);
+
+        assert!(finality.add_attestation(setup.attest(3, 3), &setup.chain, &setup.engine).unwrap());
+        assert!(finality.add_attestation(setup.attest(3, 2), &setup.chain, &setup.engine).unwrap());
+        assert!(!finality.add_attestation(setup.attest(3, 2), &setup.chain, &setup.engine).unwrap());
+        assert_eq!(finality.attestations_for_block(&setup.chain, 10).len(), 2);
+
+        // three of four validators are over the 67% quorum
+        let second = setup.carrier(6, 0, vec![setup.attest(2, 2)]);
+        assert_eq!(setup.connect(&mut finality, &second).unwrap(), Some(2));
+        assert_eq!(finality.finalized(), Some((2, block2)));
+
+        // what is at or below the finalized height is forgotten and refused
+        assert_eq!(finality.attested_stake(&block2, &setup.engine), 0);
+        let pooled = finality.attestations_for_block(&setup.chain, 10);
+        assert_eq!(pooled.len(), 1);
+        assert_eq!((pooled[0].height, &pooled[0].validator), (3, &setup.validators[3].0));
+        assert!(finality.add_attestation(setup.attest(2, 1), &setup.chain, &setup.engine).is_err());
+        let late = setup.carrier(7, 0, vec![setup.attest(3, 2)]);
+        assert!(setup.connect(&mut finality, &late).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_and_invalid_attestations_are_rejected() {
+        let mut setup = Setup::new(3);
+        let mut finality = Finality::new();
+
+        let twice = setup.carrier(4, 0, vec![setup.attest(0, 2), setup.attest(0, 2)]);
+        assert!(setup.connect(&mut finality, &twice).is_err());
+        assert_eq!(finality.attested_stake(&setup.chain.get_block_by_height(&2).unwrap().id(), &setup.engine), 0);
+
+        let once = setup.carrier(4, 1, vec![setup.attest(0, 2)]);
+        setup.connect(&mut finality, &once).unwrap();
+        let again = setup.carrier(5, 0, vec![setup.attest(0, 2)]);
+        assert!(setup.connect(&mut finality, &again).is_err());
+
+        // the block's own height or above, an outsider, a forged signature
+        let own_height = setup.carrier(3, 0, vec![setup.attest(1, 3)]);
+        assert!(setup.connect(&mut finality, &own_height).is_err());
+        let mut outsider = setup.attest(1, 2);
+        outsider.validator = public_key_to_address(&Keypair::generate().public_key(), AddressType::Base58);
+        assert!(finality.add_attestation(outsider, &setup.chain, &setup.engine).is_err());
+        let mut forged = setup.attest(1, 2);
+        forged.validator = setup.validators[2].0.clone();
+        assert!(finality.add_attestation(forged, &setup.chain, &setup.engine).is_err());
+    }
+
+    #[test]
+    fn test_reorg_takes_back_the_votes_of_disconnected_blocks() {
+        let mut setup = Setup::new(3);
+        let mut finality = Finality::new();
+        let block3 = setup.chain.get_block_by_height(&3).unwrap().id();
+
+        let old_branch = setup.carrier(4, 0, vec![setup.attest(0, 3), setup.attest(1, 3)]);
+        setup.connect(&mut finality, &old_branch).unwrap();
+        assert_eq!(finality.attested_stake(&block3, &setup.engine), 2 * STAKE);
+
+        let event = ChainEvent::BlockDisconnected(Arc::new(old_branch));
+        assert_eq!(finality.apply_event(&event, &mut setup.chain, &setup.engine).unwrap(), None);
+        assert_eq!(finality.attested_stake(&block3, &setup.engine), 0);
+        // back in the pool for the new branch to carry
+        assert_eq!(finality.attestations_for_block(&setup.chain, 10).len(), 2);
+
+        let mut attestations = finality.attestations_for_block(&setup.chain, 10);
+        attestations.push(setup.attest(2, 3));
+        let new_branch = setup.carrier(4, 1, attestations);
+        let event = ChainEvent::BlockConnected(Arc::new(new_branch));
+        assert_eq!(finality.apply_event(&event, &mut setup.chain, &setup.engine).unwrap(), Some(3));
+        assert_eq!(finality.finalized(), Some((3, block3)));
+        assert!(finality.attestations_for_block(&setup.chain, 10).is_empty());
+    }
+}
@@ -0,0 +1,15 @@
+pub mod attestation;
+pub mod miner;
+pub mod pos;
+pub mod proposer;
+pub mod slot_clock;
+pub mod validator_keys;
+#[cfg(any(test, feature = "testing"))]
+pub mod sim;
+
+pub use attestation::{Attestation, ConsensusData, Finality, MAX_ATTESTATIONS_PER_BLOCK};
+pub use miner::{Miner, MinerConfig, MinerReport};
+pub use proposer::{Proposer, ProposerConfig, ProposerReport};
+pub use slot_clock::{Slot, SlotClock, SlotTicker, SystemTimeSource, TimeSource, MAX_FUTURE_SLOTS};
+pub use pos::{Epoch, PoSConfig, PoSEngine, StakeChange, StakingState, UnbondingEntry};
+pub use validator_keys::{ProposalSignature, ValidatorKey, MAX_COSIGNERS};
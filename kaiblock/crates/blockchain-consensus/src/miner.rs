@@ -0,0 +1,176 @@
+use blockchain_core::pow::{self, CancelToken};
+use blockchain_core::{Address, Block, Blockchain, BlockchainError, Result};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use tokio::sync::{mpsc, RwLock};
+use tracing::{info, warn};
+
+
+/// How often a running search checks whether the chain tip moved
+const TIP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+
+/// Miner settings
+#[derive(Debug, Clone)]
+pub struct MinerConfig {
+    /// address paid by the coinbase of every block found
+    pub miner_address: Address,
+    /// worker threads searching the nonce space
+    pub threads: usize,
+    /// nonces each thread tries before the template is rebuilt (picks up new
+    /// transactions and a new tip)
+    pub round_iterations: u64,
+}
+
+impl MinerConfig {
+    pub fn new(miner_address: Address) -> Self {
+        Self {
+            miner_address,
+            threads: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+            round_iterations: 1_000_000,
+        }
+    }
+}
+
+
+/// Snapshot of miner counters
+#[derive(Debug, Clone, Copy)]
+pub struct MinerReport {
+    pub hashes: u64,
+    pub blocks_found: u64,
+    pub elapsed: Duration,
+}
+
+impl MinerReport {
+    /// Average hashes per second since the miner started
+    pub fn hash_rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 { 0.0 } else { self.hashes as f64 / secs }
+    }
+}
+
+
+/// Proof-of-work miner: builds a template from the mempool, searches nonces on
+/// `threads` worker threads and submits found blocks through `Blockchain::add_block`.
+pub struct Miner {
+    chain: Arc<RwLock<Blockchain>>,
+    config: MinerConfig,
+    hashes: AtomicU64,
+    blocks_found: AtomicU64,
+    /// extra nonce of the next round's coinbase, so no two rounds search the same header
+    extra_nonce: AtomicU64,
+    started: Instant,
+    /// receives every block found, for announcing to peers
+    found_blocks: Option<mpsc::UnboundedSender<Block>>,
+}
+
+impl Miner {
+    pub fn new(chain: Arc<RwLock<Blockchain>>, config: MinerConfig) -> Self {
+        Self {
+            chain,
+            config,
+            hashes: AtomicU64::new(0),
+            blocks_found: AtomicU64::new(0),
+            extra_nonce: AtomicU64::new(0),
+            started: Instant::now(),
+            found_blocks: None,
+        }
+    }
+
+    /// Send every block found to `found_blocks` once it is on the chain
+    pub fn with_found_blocks(mut self, found_blocks: mpsc::UnboundedSender<Block>) -> Self {
+        self.found_blocks = Some(found_blocks);
+        self
+    }
+
+    pub fn report(&self) -> MinerReport {
+        MinerReport {
+            hashes: self.hashes.load(Ordering::Relaxed),
+            blocks_found: self.blocks_found.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+        }
+    }
+
+    /// Mine until `stop` resolves. The round in progress is cancelled and
+    /// allowed to wind down before this returns.
+    pub async fn run_until(&self, stop: impl Future<Output = ()>) {
+        let cancel = CancelToken::new();
+
+        let mining = async {
+            while !cancel.is_cancelled() {
+                match self.mine_round(&cancel).await {
+                    Ok(Some(block)) => {
+                        info!(block = %block.id(), height = block.header.height, "mined block");
+                        if let Some(found_blocks) = &self.found_blocks {
+                            let _ = found_blocks.send(block);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        warn!(error = %e, "mining round failed");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+        };
+        let stopper = async {
+            stop.await;
+            cancel.cancel();
+        };
+
+        tokio::join!(mining, stopper);
+    }
+
+    /// One round: build a template, search it, submit the block if found.
+    /// The search is abandoned when `stop` is cancelled or the chain tip moves.
+    /// Every round rolls the coinbase extra nonce, so a template that hasn't
+    /// changed since the last round (same second, same transactions) still
+    /// gets a fresh merkle root instead of searching the same nonces again.
+    pub async fn mine_round(&self, stop: &CancelToken) -> Result<Option<Block>> {
+        let (mut block, tip, algorithm) = {
+            let chain = self.chain.read().await;
+            let tip = chain.get_chain_head().map(|head| head.id());
+            (chain.create_block_template(self.config.miner_address)?, tip, chain.validation_rules().block_hash)
+        };
+        block.set_extra_nonce(self.extra_nonce.fetch_add(1, Ordering::Relaxed))?;
+
+        let round = CancelToken::new();
+        let header = block.header.clone();
+        let threads = self.config.threads;
+        let iterations = self.config.round_iterations;
+        let search = {
+            let round = round.clone();
+            tokio::task::spawn_blocking(move || pow::mine_parallel_for(&header, header.difficulty, algorithm, threads, iterations, &round))
+        };
+        tokio::pin!(search);
+
+        let mut poll = tokio::time::interval(TIP_POLL_INTERVAL);
+        let result = loop {
+            tokio::select! {
+                result = &mut search => break result.map_err(|e| BlockchainError::InvalidBlock(
+                    format!("mining threads failed: {}", e)
+                ))?,
+                _ = poll.tick() => {
+                    let moved = self.chain.read().await.get_chain_head().map(|head| head.id()) != tip;
+                    if moved || stop.is_cancelled() {
+                        round.cancel();
+                    }
+                }
+            }
+        };
+        self.hashes.fetch_add(result.hashes, Ordering::Relaxed);
+
+        let header = match result.header {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        block.header = header;
+
+        // the tip may have moved since the last poll; add_block sorts that out
+        self.chain.write().await.add_block(block.clone())?;
+        self.blocks_found.fetch_add(1, Ordering::Relaxed);
+        Ok(Some(block))
+    }
+}
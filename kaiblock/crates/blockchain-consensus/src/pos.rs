@@ -0,0 +1,393 @@
+use crate::validator_keys::ValidatorKey;
+use blockchain_core::{Address, Amount, BlockHeader, BlockHeight, BlockchainError, ChainConfig, GenesisValidator, Result, StakingParams};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+
+/// Epoch number; epoch `n` covers heights `n * epoch_length .. (n + 1) * epoch_length`
+pub type Epoch = u64;
+
+
+/// Proof-of-stake parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoSConfig {
+    /// blocks per epoch; stake changes take effect at the first block of an epoch
+    pub epoch_length: BlockHeight,
+    /// epochs between an unbond taking effect and the funds becoming withdrawable
+    pub unbonding_period: Epoch,
+    /// smallest active stake that makes a validator
+    pub min_stake: Amount,
+    /// largest validator set; the highest stakes win
+    pub max_validators: usize,
+    /// percent of the validator set's stake whose attestations finalize a block
+    #[serde(default = "default_quorum_pct")]
+    pub required_quorum_pct: u8,
+}
+
+impl Default for PoSConfig {
+    fn default() -> Self {
+        Self {
+            epoch_length: 100,
+            unbonding_period: 7,
+            min_stake: 1_000_000,
+            max_validators: 100,
+            required_quorum_pct: default_quorum_pct(),
+        }
+    }
+}
+
+fn default_quorum_pct() -> u8 {
+    67
+}
+
+impl From<&StakingParams> for PoSConfig {
+    fn from(params: &StakingParams) -> Self {
+        Self {
+            epoch_length: params.epoch_length,
+            unbonding_period: params.unbonding_period,
+            min_stake: params.min_stake,
+            max_validators: params.max_validators,
+            required_quorum_pct: params.required_quorum_pct,
+        }
+    }
+}
+
+
+/// Bond or unbond request from a staking transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StakeChange {
+    Bond { staker: Address, amount: Amount },
+    Unbond { staker: Address, amount: Amount },
+}
+
+
+/// Stake released by an unbond, waiting out the unbonding period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnbondingEntry {
+    pub staker: Address,
+    pub amount: Amount,
+    /// first epoch in which the amount can be withdrawn
+    pub withdrawable_at: Epoch,
+}
+
+
+/// Stake bookkeeping across epochs.
+///
+/// Bonds and unbonds are queued as they are seen and only applied at the next
+/// epoch boundary, so the validator set is fixed for a whole epoch. Unbonded
+/// stake stops counting immediately at the boundary but stays locked for
+/// `unbonding_period` epochs, long enough to slash misbehaviour from the epochs
+/// it was still securing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StakingState {
+    epoch: Epoch,
+    /// stake counting towards the current epoch's validator set
+    active: HashMap<Address, Amount>,
+    /// changes that take effect at the next epoch
+    pending: Vec<StakeChange>,
+    unbonding: Vec<UnbondingEntry>,
+    /// keys each validator signs proposals with
+    #[serde(default)]
+    keys: HashMap<Address, ValidatorKey>,
+}
+
+impl StakingState {
+    /// Epoch 0 with the genesis validators bonded and their keys registered
+    pub fn genesis(validators: &[GenesisValidator]) -> Self {
+        let mut state = Self::default();
+        for validator in validators {
+            *state.active.entry(validator.address.clone()).or_insert(0) += validator.stake;
+            state.keys.insert(validator.address.clone(), ValidatorKey::Single(validator.public_key.clone()));
+        }
+        state
+    }
+
+    pub fn epoch(&self) -> Epoch {
+        self.epoch
+    }
+
+    /// Active stake of `staker` in the current epoch
+    pub fn stake_of(&self, staker: &Address) -> Amount {
+        self.active.get(staker).copied().unwrap_or(0)
+    }
+
+    pub fn total_stake(&self) -> Amount {
+        self.active.values().sum()
+    }
+
+    pub fn pending(&self) -> &[StakeChange] {
+        &self.pending
+    }
+
+    pub fn unbonding(&self) -> &[UnbondingEntry] {
+        &self.unbonding
+    }
+
+    /// Active stake of `staker` once the queued changes are applied
+    pub fn next_epoch_stake(&self, staker: &Address) -> Amount {
+        self.pending.iter().fold(self.stake_of(staker), |stake, change| match change {
+            StakeChange::Bond { staker: s, amount } if s == staker => stake.saturating_add(*amount),
+            StakeChange::Unbond { staker: s, amount } if s == staker => stake.saturating_sub(*amount),
+            _ => stake,
+        })
+    }
+
+    /// Queue a stake change for the next epoch. An unbond can't exceed what
+    /// the staker will have bonded at that point.
+    pub fn queue(&mut self, change: StakeChange) -> Result<()> {
+        match &change {
+            StakeChange::Bond { amount, .. } | StakeChange::Unbond { amount, .. } if *amount == 0 => {
+                return Err(BlockchainError::ValidationError("stake change of zero".to_string()));
+            }
+            StakeChange::Unbond { staker, amount } => {
+                let available = self.next_epoch_stake(staker);
+                if *amount > available {
+                    return Err(BlockchainError::InsufficientBalance { required: *amount, available });
+                }
+            }
+            StakeChange::Bond { .. } => {}
+        }
+        self.pending.push(change);
+        Ok(())
+    }
+
+    /// Move to `epoch`, applying the queued changes in the order they were queued
+    pub fn begin_epoch(&mut self, epoch: Epoch, config: &PoSConfig) {
+        for change in std::mem::take(&mut self.pending) {
+            match change {
+                StakeChange::Bond { staker, amount } => {
+                    let stake = self.active.entry(staker).or_insert(0);
+                    *stake = stake.saturating_add(amount);
+                }
+                StakeChange::Unbond { staker, amount } => {
+                    let stake = self.active.entry(staker.clone()).or_insert(0);
+                    let amount = amount.min(*stake);
+                    *stake -= amount;
+                    if *stake == 0 {
+                        self.active.remove(&staker);
+                    }
+                    self.unbonding.push(UnbondingEntry {
+                        staker,
+                        amount,
+                        withdrawable_at: epoch + config.unbonding_period,
+                    });
+                }
+            }
+        }
+        self.epoch = epoch;
+    }
+
+    /// Unbonded stake `staker` can withdraw now
+    pub fn withdrawable(&self, staker: &Address) -> Amount {
+        self.unbonding.iter()
+            .filter(|entry| entry.staker == *staker && entry.withdrawable_at <= self.epoch)
+            .map(|entry| entry.amount)
+            .sum()
+    }
+
+    /// Release `staker`'s withdrawable stake; returns the amount released
+    pub fn withdraw(&mut self, staker: &Address) -> Amount {
+        let amount = self.withdrawable(staker);
+        let epoch = self.epoch;
+        self.unbonding.retain(|entry| entry.staker != *staker || entry.withdrawable_at > epoch);
+        amount
+    }
+
+    /// Set the keys `validator` signs proposals with, replacing any before.
+    /// The caller checks the change is authorized by the validator.
+    pub fn register_key(&mut self, validator: Address, key: ValidatorKey) {
+        self.keys.insert(validator, key);
+    }
+
+    pub fn validator_key(&self, validator: &Address) -> Option<&ValidatorKey> {
+        self.keys.get(validator)
+    }
+
+    /// Validators of the current epoch, highest stake first (ties broken by address)
+    pub fn validator_set(&self, config: &PoSConfig) -> Vec<(Address, Amount)> {
+        let mut validators: Vec<(Address, Amount)> = self.active.iter()
+            .filter(|(_, stake)| **stake >= config.min_stake)
+            .map(|(address, stake)| (address.clone(), *stake))
+            .collect();
+        validators.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.encoded().cmp(b.0.encoded())));
+        validators.truncate(config.max_validators);
+        validators
+    }
+}
+
+
+/// Proof-of-stake engine: tracks epochs as blocks are connected and applies
+/// stake changes at epoch boundaries.
+#[derive(Debug, Clone)]
+pub struct PoSEngine {
+    config: PoSConfig,
+    state: StakingState,
+}
+
+impl PoSEngine {
+    pub fn new(config: PoSConfig) -> Self {
+        Self::with_state(config, StakingState::default())
+    }
+
+    /// Start from the staking parameters and validators of a chain config;
+    /// None if the chain isn't proof of stake
+    pub fn from_chain_config(config: &ChainConfig) -> Option<Self> {
+        let params = config.staking.as_ref()?;
+        Some(Self::with_state(params.into(), StakingState::genesis(&config.genesis.validators)))
+    }
+
+    /// Resume from saved staking state
+    pub fn with_state(config: PoSConfig, state: StakingState) -> Self {
+        Self { config, state }
+    }
+
+    pub fn config(&self) -> &PoSConfig {
+        &self.config
+    }
+
+    pub fn state(&self) -> &StakingState {
+        &self.state
+    }
+
+    pub fn epoch_of(&self, height: BlockHeight) -> Epoch {
+        height / self.config.epoch_length.max(1)
+    }
+
+    /// Whether `height` is the first block of an epoch
+    pub fn is_epoch_boundary(&self, height: BlockHeight) -> bool {
+        height % self.config.epoch_length.max(1) == 0
+    }
+
+    /// Connect the block at `height` carrying `changes`. Entering a new epoch
+    /// applies everything queued before this block; this block's own changes
+    /// wait for the next boundary.
+    pub fn process_block(&mut self, height: BlockHeight, changes: impl IntoIterator<Item = StakeChange>) -> Result<()> {
+        let epoch = self.epoch_of(height);
+        if epoch < self.state.epoch {
+            return Err(BlockchainError::InvalidChain(
+                format!("block at height {} is in epoch {}, before the current epoch {}", height, epoch, self.state.epoch)
+            ));
+        }
+        if epoch > self.state.epoch {
+            self.state.begin_epoch(epoch, &self.config);
+        }
+
+        for change in changes {
+            self.state.queue(change)?;
+        }
+        Ok(())
+    }
+
+    /// Release `staker`'s stake whose unbonding period is over
+    pub fn withdraw(&mut self, staker: &Address) -> Amount {
+        self.state.withdraw(staker)
+    }
+
+    /// Validators of the current epoch, highest stake first
+    pub fn validator_set(&self) -> Vec<(Address, Amount)> {
+        self.state.validator_set(&self.config)
+    }
+
+    /// Set the (possibly m-of-n) key `validator` signs proposals with
+    pub fn register_validator_key(&mut self, validator: Address, key: ValidatorKey) {
+        self.state.register_key(validator, key);
+    }
+
+    /// Check `header` was proposed by `proposer`, a validator of the current
+    /// epoch, and carries the signatures its registered key requires
+    pub fn verify_proposal(&self, proposer: &Address, header: &BlockHeader) -> Result<()> {
+        if !self.validator_set().iter().any(|(validator, _)| validator == proposer) {
+            return Err(BlockchainError::InvalidBlock(
+                format!("proposer {} is not a validator in epoch {}", proposer.encoded(), self.state.epoch)
+            ));
+        }
+        let key = self.state.validator_key(proposer).ok_or_else(|| BlockchainError::InvalidBlock(
+            format!("validator {} has no registered proposal key", proposer.encoded())
+        ))?;
+        key.verify_header(header)
+            .map_err(|e| BlockchainError::InvalidBlock(format!("bad proposal signature: {}", e)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{signature::Keypair, AddressType};
+
+    fn address() -> Address {
+        Address::from_public_key(Keypair::generate().public_key(), AddressType::Base58)
+    }
+
+    fn engine(unbonding_period: Epoch) -> PoSEngine {
+        PoSEngine::new(PoSConfig { epoch_length: 10, unbonding_period, min_stake: 100, ..PoSConfig::default() })
+    }
+
+    #[test]
+    fn test_stake_changes_wait_for_the_next_epoch() {
+        let mut pos = engine(2);
+        let alice = address();
+        pos.process_block(3, [StakeChange::Bond { staker: alice.clone(), amount: 500 }]).unwrap();
+
+        // the rest of epoch 0 still runs with the old set
+        for height in 4..10 {
+            pos.process_block(height, []).unwrap();
+            assert_eq!(pos.state().stake_of(&alice), 0);
+            assert!(pos.validator_set().is_empty());
+        }
+        assert_eq!(pos.state().next_epoch_stake(&alice), 500);
+
+        // a change made in the boundary block itself waits a whole epoch
+        pos.process_block(10, [StakeChange::Bond { staker: alice.clone(), amount: 200 }]).unwrap();
+        assert_eq!(pos.state().epoch(), 1);
+        assert_eq!(pos.validator_set(), vec![(alice.clone(), 500)]);
+
+        pos.process_block(19, []).unwrap();
+        assert_eq!(pos.state().stake_of(&alice), 500);
+        pos.process_block(20, []).unwrap();
+        assert_eq!(pos.state().stake_of(&alice), 700);
+
+        // going back an epoch is refused
+        assert!(pos.process_block(9, []).is_err());
+    }
+
+    #[test]
+    fn test_unbonded_stake_is_withdrawable_after_the_unbonding_period() {
+        let mut pos = engine(3);
+        let bob = address();
+        pos.process_block(1, [StakeChange::Bond { staker: bob.clone(), amount: 1_000 }]).unwrap();
+        pos.process_block(10, [StakeChange::Unbond { staker: bob.clone(), amount: 400 }]).unwrap();
+        assert_eq!(pos.state().stake_of(&bob), 1_000);
+        assert!(pos.process_block(11, [StakeChange::Unbond { staker: bob.clone(), amount: 601 }]).is_err());
+
+        // epoch 2: stake stops counting at once but stays locked
+        pos.process_block(20, []).unwrap();
+        assert_eq!(pos.state().stake_of(&bob), 600);
+        assert_eq!(pos.state().unbonding(), &[UnbondingEntry { staker: bob.clone(), amount: 400, withdrawable_at: 5 }]);
+
+        for height in [30, 40, 49] {
+            pos.process_block(height, []).unwrap();
+            assert_eq!(pos.state().withdrawable(&bob), 0);
+            assert_eq!(pos.withdraw(&bob), 0);
+        }
+
+        pos.process_block(50, []).unwrap();
+        assert_eq!(pos.state().withdrawable(&bob), 400);
+        assert_eq!(pos.withdraw(&bob), 400);
+        assert_eq!(pos.withdraw(&bob), 0);
+        assert!(pos.state().unbonding().is_empty());
+    }
+
+    #[test]
+    fn test_validator_set_honours_min_stake_and_size() {
+        let mut pos = PoSEngine::new(PoSConfig { epoch_length: 10, min_stake: 100, max_validators: 2, ..PoSConfig::default() });
+        let (big, mid, small, dust) = (address(), address(), address(), address());
+        let bonds = [(&big, 300), (&mid, 200), (&small, 150), (&dust, 99)]
+            .map(|(staker, amount)| StakeChange::Bond { staker: staker.clone(), amount });
+        pos.process_block(1, bonds).unwrap();
+        pos.process_block(10, []).unwrap();
+
+        assert_eq!(pos.validator_set(), vec![(big, 300), (mid, 200)]);
+        assert_eq!(pos.state().total_stake(), 749);
+    }
+}
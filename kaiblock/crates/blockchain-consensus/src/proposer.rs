@@ -0,0 +1,356 @@
+use crate::attestation::{Attestation, ConsensusData, Finality, MAX_ATTESTATIONS_PER_BLOCK};
+use crate::slot_clock::{Slot, SlotClock, SystemTimeSource, TimeSource};
+use crate::validator_keys::{ProposalSignature, ValidatorKey};
+use blockchain_core::{Address, Block, BlockchainError, Blockchain, Result};
+use blockchain_crypto::signature::Keypair;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
+use tracing::{info, info_span, warn, Instrument};
+
+
+/// Proposer settings.
+///
+/// Building starts as soon as the slot does. Transaction selection gets at
+/// most `selection_budget` of it, and the signed block must be out before
+/// `broadcast_cutoff` into the slot: a block that is later than that would
+/// likely arrive after the next proposer has started, so the slot is given up
+/// instead and counted as missed.
+#[derive(Debug, Clone)]
+pub struct ProposerConfig {
+    /// address paid by the coinbase of every block proposed
+    pub proposer_address: Address,
+    pub clock: SlotClock,
+    /// longest transaction selection may take
+    pub selection_budget: Duration,
+    /// how far into the slot the block may still be broadcast
+    pub broadcast_cutoff: Duration,
+}
+
+impl ProposerConfig {
+    pub fn new(proposer_address: Address, clock: SlotClock) -> Self {
+        Self {
+            proposer_address,
+            clock,
+            selection_budget: clock.slot_duration() / 4,
+            broadcast_cutoff: clock.slot_duration() / 2,
+        }
+    }
+}
+
+
+/// Snapshot of proposer counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProposerReport {
+    pub proposed: u64,
+    /// slots given up because building and signing ran past the broadcast cutoff
+    pub missed_overrun: u64,
+    /// slots given up because the proposer only got to them after the cutoff
+    pub missed_late_start: u64,
+    /// proposals whose transaction selection was cut short by the selection budget
+    pub selection_truncated: u64,
+    /// how far into its slot the latest proposal was broadcast
+    pub last_broadcast_offset: Duration,
+}
+
+impl ProposerReport {
+    pub fn missed(&self) -> u64 {
+        self.missed_overrun + self.missed_late_start
+    }
+}
+
+
+/// Proof-of-stake proposer: in each slot it leads, builds a block from the
+/// mempool, signs it with the validator key and hands it to `broadcast`
+/// within the slot's deadlines. It proposes at most one block per slot, stamped
+/// with the slot's start time.
+pub struct Proposer {
+    chain: Arc<RwLock<Blockchain>>,
+    config: ProposerConfig,
+    key: ValidatorKey,
+    keypair: Keypair,
+    broadcast: mpsc::UnboundedSender<Block>,
+    proposed: AtomicU64,
+    missed_overrun: AtomicU64,
+    missed_late_start: AtomicU64,
+    selection_truncated: AtomicU64,
+    last_broadcast_micros: AtomicU64,
+    /// one past the last slot a proposal was attempted in
+    next_slot: AtomicU64,
+    /// pooled attestations to aggregate into proposals
+    finality: Option<Arc<RwLock<Finality>>>,
+    /// clock the slot deadlines are checked against
+    time: Arc<dyn TimeSource>,
+}
+
+impl Proposer {
+    /// `keypair` must be enough to sign for `key` on its own, i.e. `key` is a
+    /// single key or a 1-of-n threshold key containing it
+    pub fn new(
+        chain: Arc<RwLock<Blockchain>>,
+        config: ProposerConfig,
+        key: ValidatorKey,
+        keypair: Keypair,
+        broadcast: mpsc::UnboundedSender<Block>,
+    ) -> Self {
+        Self {
+            chain,
+            config,
+            key,
+            keypair,
+            broadcast,
+            proposed: AtomicU64::new(0),
+            missed_overrun: AtomicU64::new(0),
+            missed_late_start: AtomicU64::new(0),
+            selection_truncated: AtomicU64::new(0),
+            last_broadcast_micros: AtomicU64::new(0),
+            next_slot: AtomicU64::new(0),
+            finality: None,
+            time: Arc::new(SystemTimeSource),
+        }
+    }
+
+    /// Pack the attestations pooled in `finality` into every proposal
+    pub fn with_finality(mut self, finality: Arc<RwLock<Finality>>) -> Self {
+        self.finality = Some(finality);
+        self
+    }
+
+    /// Check deadlines against `time` instead of the system clock
+    pub fn with_time_source(mut self, time: Arc<dyn TimeSource>) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn config(&self) -> &ProposerConfig {
+        &self.config
+    }
+
+    pub fn report(&self) -> ProposerReport {
+        ProposerReport {
+            proposed: self.proposed.load(Ordering::Relaxed),
+            missed_overrun: self.missed_overrun.load(Ordering::Relaxed),
+            missed_late_start: self.missed_late_start.load(Ordering::Relaxed),
+            selection_truncated: self.selection_truncated.load(Ordering::Relaxed),
+            last_broadcast_offset: Duration::from_micros(self.last_broadcast_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Propose in every slot `is_leader` assigns to this validator until
+    /// `stop` resolves
+    pub async fn run_until(&self, stop: impl Future<Output = ()>, is_leader: impl Fn(Slot) -> bool) {
+        tokio::pin!(stop);
+        let mut ticker = self.config.clock.ticker(self.time.clone());
+        loop {
+            let slot = tokio::select! {
+                _ = &mut stop => return,
+                slot = ticker.tick() => slot,
+            };
+            let slot_start = self.time.instant_of(self.config.clock.slot_start(slot));
+            if !is_leader(slot) {
+                continue;
+            }
+
+            match self.propose(slot, slot_start).instrument(info_span!("slot", slot)).await {
+                Ok(Some(block)) => info!(slot, block = %block.id(), height = block.header.height, "proposed block"),
+                Ok(None) => {}
+                Err(e) => warn!(slot, error = %e, "proposal failed"),
+            }
+        }
+    }
+
+    /// Build, sign, import and broadcast the block for `slot`, which started
+    /// at `slot_start`. Returns None if the slot was given up for missing its
+    /// deadlines, or because this proposer already tried a slot this late.
+    pub async fn propose(&self, slot: Slot, slot_start: Instant) -> Result<Option<Block>> {
+        if self.next_slot.fetch_max(slot + 1, Ordering::SeqCst) > slot {
+            warn!("already proposed in this slot or a later one");
+            return Ok(None);
+        }
+        let cutoff = slot_start + self.config.broadcast_cutoff;
+        if self.time.instant() >= cutoff {
+            self.missed_late_start.fetch_add(1, Ordering::Relaxed);
+            warn!("missed slot: started after the broadcast cutoff");
+            return Ok(None);
+        }
+
+        let selection_deadline = (self.time.instant() + self.config.selection_budget).min(cutoff);
+        let (mut block, attestations) = {
+            let chain = self.chain.read().await;
+            let block = chain.create_block_template_until(self.config.proposer_address, Some(selection_deadline))?;
+            let attestations = match &self.finality {
+                Some(finality) => finality.read().await.attestations_for_block(&chain, MAX_ATTESTATIONS_PER_BLOCK),
+                None => Vec::new(),
+            };
+            (block, attestations)
+        };
+        if self.time.instant() >= selection_deadline {
+            self.selection_truncated.fetch_add(1, Ordering::Relaxed);
+        }
+        block.header.timestamp = self.config.clock.block_timestamp(slot);
+        self.sign(&mut block, attestations)?;
+
+        let now = self.time.instant();
+        if now >= cutoff {
+            self.missed_overrun.fetch_add(1, Ordering::Relaxed);
+            warn!(late = ?now.duration_since(cutoff), "missed slot: block was ready after the cutoff");
+            return Ok(None);
+        }
+        self.chain.write().await.add_block(block.clone())?;
+        // nobody listening only means this node isn't connected yet
+        let _ = self.broadcast.send(block.clone());
+
+        self.proposed.fetch_add(1, Ordering::Relaxed);
+        let offset = self.time.instant().saturating_duration_since(slot_start).as_micros().min(u64::MAX as u128) as u64;
+        self.last_broadcast_micros.store(offset, Ordering::Relaxed);
+        Ok(Some(block))
+    }
+
+    //put the proposal signature over the header and the attestations into consensus_data
+    fn sign(&self, block: &mut Block, attestations: Vec<Attestation>) -> Result<()> {
+        let message = block.header.signing_hash();
+        let mut proposal = ProposalSignature::new();
+        proposal.add(&self.key, message.as_bytes(), &self.keypair.public_key(), self.keypair.sign(message.as_bytes()))?;
+        if !proposal.is_complete(&self.key) {
+            return Err(BlockchainError::ValidationError(
+                format!("validator key needs {} signatures, this proposer holds one", self.key.required_signatures())
+            ));
+        }
+        block.header.consensus_data = ConsensusData { proposal, attestations }.encode()?;
+        Ok(())
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::VirtualClock;
+    use blockchain_core::{ChainConfig, GenesisValidator, StakingParams};
+    use blockchain_crypto::{address::public_key_to_address, AddressType};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const GENESIS_SECS: u64 = 1_700_000_000;
+
+    /// Virtual clock that moves `step` forward every time the proposer reads
+    /// it, standing in for work that takes time
+    struct SteppingClock {
+        clock: VirtualClock,
+        step: Duration,
+    }
+
+    impl TimeSource for SteppingClock {
+        fn now(&self) -> SystemTime {
+            self.clock.now()
+        }
+
+        fn instant(&self) -> Instant {
+            self.clock.advance(self.step);
+            self.clock.instant()
+        }
+    }
+
+    struct Setup {
+        proposer: Proposer,
+        chain: Arc<RwLock<Blockchain>>,
+        clock: VirtualClock,
+        blocks: mpsc::UnboundedReceiver<Block>,
+    }
+
+    impl Setup {
+        fn slot_start(&self, slot: Slot) -> Instant {
+            self.clock.instant_of(self.proposer.config().clock.slot_start(slot))
+        }
+    }
+
+    // a lone validator with 6 second slots, the selection budget and
+    // cutoff at their defaults of 1.5 and 3 seconds, and its clock at the
+    // start of slot 1 reading time off a clock moving `step` per read
+    fn setup(step: Duration) -> Setup {
+        let keypair = Keypair::generate();
+        let address = public_key_to_address(&keypair.public_key(), AddressType::Base58);
+        let mut config = ChainConfig::default();
+        config.genesis.timestamp = Some(GENESIS_SECS as i64);
+        config.genesis.genesis_difficulty = 0;
+        config.mining.target_block_time = 6;
+        config.validation_rules.target_block_time = 6;
+        config.staking = Some(StakingParams::default());
+        config.genesis.validators = vec![GenesisValidator {
+            address: address.clone(),
+            stake: StakingParams::default().min_stake,
+            public_key: keypair.public_key(),
+        }];
+
+        let slots = SlotClock::from_chain_config(&config).unwrap();
+        let clock = VirtualClock::new(UNIX_EPOCH + Duration::from_secs(GENESIS_SECS));
+        clock.advance_to(slots.slot_start(1));
+        let chain = Arc::new(RwLock::new(Blockchain::new(config).unwrap()));
+        let (broadcast, blocks) = mpsc::unbounded_channel();
+        let key = ValidatorKey::Single(keypair.public_key());
+        let proposer = Proposer::new(chain.clone(), ProposerConfig::new(address, slots), key, keypair, broadcast)
+            .with_time_source(Arc::new(SteppingClock { clock: clock.clone(), step }));
+        Setup { proposer, chain, clock, blocks }
+    }
+
+    #[tokio::test]
+    async fn test_proposes_once_per_slot() {
+        let mut setup = setup(Duration::ZERO);
+        let start = setup.slot_start(1);
+
+        let block = setup.proposer.propose(1, start).await.unwrap().expect("proposed");
+        assert_eq!(block.header.timestamp, setup.proposer.config().clock.block_timestamp(1));
+        assert_eq!(setup.chain.read().await.height(), 1);
+        assert_eq!(setup.blocks.try_recv().unwrap().id(), block.id());
+
+        // neither the same slot nor an earlier one again
+        assert!(setup.proposer.propose(1, start).await.unwrap().is_none());
+        assert!(setup.proposer.propose(0, setup.slot_start(0)).await.unwrap().is_none());
+        let report = setup.proposer.report();
+        assert_eq!((report.proposed, report.missed(), report.selection_truncated), (1, 0, 0));
+        assert_eq!(report.last_broadcast_offset, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_slot_started_after_the_cutoff_is_missed() {
+        let mut setup = setup(Duration::ZERO);
+        let start = setup.slot_start(1);
+        setup.clock.advance(setup.proposer.config().broadcast_cutoff);
+
+        assert!(setup.proposer.propose(1, start).await.unwrap().is_none());
+        let report = setup.proposer.report();
+        assert_eq!((report.proposed, report.missed_late_start, report.missed_overrun), (0, 1, 0));
+        assert_eq!(setup.chain.read().await.height(), 0);
+        assert!(setup.blocks.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_block_ready_after_the_cutoff_is_dropped() {
+        // a second per step: selection runs out of budget and signing ends at the cutoff
+        let mut setup = setup(Duration::from_secs(1));
+        let start = setup.slot_start(1);
+
+        assert!(setup.proposer.propose(1, start).await.unwrap().is_none());
+        let report = setup.proposer.report();
+        assert_eq!((report.proposed, report.missed_late_start, report.missed_overrun), (0, 0, 1));
+        assert_eq!(report.selection_truncated, 1);
+        assert_eq!(setup.chain.read().await.height(), 0);
+        assert!(setup.blocks.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_selection_budget_cuts_selection_short() {
+        let mut setup = setup(Duration::from_millis(500));
+        setup.proposer.config.selection_budget = Duration::from_millis(400);
+        let start = setup.slot_start(1);
+
+        assert!(setup.proposer.propose(1, start).await.unwrap().is_some());
+        let report = setup.proposer.report();
+        assert_eq!((report.proposed, report.missed(), report.selection_truncated), (1, 0, 1));
+        // five reads of the clock, the last one after the broadcast
+        assert_eq!(report.last_broadcast_offset, Duration::from_millis(2_500));
+        assert!(setup.blocks.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,521 @@
+//! Deterministic simulation of proof-of-stake slots (enabled with the `testing` feature).
+//!
+//! A [`Simulation`] runs validators, each with its own chain, [`PoSEngine`]
+//! and [`Proposer`], against a [`VirtualClock`] instead of the system clock.
+//! Time only moves when the simulation handles its next event (a slot
+//! starting, a leader getting round to its proposal, a block reaching a peer),
+//! so a run takes no real time and the same script with the same seed plays
+//! out the same way every run. The script sets link latencies, delays a leader's start in a
+//! slot (to push it past the broadcast cutoff), takes validators offline and
+//! can replace the leader schedule.
+//!
+//! A proposer sends its block straight to every other validator; nothing is
+//! relayed or synced, so a validator that misses a block refuses its
+//! descendants and builds on its own head.
+//!
+//! ```ignore
+//! let mut sim = Simulation::new(SimConfig::default())?;
+//! sim.set_latency(0, 1, Duration::from_secs(4));
+//! sim.run_slots(10).await;
+//! assert!(sim.is_converged().await);
+//! ```
+
+use crate::pos::PoSEngine;
+use crate::proposer::{Proposer, ProposerConfig, ProposerReport};
+use crate::slot_clock::{Slot, SlotClock, TimeSource};
+use crate::validator_keys::ValidatorKey;
+use blockchain_core::{Address, Amount, Block, BlockId, BlockchainError, Blockchain, ChainConfig, GenesisValidator, Result, StakingParams};
+use blockchain_crypto::address::public_key_to_address;
+use blockchain_crypto::hash::sha256;
+use blockchain_crypto::signature::Keypair;
+use blockchain_crypto::AddressType;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, RwLock};
+
+
+/// Clock that only moves when told to. Clones share the same time.
+///
+/// Transaction selection in blockchain-core still stops on the system
+/// clock; a selection deadline read off this clock lies ahead of it, so
+/// selection is never cut short in a simulation.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    start: SystemTime,
+    // Instant standing for `start`
+    origin: Instant,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl VirtualClock {
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            start,
+            origin: Instant::now(),
+            elapsed_nanos: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Time passed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let nanos = by.as_nanos().min(u64::MAX as u128) as u64;
+        self.elapsed_nanos.fetch_add(nanos, Ordering::SeqCst);
+    }
+
+    /// Move forward to `time`; a time already passed leaves the clock as it is
+    pub fn advance_to(&self, time: SystemTime) {
+        let since_start = time.duration_since(self.start).unwrap_or_default();
+        let nanos = since_start.as_nanos().min(u64::MAX as u128) as u64;
+        self.elapsed_nanos.fetch_max(nanos, Ordering::SeqCst);
+    }
+}
+
+impl TimeSource for VirtualClock {
+    fn now(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn instant(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+}
+
+
+/// Simulation settings
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub validators: usize,
+    /// stake each validator bonds at genesis
+    pub stake: Amount,
+    /// whole seconds, as block timestamps have second resolution
+    pub slot_duration: Duration,
+    pub genesis_time: SystemTime,
+    /// latency of every link without one set
+    pub latency: Duration,
+    /// validator keys, and so the leader order, are derived from it
+    pub seed: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            validators: 4,
+            stake: StakingParams::default().min_stake,
+            slot_duration: Duration::from_secs(6),
+            genesis_time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            latency: Duration::from_millis(200),
+            seed: 0,
+        }
+    }
+}
+
+
+/// What happened in a simulation, in the order it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimEvent {
+    /// `validator` proposed the block at `height` for `slot`
+    Proposed { slot: Slot, validator: usize, height: u64 },
+    /// the slot's leader was offline or gave up the slot for missing its deadlines
+    Missed { slot: Slot, validator: usize },
+    /// the leader's proposal failed
+    Failed { slot: Slot, validator: usize, reason: String },
+    /// `validator` imported the block `from` proposed for `slot`
+    Imported { slot: Slot, validator: usize, from: usize },
+    /// `validator` refused the block `from` proposed for `slot`
+    Rejected { slot: Slot, validator: usize, from: usize, reason: String },
+}
+
+/// Leader of each slot, by validator index
+pub type LeaderSchedule = Box<dyn Fn(Slot) -> usize + Send + Sync>;
+
+enum Action {
+    StartSlot(Slot),
+    Propose { validator: usize, slot: Slot },
+    Deliver { to: usize, from: usize, block: Block },
+}
+
+struct SimValidator {
+    address: Address,
+    chain: Arc<RwLock<Blockchain>>,
+    engine: PoSEngine,
+    proposer: Proposer,
+    online: bool,
+}
+
+
+/// Validators proposing and importing blocks slot by slot on virtual time
+pub struct Simulation {
+    time: VirtualClock,
+    slots: SlotClock,
+    validators: Vec<SimValidator>,
+    // actions by when they happen, then by when they were scheduled
+    queue: BTreeMap<(SystemTime, u64), Action>,
+    scheduled: u64,
+    // first slot that hasn't started
+    next_slot: Slot,
+    default_latency: Duration,
+    latency: HashMap<(usize, usize), Duration>,
+    delays: HashMap<(usize, Slot), Duration>,
+    leader_schedule: Option<LeaderSchedule>,
+    events: Vec<SimEvent>,
+}
+
+impl Simulation {
+    /// Validators bonded at genesis with keys derived from the seed and their index, at
+    /// the genesis time with slot 1 about to start
+    pub fn new(config: SimConfig) -> Result<Self> {
+        if config.validators == 0 {
+            return Err(BlockchainError::ValidationError("a simulation needs at least one validator".to_string()));
+        }
+        let keypairs: Vec<Keypair> = (0..config.validators)
+            .map(|index| Keypair::from_private_bytes(sha256(format!("sim-validator-{}-{}", config.seed, index).as_bytes()).as_bytes()))
+            .collect::<std::result::Result<_, _>>()
+            .map_err(|e| BlockchainError::ValidationError(format!("validator key: {}", e)))?;
+
+        let mut chain_config = ChainConfig::default();
+        let genesis_secs = config.genesis_time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        chain_config.genesis.timestamp = Some(genesis_secs as i64);
+        chain_config.genesis.genesis_difficulty = 0;
+        chain_config.mining.target_block_time = config.slot_duration.as_secs().max(1);
+        chain_config.validation_rules.target_block_time = chain_config.mining.target_block_time;
+        chain_config.staking = Some(StakingParams { min_stake: config.stake, ..StakingParams::default() });
+        chain_config.genesis.validators = keypairs.iter()
+            .map(|keypair| GenesisValidator {
+                address: public_key_to_address(&keypair.public_key(), AddressType::Base58),
+                stake: config.stake,
+                public_key: keypair.public_key(),
+            })
+            .collect();
+
+        let time = VirtualClock::new(config.genesis_time);
+        let slots = SlotClock::from_chain_config(&chain_config).expect("the genesis time is set");
+        let mut validators = Vec::with_capacity(config.validators);
+        for keypair in keypairs {
+            let address = public_key_to_address(&keypair.public_key(), AddressType::Base58);
+            let chain = Arc::new(RwLock::new(Blockchain::new(chain_config.clone())?));
+            let engine = PoSEngine::from_chain_config(&chain_config).expect("staking params are set");
+            // blocks go out through the simulation, not the channel
+            let (broadcast, _) = mpsc::unbounded_channel();
+            let key = ValidatorKey::Single(keypair.public_key());
+            let proposer = Proposer::new(chain.clone(), ProposerConfig::new(address.clone(), slots), key, keypair, broadcast)
+                .with_time_source(Arc::new(time.clone()));
+            validators.push(SimValidator { address, chain, engine, proposer, online: true });
+        }
+
+        let mut sim = Self {
+            time,
+            slots,
+            validators,
+            queue: BTreeMap::new(),
+            scheduled: 0,
+            next_slot: 1,
+            default_latency: config.latency,
+            latency: HashMap::new(),
+            delays: HashMap::new(),
+            leader_schedule: None,
+            events: Vec::new(),
+        };
+        sim.schedule(sim.slots.slot_start(1), Action::StartSlot(1));
+        Ok(sim)
+    }
+
+    /// Pick leaders with `schedule` instead of rotating through the validator set
+    pub fn with_leader_schedule(mut self, schedule: impl Fn(Slot) -> usize + Send + Sync + 'static) -> Self {
+        self.leader_schedule = Some(Box::new(schedule));
+        self
+    }
+
+    /// Blocks `from` proposes reach `to` after `latency`
+    pub fn set_latency(&mut self, from: usize, to: usize, latency: Duration) {
+        self.latency.insert((from, to), latency);
+    }
+
+    /// An offline validator doesn't propose, and blocks reaching it are lost
+    pub fn set_online(&mut self, validator: usize, online: bool) {
+        self.validators[validator].online = online;
+    }
+
+    /// Have `validator` start on its proposal for `slot` only `delay` into
+    /// the slot, as if it were busy or its clock were behind
+    pub fn delay_proposal(&mut self, validator: usize, slot: Slot, delay: Duration) {
+        self.delays.insert((validator, slot), delay);
+    }
+
+    pub fn clock(&self) -> &VirtualClock {
+        &self.time
+    }
+
+    pub fn slot_clock(&self) -> &SlotClock {
+        &self.slots
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.time.now()
+    }
+
+    /// Slot in progress on the virtual clock
+    pub fn current_slot(&self) -> Slot {
+        self.slots.slot_at(self.time.now())
+    }
+
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    pub fn address(&self, validator: usize) -> &Address {
+        &self.validators[validator].address
+    }
+
+    pub fn chain(&self, validator: usize) -> Arc<RwLock<Blockchain>> {
+        self.validators[validator].chain.clone()
+    }
+
+    pub fn engine(&self, validator: usize) -> &PoSEngine {
+        &self.validators[validator].engine
+    }
+
+    pub fn proposer_report(&self, validator: usize) -> ProposerReport {
+        self.validators[validator].proposer.report()
+    }
+
+    /// Everything that happened so far
+    pub fn events(&self) -> &[SimEvent] {
+        &self.events
+    }
+
+    /// Leader of `slot`: the schedule's pick, or else the validator set of
+    /// the current epoch taken in turn; None with an empty validator set
+    pub fn leader(&self, slot: Slot) -> Option<usize> {
+        if let Some(schedule) = &self.leader_schedule {
+            return Some(schedule(slot)).filter(|&validator| validator < self.validators.len());
+        }
+        let set = self.validators[0].engine.validator_set();
+        let (address, _) = set.get((slot % set.len().max(1) as u64) as usize)?;
+        self.validators.iter().position(|validator| validator.address == *address)
+    }
+
+    /// Run the next `slots` slots to their end
+    pub async fn run_slots(&mut self, slots: u64) {
+        let end = self.slots.slot_start(self.next_slot.saturating_add(slots));
+        self.run_until(end).await;
+    }
+
+    /// Handle everything scheduled before `time`, then move the clock to it
+    pub async fn run_until(&mut self, time: SystemTime) {
+        while let Some(entry) = self.queue.first_entry() {
+            if entry.key().0 >= time {
+                break;
+            }
+            let ((at, _), action) = entry.remove_entry();
+            self.time.advance_to(at);
+            self.handle(action).await;
+        }
+        self.time.advance_to(time);
+    }
+
+    /// Head of every validator's chain, by validator index
+    pub async fn heads(&self) -> Vec<Option<BlockId>> {
+        let mut heads = Vec::with_capacity(self.validators.len());
+        for validator in &self.validators {
+            heads.push(validator.chain.read().await.get_chain_head().map(Block::id));
+        }
+        heads
+    }
+
+    /// Whether every validator has the same head
+    pub async fn is_converged(&self) -> bool {
+        let heads = self.heads().await;
+        heads.iter().all(|head| *head == heads[0])
+    }
+
+    fn schedule(&mut self, at: SystemTime, action: Action) {
+        self.queue.insert((at, self.scheduled), action);
+        self.scheduled += 1;
+    }
+
+    async fn handle(&mut self, action: Action) {
+        match action {
+            Action::StartSlot(slot) => {
+                self.next_slot = slot + 1;
+                self.schedule(self.slots.slot_start(slot + 1), Action::StartSlot(slot + 1));
+                if let Some(leader) = self.leader(slot) {
+                    let delay = self.delays.remove(&(leader, slot)).unwrap_or_default();
+                    self.schedule(self.slots.slot_start(slot) + delay, Action::Propose { validator: leader, slot });
+                }
+            }
+            Action::Propose { validator, slot } => self.propose(validator, slot).await,
+            Action::Deliver { to, from, block } => {
+                if !self.validators[to].online {
+                    return;
+                }
+                let slot = self.slots.slot_of(block.header.timestamp);
+                self.events.push(match self.import(to, slot, block).await {
+                    Ok(()) => SimEvent::Imported { slot, validator: to, from },
+                    Err(e) => SimEvent::Rejected { slot, validator: to, from, reason: e.to_string() },
+                });
+            }
+        }
+    }
+
+    async fn propose(&mut self, validator: usize, slot: Slot) {
+        if !self.validators[validator].online {
+            self.events.push(SimEvent::Missed { slot, validator });
+            return;
+        }
+        let slot_start = self.time.instant_of(self.slots.slot_start(slot));
+        let proposed = self.validators[validator].proposer.propose(slot, slot_start).await;
+        let block = match proposed {
+            Ok(Some(block)) => block,
+            Ok(None) => {
+                self.events.push(SimEvent::Missed { slot, validator });
+                return;
+            }
+            Err(e) => {
+                self.events.push(SimEvent::Failed { slot, validator, reason: e.to_string() });
+                return;
+            }
+        };
+
+        let height = block.header.height;
+        self.events.push(match self.validators[validator].engine.process_block(height, Vec::new()) {
+            Ok(()) => SimEvent::Proposed { slot, validator, height },
+            Err(e) => SimEvent::Failed { slot, validator, reason: e.to_string() },
+        });
+
+        let now = self.time.now();
+        for to in (0..self.validators.len()).filter(|&to| to != validator) {
+            let latency = self.latency.get(&(validator, to)).copied().unwrap_or(self.default_latency);
+            self.schedule(now + latency, Action::Deliver { to, from: validator, block: block.clone() });
+        }
+    }
+
+    // the checks a validator runs on a block from the network, then import
+    async fn import(&mut self, to: usize, slot: Slot, block: Block) -> Result<()> {
+        let leader = self.leader(slot)
+            .ok_or_else(|| BlockchainError::InvalidBlock(format!("slot {} has no leader", slot)))?;
+        let leader = self.validators[leader].address.clone();
+        let now = self.time.now();
+
+        let validator = &mut self.validators[to];
+        let mut chain = validator.chain.write().await;
+        let parent = chain.get_block(&block.header.prev_block_hash).map(|parent| parent.header.clone());
+        self.slots.check_block(&block.header, parent.as_ref(), now)?;
+        validator.engine.verify_proposal(&leader, &block.header)?;
+
+        let height = block.header.height;
+        chain.add_block(block)?;
+        validator.engine.process_block(height, Vec::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::Timestamp;
+
+    // heights and timestamps of a validator's main chain; block ids differ
+    // between runs, as coinbase transactions are stamped with the system clock
+    async fn main_chain(sim: &Simulation, validator: usize) -> Vec<(u64, Timestamp)> {
+        let chain = sim.chain(validator);
+        let chain = chain.read().await;
+        let tip = chain.get_chain_head().map_or(0, |head| head.header.height);
+        (1..=tip)
+            .filter_map(|height| chain.get_block_by_height(&height))
+            .map(|block| (block.header.height, block.header.timestamp))
+            .collect()
+    }
+
+    fn proposals(sim: &Simulation) -> Vec<(Slot, usize, u64)> {
+        sim.events().iter()
+            .filter_map(|event| match event {
+                SimEvent::Proposed { slot, validator, height } => Some((*slot, *validator, *height)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // uneven links and a leader running late in slot 3
+    async fn scripted(seed: u64) -> Simulation {
+        let mut sim = Simulation::new(SimConfig { seed, ..SimConfig::default() }).unwrap();
+        sim.set_latency(0, 1, Duration::from_secs(1));
+        sim.set_latency(2, 3, Duration::from_millis(1500));
+        let late = sim.leader(3).unwrap();
+        sim.delay_proposal(late, 3, Duration::from_secs(1));
+        sim.run_slots(12).await;
+        sim
+    }
+
+    #[tokio::test]
+    async fn test_same_seed_plays_out_the_same() {
+        let (first, second) = (scripted(7).await, scripted(7).await);
+        assert_eq!(first.events(), second.events());
+        for validator in 0..first.len() {
+            assert_eq!(first.address(validator), second.address(validator));
+            assert_eq!(main_chain(&first, validator).await, main_chain(&second, validator).await);
+        }
+        assert_eq!(main_chain(&first, 0).await.len(), 12);
+        assert!(first.is_converged().await && second.is_converged().await);
+
+        let other = scripted(8).await;
+        assert_ne!(first.address(0), other.address(0));
+    }
+
+    #[tokio::test]
+    async fn test_proposers_rotate_through_the_validator_set() {
+        let mut sim = Simulation::new(SimConfig::default()).unwrap();
+        sim.run_slots(8).await;
+
+        let expected: Vec<(Slot, usize, u64)> = (1..=8).map(|slot| (slot, sim.leader(slot).unwrap(), slot)).collect();
+        assert_eq!(proposals(&sim), expected);
+        let mut first_round: Vec<usize> = (1..=4).map(|slot| sim.leader(slot).unwrap()).collect();
+        first_round.sort_unstable();
+        assert_eq!(first_round, vec![0, 1, 2, 3]);
+        for validator in 0..sim.len() {
+            assert_eq!(sim.proposer_report(validator).proposed, 2);
+        }
+        assert!(!sim.events().iter().any(|event| matches!(event, SimEvent::Rejected { .. })));
+        assert!(sim.is_converged().await);
+
+        // a schedule overrides the rotation
+        let mut sim = Simulation::new(SimConfig::default()).unwrap().with_leader_schedule(|slot| (slot % 2) as usize);
+        sim.run_slots(4).await;
+        assert_eq!(proposals(&sim), vec![(1, 1, 1), (2, 0, 2), (3, 1, 3), (4, 0, 4)]);
+        assert_eq!(sim.proposer_report(2).proposed, 0);
+        assert!(sim.is_converged().await);
+    }
+
+    #[tokio::test]
+    async fn test_late_and_offline_leaders_miss_their_slots() {
+        let mut sim = Simulation::new(SimConfig::default()).unwrap();
+        let (late, on_time, offline) = (sim.leader(2).unwrap(), sim.leader(3).unwrap(), sim.leader(4).unwrap());
+        // past the 3 second broadcast cutoff, within it, and not there at all
+        sim.delay_proposal(late, 2, Duration::from_secs(4));
+        sim.delay_proposal(on_time, 3, Duration::from_secs(2));
+        sim.set_online(offline, false);
+        sim.run_slots(4).await;
+
+        assert!(sim.events().contains(&SimEvent::Missed { slot: 2, validator: late }));
+        assert!(sim.events().contains(&SimEvent::Missed { slot: 4, validator: offline }));
+        assert_eq!(sim.proposer_report(late).missed_late_start, 1);
+        let report = sim.proposer_report(on_time);
+        assert_eq!(report.proposed, 1);
+        assert!(report.last_broadcast_offset >= Duration::from_secs(2));
+
+        // the chain skips the missed slots: slot 3 builds on slot 1
+        assert_eq!(proposals(&sim), vec![(1, sim.leader(1).unwrap(), 1), (3, on_time, 2)]);
+        let slots = *sim.slot_clock();
+        assert_eq!(main_chain(&sim, on_time).await, vec![(1, slots.block_timestamp(1)), (2, slots.block_timestamp(3))]);
+        assert_eq!(sim.current_slot(), 5);
+    }
+}
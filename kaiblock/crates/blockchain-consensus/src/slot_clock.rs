@@ -0,0 +1,278 @@
+use blockchain_core::{BlockHeader, BlockchainError, ChainConfig, Result, Timestamp};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+
+/// Slot number; slot `n` starts `n * slot_duration` after genesis
+pub type Slot = u64;
+
+/// Slots past the local clock a block may be stamped with, for clock drift
+/// between validators. Blocks further ahead are rejected.
+pub const MAX_FUTURE_SLOTS: Slot = 1;
+
+
+/// Where consensus reads the time. Nodes use [`SystemTimeSource`]; a
+/// simulation substitutes a virtual clock it advances itself.
+pub trait TimeSource: Send + Sync {
+    /// Wall clock time
+    fn now(&self) -> SystemTime;
+
+    /// Monotonic time, read at the same moment as `now`
+    fn instant(&self) -> Instant;
+
+    /// The Instant at which the wall clock reads `time`
+    fn instant_of(&self, time: SystemTime) -> Instant {
+        let (now, instant) = (self.now(), self.instant());
+        match time.duration_since(now) {
+            Ok(ahead) => instant + ahead,
+            Err(behind) => instant.checked_sub(behind.duration()).unwrap_or(instant),
+        }
+    }
+}
+
+/// The operating system's clocks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+
+/// Wall clock divided into slots from the genesis time.
+///
+/// Block timestamps have second resolution, so a proposer stamps its block
+/// with the start of its slot rounded up to a second; with a genesis time and
+/// slot duration in whole seconds that is the exact slot start and every
+/// block names its slot unambiguously.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotClock {
+    genesis_time: SystemTime,
+    slot_duration: Duration,
+}
+
+impl SlotClock {
+    pub fn new(genesis_time: SystemTime, slot_duration: Duration) -> Self {
+        Self {
+            genesis_time,
+            slot_duration: slot_duration.max(Duration::from_millis(1)),
+        }
+    }
+
+    /// Slots of `target_block_time` from the genesis timestamp; None if the
+    /// chain has no fixed genesis time
+    pub fn from_chain_config(config: &ChainConfig) -> Option<Self> {
+        let genesis = config.genesis.timestamp?;
+        Some(Self::new(
+            UNIX_EPOCH + Duration::from_secs(genesis.max(0) as u64),
+            Duration::from_secs(config.mining.target_block_time.max(1)),
+        ))
+    }
+
+    pub fn genesis_time(&self) -> SystemTime {
+        self.genesis_time
+    }
+
+    pub fn slot_duration(&self) -> Duration {
+        self.slot_duration
+    }
+
+    /// Slot in progress at `time` (slot 0 before genesis)
+    pub fn slot_at(&self, time: SystemTime) -> Slot {
+        let since_genesis = time.duration_since(self.genesis_time).unwrap_or_default();
+        (since_genesis.as_nanos() / self.slot_duration.as_nanos()).min(Slot::MAX as u128) as Slot
+    }
+
+    /// Slot in progress now, as `time` reads it
+    pub fn current_slot(&self, time: &dyn TimeSource) -> Slot {
+        self.slot_at(time.now())
+    }
+
+    pub fn slot_start(&self, slot: Slot) -> SystemTime {
+        let offset = self.slot_duration.as_nanos().saturating_mul(slot as u128);
+        let offset = Duration::from_nanos(offset.min(u64::MAX as u128) as u64);
+        self.genesis_time + offset
+    }
+
+    /// Timestamp a block proposed in `slot` carries
+    pub fn block_timestamp(&self, slot: Slot) -> Timestamp {
+        let since_epoch = self.slot_start(slot).duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seconds = since_epoch.as_secs() + u64::from(since_epoch.subsec_nanos() > 0);
+        Timestamp::from_unix_timestamp(seconds.min(i64::MAX as u64) as i64)
+    }
+
+    /// Slot a block timestamp falls in
+    pub fn slot_of(&self, timestamp: Timestamp) -> Slot {
+        self.slot_at(UNIX_EPOCH + Duration::from_secs(timestamp.to_unix_timestamp().max(0) as u64))
+    }
+
+    /// Check the slot of a block as seen at `now`: it can't be more than
+    /// `MAX_FUTURE_SLOTS` ahead of the local clock, and it must come after
+    /// the slot of its parent, so a chain holds at most one block per slot.
+    /// Returns the block's slot.
+    pub fn check_block(&self, header: &BlockHeader, parent: Option<&BlockHeader>, now: SystemTime) -> Result<Slot> {
+        let slot = self.slot_of(header.timestamp);
+        let current = self.slot_at(now);
+        if slot > current.saturating_add(MAX_FUTURE_SLOTS) {
+            return Err(BlockchainError::InvalidBlock(
+                format!("block is for slot {}, {} slots ahead of the current slot {}", slot, slot - current, current)
+            ));
+        }
+        if let Some(parent) = parent {
+            let parent_slot = self.slot_of(parent.timestamp);
+            if slot <= parent_slot {
+                return Err(BlockchainError::InvalidBlock(
+                    format!("block is for slot {}, not after its parent's slot {}", slot, parent_slot)
+                ));
+            }
+        }
+        Ok(slot)
+    }
+
+    /// Ticker that wakes at the start of every slot, reading the time off `time`
+    pub fn ticker(&self, time: Arc<dyn TimeSource>) -> SlotTicker {
+        SlotTicker { clock: *self, time, next: 0 }
+    }
+}
+
+
+/// Waits for slot boundaries. Each slot is yielded at most once and in
+/// order; slots that start while the caller is busy are skipped rather than
+/// yielded late, so a slow proposal can't push the next one out of its slot.
+#[derive(Clone)]
+pub struct SlotTicker {
+    clock: SlotClock,
+    time: Arc<dyn TimeSource>,
+    /// lowest slot not yet yielded
+    next: Slot,
+}
+
+impl SlotTicker {
+    /// Sleep until the next slot starts and return it. The slot in progress
+    /// when the ticker is first polled is never yielded.
+    pub async fn tick(&mut self) -> Slot {
+        let slot = self.next_slot();
+        tokio::time::sleep_until(self.time.instant_of(self.clock.slot_start(slot)).into()).await;
+        self.next = slot + 1;
+        slot
+    }
+
+    /// Slot the next `tick` returns: the first to start from now on that
+    /// hasn't been yielded
+    pub fn next_slot(&self) -> Slot {
+        self.next.max(self.clock.current_slot(&*self.time) + 1)
+    }
+
+    pub fn clock(&self) -> &SlotClock {
+        &self.clock
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::VirtualClock;
+    use blockchain_core::{BlockId, Hash256};
+
+    const GENESIS_SECS: u64 = 1_700_000_000;
+
+    fn genesis() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(GENESIS_SECS)
+    }
+
+    fn clock(slot_duration: Duration) -> SlotClock {
+        SlotClock::new(genesis(), slot_duration)
+    }
+
+    #[test]
+    fn test_slot_at_and_slot_start() {
+        let clock = clock(Duration::from_secs(6));
+        assert_eq!(clock.slot_at(genesis() - Duration::from_secs(60)), 0);
+        assert_eq!(clock.slot_at(genesis()), 0);
+        assert_eq!(clock.slot_at(genesis() + Duration::from_millis(5_999)), 0);
+        assert_eq!(clock.slot_at(genesis() + Duration::from_secs(6)), 1);
+        assert_eq!(clock.slot_at(genesis() + Duration::from_secs(63)), 10);
+
+        for slot in [0, 1, 7, 1_000] {
+            assert_eq!(clock.slot_start(slot), genesis() + Duration::from_secs(6 * slot));
+            assert_eq!(clock.slot_at(clock.slot_start(slot)), slot);
+            assert_eq!(clock.slot_of(clock.block_timestamp(slot)), slot);
+        }
+        assert_eq!(clock.block_timestamp(2).to_unix_timestamp(), GENESIS_SECS as i64 + 12);
+    }
+
+    #[test]
+    fn test_current_slot_reads_the_time_source() {
+        let clock = clock(Duration::from_secs(6));
+        let time = VirtualClock::new(genesis());
+        assert_eq!(clock.current_slot(&time), 0);
+        time.advance(Duration::from_secs(13));
+        assert_eq!(clock.current_slot(&time), 2);
+    }
+
+    #[test]
+    fn test_sub_second_slots_round_block_timestamps_up() {
+        let clock = clock(Duration::from_millis(1_500));
+        assert_eq!(clock.block_timestamp(1).to_unix_timestamp(), GENESIS_SECS as i64 + 2);
+        assert_eq!(clock.block_timestamp(2).to_unix_timestamp(), GENESIS_SECS as i64 + 3);
+        // a zero duration is clamped instead of dividing by zero
+        assert_eq!(SlotClock::new(genesis(), Duration::ZERO).slot_duration(), Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_check_block_slots() {
+        let clock = clock(Duration::from_secs(6));
+        let header = |slot| {
+            let mut header = BlockHeader::new(BlockId::genesis(), Hash256::zero(), 0, slot, 0, 1);
+            header.timestamp = clock.block_timestamp(slot);
+            header
+        };
+        let now = clock.slot_start(5);
+
+        assert_eq!(clock.check_block(&header(5), Some(&header(3)), now).unwrap(), 5);
+        assert!(clock.check_block(&header(5 + MAX_FUTURE_SLOTS), None, now).is_ok());
+        assert!(clock.check_block(&header(6 + MAX_FUTURE_SLOTS), None, now).is_err());
+        // at most one block per slot along a chain
+        assert!(clock.check_block(&header(3), Some(&header(3)), now).is_err());
+        assert!(clock.check_block(&header(2), Some(&header(3)), now).is_err());
+    }
+
+    #[test]
+    fn test_ticker_skips_slots_that_started_while_busy() {
+        let clock = clock(Duration::from_secs(6));
+        let time = VirtualClock::new(clock.slot_start(3) + Duration::from_secs(1));
+        let mut ticker = clock.ticker(Arc::new(time.clone()));
+
+        // the slot in progress is never yielded
+        assert_eq!(ticker.next_slot(), 4);
+        ticker.next = 5;
+        assert_eq!(ticker.next_slot(), 5);
+
+        // busy until slot 7 is under way: the next tick is slot 8
+        time.advance(Duration::from_secs(24));
+        assert_eq!(ticker.next_slot(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_ticker_yields_slots_in_order() {
+        let clock = clock(Duration::from_millis(100));
+        let time = VirtualClock::new(clock.slot_start(3) - Duration::from_millis(10));
+        let mut ticker = clock.ticker(Arc::new(time.clone()));
+
+        assert_eq!(ticker.tick().await, 3);
+        time.advance(Duration::from_millis(10));
+        assert_eq!(ticker.tick().await, 4);
+
+        // slot 5 started while the caller was busy
+        time.advance(Duration::from_millis(250));
+        assert_eq!(ticker.tick().await, 6);
+    }
+}
@@ -0,0 +1,140 @@
+use blockchain_core::{BlockHeader, BlockchainError, PublicKey, Result, Signature};
+use serde::{Deserialize, Serialize};
+
+
+/// Most keys a threshold validator key may have
+pub const MAX_COSIGNERS: usize = 16;
+
+
+/// Keys that may sign block proposals for a validator.
+///
+/// A threshold key lets one validator identity be run from several operator
+/// machines, each holding one key: a proposal needs `threshold` of them, so a
+/// single compromised host can't sign blocks (or double-sign) on its own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ValidatorKey {
+    Single(PublicKey),
+    /// `threshold` of `keys` must co-sign
+    Threshold { threshold: u8, keys: Vec<PublicKey> },
+}
+
+impl ValidatorKey {
+    /// m-of-n key set; `keys` must be distinct and `threshold` between 1 and n
+    pub fn threshold(threshold: u8, keys: Vec<PublicKey>) -> Result<Self> {
+        if keys.is_empty() || keys.len() > MAX_COSIGNERS {
+            return Err(BlockchainError::ValidationError(
+                format!("threshold key needs 1 to {} keys, got {}", MAX_COSIGNERS, keys.len())
+            ));
+        }
+        if threshold == 0 || threshold as usize > keys.len() {
+            return Err(BlockchainError::ValidationError(
+                format!("threshold {} out of range for {} keys", threshold, keys.len())
+            ));
+        }
+        if keys.iter().enumerate().any(|(i, key)| keys[..i].contains(key)) {
+            return Err(BlockchainError::ValidationError("threshold key repeats a key".to_string()));
+        }
+        Ok(ValidatorKey::Threshold { threshold, keys })
+    }
+
+    pub fn keys(&self) -> &[PublicKey] {
+        match self {
+            ValidatorKey::Single(key) => std::slice::from_ref(key),
+            ValidatorKey::Threshold { keys, .. } => keys,
+        }
+    }
+
+    /// Signatures a proposal needs
+    pub fn required_signatures(&self) -> usize {
+        match self {
+            ValidatorKey::Single(_) => 1,
+            ValidatorKey::Threshold { threshold, .. } => *threshold as usize,
+        }
+    }
+
+    /// Check `proposal` carries enough valid signatures over `message` from
+    /// distinct keys of this set
+    pub fn verify(&self, message: &[u8], proposal: &ProposalSignature) -> Result<()> {
+        let keys = self.keys();
+        let mut signed = vec![false; keys.len()];
+
+        for (index, signature) in &proposal.signatures {
+            let key = keys.get(*index as usize).ok_or_else(|| BlockchainError::ValidationError(
+                format!("co-signature from key {} of a {}-key set", index, keys.len())
+            ))?;
+            if std::mem::replace(&mut signed[*index as usize], true) {
+                return Err(BlockchainError::ValidationError(format!("key {} signed twice", index)));
+            }
+            if !key.verify(message, signature) {
+                return Err(BlockchainError::ValidationError(format!("invalid co-signature from key {}", index)));
+            }
+        }
+
+        let required = self.required_signatures();
+        if proposal.signatures.len() < required {
+            return Err(BlockchainError::ValidationError(format!(
+                "proposal has {} of the {} required signatures", proposal.signatures.len(), required
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the proposal signature in `header.consensus_data`
+    pub fn verify_header(&self, header: &BlockHeader) -> Result<()> {
+        let proposal = ProposalSignature::decode(&header.consensus_data)?;
+        self.verify(header.signing_hash().as_bytes(), &proposal)
+    }
+}
+
+
+/// Signatures on a block proposal, carried in the header's `consensus_data`.
+///
+/// Ed25519 signatures don't aggregate, so a threshold proposal carries one
+/// signature per co-signer, tagged with the signer's position in the key set.
+/// Each operator signs the header's signing hash independently and the
+/// proposer collects the shares with [`ProposalSignature::add`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProposalSignature {
+    pub signatures: Vec<(u8, Signature)>,
+}
+
+impl ProposalSignature {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `signer`'s signature over `message`, checking it first so a bad
+    /// share is caught on the operator side rather than by every node
+    pub fn add(&mut self, key: &ValidatorKey, message: &[u8], signer: &PublicKey, signature: Signature) -> Result<()> {
+        let index = key.keys().iter().position(|k| k == signer)
+            .ok_or_else(|| BlockchainError::ValidationError("signer is not part of the validator key".to_string()))?;
+        if !signer.verify(message, &signature) {
+            return Err(BlockchainError::ValidationError("invalid co-signature".to_string()));
+        }
+
+        let index = index as u8;
+        if !self.signatures.iter().any(|(i, _)| *i == index) {
+            self.signatures.push((index, signature));
+            self.signatures.sort_by_key(|(i, _)| *i);
+        }
+        Ok(())
+    }
+
+    /// Whether enough shares are in to satisfy `key`
+    pub fn is_complete(&self, key: &ValidatorKey) -> bool {
+        self.signatures.len() >= key.required_signatures()
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self)
+            .map_err(|e| BlockchainError::SerializationError(format!("proposal signature: {}", e)))
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.is_empty() {
+            return Err(BlockchainError::ValidationError("block carries no proposal signature".to_string()));
+        }
+        bincode::deserialize(bytes)
+            .map_err(|e| BlockchainError::ValidationError(format!("malformed proposal signature: {}", e)))
+    }
+}
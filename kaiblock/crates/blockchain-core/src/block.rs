@@ -1,7 +1,9 @@
 use crate::types::*;
-use crate::transaction::Transaction;
+use crate::codec;
+use crate::transaction::{Transaction, EXTRA_NONCE_SIZE};
+use crate::logs::LogBloom;
 use crate::{BlockchainError, Result};
-use blockchain_crypto::{Hash256, MerkleTree, hash::sha256};
+use blockchain_crypto::{Hash256, HashAlgorithm, MerkleMultiproof, MerkleProof, MerkleTree, hash::{sha256, hash_combine, merkle_root}};
 use serde::{Deserialize, Serialize};
 
 /// Block header containing metadata
@@ -27,6 +29,12 @@ pub struct BlockHeader {
     pub size: u32,
     /// Chain ID for network identification
     pub chain_id: ChainId,
+    /// Bloom filter over the addresses and topics of every log in the block
+    pub logs_bloom: LogBloom,
+    /// Engine-specific proof not covered by the signing hash, e.g. the
+    /// proposer's (co-)signatures under proof of stake. Empty under proof of work
+    #[serde(default)]
+    pub consensus_data: Vec<u8>,
 }
 
 impl BlockHeader{
@@ -50,15 +58,24 @@ impl BlockHeader{
             tx_count,
             size: 0,
             chain_id,
+            logs_bloom: LogBloom::new(),
+            consensus_data: Vec::new(),
         }
     }
 
 
     ///calculate header hash
     pub fn hash(&self) -> Hash256{
-        let serialized = bincode::serialize(self)
-            .expect("Block header serialization should not fail");
-        sha256(&serialized)
+        sha256(&codec::encode(self))
+    }
+
+
+    ///hash a block producer signs: the header without its consensus data,
+    ///so signatures can be added without invalidating each other
+    pub fn signing_hash(&self) -> Hash256 {
+        let mut unsigned = self.clone();
+        unsigned.consensus_data.clear();
+        unsigned.hash()
     }
 
 
@@ -81,8 +98,50 @@ impl BlockHeader{
     }
 
 
+    ///header hash under `algorithm`, what proof of work is measured on.
+    ///equal to `hash()` for SHA-256
+    pub fn pow_hash(&self, algorithm: HashAlgorithm) -> Hash256 {
+        algorithm.hash(&codec::encode(self))
+    }
+
+
+    ///check the difficulty target against the `algorithm` hash
+    pub fn meets_difficulty_with(&self, algorithm: HashAlgorithm) -> bool {
+        u64::from(self.hash_difficulty_with(algorithm)) >= self.difficulty
+    }
+
+
+    ///difficulty of the `algorithm` hash
+    pub fn hash_difficulty_with(&self, algorithm: HashAlgorithm) -> u32 {
+        blockchain_crypto::hash::hash_difficulty(&self.pow_hash(algorithm))
+    }
+
 
 
+
+}
+
+
+
+/// The header as light clients see it, with the same encoding and hash
+impl From<&BlockHeader> for blockchain_primitives::BlockHeader {
+    fn from(header: &BlockHeader) -> Self {
+        let timestamp = header.timestamp.inner();
+        Self {
+            version: header.version,
+            prev_block_hash: header.prev_block_hash.hash(),
+            merkle_root: header.merkle_root,
+            timestamp: blockchain_primitives::Timestamp::from_unix(timestamp.timestamp(), timestamp.timestamp_subsec_nanos()),
+            difficulty: header.difficulty,
+            nonce: header.nonce,
+            height: header.height,
+            tx_count: header.tx_count,
+            size: header.size,
+            chain_id: header.chain_id,
+            logs_bloom: *header.logs_bloom.as_bytes(),
+            consensus_data: header.consensus_data.clone(),
+        }
+    }
 }
 
 
@@ -104,22 +163,9 @@ impl BlockBody{
 
     ///calculate merkle root for all transactions
     pub calculate_merkle_root(&self) -> Result<Hash256> {
-        if self.transactions.is_empty() {
-            return Ok(Hash256::zero());
-        }
-
-
-        let tx_hashes: Vec<Hash256> = self.transactions
-            .iter()
-            .map(|tx| tx.hash())
-            .collect();
-
-        let merkle_tree = MerkleTree::new(tx_hashes)
-            .map_err(|e| BlockchainError::InvalidBlock(
-                format!("Merkle tree error: {e}" , e)
-                ))
-
-        Ok(merkle_tree.root())
+        //streamed: only the O(log n) peaks are kept, never the whole tree
+        let root = merkle_root(self.transactions.iter().map(|tx| tx.hash()));
+        Ok(root.unwrap_or_else(Hash256::zero))
     }
 
 
@@ -161,9 +207,92 @@ impl BlockBody{
     ///calculate the total fees from all transactions
     pub fn total_fees(&self) -> Amount {
         self.transactions.iter()
-            .map(|tx| tx.calculate_gas_fee())
-            .sum()
+            .fold(0, |total: Amount, tx| total.saturating_add(tx.calculate_gas_fee()))
     }
+
+
+    ///merkle branch (siblings from leaf to root) for the coinbase transaction
+    pub fn coinbase_merkle_branch(&self) -> Result<Vec<Hash256>> {
+        let tx_hashes: Vec<Hash256> = self.transactions
+            .iter()
+            .map(|tx| tx.hash())
+            .collect();
+
+        let merkle_tree = MerkleTree::new(tx_hashes)
+            .map_err(|e| BlockchainError::InvalidBlock(
+                format!("Merkle tree error: {}", e)
+                ))?;
+
+        let proof = merkle_tree.generate_proof(0)
+            .map_err(|e| BlockchainError::InvalidBlock(
+                format!("Merkle proof error: {}", e)
+                ))?;
+
+        Ok(proof.siblings)
+    }
+
+
+    ///merkle proof that a transaction is in this body (None if it isn't)
+    pub fn transaction_proof(&self, tx_id: &TxId) -> Result<Option<MerkleProof>> {
+        let tx_hashes: Vec<Hash256> = self.transactions
+            .iter()
+            .map(|tx| tx.hash())
+            .collect();
+
+        let leaf_index = match tx_hashes.iter().position(|hash| *hash == tx_id.hash()) {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let merkle_tree = MerkleTree::new(tx_hashes)
+            .map_err(|e| BlockchainError::InvalidBlock(
+                format!("Merkle tree error: {}", e)
+                ))?;
+
+        merkle_tree.generate_proof(leaf_index)
+            .map(Some)
+            .map_err(|e| BlockchainError::InvalidBlock(
+                format!("Merkle proof error: {}", e)
+                ))
+    }
+
+
+    ///one merkle proof for several transactions in this body, sharing the hashes
+    ///their paths have in common (None if any of them isn't in it)
+    pub fn transactions_multiproof(&self, tx_ids: &[TxId]) -> Result<Option<MerkleMultiproof>> {
+        let tx_hashes: Vec<Hash256> = self.transactions
+            .iter()
+            .map(|tx| tx.hash())
+            .collect();
+
+        let mut leaf_indices = Vec::with_capacity(tx_ids.len());
+        for tx_id in tx_ids {
+            match tx_hashes.iter().position(|hash| *hash == tx_id.hash()) {
+                Some(index) => leaf_indices.push(index),
+                None => return Ok(None),
+            }
+        }
+
+        let merkle_tree = MerkleTree::new(tx_hashes)
+            .map_err(|e| BlockchainError::InvalidBlock(
+                format!("Merkle tree error: {}", e)
+                ))?;
+
+        merkle_tree.generate_multiproof(&leaf_indices)
+            .map(Some)
+            .map_err(|e| BlockchainError::InvalidBlock(
+                format!("Merkle proof error: {}", e)
+                ))
+    }
+}
+
+
+///recompute the merkle root from a coinbase hash and its merkle branch.
+///the coinbase is always leaf 0, so it is the left node at every level
+pub fn merkle_root_from_coinbase(coinbase_hash: Hash256, merkle_branch: &[Hash256]) -> Hash256 {
+    merkle_branch.iter().fold(coinbase_hash, |current, sibling| {
+        hash_combine(&[current.as_bytes(), sibling.as_bytes()])
+    })
 }
 
 
@@ -206,6 +335,7 @@ impl Block {
             );
 
         header.size = size;
+        header.logs_bloom = LogBloom::from_transactions(&body.transactions);
 
         Ok(Self{header, body})
     }
@@ -236,6 +366,15 @@ impl Block {
     }
 
 
+    ///the block without its transactions, as pruned nodes keep it
+    pub fn pruned(&self) -> Self {
+        Self {
+            header: self.header.clone(),
+            body: BlockBody::new(Vec::new()),
+        }
+    }
+
+
     ///get block timestamp
     pub fn timestamp(&self) -> Timestamp{
         self.header.timestamp
@@ -246,6 +385,20 @@ impl Block {
         self.body.transactions.len()
     }
 
+    ///total compute units requested by the block's runtime transactions
+    pub fn compute_units(&self) -> u64 {
+        self.body.transactions.iter()
+            .map(|tx| tx.compute_units())
+            .fold(0u64, |total, units| total.saturating_add(units))
+    }
+
+    ///signature checks needed to validate the block's transactions
+    pub fn sig_ops(&self) -> u64 {
+        self.body.transactions.iter()
+            .map(|tx| tx.sig_ops())
+            .fold(0u64, |total, ops| total.saturating_add(ops))
+    }
+
     //get all transactions
     pub fn transactions(&self) -> &[Transaction] {
         &self.body.transactions
@@ -257,6 +410,16 @@ impl Block {
         self.body.get_transaction((tx_id))
     }
 
+    ///merkle proof that a transaction is in this block, for light clients
+    pub fn transaction_proof(&self, tx_id: &TxId) -> Result<Option<MerkleProof>> {
+        self.body.transaction_proof(tx_id)
+    }
+
+    ///one merkle proof for several transactions in this block, for light clients
+    pub fn transactions_multiproof(&self, tx_ids: &[TxId]) -> Result<Option<MerkleMultiproof>> {
+        self.body.transactions_multiproof(tx_ids)
+    }
+
     ///check if block is genesis block
     pub fn is_genesis(&self) -> bool {
         self.header.height == 0 && self.header.prev_block_hash == BlockId::genesis()
@@ -308,6 +471,14 @@ impl Block {
         }
 
 
+        //check logs bloom covers exactly the logs the transactions emit
+        if self.header.logs_bloom != LogBloom::from_transactions(&self.body.transactions) {
+            return Err(BlockchainError::InvalidBlock(
+                "logs bloom mismatch".to_string()
+                ));
+        }
+
+
         //check block size
         let calculated_size = self.body.calculate_size() as u32;
         if self.header.size != calculated_size {
@@ -322,11 +493,17 @@ impl Block {
 
     ///mine the block by finding a valid nonce
     pub fn mine(&mut self, max_iterations: Option<u64>) -> Result<bool> {
+        self.mine_with(HashAlgorithm::Sha256, max_iterations)
+    }
+
+
+    ///mine the block for a chain measuring proof of work on `algorithm`
+    pub fn mine_with(&mut self, algorithm: HashAlgorithm, max_iterations: Option<u64>) -> Result<bool> {
         let max_iter = max_iterations.unwrap_or(u64::MAX);
         let mut iterations = 0;
 
         while iterations< max_iter{
-            if self.header.meets_difficulty(){
+            if self.header.meets_difficulty_with(algorithm){
                 return Ok(true);
 
             }
@@ -334,6 +511,12 @@ impl Block {
             self.header.nonce = self.self.header.nonce.wrapping_add(1);
             iterations += 1;
 
+            //nonce space exhausted, roll the extra nonce to get a fresh merkle root
+            if self.header.nonce == 0 && self.body.coinbase_transaction().is_some() {
+                let extra_nonce = self.extra_nonce().unwrap_or(0).wrapping_add(1);
+                self.set_extra_nonce(extra_nonce)?;
+            }
+
 
             //update timestamp occassionaly to prevent stale work
             if iterations % 1000000 == 0 {
@@ -347,6 +530,35 @@ impl Block {
 
 
 
+    ///get the coinbase extra nonce, if one has been set
+    pub fn extra_nonce(&self) -> Option<u64> {
+        self.body.coinbase_transaction()
+            .and_then(|tx| tx.extra_nonce())
+    }
+
+
+    ///set the coinbase extra nonce and recompute the merkle root via the coinbase branch.
+    ///resets the header nonce so the full 64-bit search space is available again
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64) -> Result<()> {
+        let merkle_branch = self.body.coinbase_merkle_branch()?;
+
+        let coinbase = self.body.transactions.first_mut()
+            .filter(|tx| tx.is_coinbase())
+            .ok_or_else(|| BlockchainError::InvalidBlock(
+                "Block has no coinbase transaction".to_string()
+                ))?;
+
+        coinbase.set_extra_nonce(extra_nonce)?;
+        let coinbase_hash = coinbase.hash();
+
+        self.header.merkle_root = merkle_root_from_coinbase(coinbase_hash, &merkle_branch);
+        self.header.size = self.body.calculate_size() as u32;
+        self.header.nonce = 0;
+
+        Ok(())
+    }
+
+
     ///get block size in bytes
     pub fn size(&self) -> usize{
         self.header.size as usize
@@ -360,7 +572,7 @@ impl Block {
 
         let fees = self.body.total_fees();
 
-        coinbase_reward + fees
+        coinbase_reward.saturating_add(fees)
     }
 }
 
@@ -450,6 +662,70 @@ impl Default for BlockBuilder {
 
 
 
+/// Mining job for external miners (stratum-style).
+/// Carries everything needed to roll the extra nonce and rebuild the merkle root
+/// locally, so miners don't have to request a new template when the nonce runs out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtraNonceJob {
+    /// Header template (merkle root and nonce are filled in per attempt)
+    pub header: BlockHeader,
+    /// Hashed coinbase bytes before the extra nonce region
+    pub coinbase_prefix: Vec<u8>,
+    /// Hashed coinbase bytes after the extra nonce region
+    pub coinbase_suffix: Vec<u8>,
+    /// Merkle branch for the coinbase (leaf 0)
+    pub merkle_branch: Vec<Hash256>,
+}
+
+impl ExtraNonceJob {
+    /// Build a job from a block template
+    pub fn from_block(block: &Block) -> Result<Self> {
+        let mut template = block.clone();
+
+        //reserve the extra nonce region so the header size stays fixed across attempts
+        if template.extra_nonce().is_none() {
+            template.set_extra_nonce(0)?;
+        }
+
+        let coinbase = template.body.coinbase_transaction()
+            .ok_or_else(|| BlockchainError::InvalidBlock(
+                "Block has no coinbase transaction".to_string()
+                ))?;
+
+        let (coinbase_prefix, coinbase_suffix) = coinbase.extra_nonce_split()?;
+        let merkle_branch = template.body.coinbase_merkle_branch()?;
+
+        Ok(Self {
+            header: template.header,
+            coinbase_prefix,
+            coinbase_suffix,
+            merkle_branch,
+        })
+    }
+
+    /// Merkle root for a given extra nonce
+    pub fn merkle_root(&self, extra_nonce: u64) -> Hash256 {
+        let mut coinbase = Vec::with_capacity(
+            self.coinbase_prefix.len() + EXTRA_NONCE_SIZE + self.coinbase_suffix.len()
+        );
+        coinbase.extend_from_slice(&self.coinbase_prefix);
+        coinbase.extend_from_slice(&extra_nonce.to_le_bytes());
+        coinbase.extend_from_slice(&self.coinbase_suffix);
+
+        merkle_root_from_coinbase(sha256(&coinbase), &self.merkle_branch)
+    }
+
+    /// Header for a given extra nonce and nonce
+    pub fn header_for(&self, extra_nonce: u64, nonce: u64) -> BlockHeader {
+        let mut header = self.header.clone();
+        header.merkle_root = self.merkle_root(extra_nonce);
+        header.nonce = nonce;
+        header
+    }
+}
+
+
+
 // ////////////////======tests========\\\\\\\\\\\\\\\\\\\\\\\\ \\
 
 
@@ -581,4 +857,70 @@ mod tests {
         
         assert_eq!(merkle_root, merkle_root2);
     }
+
+    #[test]
+    fn test_extra_nonce_updates_merkle_root() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        
+        let coinbase_tx = Transaction::new_coinbase(address, 5000000000, 1);
+        let tx = Transaction::new_coinbase(address, 1000000000, 2);
+        let prev_hash = BlockId::new(sha256(b"previous block"));
+        
+        let mut block = Block::new(prev_hash, vec![coinbase_tx, tx], 1, 1, 1).unwrap();
+        let original_root = block.header.merkle_root;
+        
+        block.set_extra_nonce(42).unwrap();
+        
+        assert_eq!(block.extra_nonce(), Some(42));
+        assert_ne!(block.header.merkle_root, original_root);
+        
+        // Root from the coinbase branch must match a full recomputation
+        assert_eq!(block.header.merkle_root, block.body.calculate_merkle_root().unwrap());
+        assert_eq!(block.size(), block.body.calculate_size());
+    }
+
+    #[test]
+    fn test_extra_nonce_job_matches_block() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        
+        let coinbase_tx = Transaction::new_coinbase(address, 5000000000, 1);
+        let prev_hash = BlockId::new(sha256(b"previous block"));
+        
+        let block = Block::new(prev_hash, vec![coinbase_tx], 1, 1, 1).unwrap();
+        let job = ExtraNonceJob::from_block(&block).unwrap();
+        
+        let mut rolled = block.clone();
+        rolled.set_extra_nonce(7).unwrap();
+        rolled.header.nonce = 99;
+        
+        assert_eq!(job.header_for(7, 99), rolled.header);
+    }
+
+    #[test]
+    fn test_signing_hash_ignores_consensus_data() {
+        let mut header = BlockHeader::new(BlockId::new(sha256(b"previous block")), sha256(b"merkle"), 1, 1, 1, 1);
+        let signing_hash = header.signing_hash();
+        let id = header.id();
+
+        header.consensus_data = vec![1, 2, 3];
+
+        assert_eq!(header.signing_hash(), signing_hash);
+        assert_ne!(header.id(), id);
+    }
+
+    #[test]
+    fn test_light_header_hashes_like_the_node() {
+        let mut header = BlockHeader::new(BlockId::new(sha256(b"previous block")), sha256(b"merkle"), 1, 7, 3, 1);
+        header.nonce = 42;
+        header.consensus_data = vec![9, 9];
+
+        let light = blockchain_primitives::BlockHeader::from(&header);
+        assert_eq!(light.encode(), codec::encode(&header));
+        assert_eq!(light.hash(), header.hash());
+        assert_eq!(light.signing_hash(), header.signing_hash());
+        assert_eq!(blockchain_primitives::BlockHeader::decode(&codec::encode(&header)).unwrap(), light);
+        assert_eq!(codec::decode::<BlockHeader>(&light.encode()).unwrap(), header);
+    }
 }
\ No newline at end of file
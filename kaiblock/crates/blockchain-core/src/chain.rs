@@ -1,14 +1,46 @@
 use crate::types::*;
-use crate::block::Block;
-use crate::transaction::Transaction;
-use crate::state::WorldState;
+use crate::block::{Block, BlockHeader};
+use crate::transaction::{SigningDomain, Transaction};
+use crate::state::{AccountProof, BlockUndo, WorldState, WorldStateSnapshot};
 use crate::mempool::Mempool;
 use crate::validation::{Validator, ValidationRules, BlockValidationContext};
+use crate::store::ChainStore;
+use crate::snapshot::{self, SnapshotChunk, SnapshotManifest};
+use crate::receipt::Receipt;
+use crate::difficulty;
+use crate::emission::EmissionSchedule;
+use crate::fee_market::FeeMarketConfig;
+use crate::logs::{self, LogEntry, LogFilter, MAX_LOG_QUERY_RANGE};
+use crate::light_client::{self, DifficultyProof};
+use crate::dev_accounts::{self, DevAccount, DevAccountsConfig};
+use crate::genesis::{self, GenesisValidator, StakingParams};
+use crate::weight::BlockWeight;
+use crate::conflict::ConflictProof;
+use crate::precheck::HeaderVerifier;
+use crate::events::{ChainEvent, TxDropReason, CHAIN_EVENT_CAPACITY};
 use crate::{BlockchainError, Result};
 use blockchain_crypto::{Address, Hash256};
+use blockchain_crypto::signature::{SignatureCache, SignatureCacheConfig, SignatureCacheStats, SigCacheMode};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use tracing::{info, warn, error};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tracing::{info, info_span, warn, error};
+
+
+/// Deepest reorganization accepted; state snapshots older than this are pruned
+pub const MAX_REORG_DEPTH: BlockHeight = 100;
+
+/// Pruned nodes drop block bodies this many at a time, rewriting the state
+/// they restart from once per batch rather than once per block
+pub const PRUNE_BATCH: BlockHeight = 100;
+
+
+/// Expected work to find a block at a difficulty (leading zero bits)
+pub fn block_work(difficulty: Difficulty) -> u128 {
+	1u128 << difficulty.min(127)
+}
 
 
 /// Blockchain configuration
@@ -26,6 +58,35 @@ pub struct ChainConfig {
 	pub validation_rules: ValidationRules,
 	//mining config
 	pub mining: MiningConfig,
+	//data directory for persisted chain data (None keeps the chain in memory)
+	pub storage_path: Option<PathBuf>,
+	//signature cache shared by mempool admission and block validation
+	#[serde(default)]
+	pub signature_cache: SignatureCacheConfig,
+	//hard-coded block ids by height; blocks and forks contradicting them are rejected
+	#[serde(default)]
+	pub checkpoints: BTreeMap<BlockHeight, BlockId>,
+	//this block and its ancestors skip signature checks. ancestry comes from
+	//headers (see `note_headers`), so a branch that doesn't lead here is fully checked
+	#[serde(default)]
+	pub assume_valid: Option<BlockId>,
+	//keep transactions of only the last this many main chain blocks; older blocks
+	//are cut down to their headers. at least MAX_REORG_DEPTH (None keeps every block)
+	#[serde(default)]
+	pub prune_depth: Option<BlockHeight>,
+	//proof-of-stake parameters from the genesis file (None outside proof of stake)
+	#[serde(default)]
+	pub staking: Option<StakingParams>,
+	//fork id transaction signatures commit to along with the chain id
+	#[serde(default)]
+	pub fork_id: ForkId,
+	//how the block subsidy (mining.block_reward at first) shrinks with height
+	#[serde(default)]
+	pub emission: EmissionSchedule,
+	//base fee of account transactions, burned instead of paid to the producer
+	//(None: no base fee, the whole gas fee is the producer's tip)
+	#[serde(default)]
+	pub fee_market: Option<FeeMarketConfig>,
 }
 
 /// Genesis block configuration
@@ -41,6 +102,12 @@ pub struct GenesisConfig{
 	pub timestamp: Option:<i64>,
 	//genesis_difficulty
 	pub genesis_difficulty: Difficulty,
+	//deterministic dev accounts funded at genesis (Devnet and Local only)
+	#[serde(default)]
+	pub dev_accounts: Option<DevAccountsConfig>,
+	//validators bonded at genesis (proof of stake)
+	#[serde(default)]
+	pub validators: Vec<GenesisValidator>,
 }
 
 
@@ -72,6 +139,8 @@ impl Default for ChainConfig {
 			initial_accounts: HashMap::new(),
 			timestamp: None,
 			difficulty: 1,
+			dev_accounts: Some(DevAccountsConfig::default()),
+			validators: Vec::new(),
 		},
 
 		validation_rules: ValidationRules::default(),
@@ -81,6 +150,38 @@ impl Default for ChainConfig {
 			max_mining_iterations: 1_000_000,
 			enable_mining: true,
 		},
+		storage_path: None,
+		signature_cache: SignatureCacheConfig::default(),
+		checkpoints: BTreeMap::new(),
+		assume_valid: None,
+		prune_depth: None,
+		staking: None,
+		fork_id: 0,
+		emission: EmissionSchedule::default(),
+		fee_market: None,
+	}
+}
+
+
+impl ChainConfig {
+	///what transactions on this chain sign
+	pub fn signing_domain(&self) -> SigningDomain {
+		SigningDomain::new(self.chain_id, self.fork_id)
+	}
+
+	///what the coinbase of the block at `height` may mint
+	pub fn block_subsidy(&self, height: BlockHeight) -> Amount {
+		self.emission.subsidy(self.mining.block_reward, height)
+	}
+
+	///the prune depth, if it leaves room for the deepest reorg we accept
+	pub fn checked_prune_depth(&self) -> Result<Option<BlockHeight>> {
+		match self.prune_depth {
+			Some(depth) if depth < MAX_REORG_DEPTH => Err(BlockchainError::ValidationError(
+				format!("prune_depth {} is below the maximum reorg depth {}", depth, MAX_REORG_DEPTH)
+			)),
+			depth => Ok(depth),
+		}
 	}
 }
 
@@ -106,18 +207,128 @@ pub struct Blockchain {
 	Validator: Validator,
 	///orphaned blocks(block_id -> block)
 	orphaned_blocks: HashMap<BlockId, Block>,
+	///persistent backend (None keeps the chain in memory only)
+	store: Option<Box<dyn ChainStore>>,
+	///cumulative work of the chain ending at each known block
+	chain_work: HashMap<BlockId, u128>,
+	///world state after each recent main chain block, used to rewind on reorg
+	///when undo records are missing
+	state_snapshots: BTreeMap<BlockHeight, WorldStateSnapshot>,
+	///undo records of recent main chain blocks, used to disconnect them on reorg
+	undo_records: BTreeMap<BlockHeight, BlockUndo>,
+	///receipts of main chain transactions
+	receipts: HashMap<TxId, Receipt>,
+	///chain and mempool events for subscribers
+	events: broadcast::Sender<ChainEvent>,
+	///main chain blocks up to this height have been cut down to their headers
+	pruned_height: Option<BlockHeight>,
+	///main chain blocks up to this height are final and can't be reorganized away.
+	///set by the consensus engine, which recomputes it from the chain on restart
+	finalized_height: Option<BlockHeight>,
+	///side-branch blocks that failed validation on reorg, and their descendants;
+	///refused on sight so the same branch can't trigger another reorg attempt
+	invalid_blocks: HashSet<BlockId>,
+	///headers ahead of the chain, kept until they lead to the assume-valid block
+	pending_headers: HashMap<BlockId, BlockHeader>,
 }
 
 
 
 impl Blockchain{
-	///create new blockchain with configuration
+	///create new in-memory blockchain with configuration. a chain with a
+	///storage path is opened on a store for it with `with_store` (e.g. by
+	///blockchain_storage::open_blockchain), not here
 	pub fn new(config: ChainConfig) -> Result<Self> {
-		let world_state = WorldState::new(config.account_model);
-		let validator = Validator::new(config.validation_rules.clone());
+		if let Some(path) = &config.storage_path {
+			return Err(BlockchainError::StorageError(format!(
+				"{} needs a chain store; open the chain with Blockchain::with_store", path.display()
+			)));
+		}
+
+		let mut blockchain = Self::empty(config, None)?;
+		blockchain.create_genesis_block()?;
+
+		Ok(blockchain)
+
+	}
+
+
+	///open a blockchain backed by a persistent store.
+	///an empty store gets a fresh genesis block, otherwise the main chain is re-loaded
+	///and the world state is rebuilt by replaying it
+	pub fn with_store(config: ChainConfig, store: Box<dyn ChainStore>) -> Result<Self> {
+		let has_chain = store.chain_head()?.is_some();
+		let mut blockchain = Self::empty(config, Some(store))?;
+
+		if has_chain {
+			blockchain.load_from_store()?;
+		} else {
+			blockchain.create_genesis_block()?;
+		}
+
+		Ok(blockchain)
+	}
+
+
+	///start a chain from an imported state snapshot instead of genesis.
+	///the manifest's recent blocks are stored as the main chain up to the
+	///snapshot block without being replayed; the store must be empty.
+	///chain work is counted from the first of those blocks, which is enough
+	///to compare forks that branch off after it
+	pub fn from_state_snapshot(config: ChainConfig, store: Box<dyn ChainStore>, manifest: SnapshotManifest, state: WorldState) -> Result<Self> {
+		manifest.validate()?;
+		if manifest.chain_id != config.chain_id || manifest.account_model != config.account_model {
+			return Err(BlockchainError::InvalidChain(format!(
+				"snapshot is for chain {} ({:?}), not {} ({:?})",
+				manifest.chain_id, manifest.account_model, config.chain_id, config.account_model
+			)));
+		}
+		for block in &manifest.recent_blocks {
+			if let Some(expected) = config.checkpoints.get(&block.height()).filter(|id| **id != block.id()) {
+				return Err(BlockchainError::InvalidChain(format!(
+					"snapshot block {} at height {} contradicts checkpoint {}", block.id(), block.height(), expected
+				)));
+			}
+		}
+		if store.chain_head()?.is_some() {
+			return Err(BlockchainError::StorageError("cannot import a snapshot into a store that already holds a chain".to_string()));
+		}
+
+		let mut blockchain = Self::empty(config, Some(store))?;
+		for block in manifest.recent_blocks {
+			let block_id = block.id();
+			blockchain.persist_main_chain_block(&block)?;
+			blockchain.record_chain_work(&block);
+			blockchain.main_chain.insert(block.height(), block_id);
+			blockchain.blocks.insert(block_id, block);
+		}
+
+		blockchain.chain_head = Some(manifest.block_id);
+		blockchain.height = manifest.height;
+		blockchain.world_state = state;
+		blockchain.world_state.set_fee_market(blockchain.config.fee_market);
+		blockchain.record_state_snapshot(manifest.height)?;
+		if let Some(store) = &blockchain.store {
+			store.put_state_base(&blockchain.world_state.snapshot())?;
+			store.flush()?;
+		}
+
+		info!("Chain started from state snapshot at height {} ({})", manifest.height, manifest.block_id);
+		Ok(blockchain)
+	}
+
+
+	///a chain with no blocks yet
+	fn empty(config: ChainConfig, store: Option<Box<dyn ChainStore>>) -> Result<Self> {
+		let world_state = WorldState::new(config.account_model).with_fee_market(config.fee_market);
+		let signature_cache = Arc::new(SignatureCache::new(config.signature_cache.clone()));
+		let validator = Validator::with_signature_cache(config.validation_rules.clone(), signature_cache)
+			.with_signing_domain(config.signing_domain())
+			.with_emission(config.mining.block_reward, config.emission);
 		let mempool = Mempool::default();
+		config.checked_prune_depth()?;
 
-		let mut blockchain = Self {
+		Ok(Self {
 			config,
 			world_state,
 			blocks: HashMap::new(),
@@ -127,62 +338,148 @@ impl Blockchain{
 			mempool,
 			validator,
 			orphaned_blocks: HashMap::new(),
+			store,
+			chain_work: HashMap::new(),
+			state_snapshots: BTreeMap::new(),
+			undo_records: BTreeMap::new(),
+			receipts: HashMap::new(),
+			events: broadcast::channel(CHAIN_EVENT_CAPACITY).0,
+			pruned_height: None,
+			finalized_height: None,
+			invalid_blocks: HashSet::new(),
+			pending_headers: HashMap::new(),
+		})
+	}
+
+
+	///re-load the main chain from the store and replay it into the world state.
+	///a chain started from a state snapshot replays only the blocks after it
+	fn load_from_store(&mut self) -> Result<()> {
+		let (index, head, base) = match &self.store {
+			Some(store) => (store.main_chain()?, store.chain_head()?, store.state_base()?),
+			None => return Ok(()),
 		};
+		self.pruned_height = self.store.as_ref().map(|store| store.pruned_height()).transpose()?.flatten();
 
-		blockchain.create_genesis_block()?;
+		let base_height = base.as_ref().map(|base| base.block_height());
+		if let Some(base) = base {
+			self.world_state.restore_from_snapshot(base);
+		}
 
-		Ok(blockchain)
+		info!("Loading {} blocks from chain store", index.len());
 
-	}
+		let mut blocks = Vec::with_capacity(index.len());
+		if let Some(store) = &self.store {
+			for (height, block_id) in &index {
+				let block = store.get_block(block_id)?
+					.ok_or_else(|| BlockchainError::StorageError(
+						format!("Block {} at height {} missing from store", block_id, height)
+						))?;
+				blocks.push((*height, block));
+			}
+		}
 
+		blocks.sort_by_key(|(height, _)| *height);
 
-	///create genesis block
-	fn create_genesis_block(&mut self) -> Result<()> {
-		info!("creating genesis block");
+		for (height, block) in blocks {
+			let replay = base_height.map_or(true, |base| height > base);
+			if replay {
+				let undo = self.world_state.apply_block(&block)?;
+				self.world_state.set_block_height(height);
+				self.receipts.extend(Receipt::for_block(&block, &undo).into_iter().map(|receipt| (receipt.tx_id, receipt)));
+				self.record_undo(undo);
 
-		let genesis_config = &self.config.genesis;
+				if height == 0 {
+					self.apply_initial_accounts()?;
+				}
+			}
 
-		//create coinbase transaction
-		let coinbase_tx = Transaction::new_coinbase(
-			genesis_config.coinbase_recipient,
-			genesis_config.genesis_reward,
-			0, //genesis height
-			);
+			let block_id = block.id();
+			self.record_chain_work(&block);
+			self.main_chain.insert(height, block_id);
+			self.blocks.insert(block_id, block);
+			self.height = height;
+			if replay || base_height == Some(height) {
+				self.record_state_snapshot(height)?;
+			}
+		}
 
+		self.chain_head = head;
+
+		info!("Chain store loaded at height {}", self.height);
+		Ok(())
+	}
 
-		///create genesis block
-		let mut genesis_block = Block::new(
-			BlockId::genesis(),
-			vec![coinbase_tx],
-			genesis_config.difficulty,
-			0, //genesis height
-			self.config.chain_id,
-			)?;
 
-		//set custom timestamp if provided
-		if let Some(timestamp) = genesis_config.timestamp {
-			genesis_block.header.timestamp = Timestamp::from_unix_timestamp(timestamp);
+	///write a main-chain block and the new tip through to the store
+	fn persist_main_chain_block(&self, block: &Block) -> Result<()> {
+		if let Some(store) = &self.store {
+			let block_id = block.id();
+			store.put_block(block)?;
+			store.put_main_chain(block.height(), &block_id)?;
+			store.put_chain_head(&block_id)?;
+			store.flush()?;
 		}
 
+		Ok(())
+	}
 
-		//mine genesis block if needed
-		if self.config.mining.enable_mining {
-			info!("Mining genesis block...");
-			let mined = genesis_block.mine(Some(self.config.mining.max_mining_iterations))?;
 
-			if !mines {
-				return Err(BlockchainError::InvalidBlock(
-					"Failed o mine genesis block".to_string()
-					));
-			}
+	///write new state trie nodes and the root committed at a height to the store
+	fn persist_state_root(&self, height: BlockHeight, state_root: &Hash256, nodes: &[(Hash256, Vec<u8>)]) -> Result<()> {
+		if let Some(store) = &self.store {
+			store.put_state_root(height, state_root, nodes)?;
+		}
+
+		Ok(())
+	}
+
+
+	///chain configuration
+	pub fn config(&self) -> &ChainConfig {
+		&self.config
+	}
 
 
-			info!("Genesis block mined with nonce {}", genesis_block.header.nonce);
+	///subsidy the coinbase of the block at `height` may claim
+	pub fn block_subsidy(&self, height: BlockHeight) -> Amount {
+		self.config.block_subsidy(height)
+	}
+
+
+	///hit rate and occupancy of the shared signature cache
+	pub fn signature_cache_stats(&self) -> SignatureCacheStats {
+		self.validator.signature_cache().stats()
+	}
+
+
+	///consensus limits blocks must respect (size, transaction count, compute units)
+	pub fn validation_rules(&self) -> &ValidationRules {
+		self.validator.rules()
+	}
+
+
+	///flush pending chain writes to durable storage (no-op for in-memory chains)
+	pub fn flush(&self) -> Result<()> {
+		if let Some(store) = &self.store {
+			store.flush()?;
 		}
 
+		Ok(())
+	}
+
+
+	///create genesis block
+	fn create_genesis_block(&mut self) -> Result<()> {
+		info!("creating genesis block");
+
+		let genesis_block = genesis::genesis_block(&self.config)?;
+
 		let genesi_id = genesis_block.id();
 
 		//add to chain
+		self.persist_main_chain_block(&genesis_block)?;
+		self.record_chain_work(&genesis_block);
 		self.blocks.insert(genesi_id, genesis_block.clone());
 		self.main_chain.insert(0, genesi_id);
 		self.chain_head = Some(genesis_id);
@@ -190,62 +487,73 @@ impl Blockchain{
 
 
 		//apply genesis block to world state
-		for tx in genesis_block.transactions() {
-			self.world_state.apply_transaction(tx)?;
-		}
+		let undo = self.world_state.apply_block(&genesis_block)?;
+		self.record_receipts(&genesis_block, &undo)?;
 
 		self.world_state.set_block_height(0);
 
 
 		//initialize pre-funded accounts (for account model)
+		self.apply_initial_accounts()?;
+		self.record_state_snapshot(0)?;
+
+		info!("Genesis block created: {}", genesi_id);
+		Ok(())
+
+	}
+
 
-		for(address, balance) in &genesis_config.initial_accounts{
+	///credit the genesis pre-funded accounts
+	fn apply_initial_accounts(&mut self) -> Result<()> {
+		for(address, balance) in &self.config.genesis.initial_accounts{
 			let mut account_state = self.world_state.get_account(address).clone();
 			account_state.add_balance(*balance)?;
 			self.world_state.set_account(*address, account_state);
 
 		}
 
-		info!("Genesis block created: {}", genesi_id);
+		for account in self.dev_accounts()? {
+			let mut account_state = self.world_state.get_account(&account.address).clone();
+			account_state.add_balance(account.balance)?;
+			self.world_state.set_account(account.address, account_state);
+		}
+
 		Ok(())
+	}
+
 
+	///dev accounts funded at genesis (empty unless this is a Devnet or Local chain)
+	pub fn dev_accounts(&self) -> Result<Vec<DevAccount>> {
+		match &self.config.genesis.dev_accounts {
+			Some(spec) if dev_accounts::funds_dev_accounts(self.config.network) => spec.derive(),
+			_ => Ok(Vec::new()),
+		}
 	}
 
 
-	pub fn add_block(&mut self, mut block: Block) -> Result<BlockId> {
+	pub fn add_block(&mut self, block: Block) -> Result<BlockId> {
 		let block_id = block.id();
 		let block_height = block.height();
+		let _span = info_span!("block", block = %block_id, height = block_height).entered();
 
 		info!("Adding block {} at height {}", block_id, block_height);
 
 
 		//check if block already exists
-		if self.blocks.contains_key(&block_id){
+		if self.blocks.contains_key(&block_id) || self.orphaned_blocks.contains_key(&block_id){
 			return Err(BlockchainError::InvalidBlock(
 				"rblock already exists".to_string()
 				));
 		}
+		if self.invalid_blocks.contains(&block_id) || self.invalid_blocks.contains(&block.prev_hash()) {
+			self.invalid_blocks.insert(block_id);
+			return Err(BlockchainError::InvalidBlock(
+				format!("block {} is on a branch already found invalid", block_id)
+				));
+		}
 
 
-		//get previous block for validation
-		let prev_block = if block.is_genesis() {
-			None
-		} else {
-			self.blocks.get(&block.prev_hash())
-		};
-
-
-		//validate block
-		let validation_ctx = BlockValidationContext{
-			block: &block,
-			prev_block,
-			world_state: &self.world_state,
-			rules: self.validator.rules(),
-		};
-
-		self.validator.validate_block(validation_ctx)
-
-
+		self.check_checkpoints(&block)?;
 
 		//check if this block extends the main chain
 		let extend_main_chain = match self.chain_head {
@@ -254,7 +562,8 @@ impl Blockchain{
 		};
 
 		if extend_main_chain {
-			//add to main chain
+			//validate against the current tip state and add to main chain
+			self.validate_block_on_state(&block, &self.world_state)?;
 			self.add_to_main_chain(block)?;
 
 		}else {
@@ -262,79 +571,622 @@ impl Blockchain{
 			self.handle_fork(block)?;
 		}
 
+		//try to connect orphan blocks that might now have a parent
+		self.process_orphan_blocks()?;
+
 		Ok(block_id)
 	}
 
+
+	///remember headers ahead of the chain, e.g. the ones sync is about to fetch
+	///bodies for. once they reach the assume-valid block, it and every ancestor
+	///the headers link it to skip signature checks
+	pub fn note_headers(&mut self, headers: &[BlockHeader]) {
+		let Some(assume_valid) = self.config.assume_valid else {
+			return;
+		};
+		if self.blocks.contains_key(&assume_valid) {
+			self.pending_headers.clear();
+			return;
+		}
+		for header in headers {
+			if !self.blocks.contains_key(&header.id()) {
+				self.pending_headers.insert(header.id(), header.clone());
+			}
+		}
+
+		//ids are header hashes, so following parents from the assume-valid
+		//header can't be led onto another branch
+		let mut ancestors = Vec::new();
+		let mut next = assume_valid;
+		while let Some(header) = self.pending_headers.remove(&next) {
+			ancestors.push(next);
+			next = header.prev_block_hash;
+		}
+		if !ancestors.is_empty() {
+			info!("Skipping signature checks of {} blocks leading to assume-valid block {}", ancestors.len(), assume_valid);
+			self.pending_headers.clear();
+			self.validator.assume_valid(ancestors);
+		}
+	}
+
+
+	///reject a block at a checkpoint height with the wrong id, and any block forking off
+	///below the last checkpoint the main chain has passed or at a finalized height
+	///(it could never become the main chain)
+	fn check_checkpoints(&self, block: &Block) -> Result<()> {
+		let block_height = block.height();
+
+		if let Some(expected) = self.config.checkpoints.get(&block_height) {
+			if block.id() != *expected {
+				return Err(BlockchainError::InvalidBlock(
+					format!("block at height {} contradicts checkpoint {}", block_height, expected)
+				));
+			}
+		}
+
+		if self.chain_head.is_some() {
+			if let Some((&checkpoint_height, _)) = self.config.checkpoints.range(..=self.height).next_back() {
+				if block_height <= checkpoint_height {
+					return Err(BlockchainError::InvalidBlock(
+						format!("block at height {} forks below checkpoint at height {}", block_height, checkpoint_height)
+					));
+				}
+			}
+		}
+
+		if let Some(finalized) = self.finalized_height.filter(|finalized| block_height <= *finalized) {
+			return Err(BlockchainError::InvalidBlock(
+				format!("block at height {} forks below the finalized height {}", block_height, finalized)
+			));
+		}
+		Ok(())
+	}
+
+
+	///height up to which the main chain is final
+	pub fn finalized_height(&self) -> Option<BlockHeight> {
+		self.finalized_height
+	}
+
+
+	///mark the main chain final up to `height`; forks at or below it are refused from
+	///now on. finality only moves forward: a lower height is ignored
+	pub fn finalize(&mut self, height: BlockHeight) -> Result<()> {
+		if height > self.height {
+			return Err(BlockchainError::InvalidChain(
+				format!("cannot finalize height {} above the tip at {}", height, self.height)
+			));
+		}
+		if self.finalized_height.is_none_or(|finalized| height > finalized) {
+			info!("Finalized main chain up to height {}", height);
+			self.finalized_height = Some(height);
+		}
+		Ok(())
+	}
+
+
+	///the cheap proof-of-work checks, without touching state: a block failing
+	///them is spam and never worth full validation
+	fn verify_pow_header(&self, header: &BlockHeader) -> Result<()> {
+		if header.chain_id != self.config.chain_id {
+			return Err(BlockchainError::InvalidBlock(
+				format!("block is for chain {}, not {}", header.chain_id, self.config.chain_id)
+			));
+		}
+		let algorithm = self.config.validation_rules.block_hash;
+		if !header.meets_difficulty_with(algorithm) {
+			return Err(BlockchainError::InvalidBlock(
+				format!("block does not meet its difficulty target: {} < {}", header.hash_difficulty_with(algorithm), header.difficulty)
+			));
+		}
+
+		//with the parent known, the target itself can be checked; an orphan's
+		//target can't be until its ancestors arrive
+		if let Some(parent) = self.blocks.get(&header.prev_block_hash) {
+			if header.height != parent.height() + 1 {
+				return Err(BlockchainError::InvalidBlock(
+					format!("block height {} does not follow its parent at {}", header.height, parent.height())
+				));
+			}
+			let expected = difficulty::next_difficulty(parent, self.retarget_window_start(parent), self.validator.rules())?;
+			if header.difficulty != expected {
+				return Err(BlockchainError::InvalidBlock(
+					format!("invalid difficulty: expected {}, got {}", expected, header.difficulty)
+				));
+			}
+		}
+		Ok(())
+	}
+
+
+	///hard-coded checkpoints this chain enforces
+	pub fn checkpoints(&self) -> &BTreeMap<BlockHeight, BlockId> {
+		&self.config.checkpoints
+	}
+
+
+	///receive block and transaction events from now on. a receiver that falls more than
+	///CHAIN_EVENT_CAPACITY events behind gets RecvError::Lagged and skips ahead
+	pub fn subscribe(&self) -> broadcast::Receiver<ChainEvent> {
+		self.events.subscribe()
+	}
+
+
+	fn emit(&self, event: ChainEvent) {
+		//no receivers is not an error
+		let _ = self.events.send(event);
+	}
+
+
+	///block events clone the block, so skip them when nobody listens
+	fn emit_block(&self, block_id: &BlockId, connected: bool) {
+		if self.events.receiver_count() == 0 {
+			return;
+		}
+		if let Some(block) = self.blocks.get(block_id) {
+			let block = Arc::new(block.clone());
+			self.emit(if connected { ChainEvent::BlockConnected(block) } else { ChainEvent::BlockDisconnected(block) });
+		}
+	}
+
+
+	///publish replacements and evictions the mempool recorded
+	fn emit_mempool_events(&mut self) {
+		for event in self.mempool.drain_events() {
+			self.emit(ChainEvent::from(event));
+		}
+	}
+
+
+	///validate a block against the state it would be applied on top of
+	fn validate_block_on_state(&self, block: &Block, state: &WorldState) -> Result<()> {
+		//get previous block for validation
+		let prev_block = if block.is_genesis() {
+			None
+		} else {
+			self.blocks.get(&block.prev_hash())
+		};
+
+		let retarget_start = prev_block
+			.and_then(|prev| self.retarget_window_start(prev));
+
+		let validation_ctx = BlockValidationContext{
+			block,
+			prev_block,
+			retarget_start,
+			world_state: state,
+			rules: self.validator.rules(),
+		};
+
+		self.validator.validate_block(validation_ctx)
+	}
+
 	fn add_to_main_chain(&mut self, block: Block) -> Result<()> {
 		let block_id = block.id();
 		let block_height =  block.height();
 
 		//apply block transaction to world state
 		let mut new_state = self.world_state.clone();
-		for tx in block.transactions() {
-			new_state.apply_transaction(tx)?;
-		}
+		let undo = new_state.apply_block(&block)?;
 
 		new_state.set_block_height(block_height);
 
 		//remove transaction from mempool
-		let tx_ids: Vec<TxId> = block.transactions().iter().map(|tx| tx.id());
+		let tx_ids: Vec<TxId> = block.transactions().iter().map(|tx| tx.id()).collect();
 		self.mempool.remove_transactions(&tx_ids);
+		self.mempool.remove_used_nonces(&new_state);
+		//and whatever else the block made invalid, e.g. spends of outputs it spent
+		//or gas prices below the base fee it raised
+		self.mempool.revalidate(&new_state, Vec::new());
 
 		//update chain state
+		self.persist_main_chain_block(&block)?;
+		if let Some(store) = &self.store {
+			store.put_block_undo(&undo)?;
+		}
+		self.record_receipts(&block, &undo)?;
+		self.record_undo(undo);
+		self.record_chain_work(&block);
 		self.blocks.insert(block_id, block);
 		self.main_chain.insert(block_height, block_id);
 		self.chain_head = Some(block_id);
 		self.height = block_height;
 		self.world_state = new_state;
+		self.record_state_snapshot(block_height)?;
+		self.prune_blocks()?;
+		self.emit_block(&block_id, true);
+		self.emit_mempool_events();
 
 		info!("Block {} added to main chain at height {}", block_id, block_height);
 		Ok(())
 	}
 
 
-	///handle potential blockchain fork
+	///remember cumulative work up to and including this block
+	fn record_chain_work(&mut self, block: &Block) {
+		let parent_work = self.chain_work.get(&block.prev_hash()).copied().unwrap_or(0);
+		let work = parent_work.saturating_add(block_work(block.header.difficulty));
+		self.chain_work.insert(block.id(), work);
+	}
+
+
+	///generate and persist the receipts of a block joining the main chain
+	fn record_receipts(&mut self, block: &Block, undo: &BlockUndo) -> Result<()> {
+		let receipts = Receipt::for_block(block, undo);
+		if let Some(store) = &self.store {
+			store.put_receipts(&receipts)?;
+		}
+		self.receipts.extend(receipts.into_iter().map(|receipt| (receipt.tx_id, receipt)));
+		Ok(())
+	}
+
+
+	///keep a main chain block's undo record, dropping records deeper than any reorg we would accept
+	fn record_undo(&mut self, undo: BlockUndo) {
+		let cutoff = undo.height.saturating_sub(MAX_REORG_DEPTH);
+		self.undo_records.insert(undo.height, undo);
+		self.undo_records = self.undo_records.split_off(&cutoff);
+	}
+
+
+	///undo records for the main chain above a height, newest first.
+	///None if any is missing from both memory and the store
+	fn undo_records_above(&self, height: BlockHeight) -> Result<Option<Vec<BlockUndo>>> {
+		let mut records = Vec::new();
+		for undo_height in ((height + 1)..=self.height).rev() {
+			let block_id = match self.main_chain.get(&undo_height) {
+				Some(block_id) => *block_id,
+				None => return Ok(None),
+			};
+
+			let undo = match self.undo_records.get(&undo_height).filter(|undo| undo.block_id == block_id) {
+				Some(undo) => Some(undo.clone()),
+				None => match &self.store {
+					Some(store) => store.get_block_undo(&block_id)?,
+					None => None,
+				},
+			};
+
+			match undo {
+				Some(undo) => records.push(undo),
+				None => return Ok(None),
+			}
+		}
+
+		Ok(Some(records))
+	}
+
+
+	///commit the state root for the main chain block at this height and snapshot
+	///the world state, dropping snapshots that are deeper than any reorg we would accept
+	fn record_state_snapshot(&mut self, height: BlockHeight) -> Result<()> {
+		let state_root = self.world_state.commit_state_root(height)?;
+		let nodes = self.world_state.take_new_state_nodes();
+		self.persist_state_root(height, &state_root, &nodes)?;
+
+		self.state_snapshots.insert(height, self.world_state.snapshot());
+
+		let cutoff = height.saturating_sub(MAX_REORG_DEPTH);
+		self.state_snapshots = self.state_snapshots.split_off(&cutoff);
+		Ok(())
+	}
+
+
+	///cut main chain blocks deeper than the prune depth down to their headers.
+	///the store first gets the oldest retained world state, which no reorg can
+	///undo, so a restart resumes from it instead of replaying pruned blocks
+	fn prune_blocks(&mut self) -> Result<()> {
+		let Some(depth) = self.config.prune_depth else {
+			return Ok(());
+		};
+		let Some(target) = self.height.checked_sub(depth) else {
+			return Ok(());
+		};
+		let first = self.pruned_height.map_or(0, |height| height + 1);
+		if target < first + PRUNE_BATCH - 1 {
+			return Ok(());
+		}
+
+		if let Some(store) = &self.store {
+			let (_, base) = self.state_snapshots.first_key_value()
+				.ok_or_else(|| BlockchainError::StateError("no world state to prune from".to_string()))?;
+			store.put_state_base(base)?;
+		}
+		for height in first..=target {
+			let Some(block_id) = self.main_chain.get(&height).copied() else { continue };
+			if let Some(block) = self.blocks.get_mut(&block_id) {
+				*block = block.pruned();
+			}
+			if let Some(store) = &self.store {
+				store.prune_block(height, &block_id)?;
+			}
+		}
+		if let Some(store) = &self.store {
+			store.flush()?;
+		}
+
+		self.pruned_height = Some(target);
+		info!("Pruned block bodies up to height {}", target);
+		Ok(())
+	}
+
+
+	///main chain blocks up to this height only have their headers
+	pub fn pruned_height(&self) -> Option<BlockHeight> {
+		self.pruned_height
+	}
+
+
+	///whether the main chain block at a height has been cut down to its header
+	pub fn is_pruned(&self, height: BlockHeight) -> bool {
+		self.pruned_height.is_some_and(|pruned| height <= pruned)
+	}
+
+
+	///get cumulative work of the chain ending at a block
+	pub fn chain_work(&self, block_id: &BlockId) -> Option<u128> {
+		self.chain_work.get(block_id).copied()
+	}
+
+
+	///handle potential blockchain fork. a side-branch block is checked against
+	///its parent (proof of work, header, difficulty, timestamp, merkle root)
+	///before it is stored or served to peers; its transactions are only
+	///validated, on a scratch copy of the state, once its branch has more work
 	fn handle_fork(&mut self, block: Block) -> Result<()> {
 		let block_id = block.id();
 		let block_height = block.height();
 
 		warn!("Potential fork detected with block {} at height {}", block_id, block_height);
 
+		//the proof of work is checked even for orphans, so they cost their
+		//sender real work; the target is checked once the parent is known
+		self.verify_pow_header(&block.header)?;
 
 		//check if previous block exists (might be orphan)
-		if !self.blocks.contains_key(&block.prev_hash){
+		let Some(parent) = self.blocks.get(&block.prev_hash()) else {
 			info!("Adding orphan block: {}", block_id);
 			self.orphaned_blocks.insert(block_id, block);
 			return Ok(());
+		};
+
+		self.validator.validate_block_against_parent(BlockValidationContext {
+			block: &block,
+			prev_block: Some(parent),
+			retarget_start: self.retarget_window_start(parent),
+			world_state: &self.world_state,
+			rules: self.validator.rules(),
+		})?;
+
+		//keep the side-branch block and its cumulative work
+		self.record_chain_work(&block);
+		self.blocks.insert(block_id, block);
+
+		//heaviest chain wins
+		let head_work = self.chain_head
+			.and_then(|head_id| self.chain_work(&head_id))
+			.unwrap_or(0);
+		let branch_work = self.chain_work(&block_id).unwrap_or(0);
+
+		if branch_work > head_work {
+			info!("Branch ending at {} has more work ({} > {}), reorganizing", block_id, branch_work, head_work);
+			if let Err(e) = self.reorganize(block_id) {
+				//a branch that failed for want of undo data or depth stays
+				//valid; forget its tip so it can't win fork choice again
+				if self.blocks.contains_key(&block_id) {
+					self.blocks.remove(&block_id);
+					self.chain_work.remove(&block_id);
+				}
+				return Err(e);
+			}
+		}
+
+		Ok(())
+	}
+
+
+	///forget a side-branch block that failed validation along with everything
+	///built on it, stored or orphaned, and refuse them from now on
+	fn discard_branch(&mut self, invalid: BlockId) {
+		let mut discarded = vec![invalid];
+		let mut next = 0;
+		while let Some(&parent) = discarded.get(next) {
+			next += 1;
+			let children: Vec<BlockId> = self.blocks.iter()
+				.chain(self.orphaned_blocks.iter())
+				.filter(|(_, block)| block.prev_hash() == parent)
+				.map(|(block_id, _)| *block_id)
+				.collect();
+			discarded.extend(children);
+		}
+
+		for block_id in &discarded {
+			self.blocks.remove(block_id);
+			self.orphaned_blocks.remove(block_id);
+			self.chain_work.remove(block_id);
+			self.invalid_blocks.insert(*block_id);
+		}
+		warn!("Discarded invalid block {} and {} descendants", invalid, discarded.len() - 1);
+	}
+
+
+	///switch the main chain to the branch ending at new_tip.
+	///the world state is rewound to the fork point with the old branch's undo records, the new branch is
+	///validated and applied on a scratch copy, and only committed if every block is valid
+	fn reorganize(&mut self, new_tip: BlockId) -> Result<()> {
+		//walk back from the new tip to the first block on the main chain
+		let mut new_branch = Vec::new();
+		let mut cursor = new_tip;
+		let fork_point = loop {
+			let block = self.blocks.get(&cursor)
+				.ok_or_else(|| BlockchainError::InvalidChain(
+					format!("Missing block {} while walking fork", cursor)
+					))?;
+
+			if self.main_chain.get(&block.height()) == Some(&cursor) {
+				break block.height();
+			}
+
+			new_branch.push(cursor);
+			cursor = block.prev_hash();
+		};
+		new_branch.reverse();
+
+		if self.height.saturating_sub(fork_point) > MAX_REORG_DEPTH {
+			return Err(BlockchainError::InvalidChain(
+				format!("Reorg depth {} exceeds maximum {}", self.height - fork_point, MAX_REORG_DEPTH)
+				));
 		}
 
+		//rewind world state to the fork point by undoing the old branch newest first,
+		//or from the fork point snapshot if some undo record is missing.
+		//start from a copy of the current state so the trie still has the fork point's nodes
+		let mut state = self.world_state.clone();
+		match self.undo_records_above(fork_point)? {
+			Some(records) => {
+				for undo in &records {
+					state.revert_block(undo)?;
+				}
+			}
+			None => {
+				let snapshot = self.state_snapshots.get(&fork_point)
+					.cloned()
+					.ok_or_else(|| BlockchainError::InvalidChain(
+						format!("No undo data or state snapshot at fork point {}", fork_point)
+						))?;
+				state.restore_from_snapshot(snapshot);
+			}
+		}
+		state.set_block_height(fork_point);
+		state.rollback_state_roots(fork_point);
+
+		//validate and apply the new branch
+		let mut new_snapshots = Vec::with_capacity(new_branch.len());
+		let mut new_roots = Vec::with_capacity(new_branch.len());
+		let mut new_undo = Vec::with_capacity(new_branch.len());
+		for block_id in &new_branch {
+			let block = &self.blocks[block_id];
+			let applied = self.validate_block_on_state(block, &state)
+				.and_then(|()| state.apply_block(block));
+			let undo = match applied {
+				Ok(undo) => undo,
+				Err(e) => {
+					self.discard_branch(*block_id);
+					return Err(e);
+				}
+			};
+
+			new_undo.push(undo);
+			state.set_block_height(block.height());
+			let state_root = state.commit_state_root(block.height())?;
+			new_roots.push((block.height(), state_root, state.take_new_state_nodes()));
+			new_snapshots.push((block.height(), state.snapshot()));
+		}
+
+		//disconnect the old branch
+		let old_branch: Vec<BlockId> = ((fork_point + 1)..=self.height)
+			.filter_map(|height| self.main_chain.remove(&height))
+			.collect();
+		self.state_snapshots.retain(|height, _| *height <= fork_point);
+		self.undo_records.retain(|height, _| *height <= fork_point);
+		self.receipts.retain(|_, receipt| receipt.block_height <= fork_point);
+
+		if let Some(store) = &self.store {
+			for height in (fork_point + 1)..=self.height {
+				store.remove_main_chain(height)?;
+			}
+		}
+
+		//connect the new branch
+		for block_id in &new_branch {
+			let block = &self.blocks[block_id];
+			self.main_chain.insert(block.height(), *block_id);
+			self.persist_main_chain_block(block)?;
+		}
+		for (height, state_root, nodes) in &new_roots {
+			self.persist_state_root(*height, state_root, nodes)?;
+		}
+		for (block_id, undo) in new_branch.iter().zip(new_undo) {
+			if let Some(store) = &self.store {
+				store.put_block_undo(&undo)?;
+			}
+			let block = self.blocks[block_id].clone();
+			self.record_receipts(&block, &undo)?;
+			self.record_undo(undo);
+		}
+
+		let new_height = self.blocks[&new_tip].height();
+		self.state_snapshots.extend(new_snapshots);
+		self.chain_head = Some(new_tip);
+		self.height = new_height;
+		self.world_state = state;
+		self.prune_blocks()?;
+
+		//transactions confirmed on the new branch leave the mempool
+		let mut confirmed = std::collections::HashSet::new();
+		for block_id in &new_branch {
+			for tx in self.blocks[block_id].transactions() {
+				confirmed.insert(tx.id());
+			}
+		}
+		let confirmed_ids: Vec<TxId> = confirmed.iter().copied().collect();
+		self.mempool.remove_transactions(&confirmed_ids);
+		self.mempool.remove_used_nonces(&self.world_state);
+
+		//transactions only on the old branch go back to the mempool
+		let evicted: Vec<Transaction> = old_branch.iter()
+			.filter_map(|block_id| self.blocks.get(block_id))
+			.flat_map(|block| block.transactions().iter())
+			.filter(|tx| !tx.is_coinbase() && !confirmed.contains(&tx.id()))
+			.cloned()
+			.collect();
+
+		for block_id in old_branch.iter().rev() {
+			self.emit_block(block_id, false);
+		}
+		for block_id in &new_branch {
+			self.emit_block(block_id, true);
+		}
 
-		//TODO: IMPLEMENT PROPER FORK RESOLUTION
-		//for now i store as orphan
-		self.orphaned_blocks.insert(block_id, block);
+		//pooled transactions are checked against the new branch too: they may
+		//spend outputs of the old one, or ones the new one spent
+		let evicted_count = evicted.len();
+		let revalidation = self.mempool.revalidate(&self.world_state, evicted);
+		for tx_id in &revalidation.reinserted {
+			if let Some(tx) = self.mempool.get_transaction(tx_id) {
+				self.emit(ChainEvent::TxAdded(Arc::new(tx.clone())));
+			}
+		}
+		for tx_id in revalidation.rejected {
+			warn!("Dropping transaction {} evicted by reorg", tx_id);
+			self.emit(ChainEvent::TxDropped { tx_id, reason: TxDropReason::Reorged });
+		}
+		self.emit_mempool_events();
 
-		//try to process orphan blocks that might now be valid
-		self.process_orphan_blocks()?;
+		info!(
+			"Reorganized to {} at height {} (fork point {}, {} blocks disconnected, {} transactions evicted)",
+			new_tip, new_height, fork_point, old_branch.len(), evicted_count
+		);
+		Ok(())
 	}
 
 	//process orphan blocks that may be valid
 	fn process_orphan_blocks(&mut self) -> Result<()> {
-		let mut processed = Vec::new();
-
 		//look for o_b whose parents are now available
-		for (orphan_id, orphan_block) in &self.orphaned_blocks {
-			if self.blocks.contains_key(&orphan_id.prev_hash()) {
-				//this orphan can be processed
-				processed.push(*orphan_id);
-			}
-		}
+		let processed: Vec<BlockId> = self.orphaned_blocks.iter()
+			.filter(|(_, orphan_block)| self.blocks.contains_key(&orphan_block.prev_hash()))
+			.map(|(orphan_id, _)| *orphan_id)
+			.collect();
 
 
 		//process the orphan blocks
 		for orphan_id in processed {
-			if let Some(orphaned_block) = self.orphaned_blocks.remove(&orphan_id) {
+			if let Some(orphan_block) = self.orphaned_blocks.remove(&orphan_id) {
 				info!("Processing orphan block: {}", orphan_id);
-				self.handle_fork(orphan_block)?;
+				if let Err(e) = self.add_block(orphan_block) {
+					warn!("Orphan block {} rejected: {}", orphan_id, e);
+				}
 			}
 		}
 
@@ -353,8 +1205,27 @@ impl Blockchain{
 				));
 		}
 
-		//add mempool
-		self.mempool.add_transaction(transaction, &self.world_state)?;
+		//check utxo signatures up front; valid ones are cached for when the block arrives
+		if self.config.validation_rules.verify_signatures && !transaction.inputs.is_empty() {
+			let cache = self.validator.signature_cache();
+			//inputs may spend outputs of transactions still in the mempool
+			let utxo_set = self.mempool.utxo_view(self.world_state.utxo_set(), &transaction);
+			let accept_legacy = self.config.validation_rules.accept_legacy_signatures;
+			if !transaction.verify_signatures_cached(&utxo_set, cache, SigCacheMode::Store, self.config.signing_domain(), accept_legacy)? {
+				return Err(BlockchainError::InvalidTransaction(
+					"Invalid transaction signature".to_string()
+					));
+			}
+		}
+
+		//add mempool, announcing the transaction only if someone listens
+		let announced = (self.events.receiver_count() > 0).then(|| Arc::new(transaction.clone()));
+		let result = self.mempool.add_transaction(transaction, &self.world_state);
+		if let (Ok(_), Some(tx)) = (&result, announced) {
+			self.emit(ChainEvent::TxAdded(tx));
+		}
+		self.emit_mempool_events();
+		result?;
 
 		info!("Transaction {} added to mempool". tx_id);
 
@@ -362,6 +1233,16 @@ impl Blockchain{
 	}
 
 
+	///announce a double spend seen elsewhere (e.g. relayed by a peer) to subscribers,
+	///once it checks out against the unspent output it claims to spend twice
+	pub fn report_double_spend(&self, proof: ConflictProof) -> Result<()> {
+		let utxo_set = self.mempool.utxo_view(self.world_state.utxo_set(), &proof.first);
+		proof.verify(&utxo_set, self.config.signing_domain(), self.config.validation_rules.accept_legacy_signatures)?;
+		self.emit(ChainEvent::DoubleSpend(Arc::new(proof)));
+		Ok(())
+	}
+
+
 	//check i transaction exists in blockchain
 	pub fn transaction_exists(&self, tx_id: &TxId) -> bool {
 		for block in self.blocks.values() {
@@ -388,6 +1269,21 @@ impl Blockchain{
 	}
 
 
+	///receipt of a transaction on the main chain
+	pub fn get_receipt(&self, tx_id: &TxId) -> Result<Option<Receipt>> {
+		let receipt = match self.receipts.get(tx_id) {
+			Some(receipt) => Some(receipt.clone()),
+			None => match &self.store {
+				Some(store) => store.get_receipt(tx_id)?,
+				None => None,
+			},
+		};
+
+		//the store may still hold the receipt from a block since disconnected
+		Ok(receipt.filter(|receipt| self.main_chain.get(&receipt.block_height) == Some(&receipt.block_id)))
+	}
+
+
 	///get block by id
 	pub fn get_block(&self, block_id: &BlockId) -> Option<&Block> {
 		self.blocks.get(block_id)
@@ -400,17 +1296,138 @@ impl Blockchain{
 	}
 
 
+	///world state after the main chain block at a height. only the tip and
+	///the last `MAX_REORG_DEPTH` blocks are kept
+	pub fn state_at(&self, height: BlockHeight) -> Option<WorldState> {
+		if height == self.height {
+			return Some(self.world_state.clone());
+		}
+
+		let snapshot = self.state_snapshots.get(&height)?.clone();
+		let mut state = WorldState::new(self.config.account_model).with_fee_market(self.config.fee_market);
+		state.restore_from_snapshot(snapshot);
+		Some(state)
+	}
+
+
+	///snapshot the world state at a main chain height for another node to
+	///import, split into chunks of at most `entries_per_chunk` entries
+	pub fn export_state_snapshot(&self, height: BlockHeight, entries_per_chunk: usize) -> Result<(SnapshotManifest, Vec<SnapshotChunk>)> {
+		let mut state = self.state_at(height).ok_or_else(|| BlockchainError::StateError(
+			format!("no world state kept at height {} (tip {}, depth limit {})", height, self.height, MAX_REORG_DEPTH)
+		))?;
+		let block = self.get_block_by_height(&height)
+			.ok_or_else(|| BlockchainError::BlockNotFound(format!("main chain height {}", height)))?;
+
+		//enough ancestors to check every retarget until the next period starts
+		let first = difficulty::retarget_window_start(height + 1, self.validator.rules());
+		let recent_blocks = (first..=height)
+			.map(|height| self.get_block_by_height(&height).cloned()
+				.ok_or_else(|| BlockchainError::BlockNotFound(format!("main chain height {}", height))))
+			.collect::<Result<Vec<_>>>()?;
+
+		let chunks = snapshot::state_chunks(&state, entries_per_chunk);
+		let manifest = SnapshotManifest {
+			chain_id: self.config.chain_id,
+			account_model: self.config.account_model,
+			height,
+			block_id: block.id(),
+			state_root: state.state_root(),
+			chunk_hashes: chunks.iter().map(SnapshotChunk::hash).collect::<Result<_>>()?,
+			recent_blocks,
+			base_fee: state.base_fee(),
+			burned: state.burned_supply(),
+		};
+
+		Ok((manifest, chunks))
+	}
+
+
 	///get current height
 	pub fn height(&self) -> BlockHeight {
 		self.height
 	}
 
 
+	///get the main chain tip block
+	pub fn get_chain_head(&self) -> Option<&Block> {
+		self.chain_head.and_then(|block_id| self.blocks.get(&block_id))
+	}
+
+
+	///get up to `max_count` main chain headers starting at `start_height` (for peers syncing from us)
+	pub fn get_headers(&self, start_height: BlockHeight, max_count: usize) -> Vec<BlockHeader> {
+		let max_count = max_count.min(crate::sync::MAX_HEADERS_PER_REQUEST);
+		(start_height..=self.height)
+			.take(max_count)
+			.map_while(|height| self.get_block_by_height(&height))
+			.map(|block| block.header.clone())
+			.collect()
+	}
+
+
+	///main chain ids a syncing peer is asked to find its fork point from:
+	///the last ten blocks, then back twice as far each step, ending at genesis
+	pub fn block_locator(&self) -> Vec<BlockId> {
+		let mut locator = Vec::new();
+		let mut height = self.height;
+		let mut step = 1;
+		loop {
+			if let Some(block_id) = self.main_chain.get(&height) {
+				locator.push(*block_id);
+			}
+			if height == 0 {
+				break;
+			}
+			if locator.len() >= 10 {
+				step *= 2;
+			}
+			height = height.saturating_sub(step);
+		}
+		locator
+	}
+
+
+	///get up to `max_count` main chain headers after the first `locator` block
+	///on our main chain, or after genesis if none is (for peers syncing from us)
+	pub fn get_headers_after(&self, locator: &[BlockId], max_count: usize) -> Vec<BlockHeader> {
+		let fork_height = locator.iter()
+			.filter_map(|block_id| self.blocks.get(block_id))
+			.find(|block| self.main_chain.get(&block.header.height) == Some(&block.id()))
+			.map_or(0, |block| block.header.height);
+		self.get_headers(fork_height + 1, max_count)
+	}
+
+
+	///difficulty chain summary between two main chain heights, for light clients.
+	///`from` must be a retarget boundary (or genesis)
+	pub fn prove_difficulty(&self, from: BlockHeight, to: BlockHeight) -> Result<DifficultyProof> {
+		if to > self.height {
+			return Err(BlockchainError::BlockNotFound(format!("no main chain block at height {}", to)));
+		}
+
+		let headers = light_client::proof_heights(from, to, self.validator.rules())?
+			.into_iter()
+			.map(|height| self.get_block_by_height(&height)
+				.map(|block| block.header.clone())
+				.ok_or_else(|| BlockchainError::BlockNotFound(format!("no main chain block at height {}", height))))
+			.collect::<Result<Vec<_>>>()?;
+
+		Ok(DifficultyProof { headers })
+	}
+
+
 	//get world-state
 	pub fn world_state(&self) -> &WorldState {
 		&self.world_state
 	}
 
+
+	///merkle proof of an account against the current state root
+	pub fn prove_account(&mut self, address: &Address) -> Result<AccountProof> {
+		self.world_state.prove_account(address)
+	}
+
 	///get mempool
 	pub fn mempool(&self) -> &Mempool {
 		&self.mempool
@@ -422,30 +1439,31 @@ impl Blockchain{
 	}
 
 
-	//mine a block
-	pub fn mine_block(&mut self, miner_address: Address) -> Result<Block> {
-		if !self.config.mining.enable_mining {
-			return Err(BlockchainError::InvalidBlock(
-				"Mining is disabled".to_string()
-				));
-		}
+	///build an unmined block on the current head: coinbase to miner_address plus the
+	///best mempool transactions that fit. Callers search for a nonce and submit it with add_block
+	pub fn create_block_template(&self, miner_address: Address) -> Result<Block> {
+		self.create_block_template_until(miner_address, None)
+	}
 
-		info!("Mining a mew block for address: {}", miner_address);
 
+	///build a block template, spending no longer than until `deadline` picking
+	///mempool transactions; the block then holds the best of what was picked in time
+	pub fn create_block_template_until(&self, miner_address: Address, deadline: Option<std::time::Instant>) -> Result<Block> {
 		//get transactions from mempool
 		let max_transactions = self.validator.rules().max_transactions_per_block;
-		let max_size = self.validator.rules().max_block_size;
-		let pending_txs = self.mempool.get_transaction_for_block(
+		let limits = BlockWeight::new(self.validator.rules());
+		let pending_txs = self.mempool.get_transactions_for_block_until(
 			max_transactions,
-			max_size,
+			&limits,
 			&self.world_state,
+			deadline,
 			);
 
 		//create coinbase transaction
 		let next_height = self.height + 1;
 		let coinbase_tx = Transaction::new_coinbase(
 			miner_address,
-			self.config.mining,block_reward,
+			self.block_subsidy(next_height),
 			next_height,
 			);
 
@@ -461,18 +1479,32 @@ impl Blockchain{
 		let difficulty = self.calculate_next_difficulty()?;
 
 		//create new block
-		let mut new_block = Block::new(
+		Block::new(
 			prev_hash,
 			block_transactions,
 			difficulty,
 			next_height,
 			self.config.chain_id,
-			)?;
+			)
+	}
+
+
+	//mine a block
+	pub fn mine_block(&mut self, miner_address: Address) -> Result<Block> {
+		if !self.config.mining.enable_mining {
+			return Err(BlockchainError::InvalidBlock(
+				"Mining is disabled".to_string()
+				));
+		}
+
+		info!("Mining a mew block for address: {}", miner_address);
+
+		let mut new_block = self.create_block_template(miner_address)?;
 
 		//mine the block
 		info!("Starting mining process...");
 		let mining_start = std::time::Instant::now();
-		let mined = new_block.mine(Some(self.config.mining.max_mining_iterations))?;
+		let mined = new_block.mine_with(self.config.validation_rules.block_hash, Some(self.config.mining.max_mining_iterations))?;
 
 		if !mined{
 			return Err(BlockchainError::InvalidBlock(
@@ -492,11 +1524,34 @@ impl Blockchain{
 	}
 
 
-	///calculate next block difficulty (simplified version)
+	///calculate difficulty for the block after the current head
 	fn calculate_next_difficulty(&self) -> Result<Difficulty> {
-		//for now, return a constant diff...in real implementation
-		//adjustment will be based on block times.
-		Ok(self.config.genesis.difficulty)
+		let head = match self.chain_head.and_then(|head_id| self.blocks.get(&head_id)) {
+			Some(head) => head,
+			None => return Ok(self.config.genesis.difficulty),
+		};
+
+		let window_start = self.retarget_window_start(head);
+		difficulty::next_difficulty(head, window_start, self.validator.rules())
+	}
+
+
+	///find the first block of the retarget window for the block after prev,
+	///walking back along prev's own branch so side chains retarget correctly
+	fn retarget_window_start(&self, prev: &Block) -> Option<&Block> {
+		let rules = self.validator.rules();
+		let next_height = prev.height() + 1;
+		if !difficulty::is_retarget_height(next_height, rules) {
+			return None;
+		}
+
+		let start_height = difficulty::retarget_window_start(next_height, rules);
+		let mut cursor = prev;
+		while cursor.height() > start_height {
+			cursor = self.blocks.get(&cursor.prev_hash())?;
+		}
+
+		Some(cursor)
 	}
 
 
@@ -526,6 +1581,8 @@ impl Blockchain{
 			total_blocks: self.blocks.len(),
 			total_transactions,
 			total_supply,
+			burned_supply: self.world_state.burned_supply(),
+			base_fee: self.world_state.base_fee(),
 			mempool_size: mempool_stats.transaction_count,
 			orphaned_blocks: self.orphaned_blocks.len(),
 			chain_head: self.chain_head,
@@ -542,13 +1599,19 @@ impl Blockchain{
 		let mut blocks_by_height: Vec<_> = self.main_chain.iter().collect();
 		blocks_by_height.sort_by_key(|(height, _)| *height);
 
+		if let Some(pruned) = self.pruned_height {
+			return Err(BlockchainError::InvalidChain(
+				format!("blocks up to height {} are pruned and can't be replayed", pruned)
+				));
+		}
+
 		let blocks: Vec<&Block>  = blocks_by_height.iter()
 			.filter_map(|(_, block_id)| self.blocks.get(block_id))
 			.collect();
 
 
 		//create initial state for validation
-		let initial_state = WorldState::new(self.config.account_model);
+		let initial_state = WorldState::new(self.config.account_model).with_fee_market(self.config.fee_market);
 
 		//validation chain consistency
 		crate::validation::validate_chain_consistency(&self.validator, &blocks, &initial_state)?;
@@ -565,6 +1628,39 @@ impl Blockchain{
 			.collect()
 	}
 
+	///main chain logs matching `filter`, oldest first, including what contracts
+	///logged into the receipts. blocks whose bloom rules out a match and
+	///whose contracts logged nothing are skipped
+	pub fn get_logs(&self, filter: &LogFilter) -> Result<Vec<LogEntry>> {
+		if filter.to_height < filter.from_height {
+			return Err(BlockchainError::ValidationError(
+				format!("invalid range {}..{}", filter.from_height, filter.to_height)
+				));
+		}
+		if filter.to_height - filter.from_height >= MAX_LOG_QUERY_RANGE {
+			return Err(BlockchainError::ValidationError(
+				format!("range exceeds {} blocks", MAX_LOG_QUERY_RANGE)
+				));
+		}
+
+		if self.is_pruned(filter.from_height) {
+			return Err(BlockchainError::ValidationError(
+				format!("blocks up to height {} are pruned", self.pruned_height.unwrap_or_default())
+				));
+		}
+
+		let to_height = filter.to_height.min(self.height);
+		let mut entries = Vec::new();
+		for block in (filter.from_height..=to_height).filter_map(|height| self.get_block_by_height(&height)) {
+			let mut program_logs = Vec::with_capacity(block.transaction_count());
+			for tx in block.transactions() {
+				program_logs.push(self.get_receipt(&tx.id())?.map(|receipt| receipt.logs).unwrap_or_default());
+			}
+			entries.extend(logs::block_logs(block, &program_logs, filter));
+		}
+		Ok(entries)
+	}
+
 	//get recent blocks
 	pub fn get_recent_blocks(&self, count: usize) ->Vec<&Block> {
 		let start_height = self.height.saturating_sub(count as BlockHeight);
@@ -611,6 +1707,73 @@ impl Blockchain{
 		}
 	}
 
+	///every known block in the last `depth` heights (main chain, side branches and
+	///orphans) with its status and cumulative work, for drawing fork diagrams
+	pub fn get_chain_tree(&self, depth: BlockHeight) -> ChainTree {
+		let start_height = (self.height + 1).saturating_sub(depth.max(1));
+
+		let candidates = self.blocks.values()
+			.map(|block| (block, false))
+			.chain(self.orphaned_blocks.values().map(|block| (block, true)))
+			.filter(|(block, _)| block.height() >= start_height);
+
+		let parents: std::collections::HashSet<BlockId> = self.blocks.values()
+			.chain(self.orphaned_blocks.values())
+			.map(|block| block.prev_hash())
+			.collect();
+
+		let mut nodes: Vec<ChainTreeNode> = candidates
+			.map(|(block, orphan)| {
+				let block_id = block.id();
+				let status = if orphan {
+					ChainTreeStatus::Orphan
+				} else if self.main_chain.get(&block.height()) == Some(&block_id) {
+					ChainTreeStatus::Active
+				} else {
+					ChainTreeStatus::Fork
+				};
+
+				ChainTreeNode {
+					block_id,
+					prev_block_id: block.prev_hash(),
+					height: block.height(),
+					timestamp: block.timestamp(),
+					difficulty: block.header.difficulty,
+					tx_count: block.transaction_count(),
+					status,
+					chain_work: self.chain_work(&block_id),
+					is_tip: !parents.contains(&block_id),
+					branch_length: self.branch_length(block),
+				}
+			})
+			.collect();
+
+		//main chain first at each height so renderers keep it on one line
+		nodes.sort_by_key(|node| (node.height, node.status != ChainTreeStatus::Active, node.block_id.to_string()));
+
+		ChainTree {
+			tip: self.chain_head,
+			height: self.height,
+			start_height,
+			nodes,
+		}
+	}
+
+	//blocks between `block` and the main chain, 0 for main chain blocks and None if
+	//the branch doesn't connect (orphans)
+	fn branch_length(&self, block: &Block) -> Option<BlockHeight> {
+		let mut length = 0;
+		let mut current = block;
+		loop {
+			let id = current.id();
+			if self.main_chain.get(&current.height()) == Some(&id) {
+				return Some(length);
+			}
+			current = self.blocks.get(&current.prev_hash())?;
+			length += 1;
+		}
+	}
+
 }
 
 
@@ -621,6 +1784,10 @@ pub struct BlockchainStats {
 	pub total_blocks: usize,
 	pub total_transactions: usize,
 	pub total_supply: Amount,
+	///base fees burned so far; not part of the total supply
+	pub burned_supply: Amount,
+	///base fee per gas of the next block
+	pub base_fee: GasPrice,
 	pub mempool_size: usize,
 	pub orphan_blocks: usize,
 	pub chain_head: Option<BlockId>,
@@ -645,19 +1812,77 @@ pub struct ForkInfo {
 	pub total_orphans: usize,
 }
 
+
+///where a block in the chain tree stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChainTreeStatus {
+	///on the main chain
+	Active,
+	///connected, its header and body checked against its parent, but on a
+	///losing branch; its transactions are validated only if the branch wins
+	Fork,
+	///parent unknown, waiting to be connected
+	Orphan,
+}
+
+
+///one block in the chain tree
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTreeNode {
+	pub block_id: BlockId,
+	pub prev_block_id: BlockId,
+	pub height: BlockHeight,
+	pub timestamp: Timestamp,
+	pub difficulty: Difficulty,
+	pub tx_count: usize,
+	pub status: ChainTreeStatus,
+	///cumulative work of the chain ending here (None for orphans)
+	pub chain_work: Option<u128>,
+	///no known block builds on this one
+	pub is_tip: bool,
+	///blocks back to the main chain (0 on the main chain, None for orphans)
+	pub branch_length: Option<BlockHeight>,
+}
+
+
+///known blocks near the tip, ordered by height with the main chain block first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainTree {
+	pub tip: Option<BlockId>,
+	pub height: BlockHeight,
+	pub start_height: BlockHeight,
+	pub nodes: Vec<ChainTreeNode>,
+}
+
+impl ChainTree {
+	///blocks nothing builds on: the active tip, fork tips and dangling orphans
+	pub fn tips(&self) -> impl Iterator<Item = &ChainTreeNode> {
+		self.nodes.iter().filter(|node| node.is_tip)
+	}
+}
+
 impl Default for Blockchain{
 	fn default() -> Self {
 		Self::new(ChainConfig::default()).expect("Failed to create default blockchain")
 	}
 }
 
+///proof-of-work chains check headers against their own rules
+impl HeaderVerifier for Blockchain {
+	fn verify_header(&self, header: &BlockHeader) -> Result<()> {
+		self.verify_pow_header(header)
+	}
+}
+
 
 
 ////////////////////////TESTS\\\\\\\\\\\\\\\\\\\\\\\\
 #[cfg(test)]
 mod tests {
     use super::*;
-    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType, HashAlgorithm};
+    use crate::precheck::precheck_block;
 
     #[test]
     fn test_blockchain_creation() {
@@ -736,6 +1961,22 @@ mod tests {
         assert_eq!(blockchain.get_balance(&genesis_recipient), expected_balance);
     }
 
+    #[test]
+    fn test_genesis_coinbase_has_receipt() {
+        let blockchain = Blockchain::default();
+        let genesis = blockchain.get_block_by_height(&0).unwrap();
+        let coinbase_id = genesis.transactions()[0].id();
+
+        let receipt = blockchain.get_receipt(&coinbase_id).unwrap().unwrap();
+        assert_eq!(receipt.block_id, genesis.id());
+        assert_eq!(receipt.index, 0);
+        assert_eq!(receipt.fee, 0);
+        assert!(receipt.succeeded());
+
+        let unknown = TxId::new(Hash256::zero());
+        assert!(blockchain.get_receipt(&unknown).unwrap().is_none());
+    }
+
     #[test]
     fn test_blockchain_stats() {
         let blockchain = Blockchain::default();
@@ -750,6 +1991,546 @@ mod tests {
         assert!(stats.chain_head.is_some());
     }
 
+    #[test]
+    fn test_blockchain_reload_from_store() {
+        use crate::store::MemoryChainStore;
+        use std::sync::Arc;
+
+        let store = Arc::new(MemoryChainStore::new());
+        let miner_address = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let (height, head, balance) = {
+            let mut blockchain = Blockchain::with_store(ChainConfig::default(), Box::new(store.clone())).unwrap();
+            blockchain.mine_block(miner_address).unwrap();
+            (blockchain.height(), blockchain.chain_head, blockchain.get_balance(&miner_address))
+        };
+
+        // Re-open on the same store: chain and replayed state must match
+        let reloaded = Blockchain::with_store(ChainConfig::default(), Box::new(store)).unwrap();
+
+        assert_eq!(reloaded.height(), height);
+        assert_eq!(reloaded.chain_head, head);
+        assert_eq!(reloaded.blocks.len(), 2);
+        assert_eq!(reloaded.get_balance(&miner_address), balance);
+    }
+
+    #[test]
+    fn test_pruned_chain_keeps_headers_and_reloads() {
+        use crate::store::MemoryChainStore;
+        use std::sync::Arc;
+
+        let config = ChainConfig { prune_depth: Some(MAX_REORG_DEPTH), ..ChainConfig::default() };
+        let store = Arc::new(MemoryChainStore::new());
+        let miner_address = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let mut blockchain = Blockchain::with_store(config.clone(), Box::new(store.clone())).unwrap();
+        for _ in 0..(MAX_REORG_DEPTH + PRUNE_BATCH) {
+            blockchain.mine_block(miner_address).unwrap();
+        }
+
+        assert_eq!(blockchain.pruned_height(), Some(PRUNE_BATCH - 1));
+        assert!(blockchain.is_pruned(0));
+        assert!(!blockchain.is_pruned(PRUNE_BATCH));
+        assert!(blockchain.get_block_by_height(&1).unwrap().transactions().is_empty());
+        assert!(!blockchain.get_block_by_height(&blockchain.height()).unwrap().transactions().is_empty());
+        assert_eq!(blockchain.get_headers(0, 10).len(), 10);
+        assert!(blockchain.validate_chain().is_err());
+
+        // pruned blocks can't be replayed, so the reload starts from the stored state
+        let reloaded = Blockchain::with_store(config, Box::new(store)).unwrap();
+        assert_eq!(reloaded.height(), blockchain.height());
+        assert_eq!(reloaded.pruned_height(), blockchain.pruned_height());
+        assert_eq!(reloaded.get_balance(&miner_address), blockchain.get_balance(&miner_address));
+    }
+
+    #[test]
+    fn test_prune_depth_below_reorg_depth_is_rejected() {
+        let config = ChainConfig { prune_depth: Some(MAX_REORG_DEPTH - 1), ..ChainConfig::default() };
+        assert!(Blockchain::new(config).is_err());
+    }
+
+    fn mine_side_block(blockchain: &Blockchain, prev: &Block, miner: Address) -> Block {
+        mine_side_block_with(blockchain, prev, miner, Vec::new())
+    }
+
+    fn mine_side_block_with(blockchain: &Blockchain, prev: &Block, miner: Address, transactions: Vec<Transaction>) -> Block {
+        let coinbase = Transaction::new_coinbase(miner, blockchain.config.mining.block_reward, prev.height() + 1);
+        let mut block = Block::new(
+            prev.id(),
+            std::iter::once(coinbase).chain(transactions).collect(),
+            prev.header.difficulty,
+            prev.height() + 1,
+            blockchain.config.chain_id,
+        ).unwrap();
+        block.header.timestamp = Timestamp::from_unix_timestamp(prev.header.timestamp.to_unix_timestamp() + 1);
+        assert!(block.mine(Some(blockchain.config.mining.max_mining_iterations)).unwrap());
+        block
+    }
+
+    #[test]
+    fn test_heavier_fork_triggers_reorg() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner_a = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let miner_b = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let block_a = blockchain.mine_block(miner_a).unwrap();
+        assert_eq!(blockchain.get_balance(&miner_a), blockchain.config.mining.block_reward);
+
+        // Equal-work side block does not replace the tip
+        let side_1 = mine_side_block(&blockchain, &genesis, miner_b);
+        blockchain.add_block(side_1.clone()).unwrap();
+        assert_eq!(blockchain.chain_head, Some(block_a.id()));
+
+        // Extending the side branch makes it heavier
+        let side_2 = mine_side_block(&blockchain, &side_1, miner_b);
+        blockchain.add_block(side_2.clone()).unwrap();
+
+        assert_eq!(blockchain.chain_head, Some(side_2.id()));
+        assert_eq!(blockchain.height(), 2);
+        assert_eq!(blockchain.main_chain.get(&1), Some(&side_1.id()));
+        assert_eq!(blockchain.get_balance(&miner_a), 0);
+        assert_eq!(blockchain.get_balance(&miner_b), blockchain.config.mining.block_reward * 2);
+    }
+
+    #[test]
+    fn test_side_blocks_are_checked_before_they_are_stored() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        blockchain.mine_block(miner).unwrap();
+
+        // a header claiming more work than was done, one at the wrong target
+        // and one not after its parent
+        let algorithm = blockchain.config.validation_rules.block_hash;
+        let mut unmined = mine_side_block(&blockchain, &genesis, miner);
+        while unmined.header.meets_difficulty_with(algorithm) {
+            unmined.header.nonce = unmined.header.nonce.wrapping_add(1);
+        }
+        let mut retargeted = mine_side_block(&blockchain, &genesis, miner);
+        retargeted.header.difficulty += 1;
+        assert!(retargeted.mine(Some(blockchain.config.mining.max_mining_iterations)).unwrap());
+        let mut not_after_parent = mine_side_block(&blockchain, &genesis, miner);
+        not_after_parent.header.timestamp = genesis.header.timestamp;
+        assert!(not_after_parent.mine(Some(blockchain.config.mining.max_mining_iterations)).unwrap());
+
+        for block in [unmined, retargeted, not_after_parent] {
+            assert!(blockchain.add_block(block.clone()).is_err());
+            assert!(blockchain.get_block(&block.id()).is_none());
+        }
+        assert_eq!(blockchain.get_chain_tree(10).nodes.len(), 2);
+    }
+
+    #[test]
+    fn test_invalid_branch_is_discarded_with_its_descendants() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let block_a = blockchain.mine_block(miner).unwrap();
+
+        // headers are fine, but the first block spends from an empty account
+        let pauper = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let overspend = Transaction::new_account(pauper, miner, 1_000, 0, 21000, 20, vec![]);
+        let side_1 = mine_side_block_with(&blockchain, &genesis, miner, vec![overspend]);
+        let side_2 = mine_side_block(&blockchain, &side_1, miner);
+        let side_3 = mine_side_block(&blockchain, &side_2, miner);
+
+        blockchain.add_block(side_3.clone()).unwrap();
+        blockchain.add_block(side_1.clone()).unwrap();
+        assert!(blockchain.get_block(&side_1.id()).is_some());
+        // the branch outweighs the tip, fails validation and goes entirely
+        assert!(blockchain.add_block(side_2.clone()).is_err());
+        for block in [&side_1, &side_2, &side_3] {
+            assert!(blockchain.get_block(&block.id()).is_none());
+            assert!(blockchain.chain_work(&block.id()).is_none());
+        }
+        assert!(blockchain.orphaned_blocks.is_empty());
+        assert_eq!(blockchain.chain_head, Some(block_a.id()));
+
+        // and stays refused, along with anything built on it
+        assert!(blockchain.add_block(side_1.clone()).is_err());
+        let side_4 = mine_side_block(&blockchain, &side_3, miner);
+        assert!(blockchain.add_block(side_4).is_err());
+        assert_eq!(blockchain.height(), 1);
+    }
+
+    #[test]
+    fn test_coinbase_follows_emission_schedule() {
+        let config = ChainConfig { emission: EmissionSchedule::halving_every(2), ..ChainConfig::default() };
+        let reward = config.mining.block_reward;
+        let mut blockchain = Blockchain::new(config).unwrap();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        assert_eq!(blockchain.block_subsidy(1), reward);
+        assert_eq!(blockchain.block_subsidy(2), reward / 2);
+
+        blockchain.mine_block(miner).unwrap();
+        let head = blockchain.mine_block(miner).unwrap();
+        assert_eq!(blockchain.get_balance(&miner), reward + reward / 2);
+
+        // a coinbase claiming the full reward after the first halving is rejected
+        let overpaid = mine_side_block(&blockchain, &head, miner);
+        assert!(blockchain.add_block(overpaid).is_err());
+        assert_eq!(blockchain.height(), 2);
+    }
+
+    #[test]
+    fn test_reorg_returns_evicted_transactions_to_mempool() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        // Pay from the genesis recipient so the transaction stays valid on either branch
+        let sender = blockchain.config.genesis.coinbase_recipient;
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let tx = Transaction::new_account(sender, recipient, 1000, 0, 21000, 20, vec![]);
+        let tx_id = blockchain.add_transaction(tx).unwrap();
+
+        blockchain.mine_block(miner).unwrap();
+        assert!(!blockchain.mempool.contains_transaction(&tx_id));
+
+        let side_1 = mine_side_block(&blockchain, &genesis, miner);
+        let side_2 = mine_side_block(&blockchain, &side_1, miner);
+        blockchain.add_block(side_1).unwrap();
+        blockchain.add_block(side_2.clone()).unwrap();
+
+        assert_eq!(blockchain.chain_head, Some(side_2.id()));
+        assert!(blockchain.mempool.contains_transaction(&tx_id));
+        assert_eq!(blockchain.get_balance(&recipient), 0);
+    }
+
+    #[test]
+    fn test_base_fee_is_burned_and_tip_paid_to_producer() {
+        let fee_market = FeeMarketConfig { initial_base_fee: 5, min_base_fee: 1, target_gas: 21000, max_change_denominator: 8 };
+        let config = ChainConfig { fee_market: Some(fee_market), ..ChainConfig::default() };
+        let mut blockchain = Blockchain::new(config).unwrap();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let sender = blockchain.config.genesis.coinbase_recipient;
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let base_fee = blockchain.get_stats().base_fee;
+        assert_eq!(base_fee, fee_market.next_base_fee(5, 0));
+        let supply = blockchain.world_state.total_supply();
+
+        let tx = Transaction::new_account(sender, recipient, 1000, 0, 21000, 20, vec![]);
+        blockchain.add_transaction(tx).unwrap();
+        blockchain.mine_block(miner).unwrap();
+
+        let stats = blockchain.get_stats();
+        assert_eq!(stats.burned_supply, 21000 * base_fee);
+        assert_eq!(blockchain.get_balance(&miner), blockchain.block_subsidy(1) + 21000 * (20 - base_fee));
+        assert_eq!(stats.base_fee, fee_market.next_base_fee(base_fee, 21000));
+        assert_eq!(stats.total_supply, supply + blockchain.block_subsidy(1) - stats.burned_supply);
+    }
+
+    #[test]
+    fn test_subscribers_see_reorg_events() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let mut events = blockchain.subscribe();
+
+        let sender = blockchain.config.genesis.coinbase_recipient;
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let tx_id = blockchain.add_transaction(Transaction::new_account(sender, recipient, 1000, 0, 21000, 20, vec![])).unwrap();
+        let block_a = blockchain.mine_block(miner).unwrap();
+
+        let side_1 = mine_side_block(&blockchain, &genesis, miner);
+        let side_2 = mine_side_block(&blockchain, &side_1, miner);
+        blockchain.add_block(side_1.clone()).unwrap();
+        blockchain.add_block(side_2.clone()).unwrap();
+
+        let mut seen = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            seen.push(match event {
+                ChainEvent::BlockConnected(block) => format!("+{}", block.id()),
+                ChainEvent::BlockDisconnected(block) => format!("-{}", block.id()),
+                ChainEvent::TxAdded(tx) => format!("tx {}", tx.id()),
+                ChainEvent::TxDropped { tx_id, .. } => format!("drop {}", tx_id),
+            });
+        }
+
+        assert_eq!(seen, vec![
+            format!("tx {}", tx_id),
+            format!("+{}", block_a.id()),
+            format!("-{}", block_a.id()),
+            format!("+{}", side_1.id()),
+            format!("+{}", side_2.id()),
+            // back in the mempool after the reorg
+            format!("tx {}", tx_id),
+        ]);
+    }
+
+    #[test]
+    fn test_checkpoints_reject_contradicting_forks() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        // a block at a checkpoint height must be the checkpointed one
+        blockchain.config.checkpoints.insert(1, BlockId::new(Hash256::zero()));
+        let block_1 = mine_side_block(&blockchain, &genesis, miner);
+        assert!(blockchain.add_block(block_1.clone()).is_err());
+
+        blockchain.config.checkpoints.insert(1, block_1.id());
+        blockchain.add_block(block_1).unwrap();
+
+        // once past the checkpoint, branches forking below it are refused outright
+        let side_1 = mine_side_block(&blockchain, &genesis, miner);
+        assert!(blockchain.add_block(side_1).is_err());
+        assert_eq!(blockchain.height(), 1);
+    }
+
+    #[test]
+    fn test_finalized_blocks_cannot_be_reorganized() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let block_1 = mine_side_block(&blockchain, &genesis, miner);
+        blockchain.add_block(block_1).unwrap();
+        assert!(blockchain.finalize(2).is_err(), "above the tip");
+        blockchain.finalize(1).unwrap();
+        blockchain.finalize(0).unwrap();
+        assert_eq!(blockchain.finalized_height(), Some(1));
+
+        let side_1 = mine_side_block(&blockchain, &genesis, miner);
+        assert!(blockchain.add_block(side_1).is_err());
+        assert_eq!(blockchain.height(), 1);
+    }
+
+    #[test]
+    fn test_precheck_rejects_blocks_without_valid_proof() {
+        let blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let block = mine_side_block(&blockchain, &genesis, miner);
+        assert!(precheck_block(&blockchain, &block).is_ok());
+
+        let mut other_chain = block.clone();
+        other_chain.header.chain_id += 1;
+        assert!(precheck_block(&blockchain, &other_chain).is_err());
+
+        // a valid header with a body it doesn't commit to
+        let mut swapped_body = block;
+        swapped_body.body.transactions[0].outputs[0].amount += 1;
+        assert!(blockchain.verify_header(&swapped_body.header).is_ok());
+        assert!(precheck_block(&blockchain, &swapped_body).is_err());
+    }
+
+    #[test]
+    fn test_assume_valid_covers_only_ancestors_of_its_block() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let other_miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let block_1 = mine_side_block(&blockchain, &genesis, miner.clone());
+        let block_2 = mine_side_block(&blockchain, &block_1, miner);
+        let sibling = mine_side_block(&blockchain, &genesis, other_miner);
+        blockchain.config.assume_valid = Some(block_2.id());
+
+        // headers short of the assume-valid block prove nothing yet
+        blockchain.note_headers(&[block_1.header.clone(), sibling.header.clone()]);
+        assert!(!blockchain.validator.is_assumed_valid(&block_1.id()));
+
+        blockchain.note_headers(&[block_2.header.clone()]);
+        assert!(blockchain.validator.is_assumed_valid(&block_1.id()));
+        assert!(blockchain.validator.is_assumed_valid(&block_2.id()));
+        // a block at the same height on another branch is checked in full
+        assert!(!blockchain.validator.is_assumed_valid(&sibling.id()));
+
+        blockchain.add_block(block_1).unwrap();
+        blockchain.add_block(block_2).unwrap();
+        assert_eq!(blockchain.height(), 2);
+    }
+
+    #[test]
+    fn test_orphan_connects_when_parent_arrives() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let block_1 = mine_side_block(&blockchain, &genesis, miner);
+        let block_2 = mine_side_block(&blockchain, &block_1, miner);
+
+        blockchain.add_block(block_2.clone()).unwrap();
+        assert_eq!(blockchain.orphaned_blocks.len(), 1);
+        assert_eq!(blockchain.height(), 0);
+
+        blockchain.add_block(block_1).unwrap();
+        assert!(blockchain.orphaned_blocks.is_empty());
+        assert_eq!(blockchain.chain_head, Some(block_2.id()));
+        assert_eq!(blockchain.height(), 2);
+    }
+
+    #[test]
+    fn test_chain_tree_lists_forks_and_orphans() {
+        let mut blockchain = Blockchain::default();
+        let genesis = blockchain.get_chain_head().unwrap().clone();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let block_a = blockchain.mine_block(miner).unwrap();
+        let side_1 = mine_side_block(&blockchain, &genesis, miner);
+        let side_2 = mine_side_block(&blockchain, &side_1, miner);
+        let side_3 = mine_side_block(&blockchain, &side_2, miner);
+        blockchain.add_block(side_1.clone()).unwrap();
+        blockchain.add_block(side_3.clone()).unwrap();
+
+        let tree = blockchain.get_chain_tree(10);
+        assert_eq!(tree.tip, Some(block_a.id()));
+        assert_eq!(tree.nodes.len(), 4);
+        assert_eq!(tree.nodes[0].block_id, genesis.id());
+
+        let node = |id: BlockId| tree.nodes.iter().find(|node| node.block_id == id).unwrap();
+        assert_eq!(node(block_a.id()).status, ChainTreeStatus::Active);
+        assert_eq!(node(side_1.id()).status, ChainTreeStatus::Fork);
+        assert_eq!(node(side_1.id()).branch_length, Some(1));
+        assert!(node(side_1.id()).chain_work.is_some());
+        assert_eq!(node(side_3.id()).status, ChainTreeStatus::Orphan);
+        assert_eq!(node(side_3.id()).branch_length, None);
+        assert_eq!(tree.tips().count(), 3);
+
+        // Only the last height is kept at depth 1
+        let tree = blockchain.get_chain_tree(1);
+        assert!(tree.nodes.iter().all(|node| node.height >= 1));
+    }
+
+    #[test]
+    fn test_get_logs_filters_by_recipient() {
+        let mut blockchain = Blockchain::default();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let sender = blockchain.config.genesis.coinbase_recipient;
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let tx = Transaction::new_account(sender, recipient, 1000, 0, 21000, 20, vec![]);
+        let tx_id = blockchain.add_transaction(tx).unwrap();
+        let block = blockchain.mine_block(miner).unwrap();
+        blockchain.mine_block(miner).unwrap();
+
+        let filter = LogFilter {
+            from_height: 0,
+            to_height: blockchain.height(),
+            topics: vec![vec![], vec![], vec![crate::logs::address_topic(&recipient)]],
+            ..Default::default()
+        };
+        let logs = blockchain.get_logs(&filter).unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].tx_id, tx_id);
+        assert_eq!(logs[0].block_id, block.id());
+
+        let other = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let filter = LogFilter { addresses: vec![other], ..filter };
+        assert!(blockchain.get_logs(&filter).unwrap().is_empty());
+
+        let too_wide = LogFilter { from_height: 0, to_height: MAX_LOG_QUERY_RANGE, ..Default::default() };
+        assert!(blockchain.get_logs(&too_wide).is_err());
+    }
+
+    #[test]
+    fn test_dev_accounts_funded_on_devnet_only() {
+        let devnet = Blockchain::default();
+        let accounts = devnet.dev_accounts().unwrap();
+        assert!(!accounts.is_empty());
+        for account in &accounts {
+            assert_eq!(devnet.get_balance(&account.address), account.balance);
+        }
+
+        let mut config = ChainConfig::default();
+        config.network = NetworkType::Mainnet;
+        let mainnet = Blockchain::new(config).unwrap();
+        assert!(mainnet.dev_accounts().unwrap().is_empty());
+        assert_eq!(mainnet.get_balance(&accounts[0].address), 0);
+    }
+
+    #[test]
+    fn test_get_headers_for_sync() {
+        let mut blockchain = Blockchain::default();
+        let genesis_id = blockchain.get_chain_head().unwrap().id();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        for _ in 0..3 {
+            blockchain.mine_block(miner).unwrap();
+        }
+
+        // a syncing peer at genesis asks for everything after it
+        let headers = blockchain.get_headers(1, crate::sync::MAX_HEADERS_PER_REQUEST);
+        assert_eq!(headers.len(), 3);
+        crate::sync::validate_header_chain(genesis_id, 0, &headers, blockchain.validation_rules().block_hash).unwrap();
+        assert_eq!(headers[2].id(), blockchain.get_chain_head().unwrap().id());
+
+        assert_eq!(blockchain.get_headers(2, 1).len(), 1);
+        assert!(blockchain.get_headers(4, 10).is_empty());
+    }
+
+    #[test]
+    fn test_headers_after_block_locator() {
+        let mut blockchain = Blockchain::default();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        for _ in 0..30 {
+            blockchain.mine_block(miner).unwrap();
+        }
+
+        // dense near the tip, sparse further back, always ending at genesis
+        let locator = blockchain.block_locator();
+        assert_eq!(locator[0], blockchain.get_chain_head().unwrap().id());
+        assert_eq!(locator[9], blockchain.get_block_by_height(&21).unwrap().id());
+        assert_eq!(locator[10], blockchain.get_block_by_height(&19).unwrap().id());
+        assert_eq!(*locator.last().unwrap(), blockchain.get_block_by_height(&0).unwrap().id());
+        assert!(locator.len() < 20);
+
+        // a peer at height 12 gets everything after it
+        let peer_locator = vec![blockchain.get_block_by_height(&12).unwrap().id()];
+        let headers = blockchain.get_headers_after(&peer_locator, 100);
+        assert_eq!(headers.first().unwrap().height, 13);
+        assert_eq!(headers.len(), 18);
+
+        // a peer on a fork we don't know starts again after genesis,
+        // and unknown ids ahead of a shared block are passed over
+        let unknown = BlockId::new(Hash256::from_bytes([7; 32]));
+        assert_eq!(blockchain.get_headers_after(&[unknown], 100).first().unwrap().height, 1);
+        let headers = blockchain.get_headers_after(&[unknown, peer_locator[0]], 100);
+        assert_eq!(headers.first().unwrap().height, 13);
+    }
+
+    #[test]
+    fn test_proof_of_work_uses_the_configured_hash() {
+        let mut config = ChainConfig::default();
+        config.validation_rules.block_hash = HashAlgorithm::Keccak256;
+        let mut blockchain = Blockchain::new(config).unwrap();
+        let genesis_id = blockchain.get_chain_head().unwrap().id();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        for _ in 0..2 {
+            blockchain.mine_block(miner).unwrap();
+        }
+
+        let headers = blockchain.get_headers(1, 2);
+        assert!(headers.iter().all(|header| header.meets_difficulty_with(HashAlgorithm::Keccak256)));
+        crate::sync::validate_header_chain(genesis_id, 0, &headers, HashAlgorithm::Keccak256).unwrap();
+        // ids don't depend on the proof-of-work hash
+        assert_eq!(headers[1].id(), BlockId::new(headers[1].hash()));
+    }
+
+    #[test]
+    fn test_difficulty_retargets_after_fast_blocks() {
+        let mut config = ChainConfig::default();
+        config.validation_rules.difficulty_adjustment_period = 2;
+        let mut blockchain = Blockchain::new(config).unwrap();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let genesis_difficulty = blockchain.config.genesis.difficulty;
+
+        let block_1 = blockchain.mine_block(miner).unwrap();
+        assert_eq!(block_1.header.difficulty, genesis_difficulty);
+
+        // Blocks arrive far faster than the target time: clamped 4x increase
+        let block_2 = blockchain.mine_block(miner).unwrap();
+        assert_eq!(block_2.header.difficulty, genesis_difficulty + 2);
+
+        let block_3 = blockchain.mine_block(miner).unwrap();
+        assert_eq!(block_3.header.difficulty, block_2.header.difficulty);
+    }
+
     #[test]
     fn test_chain_validation() {
         let blockchain = Blockchain::default();
@@ -0,0 +1,862 @@
+//! Canonical binary encoding of consensus objects.
+//!
+//! Hashes and network messages need bytes that don't change with a
+//! dependency's version, so consensus objects are encoded here by hand
+//! rather than with bincode. Format version 1:
+//!
+//! - integers are fixed width, little-endian; a `bool` is one byte, 0 or 1
+//! - byte strings, strings and sequences have a u32 length prefix
+//! - an `Option` is a 0/1 tag byte, then the value if present
+//! - an enum is a u8 variant tag, then the variant's fields in order
+//! - hashes, keys and signatures are their raw bytes, addresses their text
+//! - a timestamp is i64 seconds and u32 nanoseconds since the Unix epoch
+//!
+//! [`encode`] starts with the version byte. [`decode`] checks it and accepts
+//! only the exact encoding of a value (no trailing bytes, unknown tags or
+//! out-of-range fields), so every value has exactly one encoding. Light
+//! clients write headers the same way (`blockchain_primitives::BlockHeader`).
+
+use crate::block::{Block, BlockBody, BlockHeader};
+use crate::conflict::ConflictProof;
+use crate::logs::LogBloom;
+use crate::transaction::{MultisigSignature, ScriptHashSpend, Transaction, TransactionInput, TransactionOutput, UTXO};
+use crate::types::*;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{Address, Hash256, PublicKey, Signature};
+use blockchain_crypto::bip340::{Bip340Signature, XOnlyPublicKey};
+use chrono::DateTime;
+
+
+/// Version of the encoding, the first byte of everything [`encode`] writes
+pub const CODEC_VERSION: u8 = blockchain_primitives::CODEC_VERSION;
+
+//deepest nesting of scripts inside scripts
+const MAX_DEPTH: usize = 16;
+
+
+/// A value with a canonical encoding
+pub trait Encode {
+    /// Append the encoding, without the version byte
+    fn encode_to(&self, out: &mut Vec<u8>);
+}
+
+/// A value that can be read back from its canonical encoding
+pub trait Decode: Sized {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self>;
+}
+
+
+/// Versioned encoding of `value`
+pub fn encode<T: Encode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = vec![CODEC_VERSION];
+    value.encode_to(&mut out);
+    out
+}
+
+/// Read a value from its versioned encoding, which must be used up exactly
+pub fn decode<T: Decode>(bytes: &[u8]) -> Result<T> {
+    let mut reader = Reader::new(bytes);
+    let version = u8::decode_from(&mut reader)?;
+    if version != CODEC_VERSION {
+        return Err(error(format!("unsupported codec version {}", version)));
+    }
+    let value = T::decode_from(&mut reader)?;
+    if !reader.is_empty() {
+        return Err(error(format!("{} trailing bytes", reader.bytes.len())));
+    }
+    Ok(value)
+}
+
+fn error(reason: impl std::fmt::Display) -> BlockchainError {
+    BlockchainError::SerializationError(format!("Codec: {}", reason))
+}
+
+
+/// Cursor over an encoding being decoded
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, depth: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(error(format!("needed {} bytes, {} left", len, self.bytes.len())));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    /// A length prefix. Every element takes at least a byte, so a length
+    /// beyond what's left is rejected before anything is allocated for it
+    pub fn length(&mut self) -> Result<usize> {
+        let len = u32::decode_from(self)? as usize;
+        if len > self.bytes.len() {
+            return Err(error(format!("length {} exceeds the {} bytes left", len, self.bytes.len())));
+        }
+        Ok(len)
+    }
+
+    fn tag(&mut self, what: &str, variants: u8) -> Result<u8> {
+        let tag = u8::decode_from(self)?;
+        if tag >= variants {
+            return Err(error(format!("unknown {} tag {}", what, tag)));
+        }
+        Ok(tag)
+    }
+
+    fn nested<T>(&mut self, decode: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(error("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = decode(self);
+        self.depth -= 1;
+        value
+    }
+}
+
+
+macro_rules! int_codec {
+    ($($int:ty),*) => {$(
+        impl Encode for $int {
+            fn encode_to(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+
+        impl Decode for $int {
+            fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+                Ok(<$int>::from_le_bytes(reader.array()?))
+            }
+        }
+    )*};
+}
+
+int_codec!(u8, u16, u32, u64, i64);
+
+impl Encode for bool {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(reader.tag("bool", 2)? == 1)
+    }
+}
+
+fn encode_length(len: usize, out: &mut Vec<u8>) {
+    u32::try_from(len).expect("length fits in u32").encode_to(out);
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_length(self.len(), out);
+        for item in self {
+            item.encode_to(out);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_slice().encode_to(out);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        let len = reader.length()?;
+        (0..len).map(|_| T::decode_from(reader)).collect()
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        encode_length(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.as_str().encode_to(out);
+    }
+}
+
+impl Decode for String {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        let len = reader.length()?;
+        String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| error("string is not utf-8"))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode_to(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        match reader.tag("option", 2)? {
+            0 => Ok(None),
+            _ => Ok(Some(T::decode_from(reader)?)),
+        }
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.0.encode_to(out);
+        self.1.encode_to(out);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok((A::decode_from(reader)?, B::decode_from(reader)?))
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        (**self).encode_to(out);
+    }
+}
+
+
+impl Encode for Hash256 {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for Hash256 {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(Hash256::from_bytes(reader.array()?))
+    }
+}
+
+impl Encode for TxId {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.hash().encode_to(out);
+    }
+}
+
+impl Decode for TxId {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TxId::new(Hash256::decode_from(reader)?))
+    }
+}
+
+impl Encode for WtxId {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.hash().encode_to(out);
+    }
+}
+
+impl Decode for WtxId {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(WtxId::new(Hash256::decode_from(reader)?))
+    }
+}
+
+impl Encode for BlockId {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.hash().encode_to(out);
+    }
+}
+
+impl Decode for BlockId {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(BlockId::new(Hash256::decode_from(reader)?))
+    }
+}
+
+impl Encode for Timestamp {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        let inner = self.inner();
+        inner.timestamp().encode_to(out);
+        inner.timestamp_subsec_nanos().encode_to(out);
+    }
+}
+
+impl Decode for Timestamp {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        let secs = i64::decode_from(reader)?;
+        let nanos = u32::decode_from(reader)?;
+        if nanos >= 1_000_000_000 {
+            return Err(error("timestamp nanoseconds out of range"));
+        }
+        DateTime::from_timestamp(secs, nanos)
+            .map(Timestamp::from)
+            .ok_or_else(|| error("timestamp out of range"))
+    }
+}
+
+impl Encode for Address {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.encoded().encode_to(out);
+    }
+}
+
+impl Decode for Address {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        let text = String::decode_from(reader)?;
+        let address = Address::from_string(&text).map_err(error)?;
+        //e.g. an upper-case bech32 address decodes, but isn't the canonical text
+        if address.encoded() != text {
+            return Err(error(format!("address {} is not in canonical form", text)));
+        }
+        Ok(address)
+    }
+}
+
+impl Encode for PublicKey {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bytes());
+    }
+}
+
+impl Decode for PublicKey {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        PublicKey::from_bytes(reader.take(32)?).map_err(error)
+    }
+}
+
+impl Encode for Signature {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for Signature {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(Signature::from_bytes(reader.array()?))
+    }
+}
+
+impl Encode for XOnlyPublicKey {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for XOnlyPublicKey {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        XOnlyPublicKey::from_bytes(reader.array()?).map_err(error)
+    }
+}
+
+impl Encode for Bip340Signature {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_bytes());
+    }
+}
+
+impl Decode for Bip340Signature {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(Bip340Signature::from_bytes(reader.array()?))
+    }
+}
+
+impl Encode for OutPoint {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.tx_id.encode_to(out);
+        self.output_index.encode_to(out);
+    }
+}
+
+impl Decode for OutPoint {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(OutPoint::new(TxId::decode_from(reader)?, u32::decode_from(reader)?))
+    }
+}
+
+
+impl Encode for Script {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Script::PayToPubkeyHash(hash) => {
+                out.push(0);
+                hash.encode_to(out);
+            }
+            Script::PayToScriptHash(hash) => {
+                out.push(1);
+                hash.encode_to(out);
+            }
+            Script::PayToPubkey(public_key) => {
+                out.push(2);
+                public_key.encode_to(out);
+            }
+            Script::MultiSig { threshold, public_keys } => {
+                out.push(3);
+                threshold.encode_to(out);
+                public_keys.encode_to(out);
+            }
+            Script::Custom(code) => {
+                out.push(4);
+                code.encode_to(out);
+            }
+            Script::CheckLockTime { lock_time, script } => {
+                out.push(5);
+                lock_time.encode_to(out);
+                script.encode_to(out);
+            }
+            Script::CheckSequence { blocks, script } => {
+                out.push(6);
+                blocks.encode_to(out);
+                script.encode_to(out);
+            }
+            Script::PayToSchnorrKey(public_key) => {
+                out.push(7);
+                public_key.encode_to(out);
+            }
+        }
+    }
+}
+
+impl Decode for Script {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(match reader.tag("script", 8)? {
+            0 => Script::PayToPubkeyHash(Hash256::decode_from(reader)?),
+            1 => Script::PayToScriptHash(Hash256::decode_from(reader)?),
+            2 => Script::PayToPubkey(PublicKey::decode_from(reader)?),
+            3 => Script::MultiSig {
+                threshold: u8::decode_from(reader)?,
+                public_keys: Vec::decode_from(reader)?,
+            },
+            4 => Script::Custom(Vec::decode_from(reader)?),
+            5 => Script::CheckLockTime {
+                lock_time: u32::decode_from(reader)?,
+                script: Box::new(reader.nested(Script::decode_from)?),
+            },
+            6 => Script::CheckSequence {
+                blocks: u16::decode_from(reader)?,
+                script: Box::new(reader.nested(Script::decode_from)?),
+            },
+            _ => Script::PayToSchnorrKey(XOnlyPublicKey::decode_from(reader)?),
+        })
+    }
+}
+
+impl Encode for TransactionType {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(match self {
+            TransactionType::Transfer => 0,
+            TransactionType::Coinbase => 1,
+            TransactionType::ContractDeployment => 2,
+            TransactionType::ContractCall => 3,
+            TransactionType::Multisig => 4,
+        });
+    }
+}
+
+impl Decode for TransactionType {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(match reader.tag("transaction type", 5)? {
+            0 => TransactionType::Transfer,
+            1 => TransactionType::Coinbase,
+            2 => TransactionType::ContractDeployment,
+            3 => TransactionType::ContractCall,
+            _ => TransactionType::Multisig,
+        })
+    }
+}
+
+
+impl Encode for TransactionInput {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.prev_output.encode_to(out);
+        self.signature.encode_to(out);
+        self.public_key.encode_to(out);
+        self.script_sig.encode_to(out);
+        self.sequence.encode_to(out);
+    }
+}
+
+impl Decode for TransactionInput {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TransactionInput {
+            prev_output: OutPoint::decode_from(reader)?,
+            signature: Signature::decode_from(reader)?,
+            public_key: PublicKey::decode_from(reader)?,
+            script_sig: Vec::decode_from(reader)?,
+            sequence: u32::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for TransactionOutput {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.amount.encode_to(out);
+        self.script_pubkey.encode_to(out);
+        self.address.encode_to(out);
+    }
+}
+
+impl Decode for TransactionOutput {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(TransactionOutput {
+            amount: Amount::decode_from(reader)?,
+            script_pubkey: Script::decode_from(reader)?,
+            address: Address::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for Transaction {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.version.encode_to(out);
+        self.inputs.encode_to(out);
+        self.outputs.encode_to(out);
+        self.lock_time.encode_to(out);
+        self.fee.encode_to(out);
+        self.tx_type.encode_to(out);
+        self.timestamp.encode_to(out);
+        self.nonce.encode_to(out);
+        self.from.encode_to(out);
+        self.to.encode_to(out);
+        self.amount.encode_to(out);
+        self.gas_limit.encode_to(out);
+        self.gas_price.encode_to(out);
+        self.data.encode_to(out);
+    }
+}
+
+impl Decode for Transaction {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(Transaction {
+            version: u32::decode_from(reader)?,
+            inputs: Vec::decode_from(reader)?,
+            outputs: Vec::decode_from(reader)?,
+            lock_time: u32::decode_from(reader)?,
+            fee: Amount::decode_from(reader)?,
+            tx_type: TransactionType::decode_from(reader)?,
+            timestamp: Timestamp::decode_from(reader)?,
+            nonce: Option::decode_from(reader)?,
+            from: Option::decode_from(reader)?,
+            to: Option::decode_from(reader)?,
+            amount: Option::decode_from(reader)?,
+            gas_limit: Option::decode_from(reader)?,
+            gas_price: Option::decode_from(reader)?,
+            data: Vec::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for MultisigSignature {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.key_index.encode_to(out);
+        self.signature.encode_to(out);
+    }
+}
+
+impl Decode for MultisigSignature {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(MultisigSignature {
+            key_index: u8::decode_from(reader)?,
+            signature: Signature::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for ScriptHashSpend {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.redeem_script.encode_to(out);
+        self.script_sig.encode_to(out);
+    }
+}
+
+impl Decode for ScriptHashSpend {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(ScriptHashSpend {
+            redeem_script: Vec::decode_from(reader)?,
+            script_sig: Vec::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for UTXO {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.output.encode_to(out);
+        self.block_height.encode_to(out);
+        self.is_coinbase.encode_to(out);
+        self.tx_id.encode_to(out);
+        self.output_index.encode_to(out);
+    }
+}
+
+impl Decode for UTXO {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        let output = TransactionOutput::decode_from(reader)?;
+        let block_height = BlockHeight::decode_from(reader)?;
+        let is_coinbase = bool::decode_from(reader)?;
+        let tx_id = TxId::decode_from(reader)?;
+        let output_index = u32::decode_from(reader)?;
+        Ok(UTXO::new(output, block_height, tx_id, output_index, is_coinbase))
+    }
+}
+
+
+impl Encode for LogBloom {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for LogBloom {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        let bytes = reader.take(crate::logs::BLOOM_BYTES)?;
+        Ok(LogBloom::from_slice(bytes).expect("took BLOOM_BYTES bytes"))
+    }
+}
+
+impl Encode for BlockHeader {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.version.encode_to(out);
+        self.prev_block_hash.encode_to(out);
+        self.merkle_root.encode_to(out);
+        self.timestamp.encode_to(out);
+        self.difficulty.encode_to(out);
+        self.nonce.encode_to(out);
+        self.height.encode_to(out);
+        self.tx_count.encode_to(out);
+        self.size.encode_to(out);
+        self.chain_id.encode_to(out);
+        self.logs_bloom.encode_to(out);
+        self.consensus_data.encode_to(out);
+    }
+}
+
+impl Decode for BlockHeader {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(BlockHeader {
+            version: u32::decode_from(reader)?,
+            prev_block_hash: BlockId::decode_from(reader)?,
+            merkle_root: Hash256::decode_from(reader)?,
+            timestamp: Timestamp::decode_from(reader)?,
+            difficulty: Difficulty::decode_from(reader)?,
+            nonce: u64::decode_from(reader)?,
+            height: BlockHeight::decode_from(reader)?,
+            tx_count: u32::decode_from(reader)?,
+            size: u32::decode_from(reader)?,
+            chain_id: ChainId::decode_from(reader)?,
+            logs_bloom: LogBloom::decode_from(reader)?,
+            consensus_data: Vec::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for Block {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.header.encode_to(out);
+        self.body.transactions.encode_to(out);
+    }
+}
+
+impl Decode for Block {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(Block {
+            header: BlockHeader::decode_from(reader)?,
+            body: BlockBody { transactions: Vec::decode_from(reader)? },
+        })
+    }
+}
+
+impl Encode for ConflictProof {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.outpoint.encode_to(out);
+        self.first.encode_to(out);
+        self.second.encode_to(out);
+    }
+}
+
+impl Decode for ConflictProof {
+    fn decode_from(reader: &mut Reader<'_>) -> Result<Self> {
+        Ok(ConflictProof {
+            outpoint: OutPoint::decode_from(reader)?,
+            first: Transaction::decode_from(reader)?,
+            second: Transaction::decode_from(reader)?,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{hash::sha256, signature::generate_keypair, address::public_key_to_address, AddressType};
+    use blockchain_crypto::bip340::Bip340Keypair;
+    use proptest::prelude::*;
+
+    fn fixed_time() -> Timestamp {
+        Timestamp::from(DateTime::from_timestamp(1_714_564_800, 250_000_000).unwrap())
+    }
+
+    // an account-style transaction without addresses, so its bytes are fixed
+    fn golden_transaction() -> Transaction {
+        let mut tx = Transaction::new_utxo(vec![], vec![], 7);
+        tx.timestamp = fixed_time();
+        tx.nonce = Some(3);
+        tx.amount = Some(50);
+        tx.data = vec![0xaa, 0xbb];
+        tx
+    }
+
+    #[test]
+    fn test_golden_primitives() {
+        assert_eq!(encode(&OutPoint::new(TxId::new(Hash256::zero()), 1)), [&[1u8][..], &[0u8; 32], &[1, 0, 0, 0]].concat());
+        assert_eq!(encode(&Some(5u16)), vec![1, 1, 5, 0]);
+        assert_eq!(encode(&vec![true, false]), vec![1, 2, 0, 0, 0, 1, 0]);
+        assert_eq!(encode("hi"), vec![1, 2, 0, 0, 0, b'h', b'i']);
+        assert_eq!(hex::encode(encode(&fixed_time())), "01c02e32660000000080b2e60e");
+        assert_eq!(encode(&Script::check_sequence(6, Script::PayToScriptHash(Hash256::zero()))),
+            [&[1u8, 6, 6, 0, 1][..], &[0u8; 32]].concat());
+    }
+
+    #[test]
+    fn test_golden_transaction() {
+        let tx = golden_transaction();
+        assert_eq!(
+            hex::encode(encode(&tx)),
+            concat!(
+                "01",                       // codec version
+                "01000000",                 // version
+                "00000000", "00000000",     // no inputs, no outputs
+                "00000000",                 // lock time
+                "0700000000000000",         // fee
+                "00",                       // transfer
+                "c02e326600000000", "80b2e60e", // timestamp
+                "010300000000000000",       // nonce
+                "00", "00",                 // no from, no to
+                "013200000000000000",       // amount
+                "00", "00",                 // no gas limit or price
+                "02000000aabb",             // data
+            )
+        );
+        assert_eq!(tx.hash(), sha256(&encode(&tx)));
+        assert_eq!(decode::<Transaction>(&encode(&tx)).unwrap(), tx);
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 2);
+
+        let mut input = TransactionInput::new(outpoint, Signature::from_bytes([7u8; 64]), *keypair.public_key());
+        input.script_sig = vec![1, 2, 3];
+        let outputs = vec![
+            TransactionOutput::new(900, address.clone()),
+            TransactionOutput::multisig(100, 1, vec![*keypair.public_key()]).unwrap(),
+            TransactionOutput::aggregate_key(50, &[*Bip340Keypair::generate().public_key()]).unwrap(),
+        ];
+        let tx = Transaction::new_utxo(vec![input], outputs, 100);
+        assert_eq!(decode::<Transaction>(&encode(&tx)).unwrap(), tx);
+
+        let block = Block::new(BlockId::new(sha256(b"parent")), vec![tx], 1, 1, 1).unwrap();
+        assert_eq!(decode::<Block>(&encode(&block)).unwrap(), block);
+
+        let utxo = UTXO::new(TransactionOutput::new(5, address), 3, outpoint.tx_id, 2, true);
+        assert_eq!(decode::<UTXO>(&encode(&utxo)).unwrap(), utxo);
+    }
+
+    #[test]
+    fn test_rejects_non_canonical_input() {
+        let tx = golden_transaction();
+        let bytes = encode(&tx);
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(decode::<Transaction>(&trailing).is_err());
+
+        let mut other_version = bytes.clone();
+        other_version[0] = 2;
+        assert!(decode::<Transaction>(&other_version).is_err());
+
+        assert!(decode::<Transaction>(&bytes[..bytes.len() - 1]).is_err());
+        assert!(decode::<bool>(&[1, 2]).is_err());
+        assert!(decode::<Option<u8>>(&[1, 2, 0]).is_err());
+        // a sequence longer than the input is refused up front
+        assert!(decode::<Vec<u64>>(&[1, 0xff, 0xff, 0xff, 0xff]).is_err());
+
+        // scripts can't nest without bound
+        let mut script = Script::PayToPubkeyHash(Hash256::zero());
+        for _ in 0..MAX_DEPTH + 1 {
+            script = Script::check_sequence(1, script);
+        }
+        assert!(decode::<Script>(&encode(&script)).is_err());
+    }
+
+    fn arbitrary_transaction() -> impl Strategy<Value = Transaction> {
+        let amounts = (any::<u64>(), proptest::option::of(any::<u64>()), proptest::option::of(any::<u64>()));
+        let gas = (proptest::option::of(any::<u64>()), proptest::option::of(any::<u64>()));
+        let body = (any::<u32>(), any::<u32>(), 0..1_000_000_000u32, proptest::collection::vec(any::<u8>(), 0..64));
+        (amounts, gas, body).prop_map(|((fee, nonce, amount), (gas_limit, gas_price), (version, lock_time, nanos, data))| {
+            let mut tx = Transaction::new_utxo(vec![], vec![], fee);
+            tx.version = version;
+            tx.lock_time = lock_time;
+            tx.timestamp = Timestamp::from(DateTime::from_timestamp(1_714_564_800, nanos).unwrap());
+            tx.nonce = nonce;
+            tx.amount = amount;
+            tx.gas_limit = gas_limit;
+            tx.gas_price = gas_price;
+            tx.data = data;
+            tx
+        })
+    }
+
+    proptest! {
+        // bytes off the wire decode to a value or an error, never a panic
+        #[test]
+        fn prop_arbitrary_bytes_never_panic(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+            let _ = decode::<Block>(&bytes);
+            let _ = decode::<BlockHeader>(&bytes);
+            let _ = decode::<Transaction>(&bytes);
+            let _ = decode::<UTXO>(&bytes);
+            let _ = decode::<ConflictProof>(&bytes);
+            let _ = decode::<Script>(&bytes);
+        }
+
+        #[test]
+        fn prop_transactions_round_trip(tx in arbitrary_transaction()) {
+            prop_assert_eq!(decode::<Transaction>(&encode(&tx)).unwrap(), tx);
+        }
+
+        // a corrupted encoding is either refused or the exact encoding of
+        // what it decodes to, so no two encodings share a value
+        #[test]
+        fn prop_corrupted_encodings_stay_canonical(tx in arbitrary_transaction(), index: usize, byte: u8, cut: usize) {
+            let mut bytes = encode(&tx);
+            let index = index % bytes.len();
+            bytes[index] = byte;
+            bytes.truncate(bytes.len() - cut % 4);
+            if let Ok(decoded) = decode::<Transaction>(&bytes) {
+                prop_assert_eq!(encode(&decoded), bytes);
+            }
+        }
+    }
+}
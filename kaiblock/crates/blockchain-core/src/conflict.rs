@@ -0,0 +1,116 @@
+use crate::state::UTXOSet;
+use crate::transaction::{SigningDomain, Transaction};
+use crate::types::*;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{hash::sha256, Hash256};
+use serde::{Deserialize, Serialize};
+
+
+/// Proof that the owner of an output signed two different transactions
+/// spending it.
+///
+/// Anyone holding the spent UTXO can check it without trusting the sender,
+/// so it can be relayed as an alert to merchants accepting unconfirmed
+/// payments. Both transactions are carried whole: a signature covers the
+/// whole transaction hash, so nothing smaller can be verified.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConflictProof {
+    pub outpoint: OutPoint,
+    /// the conflicting transaction with the smaller id
+    pub first: Transaction,
+    pub second: Transaction,
+}
+
+impl ConflictProof {
+    /// Proof for the first outpoint `a` and `b` both spend, if any. The
+    /// order of the two transactions doesn't matter.
+    pub fn between(a: &Transaction, b: &Transaction) -> Option<Self> {
+        if a.id() == b.id() {
+            return None;
+        }
+        let outpoint = a.inputs.iter()
+            .map(|input| input.prev_output)
+            .find(|outpoint| b.inputs.iter().any(|input| input.prev_output == *outpoint))?;
+
+        let (first, second) = if a.id().hash().as_bytes() < b.id().hash().as_bytes() { (a, b) } else { (b, a) };
+        Some(Self { outpoint, first: first.clone(), second: second.clone() })
+    }
+
+    /// Identifies the conflict regardless of which side was seen first, for
+    /// deduplicating relayed alerts
+    pub fn id(&self) -> Hash256 {
+        let mut data = Vec::with_capacity(32 * 3 + 4);
+        data.extend_from_slice(self.first.id().hash().as_bytes());
+        data.extend_from_slice(self.second.id().hash().as_bytes());
+        data.extend_from_slice(self.outpoint.tx_id.hash().as_bytes());
+        data.extend_from_slice(&self.outpoint.output_index.to_le_bytes());
+        sha256(&data)
+    }
+
+    /// Check that both transactions spend the outpoint with a valid signature
+    /// from its owner for the chain of `domain`. Only the conflicting input is
+    /// checked, so the other inputs may be unknown to `utxo_set`.
+    pub fn verify(&self, utxo_set: &UTXOSet, domain: SigningDomain, accept_legacy: bool) -> Result<()> {
+        if self.first.id() == self.second.id() {
+            return Err(BlockchainError::ValidationError("Conflict proof repeats one transaction".to_string()));
+        }
+        let utxo = utxo_set.get_utxo(&self.outpoint)
+            .ok_or_else(|| BlockchainError::ValidationError(format!("Conflicting output {} is not unspent", self.outpoint)))?;
+
+        for tx in [&self.first, &self.second] {
+            let index = tx.inputs.iter()
+                .position(|input| input.prev_output == self.outpoint)
+                .ok_or_else(|| BlockchainError::ValidationError(
+                    format!("Transaction {} doesn't spend {}", tx.id(), self.outpoint)
+                ))?;
+            if !tx.verify_input_signature(index, utxo, domain, accept_legacy)? {
+                return Err(BlockchainError::ValidationError(
+                    format!("Transaction {} isn't signed by the owner of {}", tx.id(), self.outpoint)
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{TransactionInput, TransactionOutput, UTXO};
+    use blockchain_crypto::{signature::{generate_keypair, Keypair}, address::public_key_to_address, AddressType, Signature};
+
+    fn spend(keypair: &Keypair, outpoint: OutPoint, amount: Amount) -> Transaction {
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *keypair.public_key());
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(amount, address)], 100);
+        tx.sign_input(keypair, 0, SigningDomain::default()).unwrap();
+        tx
+    }
+
+    #[test]
+    fn test_conflict_proof_verifies() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let mut utxo_set = UTXOSet::new();
+        let utxo = UTXO::new(TransactionOutput::new(1000, address), 1, outpoint.tx_id, outpoint.output_index, false);
+        utxo_set.add_utxo(outpoint, utxo).unwrap();
+
+        let pay_merchant = spend(&keypair, outpoint, 900);
+        let pay_self = spend(&keypair, outpoint, 850);
+
+        let proof = ConflictProof::between(&pay_merchant, &pay_self).unwrap();
+        assert_eq!(proof.id(), ConflictProof::between(&pay_self, &pay_merchant).unwrap().id());
+        assert!(proof.verify(&utxo_set, SigningDomain::default(), false).is_ok());
+        assert!(ConflictProof::between(&pay_merchant, &pay_merchant).is_none());
+
+        // a forged second spend isn't signed by the owner
+        let mut forged = proof.clone();
+        forged.second = spend(&generate_keypair(), outpoint, 850);
+        assert!(forged.verify(&utxo_set, SigningDomain::default(), false).is_err());
+
+        // nor can it be checked once the output is gone
+        assert!(proof.verify(&UTXOSet::new(), SigningDomain::default(), false).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+//! Smart contracts on account state.
+//!
+//! A contract is an account holding WASM code (see `runtime::wasm` for the
+//! ABI) under the `code` metadata key, with its `code_hash` set to the code's
+//! sha256. Its storage is a single byte string under the `storage` key,
+//! committed by `storage_root`. Deploying creates the account at an address
+//! derived from the deployer and its nonce; calling runs the code through the
+//! runtime with the caller and the contract's storage as accounts. Contract
+//! randomness is seeded from the hash of the block the call is executed in.
+//!
+//! Gas is the runtime's compute: a transaction reserves `gas_limit * gas_price`
+//! and pays for the gas it used. A failing contract transaction is still
+//! included: it bumps the sender's nonce and pays for its whole gas limit, but
+//! moves no value and leaves the contract unchanged.
+
+use crate::logs::Log;
+use crate::state::AccountState;
+use crate::types::*;
+use blockchain_crypto::{hash::sha256, Address, Hash256};
+use runtime::{
+    AccountMeta, AccountStore, GasSchedule, HostCall, Instruction, MemoryAccountStore, Program, ProgramError,
+    Pubkey, RandomnessSource, Runtime, RuntimeConfig, RuntimeContext, StoredAccount, WasmProgram,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+
+/// Metadata key of a contract's code
+pub const CODE_KEY: &str = "code";
+
+/// Metadata key of a contract's storage
+pub const STORAGE_KEY: &str = "storage";
+
+
+/// Address of the contract `deployer` creates with its `nonce`th transaction
+pub fn contract_address(deployer: &Address, nonce: Nonce) -> Address {
+    let mut data = b"contract:".to_vec();
+    data.extend_from_slice(deployer.data());
+    data.extend_from_slice(&nonce.to_be_bytes());
+    Address::from_hash(sha256(&data), deployer.address_type())
+}
+
+//runtime key of an address
+fn runtime_key(address: &Address) -> Pubkey {
+    sha256(address.data()).to_bytes()
+}
+
+
+/// Outcome of a contract transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContractStatus {
+    Success,
+    /// the reason it failed; nothing but the fee and nonce was applied
+    Failed(String),
+}
+
+/// Record of a contract deployment or call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContractReceipt {
+    /// the contract deployed or called
+    pub contract: Address,
+    /// gas paid for: what the code used, or the whole limit on failure
+    pub gas_used: Gas,
+    pub status: ContractStatus,
+    /// what the contract logged; a failed call logs nothing
+    #[serde(default)]
+    pub logs: Vec<Log>,
+}
+
+impl ContractReceipt {
+    pub fn succeeded(&self) -> bool {
+        self.status == ContractStatus::Success
+    }
+}
+
+
+impl AccountState {
+    /// Code of a contract account
+    pub fn code(&self) -> Option<&[u8]> {
+        self.metadata.get(CODE_KEY).map(Vec::as_slice)
+    }
+
+    /// Storage of a contract account (empty for other accounts)
+    pub fn contract_storage(&self) -> &[u8] {
+        self.metadata.get(STORAGE_KEY).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn is_contract(&self) -> bool {
+        !self.code_hash.is_zero()
+    }
+
+    pub(crate) fn set_code(&mut self, code: Vec<u8>) {
+        self.code_hash = sha256(&code);
+        self.metadata.insert(CODE_KEY.to_string(), code);
+    }
+
+    pub(crate) fn set_contract_storage(&mut self, storage: Vec<u8>) {
+        if storage.is_empty() {
+            self.storage_root = Hash256::zero();
+            self.metadata.remove(STORAGE_KEY);
+        } else {
+            self.storage_root = sha256(&storage);
+            self.metadata.insert(STORAGE_KEY.to_string(), storage);
+        }
+    }
+}
+
+
+//a compiled program shared between the calls to contracts with the same code
+struct CachedProgram(Arc<WasmProgram>);
+
+impl Program for CachedProgram {
+    fn process(
+        &self,
+        accounts: &mut [runtime::AccountInfo],
+        data: &[u8],
+        ctx: &mut RuntimeContext,
+    ) -> std::result::Result<(), ProgramError> {
+        self.0.process(accounts, data, ctx)
+    }
+}
+
+
+/// Runs contract code for the world state, keeping compiled modules by code hash
+#[derive(Clone, Default)]
+pub struct ContractRuntime {
+    gas: GasSchedule,
+    programs: HashMap<Hash256, Arc<WasmProgram>>,
+}
+
+impl fmt::Debug for ContractRuntime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContractRuntime")
+            .field("gas", &self.gas)
+            .field("compiled", &self.programs.len())
+            .finish()
+    }
+}
+
+impl ContractRuntime {
+    //compile `code`, or reuse the module compiled for the same code
+    fn program(&mut self, code: &[u8]) -> std::result::Result<Arc<WasmProgram>, String> {
+        let code_hash = sha256(code);
+        if let Some(program) = self.programs.get(&code_hash) {
+            return Ok(Arc::clone(program));
+        }
+        let program = Arc::new(WasmProgram::new(code).map_err(|e| e.to_string())?);
+        self.programs.insert(code_hash, Arc::clone(&program));
+        Ok(program)
+    }
+
+    /// Check `code` compiles, returning the gas storing it costs
+    pub fn deploy(&mut self, code: &[u8], gas_limit: Gas) -> std::result::Result<Gas, String> {
+        let gas_used = self.gas.instruction
+            .saturating_add(self.gas.host_call_cost(HostCall::AccountWrite { bytes: code.len() }));
+        if gas_used > gas_limit {
+            return Err(format!("out of gas: deploying needs {}, limit is {}", gas_used, gas_limit));
+        }
+        self.program(code)?;
+        Ok(gas_used)
+    }
+
+    /// Run `contract` on `data` for `caller` in `block`, returning the gas
+    /// used, the contract's new storage and what it logged
+    #[allow(clippy::too_many_arguments)]
+    pub fn call(
+        &mut self,
+        contract: &Address,
+        account: &AccountState,
+        caller: &Address,
+        data: &[u8],
+        gas_limit: Gas,
+        block_height: BlockHeight,
+        block: &BlockId,
+    ) -> std::result::Result<(Gas, Vec<u8>, Vec<Log>), String> {
+        let code = account.code().ok_or_else(|| format!("no contract at {}", contract))?;
+        let program = self.program(code)?;
+
+        //the program is identified by its code, the storage by the contract
+        let program_id = account.code_hash.to_bytes();
+        let storage_key = runtime_key(contract);
+        let caller_key = runtime_key(caller);
+
+        let mut store = MemoryAccountStore::new();
+        store.commit(vec![(storage_key, StoredAccount {
+            owner: program_id,
+            data: account.contract_storage().to_vec(),
+        })]).map_err(|e| e.to_string())?;
+
+        //fees are settled in the world state, so the runtime charges none
+        let config = RuntimeConfig { max_compute_units: gas_limit, gas: self.gas.clone(), base_fee: 0, storage_deposit_per_byte: 0 };
+        let mut runtime = Runtime::new(config).with_account_store(Box::new(store));
+        runtime.clock = block_height;
+        let block_hash = block.hash().to_bytes();
+        runtime.randomness = RandomnessSource::BlockHash(block_hash);
+        runtime.register_program(program_id, CachedProgram(program));
+
+        let tx = runtime::Transaction {
+            fee_payer: caller_key,
+            recent_blockhash: block_hash,
+            accounts: vec![
+                AccountMeta { pubkey: caller_key, owner: caller_key, is_signer: true, is_writable: false },
+                AccountMeta { pubkey: storage_key, owner: program_id, is_signer: false, is_writable: true },
+            ],
+            instructions: vec![Instruction { program_id, accounts: vec![0, 1], data: data.to_vec() }],
+            compute_limit: gas_limit,
+            priority_fee: 0,
+        };
+
+        let receipt = runtime.execute_transaction(&tx, &[caller_key]).map_err(|e| e.to_string())?;
+        let storage = runtime.account(&storage_key).map_err(|e| e.to_string())?
+            .map(|stored| stored.data)
+            .unwrap_or_default();
+        let logs = receipt.logs.iter().map(|log| Log::from_program(contract, log)).collect();
+        Ok((receipt.compute_used, storage, logs))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::WorldState;
+    use crate::transaction::Transaction;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+
+    // writes "hi" to the contract's storage (account 1)
+    const ECHO: &str = r#"
+      (module
+        (import "env" "set_account_data" (func $set (param i32 i32 i32)))
+        (memory (export "memory") 1)
+        (data (i32.const 0) "hi")
+        (global $next (mut i32) (i32.const 1024))
+        (func (export "alloc") (param $len i32) (result i32)
+          (local $ptr i32)
+          (local.set $ptr (global.get $next))
+          (global.set $next (i32.add (global.get $next) (local.get $len)))
+          (local.get $ptr))
+        (func (export "process") (param $ptr i32) (param $len i32) (result i32)
+          (call $set (i32.const 1) (i32.const 0) (i32.const 2))
+          (i32.const 0)))
+    "#;
+
+    fn deployment(from: &Address, code: Vec<u8>, nonce: Nonce) -> Transaction {
+        let mut tx = Transaction::new_account(from.clone(), from.clone(), 0, nonce, 1_000_000, 1, code);
+        tx.tx_type = TransactionType::ContractDeployment;
+        tx.to = None;
+        tx
+    }
+
+    #[test]
+    fn test_deploy_and_call_contract() {
+        let keypair = generate_keypair();
+        let sender = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let mut world_state = WorldState::new(AccountModel::Account);
+        world_state.set_account(sender.clone(), AccountState::new(10_000_000));
+
+        let code = wat::parse_str(ECHO).unwrap();
+        let undo = world_state.apply_transaction_with_undo(&deployment(&sender, code.clone(), 0)).unwrap();
+        let receipt = undo.receipt.clone().unwrap();
+        assert!(receipt.succeeded());
+        assert_eq!(receipt.contract, contract_address(&sender, 0));
+
+        let contract = world_state.get_account(&receipt.contract);
+        assert_eq!(contract.code(), Some(code.as_slice()));
+        assert_eq!(contract.code_hash, sha256(&code));
+        assert_eq!(world_state.get_balance(&sender), 10_000_000 - receipt.gas_used);
+
+        // only the gas used is paid for
+        let call = Transaction::new_account(sender.clone(), receipt.contract.clone(), 250, 1, 1_000_000, 2, vec![1, 2]);
+        let call_undo = world_state.apply_transaction_with_undo(&call).unwrap();
+        let call_receipt = call_undo.receipt.clone().unwrap();
+        assert!(call_receipt.succeeded());
+        assert!(call_receipt.gas_used > 0 && call_receipt.gas_used < 1_000_000);
+        assert_eq!(world_state.get_balance(&receipt.contract), 250);
+        assert_eq!(world_state.get_account(&receipt.contract).contract_storage(), b"hi");
+        assert_eq!(
+            world_state.get_balance(&sender),
+            10_000_000 - receipt.gas_used - 250 - 2 * call_receipt.gas_used
+        );
+
+        world_state.revert_transaction(&call_undo).unwrap();
+        world_state.revert_transaction(&undo).unwrap();
+        assert!(!world_state.accounts().contains_key(&receipt.contract));
+        assert_eq!(world_state.get_balance(&sender), 10_000_000);
+    }
+
+    #[test]
+    fn test_failed_call_pays_gas_and_changes_nothing() {
+        let keypair = generate_keypair();
+        let sender = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let mut world_state = WorldState::new(AccountModel::Account);
+        world_state.set_account(sender.clone(), AccountState::new(10_000_000));
+
+        let nowhere = contract_address(&sender, 7);
+        let call = Transaction::new_account(sender.clone(), nowhere.clone(), 250, 0, 5_000, 1, vec![1]);
+        let receipt = world_state.apply_transaction_with_undo(&call).unwrap().receipt.unwrap();
+
+        assert!(!receipt.succeeded());
+        assert_eq!(receipt.gas_used, 5_000);
+        assert_eq!(world_state.get_balance(&sender), 10_000_000 - 5_000);
+        assert_eq!(world_state.get_account(&sender).nonce, 1);
+        assert!(!world_state.accounts().contains_key(&nowhere));
+    }
+
+    #[test]
+    fn test_randomness_is_seeded_per_block() {
+        // stores a 32-byte draw in the contract's storage
+        const DRAW: &str = r#"
+          (module
+            (import "env" "random" (func $random (param i32)))
+            (import "env" "set_account_data" (func $set (param i32 i32 i32)))
+            (memory (export "memory") 1)
+            (global $next (mut i32) (i32.const 1024))
+            (func (export "alloc") (param $len i32) (result i32)
+              (local $ptr i32)
+              (local.set $ptr (global.get $next))
+              (global.set $next (i32.add (global.get $next) (local.get $len)))
+              (local.get $ptr))
+            (func (export "process") (param $ptr i32) (param $len i32) (result i32)
+              (call $random (i32.const 0))
+              (call $set (i32.const 1) (i32.const 0) (i32.const 32))
+              (i32.const 0)))
+        "#;
+        let caller = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let contract = contract_address(&caller, 0);
+        let mut account = AccountState::new(0);
+        account.set_code(wat::parse_str(DRAW).unwrap());
+
+        let mut contracts = ContractRuntime::default();
+        let mut draw = |block: BlockId| {
+            let (_, storage, _) = contracts.call(&contract, &account, &caller, &[1], 1_000_000, 5, &block).unwrap();
+            assert_eq!(storage.len(), 32);
+            storage
+        };
+        let (block_a, block_b) = (BlockId::new(sha256(b"block a")), BlockId::new(sha256(b"block b")));
+        assert_eq!(draw(block_a), draw(block_a));
+        assert_ne!(draw(block_a), draw(block_b));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::types::*;
+use crate::Result;
+use blockchain_crypto::{hash::sha256, signature::Keypair, Address, AddressType};
+use serde::{Deserialize, Serialize};
+
+
+/// Seed phrase for the default dev accounts. Public on purpose: never fund these on a real network.
+pub const DEFAULT_DEV_SEED_PHRASE: &str =
+    "test test test test test test test test test test test junk";
+
+/// Number of dev accounts in the default chain spec
+pub const DEFAULT_DEV_ACCOUNT_COUNT: u32 = 10;
+
+/// Balance of each default dev account (1000 kai)
+pub const DEFAULT_DEV_ACCOUNT_BALANCE: Amount = 1_000_000_000;
+
+
+/// One dev account in the chain spec
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DevAccountSpec {
+    /// derivation path, e.g. `m/44'/0'/0'/0/0`
+    pub derivation_path: String,
+    /// balance credited at genesis
+    pub balance: Amount,
+}
+
+
+/// Chain spec section for deterministic dev accounts.
+/// Only funded at genesis on Devnet and Local networks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DevAccountsConfig {
+    pub seed_phrase: String,
+    pub accounts: Vec<DevAccountSpec>,
+}
+
+impl DevAccountsConfig {
+    /// `count` accounts at `m/44'/0'/0'/0/{i}`, each funded with `balance`
+    pub fn with_count(seed_phrase: &str, count: u32, balance: Amount) -> Self {
+        Self {
+            seed_phrase: seed_phrase.to_string(),
+            accounts: (0..count)
+                .map(|index| DevAccountSpec {
+                    derivation_path: format!("m/44'/0'/0'/0/{}", index),
+                    balance,
+                })
+                .collect(),
+        }
+    }
+
+    /// Derive every account in the spec
+    pub fn derive(&self) -> Result<Vec<DevAccount>> {
+        self.accounts.iter()
+            .map(|spec| {
+                let keypair = derive_keypair(&self.seed_phrase, &spec.derivation_path)?;
+                Ok(DevAccount {
+                    derivation_path: spec.derivation_path.clone(),
+                    address: Address::from_public_key(&keypair.public_key(), AddressType::Base58),
+                    public_key: keypair.export_public_key(),
+                    private_key: keypair.export_private_key(),
+                    balance: spec.balance,
+                })
+            })
+            .collect()
+    }
+}
+
+impl Default for DevAccountsConfig {
+    fn default() -> Self {
+        Self::with_count(DEFAULT_DEV_SEED_PHRASE, DEFAULT_DEV_ACCOUNT_COUNT, DEFAULT_DEV_ACCOUNT_BALANCE)
+    }
+}
+
+
+/// A derived dev account, keys hex-encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DevAccount {
+    pub derivation_path: String,
+    pub address: Address,
+    pub public_key: String,
+    pub private_key: String,
+    /// balance credited at genesis
+    pub balance: Amount,
+}
+
+
+/// Deterministic key for `path` under `seed_phrase`.
+///
+/// The private key is `sha256(seed_phrase || "/" || path)`. This is a plain
+/// hash-based scheme for test networks only, not BIP-32: the same phrase
+/// will not give the same keys in other wallets.
+pub fn derive_keypair(seed_phrase: &str, path: &str) -> Result<Keypair> {
+    let material = format!("{}/{}", seed_phrase.trim(), path);
+    let secret = sha256(material.as_bytes());
+    Ok(Keypair::from_private_bytes(secret.as_bytes())?)
+}
+
+
+/// Whether genesis funds dev accounts on this network
+pub fn funds_dev_accounts(network: NetworkType) -> bool {
+    matches!(network, NetworkType::Devnet | NetworkType::Local)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_derivation_is_deterministic() {
+        let a = derive_keypair(DEFAULT_DEV_SEED_PHRASE, "m/44'/0'/0'/0/0").unwrap();
+        let b = derive_keypair(DEFAULT_DEV_SEED_PHRASE, "m/44'/0'/0'/0/0").unwrap();
+        let c = derive_keypair(DEFAULT_DEV_SEED_PHRASE, "m/44'/0'/0'/0/1").unwrap();
+        let d = derive_keypair("another phrase", "m/44'/0'/0'/0/0").unwrap();
+
+        assert_eq!(a.export_private_key(), b.export_private_key());
+        assert_ne!(a.export_private_key(), c.export_private_key());
+        assert_ne!(a.export_private_key(), d.export_private_key());
+    }
+
+    #[test]
+    fn test_default_accounts() {
+        let accounts = DevAccountsConfig::default().derive().unwrap();
+
+        assert_eq!(accounts.len(), DEFAULT_DEV_ACCOUNT_COUNT as usize);
+        assert_eq!(accounts[3].derivation_path, "m/44'/0'/0'/0/3");
+        assert_eq!(accounts[0], DevAccountsConfig::default().derive().unwrap()[0]);
+        assert!(accounts.iter().all(|account| account.balance == DEFAULT_DEV_ACCOUNT_BALANCE));
+    }
+
+    #[test]
+    fn test_only_dev_networks_are_funded() {
+        assert!(funds_dev_accounts(NetworkType::Devnet));
+        assert!(funds_dev_accounts(NetworkType::Local));
+        assert!(!funds_dev_accounts(NetworkType::Testnet));
+        assert!(!funds_dev_accounts(NetworkType::Mainnet));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::types::*;
+use crate::{BlockchainError, Result};
+use crate::block::{Block, BlockHeader};
+use crate::validation::ValidationRules;
+
+
+/// Lowest difficulty a retarget can produce
+pub const MIN_DIFFICULTY: Difficulty = 1;
+
+/// Highest difficulty a retarget can produce (leading zero bits of a 256-bit hash)
+pub const MAX_DIFFICULTY: Difficulty = 255;
+
+
+/// Check if blocks at this height start a new difficulty period
+pub fn is_retarget_height(height: BlockHeight, rules: &ValidationRules) -> bool {
+    let period = rules.difficulty_adjustment_period.max(1);
+    height > 0 && height % period == 0
+}
+
+
+/// Height of the first block in the window a retarget at `height` looks at
+pub fn retarget_window_start(height: BlockHeight, rules: &ValidationRules) -> BlockHeight {
+    height.saturating_sub(rules.difficulty_adjustment_period.max(1))
+}
+
+
+/// Block intervals the retarget window spans. The window runs from the
+/// first block of a period to its last, so it holds one interval fewer than
+/// the period has blocks
+pub fn retarget_intervals(rules: &ValidationRules) -> u64 {
+    rules.difficulty_adjustment_period.saturating_sub(1).max(1)
+}
+
+
+/// Adjust a difficulty for the time the last period actually took.
+///
+/// Difficulty counts leading zero bits, so expected work doubles per step.
+/// The work ratio `expected / actual` is clamped to `max_difficulty_adjustment`
+/// in either direction and then applied as a whole number of bits. All of it
+/// is integer math, so every node agrees on the result.
+pub fn retarget(prev_difficulty: Difficulty, actual_timespan: i64, rules: &ValidationRules) -> Difficulty {
+    let expected_timespan = retarget_intervals(rules).saturating_mul(rules.target_block_time).max(1);
+    let actual_timespan = actual_timespan.max(1) as u64;
+
+    // the limit is a whole-number factor in the rules, applied in whole bits
+    let max_adjustment = rules.max_difficulty_adjustment.max(1.0) as u64;
+    let max_bits = rounded_log2_ratio(max_adjustment, 1, MAX_ADJUSTMENT_BITS);
+
+    let adjustment = rounded_log2_ratio(expected_timespan, actual_timespan, max_bits);
+    let next = prev_difficulty as i64 + adjustment;
+
+    next.clamp(MIN_DIFFICULTY as i64, MAX_DIFFICULTY as i64) as Difficulty
+}
+
+
+// Terms of a ratio are capped below 2^63, so the ratio is within 2^63 either way
+const MAX_RATIO_TERM: u64 = i64::MAX as u64;
+const MAX_ADJUSTMENT_BITS: i64 = 63;
+
+
+// log2(num / den) rounded to the nearest whole number and clamped to
+// `max_bits` either way. `k` is the answer when
+// 2^(2k-1) <= (num / den)^2 < 2^(2k+1), which compares squares instead of
+// taking a logarithm. Each step only runs once the previous boundary was
+// crossed, so the shifted side stays within 4 * num^2 (or 4 * den^2), which
+// fits a u128 for terms below 2^63
+fn rounded_log2_ratio(num: u64, den: u64, max_bits: i64) -> i64 {
+    let num = (num.clamp(1, MAX_RATIO_TERM) as u128).pow(2);
+    let den = (den.clamp(1, MAX_RATIO_TERM) as u128).pow(2);
+
+    let mut bits = 0i64;
+    if num >= den {
+        while bits < max_bits && num >= den << (2 * bits + 1) {
+            bits += 1;
+        }
+    } else {
+        while bits > -max_bits && num << (1 - 2 * bits) < den {
+            bits -= 1;
+        }
+    }
+    bits
+}
+
+
+/// Difficulty required for the block following `prev_block`.
+///
+/// `window_start` is the ancestor at `retarget_window_start(height)` on the same
+/// branch; it is only consulted on retarget heights, where it is an error for
+/// it to be missing (e.g. pruned away).
+pub fn next_difficulty(
+    prev_block: &Block,
+    window_start: Option<&Block>,
+    rules: &ValidationRules,
+) -> Result<Difficulty> {
+    next_difficulty_for_header(&prev_block.header, window_start.map(|start| &start.header), rules)
+}
+
+
+/// [`next_difficulty`] for header-only chains
+pub fn next_difficulty_for_header(
+    prev: &BlockHeader,
+    window_start: Option<&BlockHeader>,
+    rules: &ValidationRules,
+) -> Result<Difficulty> {
+    let height = prev.height + 1;
+
+    if !is_retarget_height(height, rules) {
+        return Ok(prev.difficulty);
+    }
+
+    let start = window_start.ok_or_else(|| BlockchainError::BlockNotFound(format!(
+        "retarget window start at height {} for block {}", retarget_window_start(height, rules), height
+    )))?;
+    let actual_timespan = prev.timestamp.to_unix_timestamp()
+        - start.timestamp.to_unix_timestamp();
+    Ok(retarget(prev.difficulty, actual_timespan, rules))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules() -> ValidationRules {
+        ValidationRules {
+            difficulty_adjustment_period: 10,
+            target_block_time: 60,
+            max_difficulty_adjustment: 4.0,
+            ..ValidationRules::default()
+        }
+    }
+
+    #[test]
+    fn test_retarget_heights() {
+        let rules = rules();
+
+        assert!(!is_retarget_height(0, &rules));
+        assert!(!is_retarget_height(9, &rules));
+        assert!(is_retarget_height(10, &rules));
+        assert_eq!(retarget_window_start(10, &rules), 0);
+        assert_eq!(retarget_window_start(30, &rules), 20);
+    }
+
+    #[test]
+    fn test_retarget_on_schedule_keeps_difficulty() {
+        assert_eq!(retarget(8, 600, &rules()), 8);
+    }
+
+    #[test]
+    fn test_retarget_fast_blocks_raise_difficulty() {
+        // twice as fast -> one more bit
+        assert_eq!(retarget(8, 300, &rules()), 9);
+        // far too fast -> clamped to 4x
+        assert_eq!(retarget(8, 1, &rules()), 10);
+    }
+
+    #[test]
+    fn test_retarget_slow_blocks_lower_difficulty() {
+        assert_eq!(retarget(8, 1200, &rules()), 7);
+        assert_eq!(retarget(8, 1_000_000, &rules()), 6);
+        assert_eq!(retarget(1, 1_000_000, &rules()), MIN_DIFFICULTY);
+    }
+
+    #[test]
+    fn test_retarget_expects_one_interval_per_window_gap() {
+        // 10 blocks per period are 9 intervals of 60s
+        assert_eq!(retarget_intervals(&rules()), 9);
+        // 540s is on schedule; counting 10 intervals would read 2x too fast
+        assert_eq!(retarget(8, 540, &rules()), 8);
+        assert_eq!(retarget(8, 270, &rules()), 9);
+        assert_eq!(retarget(8, 1080, &rules()), 7);
+    }
+
+    #[test]
+    fn test_retarget_rounds_to_nearest_bit() {
+        // log2(540 / 382) ~ 0.499 rounds down, log2(540 / 381) ~ 0.503 rounds up
+        assert_eq!(retarget(8, 382, &rules()), 8);
+        assert_eq!(retarget(8, 381, &rules()), 9);
+        // and the same boundary on the slow side
+        assert_eq!(retarget(8, 763, &rules()), 8);
+        assert_eq!(retarget(8, 764, &rules()), 7);
+    }
+
+    #[test]
+    fn test_rounded_log2_ratio() {
+        assert_eq!(rounded_log2_ratio(1, 1, 63), 0);
+        assert_eq!(rounded_log2_ratio(8, 1, 63), 3);
+        assert_eq!(rounded_log2_ratio(1, 8, 63), -3);
+        assert_eq!(rounded_log2_ratio(u64::MAX, 1, 63), 63);
+        assert_eq!(rounded_log2_ratio(1, u64::MAX, 63), -63);
+        assert_eq!(rounded_log2_ratio(u64::MAX, 3, 63), 61);
+        assert_eq!(rounded_log2_ratio(1 << 20, 1, 2), 2);
+        assert_eq!(rounded_log2_ratio(4, 1, 0), 0);
+    }
+
+    fn header(height: BlockHeight, difficulty: Difficulty) -> BlockHeader {
+        let hash = blockchain_crypto::hash::sha256(b"block");
+        BlockHeader::new(BlockId::new(hash), hash, difficulty, height, 1, 1)
+    }
+
+    #[test]
+    fn test_missing_window_start_is_an_error() {
+        let rules = rules();
+        assert!(next_difficulty_for_header(&header(9, 8), None, &rules).is_err());
+
+        // off a retarget height the window isn't needed
+        assert_eq!(next_difficulty_for_header(&header(4, 8), None, &rules).unwrap(), 8);
+    }
+}
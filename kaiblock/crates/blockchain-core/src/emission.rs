@@ -0,0 +1,89 @@
+use crate::types::{Amount, BlockHeight};
+use serde::{Deserialize, Serialize};
+
+
+/// How the block subsidy shrinks with height.
+///
+/// The subsidy starts at the chain's `block_reward` and halves every
+/// `halving_interval` blocks. Once a halving would take it below
+/// `min_subsidy` the halving curve ends; from then on blocks pay
+/// `tail_emission`, or nothing without one. The tail is also a floor: the
+/// subsidy never drops below it. The default never halves, so the subsidy
+/// stays at `block_reward` forever.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmissionSchedule {
+    /// blocks between halvings; 0 never halves
+    pub halving_interval: BlockHeight,
+    /// smallest subsidy the halving curve pays
+    pub min_subsidy: Amount,
+    /// subsidy paid forever once the halving curve ends
+    pub tail_emission: Option<Amount>,
+}
+
+impl EmissionSchedule {
+    /// Halve every `interval` blocks until the subsidy reaches zero
+    pub fn halving_every(interval: BlockHeight) -> Self {
+        Self { halving_interval: interval, ..Self::default() }
+    }
+
+    /// Subsidy of the block at `height` on a chain starting at `initial`
+    pub fn subsidy(&self, initial: Amount, height: BlockHeight) -> Amount {
+        let halved = match height.checked_div(self.halving_interval) {
+            None => initial,
+            Some(halvings) if halvings >= Amount::BITS as u64 => 0,
+            Some(halvings) => initial >> halvings,
+        };
+        let curve = if halved >= self.min_subsidy { halved } else { 0 };
+        curve.max(self.tail_emission.unwrap_or(0))
+    }
+
+    /// Height of the first block paying no more than the tail emission, if
+    /// the halving curve ever ends
+    pub fn curve_end(&self, initial: Amount) -> Option<BlockHeight> {
+        if self.halving_interval == 0 {
+            return None;
+        }
+        let floor = self.min_subsidy.max(1);
+        let halvings = (0..Amount::BITS as u64).find(|&halvings| initial >> halvings < floor)?;
+        halvings.checked_mul(self.halving_interval)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_schedule_is_constant() {
+        let schedule = EmissionSchedule::default();
+        assert_eq!(schedule.subsidy(50, 0), 50);
+        assert_eq!(schedule.subsidy(50, BlockHeight::MAX), 50);
+        assert_eq!(schedule.curve_end(50), None);
+    }
+
+    #[test]
+    fn test_subsidy_halves_every_interval() {
+        let schedule = EmissionSchedule::halving_every(10);
+        assert_eq!(schedule.subsidy(100, 9), 100);
+        assert_eq!(schedule.subsidy(100, 10), 50);
+        assert_eq!(schedule.subsidy(100, 25), 25);
+        assert_eq!(schedule.subsidy(100, 70), 0);
+        assert_eq!(schedule.subsidy(100, BlockHeight::MAX), 0);
+        assert_eq!(schedule.curve_end(100), Some(70));
+    }
+
+    #[test]
+    fn test_min_subsidy_and_tail_emission() {
+        let mut schedule = EmissionSchedule { halving_interval: 10, min_subsidy: 20, tail_emission: None };
+        assert_eq!(schedule.subsidy(100, 20), 25);
+        assert_eq!(schedule.subsidy(100, 30), 0, "12 is below the minimum");
+        assert_eq!(schedule.curve_end(100), Some(30));
+
+        schedule.tail_emission = Some(5);
+        assert_eq!(schedule.subsidy(100, 20), 25);
+        assert_eq!(schedule.subsidy(100, 30), 5);
+        assert_eq!(schedule.subsidy(100, BlockHeight::MAX), 5);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::block::Block;
+use crate::conflict::ConflictProof;
+use crate::mempool::MempoolEvent;
+use crate::transaction::Transaction;
+use crate::types::*;
+use std::sync::Arc;
+
+
+/// Events buffered per subscriber; a subscriber further behind than this
+/// gets `RecvError::Lagged` and skips ahead
+pub const CHAIN_EVENT_CAPACITY: usize = 1024;
+
+
+/// Why a transaction left the mempool without being confirmed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxDropReason {
+    /// a conflicting transaction paying more took its place
+    Replaced {
+        by: TxId,
+        old_fee_per_byte: u64,
+        new_fee_per_byte: u64,
+    },
+    /// it sat in the pool longer than the configured maximum age
+    Expired,
+    /// the pool was full and it had the lowest priority
+    Evicted,
+    /// disconnected by a reorg and no longer valid on the new chain
+    Reorged,
+    /// a different transaction with the same sender nonce was confirmed
+    NonceUsed,
+    /// the chain moved and it no longer validates, e.g. a confirmed
+    /// transaction spent its inputs
+    Invalid,
+}
+
+
+/// Chain and mempool changes, published by [`crate::Blockchain`] to every
+/// receiver returned by `Blockchain::subscribe`.
+///
+/// On a reorg the old branch's blocks are disconnected newest first, then the
+/// new branch's blocks are connected oldest first.
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    BlockConnected(Arc<Block>),
+    BlockDisconnected(Arc<Block>),
+    /// a transaction entered the mempool
+    TxAdded(Arc<Transaction>),
+    TxDropped { tx_id: TxId, reason: TxDropReason },
+    /// two signed transactions spending the same output were seen
+    DoubleSpend(Arc<ConflictProof>),
+}
+
+impl From<MempoolEvent> for ChainEvent {
+    fn from(event: MempoolEvent) -> Self {
+        match event {
+            MempoolEvent::Replaced { replaced, replacement, old_fee_per_byte, new_fee_per_byte } => ChainEvent::TxDropped {
+                tx_id: replaced,
+                reason: TxDropReason::Replaced { by: replacement, old_fee_per_byte, new_fee_per_byte },
+            },
+            MempoolEvent::Expired { tx_id } => ChainEvent::TxDropped { tx_id, reason: TxDropReason::Expired },
+            MempoolEvent::Evicted { tx_id } => ChainEvent::TxDropped { tx_id, reason: TxDropReason::Evicted },
+            MempoolEvent::NonceUsed { tx_id } => ChainEvent::TxDropped { tx_id, reason: TxDropReason::NonceUsed },
+            MempoolEvent::DoubleSpend(proof) => ChainEvent::DoubleSpend(Arc::new(proof)),
+            MempoolEvent::Invalidated { tx_id } => ChainEvent::TxDropped { tx_id, reason: TxDropReason::Invalid },
+        }
+    }
+}
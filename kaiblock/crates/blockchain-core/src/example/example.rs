@@ -68,6 +68,7 @@ async fn demo_blockchain_setup() -> Result<()> {
             initial_accounts,
             timestamp: Some(1640995200), // Jan 1, 2022
             difficulty: 1, // Low difficulty for demo
+            dev_accounts: None,
         },
         validation_rules: ValidationRules {
             max_block_size: 1024 * 1024,
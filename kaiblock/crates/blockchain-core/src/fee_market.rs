@@ -0,0 +1,99 @@
+use crate::types::{Amount, Gas, GasPrice};
+use serde::{Deserialize, Serialize};
+
+
+/// EIP-1559 style base fee for account transactions.
+///
+/// Every account transaction in a block has to offer a gas price of at least
+/// the block's base fee. The base fee part of what it pays for gas is burned;
+/// only the rest, the tip, goes to the block producer. The base fee follows
+/// block fullness: a block using more than `target_gas` raises the next
+/// block's base fee and one using less lowers it, by at most
+/// `1 / max_change_denominator` per block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeMarketConfig {
+    /// base fee of the genesis block
+    pub initial_base_fee: GasPrice,
+    /// the base fee never drops below this
+    pub min_base_fee: GasPrice,
+    /// gas used by a block that leaves the base fee where it is
+    pub target_gas: Gas,
+    /// inverse of the largest change per block; 8 moves it by up to 12.5%
+    pub max_change_denominator: u64,
+}
+
+impl Default for FeeMarketConfig {
+    fn default() -> Self {
+        Self {
+            initial_base_fee: 1,
+            min_base_fee: 1,
+            target_gas: 10_500_000, // 500 transfers
+            max_change_denominator: 8,
+        }
+    }
+}
+
+impl FeeMarketConfig {
+    /// Base fee of the block after one with `base_fee` that used `gas_used`
+    pub fn next_base_fee(&self, base_fee: GasPrice, gas_used: Gas) -> GasPrice {
+        let base_fee = u128::from(base_fee);
+        let target = u128::from(self.target_gas.max(1));
+        let denominator = u128::from(self.max_change_denominator.max(1));
+        let gas_used = u128::from(gas_used);
+
+        let next = if gas_used > target {
+            // always move up, or a base fee of 1 could never rise
+            base_fee + (base_fee * (gas_used - target) / target / denominator).max(1)
+        } else {
+            base_fee - base_fee * (target - gas_used) / target / denominator
+        };
+        next.min(u128::from(GasPrice::MAX)).max(u128::from(self.min_base_fee)) as GasPrice
+    }
+}
+
+
+/// Split what a transaction pays for `gas_used` at `gas_price` into the part
+/// burned at `base_fee` and the producer's tip
+pub fn split_gas_fee(gas_used: Gas, gas_price: GasPrice, base_fee: GasPrice) -> (Amount, Amount) {
+    let burned = gas_used.saturating_mul(gas_price.min(base_fee));
+    let tip = gas_used.saturating_mul(gas_price.saturating_sub(base_fee));
+    (burned, tip)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> FeeMarketConfig {
+        FeeMarketConfig { initial_base_fee: 100, min_base_fee: 10, target_gas: 1_000, max_change_denominator: 8 }
+    }
+
+    #[test]
+    fn test_base_fee_follows_block_fullness() {
+        let config = config();
+        assert_eq!(config.next_base_fee(100, 1_000), 100);
+        assert_eq!(config.next_base_fee(100, 2_000), 112);
+        assert_eq!(config.next_base_fee(100, 0), 88);
+        assert_eq!(config.next_base_fee(100, 1_500), 106);
+    }
+
+    #[test]
+    fn test_base_fee_bounds() {
+        let config = config();
+        assert_eq!(config.next_base_fee(10, 0), 10, "never below the minimum");
+        assert_eq!(config.next_base_fee(1, 1_001), 10);
+
+        let config = FeeMarketConfig { min_base_fee: 1, ..config };
+        assert_eq!(config.next_base_fee(1, 1_001), 2, "a full block always raises it");
+        assert_eq!(config.next_base_fee(GasPrice::MAX, Gas::MAX), GasPrice::MAX);
+    }
+
+    #[test]
+    fn test_split_gas_fee() {
+        assert_eq!(split_gas_fee(21_000, 5, 3), (63_000, 42_000));
+        assert_eq!(split_gas_fee(21_000, 3, 3), (63_000, 0));
+        assert_eq!(split_gas_fee(0, 5, 3), (0, 0));
+    }
+}
@@ -0,0 +1,341 @@
+//! Genesis files.
+//!
+//! Nodes of one network have to start from the same genesis block. A genesis
+//! file pins everything that goes into it: the chain id, the consensus
+//! parameters, a fixed timestamp, the initial balances and the initial
+//! validators. `blockchain-node init --genesis genesis.json` turns it into the
+//! node's chain config and creates the genesis block, so every node given the
+//! same file gets the same genesis hash.
+//!
+//! Balances and validators only change state, not the block itself, so the
+//! genesis coinbase carries a hash of them in its data: two files that fund
+//! different accounts can't produce the same genesis hash.
+
+use crate::types::*;
+use crate::block::Block;
+use crate::chain::{ChainConfig, GenesisConfig};
+use crate::dev_accounts::{self, DevAccountsConfig};
+use crate::emission::EmissionSchedule;
+use crate::fee_market::FeeMarketConfig;
+use crate::transaction::Transaction;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{hash::sha256, Address, PublicKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::info;
+
+
+/// Proof-of-stake parameters fixed at genesis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StakingParams {
+    /// blocks per epoch; stake changes take effect at the first block of an epoch
+    pub epoch_length: BlockHeight,
+    /// epochs between an unbond taking effect and the funds becoming withdrawable
+    pub unbonding_period: u64,
+    /// smallest active stake that makes a validator
+    pub min_stake: Amount,
+    /// largest validator set; the highest stakes win
+    pub max_validators: usize,
+    /// percent of the validator set's stake whose attestations finalize a block
+    pub required_quorum_pct: u8,
+}
+
+impl Default for StakingParams {
+    fn default() -> Self {
+        Self {
+            epoch_length: 100,
+            unbonding_period: 7,
+            min_stake: 1_000_000,
+            max_validators: 100,
+            required_quorum_pct: 67,
+        }
+    }
+}
+
+
+/// A validator bonded at genesis. The stake is bonded as is, not taken from
+/// a balance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisValidator {
+    pub address: Address,
+    pub stake: Amount,
+    /// key the validator signs proposals with
+    pub public_key: PublicKey,
+}
+
+
+/// Consensus section of a genesis file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConsensusParams {
+    /// difficulty of the genesis block (leading zero bits)
+    pub difficulty: Difficulty,
+    pub block_reward: Amount,
+    /// how the block reward shrinks with height
+    pub emission: EmissionSchedule,
+    /// target block time in seconds
+    pub target_block_time: u64,
+    /// proof-of-stake parameters; required when the file has validators
+    pub staking: Option<StakingParams>,
+    /// base fee of account transactions; none without it
+    pub fee_market: Option<FeeMarketConfig>,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self {
+            difficulty: 1,
+            block_reward: 25_000_000,
+            emission: EmissionSchedule::default(),
+            target_block_time: 600,
+            staking: None,
+            fee_market: None,
+        }
+    }
+}
+
+
+/// Validator entry of a genesis file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ValidatorEntry {
+    pub address: String,
+    pub stake: Amount,
+    /// hex-encoded Ed25519 public key
+    pub public_key: String,
+}
+
+
+/// A genesis file, JSON or TOML. Addresses are in their string form and keys
+/// are hex, so the file can be written by hand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisFile {
+    pub chain_id: ChainId,
+    pub network: NetworkType,
+    pub account_model: AccountModel,
+    /// unix seconds; required, since a genesis block stamped with the time
+    /// it was created would differ from node to node
+    pub timestamp: i64,
+    /// receives `genesis_reward` in the genesis coinbase
+    pub coinbase_recipient: String,
+    #[serde(default)]
+    pub genesis_reward: Amount,
+    #[serde(default)]
+    pub consensus: ConsensusParams,
+    /// balances credited at genesis, by address
+    #[serde(default)]
+    pub balances: BTreeMap<String, Amount>,
+    #[serde(default)]
+    pub validators: Vec<ValidatorEntry>,
+    /// deterministic dev accounts, funded on Devnet and Local only
+    #[serde(default)]
+    pub dev_accounts: Option<DevAccountsConfig>,
+}
+
+impl GenesisFile {
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text)
+            .map_err(|e| BlockchainError::SerializationError(format!("invalid genesis file: {}", e)))
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| BlockchainError::SerializationError(e.to_string()))
+    }
+
+    /// Check the file and build the genesis section of a chain config
+    pub fn genesis_config(&self) -> Result<GenesisConfig> {
+        let invalid = |message: String| BlockchainError::ValidationError(format!("genesis file: {}", message));
+
+        let mut initial_accounts = HashMap::new();
+        for (address, balance) in &self.balances {
+            let parsed = Address::from_string(address)
+                .map_err(|e| invalid(format!("bad address {}: {}", address, e)))?;
+            if initial_accounts.insert(parsed, *balance).is_some() {
+                return Err(invalid(format!("address {} is funded twice", address)));
+            }
+        }
+
+        if !self.validators.is_empty() && self.consensus.staking.is_none() {
+            return Err(invalid("validators need consensus.staking".to_string()));
+        }
+        if let Some(staking) = self.consensus.staking.as_ref().filter(|staking| !(1..=100).contains(&staking.required_quorum_pct)) {
+            return Err(invalid(format!("required_quorum_pct {} is not a percentage", staking.required_quorum_pct)));
+        }
+        let min_stake = self.consensus.staking.as_ref().map_or(0, |staking| staking.min_stake);
+        let mut seen = HashSet::new();
+        let mut validators = Vec::with_capacity(self.validators.len());
+        for entry in &self.validators {
+            let address = Address::from_string(&entry.address)
+                .map_err(|e| invalid(format!("bad validator address {}: {}", entry.address, e)))?;
+            let public_key = PublicKey::from_hex(&entry.public_key)
+                .map_err(|e| invalid(format!("bad key for validator {}: {}", entry.address, e)))?;
+            if entry.stake < min_stake.max(1) {
+                return Err(invalid(format!("validator {} stakes {}, below the minimum {}", entry.address, entry.stake, min_stake)));
+            }
+            if !seen.insert(address.clone()) {
+                return Err(invalid(format!("validator {} is listed twice", entry.address)));
+            }
+            validators.push(GenesisValidator { address, stake: entry.stake, public_key });
+        }
+
+        Ok(GenesisConfig {
+            coinbase_recipient: Address::from_string(&self.coinbase_recipient)
+                .map_err(|e| invalid(format!("bad coinbase recipient {}: {}", self.coinbase_recipient, e)))?,
+            genesis_reward: self.genesis_reward,
+            initial_accounts,
+            timestamp: Some(self.timestamp),
+            genesis_difficulty: self.consensus.difficulty,
+            dev_accounts: self.dev_accounts.clone(),
+            validators,
+        })
+    }
+
+    /// `base` with everything the file pins replaced; node-local settings
+    /// (storage, caches, checkpoints, pruning) are kept
+    pub fn chain_config(&self, base: ChainConfig) -> Result<ChainConfig> {
+        let mut config = base;
+        config.network = self.network;
+        config.chain_id = self.chain_id;
+        config.account_model = self.account_model;
+        config.genesis = self.genesis_config()?;
+        config.mining.block_reward = self.consensus.block_reward;
+        config.emission = self.consensus.emission;
+        config.mining.target_block_time = self.consensus.target_block_time;
+        config.staking = self.consensus.staking.clone();
+        config.fee_market = self.consensus.fee_market;
+        Ok(config)
+    }
+}
+
+
+/// The genesis block a chain config describes. With a genesis timestamp set
+/// the same config always gives the same block.
+pub fn genesis_block(config: &ChainConfig) -> Result<Block> {
+    let genesis = &config.genesis;
+
+    let mut coinbase_tx = Transaction::new_coinbase(genesis.coinbase_recipient.clone(), genesis.genesis_reward, 0);
+    coinbase_tx.data = allocation_commitment(config)?;
+    if let Some(timestamp) = genesis.timestamp {
+        coinbase_tx.timestamp = Timestamp::from_unix_timestamp(timestamp);
+    }
+
+    let mut block = Block::new(BlockId::genesis(), vec![coinbase_tx], genesis.genesis_difficulty, 0, config.chain_id)?;
+    if let Some(timestamp) = genesis.timestamp {
+        block.header.timestamp = Timestamp::from_unix_timestamp(timestamp);
+    }
+
+    if config.mining.enable_mining {
+        info!("Mining genesis block...");
+        if !block.mine_with(config.validation_rules.block_hash, Some(config.mining.max_mining_iterations))? {
+            return Err(BlockchainError::InvalidBlock("Failed to mine genesis block".to_string()));
+        }
+        info!("Genesis block mined with nonce {}", block.header.nonce);
+    }
+
+    Ok(block)
+}
+
+
+// hash of every balance and validator set up at genesis; empty when there are none
+fn allocation_commitment(config: &ChainConfig) -> Result<Vec<u8>> {
+    let mut balances: BTreeMap<String, Amount> = config.genesis.initial_accounts.iter()
+        .map(|(address, balance)| (address.to_string(), *balance))
+        .collect();
+    if let Some(spec) = config.genesis.dev_accounts.as_ref().filter(|_| dev_accounts::funds_dev_accounts(config.network)) {
+        for account in spec.derive()? {
+            *balances.entry(account.address.to_string()).or_insert(0) += account.balance;
+        }
+    }
+    if balances.is_empty() && config.genesis.validators.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut material = String::new();
+    for (address, balance) in &balances {
+        material.push_str(&format!("balance {} {}\n", address, balance));
+    }
+    for validator in &config.genesis.validators {
+        material.push_str(&format!("validator {} {} {}\n", validator.address, validator.stake, validator.public_key.to_hex()));
+    }
+    Ok(sha256(material.as_bytes()).as_bytes().to_vec())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+
+    fn address() -> String {
+        public_key_to_address(generate_keypair().public_key(), AddressType::Base58).to_string()
+    }
+
+    fn file() -> GenesisFile {
+        GenesisFile {
+            chain_id: 7,
+            network: NetworkType::Testnet,
+            account_model: AccountModel::Hybrid,
+            timestamp: 1_700_000_000,
+            coinbase_recipient: address(),
+            genesis_reward: 50,
+            consensus: ConsensusParams::default(),
+            balances: BTreeMap::from([(address(), 1_000)]),
+            validators: Vec::new(),
+            dev_accounts: None,
+        }
+    }
+
+    fn config(file: &GenesisFile) -> ChainConfig {
+        let mut base = ChainConfig::default();
+        base.mining.enable_mining = false;
+        file.chain_config(base).unwrap()
+    }
+
+    #[test]
+    fn test_genesis_block_is_deterministic() {
+        let file = file();
+        let parsed = GenesisFile::from_json(&file.to_json().unwrap()).unwrap();
+        assert_eq!(parsed, file);
+
+        let a = genesis_block(&config(&file)).unwrap();
+        let b = genesis_block(&config(&parsed)).unwrap();
+        assert_eq!(a.id(), b.id());
+        assert_eq!(a.header.timestamp, Timestamp::from_unix_timestamp(file.timestamp));
+    }
+
+    #[test]
+    fn test_balances_change_genesis_hash() {
+        let file = file();
+        let mut other = file.clone();
+        other.balances.insert(address(), 1);
+
+        let a = genesis_block(&config(&file)).unwrap();
+        let b = genesis_block(&config(&other)).unwrap();
+        assert_ne!(a.id(), b.id());
+    }
+
+    #[test]
+    fn test_rejects_bad_validators() {
+        let keypair = generate_keypair();
+        let mut file = file();
+        file.validators.push(ValidatorEntry {
+            address: address(),
+            stake: 5_000_000,
+            public_key: keypair.public_key().to_hex(),
+        });
+        assert!(file.genesis_config().is_err(), "validators need staking params");
+
+        file.consensus.staking = Some(StakingParams::default());
+        assert_eq!(file.genesis_config().unwrap().validators.len(), 1);
+
+        file.validators[0].stake = 1;
+        assert!(file.genesis_config().is_err(), "stake below the minimum");
+
+        file.validators[0].stake = 5_000_000;
+        file.validators.push(file.validators[0].clone());
+        assert!(file.genesis_config().is_err(), "duplicate validator");
+    }
+}
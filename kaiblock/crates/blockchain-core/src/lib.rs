@@ -5,6 +5,32 @@ pub mod mempool;
 pub mod chain;
 pub mod types;
 pub mod validation;
+pub mod difficulty;
+pub mod store;
+pub mod trie_db;
+pub mod sync;
+pub mod dev_accounts;
+pub mod genesis;
+pub mod pow;
+pub mod logs;
+pub mod smt;
+pub mod events;
+pub mod light_client;
+pub mod weight;
+pub mod tx_dag;
+pub mod conflict;
+pub mod precheck;
+pub mod contract;
+pub mod receipt;
+pub mod timelock;
+pub mod codec;
+pub mod snapshot;
+pub mod emission;
+pub mod fee_market;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(any(test, feature = "testing"))]
+pub mod state_fuzz;
 
 use thiserror::Error;
 
@@ -46,18 +72,47 @@ pub enum BlockchainError {
     
     #[error("Validation error: {0}")]
     ValidationError(String),
+    
+    #[error("Storage error: {0}")]
+    StorageError(String),
 }
 
 pub type Result<T> = std::result::Result<T, BlockchainError>;
 
+impl From<blockchain_primitives::PrimitivesError> for BlockchainError {
+    fn from(e: blockchain_primitives::PrimitivesError) -> Self {
+        BlockchainError::CryptoError(e.into())
+    }
+}
+
 // Re-export commonly used types
-pub use block::{Block, BlockHeader, BlockBody};
-pub use transaction::{Transaction, TransactionInput, TransactionOutput, UTXO};
-pub use state::{AccountState, UTXOSet, WorldState};
-pub use mempool::{Mempool, TransactionPool};
-pub use chain::{Blockchain, ChainConfig};
+pub use block::{Block, BlockHeader, BlockBody, ExtraNonceJob};
+pub use transaction::{MultisigSignature, ScriptHashSpend, SigningDomain, Transaction, TransactionBuilder, TransactionInput, TransactionOutput, UTXO, TRANSFER_GAS};
+pub use state::{AccountProof, AccountState, BlockUndo, TxUndo, UTXOSet, WorldState, WorldStateSnapshot};
+pub use mempool::{Mempool, MempoolEvent, Revalidation, TransactionPool};
+pub use chain::{Blockchain, ChainConfig, ChainTree, ChainTreeNode, ChainTreeStatus};
 pub use types::*;
 pub use validation::{Validator, ValidationRules};
+pub use store::{ChainStore, MemoryChainStore};
+pub use trie_db::{NodeDatabase, PruningConfig, PruningMetrics};
+pub use sync::{SyncProgress, SyncStage, SyncStatus};
+pub use dev_accounts::{DevAccount, DevAccountSpec, DevAccountsConfig};
+pub use emission::EmissionSchedule;
+pub use fee_market::FeeMarketConfig;
+pub use genesis::{ConsensusParams, GenesisFile, GenesisValidator, StakingParams, ValidatorEntry, genesis_block};
+pub use pow::{CancelToken, MiningResult};
+pub use logs::{Log, LogBloom, LogEntry, LogFilter};
+pub use smt::{SmtProof, SparseMerkleTree};
+pub use events::{ChainEvent, TxDropReason};
+pub use weight::{BlockWeight, ResourceUsage, WeightParams};
+pub use tx_dag::TxDag;
+pub use conflict::ConflictProof;
+pub use precheck::{HeaderVerifier, check_body_commitment, precheck_block};
+pub use contract::{ContractReceipt, ContractRuntime, ContractStatus, contract_address};
+pub use receipt::{Receipt, ReceiptStatus};
+pub use timelock::{LockTime, check_time_locks};
+pub use snapshot::{SnapshotChunk, SnapshotEntry, SnapshotImport, SnapshotManifest, DEFAULT_ENTRIES_PER_CHUNK};
+pub use light_client::{DifficultyProof, DifficultySummary, HeaderChain, verify_transaction_inclusion};
 
 // Re-export crypto types for convenience
 pub use blockchain_crypto::{
@@ -0,0 +1,518 @@
+use crate::block::BlockHeader;
+use crate::chain::block_work;
+use crate::difficulty;
+use crate::types::*;
+use crate::validation::ValidationRules;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{MerkleMultiproof, MerkleProof, MerkleTree};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+
+/// Most retarget periods a single proof may span
+pub const MAX_PROOF_PERIODS: usize = 10_000;
+
+
+/// Difficulty chain summary between two checkpoints.
+///
+/// Difficulty only changes at retarget heights, and each retarget depends on
+/// three headers: the first of the closing period, its last, and the first of
+/// the new period. The proof carries those headers for every retarget between
+/// the checkpoints, so a light client can recompute each retarget and the total
+/// work without the headers in between.
+///
+/// Headers inside a period are not checked: the proof shows the difficulty
+/// evolution follows the rules and that every boundary header carries real
+/// work, not that the periods are fully populated. Use it to pick the heaviest
+/// claimed chain between trusted checkpoints, then fetch headers on demand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DifficultyProof {
+    /// boundary headers in ascending height order, starting with the start
+    /// checkpoint and ending with the end checkpoint
+    pub headers: Vec<BlockHeader>,
+}
+
+
+/// What a verified proof shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DifficultySummary {
+    pub start_height: BlockHeight,
+    pub end_height: BlockHeight,
+    /// (first height, difficulty) of every period the proof covers
+    pub periods: Vec<(BlockHeight, Difficulty)>,
+    /// work of the blocks after the start checkpoint up to and including the end
+    pub total_work: u128,
+}
+
+
+/// Heights whose headers a proof from `start` to `end` must carry
+pub fn proof_heights(start: BlockHeight, end: BlockHeight, rules: &ValidationRules) -> Result<Vec<BlockHeight>> {
+    let period = rules.difficulty_adjustment_period.max(1);
+    if start > end {
+        return Err(BlockchainError::ValidationError(format!("proof start {} is above end {}", start, end)));
+    }
+    if start % period != 0 {
+        return Err(BlockchainError::ValidationError(
+            format!("proof start {} is not a retarget boundary (period {})", start, period)
+        ));
+    }
+    if ((end - start) / period) as usize > MAX_PROOF_PERIODS {
+        return Err(BlockchainError::ValidationError(
+            format!("proof spans more than {} retarget periods", MAX_PROOF_PERIODS)
+        ));
+    }
+
+    let mut heights = vec![start];
+    let mut boundary = start + period;
+    while boundary <= end {
+        heights.push(boundary - 1);
+        heights.push(boundary);
+        boundary += period;
+    }
+    heights.push(end);
+    heights.dedup();
+    Ok(heights)
+}
+
+
+impl DifficultyProof {
+    /// Check the proof against two trusted checkpoints and the consensus rules.
+    pub fn verify(&self, start: &BlockId, end: &BlockId, rules: &ValidationRules) -> Result<DifficultySummary> {
+        let invalid = |reason: String| BlockchainError::ValidationError(format!("invalid difficulty proof: {}", reason));
+
+        let (first, last) = match (self.headers.first(), self.headers.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(invalid("no headers".to_string())),
+        };
+        if first.id() != *start {
+            return Err(invalid("does not start at the start checkpoint".to_string()));
+        }
+        if last.id() != *end {
+            return Err(invalid("does not end at the end checkpoint".to_string()));
+        }
+
+        let heights: Vec<BlockHeight> = self.headers.iter().map(|header| header.height).collect();
+        if heights != proof_heights(first.height, last.height, rules)? {
+            return Err(invalid("wrong set of boundary headers".to_string()));
+        }
+
+        let mut summary = DifficultySummary {
+            start_height: first.height,
+            end_height: last.height,
+            periods: vec![(first.height, first.difficulty)],
+            total_work: 0,
+        };
+        // first header of the period in progress: the retarget window start
+        let mut window_start = first;
+
+        for pair in self.headers.windows(2) {
+            let (prev, header) = (&pair[0], &pair[1]);
+
+            // the start checkpoint is trusted; every other header must carry its work
+            if !header.meets_difficulty_with(rules.block_hash) {
+                return Err(invalid(format!("header {} does not meet its difficulty", header.height)));
+            }
+            if header.height == prev.height + 1 && header.prev_block_hash != prev.id() {
+                return Err(invalid(format!("header {} does not link to {}", header.height, prev.height)));
+            }
+
+            let expected = if difficulty::is_retarget_height(header.height, rules) {
+                if header.height != prev.height + 1 {
+                    return Err(invalid(format!("missing header before retarget {}", header.height)));
+                }
+                let timespan = prev.timestamp.to_unix_timestamp() - window_start.timestamp.to_unix_timestamp();
+                difficulty::retarget(prev.difficulty, timespan, rules)
+            } else {
+                prev.difficulty
+            };
+            if header.difficulty != expected {
+                return Err(invalid(format!(
+                    "header {} has difficulty {}, expected {}", header.height, header.difficulty, expected
+                )));
+            }
+
+            // blocks after prev up to and including header all share header's difficulty,
+            // except across a retarget where prev closes the old period
+            summary.total_work = summary.total_work
+                .saturating_add(block_work(header.difficulty).saturating_mul((header.height - prev.height) as u128));
+
+            if difficulty::is_retarget_height(header.height, rules) {
+                window_start = header;
+                summary.periods.push((header.height, header.difficulty));
+            }
+        }
+
+        Ok(summary)
+    }
+}
+
+
+/// Check a Merkle proof against a header's transaction root; returns the id
+/// of the transaction it proves is in the block
+pub fn verify_transaction_inclusion(header: &BlockHeader, proof: &MerkleProof) -> Result<TxId> {
+    if proof.root != header.merkle_root {
+        return Err(BlockchainError::ValidationError(
+            format!("proof is for merkle root {}, block {} has {}", proof.root, header.height, header.merkle_root)
+        ));
+    }
+    if !MerkleTree::verify_proof(proof) {
+        return Err(BlockchainError::ValidationError("merkle proof does not reach its root".to_string()));
+    }
+    Ok(TxId::new(proof.leaf_hash))
+}
+
+
+/// Check a multiproof against a header's transaction root; returns the ids
+/// of the transactions it proves are in the block
+pub fn verify_transactions_inclusion(header: &BlockHeader, proof: &MerkleMultiproof) -> Result<Vec<TxId>> {
+    if proof.root != header.merkle_root {
+        return Err(BlockchainError::ValidationError(
+            format!("proof is for merkle root {}, block {} has {}", proof.root, header.height, header.merkle_root)
+        ));
+    }
+    if header.tx_count as usize != proof.leaf_count {
+        return Err(BlockchainError::ValidationError(
+            format!("proof is for {} transactions, block {} has {}", proof.leaf_count, header.height, header.tx_count)
+        ));
+    }
+    if !MerkleTree::verify_multiproof(proof) {
+        return Err(BlockchainError::ValidationError("merkle multiproof does not reach its root".to_string()));
+    }
+    Ok(proof.leaves.iter().map(|(_, leaf_hash)| TxId::new(*leaf_hash)).collect())
+}
+
+
+/// Headers-only chain for light clients.
+///
+/// Starts from a trusted header (genesis or a checkpoint) and accepts headers
+/// that link to a known header and pass the header checks a full node makes:
+/// height, chain id, timestamp, proof of work and difficulty retargeting. The
+/// branch with the most work is the main chain. Transactions are verified
+/// against main chain headers with Merkle proofs, so payments can be checked
+/// without downloading blocks.
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    rules: ValidationRules,
+    root: BlockId,
+    headers: HashMap<BlockId, BlockHeader>,
+    /// work of the branch ending at each header, counted from the root
+    chain_work: HashMap<BlockId, u128>,
+    main_chain: HashMap<BlockHeight, BlockId>,
+    tip: BlockId,
+}
+
+impl HeaderChain {
+    /// Chain rooted at a trusted header. Retargets whose window starts before
+    /// the root can't be checked and are accepted as claimed, so the root
+    /// should sit on a retarget boundary.
+    pub fn new(trusted: BlockHeader, rules: ValidationRules) -> Self {
+        let root = trusted.id();
+        Self {
+            rules,
+            root,
+            main_chain: HashMap::from([(trusted.height, root)]),
+            chain_work: HashMap::from([(root, 0)]),
+            headers: HashMap::from([(root, trusted)]),
+            tip: root,
+        }
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        &self.headers[&self.tip]
+    }
+
+    pub fn height(&self) -> BlockHeight {
+        self.tip().height
+    }
+
+    /// Work of the main chain since the root
+    pub fn total_work(&self) -> u128 {
+        self.chain_work[&self.tip]
+    }
+
+    pub fn get_header(&self, block_id: &BlockId) -> Option<&BlockHeader> {
+        self.headers.get(block_id)
+    }
+
+    /// Main chain header at a height
+    pub fn header_at(&self, height: BlockHeight) -> Option<&BlockHeader> {
+        self.main_chain.get(&height).and_then(|block_id| self.headers.get(block_id))
+    }
+
+    pub fn is_main_chain(&self, block_id: &BlockId) -> bool {
+        self.headers.get(block_id)
+            .is_some_and(|header| self.main_chain.get(&header.height) == Some(block_id))
+    }
+
+    /// Main chain blocks on top of `block_id`, counting itself (None if it
+    /// isn't on the main chain)
+    pub fn confirmations(&self, block_id: &BlockId) -> Option<BlockHeight> {
+        if !self.is_main_chain(block_id) {
+            return None;
+        }
+        Some(self.height() - self.headers[block_id].height + 1)
+    }
+
+    /// Validate and store a header. Returns whether it became the new tip.
+    pub fn add_header(&mut self, header: BlockHeader) -> Result<bool> {
+        let block_id = header.id();
+        if self.headers.contains_key(&block_id) {
+            return Ok(false);
+        }
+
+        self.validate_header(&header)?;
+
+        let work = self.chain_work[&header.prev_block_hash].saturating_add(block_work(header.difficulty));
+        self.headers.insert(block_id, header);
+        self.chain_work.insert(block_id, work);
+
+        if work <= self.total_work() {
+            return Ok(false);
+        }
+        self.set_tip(block_id);
+        Ok(true)
+    }
+
+    /// Add headers in order, stopping at the first invalid one. Returns how
+    /// many were new.
+    pub fn add_headers(&mut self, headers: impl IntoIterator<Item = BlockHeader>) -> Result<usize> {
+        let mut added = 0;
+        for header in headers {
+            let block_id = header.id();
+            let known = self.headers.contains_key(&block_id);
+            self.add_header(header)?;
+            if !known {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Verify that a transaction is in a main chain block; returns its id
+    pub fn verify_transaction(&self, block_id: &BlockId, proof: &MerkleProof) -> Result<TxId> {
+        if !self.is_main_chain(block_id) {
+            return Err(BlockchainError::BlockNotFound(format!("{} is not on the main header chain", block_id)));
+        }
+        verify_transaction_inclusion(&self.headers[block_id], proof)
+    }
+
+    /// Verify that several transactions are in a main chain block; returns their ids
+    pub fn verify_transactions(&self, block_id: &BlockId, proof: &MerkleMultiproof) -> Result<Vec<TxId>> {
+        if !self.is_main_chain(block_id) {
+            return Err(BlockchainError::BlockNotFound(format!("{} is not on the main header chain", block_id)));
+        }
+        verify_transactions_inclusion(&self.headers[block_id], proof)
+    }
+
+    fn validate_header(&self, header: &BlockHeader) -> Result<()> {
+        let invalid = |reason: String| BlockchainError::InvalidBlock(format!("header {}: {}", header.height, reason));
+
+        let parent = self.headers.get(&header.prev_block_hash)
+            .ok_or_else(|| invalid(format!("unknown parent {}", header.prev_block_hash)))?;
+
+        if header.height != parent.height + 1 {
+            return Err(invalid(format!("height does not follow parent {}", parent.height)));
+        }
+        if header.chain_id != parent.chain_id {
+            return Err(invalid(format!("chain id {} does not match {}", header.chain_id, parent.chain_id)));
+        }
+
+        let timestamp = header.timestamp.to_unix_timestamp();
+        if timestamp <= parent.timestamp.to_unix_timestamp() {
+            return Err(invalid("timestamp must be greater than the parent's".to_string()));
+        }
+        if timestamp > chrono::Utc::now().timestamp() + self.rules.max_block_time_drift {
+            return Err(invalid("timestamp too far in the future".to_string()));
+        }
+
+        if !header.meets_difficulty_with(self.rules.block_hash) {
+            return Err(invalid(format!("does not meet difficulty {}", header.difficulty)));
+        }
+
+        if let Some(expected) = self.expected_difficulty(parent) {
+            if header.difficulty != expected {
+                return Err(invalid(format!("difficulty {}, expected {}", header.difficulty, expected)));
+            }
+        }
+        Ok(())
+    }
+
+    // Difficulty of the header after `parent`, or None when its retarget window
+    // starts before the root
+    fn expected_difficulty(&self, parent: &BlockHeader) -> Option<Difficulty> {
+        let height = parent.height + 1;
+        if !difficulty::is_retarget_height(height, &self.rules) {
+            return Some(parent.difficulty);
+        }
+
+        let start_height = difficulty::retarget_window_start(height, &self.rules);
+        let mut cursor = parent;
+        while cursor.height > start_height {
+            cursor = self.headers.get(&cursor.prev_block_hash)?;
+        }
+        difficulty::next_difficulty_for_header(parent, Some(cursor), &self.rules).ok()
+    }
+
+    // Make `tip` the head of the main chain, rewriting heights back to the fork point
+    fn set_tip(&mut self, tip: BlockId) {
+        let old_height = self.height();
+        let mut cursor = tip;
+        loop {
+            let header = &self.headers[&cursor];
+            if self.main_chain.get(&header.height) == Some(&cursor) || cursor == self.root {
+                break;
+            }
+            self.main_chain.insert(header.height, cursor);
+            cursor = header.prev_block_hash;
+        }
+
+        let new_height = self.headers[&tip].height;
+        for height in (new_height + 1)..=old_height {
+            self.main_chain.remove(&height);
+        }
+        self.tip = tip;
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::chain::{Blockchain, ChainConfig};
+    use crate::transaction::Transaction;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+
+    fn chain(blocks: usize) -> Blockchain {
+        let mut config = ChainConfig::default();
+        config.validation_rules.difficulty_adjustment_period = 2;
+        let mut blockchain = Blockchain::new(config).unwrap();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        for _ in 0..blocks {
+            blockchain.mine_block(miner).unwrap();
+        }
+        blockchain
+    }
+
+    fn id_at(blockchain: &Blockchain, height: BlockHeight) -> BlockId {
+        blockchain.get_block_by_height(&height).unwrap().id()
+    }
+
+    #[test]
+    fn test_proof_heights() {
+        let rules = ValidationRules { difficulty_adjustment_period: 10, ..ValidationRules::default() };
+        assert_eq!(proof_heights(0, 25, &rules).unwrap(), vec![0, 9, 10, 19, 20, 25]);
+        assert_eq!(proof_heights(10, 10, &rules).unwrap(), vec![10]);
+        assert!(proof_heights(5, 25, &rules).is_err());
+    }
+
+    #[test]
+    fn test_difficulty_proof_verifies() {
+        let blockchain = chain(5);
+        let rules = blockchain.validation_rules().clone();
+        let proof = blockchain.prove_difficulty(0, 5).unwrap();
+
+        let summary = proof.verify(&id_at(&blockchain, 0), &id_at(&blockchain, 5), &rules).unwrap();
+        assert_eq!(summary.end_height, 5);
+        assert_eq!(summary.periods.len(), 3);
+
+        let expected_work: u128 = (1..=5)
+            .map(|height| block_work(blockchain.get_block_by_height(&height).unwrap().header.difficulty))
+            .sum();
+        assert_eq!(summary.total_work, expected_work);
+    }
+
+    #[test]
+    fn test_difficulty_proof_rejects_tampering() {
+        let blockchain = chain(4);
+        let rules = blockchain.validation_rules().clone();
+        let (start, end) = (id_at(&blockchain, 0), id_at(&blockchain, 4));
+
+        // a rewritten timestamp breaks the link to the next header
+        let mut proof = blockchain.prove_difficulty(0, 4).unwrap();
+        proof.headers[1].timestamp = Timestamp::from_unix_timestamp(0);
+        assert!(proof.verify(&start, &end, &rules).is_err());
+
+        // dropping a boundary header
+        let mut proof = blockchain.prove_difficulty(0, 4).unwrap();
+        proof.headers.remove(1);
+        assert!(proof.verify(&start, &end, &rules).is_err());
+
+        // wrong end checkpoint
+        let proof = blockchain.prove_difficulty(0, 4).unwrap();
+        assert!(proof.verify(&start, &id_at(&blockchain, 3), &rules).is_err());
+    }
+
+    fn header_chain(blockchain: &Blockchain) -> HeaderChain {
+        let genesis = blockchain.get_block_by_height(&0).unwrap().header.clone();
+        let mut headers = HeaderChain::new(genesis, blockchain.validation_rules().clone());
+        let tip = blockchain.height();
+        let added = headers
+            .add_headers((1..=tip).map(|height| blockchain.get_block_by_height(&height).unwrap().header.clone()))
+            .unwrap();
+        assert_eq!(added as BlockHeight, tip);
+        headers
+    }
+
+    #[test]
+    fn test_header_chain_follows_blocks() {
+        let blockchain = chain(5);
+        let headers = header_chain(&blockchain);
+
+        assert_eq!(headers.height(), 5);
+        assert_eq!(headers.tip().id(), id_at(&blockchain, 5));
+        assert_eq!(headers.confirmations(&id_at(&blockchain, 3)), Some(3));
+
+        // a header moved onto another parent no longer carries its proof of work
+        let mut forged = blockchain.get_block_by_height(&5).unwrap().header.clone();
+        forged.prev_block_hash = id_at(&blockchain, 3);
+        forged.height = 4;
+        let mut rejecting = headers.clone();
+        assert!(rejecting.add_header(forged).is_err());
+        assert_eq!(rejecting.height(), 5);
+    }
+
+    #[test]
+    fn test_verify_transaction_inclusion() {
+        let blockchain = chain(3);
+        let headers = header_chain(&blockchain);
+        let block = blockchain.get_block_by_height(&2).unwrap();
+        let coinbase = block.transactions()[0].id();
+
+        let proof = block.transaction_proof(&coinbase).unwrap().unwrap();
+        assert_eq!(headers.verify_transaction(&block.id(), &proof).unwrap(), coinbase);
+
+        // the proof doesn't hold against another block's header
+        assert!(headers.verify_transaction(&id_at(&blockchain, 1), &proof).is_err());
+
+        let mut tampered = proof.clone();
+        tampered.leaf_hash = Hash256::zero();
+        assert!(verify_transaction_inclusion(&block.header, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_verify_transactions_inclusion() {
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let transactions: Vec<Transaction> = (1..=7).map(|height| Transaction::new_coinbase(miner, 50, height)).collect();
+        let block = Block::new(BlockId::genesis(), transactions, 0, 1, 1).unwrap();
+        let ids: Vec<TxId> = [5, 1, 2].iter().map(|&i| block.transactions()[i].id()).collect();
+
+        let proof = block.transactions_multiproof(&ids).unwrap().unwrap();
+        let mut proven = verify_transactions_inclusion(&block.header, &proof).unwrap();
+        proven.sort_by_key(|id| ids.iter().position(|other| other == id));
+        assert_eq!(proven, ids);
+
+        let stranger = Transaction::new_coinbase(miner, 50, 99).id();
+        assert!(block.transactions_multiproof(&[ids[0], stranger]).unwrap().is_none());
+
+        // the proof has to describe a tree of the block's size
+        let mut resized = proof.clone();
+        resized.leaf_count = 8;
+        assert!(verify_transactions_inclusion(&block.header, &resized).is_err());
+
+        let mut tampered = proof;
+        tampered.hashes[0] = Hash256::zero();
+        assert!(verify_transactions_inclusion(&block.header, &tampered).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+use crate::block::Block;
+use crate::transaction::Transaction;
+use crate::types::*;
+use blockchain_crypto::{hash::sha256, Address, Hash256};
+use runtime::{LogKind, ProgramLog};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+
+/// Size of a log bloom filter in bytes (2048 bits)
+pub const BLOOM_BYTES: usize = 256;
+
+/// Widest block range a single log query may scan
+pub const MAX_LOG_QUERY_RANGE: BlockHeight = 10_000;
+
+
+/// Topic of the log emitted by account transfers
+pub fn transfer_topic() -> Hash256 {
+    sha256(b"Transfer(address,address,uint64)")
+}
+
+/// Topic of the log emitted by contract calls
+pub fn call_topic() -> Hash256 {
+    sha256(b"Call(address,bytes4)")
+}
+
+/// Topic of the log emitted by contract deployments
+pub fn deploy_topic() -> Hash256 {
+    sha256(b"Deploy(address)")
+}
+
+/// Topic of a message a contract logged, with the text as data
+pub fn message_topic() -> Hash256 {
+    sha256(b"Message(string)")
+}
+
+/// Topic identifying an address, for filtering on senders and recipients
+pub fn address_topic(address: &Address) -> Hash256 {
+    sha256(address.data())
+}
+
+
+/// An event emitted by a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Log {
+    /// account the event is about (sender of a transfer, called contract)
+    pub address: Address,
+    /// indexed topics, the first names the event
+    pub topics: Vec<Hash256>,
+    /// unindexed payload
+    pub data: Vec<u8>,
+}
+
+impl Log {
+    /// Logs emitted by an account or contract transaction.
+    ///
+    /// UTXO and coinbase transactions emit none.
+    pub fn from_transaction(tx: &Transaction) -> Vec<Log> {
+        let from = match tx.from {
+            Some(from) if !tx.is_coinbase() => from,
+            _ => return Vec::new(),
+        };
+
+        match (tx.tx_type, tx.to) {
+            (TransactionType::Transfer, Some(to)) => vec![Log {
+                address: from,
+                topics: vec![transfer_topic(), address_topic(&from), address_topic(&to)],
+                data: tx.amount.unwrap_or(0).to_be_bytes().to_vec(),
+            }],
+            (TransactionType::ContractCall, Some(contract)) => {
+                // 4-byte selector, left-aligned and zero-padded
+                let mut selector = [0u8; 32];
+                let len = tx.data.len().min(4);
+                selector[..len].copy_from_slice(&tx.data[..len]);
+
+                vec![Log {
+                    address: contract,
+                    topics: vec![call_topic(), address_topic(&from), Hash256::from_bytes(selector)],
+                    data: tx.amount.unwrap_or(0).to_be_bytes().to_vec(),
+                }]
+            }
+            (TransactionType::ContractDeployment, _) => vec![Log {
+                address: from,
+                topics: vec![deploy_topic(), address_topic(&from)],
+                data: Vec::new(),
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    /// A log `contract` recorded while it ran. Events keep their topic;
+    /// messages are logged under [`message_topic`].
+    pub fn from_program(contract: &Address, log: &ProgramLog) -> Log {
+        let (topic, data) = match &log.kind {
+            LogKind::Message(message) => (message_topic(), message.as_bytes().to_vec()),
+            LogKind::Event { topic, data } => (Hash256::from_bytes(*topic), data.clone()),
+        };
+        Log { address: contract.clone(), topics: vec![topic], data }
+    }
+}
+
+
+/// 2048-bit bloom filter over log addresses and topics.
+///
+/// Each item sets three bits taken from its sha256. A clear bit proves the item
+/// is absent; set bits only mean it may be present.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LogBloom([u8; BLOOM_BYTES]);
+
+impl LogBloom {
+    pub fn new() -> Self {
+        Self([0u8; BLOOM_BYTES])
+    }
+
+    /// Bloom of every log the transactions emit
+    pub fn from_transactions<'a>(transactions: impl IntoIterator<Item = &'a Transaction>) -> Self {
+        let mut bloom = Self::new();
+        for tx in transactions {
+            for log in Log::from_transaction(tx) {
+                bloom.accrue_log(&log);
+            }
+        }
+        bloom
+    }
+
+    pub fn accrue(&mut self, item: &[u8]) {
+        for (byte, mask) in Self::bits(item) {
+            self.0[byte] |= mask;
+        }
+    }
+
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(log.address.data());
+        for topic in &log.topics {
+            self.accrue(topic.as_bytes());
+        }
+    }
+
+    /// False means `item` is definitely not in the filter
+    pub fn may_contain(&self, item: &[u8]) -> bool {
+        Self::bits(item).iter().all(|&(byte, mask)| self.0[byte] & mask != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&byte| byte == 0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; BLOOM_BYTES] {
+        &self.0
+    }
+
+    pub fn from_slice(slice: &[u8]) -> Option<Self> {
+        let bytes: [u8; BLOOM_BYTES] = slice.try_into().ok()?;
+        Some(Self(bytes))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    // (byte index, bit mask) for the three bits of `item`, bit 0 being the
+    // lowest bit of the last byte
+    fn bits(item: &[u8]) -> [(usize, u8); 3] {
+        let hash = sha256(item);
+        let hash = hash.as_bytes();
+        let mut bits = [(0, 0); 3];
+        for (i, bit) in bits.iter_mut().enumerate() {
+            let index = (u16::from_be_bytes([hash[2 * i], hash[2 * i + 1]]) & 0x07ff) as usize;
+            *bit = (BLOOM_BYTES - 1 - index / 8, 1u8 << (index % 8));
+        }
+        bits
+    }
+}
+
+impl Default for LogBloom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for LogBloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LogBloom({})", self.to_hex())
+    }
+}
+
+impl Serialize for LogBloom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for LogBloom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let bytes: Vec<u8> = serde::de::Deserialize::deserialize(deserializer)?;
+        Self::from_slice(&bytes)
+            .ok_or_else(|| de::Error::invalid_length(bytes.len(), &"256 bytes"))
+    }
+}
+
+
+/// Which logs a query wants. Empty lists match anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogFilter {
+    pub from_height: BlockHeight,
+    pub to_height: BlockHeight,
+    /// emitting addresses, any of which matches
+    pub addresses: Vec<Address>,
+    /// per-position alternatives; an empty position matches any topic
+    pub topics: Vec<Vec<Hash256>>,
+}
+
+impl LogFilter {
+    /// Whether a block with this bloom can contain a matching log
+    pub fn may_match(&self, bloom: &LogBloom) -> bool {
+        let address_ok = self.addresses.is_empty()
+            || self.addresses.iter().any(|address| bloom.may_contain(address.data()));
+
+        address_ok && self.topics.iter().all(|alternatives| {
+            alternatives.is_empty() || alternatives.iter().any(|topic| bloom.may_contain(topic.as_bytes()))
+        })
+    }
+
+    pub fn matches(&self, log: &Log) -> bool {
+        if !self.addresses.is_empty() && !self.addresses.contains(&log.address) {
+            return false;
+        }
+
+        self.topics.iter().enumerate().all(|(position, alternatives)| {
+            alternatives.is_empty()
+                || log.topics.get(position).is_some_and(|topic| alternatives.contains(topic))
+        })
+    }
+}
+
+
+/// A log with where it was emitted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub log: Log,
+    pub block_id: BlockId,
+    pub block_height: BlockHeight,
+    pub tx_id: TxId,
+    /// position of the transaction in the block
+    pub tx_index: usize,
+    /// position of the log in the block
+    pub log_index: usize,
+}
+
+
+/// Logs in `block` that match `filter`. `program_logs` holds what the
+/// contracts each transaction ran logged, from its receipt, and follows the
+/// transaction's own logs.
+///
+/// The header bloom only covers what the transactions themselves emit, since
+/// contract logs are only known once they have run, so the block is skipped
+/// outright only when the bloom rules a match out and no contract logged.
+pub fn block_logs(block: &Block, program_logs: &[Vec<Log>], filter: &LogFilter) -> Vec<LogEntry> {
+    if !filter.may_match(&block.header.logs_bloom) && program_logs.iter().all(Vec::is_empty) {
+        return Vec::new();
+    }
+
+    let mut entries = Vec::new();
+    let mut log_index = 0;
+    for (tx_index, tx) in block.transactions().iter().enumerate() {
+        let logged = program_logs.get(tx_index).into_iter().flatten().cloned();
+        for log in Log::from_transaction(tx).into_iter().chain(logged) {
+            if filter.matches(&log) {
+                entries.push(LogEntry {
+                    log,
+                    block_id: block.id(),
+                    block_height: block.header.height,
+                    tx_id: tx.id(),
+                    tx_index,
+                    log_index,
+                });
+            }
+            log_index += 1;
+        }
+    }
+    entries
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+
+    fn address() -> Address {
+        public_key_to_address(generate_keypair().public_key(), AddressType::Base58)
+    }
+
+    #[test]
+    fn test_transfer_emits_log() {
+        let (from, to) = (address(), address());
+        let tx = Transaction::new_account(from, to, 500, 0, 21000, 20, vec![]);
+
+        let logs = Log::from_transaction(&tx);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].address, from);
+        assert_eq!(logs[0].topics, vec![transfer_topic(), address_topic(&from), address_topic(&to)]);
+        assert_eq!(logs[0].data, 500u64.to_be_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_bloom_has_no_false_negatives() {
+        let (from, to, other) = (address(), address(), address());
+        let tx = Transaction::new_account(from, to, 1, 0, 21000, 20, vec![]);
+        let bloom = LogBloom::from_transactions([&tx]);
+
+        assert!(bloom.may_contain(from.data()));
+        assert!(bloom.may_contain(transfer_topic().as_bytes()));
+        assert!(bloom.may_contain(address_topic(&to).as_bytes()));
+        assert!(!LogBloom::new().may_contain(other.data()));
+    }
+
+    #[test]
+    fn test_filter_matches_positions() {
+        let (from, to) = (address(), address());
+        let tx = Transaction::new_account(from, to, 1, 0, 21000, 20, vec![]);
+        let log = &Log::from_transaction(&tx)[0];
+
+        let by_recipient = LogFilter {
+            topics: vec![vec![transfer_topic()], vec![], vec![address_topic(&to)]],
+            ..Default::default()
+        };
+        assert!(by_recipient.matches(log));
+
+        // Recipient in the sender position doesn't match
+        let wrong_position = LogFilter {
+            topics: vec![vec![], vec![address_topic(&to)]],
+            ..Default::default()
+        };
+        assert!(!wrong_position.matches(log));
+
+        let by_address = LogFilter { addresses: vec![to], ..Default::default() };
+        assert!(!by_address.matches(log));
+    }
+
+    #[test]
+    fn test_block_logs_include_contract_logs() {
+        let (from, contract) = (address(), address());
+        let tx = Transaction::new_account(from, contract.clone(), 1, 0, 21000, 20, vec![]);
+        let block = Block::new(BlockId::new(Hash256::zero()), vec![tx], 1, 1, 1).unwrap();
+
+        let topic = [7u8; 32];
+        let logged = vec![vec![
+            Log::from_program(&contract, &ProgramLog { instruction: 0, program: [0u8; 32], kind: LogKind::Message("hi".into()) }),
+            Log::from_program(&contract, &ProgramLog { instruction: 0, program: [0u8; 32], kind: LogKind::Event { topic, data: vec![1] } }),
+        ]];
+        assert_eq!(logged[0][0].topics, vec![message_topic()]);
+        assert_eq!(logged[0][0].data, b"hi".to_vec());
+
+        // the header bloom doesn't know the event, but the contract logged
+        let filter = LogFilter { topics: vec![vec![Hash256::from_bytes(topic)]], ..Default::default() };
+        assert!(!filter.may_match(&block.header.logs_bloom));
+        let entries = block_logs(&block, &logged, &filter);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].log.address, contract);
+        assert_eq!(entries[0].log.data, vec![1]);
+        assert_eq!(entries[0].log_index, 2, "after the transfer log and the message");
+
+        assert!(block_logs(&block, &[], &filter).is_empty());
+    }
+
+    #[test]
+    fn test_bloom_serialization_roundtrip() {
+        let mut bloom = LogBloom::new();
+        bloom.accrue(b"item");
+
+        let bytes = bincode::serialize(&bloom).unwrap();
+        assert_eq!(bincode::deserialize::<LogBloom>(&bytes).unwrap(), bloom);
+    }
+}
@@ -1,12 +1,19 @@
 use crate::types::*;
 use crate::transaction::Transaction;
-use crate::state::WorldState;
+use crate::state::{UTXOSet, WorldState};
+use crate::weight::{BlockWeight, ResourceUsage};
+use crate::tx_dag::TxDag;
+use crate::conflict::ConflictProof;
+use crate::timelock::check_time_locks;
+use crate::transaction::UTXO;
 use crate::{BlockchainError, Result};
 use blockchain_crypto::Address;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, BinaryHeap, HashSet};
+use std::collections::{BTreeMap, HashMap, BinaryHeap, HashSet, VecDeque};
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use chrono::{DateTime, Utc, Duration};
+use tracing::{debug, debug_span};
 
 
 ///Transaction with priority information for mempool ordering
@@ -16,6 +23,8 @@ pub struct PrioritizedTransaction {
     pub transaction: Transaction,
     /// Fee per byte for prioritization
     pub fee_per_byte: u64,
+    /// Fee per requested compute unit (runtime transactions only)
+    pub fee_per_compute_unit: u64,
     ///time when transaction was added to mempool
     pub added_time: DateTime<Utc>,
     ///Number of confirmations required
@@ -24,9 +33,11 @@ pub struct PrioritizedTransaction {
 
 impl PrioritizedTransaction{
     pub fn new(transaction: Transaction) -> Self {
+        let fee_per_compute_unit = transaction.fee_per_compute_unit();
         Self{
             transaction,
             fee_per_byte,
+            fee_per_compute_unit,
             added_time: Utc::now(),
             confirmation_needed: 1,
         }
@@ -66,6 +77,13 @@ pub struct MempoolConfig {
     pub min_fee_per_byte: u64,
     ///maximum transaction size in bytes
     pub max_transaction_size: usize,
+    ///percent by which a replacement must beat the fee per byte of every
+    ///transaction it replaces
+    pub min_replacement_fee_bump: u64,
+    ///most transactions a single replacement may evict
+    pub max_replacements: usize,
+    ///longest chain of unconfirmed ancestors a transaction may have
+    pub max_dependency_depth: usize,
 }
 
 
@@ -76,11 +94,56 @@ impl Default for MempoolConfig{
             max_memory: 100 *1024 * 1024, //100MB
             max_age: Duration::hours(24),
             min_fee_per_byte: 1;
-            max_transaction_size: 1024 * 1024 //1MB
+            max_transaction_size: 1024 * 1024, //1MB
+            min_replacement_fee_bump: 10,
+            max_replacements: 100,
+            max_dependency_depth: 25,
         }
     }
 }
 
+/// Events not yet drained are capped at this many; the oldest are dropped first
+const MAX_PENDING_EVENTS: usize = 1024;
+
+
+/// Mempool changes that subscribers may want to hear about
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MempoolEvent {
+    /// `replaced` was evicted in favour of `replacement`, which conflicts
+    /// with it and pays a higher fee (replace-by-fee)
+    Replaced {
+        replaced: TxId,
+        replacement: TxId,
+        old_fee_per_byte: u64,
+        new_fee_per_byte: u64,
+    },
+    /// dropped for exceeding the maximum age
+    Expired { tx_id: TxId },
+    /// dropped as the lowest priority transaction of a full pool
+    Evicted { tx_id: TxId },
+    /// dropped because a confirmed transaction used its sender nonce
+    NonceUsed { tx_id: TxId },
+    /// a transaction arrived spending an output a pooled one already spends,
+    /// whether or not it went on to replace it
+    DoubleSpend(ConflictProof),
+    /// dropped when revalidated after the chain moved: its inputs were spent
+    /// or disconnected, or its sender can no longer pay for it
+    Invalidated { tx_id: TxId },
+}
+
+
+/// Outcome of revalidating the pool after blocks were connected or disconnected
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Revalidation {
+    /// transactions of disconnected blocks put back in the pool
+    pub reinserted: Vec<TxId>,
+    /// transactions of disconnected blocks no longer valid
+    pub rejected: Vec<TxId>,
+    /// pooled transactions dropped as no longer valid
+    pub dropped: Vec<TxId>,
+}
+
+
 ///transactiion pool(mempool) for pending transactions
 #[derive(Debug, Clone)]
 pub struct TransactionPool {
@@ -90,10 +153,16 @@ pub struct TransactionPool {
     transactions: HashMap<TxId, PrioritizedTransaction>,
     ///transactions by sender address(for nonce validation)
     by_sender: HashMap<Address, Vec<TxId>>,
+    ///the one pooled transaction holding each sender nonce
+    pending_nonces: HashMap<Address, BTreeMap<Nonce, TxId>>,
     ///set of spent outpoints to prevent double spending
     spent_outpoints: HashSet<OutPoint>,
     ///curren memory usage
     memory_usage: usize,
+    ///dependency edges between pooled transactions (spender -> parent)
+    dag: TxDag,
+    ///events waiting to be drained by the node
+    events: VecDeque<MempoolEvent>,
     //Configuration
     conig: MempoolConfig,
 }
@@ -107,18 +176,25 @@ impl TransactionPool{
             priority_queue: BinaryHeap::new(),
             transactions: HashMap::new(),
             by_sender: HashMap::new(),
+            pending_nonces: HashMap::new(),
             spent_outpoints: HashSet::new(),
             memory_usage: 0,
+            dag: TxDag::new(),
+            events: VecDeque::new(),
             config,
         }
     }
 
+    ///add a transaction. one that conflicts with pooled transactions (spends the
+    ///same outpoint, or reuses a sender nonce) replaces them if it pays enough
+    ///more, and is rejected otherwise
     pub fn add_transaction(
         &mut self,
         transaction: Transaction,
         world_state: &WorldState,
         ) -> Result<TxId> {
         let tx_id = transaction.id();
+        let _span = debug_span!("mempool_add", tx = %tx_id).entered();
 
         //check if transaction already exists
         if self.transactions.contains_key(&tx_id){
@@ -130,14 +206,43 @@ impl TransactionPool{
         //validate transaction
         self.validate_transaction(&transaction, world_state)?;
 
-        //check mempool limits
-        self.check_limits(&transaction)?;
+        let prioritized_tx = PrioritizedTransaction::new(transaction.clone());
 
+        //check for conflicts(double spending), which may be replaced by fee;
+        //either way the attempt is reported, even if the replacement is refused
+        let conflicts = self.find_conflicts(&transaction);
+        for conflict in &conflicts {
+            if let Some(proof) = self.get_transaction(conflict).and_then(|pooled| ConflictProof::between(pooled, &transaction)) {
+                self.push_event(MempoolEvent::DoubleSpend(proof));
+            }
+        }
+        let evicted = self.replacement_evictions(&conflicts);
+        self.check_replacement(&prioritized_tx, &conflicts, &evicted)?;
 
-        let prioritized_tx = PrioritizedTransaction::new(transaction.clone());
+        //check mempool limits, counting the space the replaced transactions free
+        self.check_limits(&transaction, &evicted)?;
 
-        //check for conflicts(double spending)
-        self.check_conflicts(&transaction)?;
+        //the pooled transactions it spends from or follows in nonce order
+        let parents: HashSet<TxId> = self.find_parents(&transaction).into_iter()
+            .filter(|parent| !conflicts.contains(parent))
+            .collect();
+        self.dag.check_insert(&tx_id, &parents, self.config.max_dependency_depth)?;
+
+        for conflict in &conflicts {
+            if let Some(replaced) = self.transactions.get(conflict) {
+                let event = MempoolEvent::Replaced {
+                    replaced: *conflict,
+                    replacement: tx_id,
+                    old_fee_per_byte: replaced.fee_per_byte,
+                    new_fee_per_byte: prioritized_tx.fee_per_byte,
+                };
+                //children of a replaced transaction spend outputs that no longer exist
+                for descendant in self.remove_with_descendants(conflict) {
+                    self.push_event(MempoolEvent::Evicted { tx_id: descendant });
+                }
+                self.push_event(event);
+            }
+        }
 
         //add spent outpoints to conflict detection
         for input in &transaction.inputs {
@@ -150,6 +255,9 @@ impl TransactionPool{
                 .or_insert_with(Vec::new)
                 .push(tx_id);
         }
+        if let (Some(from), Some(nonce)) = (transaction.from, transaction.nonce) {
+            self.pending_nonces.entry(from).or_default().insert(nonce, tx_id);
+        }
 
         self.memory_usage += transaction.size();
 
@@ -157,11 +265,13 @@ impl TransactionPool{
         //add to collections
         self.priority_queue.push(prioritized_tx.clone());
         self.transactions.insert(tx_id, prioritized_tx);
+        self.dag.insert(tx_id, parents, self.config.max_dependency_depth)?;
 
 
         //evict old transactions if needed
         self.evict_if_needed()?;
 
+        debug!(size = self.transactions.len(), "added to mempool");
         Ok(tx_id)
     }
 
@@ -175,6 +285,9 @@ impl TransactionPool{
             //update memory usage
             self.memory_usage = self.memory_usage.saturating_sub(transaction.size());
 
+            //children stay, now depending on one less unconfirmed parent
+            self.dag.remove(tx_id);
+
             //remove spent outpoints
             for input in &transaction.inputs {
                 self.spent_outpoints.remove(&input.prev_output);
@@ -191,6 +304,16 @@ impl TransactionPool{
                     }
                 }
             }
+            if let (Some(from), Some(nonce)) = (transaction.from, transaction.nonce) {
+                if let Some(nonces) = self.pending_nonces.get_mut(&from) {
+                    if nonces.get(&nonce) == Some(tx_id) {
+                        nonces.remove(&nonce);
+                    }
+                    if nonces.is_empty() {
+                        self.pending_nonces.remove(&from);
+                    }
+                }
+            }
 
          // Rebuild priority queue (expensive but necessary)
             self.rebuild_priority_queue();
@@ -205,70 +328,191 @@ impl TransactionPool{
     pub fn get_transaction(&self, tx_id: &TxId) -> Option<&Transaction> {
         self.transactions.get(tx_id).map(|ptx| &ptx.transaction)
     }
+
+    /// Remove a transaction that is no longer valid along with everything
+    /// depending on it; returns the descendants removed (not `tx_id` itself)
+    pub fn remove_with_descendants(&mut self, tx_id: &TxId) -> Vec<TxId> {
+        let descendants = self.dag.descendants(tx_id);
+        self.remove_transaction(tx_id);
+        // children first, so none is left pointing at a removed parent
+        for descendant in descendants.iter().rev() {
+            self.remove_transaction(descendant);
+        }
+        descendants
+    }
+
+    /// Dependency edges between pooled transactions
+    pub fn dependencies(&self) -> &TxDag {
+        &self.dag
+    }
+
+    /// Output `outpoint` of a pooled transaction, as the UTXO it becomes once
+    /// that transaction confirms
+    pub fn pooled_output(&self, outpoint: &OutPoint) -> Option<UTXO> {
+        let parent = &self.transactions.get(&outpoint.tx_id)?.transaction;
+        let output = parent.outputs.get(outpoint.output_index as usize)?;
+        Some(UTXO::new(output.clone(), 0, outpoint.tx_id, outpoint.output_index, parent.is_coinbase()))
+    }
+
+    /// `utxo_set` plus the pooled outputs `tx` spends, so a transaction
+    /// spending unconfirmed parents can be checked like any other. Only clones
+    /// when `tx` actually has such inputs.
+    pub fn utxo_view<'a>(&self, utxo_set: &'a UTXOSet, tx: &Transaction) -> Cow<'a, UTXOSet> {
+        let mut view = Cow::Borrowed(utxo_set);
+        for input in &tx.inputs {
+            if utxo_set.get_utxo(&input.prev_output).is_none() {
+                if let Some(utxo) = self.pooled_output(&input.prev_output) {
+                    // can't collide, the outpoint is missing from the set
+                    let _ = view.to_mut().add_utxo(input.prev_output, utxo);
+                }
+            }
+        }
+        view
+    }
+
+    /// Pooled transactions `tx` depends on: those whose outputs it spends,
+    /// and the one from the same sender with the previous nonce
+    fn find_parents(&self, tx: &Transaction) -> HashSet<TxId> {
+        let mut parents: HashSet<TxId> = tx.inputs.iter()
+            .map(|input| input.prev_output.tx_id)
+            .filter(|parent| self.transactions.contains_key(parent))
+            .collect();
+
+        if let (Some(from), Some(nonce)) = (tx.from, tx.nonce) {
+            if let Some(previous) = nonce.checked_sub(1) {
+                parents.extend(self.pending_nonce(&from, previous));
+            }
+        }
+        parents
+    }
     
-    /// Get transactions for block creation (highest priority first)
+    /// Get transactions for block creation (highest priority first).
+    ///
+    /// Transactions are ranked by fee per unit of block weight, so runtime
+    /// calls (which mostly cost compute) and plain transfers (which mostly cost
+    /// bytes and signature checks) compete for the same block on one scale.
+    /// Ties go to the higher fee per byte, then the older transaction. A
+    /// transaction that would push the block over any of `limits` is skipped
+    /// and selection carries on with the rest, so smaller transactions can
+    /// still fill the space left. A transaction depending on pooled parents
+    /// waits until they are all selected, so the result is in topological
+    /// order.
     pub fn get_transactions_for_block(
         &self, 
         max_count: usize,
-        max_size: usize,
+        limits: &BlockWeight,
+        world_state: &WorldState,
+    ) -> Vec<Transaction> {
+        self.get_transactions_for_block_until(max_count, limits, world_state, None)
+    }
+
+    /// Like `get_transactions_for_block`, but stops selecting at `deadline`
+    /// and returns what was picked so far, best first. Proposers use this so
+    /// a large pool can't make them miss their slot.
+    pub fn get_transactions_for_block_until(
+        &self,
+        max_count: usize,
+        limits: &BlockWeight,
         world_state: &WorldState,
+        deadline: Option<std::time::Instant>,
     ) -> Vec<Transaction> {
         let mut selected = Vec::new();
-        let mut total_size = 0;
+        let mut usage = ResourceUsage::default();
         let mut used_outpoints = HashSet::new();
         let mut nonce_tracker: HashMap<Address, Nonce> = HashMap::new();
+        let mut used_nonces: HashSet<(Address, Nonce)> = HashSet::new();
         
         // Initialize nonce tracker with current world state
-        for (address, _) in &self.by_sender {
-            nonce_tracker.insert(*address, world_state.get_nonce(address));
+        for address in self.pending_nonces.keys() {
+            nonce_tracker.insert(*address, world_state.get_account(address).nonce);
         }
         
-        // Sort transactions by priority
-        let mut sorted_txs: Vec<_> = self.transactions.values().collect();
-        sorted_txs.sort_by(|a, b| b.cmp(a)); // Highest priority first
+        // Sort by fee per unit of block weight, highest first; the fractions
+        // are compared by cross-multiplying so small weights don't round
+        let mut ranked: Vec<(&PrioritizedTransaction, u128, u128)> = self.transactions.values()
+            .map(|ptx| {
+                let fee = ptx.transaction.calculate_gas_fee() as u128;
+                let weight = limits.weight(&ResourceUsage::of_transaction(&ptx.transaction)).max(1) as u128;
+                (ptx, fee, weight)
+            })
+            .collect();
+        ranked.sort_by(|(a, a_fee, a_weight), (b, b_fee, b_weight)| {
+            (b_fee * a_weight).cmp(&(a_fee * b_weight)).then_with(|| b.cmp(a))
+        });
+        let mut ranked = ranked.into_iter().map(|(ptx, _, _)| ptx);
+
+        // transactions waiting for a pooled parent to be selected first
+        let mut included: HashSet<TxId> = HashSet::new();
+        let mut deferred: HashMap<TxId, &PrioritizedTransaction> = HashMap::new();
         
-        for prioritized_tx in sorted_txs {
-            let tx = &prioritized_tx.transaction;
-            
-            // Check limits
-            if selected.len() >= max_count {
-                break;
-            }
-            
-            let tx_size = tx.size();
-            if total_size + tx_size > max_size {
-                continue;
-            }
-            
-            // Check for conflicts with already selected transactions
-            let mut has_conflict = false;
-            for input in &tx.inputs {
-                if used_outpoints.contains(&input.prev_output) {
-                    has_conflict = true;
-                    break;
+        'selection: loop {
+            let prioritized_tx = match ranked.next() {
+                Some(prioritized_tx) => prioritized_tx,
+                None => break,
+            };
+
+            // selecting a transaction can release deferred children, which
+            // are considered right after it so parents always come first
+            let mut candidates = vec![prioritized_tx];
+            while let Some(prioritized_tx) = candidates.pop() {
+                let tx = &prioritized_tx.transaction;
+                let tx_id = prioritized_tx.id();
+                
+                // Check limits
+                if selected.len() >= max_count {
+                    break 'selection;
                 }
-            }
-            
-            if has_conflict {
-                continue;
-            }
-            
-            // Check nonce ordering for account-based transactions
-            if let (Some(from), Some(tx_nonce)) = (tx.from, tx.nonce) {
-                let expected_nonce = nonce_tracker.get(&from).copied().unwrap_or(0);
-                if tx_nonce != expected_nonce {
-                    continue; // Skip out-of-order transactions
+                if deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline) {
+                    break 'selection;
                 }
-                nonce_tracker.insert(from, expected_nonce + 1);
-            }
-            
-            // Add transaction
-            for input in &tx.inputs {
-                used_outpoints.insert(input.prev_output);
+
+                if self.dag.parents(&tx_id).any(|parent| !included.contains(parent)) {
+                    deferred.insert(tx_id, prioritized_tx);
+                    continue;
+                }
+                
+                let with_tx = usage.add(&ResourceUsage::of_transaction(tx));
+                if !limits.fits(&with_tx) {
+                    continue;
+                }
+                
+                // Check for conflicts with already selected transactions
+                if tx.inputs.iter().any(|input| used_outpoints.contains(&input.prev_output)) {
+                    continue;
+                }
+                
+                // Account transactions have to offer at least the base fee
+                if tx.from.is_some() && tx.gas_price.unwrap_or(0) < world_state.base_fee() {
+                    continue;
+                }
+
+                // Check nonce ordering for account-based transactions; a
+                // (sender, nonce) pair goes into the block at most once
+                if let (Some(from), Some(tx_nonce)) = (tx.from, tx.nonce) {
+                    let expected_nonce = nonce_tracker.get(&from).copied()
+                        .unwrap_or_else(|| world_state.get_account(&from).nonce);
+                    if tx_nonce != expected_nonce || !used_nonces.insert((from, tx_nonce)) {
+                        continue; // Skip out-of-order transactions
+                    }
+                    nonce_tracker.insert(from, expected_nonce + 1);
+                }
+                
+                // Add transaction
+                for input in &tx.inputs {
+                    used_outpoints.insert(input.prev_output);
+                }
+                
+                usage = with_tx;
+                included.insert(tx_id);
+                selected.push(tx.clone());
+
+                let released: Vec<TxId> = self.dag.children(&tx_id)
+                    .filter(|child| deferred.contains_key(child))
+                    .filter(|child| self.dag.parents(child).all(|parent| included.contains(parent)))
+                    .copied()
+                    .collect();
+                candidates.extend(released.iter().filter_map(|child| deferred.remove(child)));
             }
-            
-            total_size += tx_size;
-            selected.push(tx.clone());
         }
         
         selected
@@ -292,6 +536,82 @@ impl TransactionPool{
             .unwrap_or_default()
     }
     
+    /// The pooled transaction from `sender` with `nonce`, if any
+    pub fn pending_nonce(&self, sender: &Address, nonce: Nonce) -> Option<TxId> {
+        self.pending_nonces.get(sender).and_then(|nonces| nonces.get(&nonce)).copied()
+    }
+
+    /// Drop pooled transactions whose sender nonce `world_state` has already
+    /// used, i.e. a different transaction with the same nonce was confirmed.
+    /// Transactions following them in nonce order stay, now free to be
+    /// selected. Returns the transactions dropped.
+    pub fn remove_used_nonces(&mut self, world_state: &WorldState) -> Vec<TxId> {
+        let stale: Vec<TxId> = self.pending_nonces.iter()
+            .flat_map(|(sender, nonces)| nonces.range(..world_state.get_account(sender).nonce).map(|(_, tx_id)| *tx_id))
+            .collect();
+        for tx_id in &stale {
+            self.remove_transaction(tx_id);
+            self.push_event(MempoolEvent::NonceUsed { tx_id: *tx_id });
+        }
+        stale
+    }
+    
+    /// Check the pool against `world_state` after the chain moved to it.
+    ///
+    /// `disconnected` are the transactions of blocks taken off the chain, in
+    /// chain order; they go back in first, since pooled transactions may spend
+    /// their outputs. The pool is then rebuilt from a snapshot of itself,
+    /// parents first: every transaction is admitted again as if it had just
+    /// arrived, keeping its arrival time, and the ones that no longer validate
+    /// (inputs spent or gone, nonce used, balance short, gas price below a
+    /// risen base fee) are dropped along
+    /// with an `Invalidated` event.
+    pub fn revalidate(&mut self, world_state: &WorldState, disconnected: Vec<Transaction>) -> Revalidation {
+        let _span = debug_span!("mempool_revalidate", pooled = self.transactions.len(), disconnected = disconnected.len()).entered();
+
+        let mut ids: Vec<TxId> = self.transactions.keys().copied().collect();
+        ids.sort_by_cached_key(|tx_id| (self.transactions[tx_id].added_time, tx_id.to_hex()));
+        let snapshot: Vec<PrioritizedTransaction> = self.dag.topological_order(ids).iter()
+            .filter_map(|tx_id| self.transactions.get(tx_id).cloned())
+            .collect();
+        self.clear();
+
+        let mut revalidation = Revalidation::default();
+        for tx in disconnected {
+            let tx_id = tx.id();
+            match self.add_transaction(tx, world_state) {
+                Ok(_) => revalidation.reinserted.push(tx_id),
+                Err(e) => {
+                    debug!(tx = %tx_id, error = %e, "disconnected transaction no longer valid");
+                    revalidation.rejected.push(tx_id);
+                }
+            }
+        }
+
+        for pooled in snapshot {
+            let tx_id = pooled.id();
+            if self.transactions.contains_key(&tx_id) {
+                continue;
+            }
+            match self.add_transaction(pooled.transaction, world_state) {
+                Ok(_) => {
+                    if let Some(readded) = self.transactions.get_mut(&tx_id) {
+                        readded.added_time = pooled.added_time;
+                    }
+                }
+                Err(e) => {
+                    debug!(tx = %tx_id, error = %e, "pooled transaction no longer valid");
+                    revalidation.dropped.push(tx_id);
+                    self.push_event(MempoolEvent::Invalidated { tx_id });
+                }
+            }
+        }
+
+        // arrival times changed back, so the queue has to be ordered again
+        self.rebuild_priority_queue();
+        revalidation
+    }
+
     /// Get pending transaction count
     pub fn len(&self) -> usize {
         self.transactions.len()
@@ -312,7 +632,9 @@ impl TransactionPool{
         self.priority_queue.clear();
         self.transactions.clear();
         self.by_sender.clear();
+        self.pending_nonces.clear();
         self.spent_outpoints.clear();
+        self.dag = TxDag::new();
         self.memory_usage = 0;
     }
     
@@ -343,6 +665,14 @@ impl TransactionPool{
             return Ok(());
         }
         
+        // Account transactions that can't pay the base fee would never be
+        // included; revalidation drops pooled ones once the base fee rises
+        if tx.from.is_some() && tx.gas_price.unwrap_or(0) < world_state.base_fee() {
+            return Err(BlockchainError::MempoolError(
+                format!("Gas price {} is below the base fee {}", tx.gas_price.unwrap_or(0), world_state.base_fee())
+            ));
+        }
+
         // Validate account-based transaction
         if let (Some(from), Some(tx_nonce)) = (tx.from, tx.nonce) {
             let account = world_state.get_account(&from);
@@ -350,7 +680,7 @@ impl TransactionPool{
             let current_nonce = account.nonce;
             
             // Check balance
-            let total_cost = tx.amount.unwrap_or(0) + tx.calculate_gas_fee();
+            let total_cost = tx.total_cost();
             if balance < total_cost {
                 return Err(BlockchainError::InsufficientBalance {
                     required: total_cost,
@@ -375,25 +705,33 @@ impl TransactionPool{
                         "Coinbase UTXO not mature enough".to_string()
                     ));
                 }
-            } else {
+            } else if self.pooled_output(&input.prev_output).is_none() {
                 return Err(BlockchainError::MempoolError(
                     format!("UTXO not found: {}", input.prev_output)
                 ));
             }
         }
         
+        // Premature spends are refused: the next block must be able to include it
+        check_time_locks(tx, world_state.utxo_set(), world_state.block_height() + 1, Timestamp::now())?;
+        
         Ok(())
     }
     
-    /// Check mempool limits
-    fn check_limits(&self, tx: &Transaction) -> Result<()> {
-        if self.transactions.len() >= self.config.max_transactions {
+    /// Check mempool limits, as they will be once `replaced` is evicted
+    fn check_limits(&self, tx: &Transaction, replaced: &[TxId]) -> Result<()> {
+        let freed_memory: usize = replaced.iter()
+            .filter_map(|tx_id| self.transactions.get(tx_id))
+            .map(|ptx| ptx.transaction.size())
+            .sum();
+
+        if self.transactions.len() - replaced.len() >= self.config.max_transactions {
             return Err(BlockchainError::MempoolError(
                 "Mempool transaction limit reached".to_string()
             ));
         }
         
-        if self.memory_usage + tx.size() > self.config.max_memory {
+        if self.memory_usage.saturating_sub(freed_memory) + tx.size() > self.config.max_memory {
             return Err(BlockchainError::MempoolError(
                 "Mempool memory limit reached".to_string()
             ));
@@ -402,17 +740,111 @@ impl TransactionPool{
         Ok(())
     }
     
-    /// Check for double spending conflicts
-    fn check_conflicts(&self, tx: &Transaction) -> Result<()> {
-        for input in &tx.inputs {
-            if self.spent_outpoints.contains(&input.prev_output) {
-                return Err(BlockchainError::DoubleSpending(
-                    format!("Outpoint already spent: {}", input.prev_output)
-                ));
+    /// Pooled transactions that `tx` conflicts with: those spending one of its
+    /// outpoints, and one from the same sender with the same nonce
+    fn find_conflicts(&self, tx: &Transaction) -> Vec<TxId> {
+        let mut conflicts = Vec::new();
+
+        let spends_same = tx.inputs.iter()
+            .any(|input| self.spent_outpoints.contains(&input.prev_output));
+        if spends_same {
+            let outpoints: HashSet<OutPoint> = tx.inputs.iter().map(|input| input.prev_output).collect();
+            conflicts.extend(self.transactions.values()
+                .filter(|ptx| ptx.transaction.inputs.iter().any(|input| outpoints.contains(&input.prev_output)))
+                .map(|ptx| ptx.id()));
+        }
+
+        if let (Some(from), Some(nonce)) = (tx.from, tx.nonce) {
+            conflicts.extend(self.pending_nonce(&from, nonce));
+        }
+
+        conflicts.sort_by_key(|tx_id| tx_id.to_hex());
+        conflicts.dedup();
+        conflicts
+    }
+
+    /// Replace-by-fee rules: the replacement must beat every conflicting
+    /// transaction's fee per byte by `min_replacement_fee_bump` percent and pay
+    /// at least their combined fees, so evictions can't be bought for free
+    /// The pooled conflicts and every transaction spending from them or
+    /// following them in nonce order: all of it leaves the pool with a replacement
+    fn replacement_evictions(&self, conflicts: &[TxId]) -> Vec<TxId> {
+        let mut evicted = Vec::new();
+        let mut seen = HashSet::new();
+        for conflict in conflicts.iter().filter(|conflict| self.transactions.contains_key(conflict)) {
+            for tx_id in std::iter::once(*conflict).chain(self.dag.descendants(conflict)) {
+                if seen.insert(tx_id) {
+                    evicted.push(tx_id);
+                }
             }
         }
+        evicted
+    }
+
+    /// Each direct conflict must be outbid by the fee bump, and the whole
+    /// `evicted` set (conflicts and their descendants) must stay within
+    /// `max_replacements` and be outpaid in total
+    fn check_replacement(&self, replacement: &PrioritizedTransaction, conflicts: &[TxId], evicted: &[TxId]) -> Result<()> {
+        if conflicts.is_empty() {
+            return Ok(());
+        }
+
+        if evicted.len() > self.config.max_replacements {
+            return Err(BlockchainError::DoubleSpending(
+                format!("Replacement would evict {} transactions (max {})", evicted.len(), self.config.max_replacements)
+            ));
+        }
+
+        for conflict in conflicts {
+            let existing = match self.transactions.get(conflict) {
+                Some(existing) => existing,
+                None => continue,
+            };
+
+            let required = existing.fee_per_byte
+                .saturating_mul(100 + self.config.min_replacement_fee_bump)
+                .div_ceil(100);
+            if replacement.fee_per_byte < required.max(existing.fee_per_byte + 1) {
+                return Err(BlockchainError::DoubleSpending(format!(
+                    "Conflicts with {}; replacement fee per byte {} is below the required {}",
+                    conflict, replacement.fee_per_byte, required.max(existing.fee_per_byte + 1)
+                )));
+            }
+        }
+
+        let replaced_fees = evicted.iter()
+            .filter_map(|tx_id| self.transactions.get(tx_id))
+            .fold(0u64, |total, evicted| total.saturating_add(evicted.transaction.calculate_gas_fee()));
+
+        let fee = replacement.transaction.calculate_gas_fee();
+        if fee < replaced_fees {
+            return Err(BlockchainError::DoubleSpending(format!(
+                "Replacement fee {} is below the {} paid by the transactions it replaces", fee, replaced_fees
+            )));
+        }
+
         Ok(())
     }
+
+    fn push_event(&mut self, event: MempoolEvent) {
+        match &event {
+            MempoolEvent::Replaced { replaced, replacement, .. } => debug!(tx = %replaced, by = %replacement, "replaced by fee"),
+            MempoolEvent::Expired { tx_id } => debug!(tx = %tx_id, "expired from mempool"),
+            MempoolEvent::Evicted { tx_id } => debug!(tx = %tx_id, "evicted from mempool"),
+            MempoolEvent::NonceUsed { tx_id } => debug!(tx = %tx_id, "sender nonce used on chain"),
+            MempoolEvent::DoubleSpend(proof) => debug!(outpoint = %proof.outpoint, "double spend attempt"),
+            MempoolEvent::Invalidated { tx_id } => debug!(tx = %tx_id, "invalidated by the chain"),
+        }
+        if self.events.len() >= MAX_PENDING_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+
+    /// Take the events recorded since the last call, oldest first
+    pub fn drain_events(&mut self) -> Vec<MempoolEvent> {
+        self.events.drain(..).collect()
+    }
     
     /// Evict old or low-priority transactions if needed
     fn evict_if_needed(&mut self) -> Result<()> {
@@ -426,9 +858,15 @@ impl TransactionPool{
             }
         }
         
-        // Remove old transactions
+        // Remove old transactions; their children can't confirm without them
         for tx_id in to_remove {
-            self.remove_transaction(&tx_id);
+            if !self.transactions.contains_key(&tx_id) {
+                continue;
+            }
+            let descendants = self.remove_with_descendants(&tx_id);
+            for tx_id in std::iter::once(tx_id).chain(descendants) {
+                self.push_event(MempoolEvent::Expired { tx_id });
+            }
         }
         
         // If still over limits, remove lowest priority transactions
@@ -436,7 +874,10 @@ impl TransactionPool{
               self.memory_usage > self.config.max_memory {
             
             if let Some(lowest_priority) = self.find_lowest_priority_transaction() {
-                self.remove_transaction(&lowest_priority);
+                let descendants = self.remove_with_descendants(&lowest_priority);
+                for tx_id in std::iter::once(lowest_priority).chain(descendants) {
+                    self.push_event(MempoolEvent::Evicted { tx_id });
+                }
             } else {
                 break;
             }
@@ -542,6 +983,21 @@ impl Mempool {
     pub fn get_transaction(&self, tx_id: &TxId) -> Option<&Transaction> {
         self.pool.get_transaction(tx_id)
     }
+
+    /// UTXO set as seen by `tx`, including outputs of pooled parents
+    pub fn utxo_view<'a>(&self, utxo_set: &'a UTXOSet, tx: &Transaction) -> Cow<'a, UTXOSet> {
+        self.pool.utxo_view(utxo_set, tx)
+    }
+
+    /// Remove a transaction and every pooled transaction depending on it
+    pub fn remove_with_descendants(&mut self, tx_id: &TxId) -> Vec<TxId> {
+        self.pool.remove_with_descendants(tx_id)
+    }
+
+    /// Dependency edges between pooled transactions
+    pub fn dependencies(&self) -> &TxDag {
+        self.pool.dependencies()
+    }
     
     /// Check if transaction exists in mempool
     pub fn contains_transaction(&self, tx_id: &TxId) -> bool {
@@ -552,10 +1008,21 @@ impl Mempool {
     pub fn get_transactions_for_block(
         &self,
         max_count: usize,
-        max_size: usize,
+        limits: &BlockWeight,
+        world_state: &WorldState,
+    ) -> Vec<Transaction> {
+        self.pool.get_transactions_for_block(max_count, limits, world_state)
+    }
+
+    /// Transactions for block creation, selected until `deadline`
+    pub fn get_transactions_for_block_until(
+        &self,
+        max_count: usize,
+        limits: &BlockWeight,
         world_state: &WorldState,
+        deadline: Option<std::time::Instant>,
     ) -> Vec<Transaction> {
-        self.pool.get_transactions_for_block(max_count, max_size, world_state)
+        self.pool.get_transactions_for_block_until(max_count, limits, world_state, deadline)
     }
     
     /// Remove multiple transactions (e.g., after block confirmation)
@@ -565,6 +1032,22 @@ impl Mempool {
             .collect()
     }
     
+    /// Drop transactions whose sender nonce was used by a confirmed one
+    pub fn remove_used_nonces(&mut self, world_state: &WorldState) -> Vec<TxId> {
+        self.pool.remove_used_nonces(world_state)
+    }
+
+    /// Drop what the chain moving to `world_state` invalidated and take back
+    /// the transactions of disconnected blocks
+    pub fn revalidate(&mut self, world_state: &WorldState, disconnected: Vec<Transaction>) -> Revalidation {
+        self.pool.revalidate(world_state, disconnected)
+    }
+
+    /// The pooled transaction from `sender` with `nonce`, if any
+    pub fn pending_nonce(&self, sender: &Address, nonce: Nonce) -> Option<TxId> {
+        self.pool.pending_nonce(sender, nonce)
+    }
+    
     /// Get all pending transactions
     pub fn get_pending_transactions(&self) -> Vec<&Transaction> {
         self.pool.get_all_transactions()
@@ -604,6 +1087,11 @@ impl Mempool {
     pub fn update_config(&mut self, config: MempoolConfig) {
         self.pool.update_config(config);
     }
+    
+    /// Take mempool events (e.g. fee replacements) recorded since the last call
+    pub fn drain_events(&mut self) -> Vec<MempoolEvent> {
+        self.pool.drain_events()
+    }
 }
 
 impl Default for Mempool {
@@ -618,6 +1106,7 @@ mod tests {
     use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
     use crate::state::{WorldState, AccountState};
     use crate::types::AccountModel;
+    use crate::validation::ValidationRules;
 
     #[test]
     fn test_mempool_add_transaction() {
@@ -644,6 +1133,26 @@ mod tests {
         assert!(mempool.contains_transaction(&tx_id));
     }
 
+    #[test]
+    fn test_mempool_rejects_premature_spend() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+        
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+        world_state.set_account(addr1.clone(), AccountState::new(1_000_000));
+        
+        // Locked until block 50; the next block is 1
+        let mut tx = Transaction::new_account(addr1.clone(), addr2.clone(), 100, 0, 21000, 20, vec![]);
+        tx.lock_time = 50;
+        assert!(mempool.add_transaction(tx.clone(), &world_state).is_err());
+        
+        tx.lock_time = 1;
+        assert!(mempool.add_transaction(tx, &world_state).is_ok());
+    }
+
     #[test]
     fn test_mempool_insufficient_balance() {
         let mut mempool = Mempool::default();
@@ -689,7 +1198,7 @@ mod tests {
         mempool.add_transaction(tx3, &world_state).unwrap();
         
         // Get transactions for block (should be ordered by fee, then nonce)
-        let selected = mempool.get_transactions_for_block(10, 1000000, &world_state);
+        let selected = mempool.get_transactions_for_block(10, &BlockWeight::new(&ValidationRules::default()), &world_state);
         
         // Should select in nonce order (0, 1, 2) despite fee differences
         assert_eq!(selected.len(), 3);
@@ -698,6 +1207,141 @@ mod tests {
         assert_eq!(selected[2].nonce, Some(2));
     }
 
+    #[test]
+    fn test_selection_respects_compute_limit() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+
+        world_state.set_account(addr1, AccountState::new(10_000_000));
+
+        // non-empty data makes these contract calls
+        let call1 = Transaction::new_account(addr1, addr2, 0, 0, 60_000, 10, vec![1]);
+        let call2 = Transaction::new_account(addr1, addr2, 0, 1, 60_000, 10, vec![2]);
+        assert_eq!(call1.compute_units(), 60_000);
+
+        mempool.add_transaction(call1, &world_state).unwrap();
+        mempool.add_transaction(call2, &world_state).unwrap();
+
+        let limits = |max_block_compute_units| {
+            BlockWeight::new(&ValidationRules { max_block_compute_units, ..ValidationRules::default() })
+        };
+
+        // only one call fits in the compute budget
+        let selected = mempool.get_transactions_for_block(10, &limits(100_000), &world_state);
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].nonce, Some(0));
+
+        let selected = mempool.get_transactions_for_block(10, &limits(120_000), &world_state);
+        assert_eq!(selected.len(), 2);
+    }
+
+    #[test]
+    fn test_selection_ranks_by_fee_per_weight() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let caller = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let sender = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        world_state.set_account(caller, AccountState::new(10_000_000));
+        world_state.set_account(sender, AccountState::new(10_000_000));
+
+        // the call pays more per byte, but its compute makes it far heavier
+        let call = Transaction::new_account(caller, recipient, 0, 0, 60_000, 10, vec![1]);
+        let transfer = Transaction::new_account(sender, recipient, 100, 0, 21000, 10, vec![]);
+        assert!(call.calculate_gas_fee() / call.size() as u64 > transfer.calculate_gas_fee() / transfer.size() as u64);
+
+        mempool.add_transaction(call.clone(), &world_state).unwrap();
+        mempool.add_transaction(transfer.clone(), &world_state).unwrap();
+
+        let limits = BlockWeight::new(&ValidationRules::default());
+        let selected = mempool.get_transactions_for_block(10, &limits, &world_state);
+        assert_eq!(selected.iter().map(Transaction::id).collect::<Vec<_>>(), vec![transfer.id(), call.id()]);
+    }
+
+    #[test]
+    fn test_selection_skips_what_does_not_fit_and_continues() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let caller = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let sender = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        world_state.set_account(caller, AccountState::new(100_000_000));
+        world_state.set_account(sender, AccountState::new(10_000_000));
+
+        // the call ranks first but is over the compute limit on its own
+        let call = Transaction::new_account(caller, recipient, 0, 0, 60_000, 1000, vec![1]);
+        let transfer = Transaction::new_account(sender, recipient, 100, 0, 21000, 10, vec![]);
+        mempool.add_transaction(call, &world_state).unwrap();
+        mempool.add_transaction(transfer.clone(), &world_state).unwrap();
+
+        let limits = BlockWeight::new(&ValidationRules { max_block_compute_units: 50_000, ..ValidationRules::default() });
+        let selected = mempool.get_transactions_for_block(10, &limits, &world_state);
+        assert_eq!(selected.iter().map(Transaction::id).collect::<Vec<_>>(), vec![transfer.id()]);
+    }
+
+    #[test]
+    fn test_selection_stops_at_deadline() {
+        let mut mempool = Mempool::new(MempoolConfig::default());
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+        world_state.set_account(addr1, AccountState::new(10000));
+
+        mempool.add_transaction(Transaction::new_account(addr1, addr2, 100, 0, 21000, 10, vec![]), &world_state).unwrap();
+        mempool.add_transaction(Transaction::new_account(addr1, addr2, 100, 1, 21000, 10, vec![]), &world_state).unwrap();
+        let limits = BlockWeight::new(&ValidationRules::default());
+
+        let now = std::time::Instant::now();
+        let selected = mempool.get_transactions_for_block_until(10, &limits, &world_state, Some(now));
+        assert!(selected.is_empty());
+
+        let later = now + std::time::Duration::from_secs(60);
+        let selected = mempool.get_transactions_for_block_until(10, &limits, &world_state, Some(later));
+        assert_eq!(selected.len(), 2);
+    }
+
+    #[test]
+    fn test_dependency_depth_limit() {
+        let mut mempool = Mempool::new(MempoolConfig { max_dependency_depth: 1, ..MempoolConfig::default() });
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+
+        world_state.set_account(addr1, AccountState::new(10000));
+
+        // each nonce depends on the previous one: 0 <- 1 <- 2
+        let tx0 = Transaction::new_account(addr1, addr2, 100, 0, 21000, 10, vec![]);
+        let tx1 = Transaction::new_account(addr1, addr2, 100, 1, 21000, 50, vec![]);
+        let tx2 = Transaction::new_account(addr1, addr2, 100, 2, 21000, 30, vec![]);
+        let (id0, id1) = (tx0.id(), tx1.id());
+
+        mempool.add_transaction(tx0, &world_state).unwrap();
+        mempool.add_transaction(tx1, &world_state).unwrap();
+        assert_eq!(mempool.pool.dependencies().parents(&id1).collect::<Vec<_>>(), vec![&id0]);
+        assert!(mempool.add_transaction(tx2, &world_state).is_err());
+
+        // the higher-fee child still comes after its parent
+        let selected = mempool.get_transactions_for_block(10, &BlockWeight::new(&ValidationRules::default()), &world_state);
+        assert_eq!(selected.iter().map(|tx| tx.id()).collect::<Vec<_>>(), vec![id0, id1]);
+
+        // dropping the parent as invalid takes the child with it
+        assert_eq!(mempool.pool.remove_with_descendants(&id0), vec![id1]);
+        assert!(mempool.is_empty());
+    }
+
     #[test]
     fn test_mempool_remove_transaction() {
         let mut mempool = Mempool::default();
@@ -747,6 +1391,174 @@ mod tests {
         assert!(stats.memory_usage > 0);
         assert!(stats.oldest_transaction.is_some());
     }
+
+    #[test]
+    fn test_replace_by_fee() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+
+        world_state.set_account(addr1, AccountState::new(10_000_000));
+
+        let original = Transaction::new_account(addr1, addr2, 100, 0, 21000, 20, vec![]);
+        let original_id = mempool.add_transaction(original, &world_state).unwrap();
+
+        // Same nonce, fee bump under 10%: rejected
+        let too_cheap = Transaction::new_account(addr1, addr2, 100, 0, 21000, 21, vec![]);
+        let result = mempool.add_transaction(too_cheap, &world_state);
+        assert!(matches!(result, Err(BlockchainError::DoubleSpending(_))));
+        assert!(mempool.contains_transaction(&original_id));
+
+        // Same nonce, double the fee: replaces the original
+        let bumped = Transaction::new_account(addr1, addr2, 100, 0, 21000, 40, vec![]);
+        let bumped_id = mempool.add_transaction(bumped, &world_state).unwrap();
+        assert_eq!(mempool.len(), 1);
+        assert!(!mempool.contains_transaction(&original_id));
+        assert!(mempool.contains_transaction(&bumped_id));
+
+        let events = mempool.drain_events();
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            MempoolEvent::Replaced { replaced, replacement, .. } if *replaced == original_id && *replacement == bumped_id
+        ));
+        assert!(mempool.drain_events().is_empty());
+    }
+
+    #[test]
+    fn test_replacement_pays_for_the_descendants_it_evicts() {
+        let mut mempool = Mempool::new(MempoolConfig { max_replacements: 3, ..MempoolConfig::default() });
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+        world_state.set_account(addr1, AccountState::new(10_000_000));
+
+        // nonces 1..3 follow nonce 0, so replacing it evicts them all
+        let chain: Vec<TxId> = (0..4)
+            .map(|nonce| mempool.add_transaction(Transaction::new_account(addr1, addr2, 100, nonce, 21000, 20, vec![]), &world_state).unwrap())
+            .collect();
+
+        // outbids the conflict alone, but not the four fees it would evict
+        let bumped = Transaction::new_account(addr1, addr2, 100, 0, 21000, 40, vec![]);
+        let result = mempool.add_transaction(bumped, &world_state);
+        assert!(matches!(result, Err(BlockchainError::DoubleSpending(reason)) if reason.contains("below the")));
+        assert_eq!(mempool.len(), 4);
+
+        // enough fee, but four evictions are over the limit of three
+        let bumped = Transaction::new_account(addr1, addr2, 100, 0, 21000, 100, vec![]);
+        let result = mempool.add_transaction(bumped, &world_state);
+        assert!(matches!(result, Err(BlockchainError::DoubleSpending(reason)) if reason.contains("evict 4")));
+
+        // with the tail confirmed elsewhere, the same replacement fits
+        mempool.remove_transaction(&chain[3]);
+        let bumped = Transaction::new_account(addr1, addr2, 100, 0, 21000, 100, vec![]);
+        let bumped_id = mempool.add_transaction(bumped, &world_state).unwrap();
+        assert_eq!(mempool.len(), 1);
+        assert!(mempool.contains_transaction(&bumped_id));
+    }
+
+    #[test]
+    fn test_confirmed_nonce_drops_pooled_transaction() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+
+        world_state.set_account(addr1, AccountState::new(10_000_000));
+
+        let first = mempool.add_transaction(Transaction::new_account(addr1, addr2, 100, 0, 21000, 20, vec![]), &world_state).unwrap();
+        let second = mempool.add_transaction(Transaction::new_account(addr1, addr2, 100, 1, 21000, 20, vec![]), &world_state).unwrap();
+        assert_eq!(mempool.pending_nonce(&addr1, 0), Some(first));
+        assert_eq!(mempool.pending_nonce(&addr1, 1), Some(second));
+
+        // another transaction with nonce 0 was confirmed
+        let mut account = AccountState::new(10_000_000);
+        account.increment_nonce();
+        world_state.set_account(addr1, account);
+
+        assert_eq!(mempool.remove_used_nonces(&world_state), vec![first]);
+        assert_eq!(mempool.pending_nonce(&addr1, 0), None);
+        assert!(matches!(mempool.drain_events().as_slice(), [MempoolEvent::NonceUsed { tx_id }] if *tx_id == first));
+
+        // its successor no longer waits on it
+        let selected = mempool.get_transactions_for_block(10, &BlockWeight::new(&ValidationRules::default()), &world_state);
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].id(), second);
+    }
+
+    #[test]
+    fn test_revalidation_drops_stale_and_reinserts_disconnected() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let addrs: Vec<Address> = (0..5)
+            .map(|_| public_key_to_address(generate_keypair().public_key(), AddressType::Base58))
+            .collect();
+        for addr in &addrs[..4] {
+            world_state.set_account(addr.clone(), AccountState::new(10_000_000));
+        }
+        let pay = |from: usize, nonce| Transaction::new_account(addrs[from].clone(), addrs[4].clone(), 100, nonce, 21000, 20, vec![]);
+
+        let stale = mempool.add_transaction(pay(0, 0), &world_state).unwrap();
+        let kept = mempool.add_transaction(pay(1, 0), &world_state).unwrap();
+        let arrived = mempool.pool.transactions[&kept].added_time;
+
+        // a block confirmed another nonce 0 of the first sender and was then
+        // disconnected along with the transactions below
+        let mut account = AccountState::new(10_000_000);
+        account.increment_nonce();
+        world_state.set_account(addrs[0].clone(), account);
+        world_state.set_account(addrs[3].clone(), AccountState::new(0));
+        let (returning, unfunded) = (pay(2, 0), pay(3, 0));
+
+        let revalidation = mempool.revalidate(&world_state, vec![returning.clone(), unfunded.clone()]);
+        assert_eq!(revalidation, Revalidation {
+            reinserted: vec![returning.id()],
+            rejected: vec![unfunded.id()],
+            dropped: vec![stale],
+        });
+        assert!(mempool.contains_transaction(&returning.id()));
+        assert_eq!(mempool.len(), 2);
+        assert_eq!(mempool.pool.transactions[&kept].added_time, arrived);
+        assert!(matches!(mempool.drain_events().as_slice(), [MempoolEvent::Invalidated { tx_id }] if *tx_id == stale));
+    }
+
+    #[test]
+    fn test_base_fee_gates_admission_and_revalidation() {
+        let mut mempool = Mempool::default();
+        let mut world_state = WorldState::new(AccountModel::Account);
+
+        let addrs: Vec<Address> = (0..3)
+            .map(|_| public_key_to_address(generate_keypair().public_key(), AddressType::Base58))
+            .collect();
+        for addr in &addrs[..2] {
+            world_state.set_account(addr.clone(), AccountState::new(10_000_000));
+        }
+        let pay = |from: usize, gas_price| Transaction::new_account(addrs[from].clone(), addrs[2].clone(), 100, 0, 21000, gas_price, vec![]);
+
+        world_state.set_fee_state(30, 0);
+        assert!(mempool.add_transaction(pay(0, 20), &world_state).is_err());
+        let cheap = mempool.add_transaction(pay(0, 30), &world_state).unwrap();
+        let generous = mempool.add_transaction(pay(1, 50), &world_state).unwrap();
+
+        // the base fee rose past what the first one offers
+        world_state.set_fee_state(40, 0);
+        let revalidation = mempool.revalidate(&world_state, Vec::new());
+        assert_eq!(revalidation.dropped, vec![cheap]);
+        assert!(mempool.contains_transaction(&generous));
+        assert_eq!(mempool.len(), 1);
+        assert!(matches!(mempool.drain_events().as_slice(), [MempoolEvent::Invalidated { tx_id }] if *tx_id == cheap));
+    }
 }
 
 
@@ -0,0 +1,36 @@
+use crate::block::{Block, BlockHeader};
+use crate::{BlockchainError, Result};
+
+
+/// Cheap check of the proof in a block header: the proof-of-work target, or a
+/// proof-of-stake producer signature.
+///
+/// Run on blocks arriving from the network before they are queued for full
+/// validation, so spam with no valid proof costs us a hash or a signature
+/// check instead of a pass through the validation pipeline.
+pub trait HeaderVerifier: Send + Sync {
+    fn verify_header(&self, header: &BlockHeader) -> Result<()>;
+}
+
+
+/// Header proof check plus the one thing tying the body to it: the merkle root.
+/// A block passing this can still fail full validation, but its producer paid
+/// for the proof, so it is worth validating.
+pub fn precheck_block(verifier: &dyn HeaderVerifier, block: &Block) -> Result<()> {
+    verifier.verify_header(&block.header)?;
+    check_body_commitment(block)
+}
+
+
+/// Check that the header commits to the body it arrived with
+pub fn check_body_commitment(block: &Block) -> Result<()> {
+    if block.header.tx_count as usize != block.transactions().len() {
+        return Err(BlockchainError::InvalidBlock(
+            format!("Header claims {} transactions, body has {}", block.header.tx_count, block.transactions().len())
+        ));
+    }
+    if block.body.calculate_merkle_root()? != block.header.merkle_root {
+        return Err(BlockchainError::InvalidBlock("Merkle root mismatch".to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use crate::block::Block;
+use crate::contract::ContractStatus;
+use crate::logs::Log;
+use crate::state::{BlockUndo, TxUndo};
+use crate::transaction::Transaction;
+use crate::types::*;
+use blockchain_crypto::Address;
+use serde::{Deserialize, Serialize};
+
+
+/// Whether a confirmed transaction did what it asked for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReceiptStatus {
+    Success,
+    /// included and charged, but its contract execution failed
+    Failed(String),
+}
+
+impl From<&ContractStatus> for ReceiptStatus {
+    fn from(status: &ContractStatus) -> Self {
+        match status {
+            ContractStatus::Success => ReceiptStatus::Success,
+            ContractStatus::Failed(reason) => ReceiptStatus::Failed(reason.clone()),
+        }
+    }
+}
+
+
+/// Outcome of a transaction in the main chain block that included it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Receipt {
+    pub tx_id: TxId,
+    pub block_id: BlockId,
+    pub block_height: BlockHeight,
+    /// position in the block
+    pub index: u32,
+    pub status: ReceiptStatus,
+    /// fee the transaction paid
+    pub fee: Amount,
+    /// gas of a contract transaction
+    pub gas_used: Option<Gas>,
+    /// contract deployed or called
+    pub contract: Option<Address>,
+    /// what the called contract logged, see [`Log::from_program`]
+    #[serde(default)]
+    pub logs: Vec<Log>,
+}
+
+impl Receipt {
+    /// Receipt of `tx` at `index` in `block`, from what applying it changed
+    pub fn new(block: &Block, index: u32, tx: &Transaction, undo: &TxUndo) -> Self {
+        let (status, gas_used, contract, logs) = match &undo.receipt {
+            Some(receipt) => (
+                ReceiptStatus::from(&receipt.status),
+                Some(receipt.gas_used),
+                Some(receipt.contract.clone()),
+                receipt.logs.clone(),
+            ),
+            None => (ReceiptStatus::Success, None, None, Vec::new()),
+        };
+
+        Self {
+            tx_id: tx.id(),
+            block_id: block.id(),
+            block_height: block.height(),
+            index,
+            status,
+            fee: Self::fee_paid(tx, undo),
+            gas_used,
+            contract,
+            logs,
+        }
+    }
+
+    /// Receipts of every transaction in a connected block
+    pub fn for_block(block: &Block, undo: &BlockUndo) -> Vec<Receipt> {
+        block.transactions().iter()
+            .zip(&undo.transactions)
+            .enumerate()
+            .map(|(index, (tx, tx_undo))| Self::new(block, index as u32, tx, tx_undo))
+            .collect()
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.status == ReceiptStatus::Success
+    }
+
+    fn fee_paid(tx: &Transaction, undo: &TxUndo) -> Amount {
+        if tx.is_coinbase() {
+            return 0;
+        }
+        //a utxo transaction pays what its inputs hold beyond its outputs
+        if !undo.spent.is_empty() {
+            let inputs: Amount = undo.spent.iter().map(|(_, utxo)| utxo.output.amount).sum();
+            return inputs.saturating_sub(tx.total_output_amount().unwrap_or(0));
+        }
+        match &undo.receipt {
+            Some(receipt) => receipt.gas_used.saturating_mul(tx.gas_price.unwrap_or(0)),
+            None => tx.calculate_gas_fee(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::contract::ContractReceipt;
+    use crate::state::{AccountState, WorldState};
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType, Hash256};
+
+    #[test]
+    fn test_receipts_record_fee_and_status() {
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+
+        let mut world_state = WorldState::new(AccountModel::Account);
+        world_state.set_account(addr1.clone(), AccountState::new(1_000_000));
+
+        let transfer = Transaction::new_account(addr1.clone(), addr2.clone(), 500, 0, 21000, 2, vec![]);
+        let block = Block::new(BlockId::new(Hash256::zero()), vec![transfer.clone()], 1, 1, 1).unwrap();
+        let undo = world_state.apply_block(&block).unwrap();
+
+        let receipts = Receipt::for_block(&block, &undo);
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].tx_id, transfer.id());
+        assert_eq!(receipts[0].block_id, block.id());
+        assert_eq!(receipts[0].fee, 42_000);
+        assert!(receipts[0].succeeded());
+        assert_eq!(receipts[0].gas_used, None);
+
+        let failed = TxUndo {
+            receipt: Some(ContractReceipt {
+                contract: addr2.clone(),
+                gas_used: 700,
+                status: ContractStatus::Failed("trapped".to_string()),
+                logs: Vec::new(),
+            }),
+            ..TxUndo::default()
+        };
+        let receipt = Receipt::new(&block, 0, &transfer, &failed);
+        assert_eq!(receipt.status, ReceiptStatus::Failed("trapped".to_string()));
+        assert_eq!(receipt.fee, 1_400);
+        assert_eq!(receipt.contract, Some(addr2));
+    }
+}
@@ -0,0 +1,395 @@
+use crate::trie_db::{NodeDatabase, PruningConfig};
+use crate::types::*;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{Hash256, hash::sha256};
+use serde::{Deserialize, Serialize};
+
+
+const LEAF_TAG: u8 = 0x00;
+const INTERNAL_TAG: u8 = 0x01;
+
+
+/// Where trie nodes can be loaded from: the in-memory node database or a
+/// persistent state store
+pub trait NodeSource {
+    /// Encoded node with this hash, if stored
+    fn node(&self, hash: &Hash256) -> Result<Option<Vec<u8>>>;
+}
+
+impl NodeSource for NodeDatabase {
+    fn node(&self, hash: &Hash256) -> Result<Option<Vec<u8>>> {
+        Ok(self.get(hash).map(|data| data.to_vec()))
+    }
+}
+
+
+/// A trie node. A node's hash is the sha256 of its encoding:
+/// leaf = `0x00 || key || value`, internal = `0x01 || left || right`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Node {
+    Leaf { key: Hash256, value: Hash256 },
+    Internal { left: Hash256, right: Hash256 },
+}
+
+impl Node {
+    fn encode(&self) -> Vec<u8> {
+        let (tag, a, b) = match self {
+            Node::Leaf { key, value } => (LEAF_TAG, key, value),
+            Node::Internal { left, right } => (INTERNAL_TAG, left, right),
+        };
+        let mut data = Vec::with_capacity(65);
+        data.push(tag);
+        data.extend_from_slice(a.as_bytes());
+        data.extend_from_slice(b.as_bytes());
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() != 65 {
+            return Err(BlockchainError::StateError(format!("Trie node has {} bytes, expected 65", data.len())));
+        }
+        let a = Hash256::from_slice(&data[1..33])?;
+        let b = Hash256::from_slice(&data[33..65])?;
+        match data[0] {
+            LEAF_TAG => Ok(Node::Leaf { key: a, value: b }),
+            INTERNAL_TAG => Ok(Node::Internal { left: a, right: b }),
+            tag => Err(BlockchainError::StateError(format!("Unknown trie node tag {}", tag))),
+        }
+    }
+
+    fn hash(&self) -> Hash256 {
+        sha256(&self.encode())
+    }
+}
+
+
+// Bit `depth` of `key`, most significant bit first
+fn bit(key: &Hash256, depth: usize) -> u8 {
+    (key.as_bytes()[depth / 8] >> (7 - depth % 8)) & 1
+}
+
+fn load<S: NodeSource + ?Sized>(source: &S, hash: &Hash256) -> Result<Node> {
+    let data = source.node(hash)?
+        .ok_or_else(|| BlockchainError::StateError(format!("Missing trie node {}", hash)))?;
+    Node::decode(&data)
+}
+
+
+/// Sparse Merkle tree over 256-bit keys.
+///
+/// Empty subtrees hash to zero and a subtree holding a single leaf is
+/// represented by that leaf, so the tree stays shallow (about log2(n) levels)
+/// and its root depends only on its contents, not on insertion order. Nodes are
+/// content-addressed in a [`NodeDatabase`], so earlier roots stay readable
+/// until they are pruned.
+#[derive(Debug, Clone)]
+pub struct SparseMerkleTree {
+    root: Hash256,
+    nodes: NodeDatabase,
+    /// nodes stored since the last `take_new_nodes`
+    new_nodes: Vec<Hash256>,
+}
+
+impl SparseMerkleTree {
+    pub fn new(config: PruningConfig) -> Self {
+        Self {
+            root: Hash256::zero(),
+            nodes: NodeDatabase::new(config),
+            new_nodes: Vec::new(),
+        }
+    }
+
+    /// Current root (zero for an empty tree)
+    pub fn root(&self) -> Hash256 {
+        self.root
+    }
+
+    pub fn nodes(&self) -> &NodeDatabase {
+        &self.nodes
+    }
+
+    /// Move to an earlier root whose nodes are still stored
+    pub fn set_root(&mut self, root: Hash256) -> Result<()> {
+        if !root.is_zero() && !self.nodes.contains(&root) {
+            return Err(BlockchainError::StateError(format!("Unknown state root {}", root)));
+        }
+        self.root = root;
+        Ok(())
+    }
+
+    /// Value stored under `key`
+    pub fn get(&self, key: &Hash256) -> Result<Option<Hash256>> {
+        let proof = self.prove(key)?;
+        Ok(proof.leaf.filter(|(leaf_key, _)| leaf_key == key).map(|(_, value)| value))
+    }
+
+    /// Set (`Some`) or remove (`None`) the value under `key`, returning the new root
+    pub fn update(&mut self, key: &Hash256, value: Option<Hash256>) -> Result<Hash256> {
+        self.root = self.update_at(self.root, 0, key, value)?;
+        Ok(self.root)
+    }
+
+    /// Inclusion or exclusion proof for `key` against the current root
+    pub fn prove(&self, key: &Hash256) -> Result<SmtProof> {
+        prove_from(&self.nodes, self.root, key)
+    }
+
+    /// Retain the current root at `height` in the node database (see [`NodeDatabase::commit_root`])
+    pub fn commit(&mut self, height: BlockHeight) -> Result<()> {
+        if self.root.is_zero() {
+            return Ok(());
+        }
+        self.nodes.commit_root(height, self.root)
+    }
+
+    /// Release roots committed above `height` (see [`NodeDatabase::rollback_to`])
+    pub fn rollback_to(&mut self, height: BlockHeight) {
+        self.nodes.rollback_to(height);
+    }
+
+    /// Encoded nodes stored since the last call, for writing to disk
+    pub fn take_new_nodes(&mut self) -> Vec<(Hash256, Vec<u8>)> {
+        std::mem::take(&mut self.new_nodes).into_iter()
+            .filter_map(|hash| self.nodes.get(&hash).map(|data| (hash, data.to_vec())))
+            .collect()
+    }
+
+    fn update_at(&mut self, node: Hash256, depth: usize, key: &Hash256, value: Option<Hash256>) -> Result<Hash256> {
+        if node.is_zero() {
+            return match value {
+                Some(value) => self.store(Node::Leaf { key: *key, value }),
+                None => Ok(node),
+            };
+        }
+
+        match load(&self.nodes, &node)? {
+            Node::Leaf { key: existing, .. } if existing == *key => match value {
+                Some(value) => self.store(Node::Leaf { key: *key, value }),
+                None => Ok(Hash256::zero()),
+            },
+            Node::Leaf { key: existing, .. } => match value {
+                Some(value) => {
+                    let leaf = self.store(Node::Leaf { key: *key, value })?;
+                    self.split(depth, (node, &existing), (leaf, key))
+                }
+                // removing a key that isn't there
+                None => Ok(node),
+            },
+            Node::Internal { left, right } => {
+                if bit(key, depth) == 0 {
+                    let left = self.update_at(left, depth + 1, key, value)?;
+                    self.join(left, right)
+                } else {
+                    let right = self.update_at(right, depth + 1, key, value)?;
+                    self.join(left, right)
+                }
+            }
+        }
+    }
+
+    // Parent of two subtrees, keeping the single-leaf-subtree rule
+    fn join(&mut self, left: Hash256, right: Hash256) -> Result<Hash256> {
+        match (left.is_zero(), right.is_zero()) {
+            (true, true) => Ok(Hash256::zero()),
+            (true, false) if self.is_leaf(&right)? => Ok(right),
+            (false, true) if self.is_leaf(&left)? => Ok(left),
+            _ => self.store(Node::Internal { left, right }),
+        }
+    }
+
+    // Smallest subtree holding two leaves, from `depth` down to where their keys diverge
+    fn split(&mut self, depth: usize, a: (Hash256, &Hash256), b: (Hash256, &Hash256)) -> Result<Hash256> {
+        let (a_bit, b_bit) = (bit(a.1, depth), bit(b.1, depth));
+        if a_bit == b_bit {
+            let child = self.split(depth + 1, a, b)?;
+            let zero = Hash256::zero();
+            return match a_bit {
+                0 => self.store(Node::Internal { left: child, right: zero }),
+                _ => self.store(Node::Internal { left: zero, right: child }),
+            };
+        }
+
+        match a_bit {
+            0 => self.store(Node::Internal { left: a.0, right: b.0 }),
+            _ => self.store(Node::Internal { left: b.0, right: a.0 }),
+        }
+    }
+
+    fn is_leaf(&self, hash: &Hash256) -> Result<bool> {
+        Ok(matches!(load(&self.nodes, hash)?, Node::Leaf { .. }))
+    }
+
+    fn store(&mut self, node: Node) -> Result<Hash256> {
+        let children = match node {
+            Node::Leaf { .. } => Vec::new(),
+            Node::Internal { left, right } => [left, right].into_iter().filter(|child| !child.is_zero()).collect(),
+        };
+
+        let hash = node.hash();
+        if !self.nodes.contains(&hash) {
+            self.nodes.insert(node.encode(), children)?;
+            self.new_nodes.push(hash);
+        }
+        Ok(hash)
+    }
+}
+
+impl Default for SparseMerkleTree {
+    fn default() -> Self {
+        Self::new(PruningConfig::default())
+    }
+}
+
+
+/// Proof that a key has a given value, or no value, under a root.
+///
+/// `siblings` are the hashes beside the path from the root down, and `leaf` is
+/// the leaf the path ends at (None if it ends in an empty subtree). For an
+/// absent key the path may end at another key's leaf.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SmtProof {
+    pub siblings: Vec<Hash256>,
+    pub leaf: Option<(Hash256, Hash256)>,
+}
+
+impl SmtProof {
+    /// Check that `key` maps to `value` (None: is absent) under `root`
+    pub fn verify(&self, root: &Hash256, key: &Hash256, value: Option<&Hash256>) -> bool {
+        let mut hash = match (&self.leaf, value) {
+            (Some((leaf_key, leaf_value)), Some(value)) => {
+                if leaf_key != key || leaf_value != value {
+                    return false;
+                }
+                Node::Leaf { key: *leaf_key, value: *leaf_value }.hash()
+            }
+            (Some((leaf_key, leaf_value)), None) => {
+                // another key's leaf, which must sit on our key's path
+                let on_path = (0..self.siblings.len()).all(|depth| bit(leaf_key, depth) == bit(key, depth));
+                if leaf_key == key || !on_path {
+                    return false;
+                }
+                Node::Leaf { key: *leaf_key, value: *leaf_value }.hash()
+            }
+            (None, Some(_)) => return false,
+            (None, None) => Hash256::zero(),
+        };
+
+        if self.siblings.len() > 256 {
+            return false;
+        }
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            let (left, right) = match bit(key, depth) {
+                0 => (hash, *sibling),
+                _ => (*sibling, hash),
+            };
+            hash = Node::Internal { left, right }.hash();
+        }
+        hash == *root
+    }
+}
+
+
+/// Build a proof for `key` under `root` from any node source
+pub fn prove_from<S: NodeSource + ?Sized>(source: &S, root: Hash256, key: &Hash256) -> Result<SmtProof> {
+    let mut siblings = Vec::new();
+    let mut node = root;
+
+    loop {
+        if node.is_zero() {
+            return Ok(SmtProof { siblings, leaf: None });
+        }
+
+        match load(source, &node)? {
+            Node::Leaf { key: leaf_key, value } => {
+                return Ok(SmtProof { siblings, leaf: Some((leaf_key, value)) });
+            }
+            Node::Internal { left, right } => {
+                let depth = siblings.len();
+                if bit(key, depth) == 0 {
+                    siblings.push(right);
+                    node = left;
+                } else {
+                    siblings.push(left);
+                    node = right;
+                }
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(n: u32) -> Hash256 {
+        sha256(&n.to_le_bytes())
+    }
+
+    #[test]
+    fn test_root_is_independent_of_insertion_order() {
+        let mut forward = SparseMerkleTree::default();
+        let mut backward = SparseMerkleTree::default();
+
+        for n in 0..50 {
+            forward.update(&key(n), Some(key(n + 1000))).unwrap();
+        }
+        for n in (0..50).rev() {
+            backward.update(&key(n), Some(key(n + 1000))).unwrap();
+        }
+
+        assert!(!forward.root().is_zero());
+        assert_eq!(forward.root(), backward.root());
+        assert_eq!(forward.get(&key(7)).unwrap(), Some(key(1007)));
+    }
+
+    #[test]
+    fn test_removal_restores_previous_root() {
+        let mut tree = SparseMerkleTree::default();
+        for n in 0..10 {
+            tree.update(&key(n), Some(key(n))).unwrap();
+        }
+        let root = tree.root();
+
+        tree.update(&key(99), Some(key(99))).unwrap();
+        assert_ne!(tree.root(), root);
+        tree.update(&key(99), None).unwrap();
+        assert_eq!(tree.root(), root);
+
+        for n in 0..10 {
+            tree.update(&key(n), None).unwrap();
+        }
+        assert!(tree.root().is_zero());
+    }
+
+    #[test]
+    fn test_inclusion_and_exclusion_proofs() {
+        let mut tree = SparseMerkleTree::default();
+        for n in 0..20 {
+            tree.update(&key(n), Some(key(n + 100))).unwrap();
+        }
+        let root = tree.root();
+
+        let proof = tree.prove(&key(3)).unwrap();
+        assert!(proof.verify(&root, &key(3), Some(&key(103))));
+        assert!(!proof.verify(&root, &key(3), Some(&key(104))));
+        assert!(!proof.verify(&root, &key(3), None));
+
+        let absent = tree.prove(&key(500)).unwrap();
+        assert!(absent.verify(&root, &key(500), None));
+        assert!(!absent.verify(&root, &key(500), Some(&key(600))));
+    }
+
+    #[test]
+    fn test_old_roots_remain_provable() {
+        let mut tree = SparseMerkleTree::default();
+        tree.update(&key(1), Some(key(10))).unwrap();
+        tree.update(&key(2), Some(key(20))).unwrap();
+        let old_root = tree.root();
+
+        tree.update(&key(1), Some(key(11))).unwrap();
+        tree.set_root(old_root).unwrap();
+        assert_eq!(tree.get(&key(1)).unwrap(), Some(key(10)));
+        assert!(tree.set_root(key(12345)).is_err());
+    }
+}
@@ -0,0 +1,266 @@
+use crate::types::*;
+use crate::block::Block;
+use crate::state::{account_key, utxo_key, AccountState, WorldState};
+use crate::transaction::UTXO;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{Address, Hash256, hash::sha256};
+use serde::{Deserialize, Serialize};
+
+
+/// Entries per chunk when the caller doesn't pick a size
+pub const DEFAULT_ENTRIES_PER_CHUNK: usize = 4096;
+
+
+/// One account or unspent output of a state snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SnapshotEntry {
+    Account(Address, AccountState),
+    Utxo(OutPoint, UTXO),
+}
+
+impl SnapshotEntry {
+    /// State trie key of the entry; snapshots list entries in key order
+    pub fn key(&self) -> Hash256 {
+        match self {
+            SnapshotEntry::Account(address, _) => account_key(address),
+            SnapshotEntry::Utxo(outpoint, _) => utxo_key(outpoint),
+        }
+    }
+}
+
+
+/// A run of consecutive snapshot entries, hashed on its own so it can be
+/// fetched, stored and checked independently of the others
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotChunk {
+    pub entries: Vec<SnapshotEntry>,
+}
+
+impl SnapshotChunk {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        bincode::serialize(self).map_err(|e| BlockchainError::SerializationError(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).map_err(|e| BlockchainError::SerializationError(e.to_string()))
+    }
+
+    /// Hash the manifest lists for this chunk
+    pub fn hash(&self) -> Result<Hash256> {
+        Ok(sha256(&self.to_bytes()?))
+    }
+}
+
+
+/// Describes a state snapshot: where it sits in the chain, the state root it
+/// must rebuild to and the hash of every chunk.
+///
+/// `recent_blocks` are the main chain blocks leading up to and including the
+/// snapshot block, enough for the importing node to check difficulty
+/// retargets of the blocks that follow. They are stored, not replayed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub chain_id: ChainId,
+    pub account_model: AccountModel,
+    pub height: BlockHeight,
+    pub block_id: BlockId,
+    pub state_root: Hash256,
+    pub chunk_hashes: Vec<Hash256>,
+    pub recent_blocks: Vec<Block>,
+    /// base fee and burned supply at the snapshot block; neither is covered
+    /// by the state root
+    #[serde(default)]
+    pub base_fee: GasPrice,
+    #[serde(default)]
+    pub burned: Amount,
+}
+
+impl SnapshotManifest {
+    /// Check the manifest is internally consistent: the recent blocks link up
+    /// and end at the snapshot block
+    pub fn validate(&self) -> Result<()> {
+        let tip = self.recent_blocks.last()
+            .ok_or_else(|| BlockchainError::InvalidChain("snapshot carries no blocks".to_string()))?;
+        if tip.id() != self.block_id || tip.height() != self.height {
+            return Err(BlockchainError::InvalidChain(format!(
+                "snapshot ends at block {} height {}, manifest says {} height {}",
+                tip.id(), tip.height(), self.block_id, self.height
+            )));
+        }
+        for pair in self.recent_blocks.windows(2) {
+            if pair[1].prev_hash() != pair[0].id() || pair[1].height() != pair[0].height() + 1 {
+                return Err(BlockchainError::InvalidChain(
+                    format!("snapshot block {} does not follow {}", pair[1].id(), pair[0].id())
+                ));
+            }
+        }
+        if let Some(block) = self.recent_blocks.iter().find(|block| block.header.chain_id != self.chain_id) {
+            return Err(BlockchainError::InvalidChain(
+                format!("snapshot block {} is from chain {}, not {}", block.id(), block.header.chain_id, self.chain_id)
+            ));
+        }
+        Ok(())
+    }
+}
+
+
+/// Split a world state into chunks of at most `entries_per_chunk` entries.
+/// Entries are sorted by trie key, so the same state always gives the same chunks.
+pub fn state_chunks(state: &WorldState, entries_per_chunk: usize) -> Vec<SnapshotChunk> {
+    let mut entries: Vec<SnapshotEntry> = state.accounts().iter()
+        .filter(|(_, account)| !account.is_empty())
+        .map(|(address, account)| SnapshotEntry::Account(*address, account.clone()))
+        .chain(state.utxo_set().iter().map(|(outpoint, utxo)| SnapshotEntry::Utxo(*outpoint, utxo.clone())))
+        .collect();
+    entries.sort_by_cached_key(SnapshotEntry::key);
+
+    entries.chunks(entries_per_chunk.max(1))
+        .map(|entries| SnapshotChunk { entries: entries.to_vec() })
+        .collect()
+}
+
+
+/// Collects the chunks of a snapshot, in any order and across restarts,
+/// and rebuilds the world state once all of them are in
+#[derive(Debug)]
+pub struct SnapshotImport {
+    manifest: SnapshotManifest,
+    chunks: Vec<Option<SnapshotChunk>>,
+}
+
+impl SnapshotImport {
+    pub fn new(manifest: SnapshotManifest) -> Result<Self> {
+        manifest.validate()?;
+        let chunks = vec![None; manifest.chunk_hashes.len()];
+        Ok(Self { manifest, chunks })
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// Accept chunk `index` if it hashes to what the manifest lists
+    pub fn add_chunk(&mut self, index: usize, chunk: SnapshotChunk) -> Result<()> {
+        let expected = self.manifest.chunk_hashes.get(index)
+            .ok_or_else(|| BlockchainError::StateError(format!("snapshot has no chunk {}", index)))?;
+        let hash = chunk.hash()?;
+        if hash != *expected {
+            return Err(BlockchainError::StateError(
+                format!("snapshot chunk {} hashes to {}, expected {}", index, hash, expected)
+            ));
+        }
+        self.chunks[index] = Some(chunk);
+        Ok(())
+    }
+
+    /// Indices of the chunks still to add
+    pub fn missing(&self) -> Vec<usize> {
+        self.chunks.iter().enumerate()
+            .filter(|(_, chunk)| chunk.is_none())
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.chunks.iter().all(Option::is_some)
+    }
+
+    /// Rebuild the world state and check it against the manifest's state root
+    pub fn into_state(self) -> Result<(SnapshotManifest, WorldState)> {
+        let missing = self.missing();
+        if !missing.is_empty() {
+            return Err(BlockchainError::StateError(format!("snapshot chunks {:?} are missing", missing)));
+        }
+
+        let mut state = WorldState::new(self.manifest.account_model);
+        for entry in self.chunks.into_iter().flatten().flat_map(|chunk| chunk.entries) {
+            match entry {
+                SnapshotEntry::Account(address, account) => state.set_account(address, account),
+                SnapshotEntry::Utxo(outpoint, utxo) => state.utxo_set_mut().add_utxo(outpoint, utxo)?,
+            }
+        }
+        state.set_block_height(self.manifest.height);
+        state.set_fee_state(self.manifest.base_fee, self.manifest.burned);
+
+        let state_root = state.state_root();
+        if state_root != self.manifest.state_root {
+            return Err(BlockchainError::StateError(format!(
+                "snapshot state root {} does not match the manifest's {}",
+                state_root, self.manifest.state_root
+            )));
+        }
+        Ok((self.manifest, state))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chain::Blockchain;
+    use crate::store::MemoryChainStore;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+
+    fn mined_chain(blocks: usize) -> (Blockchain, Address) {
+        let mut chain = Blockchain::default();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        for _ in 0..blocks {
+            chain.mine_block(miner).unwrap();
+        }
+        (chain, miner)
+    }
+
+    fn import(manifest: SnapshotManifest, chunks: Vec<SnapshotChunk>) -> Result<(SnapshotManifest, WorldState)> {
+        let mut import = SnapshotImport::new(manifest)?;
+        for (index, chunk) in chunks.into_iter().enumerate().rev() {
+            import.add_chunk(index, chunk)?;
+        }
+        import.into_state()
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let (mut chain, miner) = mined_chain(3);
+        let (manifest, chunks) = chain.export_state_snapshot(chain.height(), 2).unwrap();
+        assert!(chunks.len() > 1);
+        assert_eq!(manifest.block_id, chain.get_chain_head().unwrap().id());
+
+        let (manifest, state) = import(manifest, chunks).unwrap();
+        let store = MemoryChainStore::new();
+        let mut restored = Blockchain::from_state_snapshot(chain.config().clone(), Box::new(store), manifest, state).unwrap();
+
+        assert_eq!(restored.height(), chain.height());
+        assert_eq!(restored.get_balance(&miner), chain.get_balance(&miner));
+        assert_eq!(restored.world_state().current_state_root(), chain.world_state().current_state_root());
+
+        // both keep building on the same tip
+        let block = chain.mine_block(miner).unwrap();
+        restored.add_block(block).unwrap();
+        assert_eq!(restored.get_balance(&miner), chain.get_balance(&miner));
+    }
+
+    #[test]
+    fn test_snapshot_rejects_tampered_chunk() {
+        let (chain, _) = mined_chain(2);
+        let (manifest, mut chunks) = chain.export_state_snapshot(chain.height(), 2).unwrap();
+        if let Some(SnapshotEntry::Account(_, account)) = chunks[0].entries.first_mut() {
+            account.balance += 1;
+        } else if let Some(SnapshotEntry::Utxo(_, utxo)) = chunks[0].entries.first_mut() {
+            utxo.output.amount += 1;
+        }
+
+        let mut import = SnapshotImport::new(manifest).unwrap();
+        assert!(import.add_chunk(0, chunks.remove(0)).is_err());
+        assert_eq!(import.missing()[0], 0);
+        assert!(import.into_state().is_err());
+    }
+
+    #[test]
+    fn test_snapshot_rejects_wrong_state_root() {
+        let (chain, _) = mined_chain(2);
+        let (mut manifest, chunks) = chain.export_state_snapshot(chain.height(), DEFAULT_ENTRIES_PER_CHUNK).unwrap();
+        manifest.state_root = sha256(b"some other state");
+
+        assert!(import(manifest, chunks).is_err());
+    }
+}
@@ -1,5 +1,10 @@
 use crate::types::*;
+use crate::codec::{self, Encode};
+use crate::block::Block;
 use crate::transaction::{Transaction, UTXO};
+use crate::smt::{SmtProof, SparseMerkleTree};
+use crate::contract::{contract_address, ContractReceipt, ContractRuntime, ContractStatus};
+use crate::fee_market::{split_gas_fee, FeeMarketConfig};
 use crate::{BlockchainError, Result};
 use blockchain_crypto::{Hash256, Address, hash::sha256};
 use serde::{Deserialize, Serialize};
@@ -119,6 +124,11 @@ impl UTXOSet {
         Ok(())
     }
     
+    /// Every unspent output, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&OutPoint, &UTXO)> {
+        self.utxos.iter()
+    }
+
     /// Remove UTXO from the set
     pub fn remove_utxo(&mut self, outpoint: &OutPoint) -> Result<UTXO> {
         let utxo = self.utxos.remove(outpoint)
@@ -189,9 +199,18 @@ impl UTXOSet {
     
     /// Apply transaction to UTXO set
     pub fn apply_transaction(&mut self, tx: &Transaction, block_height: BlockHeight) -> Result<()> {
+        self.apply_transaction_with_undo(tx, block_height).map(|_| ())
+    }
+    
+    /// Apply transaction to UTXO set, returning the spent UTXOs and created
+    /// outpoints needed to revert it
+    pub fn apply_transaction_with_undo(&mut self, tx: &Transaction, block_height: BlockHeight) -> Result<TxUndo> {
+        let mut undo = TxUndo::default();
+        
         // Remove spent UTXOs (inputs)
         for input in &tx.inputs {
-            self.remove_utxo(&input.prev_output)?;
+            let spent = self.remove_utxo(&input.prev_output)?;
+            undo.spent.push((input.prev_output, spent));
         }
         
         // Add new UTXOs (outputs)
@@ -205,26 +224,21 @@ impl UTXOSet {
                 tx.is_coinbase(),
             );
             self.add_utxo(outpoint, utxo)?;
+            undo.created.push(outpoint);
         }
         
-        Ok(())
+        Ok(undo)
     }
     
-    /// Revert transaction from UTXO set
-    pub fn revert_transaction(&mut self, tx: &Transaction, block_height: BlockHeight) -> Result<()> {
-        // Remove UTXOs created by this transaction (outputs)
-        for (index, _) in tx.outputs.iter().enumerate() {
-            let outpoint = OutPoint::new(tx.id(), index as u32);
-            self.remove_utxo(&outpoint)?;
+    /// Revert a transaction from its undo record: created outputs are removed
+    /// and spent ones restored
+    pub fn revert_transaction(&mut self, undo: &TxUndo) -> Result<()> {
+        for outpoint in undo.created.iter().rev() {
+            self.remove_utxo(outpoint)?;
         }
         
-        // This would require having the UTXOs that were spent to restore them
-        // In a real implementation, you'd need to store them or look them up
-        // For now, we'll return an error indicating this needs more data
-        if !tx.inputs.is_empty() {
-            return Err(BlockchainError::StateError(
-                "Cannot revert transaction without original UTXOs".to_string()
-            ));
+        for (outpoint, utxo) in undo.spent.iter().rev() {
+            self.add_utxo(*outpoint, utxo.clone())?;
         }
         
         Ok(())
@@ -239,6 +253,51 @@ impl Default for UTXOSet {
 
 
 
+/// Trie key of an account
+pub fn account_key(address: &Address) -> Hash256 {
+    let mut data = b"account:".to_vec();
+    data.extend_from_slice(address.data());
+    sha256(&data)
+}
+
+/// Trie key of an unspent output
+pub fn utxo_key(outpoint: &OutPoint) -> Hash256 {
+    let mut data = b"utxo:".to_vec();
+    data.extend_from_slice(&codec::encode(outpoint));
+    sha256(&data)
+}
+
+/// Trie value committing to an account. Empty accounts are left out of the trie.
+pub fn account_commitment(account: &AccountState) -> Option<Hash256> {
+    if account.is_empty() {
+        return None;
+    }
+
+    // metadata is a HashMap, so sort it for a stable encoding
+    let mut metadata: Vec<_> = account.metadata.iter().collect();
+    metadata.sort();
+    let mut data = vec![codec::CODEC_VERSION];
+    account.balance.encode_to(&mut data);
+    account.nonce.encode_to(&mut data);
+    account.storage_root.encode_to(&mut data);
+    account.code_hash.encode_to(&mut data);
+    metadata.encode_to(&mut data);
+    Some(sha256(&data))
+}
+
+fn utxo_commitment(utxo: &UTXO) -> Hash256 {
+    sha256(&codec::encode(utxo))
+}
+
+
+/// A state entry whose trie leaf is out of date
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum StateKey {
+    Account(Address),
+    Utxo(OutPoint),
+}
+
+
 /// World state combining both account and UTXO models
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorldState {
@@ -252,6 +311,30 @@ pub struct WorldState {
     block_height: BlockHeight,
     ///account model type
     model_type: AccountModel,
+    ///base fee per gas the next block's account transactions pay
+    #[serde(default)]
+    base_fee: GasPrice,
+    ///base fees burned so far
+    #[serde(default)]
+    burned: Amount,
+    ///how the base fee follows block fullness; no base fee without one
+    #[serde(default)]
+    fee_market: Option<FeeMarketConfig>,
+    ///sparse merkle trie over accounts and utxos, rebuilt after deserializing
+    #[serde(skip)]
+    trie: SparseMerkleTree,
+    ///entries changed since the trie was last updated
+    #[serde(skip)]
+    dirty: HashSet<StateKey>,
+    ///false when the trie has to be rebuilt from the full state
+    #[serde(skip)]
+    trie_synced: bool,
+    ///runs contract code, caching compiled modules
+    #[serde(skip)]
+    contracts: ContractRuntime,
+    ///block whose transactions are being applied, seeding contract randomness
+    #[serde(skip)]
+    executing_block: Option<BlockId>,
 }
 
 
@@ -264,10 +347,26 @@ impl WorldState {
             state_root: Hash256::zero(),
             block_height: 0,
             model_type,
+            base_fee: 0,
+            burned: 0,
+            fee_market: None,
+            trie: SparseMerkleTree::default(),
+            dirty: HashSet::new(),
+            trie_synced: true,
+            contracts: ContractRuntime::default(),
+            executing_block: None,
         }
     }
 
 
+    ///charge a base fee set by `fee_market`, starting at its initial base fee
+    pub fn with_fee_market(mut self, fee_market: Option<FeeMarketConfig>) -> Self {
+        self.base_fee = fee_market.map_or(0, |fee_market| fee_market.initial_base_fee);
+        self.fee_market = fee_market;
+        self
+    }
+
+
     ///get account state
     pub fn get_account(&self, address: &Address) -> AccountState {
         self.accounts.get(address)
@@ -277,6 +376,8 @@ impl WorldState {
 
     ///get mutabble account state
     pub fn get_account_mut(&mut self, address: &Address) -> &mut AccountState{
+        self.dirty.insert(StateKey::Account(*address));
+        self.invalidate_state_root();
         self.accounts.entry(*address)
             .or_insert_with(&AccountState::empty)
     }
@@ -291,6 +392,7 @@ impl WorldState {
             self.accounts.insert(address, state);
         }
 
+        self.dirty.insert(StateKey::Account(address));
         self.invalidate_state_root();
     }
 
@@ -339,35 +441,145 @@ impl WorldState {
 
     ///Apply transaction to world state
     pub fn apply_transaction(&mut self, tx: &Transaction) -> Result<()> {
-        match self.model_type {
-            AccountModel::UTXO => {
-                self.apply_utxo_balance(tx)
+        self.apply_transaction_with_undo(tx).map(|_| ())
+    }
+
+
+    ///Apply transaction to world state, returning what it changed so it can be reverted
+    pub fn apply_transaction_with_undo(&mut self, tx: &Transaction) -> Result<TxUndo> {
+        let utxo_transaction = match self.model_type {
+            AccountModel::UTXO => true,
+            AccountModel::Account => false,
+            //try to determine transaction type and apply accordingly
+            AccountModel::Hybrid => !tx.inputs.is_empty() || !tx.outputs.is_empty(),
+        };
+
+        if utxo_transaction {
+            return self.apply_utxo_balance(tx);
+        }
+
+        //remember the accounts as they were before the transaction
+        let mut touched: Vec<Address> = tx.from.iter().chain(tx.to.iter()).copied().collect();
+        if let (TransactionType::ContractDeployment, Some(from)) = (tx.tx_type, &tx.from) {
+            touched.push(contract_address(from, self.get_account(from).nonce));
+        }
+        touched.dedup();
+        let accounts = touched.into_iter()
+            .map(|address| (address, self.accounts.get(&address).cloned()))
+            .collect();
+
+        let receipt = self.apply_account_transaction(tx)?;
+        let gas_used = match (&receipt, tx.gas_price) {
+            (Some(receipt), _) => receipt.gas_used,
+            (None, Some(_)) if !tx.is_coinbase() => tx.gas_limit.unwrap_or(0),
+            _ => 0,
+        };
+        Ok(TxUndo { accounts, receipt, gas_used, ..TxUndo::default() })
+    }
+
+
+    ///Apply every transaction in a block, returning the block's undo record.
+    ///The base fee part of what account transactions pay for gas is burned,
+    ///the tips go to the coinbase recipient, and the base fee moves on to the
+    ///next block's
+    pub fn apply_block(&mut self, block: &Block) -> Result<BlockUndo> {
+        self.set_executing_block(block.id());
+        let base_fee = self.base_fee;
+        let (mut gas_used, mut burned, mut tips): (Gas, Amount, Amount) = (0, 0, 0);
+        let mut transactions = Vec::with_capacity(block.transactions().len());
+        for tx in block.transactions() {
+            let undo = self.apply_transaction_with_undo(tx)?;
+            let (tx_burned, tx_tip) = split_gas_fee(undo.gas_used, tx.gas_price.unwrap_or(0), base_fee);
+            gas_used = gas_used.saturating_add(undo.gas_used);
+            burned = burned.saturating_add(tx_burned);
+            tips = tips.saturating_add(tx_tip);
+            transactions.push(undo);
+        }
+
+        //without a coinbase recipient the tips are burned too
+        let producer = block.transactions().first()
+            .filter(|coinbase| coinbase.is_coinbase())
+            .and_then(|coinbase| coinbase.to.or_else(|| coinbase.outputs.first().map(|output| output.address)));
+        let producer = match producer {
+            Some(producer) if tips > 0 => {
+                let before = self.accounts.get(&producer).cloned();
+                self.get_account_mut(&producer).add_balance(tips)?;
+                Some((producer, before))
+            }
+            _ => {
+                burned = burned.saturating_add(tips);
+                None
             }
+        };
+
+        self.burned = self.burned.saturating_add(burned);
+        if let Some(fee_market) = self.fee_market {
+            self.base_fee = fee_market.next_base_fee(base_fee, gas_used);
+        }
+
+        Ok(BlockUndo {
+            block_id: block.id(),
+            height: block.height(),
+            transactions,
+            base_fee,
+            burned,
+            producer,
+        })
+    }
 
-            AccountModel::Account => {
-                self.apply_account_transaction(tx)
+
+    ///Revert one transaction from its undo record
+    pub fn revert_transaction(&mut self, undo: &TxUndo) -> Result<()> {
+        self.utxo_set.revert_transaction(undo)?;
+        for outpoint in undo.created.iter().chain(undo.spent.iter().map(|(outpoint, _)| outpoint)) {
+            self.dirty.insert(StateKey::Utxo(*outpoint));
+        }
+
+        for (address, account) in undo.accounts.iter().rev() {
+            match account {
+                Some(account) => { self.accounts.insert(*address, account.clone()); }
+                None => { self.accounts.shift_remove(address); }
             }
-            AccountModel::Hybrid =>{
+            self.dirty.insert(StateKey::Account(*address));
+        }
 
-                //try to determine transaction type and apply accordingly
-                if !tx.inputs.is_empty() || !tx.outputs.is_empty() {
-                    self.apply_utxo_balance(tx)
-                }else{
-                    self.apply_account_transaction(tx)
-                }
+        self.invalidate_state_root();
+        Ok(())
+    }
+
+
+    ///Revert a block from its undo record, leaving the state as of the previous block
+    pub fn revert_block(&mut self, undo: &BlockUndo) -> Result<()> {
+        if let Some((producer, account)) = &undo.producer {
+            match account {
+                Some(account) => { self.accounts.insert(*producer, account.clone()); }
+                None => { self.accounts.shift_remove(producer); }
             }
+            self.dirty.insert(StateKey::Account(*producer));
+            self.invalidate_state_root();
+        }
+        self.base_fee = undo.base_fee;
+        self.burned = self.burned.saturating_sub(undo.burned);
 
+        for tx_undo in undo.transactions.iter().rev() {
+            self.revert_transaction(tx_undo)?;
         }
+        self.block_height = undo.height.saturating_sub(1);
+        Ok(())
     }
 
-    fn apply_utxo_balance(&mut self, tx: &Transaction) -> Result<()> {
-        self.utxo_set.apply_transaction(tx, self.block_height)?;
+    fn apply_utxo_balance(&mut self, tx: &Transaction) -> Result<TxUndo> {
+        let undo = self.utxo_set.apply_transaction_with_undo(tx, self.block_height)?;
+
+        for outpoint in undo.created.iter().chain(undo.spent.iter().map(|(outpoint, _)| outpoint)) {
+            self.dirty.insert(StateKey::Utxo(*outpoint));
+        }
         self.invalidate_state_root();
-        Ok(())
+        Ok(undo)
     }
 
-    //Apply account-based transaction
-    fn apply_account_transaction(&mut self, tx: &Transaction) -> Result<()> {
+    //Apply account-based transaction, returning the receipt of a contract transaction
+    fn apply_account_transaction(&mut self, tx: &Transaction) -> Result<Option<ContractReceipt>> {
         //skip coinbase transactions for account model
 
 
@@ -380,20 +592,23 @@ impl WorldState {
             }
 
             self.invalidate_state_root();
-            return Ok(());
+            return Ok(None);
         }
 
         let from = tx.from.ok_or_else(||
             BlockchainError::InvalidTransaction("Missing sender address".to_string())
             )?;
 
-        let to = tx.to.ok_or_else(||
-            BlockchainError::InvalidTransaction("Missing recipient address".to_string())
-            )?;
+        let gas_price = tx.gas_price.unwrap_or(0);
+        if gas_price < self.base_fee {
+            return Err(BlockchainError::InvalidTransaction(
+                format!("Gas price {} is below the base fee {}", gas_price, self.base_fee)
+                ));
+        }
 
         let amount = tx.amount.unwrap_or(0);
         let gas_fee = tx.calculate_gas_fee();
-        let total_cost = amount + gas_fee;
+        let total_cost = amount.saturating_add(gas_fee);
 
 
         //check sender balance and nonce
@@ -415,6 +630,14 @@ impl WorldState {
             }
         }
 
+        if matches!(tx.tx_type, TransactionType::ContractDeployment | TransactionType::ContractCall) {
+            return self.apply_contract_transaction(tx, &from).map(Some);
+        }
+
+        let to = tx.to.ok_or_else(||
+            BlockchainError::InvalidTransaction("Missing recipient address".to_string())
+            )?;
+
 
         //apply transaction
         if amount > 0 {
@@ -435,18 +658,68 @@ impl WorldState {
 
 
         self.invalidate_state_root();
-        Ok(())
+        Ok(None)
 
     }
 
+    //Deploy or call a contract. The sender can cover value and the whole gas
+    //limit, checked by the caller; failures are recorded in the receipt.
+    fn apply_contract_transaction(&mut self, tx: &Transaction, from: &Address) -> Result<ContractReceipt> {
+        let amount = tx.amount.unwrap_or(0);
+        let gas_limit = tx.gas_limit.unwrap_or(0);
+        let gas_price = tx.gas_price.unwrap_or(0);
+
+        let contract = match tx.tx_type {
+            TransactionType::ContractDeployment => contract_address(from, self.get_account(from).nonce),
+            _ => tx.to.clone().ok_or_else(||
+                BlockchainError::InvalidTransaction("Missing contract address".to_string())
+                )?,
+        };
+
+        let mut account = self.get_account(&contract);
+        let executed = match tx.tx_type {
+            TransactionType::ContractDeployment if account.is_contract() =>
+                Err(format!("a contract is already deployed at {}", contract)),
+            TransactionType::ContractDeployment => self.contracts.deploy(&tx.data, gas_limit)
+                .map(|gas_used| {
+                    account.set_code(tx.data.clone());
+                    (gas_used, Vec::new())
+                }),
+            _ => self.contracts.call(&contract, &account, from, &tx.data, gas_limit, self.block_height, &self.executing_block.unwrap_or_else(BlockId::genesis))
+                .map(|(gas_used, storage, logs)| {
+                    account.set_contract_storage(storage);
+                    (gas_used, logs)
+                }),
+        };
+
+        let (gas_used, status, logs) = match executed {
+            Ok((gas_used, logs)) => {
+                self.set_account(contract.clone(), account);
+                self.transfer(from, &contract, amount)?;
+                (gas_used, ContractStatus::Success, logs)
+            }
+            Err(reason) => (gas_limit, ContractStatus::Failed(reason), Vec::new()),
+        };
+
+        //pay for the gas used; the rest of the limit was only reserved
+        let sender_account = self.get_account_mut(from);
+        sender_account.sub_balance(gas_used.saturating_mul(gas_price))?;
+        sender_account.increment_nonce();
+
+        self.invalidate_state_root();
+        Ok(ContractReceipt { contract, gas_used, status, logs })
+    }
+
     //getutxo set
     pub fn utxo_set(&self) -> &UTXOSet {
         &self.utxo_set
     }
 
 
-    //get mutable utxo set
+    //get mutable utxo set. changes made through it aren't tracked, so the trie is rebuilt
     pub fn utxo_set_mut(&mut self) -> &mut UTXOSet{
+        self.trie_synced = false;
+        self.invalidate_state_root();
         &mut self.utxo_set
     }
 
@@ -456,38 +729,111 @@ impl WorldState {
     }
 
 
-    ///Calculate state root hash
+    ///Calculate the state root from scratch: the root of a sparse merkle trie
+    ///holding every non-empty account and every unspent output
     pub fn calculate_state_root_hash(&self) -> Hash256 {
-        use sha2::{Sha256, Digest};
-        let mut hasher = Sha256::new();
-
-        //hash account states
-        for (address, account) in &self.accounts {
-            let account_data = bincode::serialize(&(address, account)).unwrap_or_default();
-            hasher.update(&account_data);
+        let mut trie = SparseMerkleTree::default();
+        // a fresh trie has every node it needs, so this can't fail
+        match Self::fill_trie(&mut trie, &self.accounts, &self.utxo_set) {
+            Ok(()) => trie.root(),
+            Err(_) => Hash256::zero(),
         }
+    }
 
-        //hash utxo-set
-        let mut utxo_hashes: Vec<_> = self.utxo_set.iter().collect();
-        utxo_hashes.sort_by_key(|(outpoint, _)| *outpoint);
 
-        for (outpoint, utxo) in utxo_hashes{
-            let utxo_data = bincode::serialize(&(outpoint, utxo)).unwrap_or_default();
-            hasher.update(&utxo_data);
+    ///update and get state root. only entries changed since the last call are
+    ///written to the trie
+    pub fn state_root(&mut self) -> Hash256 {
+        if self.state_root.is_zero() {
+            self.state_root = match self.sync_trie() {
+                Ok(root) => root,
+                Err(_) => {
+                    //a trie node went missing; rebuild from the full state
+                    self.trie_synced = false;
+                    self.sync_trie().unwrap_or_else(|_| self.calculate_state_root_hash())
+                }
+            };
         }
+        self.state_root
+    }
+
+
+    ///prove an account's state (or its absence) against the current state root
+    pub fn prove_account(&mut self, address: &Address) -> Result<AccountProof> {
+        let state_root = self.sync_trie()?;
+        self.state_root = state_root;
 
-        Hash256::from_bytes(hasher.finalize().into())
+        Ok(AccountProof {
+            address: *address,
+            account: self.accounts.get(address).filter(|account| !account.is_empty()).cloned(),
+            state_root,
+            proof: self.trie.prove(&account_key(address))?,
+        })
     }
 
 
-    ///update and get state root
-    pub fn state_root(&mut self) -> Hash256 {
-        if self.state_root.is_zero() {
-            self.state_root = self.calculate_state_root_hash();
+    ///compute the state root and retain it at this height so it stays provable
+    pub fn commit_state_root(&mut self, height: BlockHeight) -> Result<Hash256> {
+        let root = self.sync_trie()?;
+        self.state_root = root;
+        self.trie.commit(height)?;
+        Ok(root)
+    }
+
+
+    ///release state roots committed above this height (a reorg disconnected them)
+    pub fn rollback_state_roots(&mut self, height: BlockHeight) {
+        self.trie.rollback_to(height);
+    }
+
+
+    ///the state trie
+    pub fn state_trie(&self) -> &SparseMerkleTree {
+        &self.trie
+    }
 
+
+    ///trie nodes created since the last call, for persisting
+    pub fn take_new_state_nodes(&mut self) -> Vec<(Hash256, Vec<u8>)> {
+        self.trie.take_new_nodes()
+    }
+
+
+    //bring the trie up to date with the state, returning its root
+    fn sync_trie(&mut self) -> Result<Hash256> {
+        if !self.trie_synced {
+            self.trie.set_root(Hash256::zero())?;
+            Self::fill_trie(&mut self.trie, &self.accounts, &self.utxo_set)?;
+            self.dirty.clear();
+            self.trie_synced = true;
+            return Ok(self.trie.root());
         }
-        self.state_root
 
+        for key in std::mem::take(&mut self.dirty) {
+            match key {
+                StateKey::Account(address) => {
+                    let value = self.accounts.get(&address).and_then(account_commitment);
+                    self.trie.update(&account_key(&address), value)?;
+                }
+                StateKey::Utxo(outpoint) => {
+                    let value = self.utxo_set.get_utxo(&outpoint).map(utxo_commitment);
+                    self.trie.update(&utxo_key(&outpoint), value)?;
+                }
+            }
+        }
+        Ok(self.trie.root())
+    }
+
+    fn fill_trie(trie: &mut SparseMerkleTree, accounts: &IndexMap<Address, AccountState>, utxo_set: &UTXOSet) -> Result<()> {
+        for (address, account) in accounts {
+            if let Some(value) = account_commitment(account) {
+                trie.update(&account_key(address), Some(value))?;
+            }
+        }
+        for (outpoint, utxo) in &utxo_set.utxos {
+            trie.update(&utxo_key(outpoint), Some(utxo_commitment(utxo)))?;
+        }
+        Ok(())
     }
 
 
@@ -502,6 +848,12 @@ impl WorldState {
         self.state_root = Hash256::zero();
     }
 
+    ///seed contract randomness from `block_id` for the transactions applied
+    ///from now on, as `apply_block` does for its own
+    pub fn set_executing_block(&mut self, block_id: BlockId) {
+        self.executing_block = Some(block_id);
+    }
+
     //set block height
     pub fn set_block_height(&mut self, height: BlockHeight) {
         self.block_height = block_height;
@@ -512,6 +864,31 @@ impl WorldState {
         self.block_height
     }
 
+    ///base fee per gas the next block's account transactions pay
+    pub fn base_fee(&self) -> GasPrice {
+        self.base_fee
+    }
+
+    ///base fees (and unclaimed tips) burned so far
+    pub fn burned_supply(&self) -> Amount {
+        self.burned
+    }
+
+    pub fn fee_market(&self) -> Option<&FeeMarketConfig> {
+        self.fee_market.as_ref()
+    }
+
+    ///use `fee_market` from the next block on, keeping the current base fee
+    pub fn set_fee_market(&mut self, fee_market: Option<FeeMarketConfig>) {
+        self.fee_market = fee_market;
+    }
+
+    ///set the base fee and burned supply, for a state rebuilt from a snapshot
+    pub fn set_fee_state(&mut self, base_fee: GasPrice, burned: Amount) {
+        self.base_fee = base_fee;
+        self.burned = burned;
+    }
+
 
     /// Create snapshot of current state(Claudie direct)
     pub fn snapshot(&self) -> WorldStateSnapshot {
@@ -520,6 +897,8 @@ impl WorldState {
             utxo_set: self.utxo_set.clone(),
             state_root: self.state_root,
             block_height: self.block_height,
+            base_fee: self.base_fee,
+            burned: self.burned,
         }
     }
 
@@ -530,6 +909,12 @@ impl WorldState {
         self.utxo_set = snapshot.utxo_set;
         self.state_root = snapshot.state_root;
         self.block_height = snapshot.block_height;
+        self.base_fee = snapshot.base_fee;
+        self.burned = snapshot.burned;
+
+        //reuse the trie if it still holds the snapshot's root, otherwise rebuild it
+        self.dirty.clear();
+        self.trie_synced = !snapshot.state_root.is_zero() && self.trie.set_root(snapshot.state_root).is_ok();
     }
 
 
@@ -546,6 +931,28 @@ impl WorldState {
     }
 
 
+    /// The `limit` largest balances, largest first, counted the way
+    /// `get_balance` counts them for this model; ties go to the lower address
+    pub fn richest_addresses(&self, limit: usize) -> Vec<(Address, Amount)> {
+        let mut balances: Vec<(Address, Amount)> = match self.model_type {
+            AccountModel::Account | AccountModel::Hybrid => self.accounts.iter()
+                .filter(|(_, account)| account.balance > 0)
+                .map(|(address, account)| (address.clone(), account.balance))
+                .collect(),
+            AccountModel::UTXO => {
+                let mut balances: HashMap<Address, Amount> = HashMap::new();
+                for (_, utxo) in self.utxo_set.iter() {
+                    *balances.entry(utxo.output.address.clone()).or_default() += utxo.output.amount;
+                }
+                balances.into_iter().collect()
+            }
+        };
+        balances.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.data().cmp(b.0.data())));
+        balances.truncate(limit);
+        balances
+    }
+
+
     pub fn validate(&self) -> Result<()> {
         //validate utxo set internal consistency
         let calculated_total: Amount = selt.utxo_set.utxos.values()
@@ -589,6 +996,73 @@ pub struct WorldStateSnapshot {
     utxo_set: UTXOSet,
     state_root: Hash256,
     block_height: BlockHeight,
+    #[serde(default)]
+    base_fee: GasPrice,
+    #[serde(default)]
+    burned: Amount,
+}
+
+impl WorldStateSnapshot {
+    pub fn block_height(&self) -> BlockHeight {
+        self.block_height
+    }
+}
+
+
+
+/// What one transaction changed in the world state
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxUndo {
+    /// outputs the transaction spent, in input order
+    pub spent: Vec<(OutPoint, UTXO)>,
+    /// outputs the transaction created
+    pub created: Vec<OutPoint>,
+    /// accounts it touched, as they were before (None: the account didn't exist)
+    pub accounts: Vec<(Address, Option<AccountState>)>,
+    /// gas used and status of a contract deployment or call
+    #[serde(default)]
+    pub receipt: Option<ContractReceipt>,
+    /// gas an account transaction paid for
+    #[serde(default)]
+    pub gas_used: Gas,
+}
+
+
+/// Undo record for a main chain block: one entry per transaction, in block
+/// order. Stored alongside the block so a reorg can disconnect it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockUndo {
+    pub block_id: BlockId,
+    pub height: BlockHeight,
+    pub transactions: Vec<TxUndo>,
+    /// base fee the block's transactions paid
+    #[serde(default)]
+    pub base_fee: GasPrice,
+    /// base fees the block burned
+    #[serde(default)]
+    pub burned: Amount,
+    /// producer account as it was before being paid the block's tips
+    #[serde(default)]
+    pub producer: Option<(Address, Option<AccountState>)>,
+}
+
+
+/// Merkle proof of an account's state under a state root
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountProof {
+    pub address: Address,
+    /// None if the account doesn't exist (or is empty)
+    pub account: Option<AccountState>,
+    pub state_root: Hash256,
+    pub proof: SmtProof,
+}
+
+impl AccountProof {
+    /// Check the proof against a trusted state root
+    pub fn verify(&self, state_root: &Hash256) -> bool {
+        let value = self.account.as_ref().and_then(account_commitment);
+        self.proof.verify(state_root, &account_key(&self.address), value.as_ref())
+    }
 }
 
 
@@ -596,7 +1070,7 @@ pub struct WorldStateSnapshot {
 mod tests {
     use super::*;
     use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
-    use crate::transaction::TransactionOutput;
+    use crate::transaction::{TransactionInput, TransactionOutput};
 
     #[test]
     fn test_account_state() {
@@ -683,6 +1157,32 @@ mod tests {
         assert_eq!(world_state.utxo_set().len(), 1);
     }
 
+    #[test]
+    fn test_richest_addresses() {
+        let addresses: Vec<Address> = (0..3)
+            .map(|_| public_key_to_address(generate_keypair().public_key(), AddressType::Base58))
+            .collect();
+
+        let mut world_state = WorldState::new(AccountModel::Account);
+        world_state.set_account(addresses[0].clone(), AccountState::new(100));
+        world_state.set_account(addresses[1].clone(), AccountState::new(300));
+        world_state.set_account(addresses[2].clone(), AccountState::new(0));
+        assert_eq!(
+            world_state.richest_addresses(5),
+            vec![(addresses[1].clone(), 300), (addresses[0].clone(), 100)]
+        );
+        assert_eq!(world_state.richest_addresses(1).len(), 1);
+
+        let mut world_state = WorldState::new(AccountModel::UTXO);
+        world_state.apply_transaction(&Transaction::new_coinbase(addresses[0].clone(), 50, 1)).unwrap();
+        world_state.apply_transaction(&Transaction::new_coinbase(addresses[0].clone(), 70, 2)).unwrap();
+        world_state.apply_transaction(&Transaction::new_coinbase(addresses[2].clone(), 100, 3)).unwrap();
+        assert_eq!(
+            world_state.richest_addresses(5),
+            vec![(addresses[0].clone(), 120), (addresses[2].clone(), 100)]
+        );
+    }
+
     #[test]
     fn test_world_state_snapshot() {
         let keypair = generate_keypair();
@@ -717,8 +1217,111 @@ mod tests {
         let result = world_state.transfer(&addr1, &addr2, 200);
         assert!(matches!(result, Err(BlockchainError::InsufficientBalance { .. })));
     }
-}
 
+    #[test]
+    fn test_incremental_state_root_matches_full_rebuild() {
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+
+        let mut world_state = WorldState::new(AccountModel::Account);
+        world_state.set_account(addr1, AccountState::new(1000));
+        world_state.set_account(addr2, AccountState::new(500));
+        let first_root = world_state.state_root();
+        assert!(!first_root.is_zero());
 
+        world_state.transfer(&addr1, &addr2, 200).unwrap();
+        let root = world_state.state_root();
+        assert_ne!(root, first_root);
+        assert_eq!(root, world_state.calculate_state_root_hash());
+
+        // Same contents inserted in another order give the same root
+        let mut reordered = WorldState::new(AccountModel::Account);
+        reordered.set_account(addr2, AccountState::new(700));
+        reordered.set_account(addr1, AccountState::new(800));
+        assert_eq!(reordered.state_root(), root);
+    }
 
+    #[test]
+    fn test_prove_account() {
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
 
+        let mut world_state = WorldState::new(AccountModel::Account);
+        world_state.set_account(addr1, AccountState::new(1000));
+        let root = world_state.commit_state_root(1).unwrap();
+
+        let proof = world_state.prove_account(&addr1).unwrap();
+        assert_eq!(proof.state_root, root);
+        assert_eq!(proof.account.as_ref().map(|account| account.balance), Some(1000));
+        assert!(proof.verify(&root));
+
+        // A forged balance doesn't verify
+        let mut forged = proof.clone();
+        forged.account = Some(AccountState::new(5000));
+        assert!(!forged.verify(&root));
+
+        // Absent accounts are provably absent
+        let absent = world_state.prove_account(&addr2).unwrap();
+        assert!(absent.account.is_none());
+        assert!(absent.verify(&root));
+    }
+
+    #[test]
+    fn test_revert_utxo_transaction_from_undo() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+
+        let mut world_state = WorldState::new(AccountModel::UTXO);
+        let coinbase_tx = Transaction::new_coinbase(address, 5000, 1);
+        world_state.apply_transaction(&coinbase_tx).unwrap();
+        let root_before = world_state.state_root();
+
+        let input = TransactionInput::new(
+            OutPoint::new(coinbase_tx.id(), 0),
+            keypair.sign(b"spend"),
+            keypair.public_key(),
+        );
+        let spend = Transaction::new_utxo(
+            vec![input],
+            vec![TransactionOutput::new(3000, address), TransactionOutput::new(1900, address)],
+            100,
+        );
+
+        let undo = world_state.apply_transaction_with_undo(&spend).unwrap();
+        assert_eq!(undo.spent.len(), 1);
+        assert_eq!(undo.created.len(), 2);
+        assert_eq!(world_state.utxo_set().len(), 2);
+
+        world_state.revert_transaction(&undo).unwrap();
+        assert_eq!(world_state.utxo_set().len(), 1);
+        assert!(world_state.utxo_set().contains(&OutPoint::new(coinbase_tx.id(), 0)));
+        assert_eq!(world_state.get_balance(&address), 5000);
+        assert_eq!(world_state.state_root(), root_before);
+    }
+
+    #[test]
+    fn test_revert_account_transaction_from_undo() {
+        let keypair1 = generate_keypair();
+        let keypair2 = generate_keypair();
+        let addr1 = public_key_to_address(keypair1.public_key(), AddressType::Base58);
+        let addr2 = public_key_to_address(keypair2.public_key(), AddressType::Base58);
+
+        let mut world_state = WorldState::new(AccountModel::Account);
+        world_state.set_account(addr1, AccountState::new(1_000_000));
+        let root_before = world_state.state_root();
+
+        let tx = Transaction::new_account(addr1, addr2, 500, 0, 21000, 1, vec![]);
+        let undo = world_state.apply_transaction_with_undo(&tx).unwrap();
+        assert_eq!(world_state.get_balance(&addr2), 500);
+
+        world_state.revert_transaction(&undo).unwrap();
+        assert_eq!(world_state.get_balance(&addr1), 1_000_000);
+        assert_eq!(world_state.get_account(&addr1).nonce, 0);
+        assert!(!world_state.accounts().contains_key(&addr2));
+        assert_eq!(world_state.state_root(), root_before);
+    }
+}
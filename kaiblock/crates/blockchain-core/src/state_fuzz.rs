@@ -0,0 +1,567 @@
+//! Stateful fuzzing of the mempool, chain and UTXO set (enabled with the `testing` feature).
+//!
+//! A script of random [`Op`]s (transfers, double-spend attempts, mining,
+//! reorgs, evictions, restarts) is run against one chain, and global
+//! invariants are checked after every step. A failing script is shrunk to a
+//! minimal one that still fails, printed so it can be pasted into a test.
+//!
+//! ```ignore
+//! if let Err(failure) = state_fuzz::fuzz(seed, &FuzzConfig::default()) {
+//!     panic!("{}", failure);
+//! }
+//! ```
+//!
+//! Ops refer to wallets and outputs by index, wrapping around whatever exists
+//! when they run, so any subsequence of a script is still a valid script.
+//! An op the chain rejects (an immature coinbase spend, a replacement paying
+//! too little) is not a failure; only a broken invariant is.
+
+use crate::types::*;
+use crate::chain::{Blockchain, ChainConfig};
+use crate::dev_accounts::derive_keypair;
+use crate::store::MemoryChainStore;
+use crate::transaction::{Transaction, TransactionInput, TransactionOutput};
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{signature::Keypair, address::public_key_to_address, Address, AddressType, Signature};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+
+/// Seed phrase the harness wallets are derived from
+const WALLET_SEED_PHRASE: &str = "state fuzz state fuzz state fuzz state fuzz";
+
+
+/// One step of a fuzz script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// pay `to` from the `pick`th output `from` can spend, confirmed or pooled
+    Transfer { from: u8, to: u8, pick: u8, fee: u16 },
+    /// spend again the `pick`th output a pooled transaction of `from` already spends
+    DoubleSpend { from: u8, to: u8, pick: u8, fee: u16 },
+    Mine { miner: u8 },
+    /// replace the top `depth` blocks with a heavier branch mined by `miner`
+    Reorg { depth: u8, miner: u8 },
+    /// drop the `pick`th pooled transaction and everything spending its outputs
+    Evict { pick: u8 },
+    /// reopen the chain from its store
+    Restart,
+}
+
+
+/// Shape of generated scripts
+#[derive(Debug, Clone)]
+pub struct FuzzConfig {
+    pub wallets: usize,
+    pub steps: usize,
+    /// deepest reorg generated
+    pub max_reorg_depth: u8,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> Self {
+        Self {
+            wallets: 4,
+            steps: 60,
+            max_reorg_depth: 3,
+        }
+    }
+}
+
+
+/// A script that broke an invariant
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub script: Vec<Op>,
+    /// index of the op after which the invariant check failed
+    pub step: usize,
+    pub error: BlockchainError,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invariant broken after step {}: {}", self.step, self.error)?;
+        writeln!(f, "let script = vec![")?;
+        for op in &self.script {
+            writeln!(f, "    Op::{:?},", op)?;
+        }
+        write!(f, "];")
+    }
+}
+
+
+/// Small deterministic generator, so a seed always gives the same script
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n.max(1)
+    }
+}
+
+
+/// Random script for `seed`. Mining is weighted up so there are outputs to spend.
+pub fn random_script(seed: u64, config: &FuzzConfig) -> Vec<Op> {
+    let mut rng = SplitMix64(seed);
+    let wallet = |rng: &mut SplitMix64| rng.below(config.wallets as u64) as u8;
+
+    (0..config.steps).map(|_| match rng.below(20) {
+        0..=6 => Op::Transfer { from: wallet(&mut rng), to: wallet(&mut rng), pick: rng.next() as u8, fee: rng.next() as u16 },
+        7..=8 => Op::DoubleSpend { from: wallet(&mut rng), to: wallet(&mut rng), pick: rng.next() as u8, fee: rng.next() as u16 },
+        9..=14 => Op::Mine { miner: wallet(&mut rng) },
+        15..=16 => Op::Reorg { depth: 1 + rng.below(config.max_reorg_depth as u64) as u8, miner: wallet(&mut rng) },
+        17..=18 => Op::Evict { pick: rng.next() as u8 },
+        _ => Op::Restart,
+    }).collect()
+}
+
+
+/// Chain under test plus the store it is reopened from and the wallets
+/// the script spends with
+pub struct Harness {
+    config: ChainConfig,
+    store: Arc<MemoryChainStore>,
+    chain: Blockchain,
+    wallets: Vec<Keypair>,
+    addresses: Vec<Address>,
+    /// account balances at genesis; the script only moves UTXOs
+    account_supply: Amount,
+}
+
+impl Harness {
+    /// Fresh chain with `wallets` deterministic wallets. Coinbases mature
+    /// after one block so mined rewards are spendable right away.
+    pub fn new(wallets: usize) -> Result<Self> {
+        let mut config = ChainConfig::default();
+        config.validation_rules.coinbase_maturity = 1;
+
+        let store = Arc::new(MemoryChainStore::new());
+        let chain = Blockchain::with_store(config.clone(), Box::new(store.clone()))?;
+
+        let wallets = (0..wallets.max(1))
+            .map(|index| derive_keypair(WALLET_SEED_PHRASE, &format!("m/{}", index)))
+            .collect::<Result<Vec<_>>>()?;
+        let addresses = wallets.iter()
+            .map(|keypair| public_key_to_address(&keypair.public_key(), AddressType::Base58))
+            .collect();
+
+        let state = chain.world_state();
+        let account_supply = state.total_supply() - state.utxo_set().total_value();
+
+        Ok(Self { config, store, chain, wallets, addresses, account_supply })
+    }
+
+    pub fn chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    /// Run `op`, then check every invariant. Errors are broken invariants.
+    pub fn step(&mut self, op: &Op) -> Result<()> {
+        match *op {
+            Op::Transfer { from, to, pick, fee } => {
+                let from = self.wallet_index(from);
+                if let Some(outpoint) = pick_from(self.spendable(from), pick) {
+                    self.submit_transfer(from, to, outpoint, fee);
+                }
+            }
+            Op::DoubleSpend { from, to, pick, fee } => {
+                let from = self.wallet_index(from);
+                if let Some(outpoint) = pick_from(self.pooled_spends(from), pick) {
+                    self.submit_transfer(from, to, outpoint, fee);
+                }
+            }
+            Op::Mine { miner } => {
+                let miner = self.addresses[self.wallet_index(miner)];
+                let _ = self.chain.mine_block(miner);
+            }
+            Op::Reorg { depth, miner } => self.reorg(depth, miner),
+            Op::Evict { pick } => {
+                let mut pooled: Vec<TxId> = self.chain.mempool().get_pending_transactions()
+                    .iter()
+                    .map(|tx| tx.id())
+                    .collect();
+                pooled.sort_by_key(|tx_id| tx_id.to_hex());
+                if let Some(tx_id) = pick_from(pooled, pick) {
+                    self.chain.mempool_mut().remove_with_descendants(&tx_id);
+                }
+            }
+            Op::Restart => self.restart()?,
+        }
+
+        self.check_invariants()
+    }
+
+    /// Check the chain, UTXO set and mempool agree with each other
+    pub fn check_invariants(&self) -> Result<()> {
+        self.check_chain_index()?;
+        let spent_on_chain = self.check_utxo_replay()?;
+        self.check_mempool(&spent_on_chain)
+    }
+
+    fn wallet_index(&self, wallet: u8) -> usize {
+        wallet as usize % self.wallets.len()
+    }
+
+    /// Outputs `wallet` owns that no pooled transaction spends, in a stable order
+    fn spendable(&self, wallet: usize) -> Vec<OutPoint> {
+        let address = &self.addresses[wallet];
+        let pool = self.chain.mempool();
+        let spent = pooled_inputs(&self.chain);
+
+        let confirmed = self.chain.world_state().utxo_set().get_utxos_by_address(address)
+            .into_iter()
+            .map(|(outpoint, _)| *outpoint);
+        let pooled = pool.get_pending_transactions().into_iter()
+            .flat_map(|tx| {
+                let tx_id = tx.id();
+                tx.outputs.iter().enumerate()
+                    .filter(|(_, output)| output.address == *address)
+                    .map(move |(index, _)| OutPoint::new(tx_id, index as u32))
+            });
+
+        let mut outpoints: Vec<OutPoint> = confirmed.chain(pooled)
+            .filter(|outpoint| !spent.contains(outpoint))
+            .collect();
+        sort_outpoints(&mut outpoints);
+        outpoints
+    }
+
+    /// Outputs of `wallet` that pooled transactions already spend
+    fn pooled_spends(&self, wallet: usize) -> Vec<OutPoint> {
+        let public_key = self.wallets[wallet].public_key();
+        let mut outpoints: Vec<OutPoint> = self.chain.mempool().get_pending_transactions().into_iter()
+            .flat_map(|tx| tx.inputs.iter())
+            .filter(|input| input.public_key == public_key)
+            .map(|input| input.prev_output)
+            .collect();
+        sort_outpoints(&mut outpoints);
+        outpoints
+    }
+
+    /// Spend `outpoint` to `to`, keeping half as change. Rejections are fine.
+    fn submit_transfer(&mut self, from: usize, to: u8, outpoint: OutPoint, fee: u16) {
+        let value = self.chain.world_state().utxo_set().get_utxo(&outpoint)
+            .map(|utxo| utxo.output.amount)
+            .or_else(|| pooled_output_value(&self.chain, &outpoint));
+        let Some(value) = value else { return };
+
+        let fee = self.config.validation_rules.min_transaction_fee + fee as Amount;
+        let Some(remaining) = value.checked_sub(fee) else { return };
+        let payment = remaining / 2;
+
+        let keypair = &self.wallets[from];
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), keypair.public_key());
+        let outputs = vec![
+            TransactionOutput::new(payment, self.addresses[self.wallet_index(to)]),
+            TransactionOutput::new(remaining - payment, self.addresses[from]),
+        ];
+        let mut tx = Transaction::new_utxo(vec![input], outputs, fee);
+        if tx.sign_input(keypair, 0, self.config.signing_domain()).is_ok() {
+            let _ = self.chain.add_transaction(tx);
+        }
+    }
+
+    fn reorg(&mut self, depth: u8, miner: u8) {
+        let fork_height = self.chain.height().saturating_sub(depth as BlockHeight);
+        let miner = self.addresses[self.wallet_index(miner)];
+        let Ok(branch) = self.chain.fork_at(fork_height) else { return };
+        let mut branch = branch.with_miner(miner);
+        // one block more than it replaces, so the branch carries more work
+        if self.chain.extend_branch(&mut branch, (self.chain.height() - fork_height) as usize + 1).is_ok() {
+            let _ = self.chain.reorg_to(&branch);
+        }
+    }
+
+    /// Reopen from the store; the reloaded chain must match the one it replaces
+    fn restart(&mut self) -> Result<()> {
+        let head = self.chain.get_chain_head().map(|block| block.id());
+        let height = self.chain.height();
+        let state_root = self.chain.world_state().current_state_root();
+        self.chain.flush()?;
+
+        self.chain = Blockchain::with_store(self.config.clone(), Box::new(self.store.clone()))?;
+
+        let reloaded_head = self.chain.get_chain_head().map(|block| block.id());
+        if reloaded_head != head || self.chain.height() != height {
+            return Err(BlockchainError::StateError(format!(
+                "Restart moved the head from {:?} at {} to {:?} at {}",
+                head, height, reloaded_head, self.chain.height()
+            )));
+        }
+        if self.chain.world_state().current_state_root() != state_root {
+            return Err(BlockchainError::StateError("Restart changed the state root".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Heights 0..=height each map to a block linking to the one below,
+    /// the head is the top one, and every confirmed transaction is indexed
+    fn check_chain_index(&self) -> Result<()> {
+        let mut prev: Option<BlockId> = None;
+        for height in 0..=self.chain.height() {
+            let block = self.chain.get_block_by_height(&height)
+                .ok_or_else(|| BlockchainError::StateError(format!("No main chain block at height {}", height)))?;
+            if block.height() != height {
+                return Err(BlockchainError::StateError(format!(
+                    "Block at height {} claims height {}", height, block.height()
+                )));
+            }
+            if let Some(prev) = prev {
+                if block.prev_hash() != prev {
+                    return Err(BlockchainError::StateError(format!("Block at height {} does not link to its parent", height)));
+                }
+            }
+            for tx in block.transactions() {
+                if !self.chain.transaction_exists(&tx.id()) {
+                    return Err(BlockchainError::StateError(format!("Confirmed transaction {} is not indexed", tx.id())));
+                }
+            }
+            prev = Some(block.id());
+        }
+
+        if self.chain.get_chain_head().map(|block| block.id()) != prev {
+            return Err(BlockchainError::StateError("Chain head is not the top of the main chain".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Replay the main chain into a fresh output map and compare it with the
+    /// live UTXO set: no output spent twice or before it exists, the same
+    /// unspent outputs, and supply equal to genesis plus rewards minus fees.
+    /// Returns every outpoint spent on the main chain.
+    fn check_utxo_replay(&self) -> Result<HashSet<OutPoint>> {
+        let mut unspent: HashMap<OutPoint, Amount> = HashMap::new();
+        let mut spent = HashSet::new();
+
+        for height in 0..=self.chain.height() {
+            let Some(block) = self.chain.get_block_by_height(&height) else { break };
+            for tx in block.transactions() {
+                if tx.is_coinbase() {
+                    let minted: Amount = tx.outputs.iter().map(|output| output.amount).sum();
+                    let reward = self.config.block_subsidy(height);
+                    if height > 0 && minted > reward {
+                        return Err(BlockchainError::StateError(format!(
+                            "Coinbase at height {} mints {} over the {} subsidy", height, minted, reward
+                        )));
+                    }
+                } else {
+                    let mut input_value: Amount = 0;
+                    for input in &tx.inputs {
+                        let value = unspent.remove(&input.prev_output).ok_or_else(|| BlockchainError::StateError(format!(
+                            "{} spends {}, which is spent or does not exist", tx.id(), input.prev_output
+                        )))?;
+                        input_value += value;
+                        spent.insert(input.prev_output);
+                    }
+                    let output_value: Amount = tx.outputs.iter().map(|output| output.amount).sum();
+                    if output_value > input_value {
+                        return Err(BlockchainError::StateError(format!(
+                            "{} creates {} from {}", tx.id(), output_value, input_value
+                        )));
+                    }
+                }
+
+                let tx_id = tx.id();
+                for (index, output) in tx.outputs.iter().enumerate() {
+                    unspent.insert(OutPoint::new(tx_id, index as u32), output.amount);
+                }
+            }
+        }
+
+        let state = self.chain.world_state();
+        state.validate()?;
+        let utxo_set = state.utxo_set();
+        if utxo_set.len() != unspent.len() {
+            return Err(BlockchainError::StateError(format!(
+                "UTXO set has {} outputs, replaying the chain gives {}", utxo_set.len(), unspent.len()
+            )));
+        }
+        for (outpoint, amount) in &unspent {
+            match utxo_set.get_utxo(outpoint) {
+                Some(utxo) if utxo.output.amount == *amount => {}
+                _ => return Err(BlockchainError::StateError(format!("UTXO set disagrees with the chain about {}", outpoint))),
+            }
+        }
+
+        let expected_supply = self.account_supply + unspent.values().sum::<Amount>();
+        if state.total_supply() != expected_supply {
+            return Err(BlockchainError::StateError(format!(
+                "Supply is {}, the chain accounts for {}", state.total_supply(), expected_supply
+            )));
+        }
+        Ok(spent)
+    }
+
+    /// No outpoint is spent by two pooled transactions or by a pooled and a
+    /// confirmed one, and the dependency graph matches the pooled inputs.
+    ///
+    /// Pooled transactions whose inputs vanished in a reorg are not flagged:
+    /// the pool keeps them until something revalidates it.
+    fn check_mempool(&self, spent_on_chain: &HashSet<OutPoint>) -> Result<()> {
+        let pool = self.chain.mempool();
+        let dag = pool.dependencies();
+        let mut spent_in_pool = HashSet::new();
+
+        for tx in pool.get_pending_transactions() {
+            let tx_id = tx.id();
+            if !dag.contains(&tx_id) {
+                return Err(BlockchainError::StateError(format!("Pooled {} missing from the dependency graph", tx_id)));
+            }
+
+            for input in &tx.inputs {
+                let outpoint = input.prev_output;
+                if !spent_in_pool.insert(outpoint) {
+                    return Err(BlockchainError::StateError(format!("{} is spent by two pooled transactions", outpoint)));
+                }
+                if spent_on_chain.contains(&outpoint) {
+                    return Err(BlockchainError::StateError(format!(
+                        "Pooled {} spends {}, already spent on the main chain", tx_id, outpoint
+                    )));
+                }
+                if pool.contains_transaction(&outpoint.tx_id)
+                    && !dag.parents(&tx_id).any(|parent| *parent == outpoint.tx_id) {
+                    return Err(BlockchainError::StateError(format!(
+                        "Pooled {} spends pooled {} without depending on it", tx_id, outpoint.tx_id
+                    )));
+                }
+            }
+
+            if let Some(parent) = dag.parents(&tx_id).find(|parent| !pool.contains_transaction(parent)) {
+                return Err(BlockchainError::StateError(format!("Pooled {} depends on {}, which left the pool", tx_id, parent)));
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Run `script` on a fresh harness, stopping at the first broken invariant
+pub fn run_script(script: &[Op], wallets: usize) -> std::result::Result<(), Failure> {
+    let fail = |step, error| Failure { script: script.to_vec(), step, error };
+    let mut harness = Harness::new(wallets).map_err(|error| fail(0, error))?;
+    for (step, op) in script.iter().enumerate() {
+        harness.step(op).map_err(|error| fail(step, error))?;
+    }
+    Ok(())
+}
+
+
+/// Smallest script found that still fails: first cut everything after the
+/// failing step, then delete ever smaller chunks while the failure remains.
+pub fn shrink(failure: Failure, wallets: usize) -> Failure {
+    let mut best = failure;
+    best.script.truncate(best.step + 1);
+
+    let mut chunk = best.script.len() / 2;
+    while chunk > 0 {
+        let mut start = 0;
+        while start < best.script.len() {
+            let end = (start + chunk).min(best.script.len());
+            let mut candidate = best.script.clone();
+            candidate.drain(start..end);
+
+            match run_script(&candidate, wallets) {
+                Err(mut smaller) => {
+                    smaller.script.truncate(smaller.step + 1);
+                    best = smaller;
+                }
+                Ok(()) => start += chunk,
+            }
+        }
+        chunk /= 2;
+    }
+    best
+}
+
+
+/// Generate a script from `seed`, run it, and shrink it if it fails
+pub fn fuzz(seed: u64, config: &FuzzConfig) -> std::result::Result<(), Failure> {
+    let script = random_script(seed, config);
+    run_script(&script, config.wallets).map_err(|failure| shrink(failure, config.wallets))
+}
+
+
+fn pick_from<T>(mut items: Vec<T>, pick: u8) -> Option<T> {
+    if items.is_empty() {
+        return None;
+    }
+    let index = pick as usize % items.len();
+    Some(items.swap_remove(index))
+}
+
+fn sort_outpoints(outpoints: &mut [OutPoint]) {
+    outpoints.sort_by_key(|outpoint| (outpoint.tx_id.to_hex(), outpoint.output_index));
+}
+
+fn pooled_inputs(chain: &Blockchain) -> HashSet<OutPoint> {
+    chain.mempool().get_pending_transactions().into_iter()
+        .flat_map(|tx| tx.inputs.iter().map(|input| input.prev_output))
+        .collect()
+}
+
+fn pooled_output_value(chain: &Blockchain, outpoint: &OutPoint) -> Option<Amount> {
+    let parent = chain.mempool().get_transaction(&outpoint.tx_id)?;
+    parent.outputs.get(outpoint.output_index as usize).map(|output| output.amount)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_are_deterministic() {
+        let config = FuzzConfig::default();
+        assert_eq!(random_script(7, &config), random_script(7, &config));
+        assert_ne!(random_script(7, &config), random_script(8, &config));
+    }
+
+    #[test]
+    fn test_invariants_hold_on_random_scripts() {
+        let config = FuzzConfig { steps: 30, ..FuzzConfig::default() };
+        for seed in 0..4 {
+            if let Err(failure) = fuzz(seed, &config) {
+                panic!("seed {}: {}", seed, failure);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chained_spends_and_restart_keep_state() {
+        let script = vec![
+            Op::Mine { miner: 0 },
+            Op::Mine { miner: 0 },
+            Op::Transfer { from: 0, to: 1, pick: 0, fee: 0 },
+            Op::Transfer { from: 1, to: 2, pick: 0, fee: 0 },
+            Op::DoubleSpend { from: 0, to: 2, pick: 0, fee: 5000 },
+            Op::Mine { miner: 1 },
+            Op::Restart,
+            Op::Reorg { depth: 1, miner: 2 },
+            Op::Evict { pick: 0 },
+            Op::Restart,
+        ];
+        run_script(&script, 3).unwrap();
+    }
+
+    #[test]
+    fn test_shrink_keeps_a_failing_prefix() {
+        let script = vec![Op::Mine { miner: 0 }, Op::Restart, Op::Mine { miner: 1 }];
+        let failure = Failure {
+            script: script.clone(),
+            step: 1,
+            error: BlockchainError::StateError("injected".to_string()),
+        };
+        // the injected failure does not reproduce, so shrinking only cuts the tail
+        let shrunk = shrink(failure, 2);
+        assert_eq!(shrunk.script, script[..2].to_vec());
+    }
+}
@@ -0,0 +1,316 @@
+use crate::types::*;
+use crate::block::Block;
+use crate::state::{BlockUndo, WorldStateSnapshot};
+use crate::receipt::Receipt;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::Hash256;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+
+/// Persistent backend for chain data.
+///
+/// blockchain-storage depends on this crate, so `Blockchain` talks to disk
+/// through this trait rather than naming a concrete store.
+pub trait ChainStore: fmt::Debug + Send + Sync {
+    /// Persist a block, keyed by its id
+    fn put_block(&self, block: &Block) -> Result<()>;
+
+    /// Load a block by id
+    fn get_block(&self, block_id: &BlockId) -> Result<Option<Block>>;
+
+    /// Record which block is on the main chain at a height
+    fn put_main_chain(&self, height: BlockHeight, block_id: &BlockId) -> Result<()>;
+
+    /// Drop the main chain entry at a height (used when a reorg disconnects blocks)
+    fn remove_main_chain(&self, height: BlockHeight) -> Result<()>;
+
+    /// Load the main chain index (height -> block id)
+    fn main_chain(&self) -> Result<Vec<(BlockHeight, BlockId)>>;
+
+    /// Persist the current chain tip
+    fn put_chain_head(&self, block_id: &BlockId) -> Result<()>;
+
+    /// Load the persisted chain tip
+    fn chain_head(&self) -> Result<Option<BlockId>>;
+
+    /// Persist the undo record of a main chain block, keyed by its block id
+    fn put_block_undo(&self, _undo: &BlockUndo) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load a block's undo record. Reorgs fall back to state snapshots
+    /// when this returns None.
+    fn get_block_undo(&self, _block_id: &BlockId) -> Result<Option<BlockUndo>> {
+        Ok(None)
+    }
+
+    /// Persist the receipts of a connected block, keyed by transaction id.
+    /// A receipt from a block that was later disconnected may stay behind;
+    /// readers check its block is still on the main chain.
+    fn put_receipts(&self, _receipts: &[Receipt]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Load the latest receipt stored for a transaction
+    fn get_receipt(&self, _tx_id: &TxId) -> Result<Option<Receipt>> {
+        Ok(None)
+    }
+
+    /// Persist new state trie nodes and the state root committed at a height.
+    /// Stores that don't keep state (the chain replays blocks on load) can ignore this.
+    fn put_state_root(&self, _height: BlockHeight, _state_root: &Hash256, _nodes: &[(Hash256, Vec<u8>)]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Persist the world state a snapshot-synced chain starts from. Blocks
+    /// up to its height are stored for their headers but never replayed.
+    fn put_state_base(&self, _state: &WorldStateSnapshot) -> Result<()> {
+        Err(BlockchainError::StorageError("store can't hold a state snapshot".to_string()))
+    }
+
+    /// Load the state a snapshot-synced chain starts from (None: it starts at genesis)
+    fn state_base(&self) -> Result<Option<WorldStateSnapshot>> {
+        Ok(None)
+    }
+
+    /// Drop the transactions and undo record of the main chain block at a
+    /// height, keeping its header, and note the chain is pruned up to there
+    fn prune_block(&self, _height: BlockHeight, _block_id: &BlockId) -> Result<()> {
+        Err(BlockchainError::StorageError("store can't prune blocks".to_string()))
+    }
+
+    /// Height up to which main chain blocks have been pruned
+    fn pruned_height(&self) -> Result<Option<BlockHeight>> {
+        Ok(None)
+    }
+
+    /// Flush pending writes to durable storage
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+
+impl<S: ChainStore + ?Sized> ChainStore for Arc<S> {
+    fn put_block(&self, block: &Block) -> Result<()> {
+        (**self).put_block(block)
+    }
+
+    fn get_block(&self, block_id: &BlockId) -> Result<Option<Block>> {
+        (**self).get_block(block_id)
+    }
+
+    fn put_main_chain(&self, height: BlockHeight, block_id: &BlockId) -> Result<()> {
+        (**self).put_main_chain(height, block_id)
+    }
+
+    fn remove_main_chain(&self, height: BlockHeight) -> Result<()> {
+        (**self).remove_main_chain(height)
+    }
+
+    fn main_chain(&self) -> Result<Vec<(BlockHeight, BlockId)>> {
+        (**self).main_chain()
+    }
+
+    fn put_chain_head(&self, block_id: &BlockId) -> Result<()> {
+        (**self).put_chain_head(block_id)
+    }
+
+    fn chain_head(&self) -> Result<Option<BlockId>> {
+        (**self).chain_head()
+    }
+
+    fn put_block_undo(&self, undo: &BlockUndo) -> Result<()> {
+        (**self).put_block_undo(undo)
+    }
+
+    fn get_block_undo(&self, block_id: &BlockId) -> Result<Option<BlockUndo>> {
+        (**self).get_block_undo(block_id)
+    }
+
+    fn put_receipts(&self, receipts: &[Receipt]) -> Result<()> {
+        (**self).put_receipts(receipts)
+    }
+
+    fn get_receipt(&self, tx_id: &TxId) -> Result<Option<Receipt>> {
+        (**self).get_receipt(tx_id)
+    }
+
+    fn put_state_root(&self, height: BlockHeight, state_root: &Hash256, nodes: &[(Hash256, Vec<u8>)]) -> Result<()> {
+        (**self).put_state_root(height, state_root, nodes)
+    }
+
+    fn put_state_base(&self, state: &WorldStateSnapshot) -> Result<()> {
+        (**self).put_state_base(state)
+    }
+
+    fn state_base(&self) -> Result<Option<WorldStateSnapshot>> {
+        (**self).state_base()
+    }
+
+    fn prune_block(&self, height: BlockHeight, block_id: &BlockId) -> Result<()> {
+        (**self).prune_block(height, block_id)
+    }
+
+    fn pruned_height(&self) -> Result<Option<BlockHeight>> {
+        (**self).pruned_height()
+    }
+
+    fn flush(&self) -> Result<()> {
+        (**self).flush()
+    }
+}
+
+
+/// In-memory chain store, useful for tests and ephemeral nodes
+#[derive(Debug, Default)]
+pub struct MemoryChainStore {
+    blocks: RwLock<HashMap<BlockId, Block>>,
+    undo: RwLock<HashMap<BlockId, BlockUndo>>,
+    receipts: RwLock<HashMap<TxId, Receipt>>,
+    main_chain: RwLock<HashMap<BlockHeight, BlockId>>,
+    chain_head: RwLock<Option<BlockId>>,
+    state_base: RwLock<Option<WorldStateSnapshot>>,
+    pruned_height: RwLock<Option<BlockHeight>>,
+}
+
+impl MemoryChainStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn lock_error<T>(_: T) -> BlockchainError {
+    BlockchainError::StorageError("Store lock poisoned".to_string())
+}
+
+impl ChainStore for MemoryChainStore {
+    fn put_block(&self, block: &Block) -> Result<()> {
+        self.blocks.write().map_err(lock_error)?
+            .insert(block.id(), block.clone());
+        Ok(())
+    }
+
+    fn get_block(&self, block_id: &BlockId) -> Result<Option<Block>> {
+        Ok(self.blocks.read().map_err(lock_error)?
+            .get(block_id)
+            .cloned())
+    }
+
+    fn put_block_undo(&self, undo: &BlockUndo) -> Result<()> {
+        self.undo.write().map_err(lock_error)?
+            .insert(undo.block_id, undo.clone());
+        Ok(())
+    }
+
+    fn get_block_undo(&self, block_id: &BlockId) -> Result<Option<BlockUndo>> {
+        Ok(self.undo.read().map_err(lock_error)?
+            .get(block_id)
+            .cloned())
+    }
+
+    fn put_receipts(&self, receipts: &[Receipt]) -> Result<()> {
+        let mut stored = self.receipts.write().map_err(lock_error)?;
+        for receipt in receipts {
+            stored.insert(receipt.tx_id, receipt.clone());
+        }
+        Ok(())
+    }
+
+    fn get_receipt(&self, tx_id: &TxId) -> Result<Option<Receipt>> {
+        Ok(self.receipts.read().map_err(lock_error)?
+            .get(tx_id)
+            .cloned())
+    }
+
+    fn put_main_chain(&self, height: BlockHeight, block_id: &BlockId) -> Result<()> {
+        self.main_chain.write().map_err(lock_error)?
+            .insert(height, *block_id);
+        Ok(())
+    }
+
+    fn remove_main_chain(&self, height: BlockHeight) -> Result<()> {
+        self.main_chain.write().map_err(lock_error)?
+            .remove(&height);
+        Ok(())
+    }
+
+    fn main_chain(&self) -> Result<Vec<(BlockHeight, BlockId)>> {
+        let mut index: Vec<_> = self.main_chain.read().map_err(lock_error)?
+            .iter()
+            .map(|(height, block_id)| (*height, *block_id))
+            .collect();
+        index.sort_by_key(|(height, _)| *height);
+        Ok(index)
+    }
+
+    fn put_chain_head(&self, block_id: &BlockId) -> Result<()> {
+        *self.chain_head.write().map_err(lock_error)? = Some(*block_id);
+        Ok(())
+    }
+
+    fn chain_head(&self) -> Result<Option<BlockId>> {
+        Ok(*self.chain_head.read().map_err(lock_error)?)
+    }
+
+    fn put_state_base(&self, state: &WorldStateSnapshot) -> Result<()> {
+        *self.state_base.write().map_err(lock_error)? = Some(state.clone());
+        Ok(())
+    }
+
+    fn state_base(&self) -> Result<Option<WorldStateSnapshot>> {
+        Ok(self.state_base.read().map_err(lock_error)?.clone())
+    }
+
+    fn prune_block(&self, height: BlockHeight, block_id: &BlockId) -> Result<()> {
+        if let Some(block) = self.blocks.write().map_err(lock_error)?.get_mut(block_id) {
+            *block = block.pruned();
+        }
+        self.undo.write().map_err(lock_error)?.remove(block_id);
+        *self.pruned_height.write().map_err(lock_error)? = Some(height);
+        Ok(())
+    }
+
+    fn pruned_height(&self) -> Result<Option<BlockHeight>> {
+        Ok(*self.pruned_height.read().map_err(lock_error)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::hash::sha256;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+
+    #[test]
+    fn test_memory_store_roundtrip() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+
+        let store = MemoryChainStore::new();
+        let block = Block::genesis(1, address, 5000000000).unwrap();
+        let block_id = block.id();
+
+        store.put_block(&block).unwrap();
+        store.put_main_chain(0, &block_id).unwrap();
+        store.put_chain_head(&block_id).unwrap();
+
+        assert_eq!(store.get_block(&block_id).unwrap(), Some(block));
+        assert_eq!(store.main_chain().unwrap(), vec![(0, block_id)]);
+        assert_eq!(store.chain_head().unwrap(), Some(block_id));
+    }
+
+    #[test]
+    fn test_memory_store_missing_block() {
+        let store = MemoryChainStore::new();
+        let block_id = BlockId::new(sha256(b"unknown block"));
+
+        assert!(store.get_block(&block_id).unwrap().is_none());
+        assert!(store.main_chain().unwrap().is_empty());
+        assert!(store.chain_head().unwrap().is_none());
+    }
+}
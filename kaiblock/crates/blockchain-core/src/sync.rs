@@ -0,0 +1,219 @@
+use crate::types::*;
+use crate::block::BlockHeader;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::HashAlgorithm;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+
+/// Most headers a peer returns for one request
+pub const MAX_HEADERS_PER_REQUEST: usize = 2000;
+
+/// Most block bodies requested from a peer at once (keeps a request small
+/// enough for peers to read in one go; replies can be any size)
+pub const MAX_BLOCKS_PER_REQUEST: usize = 16;
+
+
+/// Phase of initial block download
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStage {
+    /// Not started, or no peers to sync from
+    Idle,
+    /// Downloading and checking headers
+    Headers,
+    /// Downloading block bodies and appending them to the chain
+    Blocks,
+    /// Caught up with the best peer
+    Synced,
+}
+
+
+/// Snapshot of sync progress
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncProgress {
+    pub stage: SyncStage,
+    /// Height of our main chain
+    pub current_height: BlockHeight,
+    /// Best height reported by the peers we sync from
+    pub target_height: BlockHeight,
+    /// Headers downloaded and checked this session
+    pub headers_downloaded: u64,
+    /// Blocks downloaded and appended this session
+    pub blocks_downloaded: u64,
+    /// Peers we are syncing from
+    pub peers: usize,
+}
+
+impl SyncProgress {
+    /// Whether we are still behind the best known peer
+    pub fn is_syncing(&self) -> bool {
+        matches!(self.stage, SyncStage::Headers | SyncStage::Blocks)
+    }
+
+    /// Progress towards the target height in percent
+    pub fn percent(&self) -> f64 {
+        if self.target_height == 0 || self.current_height >= self.target_height {
+            return 100.0;
+        }
+        self.current_height as f64 * 100.0 / self.target_height as f64
+    }
+}
+
+impl Default for SyncProgress {
+    fn default() -> Self {
+        Self {
+            stage: SyncStage::Idle,
+            current_height: 0,
+            target_height: 0,
+            headers_downloaded: 0,
+            blocks_downloaded: 0,
+            peers: 0,
+        }
+    }
+}
+
+
+/// Shared view of sync progress. The sync task updates it and RPC reads it.
+#[derive(Debug, Clone, Default)]
+pub struct SyncStatus {
+    progress: Arc<RwLock<SyncProgress>>,
+}
+
+impl SyncStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current progress
+    pub fn progress(&self) -> SyncProgress {
+        self.progress.read().expect("sync status lock poisoned").clone()
+    }
+
+    /// Apply an update to the progress
+    pub fn update<F: FnOnce(&mut SyncProgress)>(&self, f: F) {
+        f(&mut self.progress.write().expect("sync status lock poisoned"));
+    }
+}
+
+
+/// Check that `headers` extend the block `tip_id` at `tip_height` one height
+/// at a time and that every header meets its own difficulty target, measured
+/// on the chain's `algorithm` hash.
+///
+/// This is only a cheap filter before bodies are downloaded; full validation
+/// still happens when the blocks are added to the chain.
+pub fn validate_header_chain(tip_id: BlockId, tip_height: BlockHeight, headers: &[BlockHeader], algorithm: HashAlgorithm) -> Result<()> {
+    let mut prev_id = tip_id;
+    let mut prev_height = tip_height;
+
+    for header in headers {
+        if header.prev_block_hash != prev_id {
+            return Err(BlockchainError::InvalidChain(
+                format!("Header at height {} does not connect to {}", header.height, prev_id.to_hex())
+            ));
+        }
+        if header.height != prev_height + 1 {
+            return Err(BlockchainError::InvalidChain(
+                format!("Header height {} does not follow {}", header.height, prev_height)
+            ));
+        }
+        if !header.meets_difficulty_with(algorithm) {
+            return Err(BlockchainError::InvalidChain(
+                format!("Header at height {} does not meet its difficulty", header.height)
+            ));
+        }
+
+        prev_id = header.id();
+        prev_height = header.height;
+    }
+
+    Ok(())
+}
+
+
+/// Check that none of `headers` sits at a checkpoint height with a different
+/// block. Run before downloading bodies so a peer on a contradicting fork is
+/// dropped early.
+pub fn check_checkpoints(checkpoints: &BTreeMap<BlockHeight, BlockId>, headers: &[BlockHeader]) -> Result<()> {
+    for header in headers {
+        if let Some(expected) = checkpoints.get(&header.height) {
+            if header.id() != *expected {
+                return Err(BlockchainError::InvalidChain(
+                    format!("Header at height {} contradicts checkpoint {}", header.height, expected.to_hex())
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::Hash256;
+
+    fn header_chain(tip_id: BlockId, tip_height: BlockHeight, count: usize) -> Vec<BlockHeader> {
+        let mut headers = Vec::new();
+        let mut prev = tip_id;
+        for i in 1..=count as u64 {
+            let header = BlockHeader::new(prev, Hash256::zero(), 0, tip_height + i, 0, 1);
+            prev = header.id();
+            headers.push(header);
+        }
+        headers
+    }
+
+    #[test]
+    fn test_valid_header_chain() {
+        let tip = BlockId::new(Hash256::zero());
+        let headers = header_chain(tip, 5, 10);
+
+        assert!(validate_header_chain(tip, 5, &headers, HashAlgorithm::Sha256).is_ok());
+        assert!(validate_header_chain(tip, 5, &[], HashAlgorithm::Sha256).is_ok());
+    }
+
+    #[test]
+    fn test_header_chain_rejects_gaps() {
+        let tip = BlockId::new(Hash256::zero());
+
+        // wrong parent
+        let mut headers = header_chain(tip, 5, 3);
+        headers.remove(1);
+        assert!(validate_header_chain(tip, 5, &headers, HashAlgorithm::Sha256).is_err());
+
+        // wrong height
+        let headers = header_chain(tip, 6, 3);
+        assert!(validate_header_chain(tip, 5, &headers, HashAlgorithm::Sha256).is_err());
+    }
+
+    #[test]
+    fn test_header_chain_checkpoints() {
+        let tip = BlockId::new(Hash256::zero());
+        let headers = header_chain(tip, 0, 5);
+
+        let matching = BTreeMap::from([(3, headers[2].id())]);
+        assert!(check_checkpoints(&matching, &headers).is_ok());
+
+        let contradicting = BTreeMap::from([(3, headers[0].id())]);
+        assert!(check_checkpoints(&contradicting, &headers).is_err());
+    }
+
+    #[test]
+    fn test_sync_status_shared() {
+        let status = SyncStatus::new();
+        let reader = status.clone();
+
+        status.update(|p| {
+            p.stage = SyncStage::Blocks;
+            p.current_height = 50;
+            p.target_height = 200;
+        });
+
+        let progress = reader.progress();
+        assert!(progress.is_syncing());
+        assert_eq!(progress.percent(), 25.0);
+    }
+}
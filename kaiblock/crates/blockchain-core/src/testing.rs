@@ -0,0 +1,240 @@
+//! Helpers for building competing branches in tests (enabled with the `testing` feature).
+//!
+//! Branch blocks are built off-chain, so a branch can be grown to any length
+//! before the chain sees it. Block contents are deterministic: timestamps step
+//! one second past the parent and the coinbase pays a miner derived from the
+//! fork height.
+//!
+//! ```ignore
+//! let mut branch = chain.fork_at(5)?;
+//! chain.extend_branch(&mut branch, 3)?;
+//! let disconnected = chain.reorg_to(&branch)?;
+//! ```
+
+use crate::types::*;
+use crate::block::Block;
+use crate::chain::Blockchain;
+use crate::transaction::Transaction;
+use crate::difficulty;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{hash::sha256, Address, AddressType};
+
+
+/// A side branch growing from a main chain block
+#[derive(Debug, Clone)]
+pub struct Branch {
+    fork_height: BlockHeight,
+    fork_point: BlockId,
+    miner: Address,
+    blocks: Vec<Block>,
+}
+
+impl Branch {
+    /// Pay branch coinbases to `miner`. Two branches forked at the same height
+    /// need different miners, or they would build identical blocks.
+    pub fn with_miner(mut self, miner: Address) -> Self {
+        self.miner = miner;
+        self
+    }
+
+    /// Height of the main chain block the branch grows from
+    pub fn fork_height(&self) -> BlockHeight {
+        self.fork_height
+    }
+
+    /// Main chain block the branch grows from
+    pub fn fork_point(&self) -> BlockId {
+        self.fork_point
+    }
+
+    pub fn miner(&self) -> Address {
+        self.miner
+    }
+
+    /// Blocks on the branch, oldest first
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// Height of the branch tip
+    pub fn height(&self) -> BlockHeight {
+        self.fork_height + self.blocks.len() as BlockHeight
+    }
+
+    /// Id of the branch tip (the fork point while the branch is empty)
+    pub fn tip_id(&self) -> BlockId {
+        self.blocks.last().map(Block::id).unwrap_or(self.fork_point)
+    }
+
+    fn find(&self, block_id: &BlockId) -> Option<&Block> {
+        self.blocks.iter().find(|block| block.id() == *block_id)
+    }
+}
+
+
+impl Blockchain {
+    /// Start an empty branch from the main chain block at `height`
+    pub fn fork_at(&self, height: BlockHeight) -> Result<Branch> {
+        let fork_point = self.get_block_by_height(&height)
+            .ok_or_else(|| BlockchainError::BlockNotFound(format!("No main chain block at height {}", height)))?
+            .id();
+
+        let miner = Address::from_hash(
+            sha256(format!("fork-at-{}", height).as_bytes()),
+            AddressType::Base58,
+        );
+
+        Ok(Branch { fork_height: height, fork_point, miner, blocks: Vec::new() })
+    }
+
+    /// Mine `n` coinbase-only blocks onto the branch
+    pub fn extend_branch(&self, branch: &mut Branch, n: usize) -> Result<()> {
+        for _ in 0..n {
+            self.extend_branch_with(branch, Vec::new())?;
+        }
+        Ok(())
+    }
+
+    /// Mine one block carrying `transactions` (after its coinbase) onto the branch
+    pub fn extend_branch_with(&self, branch: &mut Branch, transactions: Vec<Transaction>) -> Result<()> {
+        let prev = match branch.blocks.last() {
+            Some(block) => block,
+            None => self.get_block(&branch.fork_point)
+                .ok_or_else(|| BlockchainError::BlockNotFound(format!("Fork point {} is gone", branch.fork_point)))?,
+        };
+        let height = prev.height() + 1;
+
+        let window_start = self.branch_window_start(branch, prev);
+        let difficulty = difficulty::next_difficulty(prev, window_start, self.validation_rules())?;
+
+        let timestamp = Timestamp::from_unix_timestamp(prev.timestamp().to_unix_timestamp() + 1);
+        let mut coinbase = Transaction::new_coinbase(branch.miner, self.block_subsidy(height), height);
+        coinbase.timestamp = timestamp;
+
+        let mut block_transactions = vec![coinbase];
+        block_transactions.extend(transactions);
+
+        let mut block = Block::new(prev.id(), block_transactions, difficulty, height, self.config().chain_id)?;
+        block.header.timestamp = timestamp;
+
+        if !block.mine_with(self.validation_rules().block_hash, Some(self.config().mining.max_mining_iterations))? {
+            return Err(BlockchainError::InvalidBlock(
+                format!("Failed to mine branch block at height {}", height)
+            ));
+        }
+
+        branch.blocks.push(block);
+        Ok(())
+    }
+
+    /// Feed the branch to the chain and make it the main chain.
+    ///
+    /// The branch goes through normal fork choice, so it must carry more work
+    /// than the current main chain. Returns the ids of the main chain blocks
+    /// that were disconnected, oldest first.
+    pub fn reorg_to(&mut self, branch: &Branch) -> Result<Vec<BlockId>> {
+        let old_height = self.height();
+        let disconnected: Vec<BlockId> = (branch.fork_height + 1..=old_height)
+            .filter_map(|height| self.get_block_by_height(&height).map(Block::id))
+            .collect();
+
+        for block in &branch.blocks {
+            if self.get_block(&block.id()).is_none() {
+                self.add_block(block.clone())?;
+            }
+        }
+
+        if self.get_chain_head().map(Block::id) != Some(branch.tip_id()) {
+            return Err(BlockchainError::InvalidChain(format!(
+                "Branch at height {} does not have more work than the main chain at height {}",
+                branch.height(), old_height
+            )));
+        }
+
+        Ok(disconnected)
+    }
+
+    // Same walk as the chain's own retarget lookup, but also through unsubmitted branch blocks
+    fn branch_window_start<'a>(&'a self, branch: &'a Branch, prev: &'a Block) -> Option<&'a Block> {
+        let rules = self.validation_rules();
+        let next_height = prev.height() + 1;
+        if !difficulty::is_retarget_height(next_height, rules) {
+            return None;
+        }
+
+        let start_height = difficulty::retarget_window_start(next_height, rules);
+        let mut cursor = prev;
+        while cursor.height() > start_height {
+            let prev_id = cursor.prev_hash();
+            cursor = branch.find(&prev_id).or_else(|| self.get_block(&prev_id))?;
+        }
+
+        Some(cursor)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address};
+
+    fn chain_with_blocks(n: usize) -> Blockchain {
+        let mut blockchain = Blockchain::default();
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        for _ in 0..n {
+            blockchain.mine_block(miner).unwrap();
+        }
+        blockchain
+    }
+
+    #[test]
+    fn test_branch_is_deterministic() {
+        let blockchain = chain_with_blocks(2);
+
+        let mut a = blockchain.fork_at(1).unwrap();
+        let mut b = blockchain.fork_at(1).unwrap();
+        blockchain.extend_branch(&mut a, 2).unwrap();
+        blockchain.extend_branch(&mut b, 2).unwrap();
+
+        assert_eq!(a.height(), 3);
+        assert_eq!(a.tip_id(), b.tip_id());
+        assert_eq!(a.blocks()[0].prev_hash(), blockchain.get_block_by_height(&1).unwrap().id());
+    }
+
+    #[test]
+    fn test_reorg_to_longer_branch() {
+        let mut blockchain = chain_with_blocks(3);
+        let old_tip = blockchain.get_chain_head().unwrap().id();
+
+        let mut branch = blockchain.fork_at(1).unwrap();
+        blockchain.extend_branch(&mut branch, 3).unwrap();
+
+        let disconnected = blockchain.reorg_to(&branch).unwrap();
+        assert_eq!(disconnected.len(), 2);
+        assert_eq!(disconnected[1], old_tip);
+        assert_eq!(blockchain.height(), 4);
+        assert_eq!(blockchain.get_chain_head().unwrap().id(), branch.tip_id());
+        assert_eq!(blockchain.get_balance(&branch.miner()), 3 * blockchain.config().mining.block_reward);
+    }
+
+    #[test]
+    fn test_reorg_to_lighter_branch_fails() {
+        let mut blockchain = chain_with_blocks(3);
+        let tip = blockchain.get_chain_head().unwrap().id();
+
+        let mut branch = blockchain.fork_at(1).unwrap();
+        blockchain.extend_branch(&mut branch, 1).unwrap();
+
+        assert!(blockchain.reorg_to(&branch).is_err());
+        assert_eq!(blockchain.get_chain_head().unwrap().id(), tip);
+    }
+}
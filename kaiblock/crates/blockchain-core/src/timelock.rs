@@ -0,0 +1,177 @@
+//! Absolute and relative time locks.
+//!
+//! A transaction's `lock_time` is absolute: below `LOCKTIME_THRESHOLD` it is
+//! the first block height the transaction may be included at, above it a unix
+//! time the block must have reached. An input's `sequence` carries a relative
+//! lock (BIP 68 style): unless the disable flag is set, its low 16 bits are
+//! how many blocks deep the spent output must be. Only block-based relative
+//! locks are supported.
+//!
+//! Outputs commit to these with `Script::CheckLockTime` and
+//! `Script::CheckSequence`. Checking a script only compares against the
+//! spending transaction's own fields; `check_time_locks` then holds those
+//! fields to the chain, so an output can't be spent before its lock.
+
+use crate::state::UTXOSet;
+use crate::transaction::Transaction;
+use crate::types::*;
+use crate::{BlockchainError, Result};
+use serde::{Deserialize, Serialize};
+
+
+/// Lock times below this are block heights, the rest unix times
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// Sequence of an input without a relative lock
+pub const SEQUENCE_FINAL: u32 = 0xFFFF_FFFF;
+
+/// Set in a sequence to disable its relative lock
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// Set in a sequence for a time-based relative lock (not supported)
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// Bits of a sequence holding its relative lock
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_FFFF;
+
+
+/// An absolute lock, as held in a transaction's `lock_time`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LockTime {
+    /// first block height a spend may be included at
+    Height(u32),
+    /// unix time the including block must have reached
+    Timestamp(u32),
+}
+
+impl LockTime {
+    /// Decode a transaction's `lock_time`; 0 means no lock
+    pub fn from_consensus(lock_time: u32) -> Option<Self> {
+        match lock_time {
+            0 => None,
+            height if height < LOCKTIME_THRESHOLD => Some(LockTime::Height(height)),
+            timestamp => Some(LockTime::Timestamp(timestamp)),
+        }
+    }
+
+    pub fn to_consensus(self) -> u32 {
+        match self {
+            LockTime::Height(height) => height,
+            LockTime::Timestamp(timestamp) => timestamp,
+        }
+    }
+
+    /// Whether `other` is at least this lock, measured in the same unit
+    pub fn is_reached_by(self, other: LockTime) -> bool {
+        match (self, other) {
+            (LockTime::Height(lock), LockTime::Height(at)) => at >= lock,
+            (LockTime::Timestamp(lock), LockTime::Timestamp(at)) => at >= lock,
+            _ => false,
+        }
+    }
+
+    /// Whether a block at `height` and `timestamp` may include a transaction with this lock
+    pub fn is_satisfied(self, height: BlockHeight, timestamp: Timestamp) -> bool {
+        match self {
+            LockTime::Height(lock) => height >= lock as BlockHeight,
+            LockTime::Timestamp(lock) => timestamp.to_unix_timestamp() >= lock as i64,
+        }
+    }
+}
+
+
+/// Sequence locking an input until the output it spends is `blocks` deep
+pub fn relative_lock_sequence(blocks: u16) -> u32 {
+    blocks as u32
+}
+
+/// Relative lock of an input's sequence in blocks, `None` when disabled
+pub fn relative_lock(sequence: u32) -> Result<Option<u16>> {
+    if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+        return Ok(None);
+    }
+    if sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+        return Err(BlockchainError::InvalidTransaction(
+            "Time-based relative locks are not supported".to_string()
+        ));
+    }
+    Ok(Some((sequence & SEQUENCE_LOCKTIME_MASK) as u16))
+}
+
+
+/// Check `tx` may be included in a block at `height` and `timestamp`.
+///
+/// Outputs missing from `utxo_set` (e.g. spent from the mempool) are taken
+/// to confirm in that same block, so only a zero relative lock lets them
+/// be spent.
+pub fn check_time_locks(tx: &Transaction, utxo_set: &UTXOSet, height: BlockHeight, timestamp: Timestamp) -> Result<()> {
+    if let Some(lock_time) = LockTime::from_consensus(tx.lock_time) {
+        if !lock_time.is_satisfied(height, timestamp) {
+            return Err(BlockchainError::InvalidTransaction(match lock_time {
+                LockTime::Height(lock) => format!("Transaction locked until block {}", lock),
+                LockTime::Timestamp(lock) => format!("Transaction locked until timestamp {}", lock),
+            }));
+        }
+    }
+
+    for input in &tx.inputs {
+        let Some(blocks) = relative_lock(input.sequence)? else { continue };
+        let confirmed = utxo_set.get_utxo(&input.prev_output)
+            .map(|utxo| utxo.block_height)
+            .unwrap_or(height);
+        if height < confirmed.saturating_add(blocks as BlockHeight) {
+            return Err(BlockchainError::InvalidTransaction(format!(
+                "Input {} locked until block {}",
+                input.prev_output,
+                confirmed.saturating_add(blocks as BlockHeight)
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{TransactionInput, TransactionOutput, UTXO};
+    use blockchain_crypto::{hash::sha256, signature::generate_keypair, address::public_key_to_address, AddressType, Signature};
+
+    #[test]
+    fn test_lock_time_encoding() {
+        assert_eq!(LockTime::from_consensus(0), None);
+        assert_eq!(LockTime::from_consensus(100), Some(LockTime::Height(100)));
+        assert_eq!(LockTime::from_consensus(1_700_000_000), Some(LockTime::Timestamp(1_700_000_000)));
+        assert!(LockTime::Height(10).is_reached_by(LockTime::Height(12)));
+        assert!(!LockTime::Height(10).is_reached_by(LockTime::Timestamp(1_700_000_000)));
+
+        assert_eq!(relative_lock(SEQUENCE_FINAL).unwrap(), None);
+        assert_eq!(relative_lock(relative_lock_sequence(6)).unwrap(), Some(6));
+        assert!(relative_lock(SEQUENCE_LOCKTIME_TYPE_FLAG | 6).is_err());
+    }
+
+    #[test]
+    fn test_relative_and_absolute_locks() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let mut utxo_set = UTXOSet::new();
+        utxo_set.add_utxo(outpoint, UTXO::new(TransactionOutput::new(1000, address.clone()), 10, outpoint.tx_id, 0, false)).unwrap();
+
+        let mut input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *keypair.public_key());
+        input.sequence = relative_lock_sequence(5);
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, address)], 100);
+        let now = Timestamp::now();
+
+        assert!(check_time_locks(&tx, &utxo_set, 14, now).is_err());
+        assert!(check_time_locks(&tx, &utxo_set, 15, now).is_ok());
+
+        // An unconfirmed parent only satisfies a zero relative lock
+        assert!(check_time_locks(&tx, &UTXOSet::new(), 100, now).is_err());
+
+        tx.lock_time = 20;
+        assert!(check_time_locks(&tx, &utxo_set, 19, now).is_err());
+        assert!(check_time_locks(&tx, &utxo_set, 20, now).is_ok());
+    }
+}
@@ -1,10 +1,45 @@
 use crate::types::*;
+use crate::codec;
+use crate::state::UTXOSet;
+use crate::timelock::{relative_lock, LockTime, SEQUENCE_FINAL};
 use crate::{BlockchainError, Result};
-use blockchain_crypto::{Hash256, Address, PublicKey, Signature, hash::sha256};
+use blockchain_crypto::bip340::{Bip340Signature, XOnlyPublicKey};
+use blockchain_crypto::{Hash256, Address, AddressType, PublicKey, Signature, hash::sha256, signature::{Keypair, SignatureCache, SigCacheMode}};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 
+///size in bytes of the coinbase extra nonce
+pub const EXTRA_NONCE_SIZE: usize = 8;
+
+///marks the extra nonce region appended to coinbase data. the region is the
+///marker, a length byte of EXTRA_NONCE_SIZE, then the nonce, so data that
+///merely ends in 8 bytes is never taken for one
+pub const EXTRA_NONCE_MARKER: &[u8; 4] = b"xnon";
+
+const EXTRA_NONCE_REGION: usize = EXTRA_NONCE_MARKER.len() + 1 + EXTRA_NONCE_SIZE;
+
+///gas limit `TransactionBuilder` gives a plain account transfer
+pub const TRANSFER_GAS: Gas = 21_000;
+
+
+
+///the chain a signature is valid on. input signatures commit to it along
+///with the transaction, so a transaction signed for one network (or one side
+///of a fork) can't be replayed on another
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct SigningDomain {
+	pub chain_id: ChainId,
+	pub fork_id: ForkId,
+}
+
+
+impl SigningDomain {
+	pub fn new(chain_id: ChainId, fork_id: ForkId) -> Self {
+		Self { chain_id, fork_id }
+	}
+}
+
 
 ///transaction input for utxo model
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -15,8 +50,8 @@ pub struct TransactionInput {
 	pub script_sig: Signature,
 	///public key of the sender
 	pub public_key: PublicKey,
-	///sequence number(for time-locked transactions)
-	pub sequence: i32,
+	///sequence number, holding the input's relative lock (see `timelock`)
+	pub sequence: u32,
 }
 
 
@@ -32,12 +67,81 @@ impl TransactionInput {
 			script_sig: Vec::new(),
 			signature,
 			public_key,
-			sequence: 0xFFFFFFFF, //NO TIME LOCK
+			sequence: SEQUENCE_FINAL, //NO TIME LOCK
 		}
 	}
 }
 
 
+///unlocking data of a P2SH spend, carried in the input's `script_sig`: the
+///redeem script the output's hash commits to, and what unlocks that script
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptHashSpend {
+	///serialized redeem script (see `Script::to_bytes`)
+	pub redeem_script: Vec<u8>,
+	///`script_sig` the redeem script is evaluated with
+	pub script_sig: Vec<u8>,
+}
+
+
+///one signature of a multisig spend, and which of the script's keys made it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigSignature {
+	///position of the signing key in the script
+	pub key_index: u8,
+	pub signature: Signature,
+}
+
+
+impl TransactionInput {
+	///signatures of a multisig spend. they travel in `script_sig`, which the
+	///signed hash leaves out, so cosigners can add theirs in any order
+	pub fn multisig_signatures(&self) -> Result<Vec<MultisigSignature>> {
+		if self.script_sig.is_empty() {
+			return Ok(Vec::new());
+		}
+		codec::decode(&self.script_sig)
+			.map_err(|e| BlockchainError::InvalidTransaction(format!("Malformed multisig signatures: {}", e)))
+	}
+
+	///reveal `redeem_script` to spend a P2SH output. call it once the input
+	///holds what unlocks the redeem script (e.g. its multisig signatures)
+	pub fn set_redeem_script(&mut self, redeem_script: &Script) {
+		let spend = ScriptHashSpend {
+			redeem_script: redeem_script.to_bytes(),
+			script_sig: std::mem::take(&mut self.script_sig),
+		};
+		self.script_sig = codec::encode(&spend);
+	}
+
+	///the redeem script and unlocking data of a P2SH spend
+	pub fn script_hash_spend(&self) -> Result<ScriptHashSpend> {
+		codec::decode(&self.script_sig)
+			.map_err(|e| BlockchainError::InvalidTransaction(format!("Malformed P2SH spend: {}", e)))
+	}
+
+	///set the signatures of a multisig spend, ordered by key index
+	pub fn set_multisig_signatures(&mut self, mut signatures: Vec<MultisigSignature>) {
+		signatures.sort_by_key(|signature| signature.key_index);
+		signatures.dedup_by_key(|signature| signature.key_index);
+		self.script_sig = codec::encode(&signatures);
+	}
+
+	///set the BIP-340 signature spending a Schnorr key output: a MuSig2
+	///aggregated signature, or one completed from an adaptor signature. like
+	///multisig signatures it travels in `script_sig`, since it signs the transaction hash
+	pub fn set_schnorr_signature(&mut self, signature: &Bip340Signature) {
+		self.script_sig = codec::encode(signature);
+	}
+
+	///the signature of a Schnorr key spend
+	pub fn schnorr_signature(&self) -> Result<Bip340Signature> {
+		codec::decode(&self.script_sig)
+			.map_err(|e| BlockchainError::InvalidTransaction(format!("Malformed Schnorr signature: {}", e)))
+	}
+}
+
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TransactionOutput {
 	///amount of cryptocurrency
@@ -60,9 +164,67 @@ impl TransactionOutput{
 			address,
 		}
 	}
+
+	///output paying to the hash of `redeem_script`, which its spender reveals.
+	///the address is the script-hash address, so it can be shared before the
+	///script is
+	pub fn pay_to_script(amount: Amount, redeem_script: &Script) -> Result<Self> {
+		if !redeem_script.is_valid() || matches!(redeem_script, Script::PayToScriptHash(_)) {
+			return Err(BlockchainError::InvalidTransaction(
+				format!("Invalid redeem script: {:?}", redeem_script)
+				));
+		}
+
+		Ok(Self {
+			amount,
+			script_pubkey: Script::pay_to_script_hash(redeem_script),
+			address: Address::from_script(&redeem_script.to_bytes(), AddressType::Base58),
+		})
+	}
+
+	///output spendable by any `threshold` of `public_keys`. its address is
+	///derived from the script, for display and indexing
+	pub fn multisig(amount: Amount, threshold: u8, public_keys: Vec<PublicKey>) -> Result<Self> {
+		let key_count = public_keys.len();
+		let script = Script::multi_sig(threshold, public_keys);
+		if !script.is_valid() {
+			return Err(BlockchainError::InvalidTransaction(
+				format!("Invalid multisig script: {} of {} keys", threshold, key_count)
+				));
+		}
+
+		Ok(Self {
+			amount,
+			address: Address::from_hash(script.hash(), AddressType::Base58),
+			script_pubkey: script,
+		})
+	}
+
+	///output spendable only by all of `public_keys` together, with one
+	///aggregated signature. it looks like a single-key output on chain
+	pub fn aggregate_key(amount: Amount, public_keys: &[XOnlyPublicKey]) -> Result<Self> {
+		let script = Script::pay_to_aggregate_key(public_keys)?;
+		Ok(Self {
+			amount,
+			address: Address::from_hash(script.hash(), AddressType::Base58),
+			script_pubkey: script,
+		})
+	}
+
+	///output spendable with a BIP-340 signature by `public_key`, which an
+	///adaptor signature can lock to a secret
+	pub fn schnorr_key(amount: Amount, public_key: XOnlyPublicKey) -> Self {
+		let script = Script::pay_to_schnorr_key(public_key);
+		Self {
+			amount,
+			address: Address::from_hash(script.hash(), AddressType::Base58),
+			script_pubkey: script,
+		}
+	}
 }
 
 
+
 //=================UTXO===================
 
 ///utxo (unspent transaction output)
@@ -238,20 +400,39 @@ impl Transaction {
 		}
 	}
 
-	///calculate transaction hash
+	///calculate transaction hash, leaving out input signatures and unlocking
+	///data: it is what inputs sign, so it can't cover the signatures
 	pub fn hash(&self) -> Hash256 {
 		let serialized = self.serialize_for_hash();
 		sha256(&serialized)
 	}
 
-	///Get transaction ID
+	///Get transaction ID. outpoints, the block merkle root and the mempool all
+	///refer to transactions by it, so signing or re-encoding a signature
+	///doesn't change what spends the transaction's outputs
 	pub fn id(&self) -> TxId {
 		TxId::new(self.hash())
 	}
 
+	///what input signatures sign: the transaction hash bound to the chain
+	///and fork of `domain`
+	pub fn signing_hash(&self, domain: SigningDomain) -> Hash256 {
+		let mut message = b"kaiblock tx".to_vec();
+		message.extend_from_slice(&domain.chain_id.to_be_bytes());
+		message.extend_from_slice(&domain.fork_id.to_be_bytes());
+		message.extend_from_slice(self.hash().as_bytes());
+		sha256(&message)
+	}
+
+	///Get the witness transaction ID, which covers the signatures too and so
+	///tells apart two differently signed copies of one transaction
+	pub fn wtxid(&self) -> WtxId {
+		WtxId::new(sha256(&codec::encode(self)))
+	}
+
 
 	///serialize transaction for hashing(excluding signatures)
-	fn serialize_for_hash(&self) -> Vec<u8> {
+	pub(crate) fn serialize_for_hash(&self) -> Vec<u8> {
 		///create a copy without signatures for hasjing
 		let mut tx_for_hash = self.clone();
 
@@ -261,7 +442,7 @@ impl Transaction {
 			input.script_sig.clear();
 
 		}
-		bincode::serialize(&tx_for_hash).unwrap_or_default()
+		codec::encode(&tx_for_hash)
 	}
 
 
@@ -305,72 +486,344 @@ impl Transaction {
 
 
 
-	///calculate transaction fee for account model
+	///calculate transaction fee for account model. Saturates: gas limit and
+	///price come off the wire, and a fee that doesn't fit is unaffordable anyway
 	pub fn calculate_gas_fee(&self) -> Fee {
 		if let (Some(gas_limit), Some(gas_price)) = (self.gas_limit, self.gas_price) {
-			gas_limit * gas_price
+			gas_limit.saturating_mul(gas_price)
 		}els {
 			self.fee
 		}
 	}
 
+	///amount moved plus the gas fee, what the sender's balance has to cover
+	pub fn total_cost(&self) -> Amount {
+		self.amount.unwrap_or(0).saturating_add(self.calculate_gas_fee())
+	}
+
+	///compute units requested from the runtime (0 for transactions that don't run programs)
+	pub fn compute_units(&self) -> u64 {
+		match self.tx_type {
+			TransactionType::ContractCall | TransactionType::ContractDeployment => self.gas_limit.unwrap_or(0),
+			_ => 0,
+		}
+	}
+
+	///signature checks needed to validate: one per input, plus the sender's for account transactions
+	pub fn sig_ops(&self) -> u64 {
+		if self.is_coinbase() {
+			return 0;
+		}
+		self.inputs.len() as u64 + u64::from(self.from.is_some())
+	}
+
+	///fee paid per requested compute unit, used to rank runtime transactions
+	pub fn fee_per_compute_unit(&self) -> u64 {
+		match self.compute_units() {
+			0 => 0,
+			units => self.calculate_gas_fee() / units,
+		}
+	}
+
 	///check if transaction is coinbase
 	pub fn is_coinbase(&self) -> bool{
 		self.tx_type = TransactionType::Coinbase
 	}
 
 
-	///get transaction size in bytes
+	///offset in data of the coinbase extra nonce, if the region is there
+	fn extra_nonce_offset(&self) -> Option<usize> {
+		if !self.is_coinbase() || self.data.len() < EXTRA_NONCE_REGION {
+			return None;
+		}
+
+		let start = self.data.len() - EXTRA_NONCE_REGION;
+		let (marker, rest) = self.data[start..].split_at(EXTRA_NONCE_MARKER.len());
+		(marker == EXTRA_NONCE_MARKER && rest[0] as usize == EXTRA_NONCE_SIZE)
+			.then_some(self.data.len() - EXTRA_NONCE_SIZE)
+	}
+
+
+	///get the coinbase extra nonce, if its region has been appended to the data
+	pub fn extra_nonce(&self) -> Option<u64> {
+		let offset = self.extra_nonce_offset()?;
+		let mut bytes = [0u8; EXTRA_NONCE_SIZE];
+		bytes.copy_from_slice(&self.data[offset..]);
+		Some(u64::from_le_bytes(bytes))
+	}
+
+
+	///coinbase data without the extra nonce region
+	pub fn coinbase_data(&self) -> &[u8] {
+		match self.extra_nonce_offset() {
+			Some(offset) => &self.data[..offset - EXTRA_NONCE_MARKER.len() - 1],
+			None => &self.data,
+		}
+	}
+
+
+	///set the coinbase extra nonce, appending its region to the data on first use.
+	///whatever the data held before stays as it was
+	pub fn set_extra_nonce(&mut self, extra_nonce: u64) -> Result<()> {
+		if !self.is_coinbase() {
+			return Err(BlockchainError::InvalidTransaction(
+				"Extra nonce can only be set on a coinbase transaction".to_string()
+				));
+		}
+
+		match self.extra_nonce_offset() {
+			Some(offset) => self.data[offset..].copy_from_slice(&extra_nonce.to_le_bytes()),
+			None => {
+				self.data.extend_from_slice(EXTRA_NONCE_MARKER);
+				self.data.push(EXTRA_NONCE_SIZE as u8);
+				self.data.extend_from_slice(&extra_nonce.to_le_bytes());
+			}
+		}
+
+		Ok(())
+	}
+
+
+	///split the hashed coinbase serialization around the extra nonce region.
+	///external miners rebuild the coinbase hash as sha256(prefix || extra_nonce || suffix)
+	pub fn extra_nonce_split(&self) -> Result<(Vec<u8>, Vec<u8>)> {
+		let mut low = self.clone();
+		low.set_extra_nonce(0)?;
+		let mut high = self.clone();
+		high.set_extra_nonce(u64::MAX)?;
+
+		let low_bytes = low.serialize_for_hash();
+		let high_bytes = high.serialize_for_hash();
+
+		//every byte of the region differs between 0x00.. and 0xff.., so the first mismatch is its start
+		let start = low_bytes.iter()
+			.zip(high_bytes.iter())
+			.position(|(a, b)| a != b)
+			.ok_or_else(|| BlockchainError::SerializationError(
+				"Extra nonce region not found in coinbase serialization".to_string()
+				))?;
+
+		Ok((
+			low_bytes[..start].to_vec(),
+			low_bytes[start + EXTRA_NONCE_SIZE..].to_vec(),
+		))
+	}
+
+
+	///get transaction size in bytes, as encoded on the wire
 	pub fn size(&self) -> usize{
-		bincode::serialize(self)
-			.map(|data| data.len())
-			.unwrap_or(0)
+		codec::encode(self).len()
+	}
+
+
+	///sign the input at index for the chain of `domain`. the keypair must own the
+	///public key already on the input, since public keys are part of the signed hash
+	pub fn sign_input(&mut self, keypair: &Keypair, index: usize, domain: SigningDomain) -> Result<()> {
+		let input = self.inputs.get(index)
+			.ok_or_else(|| BlockchainError::InvalidTransaction(
+				format!("Input index {} out of range ({} inputs)", index, self.inputs.len())
+				))?;
+
+		if input.public_key != *keypair.public_key() {
+			return Err(BlockchainError::InvalidTransaction(
+				format!("Key does not match public key of input {}", index)
+				));
+		}
+
+		let signing_hash = self.signing_hash(domain);
+		self.inputs[index].signature = keypair.sign(signing_hash.as_bytes());
+		Ok(())
 	}
 
 
-	///verify transaction signatures
-	pub fn verify_signature(&self, utxo_set: &HashMap<OutPoint, UTXO>) -> Result<bool> {
+	///verify every input is signed for the chain of `domain` by the key that can
+	///spend the utxo it references
+	pub fn verify_signatures(&self, utxo_set: &UTXOSet, domain: SigningDomain) -> Result<bool> {
+		self.verify_inputs(utxo_set, &self.signed_hashes(domain, false), |public_key, message, signature| public_key.verify(message, signature))
+	}
+
+
+	///verify_signatures, answering from and updating a shared signature cache.
+	///with `accept_legacy`, inputs signed over the bare transaction hash, as
+	///before signatures committed to the chain, pass too
+	pub fn verify_signatures_cached(&self, utxo_set: &UTXOSet, cache: &SignatureCache, mode: SigCacheMode, domain: SigningDomain, accept_legacy: bool) -> Result<bool> {
+		self.verify_inputs(utxo_set, &self.signed_hashes(domain, accept_legacy), |public_key, message, signature| cache.verify(public_key, message, signature, mode))
+	}
+
+
+	fn verify_inputs<F>(&self, utxo_set: &UTXOSet, signed_hashes: &[Hash256], verify: F) -> Result<bool>
+	where
+		F: Fn(&PublicKey, &[u8], &Signature) -> bool,
+	{
 		//skip signature verification for coinbase
 		if self.is_coinbase() {
 			return Ok(true);
 		}
-		let tx_hash = self.hash();
 
 		//verify utxo input signatures
 		for input in &self.inputs{
-			//erify that the public key can spend utxo
-			if let Some(utxo) = utxo_set.get(&input.prev_output) {
-
-				//for p2pkh, verify the public key hashed matches
-				if let Script::PayToPubKeyHash(expected_hash) = &utxo.output.script_pubkey{
-					let pubkey_hash = sha256(&input.public_key.to_bytes());
-					if pubkey_hash != *expected_hash{
-						return Ok(false);
-					}
-				}
-
-				//verify signature
-				if !input.public_key.verify(tx_hash.as_bytes(), &input.signature){
-					return Ok(false);
-				}
-			}else {
-				return Err(BlockchainError:;InvalidTransaction(
+			let utxo = utxo_set.get_utxo(&input.prev_output)
+				.ok_or_else(|| BlockchainError::InvalidTransaction(
 					format!("UTXO not found: {}", input.prev_output)
-					));
+					))?;
+
+			if !Self::input_authorized_by_any(input, utxo, self.lock_time, signed_hashes, &verify)? {
+				return Ok(false);
 			}
 		}
 		Ok(true)
 	}
 
+
+	///verify the single input spending `utxo`, e.g. to check a transaction whose other inputs are unknown
+	pub fn verify_input_signature(&self, input_index: usize, utxo: &UTXO, domain: SigningDomain, accept_legacy: bool) -> Result<bool> {
+		let input = self.inputs.get(input_index)
+			.ok_or_else(|| BlockchainError::InvalidTransaction(
+				format!("Input {} out of range", input_index)
+				))?;
+		Self::input_authorized_by_any(input, utxo, self.lock_time, &self.signed_hashes(domain, accept_legacy), &|public_key: &PublicKey, message: &[u8], signature: &Signature| {
+			public_key.verify(message, signature)
+		})
+	}
+
+
+	//hashes an input signature may be over: the domain's signing hash, and
+	//the bare transaction hash of legacy signatures if they are accepted
+	fn signed_hashes(&self, domain: SigningDomain, accept_legacy: bool) -> Vec<Hash256> {
+		let mut hashes = vec![self.signing_hash(domain)];
+		if accept_legacy {
+			hashes.push(self.hash());
+		}
+		hashes
+	}
+
+
+	//an input passes if it is authorized over one of `signed_hashes`
+	fn input_authorized_by_any<F>(input: &TransactionInput, utxo: &UTXO, lock_time: u32, signed_hashes: &[Hash256], verify: &F) -> Result<bool>
+	where
+		F: Fn(&PublicKey, &[u8], &Signature) -> bool,
+	{
+		for signed_hash in signed_hashes {
+			if Self::input_authorized(input, &utxo.output.script_pubkey, utxo, lock_time, signed_hash, verify)? {
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	}
+
+
+	//whether `input` satisfies `script`, the locking script of `utxo` or one
+	//nested in it. time lock scripts only compare against the transaction's
+	//own lock fields; validation holds those to the chain
+	fn input_authorized<F>(input: &TransactionInput, script: &Script, utxo: &UTXO, lock_time: u32, tx_hash: &Hash256, verify: &F) -> Result<bool>
+	where
+		F: Fn(&PublicKey, &[u8], &Signature) -> bool,
+	{
+		//verify that the public key can spend the utxo
+		let owns_output = match script {
+			Script::PayToPubkeyHash(expected_hash) => {
+				let address = Address::from_public_key(&input.public_key, utxo.output.address.address_type());
+				sha256(address.data()) == *expected_hash
+			}
+			Script::PayToPubkey(public_key) => *public_key == input.public_key,
+			Script::MultiSig { threshold, public_keys } => {
+				return Self::multisig_authorized(input, *threshold, public_keys, tx_hash, verify);
+			}
+			Script::PayToScriptHash(expected_hash) => {
+				return Self::script_hash_authorized(input, expected_hash, utxo, lock_time, tx_hash, verify);
+			}
+			Script::CheckLockTime { lock_time: required, script } => {
+				let reached = match (LockTime::from_consensus(*required), LockTime::from_consensus(lock_time)) {
+					(Some(required), Some(lock_time)) => required.is_reached_by(lock_time),
+					_ => false,
+				};
+				return Ok(reached && Self::input_authorized(input, script, utxo, lock_time, tx_hash, verify)?);
+			}
+			Script::CheckSequence { blocks, script } => {
+				let reached = relative_lock(input.sequence)?.is_some_and(|lock| lock >= *blocks);
+				return Ok(reached && Self::input_authorized(input, script, utxo, lock_time, tx_hash, verify)?);
+			}
+			Script::PayToSchnorrKey(public_key) => {
+				return Ok(public_key.verify(tx_hash.as_bytes(), &input.schnorr_signature()?));
+			}
+			script => {
+				return Err(BlockchainError::InvalidTransaction(
+					format!("Unsupported script for signature verification: {:?}", script)
+					));
+			}
+		};
+
+		//verify signature
+		Ok(owns_output && verify(&input.public_key, tx_hash.as_bytes(), &input.signature))
+	}
+
+
+	//a P2SH spend reveals a redeem script matching the output's hash, then
+	//satisfies the redeem script with the rest of its script_sig. redeem
+	//scripts don't nest
+	fn script_hash_authorized<F>(input: &TransactionInput, expected_hash: &Hash256, utxo: &UTXO, lock_time: u32, tx_hash: &Hash256, verify: &F) -> Result<bool>
+	where
+		F: Fn(&PublicKey, &[u8], &Signature) -> bool,
+	{
+		let spend = input.script_hash_spend()?;
+		if blockchain_crypto::address::script_hash(&spend.redeem_script) != *expected_hash {
+			return Ok(false);
+		}
+		let redeem_script = Script::from_bytes(&spend.redeem_script)?;
+		if matches!(redeem_script, Script::PayToScriptHash(_)) || !redeem_script.is_valid() {
+			return Ok(false);
+		}
+
+		let inner = TransactionInput { script_sig: spend.script_sig, ..input.clone() };
+		Self::input_authorized(&inner, &redeem_script, utxo, lock_time, tx_hash, verify)
+	}
+
+
+	//a multisig spend needs `threshold` valid signatures by distinct keys of
+	//the script. a bad or extra signature fails the input rather than being
+	//skipped, so the signatures can't be padded with junk
+	fn multisig_authorized<F>(input: &TransactionInput, threshold: u8, public_keys: &[PublicKey], tx_hash: &Hash256, verify: &F) -> Result<bool>
+	where
+		F: Fn(&PublicKey, &[u8], &Signature) -> bool,
+	{
+		let script = Script::multi_sig(threshold, public_keys.to_vec());
+		if !script.is_valid() {
+			return Ok(false);
+		}
+
+		let signatures = input.multisig_signatures()?;
+		if signatures.len() != threshold as usize {
+			return Ok(false);
+		}
+		//strictly increasing indexes: no key counts twice
+		if signatures.windows(2).any(|pair| pair[0].key_index >= pair[1].key_index) {
+			return Ok(false);
+		}
+
+		Ok(signatures.iter().all(|signature| {
+			public_keys.get(signature.key_index as usize)
+				.is_some_and(|public_key| verify(public_key, tx_hash.as_bytes(), &signature.signature))
+		}))
+	}
+
 }
 
 
-/// Transaction builder for easier construction
+/// Transaction builder for easier construction.
+///
+/// `build` assembles exactly what it was given. `build_and_sign` also fills in
+/// what can be worked out: for UTXO transactions it turns `spend`s into
+/// signed inputs, charges `fee_rate` per encoded byte and pays what is left
+/// to `change_address`; for account transactions it prices gas at `fee_rate`.
 pub struct TransactionBuilder {
     version: u32,
     inputs: Vec<TransactionInput>,
     outputs: Vec<TransactionOutput>,
+    //outputs to spend with the signing key, whose amounts are known
+    spends: Vec<UTXO>,
     fee: Fee,
+    fee_rate: Option<Fee>,
+    change_address: Option<Address>,
     tx_type: TransactionType,
     from: Option<Address>,
     to: Option<Address>,
@@ -389,7 +842,10 @@ impl TransactionBuilder {
             version: 1,
             inputs: Vec::new(),
             outputs: Vec::new(),
+            spends: Vec::new(),
             fee: 0,
+            fee_rate: None,
+            change_address: None,
             tx_type: TransactionType::Transfer,
             from: None,
             to: None,
@@ -411,6 +867,30 @@ impl TransactionBuilder {
     	self.outputs.push(output);
     	self
     }
+
+    /// Pay `amount` to `address`
+    pub fn pay(self, address: Address, amount: Amount) -> Self {
+        self.add_output(TransactionOutput::new(amount, address))
+    }
+
+    /// Spend an output owned by the key given to `build_and_sign`
+    pub fn spend(mut self, utxo: UTXO) -> Self {
+        self.spends.push(utxo);
+        self
+    }
+
+    /// Fee per encoded byte for UTXO transactions, or gas price for account
+    /// transactions. Replaces any fixed `fee`.
+    pub fn fee_rate(mut self, fee_rate: Fee) -> Self {
+        self.fee_rate = Some(fee_rate);
+        self
+    }
+
+    /// Where what the spent outputs hold beyond the payments and fee goes
+    pub fn change_address(mut self, change_address: Address) -> Self {
+        self.change_address = Some(change_address);
+        self
+    }
 pub fn fee(mut self, fee: Fee) -> Self {
         self.fee = fee;
         self
@@ -471,6 +951,122 @@ pub fn fee(mut self, fee: Fee) -> Self {
     		data: self.data,
     	}
     }
+
+
+    /// Complete the transaction and sign it with `keypair` for the chain of `domain`
+    pub fn build_and_sign(self, keypair: &Keypair, domain: SigningDomain) -> Result<Transaction> {
+        let account = self.from.is_some() || self.to.is_some() || self.amount.is_some();
+        let utxo = !self.inputs.is_empty() || !self.spends.is_empty() || !self.outputs.is_empty();
+        match (account, utxo) {
+            (true, true) => Err(BlockchainError::InvalidTransaction(
+                "Transaction mixes account fields with inputs and outputs".to_string()
+            )),
+            (true, false) => self.build_account(keypair),
+            (false, _) => self.build_utxo(keypair, domain),
+        }
+    }
+
+    //account transactions carry no signature of their own; the key has to
+    //be the sender's
+    fn build_account(mut self, keypair: &Keypair) -> Result<Transaction> {
+        let missing = |field: &str| BlockchainError::InvalidTransaction(format!("Account transaction needs {}", field));
+        let from = self.from.clone().ok_or_else(|| missing("a sender"))?;
+        self.to.as_ref().ok_or_else(|| missing("a recipient"))?;
+        self.amount.ok_or_else(|| missing("an amount"))?;
+        self.nonce.ok_or_else(|| missing("a nonce"))?;
+        if Address::from_public_key(keypair.public_key(), from.address_type()).data() != from.data() {
+            return Err(BlockchainError::InvalidTransaction("Key does not match the sender".to_string()));
+        }
+
+        if self.tx_type == TransactionType::Transfer && !self.data.is_empty() {
+            self.tx_type = TransactionType::ContractCall;
+        }
+        if self.gas_limit.is_none() {
+            if self.tx_type != TransactionType::Transfer {
+                return Err(missing("a gas limit to run a program"));
+            }
+            self.gas_limit = Some(TRANSFER_GAS);
+        }
+        if let Some(fee_rate) = self.fee_rate {
+            self.gas_price = Some(fee_rate);
+        }
+        self.gas_price.ok_or_else(|| missing("a gas price or fee rate"))?;
+        Ok(self.build())
+    }
+
+    fn build_utxo(mut self, keypair: &Keypair, domain: SigningDomain) -> Result<Transaction> {
+        if !self.inputs.is_empty() && (self.fee_rate.is_some() || self.change_address.is_some()) {
+            return Err(BlockchainError::InvalidTransaction(
+                "Fee rate and change need input amounts; add inputs with spend".to_string()
+            ));
+        }
+        let spends = std::mem::take(&mut self.spends);
+        if spends.is_empty() {
+            return sign_inputs(self.build(), keypair, domain);
+        }
+        //blank signatures have the size of real ones, so sizing works before signing
+        self.inputs.extend(spends.iter()
+            .map(|utxo| TransactionInput::new(utxo.outpoint(), Signature::from_bytes([0u8; 64]), *keypair.public_key())));
+
+        let available: Amount = spends.iter().map(|utxo| utxo.output.amount).sum();
+        let paid = self.outputs.iter().map(|output| output.amount).sum::<Amount>();
+        let change_address = self.change_address.take();
+        let fee_rate = self.fee_rate;
+        let mut tx = self.build();
+
+        //size with amounts and fee at the largest values they can take, so
+        //the fee never comes out short
+        let fee_for = |tx: &Transaction, with_change: bool| -> Fee {
+            let Some(fee_rate) = fee_rate else { return tx.fee };
+            let mut sized = tx.clone();
+            sized.fee = available;
+            if let (true, Some(address)) = (with_change, &change_address) {
+                sized.outputs.push(TransactionOutput::new(available, address.clone()));
+            }
+            sized.size() as Fee * fee_rate
+        };
+
+        let fee = fee_for(&tx, false);
+        let required = paid + fee;
+        if available < required {
+            return Err(BlockchainError::InsufficientBalance { required, available });
+        }
+        tx.fee = fee;
+
+        let fee_with_change = fee_for(&tx, true);
+        let change = available.saturating_sub(paid + fee_with_change);
+        match &change_address {
+            Some(address) if change > 0 => {
+                tx.fee = fee_with_change;
+                tx.outputs.push(TransactionOutput::new(change, address.clone()));
+            }
+            //too little left for a change output to pay for itself
+            Some(_) => tx.fee = available - paid,
+            None if available > required => {
+                return Err(BlockchainError::InvalidTransaction(format!(
+                    "Spent outputs exceed payments and fee by {}; set a change address", available - required
+                )));
+            }
+            None => {}
+        }
+        sign_inputs(tx, keypair, domain)
+    }
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//sign every input the key can spend, leaving others to their owners
+fn sign_inputs(mut tx: Transaction, keypair: &Keypair, domain: SigningDomain) -> Result<Transaction> {
+    for index in 0..tx.inputs.len() {
+        if tx.inputs[index].public_key == *keypair.public_key() {
+            tx.sign_input(keypair, index, domain)?;
+        }
+    }
+    Ok(tx)
 }
 
 
@@ -481,6 +1077,10 @@ mod tests {
     use super::*;
     use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
 
+    fn domain() -> SigningDomain {
+        SigningDomain::new(1, 0)
+    }
+
     #[test]
     fn test_utxo_transaction_creation() {
         let keypair = generate_keypair();
@@ -520,6 +1120,27 @@ mod tests {
         assert_eq!(tx.outputs[0].amount, 5000000000);
     }
 
+    #[test]
+    fn test_extra_nonce_keeps_coinbase_data() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+
+        let mut tx = Transaction::new_coinbase(address, 5000000000, 1);
+        tx.data = b"pool tag, 16 b..".to_vec();
+
+        // data that happens to be long enough is not a nonce
+        assert_eq!(tx.extra_nonce(), None);
+
+        tx.set_extra_nonce(7).unwrap();
+        tx.set_extra_nonce(8).unwrap();
+        assert_eq!(tx.extra_nonce(), Some(8));
+        assert_eq!(tx.coinbase_data(), b"pool tag, 16 b..");
+        assert_eq!(tx.data.len(), 16 + EXTRA_NONCE_MARKER.len() + 1 + EXTRA_NONCE_SIZE);
+
+        let mut transfer = Transaction::new_utxo(vec![], vec![], 1);
+        assert!(transfer.set_extra_nonce(1).is_err());
+    }
+
     #[test]
     fn test_transaction_builder() {
         let keypair1 = generate_keypair();
@@ -542,6 +1163,77 @@ mod tests {
         assert_eq!(tx.fee, 10);
     }
 
+    #[test]
+    fn test_transaction_builder_utxo_fee_and_change() {
+        let keypair = generate_keypair();
+        let owner = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let coin = |amount: Amount, seed: &[u8]| {
+            UTXO::new(TransactionOutput::new(amount, owner.clone()), 1, TxId::new(sha256(seed)), 0, false)
+        };
+
+        let tx = TransactionBuilder::new()
+            .spend(coin(60_000, b"a"))
+            .spend(coin(50_000, b"b"))
+            .pay(recipient.clone(), 70_000)
+            .fee_rate(10)
+            .change_address(owner.clone())
+            .build_and_sign(&keypair, domain())
+            .unwrap();
+
+        assert_eq!(tx.inputs.len(), 2);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].address, owner);
+        assert!(tx.fee >= tx.size() as Fee * 10);
+        assert_eq!(tx.total_output_amount().unwrap() + tx.fee, 110_000);
+        for (index, input) in tx.inputs.iter().enumerate() {
+            assert!(keypair.public_key().verify(tx.hash().as_bytes(), &input.signature), "input {} unsigned", index);
+        }
+
+        let short = TransactionBuilder::new()
+            .spend(coin(1_000, b"c"))
+            .pay(recipient.clone(), 1_000)
+            .fee_rate(10)
+            .change_address(owner.clone())
+            .build_and_sign(&keypair, domain());
+        assert!(matches!(short, Err(BlockchainError::InsufficientBalance { .. })));
+
+        let no_change = TransactionBuilder::new()
+            .spend(coin(60_000, b"d"))
+            .pay(recipient, 1_000)
+            .fee(10)
+            .build_and_sign(&keypair, domain());
+        assert!(no_change.is_err());
+    }
+
+    #[test]
+    fn test_transaction_builder_account() {
+        let keypair = generate_keypair();
+        let from = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let to = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let tx = TransactionBuilder::new()
+            .from(from.clone())
+            .to(to.clone())
+            .amount(100)
+            .nonce(3)
+            .fee_rate(20)
+            .build_and_sign(&keypair, domain())
+            .unwrap();
+        assert_eq!(tx.gas_limit, Some(TRANSFER_GAS));
+        assert_eq!(tx.calculate_gas_fee(), TRANSFER_GAS * 20);
+        assert_eq!(tx.tx_type, TransactionType::Transfer);
+
+        let wrong_key = TransactionBuilder::new()
+            .from(from)
+            .to(to)
+            .amount(100)
+            .nonce(3)
+            .fee_rate(20)
+            .build_and_sign(&generate_keypair(), domain());
+        assert!(wrong_key.is_err());
+    }
+
     #[test]
     fn test_transaction_hash_consistency() {
         let keypair = generate_keypair();
@@ -594,4 +1286,274 @@ mod tests {
             vec![], // no data
         );
     }
-}
\ No newline at end of file
+
+    fn funded_utxo_set(address: Address, outpoint: OutPoint) -> UTXOSet {
+        let mut utxo_set = UTXOSet::new();
+        let output = TransactionOutput::new(1000, address);
+        let utxo = UTXO::new(output, 1, outpoint.tx_id, outpoint.output_index, false);
+        utxo_set.add_utxo(outpoint, utxo).unwrap();
+        utxo_set
+    }
+
+    #[test]
+    fn test_sign_and_verify_inputs() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let utxo_set = funded_utxo_set(address.clone(), outpoint);
+
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *keypair.public_key());
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, address)], 100);
+
+        // Unsigned spends don't verify
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+
+        tx.sign_input(&keypair, 0, domain()).unwrap();
+        assert!(tx.verify_signatures(&utxo_set, domain()).unwrap());
+
+        // Signing doesn't change the transaction id
+        let tx_id = tx.id();
+        tx.sign_input(&keypair, 0, domain()).unwrap();
+        assert_eq!(tx.id(), tx_id);
+
+        // Tampering after signing invalidates the signature
+        tx.outputs[0].amount = 999;
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+    }
+
+    #[test]
+    fn test_signatures_are_bound_to_the_chain() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let utxo_set = funded_utxo_set(address.clone(), outpoint);
+
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *keypair.public_key());
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, address)], 100);
+        tx.sign_input(&keypair, 0, domain()).unwrap();
+
+        // Replayed on another chain or the other side of a fork, it doesn't verify
+        assert!(!tx.verify_signatures(&utxo_set, SigningDomain::new(2, 0)).unwrap());
+        assert!(!tx.verify_signatures(&utxo_set, SigningDomain::new(1, 1)).unwrap());
+
+        // Legacy signatures over the bare hash pass only when accepted
+        let cache = SignatureCache::default();
+        tx.inputs[0].signature = keypair.sign(tx.hash().as_bytes());
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+        assert!(!tx.verify_signatures_cached(&utxo_set, &cache, SigCacheMode::Store, domain(), false).unwrap());
+        assert!(tx.verify_signatures_cached(&utxo_set, &cache, SigCacheMode::Store, domain(), true).unwrap());
+    }
+
+    #[test]
+    fn test_wtxid_covers_signatures() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *keypair.public_key());
+        let unsigned = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, address)], 100);
+        let mut signed = unsigned.clone();
+        signed.sign_input(&keypair, 0, domain()).unwrap();
+
+        assert_eq!(signed.id(), unsigned.id());
+        assert_ne!(signed.wtxid(), unsigned.wtxid());
+
+        // the witness id round-trips through the codec
+        let decoded: Transaction = codec::decode(&codec::encode(&signed)).unwrap();
+        assert_eq!(decoded.wtxid(), signed.wtxid());
+    }
+
+    #[test]
+    fn test_sign_input_rejects_wrong_key() {
+        let owner = generate_keypair();
+        let other = generate_keypair();
+        let address = public_key_to_address(owner.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let utxo_set = funded_utxo_set(address.clone(), outpoint);
+
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *owner.public_key());
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, address.clone())], 100);
+
+        assert!(tx.sign_input(&other, 0, domain()).is_err());
+        assert!(tx.sign_input(&owner, 1, domain()).is_err());
+
+        // A key that signs correctly but doesn't own the utxo is rejected
+        let thief_input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *other.public_key());
+        let mut theft = Transaction::new_utxo(vec![thief_input], vec![TransactionOutput::new(900, address)], 100);
+        theft.sign_input(&other, 0, domain()).unwrap();
+        assert!(!theft.verify_signatures(&utxo_set, domain()).unwrap());
+    }
+
+    #[test]
+    fn test_multisig_spend_needs_threshold_signatures() {
+        let keypairs: Vec<_> = (0..3).map(|_| generate_keypair()).collect();
+        let public_keys: Vec<PublicKey> = keypairs.iter().map(|keypair| *keypair.public_key()).collect();
+        let output = TransactionOutput::multisig(1000, 2, public_keys.clone()).unwrap();
+        assert!(TransactionOutput::multisig(1000, 4, public_keys.clone()).is_err());
+        assert!(TransactionOutput::multisig(1000, 0, public_keys.clone()).is_err());
+
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let mut utxo_set = UTXOSet::new();
+        utxo_set.add_utxo(outpoint, UTXO::new(output.clone(), 1, outpoint.tx_id, 0, false)).unwrap();
+
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), public_keys[0]);
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, output.address.clone())], 100);
+        let tx_hash = tx.hash();
+        let signing_hash = tx.signing_hash(domain());
+        let sign = |index: usize| MultisigSignature {
+            key_index: index as u8,
+            signature: keypairs[index].sign(signing_hash.as_bytes()),
+        };
+
+        // One signature is not enough
+        tx.inputs[0].set_multisig_signatures(vec![sign(2)]);
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+
+        // Two keys of the script are, in any order, without changing the id
+        tx.inputs[0].set_multisig_signatures(vec![sign(2), sign(0)]);
+        assert!(tx.verify_signatures(&utxo_set, domain()).unwrap());
+        assert_eq!(tx.hash(), tx_hash);
+
+        // The same key twice doesn't count as two
+        tx.inputs[0].script_sig = codec::encode(&vec![sign(1), sign(1)]);
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+
+        // Nor does a signature under the wrong key index
+        let mut wrong = sign(0);
+        wrong.key_index = 1;
+        tx.inputs[0].set_multisig_signatures(vec![wrong, sign(2)]);
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+    }
+
+    #[test]
+    fn test_aggregate_key_spend_needs_every_signer() {
+        use blockchain_crypto::bip340::{aggregate_nonces, generate_nonce, Bip340Keypair, KeyAggContext, SigningSession};
+
+        let keypairs: Vec<Bip340Keypair> = (0..2).map(|_| Bip340Keypair::generate()).collect();
+        let keys: Vec<XOnlyPublicKey> = keypairs.iter().map(|keypair| *keypair.public_key()).collect();
+        let output = TransactionOutput::aggregate_key(1000, &keys).unwrap();
+        assert!(TransactionOutput::aggregate_key(1000, &[keys[0], keys[0]]).is_err());
+
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let mut utxo_set = UTXOSet::new();
+        utxo_set.add_utxo(outpoint, UTXO::new(output.clone(), 1, outpoint.tx_id, 0, false)).unwrap();
+
+        let owner = generate_keypair();
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *owner.public_key());
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, output.address.clone())], 100);
+        let tx_hash = tx.hash();
+        let message = tx.signing_hash(domain());
+
+        // No signature at all
+        assert!(tx.verify_signatures(&utxo_set, domain()).is_err());
+
+        // Both signers, in two rounds
+        let context = KeyAggContext::new(keys.clone()).unwrap();
+        let (secret_nonces, public_nonces): (Vec<_>, Vec<_>) = keypairs.iter()
+            .map(|keypair| generate_nonce(keypair, context.aggregate_key(), message.as_bytes()))
+            .unzip();
+        let session = SigningSession::new(&context, &aggregate_nonces(&public_nonces).unwrap(), message.as_bytes());
+        let partials: Vec<_> = keypairs.iter()
+            .zip(secret_nonces)
+            .map(|(keypair, nonce)| session.partial_sign(keypair, nonce).unwrap())
+            .collect();
+        tx.inputs[0].set_schnorr_signature(&session.aggregate(&partials));
+        assert!(tx.verify_signatures(&utxo_set, domain()).unwrap());
+        assert_eq!(tx.hash(), tx_hash);
+
+        // One signer alone can't spend it
+        tx.inputs[0].set_schnorr_signature(&keypairs[0].sign(message.as_bytes()));
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+    }
+
+    #[test]
+    fn test_schnorr_key_spend() {
+        use blockchain_crypto::bip340::Bip340Keypair;
+
+        let keypair = Bip340Keypair::generate();
+        let output = TransactionOutput::schnorr_key(1000, *keypair.public_key());
+        assert_eq!(Script::from_bytes(&output.script_pubkey.to_bytes()).unwrap(), output.script_pubkey);
+
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let mut utxo_set = UTXOSet::new();
+        utxo_set.add_utxo(outpoint, UTXO::new(output.clone(), 1, outpoint.tx_id, 0, false)).unwrap();
+
+        let owner = generate_keypair();
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *owner.public_key());
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, output.address.clone())], 100);
+        let message = tx.signing_hash(domain());
+
+        // No signature at all
+        assert!(tx.verify_signatures(&utxo_set, domain()).is_err());
+
+        tx.inputs[0].set_schnorr_signature(&keypair.sign(message.as_bytes()));
+        assert!(tx.verify_signatures(&utxo_set, domain()).unwrap());
+
+        // Another key's signature doesn't spend it
+        tx.inputs[0].set_schnorr_signature(&Bip340Keypair::generate().sign(message.as_bytes()));
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+    }
+
+    #[test]
+    fn test_multisig_inside_p2sh() {
+        let keypairs: Vec<_> = (0..3).map(|_| generate_keypair()).collect();
+        let public_keys: Vec<PublicKey> = keypairs.iter().map(|keypair| *keypair.public_key()).collect();
+        let redeem_script = Script::multi_sig(2, public_keys.clone());
+        let output = TransactionOutput::pay_to_script(1000, &redeem_script).unwrap();
+        assert_eq!(output.script_pubkey, Script::PayToScriptHash(redeem_script.hash()));
+        assert_eq!(output.address, Address::from_script(&redeem_script.to_bytes(), AddressType::Base58));
+        assert!(TransactionOutput::pay_to_script(1000, &output.script_pubkey).is_err());
+
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let mut utxo_set = UTXOSet::new();
+        utxo_set.add_utxo(outpoint, UTXO::new(output.clone(), 1, outpoint.tx_id, 0, false)).unwrap();
+
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), public_keys[0]);
+        let unsigned = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, output.address.clone())], 100);
+        let tx_hash = unsigned.hash();
+        let signing_hash = unsigned.signing_hash(domain());
+        let signatures: Vec<MultisigSignature> = [0, 2].iter()
+            .map(|&index| MultisigSignature { key_index: index as u8, signature: keypairs[index].sign(signing_hash.as_bytes()) })
+            .collect();
+
+        // The redeem script must be revealed
+        let mut tx = unsigned.clone();
+        tx.inputs[0].set_multisig_signatures(signatures.clone());
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap_or(false));
+
+        tx.inputs[0].set_redeem_script(&redeem_script);
+        assert!(tx.verify_signatures(&utxo_set, domain()).unwrap());
+        assert_eq!(tx.hash(), tx_hash);
+
+        // A different redeem script doesn't match the output's hash, even if it is satisfied
+        let mut other = unsigned.clone();
+        other.inputs[0].set_multisig_signatures(signatures[..1].to_vec());
+        other.inputs[0].set_redeem_script(&Script::multi_sig(1, public_keys.clone()));
+        assert!(!other.verify_signatures(&utxo_set, domain()).unwrap());
+
+        // The redeem script's own threshold still applies
+        let mut short = unsigned;
+        short.inputs[0].set_multisig_signatures(signatures[..1].to_vec());
+        short.inputs[0].set_redeem_script(&redeem_script);
+        assert!(!short.verify_signatures(&utxo_set, domain()).unwrap());
+    }
+
+    #[test]
+    fn test_cached_verification_matches_uncached() {
+        let keypair = generate_keypair();
+        let address = public_key_to_address(keypair.public_key(), AddressType::Base58);
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let utxo_set = funded_utxo_set(address.clone(), outpoint);
+        let cache = SignatureCache::default();
+
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *keypair.public_key());
+        let mut tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, address)], 100);
+        assert!(!tx.verify_signatures_cached(&utxo_set, &cache, SigCacheMode::Store, domain(), false).unwrap());
+
+        tx.sign_input(&keypair, 0, domain()).unwrap();
+        assert!(tx.verify_signatures_cached(&utxo_set, &cache, SigCacheMode::Store, domain(), false).unwrap());
+        assert!(tx.verify_signatures_cached(&utxo_set, &cache, SigCacheMode::Consume, domain(), false).unwrap());
+        assert_eq!(cache.stats().hits, 1);
+        assert!(cache.is_empty());
+    }
+}
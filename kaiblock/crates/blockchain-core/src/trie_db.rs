@@ -0,0 +1,329 @@
+use crate::types::*;
+use crate::{BlockchainError, Result};
+use blockchain_crypto::{Hash256, hash::sha256};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+
+/// Pruning configuration for the state trie node database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PruningConfig {
+    /// number of recent state roots whose nodes are kept
+    pub retained_roots: usize,
+    /// run a full reachability sweep every N committed roots (0 disables it)
+    pub gc_interval: u64,
+}
+
+impl Default for PruningConfig {
+    fn default() -> Self {
+        Self {
+            retained_roots: 128,
+            gc_interval: 1024,
+        }
+    }
+}
+
+
+/// Size and garbage collection counters for the node database
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruningMetrics {
+    /// nodes currently stored
+    pub node_count: usize,
+    /// encoded bytes currently stored
+    pub total_bytes: usize,
+    /// roots currently retained
+    pub retained_roots: usize,
+    /// nodes removed since startup
+    pub nodes_pruned: u64,
+    /// bytes removed since startup
+    pub bytes_pruned: u64,
+    /// full reachability sweeps run
+    pub gc_runs: u64,
+}
+
+
+/// A stored trie node: its encoding, the nodes it points to, and how many
+/// parents and retained roots reference it
+#[derive(Debug, Clone)]
+struct StoredNode {
+    data: Vec<u8>,
+    children: Vec<Hash256>,
+    refs: u32,
+}
+
+
+/// Content-addressed, reference-counted store of state trie nodes.
+///
+/// A node's count is the number of stored parents pointing at it plus the
+/// number of retained roots equal to it. When a root falls out of the
+/// retention window it is dereferenced, and any node whose count drops to
+/// zero is removed along with its now-unreferenced children.
+#[derive(Debug, Clone)]
+pub struct NodeDatabase {
+    config: PruningConfig,
+    nodes: HashMap<Hash256, StoredNode>,
+    roots: VecDeque<(BlockHeight, Hash256)>,
+    commits_since_gc: u64,
+    metrics: PruningMetrics,
+}
+
+impl NodeDatabase {
+    /// Create an empty node database
+    pub fn new(config: PruningConfig) -> Self {
+        Self {
+            config,
+            nodes: HashMap::new(),
+            roots: VecDeque::new(),
+            commits_since_gc: 0,
+            metrics: PruningMetrics::default(),
+        }
+    }
+
+    /// Store an encoded node and the hashes of its children.
+    /// Children must already be stored; inserting an existing node is a no-op.
+    pub fn insert(&mut self, data: Vec<u8>, children: Vec<Hash256>) -> Result<Hash256> {
+        let hash = sha256(&data);
+        if self.nodes.contains_key(&hash) {
+            return Ok(hash);
+        }
+
+        for child in &children {
+            let node = self.nodes.get_mut(child)
+                .ok_or_else(|| BlockchainError::StateError(
+                    format!("Trie node {} references missing child {}", hash, child)
+                ))?;
+            node.refs += 1;
+        }
+
+        self.metrics.total_bytes += data.len();
+        self.nodes.insert(hash, StoredNode { data, children, refs: 0 });
+        self.metrics.node_count = self.nodes.len();
+
+        Ok(hash)
+    }
+
+    /// Get a node's encoding
+    pub fn get(&self, hash: &Hash256) -> Option<&[u8]> {
+        self.nodes.get(hash).map(|node| node.data.as_slice())
+    }
+
+    /// Check if a node is stored
+    pub fn contains(&self, hash: &Hash256) -> bool {
+        self.nodes.contains_key(hash)
+    }
+
+    /// Reference count of a node
+    pub fn ref_count(&self, hash: &Hash256) -> u32 {
+        self.nodes.get(hash).map(|node| node.refs).unwrap_or(0)
+    }
+
+    /// Retain the state root committed at a block height, pruning roots
+    /// that fall outside the retention window
+    pub fn commit_root(&mut self, height: BlockHeight, root: Hash256) -> Result<()> {
+        let node = self.nodes.get_mut(&root)
+            .ok_or_else(|| BlockchainError::StateError(
+                format!("Cannot commit unknown state root {}", root)
+            ))?;
+        node.refs += 1;
+        self.roots.push_back((height, root));
+
+        while self.roots.len() > self.config.retained_roots.max(1) {
+            if let Some((_, old_root)) = self.roots.pop_front() {
+                self.dereference(old_root);
+            }
+        }
+        self.metrics.retained_roots = self.roots.len();
+
+        self.commits_since_gc += 1;
+        if self.config.gc_interval > 0 && self.commits_since_gc >= self.config.gc_interval {
+            self.collect_garbage();
+        }
+
+        Ok(())
+    }
+
+    /// Drop retained roots above a height, e.g. when a reorg disconnects blocks
+    pub fn rollback_to(&mut self, height: BlockHeight) {
+        while let Some(&(root_height, root)) = self.roots.back() {
+            if root_height <= height {
+                break;
+            }
+            self.roots.pop_back();
+            self.dereference(root);
+        }
+        self.metrics.retained_roots = self.roots.len();
+    }
+
+    /// Roots currently retained, oldest first
+    pub fn retained_roots(&self) -> impl Iterator<Item = &(BlockHeight, Hash256)> {
+        self.roots.iter()
+    }
+
+    /// Remove every node not reachable from a retained root.
+    /// This also catches nodes that were inserted but never committed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let mut reachable = HashSet::new();
+        let mut stack: Vec<Hash256> = self.roots.iter().map(|(_, root)| *root).collect();
+
+        while let Some(hash) = stack.pop() {
+            if !reachable.insert(hash) {
+                continue;
+            }
+            if let Some(node) = self.nodes.get(&hash) {
+                stack.extend(node.children.iter().copied());
+            }
+        }
+
+        let unreachable: Vec<Hash256> = self.nodes.keys()
+            .filter(|hash| !reachable.contains(*hash))
+            .copied()
+            .collect();
+
+        for hash in &unreachable {
+            if let Some(node) = self.nodes.remove(hash) {
+                self.record_pruned(&node);
+            }
+        }
+
+        // unreachable parents may have been counted against surviving children
+        for node in self.nodes.values_mut() {
+            node.refs = 0;
+        }
+        let edges: Vec<Hash256> = self.nodes.values()
+            .flat_map(|node| node.children.iter().copied())
+            .chain(self.roots.iter().map(|(_, root)| *root))
+            .collect();
+        for hash in edges {
+            if let Some(node) = self.nodes.get_mut(&hash) {
+                node.refs += 1;
+            }
+        }
+
+        self.commits_since_gc = 0;
+        self.metrics.gc_runs += 1;
+        self.metrics.node_count = self.nodes.len();
+
+        unreachable.len()
+    }
+
+    /// Current size and GC counters
+    pub fn metrics(&self) -> &PruningMetrics {
+        &self.metrics
+    }
+
+    /// Pruning configuration
+    pub fn config(&self) -> &PruningConfig {
+        &self.config
+    }
+
+    fn dereference(&mut self, hash: Hash256) {
+        let mut stack = vec![hash];
+
+        while let Some(hash) = stack.pop() {
+            let remove = match self.nodes.get_mut(&hash) {
+                Some(node) => {
+                    node.refs = node.refs.saturating_sub(1);
+                    node.refs == 0
+                }
+                None => false,
+            };
+
+            if remove {
+                if let Some(node) = self.nodes.remove(&hash) {
+                    stack.extend(node.children.iter().copied());
+                    self.record_pruned(&node);
+                }
+            }
+        }
+
+        self.metrics.node_count = self.nodes.len();
+    }
+
+    fn record_pruned(&mut self, node: &StoredNode) {
+        self.metrics.total_bytes -= node.data.len();
+        self.metrics.nodes_pruned += 1;
+        self.metrics.bytes_pruned += node.data.len() as u64;
+    }
+}
+
+impl Default for NodeDatabase {
+    fn default() -> Self {
+        Self::new(PruningConfig::default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(retained_roots: usize) -> PruningConfig {
+        PruningConfig { retained_roots, gc_interval: 0 }
+    }
+
+    #[test]
+    fn test_shared_nodes_survive_pruning() {
+        let mut db = NodeDatabase::new(config(1));
+
+        let shared = db.insert(b"shared leaf".to_vec(), vec![]).unwrap();
+        let leaf_a = db.insert(b"leaf a".to_vec(), vec![]).unwrap();
+        let leaf_b = db.insert(b"leaf b".to_vec(), vec![]).unwrap();
+        let root_a = db.insert(b"root a".to_vec(), vec![shared, leaf_a]).unwrap();
+        let root_b = db.insert(b"root b".to_vec(), vec![shared, leaf_b]).unwrap();
+
+        db.commit_root(1, root_a).unwrap();
+        assert_eq!(db.ref_count(&shared), 2);
+
+        db.commit_root(2, root_b).unwrap();
+
+        assert!(!db.contains(&root_a));
+        assert!(!db.contains(&leaf_a));
+        assert!(db.contains(&shared));
+        assert!(db.contains(&leaf_b));
+        assert_eq!(db.ref_count(&shared), 1);
+        assert_eq!(db.metrics().nodes_pruned, 2);
+        assert_eq!(db.metrics().retained_roots, 1);
+    }
+
+    #[test]
+    fn test_collect_garbage_removes_uncommitted_nodes() {
+        let mut db = NodeDatabase::new(config(4));
+
+        let leaf = db.insert(b"leaf".to_vec(), vec![]).unwrap();
+        let root = db.insert(b"root".to_vec(), vec![leaf]).unwrap();
+        db.commit_root(1, root).unwrap();
+
+        let stray = db.insert(b"stray".to_vec(), vec![leaf]).unwrap();
+        assert_eq!(db.collect_garbage(), 1);
+
+        assert!(!db.contains(&stray));
+        assert_eq!(db.ref_count(&leaf), 1);
+        assert_eq!(db.metrics().node_count, 2);
+        assert_eq!(db.metrics().gc_runs, 1);
+    }
+
+    #[test]
+    fn test_rollback_releases_disconnected_roots() {
+        let mut db = NodeDatabase::new(config(8));
+
+        let root_1 = db.insert(b"root 1".to_vec(), vec![]).unwrap();
+        let root_2 = db.insert(b"root 2".to_vec(), vec![]).unwrap();
+        db.commit_root(1, root_1).unwrap();
+        db.commit_root(2, root_2).unwrap();
+
+        db.rollback_to(1);
+
+        assert!(db.contains(&root_1));
+        assert!(!db.contains(&root_2));
+        assert_eq!(db.retained_roots().count(), 1);
+    }
+
+    #[test]
+    fn test_insert_requires_children() {
+        let mut db = NodeDatabase::default();
+        let missing = sha256(b"missing");
+
+        assert!(db.insert(b"parent".to_vec(), vec![missing]).is_err());
+        assert!(db.commit_root(0, missing).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::types::*;
+use crate::{BlockchainError, Result};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+
+/// Dependency edges between unconfirmed transactions.
+///
+/// A transaction depends on (is a child of) every pooled transaction whose
+/// output it spends, and on the pooled transaction from the same sender with
+/// the previous nonce. Parents must be included in a block before their
+/// children.
+#[derive(Debug, Clone, Default)]
+pub struct TxDag {
+    parents: HashMap<TxId, HashSet<TxId>>,
+    children: HashMap<TxId, HashSet<TxId>>,
+}
+
+impl TxDag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contains(&self, tx_id: &TxId) -> bool {
+        self.parents.contains_key(tx_id)
+    }
+
+    pub fn parents(&self, tx_id: &TxId) -> impl Iterator<Item = &TxId> {
+        self.parents.get(tx_id).into_iter().flatten()
+    }
+
+    pub fn children(&self, tx_id: &TxId) -> impl Iterator<Item = &TxId> {
+        self.children.get(tx_id).into_iter().flatten()
+    }
+
+    /// Length of the longest chain of unconfirmed ancestors (0 for a root)
+    pub fn depth(&self, tx_id: &TxId) -> usize {
+        let mut memo = HashMap::new();
+        self.depth_memo(tx_id, &mut memo)
+    }
+
+    fn depth_memo(&self, tx_id: &TxId, memo: &mut HashMap<TxId, usize>) -> usize {
+        if let Some(depth) = memo.get(tx_id) {
+            return *depth;
+        }
+        let depth = self.parents(tx_id)
+            .map(|parent| self.depth_memo(parent, memo) + 1)
+            .max()
+            .unwrap_or(0);
+        memo.insert(*tx_id, depth);
+        depth
+    }
+
+    /// Check that adding `tx_id` with `parents` keeps the graph acyclic and
+    /// no deeper than `max_depth`, without changing it
+    pub fn check_insert(&self, tx_id: &TxId, parents: &HashSet<TxId>, max_depth: usize) -> Result<()> {
+        if parents.contains(tx_id) {
+            return Err(BlockchainError::MempoolError(format!("Transaction {} depends on itself", tx_id)));
+        }
+
+        // a cycle needs one of the new parents to already descend from tx_id
+        let descendants = self.descendants(tx_id);
+        if let Some(parent) = parents.iter().find(|parent| descendants.contains(parent)) {
+            return Err(BlockchainError::MempoolError(
+                format!("Transaction {} would create a dependency cycle through {}", tx_id, parent)
+            ));
+        }
+
+        let mut memo = HashMap::new();
+        let depth = parents.iter()
+            .map(|parent| self.depth_memo(parent, &mut memo) + 1)
+            .max()
+            .unwrap_or(0);
+        if depth > max_depth {
+            return Err(BlockchainError::MempoolError(
+                format!("Transaction {} has {} unconfirmed ancestors in a chain (max {})", tx_id, depth, max_depth)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Add `tx_id` with edges to `parents`, after [`TxDag::check_insert`]
+    pub fn insert(&mut self, tx_id: TxId, parents: HashSet<TxId>, max_depth: usize) -> Result<()> {
+        self.check_insert(&tx_id, &parents, max_depth)?;
+        for parent in &parents {
+            self.children.entry(*parent).or_default().insert(tx_id);
+        }
+        self.parents.insert(tx_id, parents);
+        self.children.entry(tx_id).or_default();
+        Ok(())
+    }
+
+    /// Drop `tx_id` and its edges. Its children lose the dependency, which is
+    /// right when it was confirmed; use [`TxDag::descendants`] first when it
+    /// was dropped and its children are no longer valid.
+    pub fn remove(&mut self, tx_id: &TxId) {
+        for parent in self.parents.remove(tx_id).unwrap_or_default() {
+            if let Some(children) = self.children.get_mut(&parent) {
+                children.remove(tx_id);
+            }
+        }
+        for child in self.children.remove(tx_id).unwrap_or_default() {
+            if let Some(parents) = self.parents.get_mut(&child) {
+                parents.remove(tx_id);
+            }
+        }
+    }
+
+    /// Every transaction depending on `tx_id`, directly or not, parents
+    /// before children
+    pub fn descendants(&self, tx_id: &TxId) -> Vec<TxId> {
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        let mut stack: Vec<TxId> = self.children(tx_id).copied().collect();
+        while let Some(next) = stack.pop() {
+            if seen.insert(next) {
+                found.push(next);
+                stack.extend(self.children(&next).copied());
+            }
+        }
+        self.topological_order(found)
+    }
+
+    /// Order `tx_ids` so every transaction comes after its parents among them.
+    /// Otherwise the input order is kept, so callers can pass them sorted by
+    /// priority.
+    pub fn topological_order(&self, tx_ids: Vec<TxId>) -> Vec<TxId> {
+        let position: HashMap<TxId, usize> = tx_ids.iter().enumerate().map(|(index, tx_id)| (*tx_id, index)).collect();
+        let mut waiting: Vec<usize> = tx_ids.iter()
+            .map(|tx_id| self.parents(tx_id).filter(|parent| position.contains_key(parent)).count())
+            .collect();
+
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..tx_ids.len())
+            .filter(|index| waiting[*index] == 0)
+            .map(Reverse)
+            .collect();
+        let mut ordered = Vec::with_capacity(tx_ids.len());
+        while let Some(Reverse(index)) = ready.pop() {
+            let tx_id = tx_ids[index];
+            ordered.push(tx_id);
+            for child in self.children(&tx_id) {
+                if let Some(&child_index) = position.get(child) {
+                    waiting[child_index] -= 1;
+                    if waiting[child_index] == 0 {
+                        ready.push(Reverse(child_index));
+                    }
+                }
+            }
+        }
+        ordered
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::hash::sha256;
+
+    fn id(n: u8) -> TxId {
+        TxId::new(sha256(&[n]))
+    }
+
+    fn set(ids: &[TxId]) -> HashSet<TxId> {
+        ids.iter().copied().collect()
+    }
+
+    #[test]
+    fn test_topological_order_puts_parents_first() {
+        let mut dag = TxDag::new();
+        dag.insert(id(1), set(&[]), 10).unwrap();
+        dag.insert(id(2), set(&[id(1)]), 10).unwrap();
+        dag.insert(id(3), set(&[id(2)]), 10).unwrap();
+        dag.insert(id(4), set(&[]), 10).unwrap();
+
+        // highest priority first, but children can't jump ahead of parents
+        let ordered = dag.topological_order(vec![id(3), id(4), id(2), id(1)]);
+        assert_eq!(ordered, vec![id(4), id(1), id(2), id(3)]);
+        assert_eq!(dag.descendants(&id(1)), vec![id(2), id(3)]);
+    }
+
+    #[test]
+    fn test_depth_limit_and_cycles() {
+        let mut dag = TxDag::new();
+        dag.insert(id(1), set(&[]), 2).unwrap();
+        dag.insert(id(2), set(&[id(1)]), 2).unwrap();
+        dag.insert(id(3), set(&[id(2)]), 2).unwrap();
+        assert_eq!(dag.depth(&id(3)), 2);
+        assert!(dag.insert(id(4), set(&[id(3)]), 2).is_err());
+
+        // making 1 depend on 3 would close the loop 1 -> 2 -> 3 -> 1
+        assert!(dag.check_insert(&id(1), &set(&[id(3)]), 10).is_err());
+        assert!(dag.check_insert(&id(5), &set(&[id(5)]), 10).is_err());
+
+        dag.remove(&id(1));
+        assert_eq!(dag.depth(&id(3)), 1);
+    }
+}
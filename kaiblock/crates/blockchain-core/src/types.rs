@@ -32,6 +32,10 @@ pub type Difficulty = u64;
 ///Chain ID for network identification
 pub type ChainId = u32;
 
+///Fork ID, bumped when a chain splits so transactions signed on one side
+///of the split aren't valid on the other
+pub type ForkId = u32;
+
 
 ///Transaction Id
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -83,6 +87,49 @@ impl AsRef<Hash256> for TxId {
 	}
 }
 
+
+///Witness transaction ID: hash of the whole transaction, signatures and
+///unlocking data included. Two encodings of one spend share a `TxId` but
+///not a `WtxId`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WtxId(Hash256);
+
+
+impl WtxId {
+	pub fn new(hash: Hash256) -> Self {
+		Self(hash)
+	}
+
+
+	pub fn hash(&self) -> Hash256 {
+		self.0
+	}
+
+
+	pub fn from_hex(hex: &str) -> blockchain_crypto::Result<Self> {
+		Ok(Self(Hash256::from_hex(hex)?))
+	}
+
+
+	pub fn to_hex(&self) -> String {
+		self.0.to_hex()
+	}
+}
+
+
+impl fmt::Display for WtxId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
+
+
+impl From<Hash256> for WtxId {
+	fn from(hash: Hash256) -> Self {
+		Self(hash)
+	}
+}
+
 ///Block ID
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct BlockID(Hash256);
@@ -160,6 +207,12 @@ impl fmt::Display for Timestamp {
     }
 }
 
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(time: DateTime<Utc>) -> Self {
+        Self(time)
+    }
+}
+
 impl Default for Timestamp {
     fn default() -> Self {
         Self::now()
@@ -210,6 +263,18 @@ pub NetworkType {
 	Local,
 }
 
+impl NetworkType{
+	///human-readable prefix of this network's Bech32 addresses
+	pub fn bech32_hrp(&self) -> &'static str{
+		match self{
+			NetworkType::Mainnet => blockchain_crypto::address::MAINNET_HRP,
+			NetworkType::Testnet => blockchain_crypto::address::TESTNET_HRP,
+			NetworkType::Devnet => "dkai",
+			NetworkType::Local => "lkai",
+		}
+	}
+}
+
 
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -238,7 +303,7 @@ pub enum TransactionStatus{
 
 
 ///utxo reference for inputs
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct OutPoint {
 	///transaction hash containinf utxo
 	pub tx_id: TxId,
@@ -261,6 +326,10 @@ impl fmt::Display for OutPoint {
 
 
 
+/// Most public keys a multi-signature script may list
+pub const MAX_MULTISIG_KEYS: usize = 15;
+
+
 /// Script for transaction validation (simplified)
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Script {
@@ -277,6 +346,21 @@ pub enum Script {
     },
     /// Custom script bytecode
     Custom(Vec<u8>),
+    /// `script`, once the spending transaction's lock time reaches
+    /// `lock_time` (CHECKLOCKTIMEVERIFY)
+    CheckLockTime {
+        lock_time: u32,
+        script: Box<Script>,
+    },
+    /// `script`, once the spent output is `blocks` deep (CHECKSEQUENCEVERIFY)
+    CheckSequence {
+        blocks: u16,
+        script: Box<Script>,
+    },
+    /// Pay to a BIP-340 x-only key, spent with one Schnorr signature. The key
+    /// may be a MuSig2 aggregate of several keys (n-of-n), and the signature
+    /// may be completed from an adaptor signature (conditional payments)
+    PayToSchnorrKey(blockchain_crypto::bip340::XOnlyPublicKey),
 }
 
 
@@ -291,10 +375,70 @@ impl Script {
         Script::PayToPubkey(public_key)
     }
     
+    /// Lock `script` until an absolute height or time
+    pub fn check_lock_time(lock_time: u32, script: Script) -> Self {
+        Script::CheckLockTime { lock_time, script: Box::new(script) }
+    }
+
+    /// Lock `script` until the output is `blocks` deep
+    pub fn check_sequence(blocks: u16, script: Script) -> Self {
+        Script::CheckSequence { blocks, script: Box::new(script) }
+    }
+    
     /// Create a multi-sig script
     pub fn multi_sig(threshold: u8, public_keys: Vec<blockchain_crypto::PublicKey>) -> Self {
         Script::MultiSig { threshold, public_keys }
     }
+
+    /// Create an n-of-n script locked to the aggregate of `keys`, in that order
+    pub fn pay_to_aggregate_key(keys: &[blockchain_crypto::bip340::XOnlyPublicKey]) -> crate::Result<Self> {
+        blockchain_crypto::bip340::aggregate_public_keys(keys)
+            .map(Script::PayToSchnorrKey)
+            .map_err(|e| crate::BlockchainError::InvalidTransaction(format!("Invalid aggregate key: {}", e)))
+    }
+
+    /// Create a script spendable with a BIP-340 signature by `public_key`
+    pub fn pay_to_schnorr_key(public_key: blockchain_crypto::bip340::XOnlyPublicKey) -> Self {
+        Script::PayToSchnorrKey(public_key)
+    }
+
+    /// Whether the script can ever be satisfied: a multi-sig script needs a
+    /// threshold between 1 and its number of keys, and distinct keys
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Script::MultiSig { threshold, public_keys } => {
+                let distinct = public_keys.iter()
+                    .enumerate()
+                    .all(|(i, key)| !public_keys[..i].contains(key));
+                *threshold > 0
+                    && *threshold as usize <= public_keys.len()
+                    && public_keys.len() <= MAX_MULTISIG_KEYS
+                    && distinct
+            }
+            Script::CheckLockTime { lock_time, script } => *lock_time > 0 && script.is_valid(),
+            Script::CheckSequence { script, .. } => script.is_valid(),
+            _ => true,
+        }
+    }
+
+    /// Create a P2SH script paying to the hash of `redeem_script`
+    pub fn pay_to_script_hash(redeem_script: &Script) -> Self {
+        Script::PayToScriptHash(redeem_script.hash())
+    }
+
+    /// Serialized form of a redeem script, as revealed when spending a P2SH output
+    pub fn to_bytes(&self) -> Vec<u8> {
+        crate::codec::encode(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> crate::Result<Self> {
+        crate::codec::decode(bytes)
+    }
+
+    /// Hash committing to the whole script, which a P2SH output pays to
+    pub fn hash(&self) -> Hash256 {
+        blockchain_crypto::address::script_hash(&self.to_bytes())
+    }
 }
 
 #[cfg(test)]
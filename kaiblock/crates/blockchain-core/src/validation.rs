@@ -2,6 +2,7 @@
 // use crate::transaction::Transaction;
 // use crate::block::Block;
 // use crate::state::WorldState;
+use crate::timelock::check_time_locks;
 // use crate::{BlockchainError, Result};
 // use blockchain_crypto::Hash256;
 // use serde::{Deserialize, Serialize};
@@ -96,13 +97,18 @@
 
 ///////////////Claudie direct //////////////////////
 use crate::types::*;
-use crate::transaction::Transaction;
+use crate::transaction::{SigningDomain, Transaction};
 use crate::block::Block;
+use crate::difficulty;
+use crate::emission::EmissionSchedule;
 use crate::state::WorldState;
 use crate::{BlockchainError, Result};
-use blockchain_crypto::Hash256;
+use blockchain_crypto::{Hash256, HashAlgorithm};
+use crate::weight::{BlockWeight, ResourceUsage, WeightParams};
+use blockchain_crypto::signature::{SignatureCache, SigCacheMode};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 /// Validation configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -113,6 +119,8 @@ pub struct ValidationRules {
     pub max_transactions_per_block: usize,
     /// Maximum transaction size in bytes
     pub max_transaction_size: usize,
+    /// Maximum runtime compute units per block
+    pub max_block_compute_units: u64,
     /// Minimum transaction fee
     pub min_transaction_fee: Fee,
     /// Coinbase maturity period (blocks)
@@ -127,10 +135,22 @@ pub struct ValidationRules {
     pub max_difficulty_adjustment: f64,
     /// Enable signature verification
     pub verify_signatures: bool,
+    /// Also accept input signatures over the bare transaction hash, made
+    /// before signatures committed to the chain id. For the transition only:
+    /// such signatures can be replayed on any chain
+    #[serde(default)]
+    pub accept_legacy_signatures: bool,
     /// Enable merkle root verification
     pub verify_merkle_root: bool,
     /// Enable double spend checking
     pub check_double_spend: bool,
+    /// Weights of the combined block limit
+    #[serde(default)]
+    pub block_weight: WeightParams,
+    /// Hash a header's proof of work is measured on. Block ids stay SHA-256
+    /// whatever is picked, so they mean the same thing on every chain
+    #[serde(default)]
+    pub block_hash: HashAlgorithm,
 }
 
 impl Default for ValidationRules {
@@ -139,6 +159,7 @@ impl Default for ValidationRules {
             max_block_size: 2 * 1024 * 1024, // 2MB
             max_transactions_per_block: 10000,
             max_transaction_size: 1024 * 1024, // 1MB
+            max_block_compute_units: 48_000_000, // 48 max-budget runtime transactions
             min_transaction_fee: 1000, // 1000 satoshis
             coinbase_maturity: 100, // 100 blocks
             max_block_time_drift: 7200, // 2 hours
@@ -146,8 +167,11 @@ impl Default for ValidationRules {
             target_block_time: 600, // 10 minutes
             max_difficulty_adjustment: 4.0, // Maximum 4x adjustment
             verify_signatures: true,
+            accept_legacy_signatures: false,
             verify_merkle_root: true,
             check_double_spend: true,
+            block_weight: WeightParams::default(),
+            block_hash: HashAlgorithm::default(),
         }
     }
 }
@@ -167,6 +191,8 @@ pub struct TransactionValidationContext<'a> {
 pub struct BlockValidationContext<'a> {
     pub block: &'a Block,
     pub prev_block: Option<&'a Block>,
+    /// First block of the difficulty window, set when the block is on a retarget height
+    pub retarget_start: Option<&'a Block>,
     pub world_state: &'a WorldState,
     pub rules: &'a ValidationRules,
 }
@@ -175,18 +201,71 @@ pub struct BlockValidationContext<'a> {
 #[derive(Debug)]
 pub struct Validator {
     rules: ValidationRules,
+    /// Verified signatures, shared with mempool admission
+    signature_cache: Arc<SignatureCache>,
+    /// Blocks that skip signature checks: the assume-valid block and its
+    /// ancestors, once the chain has seen their headers
+    assumed_valid: HashSet<BlockId>,
+    /// Chain and fork input signatures must commit to
+    signing_domain: SigningDomain,
+    /// Initial block reward and how it shrinks; coinbase amounts are
+    /// unchecked without one
+    emission: Option<(Amount, EmissionSchedule)>,
 }
 
 impl Validator {
     /// Create new validator with rules
     pub fn new(rules: ValidationRules) -> Self {
-        Self { rules }
+        Self::with_signature_cache(rules, Arc::new(SignatureCache::default()))
+    }
+
+    /// Create a validator that shares `signature_cache` with other verifiers
+    pub fn with_signature_cache(rules: ValidationRules, signature_cache: Arc<SignatureCache>) -> Self {
+        Self { rules, signature_cache, assumed_valid: HashSet::new(), signing_domain: SigningDomain::default(), emission: None }
+    }
+
+    /// Skip signature checks of `blocks`, ancestors of a block assumed valid
+    /// (faster initial sync). Only block validation is affected; mempool
+    /// transactions are always fully checked.
+    pub fn assume_valid(&mut self, blocks: impl IntoIterator<Item = BlockId>) {
+        self.assumed_valid.extend(blocks);
+    }
+
+    /// Whether the block's signatures go unchecked
+    pub fn is_assumed_valid(&self, block_id: &BlockId) -> bool {
+        self.assumed_valid.contains(block_id)
+    }
+
+    /// Require input signatures to commit to `domain`, the chain being validated
+    pub fn with_signing_domain(mut self, domain: SigningDomain) -> Self {
+        self.signing_domain = domain;
+        self
+    }
+
+    /// Cap the coinbase of every block after genesis at the subsidy
+    /// `schedule` gives for its height, starting from `block_reward`
+    pub fn with_emission(mut self, block_reward: Amount, schedule: EmissionSchedule) -> Self {
+        self.emission = Some((block_reward, schedule));
+        self
+    }
+
+    pub fn signature_cache(&self) -> &Arc<SignatureCache> {
+        &self.signature_cache
     }
     
     /// Validate a single transaction
     pub fn validate_transaction(
         &self,
         ctx: TransactionValidationContext,
+    ) -> Result<()> {
+        self.validate_transaction_with(ctx, SigCacheMode::Store, true)
+    }
+
+    fn validate_transaction_with(
+        &self,
+        ctx: TransactionValidationContext,
+        cache_mode: SigCacheMode,
+        verify_signatures: bool,
     ) -> Result<()> {
         let tx = ctx.transaction;
         
@@ -202,8 +281,8 @@ impl Validator {
         self.validate_transaction_amounts(ctx)?;
         
         // Validate signatures if enabled
-        if self.rules.verify_signatures {
-            self.validate_transaction_signatures(ctx)?;
+        if self.rules.verify_signatures && verify_signatures {
+            self.validate_transaction_signatures(ctx, cache_mode)?;
         }
         
         // Validate account-based transaction
@@ -238,7 +317,303 @@ impl Validator {
         // Validate block header
         self.validate_block_header(ctx)?;
         
-        // Validate block size
+        // Validate block size and compute limits
+        self.validate_block_size(ctx)?;
+        
+        // Validate timestamp
+        self.validate_block_timestamp(ctx)?;
+        
+        // Validate difficulty
+        self.validate_block_difficulty(ctx)?;
+        
+        // Validate merkle root if enabled
+        if self.rules.verify_merkle_root {
+            self.validate_merkle_root(ctx)?;
+        }
+        
+        // Validate all transactions in block
+        self.validate_block_transactions(ctx)?;
+        
+        Ok(())
+    }
+    
+    /// Everything `validate_block` checks except the transactions, which need
+    /// the state of the parent: enough to store a side-branch block until
+    /// its branch has the work to be applied
+    pub fn validate_block_against_parent(
+        &self,
+        ctx: BlockValidationContext,
+    ) -> Result<()> {
+        self.validate_block_structure(ctx)?;
+        self.validate_block_header(ctx)?;
+        self.validate_block_size(ctx)?;
+        self.validate_block_timestamp(ctx)?;
+        self.validate_block_difficulty(ctx)?;
+        if self.rules.verify_merkle_root {
+            self.validate_merkle_root(ctx)?;
+        }
+        Ok(())
+    }
+    
+    /// Validate transaction structure
+    fn validate_transaction_structure(&self, tx: &Transaction) -> Result<()> {
+        // Check version
+        if tx.version == 0 {
+            return Err(BlockchainError::InvalidTransaction(
+                "Invalid transaction version".to_string()
+            ));
+        }
+        
+        // Check size
+        if tx.size() > self.rules.max_transaction_size {
+            return Err(BlockchainError::InvalidTransaction(
+                format!("Transaction too large: {} > {}", tx.size(), self.rules.max_transaction_size)
+            ));
+        }
+        
+        // Check that transaction has either inputs/outputs or from/to
+        if tx.inputs.is_empty() && tx.outputs.is_empty() && 
+           tx.from.is_none() && tx.to.is_none() && !tx.is_coinbase() {
+            return Err(BlockchainError::InvalidTransaction(
+                "Transaction has no inputs, outputs, or addresses".to_string()
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate coinbase transaction
+    fn validate_coinbase_transaction(
+        &self,
+        ctx: TransactionValidationContext,
+    ) -> Result<()> {
+        let tx = ctx.transaction;
+        
+        // Coinbase must have no inputs
+        if !tx.inputs.is_empty() {
+            return Err(BlockchainError::InvalidTransaction(
+                "Coinbase transaction cannot have inputs".to_string()
+            ));
+        }
+        
+        // Coinbase must have at least one output
+        if tx.outputs.is_empty() && tx.to.is_none() {
+            return Err(BlockchainError::InvalidTransaction(
+                "Coinbase transaction must have outputs".to_string()
+            ));
+        }
+        
+        // Validate coinbase reward (this would need more context in real implementation)
+        // For now, just check that amounts are reasonable
+        let total_output = tx.total_output_amount()?;
+        if total_output == 0 {
+            return Err(BlockchainError::InvalidTransaction(
+                "Coinbase transaction must have non-zero output".to_string()
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate transaction amounts
+    fn validate_transaction_amounts(
+        &self,
+        ctx: TransactionValidationContext,
+    ) -> Result<()> {
+        let tx = ctx.transaction;
+        
+        // Check for overflow in outputs
+        let _total_output = tx.total_output_amount()?;
+        
+        // For UTXO transactions, validate input/output balance
+        if !tx.inputs.is_empty() {
+            let total_input = tx.total_input_amount(&ctx.world_state.utxo_set().utxos)?;
+            let total_output = tx.total_output_amount()?;
+            
+            if total_input < total_output + tx.fee {
+                return Err(BlockchainError::InvalidTransaction(
+                    format!("Insufficient input amount: {} < {} + {}", 
+                           total_input, total_output, tx.fee)
+                ));
+            }
+        }
+        
+        // Check for zero or negative amounts
+        for output in &tx.outputs {
+            if output.amount == 0 {
+                return Err(BlockchainError::InvalidTransaction(
+                    "Transaction output cannot be zero".to_string()
+                ));
+            }
+        }
+        
+        if let Some(amount) = tx.amount {
+            if amount == 0 {
+                return Err(BlockchainError::InvalidTransaction(
+                    "Transaction amount cannot be zero".to_string()
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate transaction signatures
+    fn validate_transaction_signatures(
+        &self,
+        ctx: TransactionValidationContext,
+        cache_mode: SigCacheMode,
+    ) -> Result<()> {
+        let tx = ctx.transaction;
+        
+        // Validate UTXO input signatures
+        let accept_legacy = self.rules.accept_legacy_signatures;
+        if !tx.verify_signatures_cached(ctx.world_state.utxo_set(), &self.signature_cache, cache_mode, self.signing_domain, accept_legacy)? {
+            return Err(BlockchainError::InvalidTransaction(
+                "Invalid transaction signature".to_string()
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate account-based transaction
+    fn validate_account_transaction(
+        &self,
+        ctx: TransactionValidationContext,
+    ) -> Result<()> {
+        let tx = ctx.transaction;
+        let from = tx.from.ok_or_else(|| {
+            BlockchainError::InvalidTransaction("Missing sender address".to_string())
+        })?;
+        
+        let account = ctx.world_state.get_account(&from);
+        
+        // Validate nonce
+        if let Some(tx_nonce) = tx.nonce {
+            if tx_nonce != account.nonce {
+                return Err(BlockchainError::InvalidTransaction(
+                    format!("Invalid nonce: expected {}, got {}", account.nonce, tx_nonce)
+                ));
+            }
+        }
+        
+        // Validate balance
+        let total_cost = tx.total_cost();
+        if account.balance < total_cost {
+            return Err(BlockchainError::InsufficientBalance {
+                required: total_cost,
+                available: account.balance,
+            });
+        }
+        
+        // Validate gas limits
+        if let Some(gas_limit) = tx.gas_limit {
+            if gas_limit == 0 {
+                return Err(BlockchainError::InvalidTransaction(
+                    "Gas limit cannot be zero".to_string()
+                ));
+            }
+            
+            // Could add maximum gas limit check here
+            if gas_limit > 10_000_000 { // Example limit
+                return Err(BlockchainError::InvalidTransaction(
+                    "Gas limit too high".to_string()
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate UTXO-based transaction
+    fn validate_utxo_transaction(
+        &self,
+        ctx: TransactionValidationContext,
+    ) -> Result<()> {
+        let tx = ctx.transaction;
+        let utxo_set = ctx.world_state.utxo_set();
+        
+        // Validate that all inputs exist and are unspent
+        for input in &tx.inputs {
+            let utxo = utxo_set.get_utxo(&input.prev_output)
+                .ok_or_else(|| BlockchainError::InvalidTransaction(
+                    format!("UTXO not found: {}", input.prev_output)
+                ))?;
+            
+            // Check coinbase maturity
+            if utxo.is_coinbase && 
+               ctx.block_height - utxo.block_height < self.rules.coinbase_maturity {
+                return Err(BlockchainError::InvalidTransaction(
+                    format!("Coinbase UTXO not mature: {} < {}", 
+                           ctx.block_height - utxo.block_height, 
+                           self.rules.coinbase_maturity)
+                ));
+            }
+        }
+        
+        // Check for double spending within transaction
+        let mut used_outpoints = std::collections::HashSet::new();
+        for input in &tx.inputs {
+            if !used_outpoints.insert(input.prev_output) {
+                return Err(BlockchainError::DoubleSpending(
+                    format!("Double spend within transaction: {}", input.prev_output)
+                ));
+            }
+        }
+        
+        // Outputs nobody could ever spend are rejected
+        for output in &tx.outputs {
+            if !output.script_pubkey.is_valid() {
+                return Err(BlockchainError::InvalidTransaction(
+                    format!("Unspendable output script: {:?}", output.script_pubkey)
+                ));
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate transaction fees
+    fn validate_transaction_fees(
+        &self,
+        ctx: TransactionValidationContext,
+    ) -> Result<()> {
+        let tx = ctx.transaction;
+        let fee = tx.calculate_gas_fee();
+        
+        if fee < self.rules.min_transaction_fee {
+            return Err(BlockchainError::InvalidTransaction(
+                format!("Transaction fee too low: {} < {}", fee, self.rules.min_transaction_fee)
+            ));
+        }
+        
+        Ok(())
+    }
+    
+    /// Validate time locks
+    fn validate_time_locks(
+        &self,
+        ctx: TransactionValidationContext,
+    ) -> Result<()> {
+        // Absolute lock time, and relative locks against the age of each spent output
+        check_time_locks(ctx.transaction, ctx.world_state.utxo_set(), ctx.block_height, ctx.block_timestamp)
+    }
+    
+    /// Validate block structure and transactions
+    pub fn validate_block(
+        &self,
+        ctx: BlockValidationContext,
+    ) -> Result<()> {
+        let block = ctx.block;
+        
+        // Basic structure validation
+        self.validate_block_structure(ctx)?;
+        
+        // Validate block header
+        self.validate_block_header(ctx)?;
+        
+        // Validate block size and compute limits
         self.validate_block_size(ctx)?;
         
         // Validate timestamp
@@ -365,11 +740,13 @@ impl Validator {
     fn validate_transaction_signatures(
         &self,
         ctx: TransactionValidationContext,
+        cache_mode: SigCacheMode,
     ) -> Result<()> {
         let tx = ctx.transaction;
         
         // Validate UTXO input signatures
-        if !tx.verify_signatures(&ctx.world_state.utxo_set().utxos)? {
+        let accept_legacy = self.rules.accept_legacy_signatures;
+        if !tx.verify_signatures_cached(ctx.world_state.utxo_set(), &self.signature_cache, cache_mode, self.signing_domain, accept_legacy)? {
             return Err(BlockchainError::InvalidTransaction(
                 "Invalid transaction signature".to_string()
             ));
@@ -400,7 +777,7 @@ impl Validator {
         }
         
         // Validate balance
-        let total_cost = tx.amount.unwrap_or(0) + tx.calculate_gas_fee();
+        let total_cost = tx.total_cost();
         if account.balance < total_cost {
             return Err(BlockchainError::InsufficientBalance {
                 required: total_cost,
@@ -463,6 +840,15 @@ impl Validator {
             }
         }
         
+        // Outputs nobody could ever spend are rejected
+        for output in &tx.outputs {
+            if !output.script_pubkey.is_valid() {
+                return Err(BlockchainError::InvalidTransaction(
+                    format!("Unspendable output script: {:?}", output.script_pubkey)
+                ));
+            }
+        }
+        
         Ok(())
     }
     
@@ -553,6 +939,17 @@ impl Validator {
                 ));
             }
         }
+
+        // Validate that the coinbase mints no more than the subsidy
+        if let Some((block_reward, schedule)) = self.emission.filter(|_| block.height() > 0) {
+            let subsidy = schedule.subsidy(block_reward, block.height());
+            let minted = block.transactions()[0].total_output_amount()?;
+            if minted > subsidy {
+                return Err(BlockchainError::InvalidBlock(
+                    format!("Coinbase mints {}, over the {} subsidy at height {}", minted, subsidy, block.height())
+                ));
+            }
+        }
         
         // Validate transaction count
         if block.transaction_count() > self.rules.max_transactions_per_block {
@@ -616,17 +1013,9 @@ impl Validator {
         Ok(())
     }
     
-    /// Validate block size
+    /// Validate block size, signature op, compute and combined weight limits
     fn validate_block_size(&self, ctx: BlockValidationContext) -> Result<()> {
-        let block_size = ctx.block.size();
-        
-        if block_size > self.rules.max_block_size {
-            return Err(BlockchainError::InvalidBlock(
-                format!("Block too large: {} > {}", block_size, self.rules.max_block_size)
-            ));
-        }
-        
-        Ok(())
+        BlockWeight::new(&self.rules).check(&ResourceUsage::of_block(ctx.block))
     }
     
     /// Validate block timestamp
@@ -660,25 +1049,21 @@ impl Validator {
         let header = &ctx.block.header;
         
         // Check that block meets difficulty target
-        if !header.meets_difficulty() {
+        if !header.meets_difficulty_with(self.rules.block_hash) {
             return Err(BlockchainError::InvalidBlock(
                 format!("Block does not meet difficulty target: {} < {}", 
-                       header.hash_difficulty(), header.difficulty)
+                       header.hash_difficulty_with(self.rules.block_hash), header.difficulty)
             ));
         }
         
-        // Validate difficulty adjustment (simplified)
+        // Validate difficulty retarget
         if let Some(prev_block) = ctx.prev_block {
-            let expected_difficulty = self.calculate_next_difficulty(ctx.prev_block, ctx.block.height())?;
-            
-            // Allow some tolerance for difficulty adjustments
-            let min_difficulty = (expected_difficulty as f64 / self.rules.max_difficulty_adjustment) as u32;
-            let max_difficulty = (expected_difficulty as f64 * self.rules.max_difficulty_adjustment) as u32;
-            
-            if header.difficulty < min_difficulty || header.difficulty > max_difficulty {
+            let expected_difficulty = difficulty::next_difficulty(prev_block, ctx.retarget_start, self.rules())?;
+
+            if header.difficulty != expected_difficulty {
                 return Err(BlockchainError::InvalidBlock(
-                    format!("Invalid difficulty adjustment: {} not in range [{}, {}]", 
-                           header.difficulty, min_difficulty, max_difficulty)
+                    format!("Invalid difficulty: expected {}, got {}",
+                           expected_difficulty, header.difficulty)
                 ));
             }
         }
@@ -686,25 +1071,6 @@ impl Validator {
         Ok(())
     }
     
-    /// Calculate next difficulty (simplified implementation)
-    fn calculate_next_difficulty(
-        &self, 
-        _prev_block: Option<&Block>, 
-        current_height: BlockHeight
-    ) -> Result<Difficulty> {
-        // Simplified difficulty calculation
-        // In a real implementation, this would look at block times over the adjustment period
-        
-        if current_height % self.rules.difficulty_adjustment_period == 0 {
-            // Difficulty adjustment block
-            // For now, just return a constant difficulty
-            Ok(1)
-        } else {
-            // No adjustment needed
-            Ok(1)
-        }
-    }
-    
     /// Validate merkle root
     fn validate_merkle_root(&self, ctx: BlockValidationContext) -> Result<()> {
         let calculated_root = ctx.block.body.calculate_merkle_root()?;
@@ -723,21 +1089,29 @@ impl Validator {
         let block_height = ctx.block.height();
         let block_timestamp = ctx.block.timestamp();
         
+        let verify_signatures = !self.is_assumed_valid(&ctx.block.id());
+        
         // Track double spending within the block
         let mut used_outpoints = std::collections::HashSet::new();
+
+        // Each transaction sees the state left by the ones before it, so a
+        // block may spend outputs (and use nonces) created earlier in it
+        let mut state = ctx.world_state.clone();
+        state.set_executing_block(ctx.block.id());
         
         for (i, tx) in ctx.block.transactions().iter().enumerate() {
             // Create transaction validation context
             let tx_ctx = TransactionValidationContext {
                 transaction: tx,
-                world_state: ctx.world_state,
+                world_state: &state,
                 block_height,
                 block_timestamp,
                 rules: ctx.rules,
             };
             
-            // Validate individual transaction
-            self.validate_transaction(tx_ctx)?;
+            // Validate individual transaction; signatures seen in the mempool
+            // are served from the cache and dropped from it
+            self.validate_transaction_with(tx_ctx, SigCacheMode::Consume, verify_signatures)?;
             
             // Check for double spending within block
             if self.rules.check_double_spend {
@@ -750,6 +1124,10 @@ impl Validator {
                     }
                 }
             }
+
+            state.apply_transaction(tx).map_err(|e| BlockchainError::InvalidBlock(
+                format!("Transaction {} can't be applied: {}", i, e)
+            ))?;
         }
         
         Ok(())
@@ -821,6 +1199,7 @@ pub fn validate_chain_consistency(
         validator.validate_block(ctx)?;
         
         // Apply block to state for next validation
+        current_state.set_executing_block(block.id());
         for tx in block.transactions() {
             current_state.apply_transaction(tx)?;
         }
@@ -0,0 +1,165 @@
+use crate::block::Block;
+use crate::transaction::Transaction;
+use crate::validation::ValidationRules;
+use crate::{BlockchainError, Result};
+use serde::{Deserialize, Serialize};
+
+
+/// Block capacity a transaction or block consumes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub bytes: u64,
+    /// signature checks needed to validate it
+    pub sig_ops: u64,
+    pub compute_units: u64,
+}
+
+impl ResourceUsage {
+    pub fn of_transaction(tx: &Transaction) -> Self {
+        Self {
+            bytes: tx.size() as u64,
+            sig_ops: tx.sig_ops(),
+            compute_units: tx.compute_units(),
+        }
+    }
+
+    pub fn of_block(block: &Block) -> Self {
+        Self {
+            bytes: block.size() as u64,
+            sig_ops: block.sig_ops(),
+            compute_units: block.compute_units(),
+        }
+    }
+
+    pub fn add(&self, other: &Self) -> Self {
+        Self {
+            bytes: self.bytes.saturating_add(other.bytes),
+            sig_ops: self.sig_ops.saturating_add(other.sig_ops),
+            compute_units: self.compute_units.saturating_add(other.compute_units),
+        }
+    }
+}
+
+
+/// Per-chain weights of the combined block limit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeightParams {
+    /// weight of one byte
+    pub byte_weight: u64,
+    /// weight of one signature check
+    pub sig_op_weight: u64,
+    /// compute units that add one unit of weight
+    pub compute_units_per_weight: u64,
+    /// combined weight a block may not exceed
+    pub max_block_weight: u64,
+    /// signature checks a block may not exceed
+    pub max_block_sig_ops: u64,
+}
+
+impl Default for WeightParams {
+    fn default() -> Self {
+        Self {
+            byte_weight: 1,
+            sig_op_weight: 50,
+            compute_units_per_weight: 24, // a full compute budget weighs as much as a full 2MB block
+            max_block_weight: 4_000_000,
+            max_block_sig_ops: 80_000,
+        }
+    }
+}
+
+
+/// Block capacity: a cap on each resource plus a cap on their weighted sum.
+///
+/// The per-resource caps stop any one resource from crowding out the rest;
+/// the combined weight lets a chain trade them off, e.g. favouring cheap
+/// plain transfers (bytes) over runtime calls (compute) or the reverse.
+/// Block assembly and block validation both enforce it.
+#[derive(Debug, Clone)]
+pub struct BlockWeight {
+    pub params: WeightParams,
+    pub max_bytes: u64,
+    pub max_compute_units: u64,
+}
+
+impl BlockWeight {
+    pub fn new(rules: &ValidationRules) -> Self {
+        Self {
+            params: rules.block_weight.clone(),
+            max_bytes: rules.max_block_size as u64,
+            max_compute_units: rules.max_block_compute_units,
+        }
+    }
+
+    /// Combined weight of `usage`
+    pub fn weight(&self, usage: &ResourceUsage) -> u64 {
+        usage.bytes.saturating_mul(self.params.byte_weight)
+            .saturating_add(usage.sig_ops.saturating_mul(self.params.sig_op_weight))
+            .saturating_add(usage.compute_units / self.params.compute_units_per_weight.max(1))
+    }
+
+    pub fn fits(&self, usage: &ResourceUsage) -> bool {
+        self.check(usage).is_ok()
+    }
+
+    /// Ok if `usage` is within every limit, otherwise names the one exceeded
+    pub fn check(&self, usage: &ResourceUsage) -> Result<()> {
+        let exceeded = |what: &str, value: u64, limit: u64| {
+            Err(BlockchainError::InvalidBlock(format!("Block exceeds {} limit: {} > {}", what, value, limit)))
+        };
+
+        if usage.bytes > self.max_bytes {
+            return exceeded("size", usage.bytes, self.max_bytes);
+        }
+        if usage.compute_units > self.max_compute_units {
+            return exceeded("compute", usage.compute_units, self.max_compute_units);
+        }
+        if usage.sig_ops > self.params.max_block_sig_ops {
+            return exceeded("signature op", usage.sig_ops, self.params.max_block_sig_ops);
+        }
+        let weight = self.weight(usage);
+        if weight > self.params.max_block_weight {
+            return exceeded("weight", weight, self.params.max_block_weight);
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> BlockWeight {
+        BlockWeight {
+            params: WeightParams {
+                byte_weight: 1,
+                sig_op_weight: 10,
+                compute_units_per_weight: 100,
+                max_block_weight: 1000,
+                max_block_sig_ops: 50,
+            },
+            max_bytes: 800,
+            max_compute_units: 80_000,
+        }
+    }
+
+    #[test]
+    fn test_weight_combines_resources() {
+        let usage = ResourceUsage { bytes: 200, sig_ops: 5, compute_units: 30_000 };
+        assert_eq!(limits().weight(&usage), 200 + 50 + 300);
+        assert!(limits().fits(&usage));
+    }
+
+    #[test]
+    fn test_each_limit_is_enforced() {
+        let limits = limits();
+        assert!(!limits.fits(&ResourceUsage { bytes: 801, ..Default::default() }));
+        assert!(!limits.fits(&ResourceUsage { sig_ops: 51, ..Default::default() }));
+        assert!(!limits.fits(&ResourceUsage { compute_units: 80_001, ..Default::default() }));
+
+        // every resource within its own cap, but together too heavy
+        let usage = ResourceUsage { bytes: 600, sig_ops: 20, compute_units: 30_000 };
+        assert!(limits.check(&usage).unwrap_err().to_string().contains("weight"));
+    }
+}
@@ -0,0 +1,198 @@
+use k256::elliptic_curve::PrimeField;
+use k256::{FieldBytes, NonZeroScalar, ProjectivePoint, Scalar};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use crate::bip340::{challenge, tagged_scalar, x_and_parity, Bip340Keypair, Bip340Signature, XOnlyPublicKey};
+use crate::{CryptoError, Result};
+
+
+fn scalar_from_bytes(bytes: [u8; 32]) -> Result<Scalar> {
+	Option::from(Scalar::from_repr(FieldBytes::from(bytes)))
+		.ok_or(CryptoError::InvalidSignature)
+}
+
+/// t, negated if need be so that t*G has an even y
+fn even_y_scalar(t: Scalar) -> Scalar {
+	if x_and_parity(&(ProjectivePoint::GENERATOR * t)).1 { -t } else { t }
+}
+
+
+///Secret t whose point T = t*G an adaptor signature is locked to. Like a
+///BIP-340 secret key it is negated if need be so that T has an even y
+#[derive(Clone, PartialEq, Eq)]
+pub struct AdaptorSecret([u8; 32]);
+
+impl AdaptorSecret {
+	///generate a random secret
+	pub fn generate() -> Self {
+		Self::from_scalar(*NonZeroScalar::random(&mut OsRng))
+	}
+
+	///a secret from 32 big-endian bytes (non-zero, below the group order)
+	pub fn from_bytes(bytes: [u8; 32]) -> Result<Self> {
+		let invalid = || CryptoError::InvalidKey("Invalid adaptor secret".to_string());
+		let t = scalar_from_bytes(bytes).map_err(|_| invalid())?;
+		if t == Scalar::ZERO {
+			return Err(invalid());
+		}
+		Ok(Self::from_scalar(t))
+	}
+
+	fn from_scalar(t: Scalar) -> Self {
+		Self(even_y_scalar(t).to_bytes().into())
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.0
+	}
+
+	///the public adaptor point T = t*G
+	pub fn adaptor_point(&self) -> AdaptorPoint {
+		let point = ProjectivePoint::GENERATOR * self.scalar();
+		AdaptorPoint(XOnlyPublicKey::from_point(&point).expect("adaptor secrets are non-zero"))
+	}
+
+	fn scalar(&self) -> Scalar {
+		scalar_from_bytes(self.0).expect("adaptor secrets are validated when built")
+	}
+}
+
+impl std::fmt::Debug for AdaptorSecret {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str("AdaptorSecret(..)")
+	}
+}
+
+
+///Public adaptor point T, x-only like a BIP-340 key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdaptorPoint(XOnlyPublicKey);
+
+impl AdaptorPoint {
+	pub fn from_bytes(bytes: [u8; 32]) -> Result<Self> {
+		XOnlyPublicKey::from_bytes(bytes).map(Self)
+	}
+
+	pub fn as_bytes(&self) -> &[u8; 32] {
+		self.0.as_bytes()
+	}
+
+	fn point(&self) -> ProjectivePoint {
+		self.0.point()
+	}
+}
+
+
+///Pre-signature (R', s') that becomes a BIP-340 signature (R.x, s) once the
+///adaptor secret is added: s = s' + t and R = R' + T. Both R' and R have an
+///even y, so R' travels x-only like R does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AdaptorSignature {
+	pub r: [u8; 32],
+	pub s: [u8; 32],
+	pub adaptor_point: AdaptorPoint,
+}
+
+impl AdaptorSignature {
+	fn nonce_point(&self) -> Result<ProjectivePoint> {
+		Ok(XOnlyPublicKey::from_bytes(self.r)
+			.map_err(|_| CryptoError::InvalidSignature)?
+			.point())
+	}
+
+	///x coordinate of the completed signature's nonce point R' + T, which
+	///must have an even y for the completed signature to verify
+	fn completed_nonce_x(&self) -> Result<[u8; 32]> {
+		let (x, odd) = x_and_parity(&(self.nonce_point()? + self.adaptor_point.point()));
+		if odd {
+			return Err(CryptoError::InvalidSignature);
+		}
+		Ok(x)
+	}
+}
+
+
+///create an adaptor signature on a message, locked to an adaptor point.
+///nonces are drawn until R' and R' + T both have an even y
+pub fn create_adaptor_signature(keypair: &Bip340Keypair, message: &[u8], adaptor_point: &AdaptorPoint) -> Result<AdaptorSignature> {
+	let secret = keypair.secret();
+	let big_t = adaptor_point.point();
+
+	loop {
+		let mut rand = [0u8; 32];
+		OsRng.fill_bytes(&mut rand);
+		let r = tagged_scalar(
+			b"KaiBlock/adaptor/nonce",
+			&[&rand, &secret.to_bytes(), adaptor_point.as_bytes(), message],
+		);
+		if r == Scalar::ZERO {
+			continue;
+		}
+
+		let r = even_y_scalar(r);
+		let big_r = ProjectivePoint::GENERATOR * r;
+		let completed = big_r + big_t;
+		if completed == ProjectivePoint::IDENTITY {
+			continue;
+		}
+		let (completed_x, odd) = x_and_parity(&completed);
+		if odd {
+			continue;
+		}
+
+		let e = challenge(&completed_x, keypair.public_key(), message);
+		return Ok(AdaptorSignature {
+			r: x_and_parity(&big_r).0,
+			s: (r + e * secret).to_bytes().into(),
+			adaptor_point: *adaptor_point,
+		});
+	}
+}
+
+
+///check s'*G == R' + e*P where e is the BIP-340 challenge of R' + T.
+///a valid adaptor signature guarantees that learning t yields a valid signature
+pub fn verify_adaptor_signature(public_key: &XOnlyPublicKey, message: &[u8], adaptor_signature: &AdaptorSignature) -> Result<()> {
+	let s = scalar_from_bytes(adaptor_signature.s)?;
+	let e = challenge(&adaptor_signature.completed_nonce_x()?, public_key, message);
+
+	if ProjectivePoint::GENERATOR * s == adaptor_signature.nonce_point()? + public_key.point() * e {
+		Ok(())
+	} else {
+		Err(CryptoError::InvalidSignature)
+	}
+}
+
+
+///complete an adaptor signature with the secret, producing a BIP-340 signature
+pub fn complete_adaptor_signature(adaptor_signature: &AdaptorSignature, secret: &AdaptorSecret) -> Result<Bip340Signature> {
+	if secret.adaptor_point() != adaptor_signature.adaptor_point {
+		return Err(CryptoError::InvalidKey("Secret does not match adaptor point".to_string()));
+	}
+
+	let s = scalar_from_bytes(adaptor_signature.s)?;
+	Ok(Bip340Signature {
+		r: adaptor_signature.completed_nonce_x()?,
+		s: (s + secret.scalar()).to_bytes().into(),
+	})
+}
+
+
+///recover the adaptor secret t = s - s' from a completed signature
+pub fn extract_adaptor_secret(adaptor_signature: &AdaptorSignature, signature: &Bip340Signature) -> Result<AdaptorSecret> {
+	if signature.r != adaptor_signature.completed_nonce_x()? {
+		return Err(CryptoError::InvalidSignature);
+	}
+
+	let s = scalar_from_bytes(signature.s)?;
+	let s_adaptor = scalar_from_bytes(adaptor_signature.s)?;
+	let secret = AdaptorSecret::from_bytes((s - s_adaptor).to_bytes().into())
+		.map_err(|_| CryptoError::InvalidSignature)?;
+
+	if secret.adaptor_point() != adaptor_signature.adaptor_point {
+		return Err(CryptoError::InvalidSignature);
+	}
+
+	Ok(secret)
+}
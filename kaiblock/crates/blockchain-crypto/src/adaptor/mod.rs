@@ -0,0 +1,62 @@
+mod adaptor;
+
+pub use adaptor::{
+	AdaptorPoint, AdaptorSecret, AdaptorSignature,
+	create_adaptor_signature, verify_adaptor_signature, complete_adaptor_signature, extract_adaptor_secret,
+};
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bip340::{Bip340Keypair, Bip340Signature};
+
+    #[test]
+    fn test_adaptor_signature_flow() {
+        let keypair = Bip340Keypair::generate();
+        let secret = AdaptorSecret::generate();
+        let message = b"pay on secret reveal";
+
+        let adaptor_sig = create_adaptor_signature(&keypair, message, &secret.adaptor_point()).unwrap();
+        assert!(verify_adaptor_signature(keypair.public_key(), message, &adaptor_sig).is_ok());
+
+        // The pre-signature alone is not a valid signature
+        let incomplete = Bip340Signature { r: adaptor_sig.r, s: adaptor_sig.s };
+        assert!(!keypair.public_key().verify(message, &incomplete));
+
+        // The completed signature is a plain BIP-340 signature
+        let signature = complete_adaptor_signature(&adaptor_sig, &secret).unwrap();
+        assert!(keypair.public_key().verify(message, &signature));
+
+        // Publishing the signature reveals the secret
+        let extracted = extract_adaptor_secret(&adaptor_sig, &signature).unwrap();
+        assert_eq!(extracted, secret);
+    }
+
+    #[test]
+    fn test_adaptor_signature_rejects_wrong_inputs() {
+        let keypair = Bip340Keypair::generate();
+        let secret = AdaptorSecret::generate();
+        let message = b"pay on secret reveal";
+        let adaptor_sig = create_adaptor_signature(&keypair, message, &secret.adaptor_point()).unwrap();
+
+        assert!(verify_adaptor_signature(keypair.public_key(), b"tampered", &adaptor_sig).is_err());
+        assert!(verify_adaptor_signature(Bip340Keypair::generate().public_key(), message, &adaptor_sig).is_err());
+        assert!(complete_adaptor_signature(&adaptor_sig, &AdaptorSecret::generate()).is_err());
+
+        // A signature that didn't come from this adaptor signature reveals nothing
+        let unrelated = keypair.sign(message);
+        assert!(extract_adaptor_secret(&adaptor_sig, &unrelated).is_err());
+    }
+
+    #[test]
+    fn test_adaptor_secret_is_normalized_to_an_even_point() {
+        let secret = AdaptorSecret::generate();
+        let point = secret.adaptor_point();
+        assert_eq!(AdaptorPoint::from_bytes(*point.as_bytes()).unwrap(), point);
+        assert_eq!(AdaptorSecret::from_bytes(secret.to_bytes()).unwrap(), secret);
+        assert!(AdaptorSecret::from_bytes([0u8; 32]).is_err());
+        assert!(AdaptorSecret::from_bytes([0xff; 32]).is_err());
+    }
+}
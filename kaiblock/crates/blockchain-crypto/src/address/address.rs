@@ -1,4 +1,5 @@
-use super::AddressType;
+use super::{AddressType, MAINNET_HRP};
+use bech32::{FromBase32, ToBase32, Variant, u5};
 use crate::signature::PublicKey;
 use crate::hash::{sha256, Hash256};
 use crate::{CryptoError, Result};
@@ -23,6 +24,7 @@ impl Address {
             AddressType::Base58 => Self::create_base58_address(hash),
             AddressType::HexChecksum => Self::create_hex_checksum_address(hash),
             AddressType::Hex => Self::create_hex_address(hash),
+            AddressType::Bech32 => Self::create_bech32_address(hash, MAINNET_HRP),
         }
     }
     
@@ -32,8 +34,22 @@ impl Address {
             AddressType::Base58 => Self::create_base58_address(hash),
             AddressType::HexChecksum => Self::create_hex_checksum_address(hash),
             AddressType::Hex => Self::create_hex_address(hash),
+            AddressType::Bech32 => Self::create_bech32_address(hash, MAINNET_HRP),
         }
     }
+
+    /// Create a pay-to-script-hash address from a serialized redeem script
+    pub fn from_script(redeem_script: &[u8], address_type: AddressType) -> Self {
+        Self::from_hash(super::script_hash(redeem_script), address_type)
+    }
+
+    /// Create a Bech32 address with the human-readable prefix of a network
+    pub fn bech32_from_hash(hash: Hash256, hrp: &str) -> Result<Self> {
+        if hrp.is_empty() || !hrp.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit()) {
+            return Err(CryptoError::AddressError(format!("Invalid Bech32 prefix: {:?}", hrp)));
+        }
+        Ok(Self::create_bech32_address(hash, hrp))
+    }
     
     /// Parse address from string
     pub fn from_string(address_str: &str) -> Result<Self> {
@@ -44,6 +60,7 @@ impl Address {
             AddressType::Base58 => Self::parse_base58_address(address_str),
             AddressType::HexChecksum => Self::parse_hex_checksum_address(address_str),
             AddressType::Hex => Self::parse_hex_address(address_str),
+            AddressType::Bech32 => Self::parse_bech32_address(address_str),
         }
     }
     
@@ -62,6 +79,14 @@ impl Address {
         &self.encoded
     }
     
+    /// Human-readable prefix of a Bech32 address
+    pub fn hrp(&self) -> Option<&str> {
+        match self.address_type {
+            AddressType::Bech32 => self.encoded.rsplit_once('1').map(|(hrp, _)| hrp),
+            _ => None,
+        }
+    }
+    
     /// Validate an address string
     pub fn validate(address_str: &str) -> Result<AddressType> {
         let address = Self::from_string(address_str)?;
@@ -130,6 +155,49 @@ impl Address {
         }
     }
     
+    /// Create Bech32 address: witness version 0 and the first 20 bytes of the hash
+    fn create_bech32_address(hash: Hash256, hrp: &str) -> Self {
+        let data = &hash.as_bytes()[..20];
+        let mut words = vec![u5::try_from_u8(0).expect("0 is a valid 5-bit value")];
+        words.extend(data.to_base32());
+        let encoded = bech32::encode(hrp, words, Variant::Bech32)
+            .expect("prefix is checked and the payload is 20 bytes");
+        
+        Self {
+            address_type: AddressType::Bech32,
+            data: data.to_vec(),
+            encoded,
+        }
+    }
+    
+    /// Parse Bech32 address, checking its checksum
+    fn parse_bech32_address(address_str: &str) -> Result<Self> {
+        let (hrp, words, variant) = bech32::decode(address_str)
+            .map_err(|e| CryptoError::AddressError(format!("Invalid Bech32: {}", e)))?;
+        if variant != Variant::Bech32 {
+            return Err(CryptoError::AddressError("Bech32m addresses are not supported".to_string()));
+        }
+        
+        let (version, payload) = words.split_first()
+            .ok_or_else(|| CryptoError::AddressError("Empty Bech32 address".to_string()))?;
+        if version.to_u8() != 0 {
+            return Err(CryptoError::AddressError(format!("Unsupported address version {}", version.to_u8())));
+        }
+        let data = Vec::<u8>::from_base32(payload)
+            .map_err(|e| CryptoError::AddressError(format!("Invalid Bech32 payload: {}", e)))?;
+        if data.len() != 20 {
+            return Err(CryptoError::AddressError("Invalid Bech32 address length".to_string()));
+        }
+        
+        //keep the lowercase form, so mixed-case input and its canonical form compare equal
+        Ok(Self {
+            address_type: AddressType::Bech32,
+            data,
+            encoded: bech32::encode(&hrp, words, Variant::Bech32)
+                .map_err(|e| CryptoError::AddressError(format!("Invalid Bech32: {}", e)))?,
+        })
+    }
+    
     /// Parse Base58 address
     fn parse_base58_address(address_str: &str) -> Result<Self> {
         let decoded = bs58::decode(address_str)
@@ -158,11 +226,12 @@ impl Address {
     
     /// Parse hex checksum address
     fn parse_hex_checksum_address(address_str: &str) -> Result<Self> {
-        if address_str.len() != 42 {
+        let hex_part = address_str.strip_prefix("0x")
+            .ok_or_else(|| CryptoError::AddressError("Hex address must start with 0x".to_string()))?;
+        if hex_part.len() != 40 {
             return Err(CryptoError::AddressError("Invalid hex address length".to_string()));
         }
         
-        let hex_part = &address_str[2..];
         let data = hex::decode(hex_part)
             .map_err(|e| CryptoError::AddressError(format!("Invalid hex: {}", e)))?;
         
@@ -188,11 +257,8 @@ impl Address {
     
     /// Parse simple hex address
     fn parse_hex_address(address_str: &str) -> Result<Self> {
-        if !address_str.starts_with("0x") {
-            return Err(CryptoError::AddressError("Hex address must start with 0x".to_string()));
-        }
-        
-        let hex_part = &address_str[2..];
+        let hex_part = address_str.strip_prefix("0x")
+            .ok_or_else(|| CryptoError::AddressError("Hex address must start with 0x".to_string()))?;
         let data = hex::decode(hex_part)
             .map_err(|e| CryptoError::AddressError(format!("Invalid hex: {}", e)))?;
         
@@ -220,6 +286,7 @@ impl From<Address> for String {
 mod tests {
     use super::*;
     use crate::signature::generate_keypair;
+    use proptest::prelude::*;
 
     #[test]
     fn test_base58_address_creation() {
@@ -278,6 +345,29 @@ mod tests {
         assert!(Address::validate("1234567890").is_err());
     }
 
+    #[test]
+    fn test_bech32_address() {
+        let keypair = generate_keypair();
+        let mainnet = Address::from_public_key(keypair.public_key(), AddressType::Bech32);
+        assert!(mainnet.encoded().starts_with("kai1"));
+        assert_eq!(mainnet.hrp(), Some(MAINNET_HRP));
+        
+        let parsed = Address::from_string(mainnet.encoded()).unwrap();
+        assert_eq!(parsed, mainnet);
+        assert_eq!(Address::from_string(&mainnet.encoded().to_uppercase()).unwrap(), mainnet);
+        
+        let testnet = Address::bech32_from_hash(sha256(&keypair.public_key().to_bytes()), crate::address::TESTNET_HRP).unwrap();
+        assert!(testnet.encoded().starts_with("tkai1"));
+        assert_eq!(testnet.data(), mainnet.data());
+        assert_eq!(Address::from_string(testnet.encoded()).unwrap().hrp(), Some("tkai"));
+        assert!(Address::bech32_from_hash(Hash256::zero(), "Kai").is_err());
+        
+        // Known vector, and a single changed character fails the checksum
+        let known = Address::from_string("kai1qqurswpc8qurswpc8qurswpc8qurswpc8cn6xf0").unwrap();
+        assert_eq!(known.data(), &[7u8; 20]);
+        assert!(Address::validate("kai1qqurswpc8qurswpc8qurswpc8qurswpc8cn6xf2").is_err());
+    }
+
     #[test]
     fn test_different_address_types_same_key() {
         let keypair = generate_keypair();
@@ -316,4 +406,24 @@ mod tests {
         // Should fail validation
         assert!(Address::from_string(&corrupted_str).is_err());
     }
+
+    proptest! {
+        // text off the wire parses or is refused, never panics; whatever
+        // parses reads back the same from its own encoding
+        #[test]
+        fn prop_parsing_arbitrary_text_never_panics(text in prop_oneof![".{0,64}", "0x.{0,42}", "kai1[a-z0-9]{0,40}"]) {
+            if let Ok(address) = Address::from_string(&text) {
+                prop_assert_eq!(Address::from_string(address.encoded()).unwrap(), address);
+            }
+        }
+
+        #[test]
+        fn prop_every_address_type_round_trips(seed: [u8; 32]) {
+            let hash = Hash256::from_bytes(seed);
+            for address_type in [AddressType::Base58, AddressType::HexChecksum, AddressType::Bech32] {
+                let address = Address::from_hash(hash, address_type);
+                prop_assert_eq!(Address::from_string(address.encoded()).unwrap(), address);
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -2,10 +2,11 @@ mod address;
 mod types;
 
 pub use address::Address;
-pub use types::AddressType;
+pub use types::{AddressType, MAINNET_HRP, TESTNET_HRP};
 
 
 use crate::signature::PublicKey;
+use crate::hash::{sha256, Hash256};
 use crate::{CryptoError, Result};
 
 
@@ -14,6 +15,11 @@ pub fn public_key_to_address(public_key: &PublicKey, address_type: AddressType)
 	Address::from_public_key(public_key, address_type)
 }
 
+///Hash a pay-to-script-hash output commits to, of a serialized redeem script
+pub fn script_hash(redeem_script: &[u8]) -> Hash256 {
+	sha256(redeem_script)
+}
+
 ///Validate an address form string
 pub fn validate_address(address_str: &str) -> Result<AddressType> {
 	Address::validate(address_str)
@@ -50,6 +56,16 @@ mod tests {
         assert_eq!(addr_type, AddressType::Base58);
     }
 
+    #[test]
+    fn test_script_hash_address() {
+        let redeem_script = b"2 of 3 multisig";
+        let address = Address::from_script(redeem_script, AddressType::Base58);
+        
+        assert_eq!(address, Address::from_hash(script_hash(redeem_script), AddressType::Base58));
+        assert_ne!(address, Address::from_script(b"1 of 3 multisig", AddressType::Base58));
+        assert!(is_valid_address(&address.to_string()));
+    }
+
     #[test]
     fn test_invalid_address() {
         assert!(!is_valid_address("invalid_address"));
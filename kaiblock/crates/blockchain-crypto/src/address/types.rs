@@ -9,20 +9,33 @@ pub enum AddressType {
 	HexChecksum,
 	///Raw hexadecimal
 	Hex,
+	///Bech32 with a per-network human-readable prefix (SegWit style)
+	Bech32,
 }
 
 
+/// Human-readable prefix of mainnet Bech32 addresses
+pub const MAINNET_HRP: &str = "kai";
+/// Human-readable prefix of testnet Bech32 addresses
+pub const TESTNET_HRP: &str = "tkai";
+
+
 impl AddressType{
 	pub fn prefix(&self) -> &'static str{
 		match self{
 			AddressType::Base58 => "1",
 			AddressType::HexChecksum => "0x",
 			AddressType::Hex => "0x",
+			AddressType::Bech32 => "kai1",
 		}
 	}
 
 	///detect address type from string
 	pub fn detect(address_str: &str) -> Option<Self>{
+		//checked first: a bech32 string can also be made of base58 characters
+		if bech32::decode(address_str).is_ok() {
+			return Some(AddressType::Bech32);
+		}
 		if address_str.starts_with("0x") {
 			if address_str.len() == 42 { //0x + 40 chars
 				Some(AddressType::HexChecksum)
@@ -56,6 +69,7 @@ mod tests {
         assert_eq!(AddressType::detect("0x123"), Some(AddressType::Hex));
         assert_eq!(AddressType::detect("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"), Some(AddressType::Base58));
         assert_eq!(AddressType::detect("invalid!"), None);
+        assert_eq!(AddressType::detect("kai1qqurswpc8qurswpc8qurswpc8qurswpc8cn6xf0"), Some(AddressType::Bech32));
     }
 
     #[test]
@@ -63,5 +77,6 @@ mod tests {
         assert_eq!(AddressType::Base58.prefix(), "1");
         assert_eq!(AddressType::HexChecksum.prefix(), "0x");
         assert_eq!(AddressType::Hex.prefix(), "0x");
+        assert_eq!(AddressType::Bech32.prefix(), "kai1");
     }
 }
\ No newline at end of file
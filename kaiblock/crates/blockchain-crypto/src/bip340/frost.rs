@@ -0,0 +1,498 @@
+//! FROST t-of-n threshold signing (the two rounds of RFC 9591, over
+//! secp256k1 with BIP-340 signatures), keyed by a Pedersen distributed key
+//! generation.
+//!
+//! `n` participants generate a group key together; each ends up with a share
+//! of its secret, which no one ever holds whole. Any `t` of them can then sign
+//! for the group, and the result is one BIP-340 signature that verifies under
+//! the group's x-only key like any other, so a validator set or a custody
+//! service signs without a single point of compromise.
+//!
+//! Key generation:
+//! 1. every participant calls [`dkg_round1`] and broadcasts the
+//!    [`DkgCommitment`] it returns, then sends every other participant, and
+//!    only them, [`DkgSecret::share_for`] them;
+//! 2. with everyone's commitments and the shares sent to it, each calls
+//!    [`dkg_finish`], which checks every share against its sender's
+//!    commitment and names the sender of one that doesn't match.
+//!
+//! Signing takes two rounds, as MuSig2 does: the signers publish
+//! [`NonceCommitment`]s, then each makes a [`SignatureShare`] in a
+//! [`ThresholdSigningSession`], which adds them up.
+
+use std::collections::{BTreeMap, BTreeSet};
+use k256::elliptic_curve::{Field, PrimeField};
+use k256::{FieldBytes, ProjectivePoint, Scalar};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use crate::{CryptoError, Result};
+use super::schnorr::{challenge, decode_point, encode_point, tagged_hash, tagged_scalar, x_and_parity, Bip340Signature, XOnlyPublicKey};
+
+
+///Index of a participant, from 1 to n. Its key share is the group
+///polynomial evaluated there
+pub type ParticipantId = u16;
+
+fn scalar(id: ParticipantId) -> Scalar {
+	Scalar::from(id as u64)
+}
+
+//f(x) = sum(coefficients[k] * x^k), by Horner's rule
+fn evaluate(coefficients: &[Scalar], x: Scalar) -> Scalar {
+	coefficients.iter().rev().fold(Scalar::ZERO, |sum, coefficient| sum * x + coefficient)
+}
+
+//the same polynomial over the coefficients' commitments: f(x) * G
+fn evaluate_commitments(commitments: &[ProjectivePoint], x: Scalar) -> ProjectivePoint {
+	commitments.iter().rev().fold(ProjectivePoint::IDENTITY, |sum, commitment| sum * x + commitment)
+}
+
+//Lagrange coefficient of `id` for interpolating at zero from `signers`
+fn lagrange_coefficient(id: ParticipantId, signers: &[ParticipantId]) -> Scalar {
+	let (numerator, denominator) = signers.iter()
+		.filter(|&&other| other != id)
+		.fold((Scalar::ONE, Scalar::ONE), |(numerator, denominator), &other| {
+			(numerator * scalar(other), denominator * (scalar(other) - scalar(id)))
+		});
+	numerator * Option::<Scalar>::from(denominator.invert()).expect("signer ids are distinct")
+}
+
+fn check_participant(id: ParticipantId, participants: u16) -> Result<()> {
+	if id == 0 || id > participants {
+		return Err(CryptoError::InvalidKey(format!("Participant {} is not between 1 and {}", id, participants)));
+	}
+	Ok(())
+}
+
+
+///A participant's secret polynomial for one key generation, kept until it
+///has sent its shares and finished
+pub struct DkgSecret {
+	id: ParticipantId,
+	participants: u16,
+	coefficients: Vec<Scalar>,
+}
+
+impl std::fmt::Debug for DkgSecret {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("DkgSecret").field("id", &self.id).field("participants", &self.participants).finish()
+	}
+}
+
+///What a participant broadcasts in the first round: commitments to the
+///coefficients of its polynomial, and a proof it knows the constant one, so
+///no one can pick a commitment cancelling the others'
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DkgCommitment {
+	pub id: ParticipantId,
+	commitments: Vec<ProjectivePoint>,
+	proof_nonce: ProjectivePoint,
+	proof: Scalar,
+}
+
+fn proof_challenge(id: ParticipantId, constant: &ProjectivePoint, nonce: &ProjectivePoint) -> Scalar {
+	tagged_scalar(b"FROST/dkg", &[&id.to_be_bytes(), &encode_point(constant), &encode_point(nonce)])
+}
+
+impl DkgCommitment {
+	///number of signers the resulting key needs
+	pub fn threshold(&self) -> u16 {
+		self.commitments.len() as u16
+	}
+
+	///check the proof of knowledge of the constant coefficient
+	pub fn verify(&self) -> bool {
+		let Some(constant) = self.commitments.first() else {
+			return false;
+		};
+		let c = proof_challenge(self.id, constant, &self.proof_nonce);
+		ProjectivePoint::GENERATOR * self.proof == self.proof_nonce + *constant * c
+	}
+}
+
+///Start a key generation as participant `id` of `participants`, for keys
+///any `threshold` of them can sign with
+pub fn dkg_round1(id: ParticipantId, threshold: u16, participants: u16) -> Result<(DkgSecret, DkgCommitment)> {
+	check_participant(id, participants)?;
+	if threshold == 0 || threshold > participants {
+		return Err(CryptoError::InvalidKey(format!("Invalid threshold: {} of {}", threshold, participants)));
+	}
+
+	let coefficients: Vec<Scalar> = (0..threshold).map(|_| Scalar::random(&mut OsRng)).collect();
+	let commitments: Vec<ProjectivePoint> = coefficients.iter()
+		.map(|coefficient| ProjectivePoint::GENERATOR * coefficient)
+		.collect();
+
+	let nonce = Scalar::random(&mut OsRng);
+	let proof_nonce = ProjectivePoint::GENERATOR * nonce;
+	let proof = nonce + coefficients[0] * proof_challenge(id, &commitments[0], &proof_nonce);
+
+	Ok((
+		DkgSecret { id, participants, coefficients },
+		DkgCommitment { id, commitments, proof_nonce, proof },
+	))
+}
+
+
+///One participant's polynomial evaluated at another's id, sent to that
+///participant alone
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SecretShare {
+	from: ParticipantId,
+	value: Scalar,
+}
+
+impl std::fmt::Debug for SecretShare {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("SecretShare").field("from", &self.from).finish()
+	}
+}
+
+impl SecretShare {
+	///the participant that made the share
+	pub fn from(&self) -> ParticipantId {
+		self.from
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.value.to_bytes().into()
+	}
+
+	pub fn from_bytes(from: ParticipantId, bytes: [u8; 32]) -> Result<Self> {
+		Option::from(Scalar::from_repr(FieldBytes::from(bytes)))
+			.map(|value| Self { from, value })
+			.ok_or_else(|| CryptoError::InvalidKey("Invalid secret share".to_string()))
+	}
+}
+
+impl DkgSecret {
+	///the share to send participant `id`
+	pub fn share_for(&self, id: ParticipantId) -> Result<SecretShare> {
+		check_participant(id, self.participants)?;
+		Ok(SecretShare { from: self.id, value: evaluate(&self.coefficients, scalar(id)) })
+	}
+}
+
+
+///The group key, with every participant's public share of it. Everyone
+///finishing the same key generation gets the same one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupPublicKey {
+	threshold: u16,
+	key: XOnlyPublicKey,
+	//whether the group point has an odd y, so every share is negated
+	negated: bool,
+	verifying_shares: BTreeMap<ParticipantId, ProjectivePoint>,
+}
+
+impl GroupPublicKey {
+	///the key group signatures verify under
+	pub fn key(&self) -> &XOnlyPublicKey {
+		&self.key
+	}
+
+	///number of signers a signature needs
+	pub fn threshold(&self) -> u16 {
+		self.threshold
+	}
+
+	pub fn participants(&self) -> impl Iterator<Item = ParticipantId> + '_ {
+		self.verifying_shares.keys().copied()
+	}
+
+	//1 or -1, turning the group point into its even-y key
+	fn parity(&self) -> Scalar {
+		if self.negated { -Scalar::ONE } else { Scalar::ONE }
+	}
+}
+
+///A participant's share of the group secret
+#[derive(Clone)]
+pub struct KeyShare {
+	id: ParticipantId,
+	secret: Scalar,
+	group: GroupPublicKey,
+}
+
+impl std::fmt::Debug for KeyShare {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("KeyShare").field("id", &self.id).field("group", &self.group).finish()
+	}
+}
+
+impl KeyShare {
+	pub fn id(&self) -> ParticipantId {
+		self.id
+	}
+
+	pub fn group(&self) -> &GroupPublicKey {
+		&self.group
+	}
+}
+
+///Finish a key generation with every participant's commitment (this one's
+///included) and the share every other participant sent this one. A share not
+///matching its sender's commitment fails with the sender's id
+pub fn dkg_finish(secret: DkgSecret, commitments: &[DkgCommitment], shares: &[SecretShare]) -> Result<KeyShare> {
+	let threshold = secret.coefficients.len();
+	let mut by_id = BTreeMap::new();
+	for commitment in commitments {
+		check_participant(commitment.id, secret.participants)?;
+		if commitment.commitments.len() != threshold || !commitment.verify() {
+			return Err(CryptoError::InvalidKey(format!("Invalid commitment from participant {}", commitment.id)));
+		}
+		if by_id.insert(commitment.id, &commitment.commitments).is_some() {
+			return Err(CryptoError::InvalidKey(format!("Two commitments from participant {}", commitment.id)));
+		}
+	}
+	if by_id.len() != secret.participants as usize {
+		return Err(CryptoError::InvalidKey("Missing commitments".to_string()));
+	}
+	if by_id[&secret.id][0] != ProjectivePoint::GENERATOR * secret.coefficients[0] {
+		return Err(CryptoError::InvalidKey("Own commitment does not match the secret".to_string()));
+	}
+
+	let mut total = evaluate(&secret.coefficients, scalar(secret.id));
+	let mut senders = BTreeSet::new();
+	for share in shares {
+		let commitment = by_id.get(&share.from)
+			.filter(|_| share.from != secret.id)
+			.ok_or_else(|| CryptoError::InvalidKey(format!("Unexpected share from participant {}", share.from)))?;
+		if !senders.insert(share.from) {
+			return Err(CryptoError::InvalidKey(format!("Two shares from participant {}", share.from)));
+		}
+		if ProjectivePoint::GENERATOR * share.value != evaluate_commitments(commitment, scalar(secret.id)) {
+			return Err(CryptoError::InvalidKey(format!("Share from participant {} does not match its commitment", share.from)));
+		}
+		total += share.value;
+	}
+	if senders.len() + 1 != secret.participants as usize {
+		return Err(CryptoError::InvalidKey("Missing shares".to_string()));
+	}
+
+	let point = by_id.values().fold(ProjectivePoint::IDENTITY, |sum, commitment| sum + commitment[0]);
+	let key = XOnlyPublicKey::from_point(&point)
+		.ok_or_else(|| CryptoError::InvalidKey("Group key is the identity".to_string()))?;
+	let verifying_shares = (1..=secret.participants)
+		.map(|id| {
+			let share = by_id.values()
+				.fold(ProjectivePoint::IDENTITY, |sum, commitment| sum + evaluate_commitments(commitment, scalar(id)));
+			(id, share)
+		})
+		.collect();
+
+	Ok(KeyShare {
+		id: secret.id,
+		secret: total,
+		group: GroupPublicKey {
+			threshold: threshold as u16,
+			key,
+			negated: x_and_parity(&point).1,
+			verifying_shares,
+		},
+	})
+}
+
+
+///A signer's nonces for one signature. Not Clone: signing consumes them, so
+///they can't sign twice
+pub struct SigningNonces {
+	id: ParticipantId,
+	hiding: Scalar,
+	binding: Scalar,
+}
+
+impl std::fmt::Debug for SigningNonces {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("SigningNonces").field("id", &self.id).finish()
+	}
+}
+
+///The nonce points a signer publishes in the first round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonceCommitment {
+	pub id: ParticipantId,
+	hiding: ProjectivePoint,
+	binding: ProjectivePoint,
+}
+
+impl NonceCommitment {
+	///both points, compressed
+	pub fn to_bytes(&self) -> [u8; 66] {
+		let mut bytes = [0u8; 66];
+		bytes[..33].copy_from_slice(&encode_point(&self.hiding));
+		bytes[33..].copy_from_slice(&encode_point(&self.binding));
+		bytes
+	}
+
+	pub fn from_bytes(id: ParticipantId, bytes: &[u8; 66]) -> Result<Self> {
+		Ok(Self {
+			id,
+			hiding: decode_point(&bytes[..33])?,
+			binding: decode_point(&bytes[33..])?,
+		})
+	}
+}
+
+///Generate the nonces `share` signs its next signature with. Fresh randomness
+///is hashed with the secret, so a weak random source alone doesn't repeat them
+pub fn commit(share: &KeyShare) -> (SigningNonces, NonceCommitment) {
+	let mut rand = [0u8; 32];
+	OsRng.fill_bytes(&mut rand);
+	let secret = share.secret.to_bytes();
+	let nonce = |index: u8| tagged_scalar(b"FROST/nonce", &[&rand, &secret, &[index]]);
+
+	let (hiding, binding) = (nonce(0), nonce(1));
+	let commitment = NonceCommitment {
+		id: share.id,
+		hiding: ProjectivePoint::GENERATOR * hiding,
+		binding: ProjectivePoint::GENERATOR * binding,
+	};
+	(SigningNonces { id: share.id, hiding, binding }, commitment)
+}
+
+
+///A signer's share of a group signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureShare {
+	pub id: ParticipantId,
+	value: Scalar,
+}
+
+impl SignatureShare {
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.value.to_bytes().into()
+	}
+
+	pub fn from_bytes(id: ParticipantId, bytes: [u8; 32]) -> Result<Self> {
+		Option::from(Scalar::from_repr(FieldBytes::from(bytes)))
+			.map(|value| Self { id, value })
+			.ok_or(CryptoError::InvalidSignature)
+	}
+}
+
+
+///Second round of signing one message by the signers whose nonce commitments
+///it was built from
+#[derive(Debug, Clone)]
+pub struct ThresholdSigningSession {
+	group: GroupPublicKey,
+	commitments: BTreeMap<ParticipantId, NonceCommitment>,
+	//binding factor rho_i of every signer: R = sum(D_i + rho_i * E_i)
+	binding_factors: BTreeMap<ParticipantId, Scalar>,
+	nonce_x: [u8; 32],
+	//1 or -1, for R to have an even y
+	nonce_parity: Scalar,
+	e: Scalar,
+}
+
+impl ThresholdSigningSession {
+	///start signing `message` with one nonce commitment from each of at
+	///least `threshold` distinct participants
+	pub fn new(group: &GroupPublicKey, commitments: &[NonceCommitment], message: &[u8]) -> Result<Self> {
+		let mut by_id = BTreeMap::new();
+		for commitment in commitments {
+			if !group.verifying_shares.contains_key(&commitment.id) {
+				return Err(CryptoError::InvalidKey(format!("Participant {} is not in the group", commitment.id)));
+			}
+			if by_id.insert(commitment.id, *commitment).is_some() {
+				return Err(CryptoError::InvalidKey(format!("Two nonce commitments from participant {}", commitment.id)));
+			}
+		}
+		if by_id.len() < group.threshold as usize {
+			return Err(CryptoError::InvalidKey(format!(
+				"{} signers, the group needs {}", by_id.len(), group.threshold
+			)));
+		}
+
+		//every binding factor commits to every signer's nonces, so no signer
+		//can pick theirs after seeing the others' to steer R
+		let encoded: Vec<[u8; 68]> = by_id.values()
+			.map(|commitment| {
+				let mut bytes = [0u8; 68];
+				bytes[..2].copy_from_slice(&commitment.id.to_be_bytes());
+				bytes[2..].copy_from_slice(&commitment.to_bytes());
+				bytes
+			})
+			.collect();
+		let parts: Vec<&[u8]> = encoded.iter().map(|bytes| &bytes[..]).collect();
+		let commitments_hash = tagged_hash(b"FROST/commitments", &parts);
+		let binding_factors: BTreeMap<ParticipantId, Scalar> = by_id.keys()
+			.map(|&id| (id, tagged_scalar(b"FROST/rho", &[&id.to_be_bytes(), group.key.as_bytes(), &commitments_hash, message])))
+			.collect();
+
+		let nonce_point = by_id.values()
+			.fold(ProjectivePoint::IDENTITY, |sum, commitment| {
+				sum + commitment.hiding + commitment.binding * binding_factors[&commitment.id]
+			});
+		if nonce_point == ProjectivePoint::IDENTITY {
+			return Err(CryptoError::InvalidKey("Nonce commitments cancel out".to_string()));
+		}
+		let (nonce_x, odd) = x_and_parity(&nonce_point);
+		let e = challenge(&nonce_x, &group.key, message);
+
+		Ok(Self {
+			group: group.clone(),
+			commitments: by_id,
+			binding_factors,
+			nonce_x,
+			nonce_parity: if odd { -Scalar::ONE } else { Scalar::ONE },
+			e,
+		})
+	}
+
+	///ids of the participants signing
+	pub fn signers(&self) -> Vec<ParticipantId> {
+		self.commitments.keys().copied().collect()
+	}
+
+	//lambda_i * e * parity: the weight of signer `id`'s key share
+	fn key_weight(&self, id: ParticipantId) -> Scalar {
+		lagrange_coefficient(id, &self.signers()) * self.e * self.group.parity()
+	}
+
+	///z_i = d_i + rho_i*e_i + lambda_i*e*s_i, with nonces and share negated as
+	///the parities of R and the group key ask
+	pub fn sign(&self, share: &KeyShare, nonces: SigningNonces) -> Result<SignatureShare> {
+		if share.group != self.group {
+			return Err(CryptoError::InvalidKey("Key share is for another group".to_string()));
+		}
+		if nonces.id != share.id {
+			return Err(CryptoError::InvalidKey("Nonces were generated for another participant".to_string()));
+		}
+		let binding_factor = self.binding_factors.get(&share.id)
+			.ok_or_else(|| CryptoError::InvalidKey(format!("Participant {} is not signing", share.id)))?;
+
+		let nonce = (nonces.hiding + nonces.binding * binding_factor) * self.nonce_parity;
+		Ok(SignatureShare { id: share.id, value: nonce + self.key_weight(share.id) * share.secret })
+	}
+
+	///check one signer's share against its nonce commitment and public key
+	///share, so a bad share can be blamed on its signer
+	pub fn verify_share(&self, share: &SignatureShare) -> bool {
+		let (Some(commitment), Some(verifying_share)) = (
+			self.commitments.get(&share.id),
+			self.group.verifying_shares.get(&share.id),
+		) else {
+			return false;
+		};
+		let nonce_point = (commitment.hiding + commitment.binding * self.binding_factors[&share.id]) * self.nonce_parity;
+		ProjectivePoint::GENERATOR * share.value == nonce_point + *verifying_share * self.key_weight(share.id)
+	}
+
+	///add one share from every signer up into a BIP-340 signature under the
+	///group key. every share is checked first; `verify_share` tells which one
+	///failed
+	pub fn aggregate(&self, shares: &[SignatureShare]) -> Result<Bip340Signature> {
+		let ids: BTreeSet<ParticipantId> = shares.iter().map(|share| share.id).collect();
+		if ids.len() != shares.len() || !ids.iter().eq(self.commitments.keys()) {
+			return Err(CryptoError::InvalidKey("Need exactly one share from every signer".to_string()));
+		}
+		if !shares.iter().all(|share| self.verify_share(share)) {
+			return Err(CryptoError::InvalidSignature);
+		}
+
+		let s = shares.iter().fold(Scalar::ZERO, |sum, share| sum + share.value);
+		Ok(Bip340Signature { r: self.nonce_x, s: s.to_bytes().into() })
+	}
+}
@@ -0,0 +1,241 @@
+mod frost;
+mod musig;
+mod schnorr;
+
+pub use frost::{
+	commit, dkg_finish, dkg_round1, DkgCommitment, DkgSecret, GroupPublicKey, KeyShare,
+	NonceCommitment, ParticipantId, SecretShare, SignatureShare, SigningNonces, ThresholdSigningSession,
+};
+pub use musig::{
+	aggregate_nonces, aggregate_public_keys, generate_nonce, verify_aggregate,
+	AggregateNonce, KeyAggContext, PartialSignature, PublicNonce, SecretNonce, SigningSession,
+};
+pub use schnorr::{Bip340Keypair, Bip340Signature, XOnlyPublicKey};
+pub(crate) use schnorr::{challenge, tagged_scalar, x_and_parity};
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex32(s: &str) -> [u8; 32] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_bip340_vector_0() {
+        let keypair = Bip340Keypair::from_secret_bytes(hex32("0000000000000000000000000000000000000000000000000000000000000003")).unwrap();
+        assert_eq!(hex::encode(keypair.public_key().as_bytes()), "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9");
+
+        let message = [0u8; 32];
+        let signature = keypair.sign_with_aux_rand(&message, &[0u8; 32]);
+        assert_eq!(
+            hex::encode(signature.to_bytes()),
+            "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0"
+        );
+        assert!(keypair.public_key().verify(&message, &signature));
+    }
+
+    #[test]
+    fn test_bip340_roundtrip() {
+        let keypair = Bip340Keypair::generate();
+        let signature = keypair.sign(b"message");
+
+        assert!(keypair.public_key().verify(b"message", &signature));
+        assert!(!keypair.public_key().verify(b"other message", &signature));
+        assert!(!Bip340Keypair::generate().public_key().verify(b"message", &signature));
+        assert_eq!(Bip340Signature::from_slice(&signature.to_bytes()).unwrap(), signature);
+        assert!(XOnlyPublicKey::from_bytes([0xff; 32]).is_err());
+    }
+
+    fn musig_sign(keypairs: &[Bip340Keypair], message: &[u8]) -> (KeyAggContext, Bip340Signature) {
+        let context = KeyAggContext::new(keypairs.iter().map(|keypair| *keypair.public_key()).collect()).unwrap();
+        let (secret_nonces, public_nonces): (Vec<_>, Vec<_>) = keypairs.iter()
+            .map(|keypair| generate_nonce(keypair, context.aggregate_key(), message))
+            .unzip();
+
+        let session = SigningSession::new(&context, &aggregate_nonces(&public_nonces).unwrap(), message);
+        let partials: Vec<PartialSignature> = keypairs.iter()
+            .zip(secret_nonces)
+            .map(|(keypair, nonce)| session.partial_sign(keypair, nonce).unwrap())
+            .collect();
+        for ((partial, keypair), nonce) in partials.iter().zip(keypairs).zip(&public_nonces) {
+            assert!(session.verify_partial(partial, keypair.public_key(), nonce));
+        }
+        (context, session.aggregate(&partials))
+    }
+
+    #[test]
+    fn test_musig_signature_verifies_under_the_aggregate_key() {
+        for signers in 1..=5 {
+            let keypairs: Vec<Bip340Keypair> = (0..signers).map(|_| Bip340Keypair::generate()).collect();
+            let (context, signature) = musig_sign(&keypairs, b"spend the multisig output");
+
+            assert!(context.aggregate_key().verify(b"spend the multisig output", &signature));
+            assert!(verify_aggregate(context.keys(), b"spend the multisig output", &signature).is_ok());
+            assert!(verify_aggregate(context.keys(), b"something else", &signature).is_err());
+        }
+    }
+
+    #[test]
+    fn test_aggregate_key_depends_on_every_key_and_order() {
+        let keys: Vec<XOnlyPublicKey> = (0..3).map(|_| *Bip340Keypair::generate().public_key()).collect();
+        let aggregate = aggregate_public_keys(&keys).unwrap();
+
+        assert_ne!(aggregate_public_keys(&keys[..2]).unwrap(), aggregate);
+        assert_ne!(aggregate_public_keys(&[keys[1], keys[0], keys[2]]).unwrap(), aggregate);
+        assert!(aggregate_public_keys(&[]).is_err());
+        assert!(aggregate_public_keys(&[keys[0], keys[0]]).is_err());
+    }
+
+    #[test]
+    fn test_bad_partial_signatures_are_caught() {
+        let keypairs: Vec<Bip340Keypair> = (0..3).map(|_| Bip340Keypair::generate()).collect();
+        let context = KeyAggContext::new(keypairs.iter().map(|keypair| *keypair.public_key()).collect()).unwrap();
+        let message = b"message";
+        let (secret_nonces, public_nonces): (Vec<_>, Vec<_>) = keypairs.iter()
+            .map(|keypair| generate_nonce(keypair, context.aggregate_key(), message))
+            .unzip();
+        let session = SigningSession::new(&context, &aggregate_nonces(&public_nonces).unwrap(), message);
+
+        let mut nonces = secret_nonces.into_iter();
+        let first = nonces.next().unwrap();
+        // a nonce only signs for the key it was made for
+        assert!(session.partial_sign(&keypairs[1], first).is_err());
+
+        let partial = session.partial_sign(&keypairs[1], nonces.next().unwrap()).unwrap();
+        assert!(!session.verify_partial(&partial, keypairs[2].public_key(), &public_nonces[2]));
+        assert!(!session.verify_partial(&partial, keypairs[1].public_key(), &public_nonces[0]));
+
+        let outsider = Bip340Keypair::generate();
+        let (outsider_nonce, _) = generate_nonce(&outsider, context.aggregate_key(), message);
+        assert!(session.partial_sign(&outsider, outsider_nonce).is_err());
+
+        // too few shares don't make a signature
+        let short = session.aggregate(&[partial]);
+        assert!(!context.aggregate_key().verify(message, &short));
+    }
+
+    #[test]
+    fn test_nonce_and_partial_encodings_roundtrip() {
+        let keypair = Bip340Keypair::generate();
+        let (_, nonce) = generate_nonce(&keypair, keypair.public_key(), b"message");
+        assert_eq!(PublicNonce::from_bytes(&nonce.to_bytes()).unwrap(), nonce);
+        assert!(PublicNonce::from_bytes(&[0u8; 66]).is_err());
+
+        let (context, _) = musig_sign(std::slice::from_ref(&keypair), b"message");
+        assert_eq!(context.keys(), &[*keypair.public_key()]);
+        assert!(PartialSignature::from_bytes([0xff; 32]).is_err());
+    }
+
+    fn run_dkg(threshold: u16, participants: u16) -> Vec<KeyShare> {
+        let (secrets, commitments): (Vec<DkgSecret>, Vec<DkgCommitment>) = (1..=participants)
+            .map(|id| dkg_round1(id, threshold, participants).unwrap())
+            .unzip();
+        // participant i sends participant j the share of its polynomial at j
+        let received: Vec<Vec<SecretShare>> = (1..=participants)
+            .map(|to| secrets.iter()
+                .zip(1..)
+                .filter(|&(_, from)| from != to)
+                .map(|(secret, _)| secret.share_for(to).unwrap())
+                .collect())
+            .collect();
+        secrets.into_iter()
+            .zip(received)
+            .map(|(secret, shares)| dkg_finish(secret, &commitments, &shares).unwrap())
+            .collect()
+    }
+
+    fn frost_sign(signers: &[&KeyShare], message: &[u8]) -> Result<Bip340Signature, crate::CryptoError> {
+        let (nonces, commitments): (Vec<_>, Vec<_>) = signers.iter().map(|share| commit(share)).unzip();
+        let session = ThresholdSigningSession::new(signers[0].group(), &commitments, message)?;
+        let shares: Vec<SignatureShare> = signers.iter()
+            .zip(nonces)
+            .map(|(share, nonces)| session.sign(share, nonces).unwrap())
+            .collect();
+        session.aggregate(&shares)
+    }
+
+    #[test]
+    fn test_any_threshold_of_participants_can_sign() {
+        for (threshold, participants) in [(1, 1), (2, 3), (3, 5)] {
+            let shares = run_dkg(threshold, participants);
+            let group = shares[0].group().clone();
+            assert!(shares.iter().all(|share| *share.group() == group));
+            assert_eq!(group.threshold(), threshold);
+            assert_eq!(group.participants().count(), participants as usize);
+
+            // every window of `threshold` signers, and everyone at once
+            for start in 0..participants as usize {
+                let signers: Vec<&KeyShare> = (0..threshold as usize)
+                    .map(|offset| &shares[(start + offset) % participants as usize])
+                    .collect();
+                let signature = frost_sign(&signers, b"block header").unwrap();
+                assert!(group.key().verify(b"block header", &signature));
+                assert!(!group.key().verify(b"another header", &signature));
+            }
+            let everyone: Vec<&KeyShare> = shares.iter().collect();
+            assert!(group.key().verify(b"block header", &frost_sign(&everyone, b"block header").unwrap()));
+        }
+    }
+
+    #[test]
+    fn test_fewer_than_threshold_signers_are_refused() {
+        let shares = run_dkg(3, 4);
+        assert!(frost_sign(&[&shares[0], &shares[2]], b"message").is_err());
+        assert!(frost_sign(&[&shares[0], &shares[0], &shares[2]], b"message").is_err());
+        assert!(dkg_round1(1, 0, 3).is_err());
+        assert!(dkg_round1(1, 4, 3).is_err());
+        assert!(dkg_round1(4, 2, 3).is_err());
+    }
+
+    #[test]
+    fn test_dkg_names_the_sender_of_a_bad_share() {
+        let (secrets, commitments): (Vec<DkgSecret>, Vec<DkgCommitment>) = (1..=3)
+            .map(|id| dkg_round1(id, 2, 3).unwrap())
+            .unzip();
+        let to_first = vec![SecretShare::from_bytes(2, [7u8; 32]).unwrap(), secrets[2].share_for(1).unwrap()];
+        let to_third = vec![secrets[0].share_for(3).unwrap(), secrets[1].share_for(3).unwrap()];
+        let [first, _, third] = <[DkgSecret; 3]>::try_from(secrets).unwrap();
+
+        let error = dkg_finish(first, &commitments, &to_first).unwrap_err();
+        assert!(error.to_string().contains("participant 2"));
+
+        // a commitment whose proof of knowledge was made by someone else is refused too
+        let mut tampered = commitments.clone();
+        tampered[1] = dkg_round1(3, 2, 3).unwrap().1;
+        tampered[1].id = 2;
+        let error = dkg_finish(third, &tampered, &to_third).unwrap_err();
+        assert!(error.to_string().contains("participant 2"));
+    }
+
+    #[test]
+    fn test_bad_signature_shares_are_caught() {
+        let shares = run_dkg(2, 3);
+        let (nonces, commitments): (Vec<_>, Vec<_>) = shares[..2].iter().map(commit).unzip();
+        let session = ThresholdSigningSession::new(shares[0].group(), &commitments, b"message").unwrap();
+        assert_eq!(session.signers(), vec![1, 2]);
+
+        let mut nonces = nonces.into_iter();
+        let first = nonces.next().unwrap();
+        // nonces only sign for the participant they were made for
+        assert!(session.sign(&shares[1], first).is_err());
+
+        let good = session.sign(&shares[1], nonces.next().unwrap()).unwrap();
+        assert!(session.verify_share(&good));
+        let bad = SignatureShare::from_bytes(1, [1u8; 32]).unwrap();
+        assert!(!session.verify_share(&bad));
+        assert!(session.aggregate(&[bad, good]).is_err());
+        assert!(session.aggregate(&[good]).is_err());
+
+        // a participant outside the signing set can't join in
+        let (outsider, _) = commit(&shares[2]);
+        assert!(session.sign(&shares[2], outsider).is_err());
+        assert_eq!(
+            NonceCommitment::from_bytes(1, &commitments[0].to_bytes()).unwrap(),
+            commitments[0]
+        );
+        assert_eq!(SignatureShare::from_bytes(2, good.to_bytes()).unwrap(), good);
+    }
+}
@@ -0,0 +1,265 @@
+//! MuSig2 n-of-n signing (the construction of BIP-327, over x-only keys).
+//!
+//! The signers' keys are aggregated into one x-only key. Coins locked to it
+//! are spent with a single BIP-340 signature, which none of the signers can
+//! make alone and which verifies like any other. Signing takes two rounds:
+//! every signer publishes a [`PublicNonce`], then, once the nonces are
+//! aggregated, a [`PartialSignature`]; the partial signatures add up to the
+//! final signature.
+//!
+//! A [`SecretNonce`] is consumed by signing: signing two messages with one
+//! nonce reveals the secret key.
+
+use k256::elliptic_curve::PrimeField;
+use k256::{FieldBytes, ProjectivePoint, Scalar};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use crate::{CryptoError, Result};
+use super::schnorr::{challenge, decode_point, encode_point, tagged_scalar, x_and_parity, Bip340Keypair, Bip340Signature, XOnlyPublicKey};
+
+
+///Signers' keys, each with the coefficient it is weighted by in the
+///aggregate key. Key order matters: the same keys in another order give
+///another aggregate key
+#[derive(Debug, Clone)]
+pub struct KeyAggContext {
+	keys: Vec<XOnlyPublicKey>,
+	coefficients: Vec<Scalar>,
+	aggregate_key: XOnlyPublicKey,
+	//whether the aggregate point has an odd y, so every secret is negated
+	negated: bool,
+}
+
+impl KeyAggContext {
+	///aggregate distinct keys: Q = sum(a_i * P_i) with a_i = H(L || P_i) for
+	///L the hash of every key, so no signer can pick a key cancelling the others
+	pub fn new(keys: Vec<XOnlyPublicKey>) -> Result<Self> {
+		if keys.is_empty() {
+			return Err(CryptoError::InvalidKey("No keys to aggregate".to_string()));
+		}
+		if keys.iter().enumerate().any(|(i, key)| keys[..i].contains(key)) {
+			return Err(CryptoError::InvalidKey("Aggregated keys must be distinct".to_string()));
+		}
+
+		let list: Vec<&[u8]> = keys.iter().map(|key| &key.as_bytes()[..]).collect();
+		let list_hash = super::schnorr::tagged_hash(b"KeyAgg list", &list);
+		let coefficients: Vec<Scalar> = keys.iter()
+			.enumerate()
+			.map(|(i, key)| {
+				//as in BIP-327, the second key is weighted by 1, saving one multiplication
+				if i == 1 {
+					Scalar::ONE
+				} else {
+					tagged_scalar(b"KeyAgg coefficient", &[&list_hash, key.as_bytes()])
+				}
+			})
+			.collect();
+
+		let point = keys.iter()
+			.zip(&coefficients)
+			.fold(ProjectivePoint::IDENTITY, |sum, (key, coefficient)| sum + key.point() * coefficient);
+		let aggregate_key = XOnlyPublicKey::from_point(&point)
+			.ok_or_else(|| CryptoError::InvalidKey("Keys aggregate to the identity".to_string()))?;
+		let negated = x_and_parity(&point).1;
+
+		Ok(Self { keys, coefficients, aggregate_key, negated })
+	}
+
+	pub fn keys(&self) -> &[XOnlyPublicKey] {
+		&self.keys
+	}
+
+	///the key signatures of the whole group verify under
+	pub fn aggregate_key(&self) -> &XOnlyPublicKey {
+		&self.aggregate_key
+	}
+
+	fn coefficient(&self, key: &XOnlyPublicKey) -> Option<Scalar> {
+		let index = self.keys.iter().position(|other| other == key)?;
+		Some(self.coefficients[index])
+	}
+
+	//1 or -1, turning the aggregate point into its even-y key
+	fn parity(&self) -> Scalar {
+		if self.negated { -Scalar::ONE } else { Scalar::ONE }
+	}
+}
+
+
+///Aggregate key of distinct `keys`, in that order
+pub fn aggregate_public_keys(keys: &[XOnlyPublicKey]) -> Result<XOnlyPublicKey> {
+	KeyAggContext::new(keys.to_vec()).map(|context| context.aggregate_key)
+}
+
+///Verify an aggregated signature against the keys that made it
+pub fn verify_aggregate(keys: &[XOnlyPublicKey], message: &[u8], signature: &Bip340Signature) -> Result<()> {
+	signature.verify(&aggregate_public_keys(keys)?, message)
+}
+
+
+///A signer's secret nonces for one signing session. Not Clone: signing
+///consumes it, so it can't sign twice
+pub struct SecretNonce {
+	k1: Scalar,
+	k2: Scalar,
+	public_key: XOnlyPublicKey,
+}
+
+impl std::fmt::Debug for SecretNonce {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("SecretNonce").field("public_key", &self.public_key).finish()
+	}
+}
+
+///The nonce points a signer publishes in the first round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicNonce {
+	r1: ProjectivePoint,
+	r2: ProjectivePoint,
+}
+
+impl PublicNonce {
+	///both points, compressed
+	pub fn to_bytes(&self) -> [u8; 66] {
+		let mut bytes = [0u8; 66];
+		bytes[..33].copy_from_slice(&encode_point(&self.r1));
+		bytes[33..].copy_from_slice(&encode_point(&self.r2));
+		bytes
+	}
+
+	pub fn from_bytes(bytes: &[u8; 66]) -> Result<Self> {
+		Ok(Self {
+			r1: decode_point(&bytes[..33])?,
+			r2: decode_point(&bytes[33..])?,
+		})
+	}
+}
+
+///Generate the nonces `keypair` signs `message` with under `aggregate_key`.
+///Fresh randomness is mixed with the inputs, so a weak random source alone
+///doesn't repeat nonces across messages
+pub fn generate_nonce(keypair: &Bip340Keypair, aggregate_key: &XOnlyPublicKey, message: &[u8]) -> (SecretNonce, PublicNonce) {
+	let mut rand = [0u8; 32];
+	OsRng.fill_bytes(&mut rand);
+	let secret = keypair.secret().to_bytes();
+	let nonce = |index: u8| {
+		tagged_scalar(b"MuSig/nonce", &[&rand, &secret, aggregate_key.as_bytes(), message, &[index]])
+	};
+
+	let (k1, k2) = (nonce(0), nonce(1));
+	let public = PublicNonce {
+		r1: ProjectivePoint::GENERATOR * k1,
+		r2: ProjectivePoint::GENERATOR * k2,
+	};
+	(SecretNonce { k1, k2, public_key: *keypair.public_key() }, public)
+}
+
+
+///Sum of every signer's nonce points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AggregateNonce {
+	r1: ProjectivePoint,
+	r2: ProjectivePoint,
+}
+
+impl AggregateNonce {
+	fn to_bytes(self) -> [u8; 66] {
+		PublicNonce { r1: self.r1, r2: self.r2 }.to_bytes()
+	}
+}
+
+///Add up the signers' public nonces
+pub fn aggregate_nonces(nonces: &[PublicNonce]) -> Result<AggregateNonce> {
+	if nonces.is_empty() {
+		return Err(CryptoError::InvalidKey("No nonces to aggregate".to_string()));
+	}
+	Ok(nonces.iter().fold(
+		AggregateNonce { r1: ProjectivePoint::IDENTITY, r2: ProjectivePoint::IDENTITY },
+		|sum, nonce| AggregateNonce { r1: sum.r1 + nonce.r1, r2: sum.r2 + nonce.r2 },
+	))
+}
+
+
+///A signer's share of the aggregated signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartialSignature(Scalar);
+
+impl PartialSignature {
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.0.to_bytes().into()
+	}
+
+	pub fn from_bytes(bytes: [u8; 32]) -> Result<Self> {
+		Option::from(Scalar::from_repr(FieldBytes::from(bytes)))
+			.map(Self)
+			.ok_or(CryptoError::InvalidSignature)
+	}
+}
+
+
+///Second round of signing one message with the aggregated nonce: every
+///signer makes a partial signature, and any of them can add them up
+#[derive(Debug, Clone)]
+pub struct SigningSession {
+	context: KeyAggContext,
+	//nonce coefficient b: R = R1 + b*R2
+	b: Scalar,
+	nonce_x: [u8; 32],
+	//1 or -1, for R to have an even y
+	nonce_parity: Scalar,
+	e: Scalar,
+}
+
+impl SigningSession {
+	pub fn new(context: &KeyAggContext, aggregate_nonce: &AggregateNonce, message: &[u8]) -> Self {
+		let b = tagged_scalar(
+			b"MuSig/noncecoef",
+			&[&aggregate_nonce.to_bytes(), context.aggregate_key.as_bytes(), message],
+		);
+		let mut nonce_point = aggregate_nonce.r1 + aggregate_nonce.r2 * b;
+		if nonce_point == ProjectivePoint::IDENTITY {
+			//only reachable by cancelling nonces; BIP-327 signs with G instead
+			nonce_point = ProjectivePoint::GENERATOR;
+		}
+		let (nonce_x, odd) = x_and_parity(&nonce_point);
+		let e = challenge(&nonce_x, &context.aggregate_key, message);
+
+		Self {
+			context: context.clone(),
+			b,
+			nonce_x,
+			nonce_parity: if odd { -Scalar::ONE } else { Scalar::ONE },
+			e,
+		}
+	}
+
+	///s_i = k1 + b*k2 + e*a_i*x_i, with nonces and secret negated as the
+	///parities of R and the aggregate key ask
+	pub fn partial_sign(&self, keypair: &Bip340Keypair, nonce: SecretNonce) -> Result<PartialSignature> {
+		if nonce.public_key != *keypair.public_key() {
+			return Err(CryptoError::InvalidKey("Nonce was generated for another key".to_string()));
+		}
+		let coefficient = self.context.coefficient(keypair.public_key())
+			.ok_or_else(|| CryptoError::InvalidKey("Key is not part of the aggregate".to_string()))?;
+
+		let nonces = (nonce.k1 + self.b * nonce.k2) * self.nonce_parity;
+		Ok(PartialSignature(nonces + self.e * coefficient * self.context.parity() * keypair.secret()))
+	}
+
+	///check one signer's partial signature against the nonce it published,
+	///so a bad share can be blamed on its signer
+	pub fn verify_partial(&self, partial: &PartialSignature, public_key: &XOnlyPublicKey, nonce: &PublicNonce) -> bool {
+		let Some(coefficient) = self.context.coefficient(public_key) else {
+			return false;
+		};
+		let nonce_point = (nonce.r1 + nonce.r2 * self.b) * self.nonce_parity;
+		let expected = nonce_point + public_key.point() * (self.e * coefficient * self.context.parity());
+		ProjectivePoint::GENERATOR * partial.0 == expected
+	}
+
+	///add the partial signatures up into a BIP-340 signature under the aggregate key
+	pub fn aggregate(&self, partials: &[PartialSignature]) -> Bip340Signature {
+		let s = partials.iter().fold(Scalar::ZERO, |sum, partial| sum + partial.0);
+		Bip340Signature { r: self.nonce_x, s: s.to_bytes().into() }
+	}
+}
@@ -0,0 +1,198 @@
+use k256::elliptic_curve::ops::Reduce;
+use k256::elliptic_curve::point::AffineCoordinates;
+use k256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use k256::schnorr::{SigningKey, VerifyingKey};
+use k256::{AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar, U256};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{CryptoError, Result};
+
+
+/// Tagged hash of BIP-340: SHA256(SHA256(tag) || SHA256(tag) || data...)
+pub(crate) fn tagged_hash(tag: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+	let tag_hash = Sha256::digest(tag);
+	let mut hasher = Sha256::new();
+	hasher.update(tag_hash);
+	hasher.update(tag_hash);
+	for part in parts {
+		hasher.update(part);
+	}
+	hasher.finalize().into()
+}
+
+/// Tagged hash reduced to a scalar
+pub(crate) fn tagged_scalar(tag: &[u8], parts: &[&[u8]]) -> Scalar {
+	<Scalar as Reduce<U256>>::reduce_bytes(&FieldBytes::from(tagged_hash(tag, parts)))
+}
+
+/// BIP-340 challenge e = H("BIP0340/challenge", R.x || P.x || m)
+pub(crate) fn challenge(nonce_x: &[u8; 32], public_key: &XOnlyPublicKey, message: &[u8]) -> Scalar {
+	tagged_scalar(b"BIP0340/challenge", &[nonce_x, public_key.as_bytes(), message])
+}
+
+/// x coordinate of a point, and whether its y is odd
+pub(crate) fn x_and_parity(point: &ProjectivePoint) -> ([u8; 32], bool) {
+	let affine = point.to_affine();
+	(affine.x().into(), affine.y_is_odd().into())
+}
+
+/// Compressed encoding of a point
+pub(crate) fn encode_point(point: &ProjectivePoint) -> [u8; 33] {
+	let mut bytes = [0u8; 33];
+	//the identity encodes as a single zero byte; it stays all zeroes here
+	let encoded = point.to_affine().to_encoded_point(true);
+	bytes[..encoded.len()].copy_from_slice(encoded.as_bytes());
+	bytes
+}
+
+/// Parse a compressed point, refusing the identity
+pub(crate) fn decode_point(bytes: &[u8]) -> Result<ProjectivePoint> {
+	let invalid = || CryptoError::InvalidKey("Invalid curve point".to_string());
+	let encoded = EncodedPoint::from_bytes(bytes).map_err(|_| invalid())?;
+	Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded))
+		.map(ProjectivePoint::from)
+		.filter(|point| *point != ProjectivePoint::IDENTITY)
+		.ok_or_else(invalid)
+}
+
+
+///BIP-340 public key: the x coordinate of a point whose y is even
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct XOnlyPublicKey([u8; 32]);
+
+impl XOnlyPublicKey {
+	///parse and validate an x-only key
+	pub fn from_bytes(bytes: [u8; 32]) -> Result<Self> {
+		VerifyingKey::from_bytes(&bytes)
+			.map_err(|_| CryptoError::InvalidKey("Invalid x-only public key".to_string()))?;
+		Ok(Self(bytes))
+	}
+
+	///key of an even-y point; None for the identity
+	pub(crate) fn from_point(point: &ProjectivePoint) -> Option<Self> {
+		if *point == ProjectivePoint::IDENTITY {
+			return None;
+		}
+		Some(Self(x_and_parity(point).0))
+	}
+
+	pub fn as_bytes(&self) -> &[u8; 32] {
+		&self.0
+	}
+
+	///the even-y point of the key
+	pub(crate) fn point(&self) -> ProjectivePoint {
+		let key = VerifyingKey::from_bytes(&self.0).expect("x-only keys are validated when built");
+		ProjectivePoint::from(*key.as_affine())
+	}
+
+	///verify a BIP-340 signature over a message
+	pub fn verify(&self, message: &[u8], signature: &Bip340Signature) -> bool {
+		signature.verify(self, message).is_ok()
+	}
+}
+
+
+///BIP-340 key pair over secp256k1. The secret is negated if need be so the
+///public key has an even y, as BIP-340 signing does
+#[derive(Clone)]
+pub struct Bip340Keypair {
+	signing_key: SigningKey,
+	public: XOnlyPublicKey,
+}
+
+impl Bip340Keypair {
+	///generate a new random key pair
+	pub fn generate() -> Self {
+		Self::from_signing_key(SigningKey::random(&mut OsRng))
+	}
+
+	///create a key pair from a 32-byte big-endian secret (non-zero, below the group order)
+	pub fn from_secret_bytes(bytes: [u8; 32]) -> Result<Self> {
+		let signing_key = SigningKey::from_bytes(&bytes)
+			.map_err(|_| CryptoError::InvalidKey("Invalid BIP-340 secret key".to_string()))?;
+		Ok(Self::from_signing_key(signing_key))
+	}
+
+	fn from_signing_key(signing_key: SigningKey) -> Self {
+		let public = XOnlyPublicKey(signing_key.verifying_key().to_bytes().into());
+		Self { signing_key, public }
+	}
+
+	pub fn public_key(&self) -> &XOnlyPublicKey {
+		&self.public
+	}
+
+	///the secret, negated if need be to match the even-y public key
+	pub fn secret_bytes(&self) -> [u8; 32] {
+		self.signing_key.to_bytes().into()
+	}
+
+	pub(crate) fn secret(&self) -> Scalar {
+		*self.signing_key.as_nonzero_scalar().as_ref()
+	}
+
+	///sign a message with fresh auxiliary randomness
+	pub fn sign(&self, message: &[u8]) -> Bip340Signature {
+		let mut aux_rand = [0u8; 32];
+		OsRng.fill_bytes(&mut aux_rand);
+		self.sign_with_aux_rand(message, &aux_rand)
+	}
+
+	///sign a message with the given auxiliary randomness; the signature is
+	///deterministic in it, as BIP-340's test vectors need
+	pub fn sign_with_aux_rand(&self, message: &[u8], aux_rand: &[u8; 32]) -> Bip340Signature {
+		let signature = self.signing_key.sign_raw(message, aux_rand)
+			.expect("BIP-340 signing fails only with negligible probability");
+		Bip340Signature::from_bytes(signature.to_bytes())
+	}
+}
+
+impl std::fmt::Debug for Bip340Keypair {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Bip340Keypair").field("public", &self.public).finish()
+	}
+}
+
+
+///BIP-340 signature: the x coordinate of R, then s
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bip340Signature {
+	pub r: [u8; 32],
+	pub s: [u8; 32],
+}
+
+impl Bip340Signature {
+	pub fn from_bytes(bytes: [u8; 64]) -> Self {
+		let mut r = [0u8; 32];
+		let mut s = [0u8; 32];
+		r.copy_from_slice(&bytes[..32]);
+		s.copy_from_slice(&bytes[32..]);
+		Self { r, s }
+	}
+
+	pub fn to_bytes(&self) -> [u8; 64] {
+		let mut bytes = [0u8; 64];
+		bytes[..32].copy_from_slice(&self.r);
+		bytes[32..].copy_from_slice(&self.s);
+		bytes
+	}
+
+	pub fn from_slice(slice: &[u8]) -> Result<Self> {
+		let bytes: [u8; 64] = slice.try_into().map_err(|_| CryptoError::InvalidSignature)?;
+		Ok(Self::from_bytes(bytes))
+	}
+
+	///check the signature as BIP-340 does: R = s*G - e*P has an even y and
+	///R.x as its x coordinate
+	pub fn verify(&self, public_key: &XOnlyPublicKey, message: &[u8]) -> Result<()> {
+		let verifying_key = VerifyingKey::from_bytes(public_key.as_bytes())
+			.map_err(|_| CryptoError::InvalidKey("Invalid x-only public key".to_string()))?;
+		let signature = k256::schnorr::Signature::try_from(&self.to_bytes()[..])
+			.map_err(|_| CryptoError::InvalidSignature)?;
+		verifying_key.verify_raw(message, &signature)
+			.map_err(|_| CryptoError::InvalidSignature)
+	}
+}
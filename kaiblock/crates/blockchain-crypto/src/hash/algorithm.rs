@@ -0,0 +1,254 @@
+use super::Hash256;
+use crate::CryptoError;
+use blake2::{digest::consts::U32, Blake2b};
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+use std::fmt;
+use std::str::FromStr;
+
+/// 256-bit hash functions a chain can be configured with.
+/// Every one of them yields a [`Hash256`], so they are interchangeable
+/// wherever a hash is stored or compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// SHA-256 applied twice, as Bitcoin hashes its headers
+    DoubleSha256,
+    /// BLAKE2b with a 32-byte output
+    Blake2b256,
+    /// Keccak-256 as Ethereum uses it, i.e. without SHA-3's padding
+    Keccak256,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 4] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::DoubleSha256,
+        HashAlgorithm::Blake2b256,
+        HashAlgorithm::Keccak256,
+    ];
+
+    /// Hash `data` in one go
+    pub fn hash(self, data: &[u8]) -> Hash256 {
+        let mut hasher = self.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Hasher to feed data piece by piece
+    pub fn hasher(self) -> AnyHasher {
+        match self {
+            HashAlgorithm::Sha256 => AnyHasher::Sha256(Sha256Hasher::default()),
+            HashAlgorithm::DoubleSha256 => AnyHasher::DoubleSha256(DoubleSha256Hasher::default()),
+            HashAlgorithm::Blake2b256 => AnyHasher::Blake2b256(Blake2b256Hasher::default()),
+            HashAlgorithm::Keccak256 => AnyHasher::Keccak256(Keccak256Hasher::default()),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::DoubleSha256 => "double_sha256",
+            HashAlgorithm::Blake2b256 => "blake2b256",
+            HashAlgorithm::Keccak256 => "keccak256",
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase().replace('-', "_");
+        HashAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+            .ok_or_else(|| CryptoError::InvalidHash(format!("unknown hash algorithm: {}", s)))
+    }
+}
+
+/// Incremental hashing with a 256-bit result
+pub trait Hasher {
+    /// Feed more data
+    fn update(&mut self, data: &[u8]);
+
+    /// Hash of everything fed so far
+    fn finalize(self) -> Hash256;
+
+    /// Hash `data` in one go
+    fn digest(data: &[u8]) -> Hash256
+    where
+        Self: Default + Sized,
+    {
+        let mut hasher = Self::default();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+/// SHA-256
+#[derive(Debug, Clone, Default)]
+pub struct Sha256Hasher(sha2::Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> Hash256 {
+        Hash256::from_bytes(self.0.finalize().into())
+    }
+}
+
+/// SHA-256 of the SHA-256 of the data
+#[derive(Debug, Clone, Default)]
+pub struct DoubleSha256Hasher(sha2::Sha256);
+
+impl Hasher for DoubleSha256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> Hash256 {
+        Hash256::from_bytes(sha2::Sha256::digest(self.0.finalize()).into())
+    }
+}
+
+/// BLAKE2b-256
+#[derive(Debug, Clone, Default)]
+pub struct Blake2b256Hasher(Blake2b<U32>);
+
+impl Hasher for Blake2b256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> Hash256 {
+        Hash256::from_bytes(self.0.finalize().into())
+    }
+}
+
+/// Keccak-256
+#[derive(Debug, Clone, Default)]
+pub struct Keccak256Hasher(sha3::Keccak256);
+
+impl Hasher for Keccak256Hasher {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self) -> Hash256 {
+        Hash256::from_bytes(self.0.finalize().into())
+    }
+}
+
+/// Hasher of an algorithm picked at runtime, see [`HashAlgorithm::hasher`]
+#[derive(Debug, Clone)]
+pub enum AnyHasher {
+    Sha256(Sha256Hasher),
+    DoubleSha256(DoubleSha256Hasher),
+    Blake2b256(Blake2b256Hasher),
+    Keccak256(Keccak256Hasher),
+}
+
+impl AnyHasher {
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            AnyHasher::Sha256(_) => HashAlgorithm::Sha256,
+            AnyHasher::DoubleSha256(_) => HashAlgorithm::DoubleSha256,
+            AnyHasher::Blake2b256(_) => HashAlgorithm::Blake2b256,
+            AnyHasher::Keccak256(_) => HashAlgorithm::Keccak256,
+        }
+    }
+}
+
+impl Hasher for AnyHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            AnyHasher::Sha256(hasher) => hasher.update(data),
+            AnyHasher::DoubleSha256(hasher) => hasher.update(data),
+            AnyHasher::Blake2b256(hasher) => hasher.update(data),
+            AnyHasher::Keccak256(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> Hash256 {
+        match self {
+            AnyHasher::Sha256(hasher) => hasher.finalize(),
+            AnyHasher::DoubleSha256(hasher) => hasher.finalize(),
+            AnyHasher::Blake2b256(hasher) => hasher.finalize(),
+            AnyHasher::Keccak256(hasher) => hasher.finalize(),
+        }
+    }
+}
+
+/// BLAKE2b-256 hash
+pub fn blake2b256(data: &[u8]) -> Hash256 {
+    Blake2b256Hasher::digest(data)
+}
+
+/// Keccak-256 hash
+pub fn keccak256(data: &[u8]) -> Hash256 {
+    Keccak256Hasher::digest(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{double_sha256, sha256};
+
+    #[test]
+    fn test_known_vectors() {
+        assert_eq!(
+            blake2b256(b"").to_hex(),
+            "0e5751c026e543b2e8ab2eb06099daa1d1e5df47778f7787faab45cdf12fe3a8"
+        );
+        assert_eq!(
+            blake2b256(b"abc").to_hex(),
+            "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319"
+        );
+        assert_eq!(
+            keccak256(b"").to_hex(),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            keccak256(b"abc").to_hex(),
+            "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+        );
+    }
+
+    #[test]
+    fn test_sha_variants_match_the_plain_functions() {
+        assert_eq!(HashAlgorithm::Sha256.hash(b"hello world"), sha256(b"hello world"));
+        assert_eq!(HashAlgorithm::DoubleSha256.hash(b"hello world"), double_sha256(b"hello world"));
+    }
+
+    #[test]
+    fn test_incremental_matches_one_shot() {
+        for algorithm in HashAlgorithm::ALL {
+            let mut hasher = algorithm.hasher();
+            hasher.update(b"hello ");
+            hasher.update(b"world");
+            assert_eq!(hasher.algorithm(), algorithm);
+            assert_eq!(hasher.finalize(), algorithm.hash(b"hello world"));
+        }
+    }
+
+    #[test]
+    fn test_names_round_trip() {
+        for algorithm in HashAlgorithm::ALL {
+            assert_eq!(algorithm.to_string().parse::<HashAlgorithm>().unwrap(), algorithm);
+        }
+        assert_eq!("Blake2b256".parse::<HashAlgorithm>().unwrap(), HashAlgorithm::Blake2b256);
+        assert_eq!("double-sha256".parse::<HashAlgorithm>().unwrap(), HashAlgorithm::DoubleSha256);
+        assert!("md5".parse::<HashAlgorithm>().is_err());
+    }
+}
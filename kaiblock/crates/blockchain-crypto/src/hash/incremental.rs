@@ -0,0 +1,133 @@
+use super::{hash_combine, Hash256};
+
+/// Append-only merkle tree keeping only the peaks: for every level, the last
+/// complete subtree still waiting for a right sibling. Appending is O(log n)
+/// and memory stays O(log n) however many leaves go in, so a block builder
+/// can add transactions one at a time and ask for the root whenever it likes.
+///
+/// Roots match [`MerkleTree`](super::MerkleTree) over the same leaves,
+/// including the duplicated last node of odd levels.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IncrementalMerkleTree {
+    // peaks[level] is live while bit `level` of len is set
+    peaks: Vec<Hash256>,
+    len: usize,
+}
+
+impl IncrementalMerkleTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of leaves appended
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add a leaf after the last one
+    pub fn append(&mut self, leaf: Hash256) {
+        let mut node = leaf;
+        let mut level = 0;
+        // every set low bit is a left subtree this leaf completes
+        while self.len >> level & 1 == 1 {
+            node = hash_combine(&[self.peaks[level].as_bytes(), node.as_bytes()]);
+            level += 1;
+        }
+
+        if level == self.peaks.len() {
+            self.peaks.push(node);
+        } else {
+            self.peaks[level] = node;
+        }
+        self.len += 1;
+    }
+
+    /// Root over every leaf appended so far; None while empty
+    pub fn root(&self) -> Option<Hash256> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let depth = self.len.next_power_of_two().trailing_zeros() as usize;
+        // root of the incomplete subtree right of the peaks handled so far
+        let mut tail: Option<Hash256> = None;
+        for level in 0..depth {
+            let peak = (self.len >> level & 1 == 1).then(|| self.peaks[level]);
+            tail = match (peak, tail) {
+                (Some(left), Some(right)) => Some(hash_combine(&[left.as_bytes(), right.as_bytes()])),
+                // a lone last node on its level is paired with itself
+                (Some(node), None) | (None, Some(node)) => Some(hash_combine(&[node.as_bytes(), node.as_bytes()])),
+                (None, None) => None,
+            };
+        }
+        tail.or_else(|| self.peaks.get(depth).copied())
+    }
+}
+
+impl Extend<Hash256> for IncrementalMerkleTree {
+    fn extend<I: IntoIterator<Item = Hash256>>(&mut self, leaves: I) {
+        for leaf in leaves {
+            self.append(leaf);
+        }
+    }
+}
+
+/// Stream leaves in without collecting them first
+impl FromIterator<Hash256> for IncrementalMerkleTree {
+    fn from_iter<I: IntoIterator<Item = Hash256>>(leaves: I) -> Self {
+        let mut tree = Self::new();
+        tree.extend(leaves);
+        tree
+    }
+}
+
+/// Merkle root of `leaves` in O(log n) memory; None when there are none
+pub fn merkle_root<I: IntoIterator<Item = Hash256>>(leaves: I) -> Option<Hash256> {
+    leaves.into_iter().collect::<IncrementalMerkleTree>().root()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{sha256, MerkleTree};
+
+    fn leaves(n: usize) -> Vec<Hash256> {
+        (0..n).map(|i| sha256(&i.to_le_bytes())).collect()
+    }
+
+    #[test]
+    fn test_roots_match_the_full_tree() {
+        let mut tree = IncrementalMerkleTree::new();
+        assert_eq!(tree.root(), None);
+
+        for (count, leaf) in leaves(70).into_iter().enumerate() {
+            tree.append(leaf);
+            let full = MerkleTree::new(leaves(count + 1)).unwrap();
+            assert_eq!(tree.root(), Some(full.root()), "{} leaves", count + 1);
+        }
+        assert_eq!(tree.len(), 70);
+    }
+
+    #[test]
+    fn test_streaming_matches_appending() {
+        let leaves = leaves(1000);
+        let mut appended = IncrementalMerkleTree::new();
+        for leaf in &leaves {
+            appended.append(*leaf);
+        }
+
+        assert_eq!(merkle_root(leaves.iter().copied()), appended.root());
+        assert_eq!(merkle_root(leaves.iter().copied()), Some(MerkleTree::new(leaves).unwrap().root()));
+        assert_eq!(merkle_root(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_memory_stays_logarithmic() {
+        let tree: IncrementalMerkleTree = leaves(4097).into_iter().collect();
+        assert_eq!(tree.peaks.len(), 13);
+    }
+}
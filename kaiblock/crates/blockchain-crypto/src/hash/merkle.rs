@@ -1,23 +1,21 @@
 use super::{Hash256, hash_combine};
 use crate::{CryptoError, Result};
-use serde::{Deserialize, Serialize};
 
-/// Merkle tree for efficient verification of large datasets
+/// Proof that a leaf exists in the merkle tree; verifiable without the tree
+/// (or std), see blockchain-primitives
+pub use blockchain_primitives::{MerkleMultiproof, MerkleProof};
+
+/// Merkle tree for efficient verification of large datasets.
+/// Keeps every level for proofs; when only the root is needed,
+/// [`IncrementalMerkleTree`](super::IncrementalMerkleTree) does it in
+/// O(log n) memory.
 #[derive(Debug, Clone)]
 pub struct MerkleTree {
-    leaves: Vec<Hash256>,
+    // levels from the leaves up to the root
     nodes: Vec<Vec<Hash256>>,
     root: Hash256,
 }
 
-/// Proof that a leaf exists in the merkle tree
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MerkleProof {
-    pub leaf_index: usize,
-    pub leaf_hash: Hash256,
-    pub siblings: Vec<Hash256>,
-    pub root: Hash256,
-}
 
 impl MerkleTree {
     /// Create a new merkle tree from leaf hashes
@@ -27,38 +25,35 @@ impl MerkleTree {
         }
         
         let mut tree = MerkleTree {
-            leaves: leaves.clone(),
-            nodes: Vec::new(),
+            nodes: vec![leaves],
             root: Hash256::zero(),
         };
         
-        tree.build_tree()?;
+        tree.build_tree();
         Ok(tree)
     }
+
+    /// Create a merkle tree from any iterator of leaf hashes
+    pub fn from_leaves<I: IntoIterator<Item = Hash256>>(leaves: I) -> Result<Self> {
+        Self::new(leaves.into_iter().collect())
+    }
     
-    /// Build the merkle tree
-    fn build_tree(&mut self) -> Result<()> {
-        let mut current_level = self.leaves.clone();
-        self.nodes.push(current_level.clone());
-        
-        while current_level.len() > 1 {
-            let mut next_level = Vec::new();
-            
+    /// Build the levels above the leaves
+    fn build_tree(&mut self) {
+        while let Some(current_level) = self.nodes.last().filter(|level| level.len() > 1) {
             // Process pairs of nodes
-            for chunk in current_level.chunks(2) {
-                let left = chunk[0];
-                let right = chunk.get(1).copied().unwrap_or(left); // Duplicate last node if odd
-                
-                let combined = hash_combine(&[left.as_bytes(), right.as_bytes()]);
-                next_level.push(combined);
-            }
+            let next_level = current_level.chunks(2)
+                .map(|chunk| {
+                    let left = chunk[0];
+                    let right = chunk.get(1).copied().unwrap_or(left); // Duplicate last node if odd
+                    hash_combine(&[left.as_bytes(), right.as_bytes()])
+                })
+                .collect();
             
-            self.nodes.push(next_level.clone());
-            current_level = next_level;
+            self.nodes.push(next_level);
         }
         
-        self.root = current_level[0];
-        Ok(())
+        self.root = self.nodes[self.nodes.len() - 1][0];
     }
     
     /// Get the merkle root
@@ -68,12 +63,12 @@ impl MerkleTree {
     
     /// Get all leaf hashes
     pub fn leaves(&self) -> &[Hash256] {
-        &self.leaves
+        &self.nodes[0]
     }
     
     /// Generate a merkle proof for a specific leaf
     pub fn generate_proof(&self, leaf_index: usize) -> Result<MerkleProof> {
-        if leaf_index >= self.leaves.len() {
+        if leaf_index >= self.leaves().len() {
             return Err(CryptoError::InvalidMerkleProof);
         }
         
@@ -85,7 +80,7 @@ impl MerkleTree {
             let level_nodes = &self.nodes[level];
             
             // Find sibling
-            let sibling_index = if current_index % 2 == 0 {
+            let sibling_index = if current_index.is_multiple_of(2) {
                 // Left node, sibling is right
                 if current_index + 1 < level_nodes.len() {
                     current_index + 1
@@ -103,7 +98,7 @@ impl MerkleTree {
         
         Ok(MerkleProof {
             leaf_index,
-            leaf_hash: self.leaves[leaf_index],
+            leaf_hash: self.leaves()[leaf_index],
             siblings,
             root: self.root,
         })
@@ -111,22 +106,51 @@ impl MerkleTree {
     
     /// Verify a merkle proof
     pub fn verify_proof(proof: &MerkleProof) -> bool {
-        let mut current_hash = proof.leaf_hash;
-        let mut current_index = proof.leaf_index;
-        
-        for &sibling in &proof.siblings {
-            current_hash = if current_index % 2 == 0 {
-                // Current is left, sibling is right
-                hash_combine(&[current_hash.as_bytes(), sibling.as_bytes()])
-            } else {
-                // Current is right, sibling is left
-                hash_combine(&[sibling.as_bytes(), current_hash.as_bytes()])
-            };
-            
-            current_index /= 2;
+        proof.verify()
+    }
+
+    /// Generate one proof for several leaves. Siblings two paths share, and
+    /// nodes the proven leaves compute themselves, are left out, so it is
+    /// smaller than a proof per leaf. Duplicate indexes are proven once.
+    pub fn generate_multiproof(&self, leaf_indices: &[usize]) -> Result<MerkleMultiproof> {
+        let mut known = leaf_indices.to_vec();
+        known.sort_unstable();
+        known.dedup();
+        match known.last() {
+            Some(&last) if last < self.leaves().len() => {}
+            _ => return Err(CryptoError::InvalidMerkleProof),
         }
-        
-        current_hash == proof.root
+
+        let leaves = known.iter().map(|&index| (index, self.leaves()[index])).collect();
+        let mut hashes = Vec::new();
+        for level_nodes in &self.nodes[..self.nodes.len() - 1] {
+            for (position, &index) in known.iter().enumerate() {
+                let sibling = index ^ 1;
+                let sibling_known = if index.is_multiple_of(2) {
+                    known.get(position + 1) == Some(&sibling)
+                } else {
+                    position > 0 && known[position - 1] == sibling
+                };
+                // a last node without a sibling is paired with itself
+                if !sibling_known && sibling < level_nodes.len() {
+                    hashes.push(level_nodes[sibling]);
+                }
+            }
+            known = known.iter().map(|index| index / 2).collect();
+            known.dedup();
+        }
+
+        Ok(MerkleMultiproof {
+            leaf_count: self.leaves().len(),
+            leaves,
+            hashes,
+            root: self.root,
+        })
+    }
+
+    /// Verify a proof for several leaves
+    pub fn verify_multiproof(proof: &MerkleMultiproof) -> bool {
+        proof.verify()
     }
 }
 
@@ -190,6 +214,48 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_multiproofs_for_every_subset() {
+        for leaf_count in 1..=9usize {
+            let leaves: Vec<Hash256> = (0..leaf_count).map(|i| sha256(&i.to_le_bytes())).collect();
+            let tree = MerkleTree::new(leaves).unwrap();
+
+            for subset in 1u32..(1 << leaf_count) {
+                let indices: Vec<usize> = (0..leaf_count).filter(|i| subset >> i & 1 == 1).collect();
+                let proof = tree.generate_multiproof(&indices).unwrap();
+                assert!(MerkleTree::verify_multiproof(&proof), "{} leaves, {:?}", leaf_count, indices);
+
+                // never more hashes than separate proofs would carry
+                let separate: usize = indices.iter().map(|&i| tree.generate_proof(i).unwrap().siblings.len()).sum();
+                assert!(proof.hashes.len() <= separate);
+            }
+        }
+    }
+
+    #[test]
+    fn test_multiproof_rejects_tampering() {
+        let data: Vec<&[u8]> = vec![b"a", b"b", b"c", b"d", b"e"];
+        let tree = merkle_tree_from_data(&data).unwrap();
+        let proof = tree.generate_multiproof(&[3, 1, 1]).unwrap();
+        assert_eq!(proof.leaves.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![1, 3]);
+        assert!(MerkleTree::verify_multiproof(&proof));
+
+        let mut wrong_leaf = proof.clone();
+        wrong_leaf.leaves[1].1 = sha256(b"x");
+        assert!(!MerkleTree::verify_multiproof(&wrong_leaf));
+
+        let mut wrong_count = proof.clone();
+        wrong_count.leaf_count = 4;
+        assert!(!MerkleTree::verify_multiproof(&wrong_count));
+
+        let mut missing_hash = proof;
+        missing_hash.hashes.pop();
+        assert!(!MerkleTree::verify_multiproof(&missing_hash));
+
+        assert!(tree.generate_multiproof(&[]).is_err());
+        assert!(tree.generate_multiproof(&[5]).is_err());
+    }
+
     #[test]
     fn test_invalid_proof() {
         let data = vec![b"test1", b"test2"];
@@ -1,35 +1,21 @@
+mod algorithm;
+mod incremental;
 mod merkle;
 mod types;
 mod utils;
 
-pub use merkle::{MerkleTree, MerkleProof};
+pub use algorithm::{
+    blake2b256, keccak256, AnyHasher, Blake2b256Hasher, DoubleSha256Hasher, HashAlgorithm, Hasher,
+    Keccak256Hasher, Sha256Hasher,
+};
+pub use incremental::{merkle_root, IncrementalMerkleTree};
+pub use merkle::{MerkleMultiproof, MerkleTree, MerkleProof};
 pub use types::Hash256;
 pub use utils::*;
 
-use sha2::{Sha256, Digest};
-use crate::{CryptoError, Result};
-
-/// SHA-256 hash function wrapper
-pub fn sha256(data: &[u8]) -> Hash256 {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    Hash256::from_bytes(hasher.finalize().into())
-}
-
-/// Double SHA-256 hash (commonly used in Bitcoin)
-pub fn double_sha256(data: &[u8]) -> Hash256 {
-    let first_hash = sha256(data);
-    sha256(first_hash.as_bytes())
-}
-
-/// Hash multiple data pieces together
-pub fn hash_combine(data: &[&[u8]]) -> Hash256 {
-    let mut hasher = Sha256::new();
-    for chunk in data {
-        hasher.update(chunk);
-    }
-    Hash256::from_bytes(hasher.finalize().into())
-}
+/// SHA-256, double SHA-256 and hash_combine live in blockchain-primitives so
+/// no_std builds hash the same way
+pub use blockchain_primitives::{double_sha256, hash_combine, sha256};
 
 #[cfg(test)]
 mod tests {
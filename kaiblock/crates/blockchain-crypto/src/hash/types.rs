@@ -1,94 +1,5 @@
-use serde::{Deserialize, Serialize};
-use std::fmt;
-use crate::{CryptoError, Result};
-
-/// 256-bit hash value
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub struct Hash256([u8; 32]);
-
-impl Hash256 {
-    /// Create a new hash from 32 bytes
-    pub fn from_bytes(bytes: [u8; 32]) -> Self {
-        Self(bytes)
-    }
-    
-    /// Create a hash from a byte slice
-    pub fn from_slice(slice: &[u8]) -> Result<Self> {
-        if slice.len() != 32 {
-            return Err(CryptoError::InvalidHash(
-                format!("Expected 32 bytes, got {}", slice.len())
-            ));
-        }
-        
-        let mut bytes = [0u8; 32];
-        bytes.copy_from_slice(slice);
-        Ok(Self(bytes))
-    }
-    
-    /// Create a hash from hex string
-    pub fn from_hex(hex_str: &str) -> Result<Self> {
-        let hex_str = hex_str.strip_prefix("0x").unwrap_or(hex_str);
-        
-        let bytes = hex::decode(hex_str)
-            .map_err(|e| CryptoError::InvalidHash(format!("Invalid hex: {}", e)))?;
-        
-        Self::from_slice(&bytes)
-    }
-    
-    /// Get the underlying bytes
-    pub fn as_bytes(&self) -> &[u8; 32] {
-        &self.0
-    }
-    
-    /// Convert to byte slice
-    pub fn to_bytes(&self) -> [u8; 32] {
-        self.0
-    }
-    
-    /// Convert to hex string
-    pub fn to_hex(&self) -> String {
-        hex::encode(self.0)
-    }
-    
-    /// Convert to hex string with 0x prefix
-    pub fn to_hex_prefixed(&self) -> String {
-        format!("0x{}", self.to_hex())
-    }
-    
-    /// Create a zero hash
-    pub fn zero() -> Self {
-        Self([0u8; 32])
-    }
-    
-    /// Check if hash is zero
-    pub fn is_zero(&self) -> bool {
-        self.0.iter().all(|&b| b == 0)
-    }
-}
-
-impl Default for Hash256 {
-    fn default() -> Self {
-        Self::zero()
-    }
-}
-
-impl fmt::Display for Hash256 {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.to_hex())
-    }
-}
-
-impl From<[u8; 32]> for Hash256 {
-    fn from(bytes: [u8; 32]) -> Self {
-        Self(bytes)
-    }
-}
-
-impl AsRef<[u8]> for Hash256 {
-    fn as_ref(&self) -> &[u8] {
-        &self.0
-    }
-}
+/// 256-bit hash value, defined in blockchain-primitives so no_std builds share it
+pub use blockchain_primitives::Hash256;
 
 #[cfg(test)]
 mod tests {
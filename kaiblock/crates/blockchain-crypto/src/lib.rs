@@ -1,6 +1,9 @@
 pub mod address; 
 pub mod hash;
 pub mod signature;
+pub mod adaptor;
+pub mod bip340;
+pub mod vrf;
 
 use thiserror::Error;
 
@@ -26,7 +29,13 @@ pub enum CryptoError {
 
 pub type Result<T> = std::result::Result<T, CryptoError>;
 
+impl From<blockchain_primitives::PrimitivesError> for CryptoError {
+	fn from(e: blockchain_primitives::PrimitivesError) -> Self {
+		CryptoError::InvalidHash(e.to_string())
+	}
+}
+
 //re-export commonly used types
 pub use address::{Address, AddressType};
-pub use hash::{Hash256, MerkleTree, MerkleProof};
+pub use hash::{Hash256, HashAlgorithm, MerkleMultiproof, MerkleTree, MerkleProof};
 pub use signature::{Keypair, Publickey, Privatekey}
\ No newline at end of file
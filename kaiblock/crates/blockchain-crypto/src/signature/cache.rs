@@ -0,0 +1,284 @@
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use super::types::PublicKey;
+use super::Signature;
+
+/// Approximate memory per cached entry: the key in the entry list plus the
+/// key, slot index and table overhead in the index map
+const ENTRY_COST: usize = 32 + 32 + 8 + 24;
+
+/// Signature cache sizing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureCacheConfig {
+    /// Upper bound on memory used by cached entries
+    pub max_bytes: usize,
+    /// Independently locked shards, to keep parallel verifiers off one lock
+    pub shards: usize,
+}
+
+impl Default for SignatureCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 32 * 1024 * 1024, // 32MB
+            shards: 16,
+        }
+    }
+}
+
+/// What a lookup does with the cache
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigCacheMode {
+    /// Remember signatures that verify (mempool admission)
+    Store,
+    /// Use cached results but don't add new ones, and drop entries that hit
+    /// (block connect: a confirmed signature is not checked again)
+    Consume,
+}
+
+/// Counters for the signature cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub insertions: u64,
+    pub evictions: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+impl SignatureCacheStats {
+    /// Fraction of lookups answered from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+type CacheKey = [u8; 32];
+
+#[derive(Debug, Default)]
+struct Shard {
+    entries: Vec<CacheKey>,
+    index: HashMap<CacheKey, usize>,
+}
+
+impl Shard {
+    fn contains(&self, key: &CacheKey) -> bool {
+        self.index.contains_key(key)
+    }
+
+    // Returns whether an entry was evicted to make room
+    fn insert(&mut self, key: CacheKey, capacity: usize) -> bool {
+        if self.index.contains_key(&key) || capacity == 0 {
+            return false;
+        }
+
+        // Evict a random entry rather than the oldest: an attacker can flush
+        // an LRU by sending fresh signatures, but can't pick what gets dropped here
+        let evicted = self.entries.len() >= capacity;
+        if evicted {
+            let slot = rand::thread_rng().gen_range(0..self.entries.len());
+            self.remove_slot(slot);
+        }
+
+        self.index.insert(key, self.entries.len());
+        self.entries.push(key);
+        evicted
+    }
+
+    fn remove(&mut self, key: &CacheKey) -> bool {
+        match self.index.get(key) {
+            Some(&slot) => {
+                self.remove_slot(slot);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove_slot(&mut self, slot: usize) {
+        let key = self.entries.swap_remove(slot);
+        self.index.remove(&key);
+        if let Some(moved) = self.entries.get(slot) {
+            self.index.insert(*moved, slot);
+        }
+    }
+}
+
+/// Memory-bounded cache of signatures that verified.
+///
+/// Entries are keyed by a salted hash of (message, public key, signature), so
+/// peers can't aim collisions at one shard. Only valid signatures are stored:
+/// invalid ones cost an attacker nothing to produce and would just push out
+/// useful entries.
+#[derive(Debug)]
+pub struct SignatureCache {
+    salt: [u8; 32],
+    shards: Vec<Mutex<Shard>>,
+    shard_capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    insertions: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl SignatureCache {
+    pub fn new(config: SignatureCacheConfig) -> Self {
+        let shard_count = config.shards.max(1);
+        let mut salt = [0u8; 32];
+        OsRng.fill_bytes(&mut salt);
+
+        Self {
+            salt,
+            shards: (0..shard_count).map(|_| Mutex::new(Shard::default())).collect(),
+            shard_capacity: config.max_bytes / ENTRY_COST / shard_count,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            insertions: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Verify `signature` over `message`, answering from the cache when possible
+    pub fn verify(&self, public_key: &PublicKey, message: &[u8], signature: &Signature, mode: SigCacheMode) -> bool {
+        let key = self.key(public_key, message, signature);
+        let mut shard = self.shard(&key).lock().unwrap_or_else(|e| e.into_inner());
+
+        let hit = match mode {
+            SigCacheMode::Store => shard.contains(&key),
+            SigCacheMode::Consume => shard.remove(&key),
+        };
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        // Don't hold the shard lock through the expensive part
+        drop(shard);
+        let valid = public_key.verify(message, signature);
+
+        if valid && mode == SigCacheMode::Store {
+            let evicted = self.shard(&key).lock().unwrap_or_else(|e| e.into_inner())
+                .insert(key, self.shard_capacity);
+            self.insertions.fetch_add(1, Ordering::Relaxed);
+            if evicted {
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        valid
+    }
+
+    pub fn stats(&self) -> SignatureCacheStats {
+        SignatureCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            insertions: self.insertions.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.len(),
+            capacity: self.shard_capacity * self.shards.len(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter()
+            .map(|shard| shard.lock().unwrap_or_else(|e| e.into_inner()).entries.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn key(&self, public_key: &PublicKey, message: &[u8], signature: &Signature) -> CacheKey {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        hasher.update(public_key.to_bytes());
+        hasher.update(signature.to_bytes());
+        hasher.update(message);
+        hasher.finalize().into()
+    }
+
+    fn shard(&self, key: &CacheKey) -> &Mutex<Shard> {
+        let index = u64::from_le_bytes(key[..8].try_into().expect("8 bytes")) as usize % self.shards.len();
+        &self.shards[index]
+    }
+}
+
+impl Default for SignatureCache {
+    fn default() -> Self {
+        Self::new(SignatureCacheConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signature::Keypair;
+
+    #[test]
+    fn test_valid_signature_is_cached() {
+        let cache = SignatureCache::default();
+        let keypair = Keypair::generate();
+        let signature = keypair.sign(b"message");
+
+        assert!(cache.verify(&keypair.public_key(), b"message", &signature, SigCacheMode::Store));
+        assert!(cache.verify(&keypair.public_key(), b"message", &signature, SigCacheMode::Store));
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+
+    #[test]
+    fn test_invalid_signature_is_not_cached() {
+        let cache = SignatureCache::default();
+        let keypair = Keypair::generate();
+        let signature = keypair.sign(b"message");
+
+        assert!(!cache.verify(&keypair.public_key(), b"tampered", &signature, SigCacheMode::Store));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_consume_removes_hit_and_never_stores() {
+        let cache = SignatureCache::default();
+        let keypair = Keypair::generate();
+        let signature = keypair.sign(b"message");
+
+        assert!(cache.verify(&keypair.public_key(), b"message", &signature, SigCacheMode::Consume));
+        assert!(cache.is_empty());
+
+        cache.verify(&keypair.public_key(), b"message", &signature, SigCacheMode::Store);
+        assert!(cache.verify(&keypair.public_key(), b"message", &signature, SigCacheMode::Consume));
+        assert!(cache.is_empty());
+        assert_eq!(cache.stats().hits, 1);
+    }
+
+    #[test]
+    fn test_memory_bound_is_enforced() {
+        let cache = SignatureCache::new(SignatureCacheConfig { max_bytes: ENTRY_COST * 8, shards: 2 });
+        let keypair = Keypair::generate();
+
+        for i in 0u32..50 {
+            let message = i.to_le_bytes();
+            let signature = keypair.sign(&message);
+            assert!(cache.verify(&keypair.public_key(), &message, &signature, SigCacheMode::Store));
+        }
+
+        let stats = cache.stats();
+        assert_eq!(stats.capacity, 8);
+        assert!(stats.entries <= 8);
+        assert_eq!(stats.insertions, 50);
+        assert!(stats.evictions >= 42);
+    }
+}
@@ -0,0 +1,129 @@
+use super::{Keypair, PublicKey, Signature};
+use crate::address::Address;
+use crate::hash::{sha256, Hash256};
+use crate::{CryptoError, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Prefix of every signed message. Transactions are signed over their hash
+/// with no prefix, so a message signature can never be replayed as a
+/// transaction signature and vice versa.
+pub const MESSAGE_DOMAIN: &[u8] = b"KaiBlock Signed Message:\n";
+
+/// Encoded length: 32-byte public key followed by the 64-byte signature
+pub const MESSAGE_SIGNATURE_LEN: usize = 96;
+
+/// Hash that is actually signed: H(domain || message length || message)
+pub fn message_hash(message: &[u8]) -> Hash256 {
+    let mut data = Vec::with_capacity(MESSAGE_DOMAIN.len() + 8 + message.len());
+    data.extend_from_slice(MESSAGE_DOMAIN);
+    data.extend_from_slice(&(message.len() as u64).to_le_bytes());
+    data.extend_from_slice(message);
+    sha256(&data)
+}
+
+/// Signature proving control of an address.
+///
+/// Ed25519 can't recover a public key from a signature, so the key travels
+/// with it; verification recovers the address by hashing that key. Encoded as
+/// base58 of the 96 raw bytes, so it is compact and copy-paste safe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageSignature {
+    pub public_key: PublicKey,
+    pub signature: Signature,
+}
+
+impl MessageSignature {
+    /// Sign `message` with `keypair`
+    pub fn sign(keypair: &Keypair, message: &[u8]) -> Self {
+        Self {
+            public_key: keypair.public_key().clone(),
+            signature: keypair.sign(message_hash(message).as_bytes()),
+        }
+    }
+
+    /// Address of the key that made the signature, in the format of `like`
+    pub fn recover_address(&self, message: &[u8], like: &Address) -> Result<Address> {
+        if !self.public_key.verify(message_hash(message).as_bytes(), &self.signature) {
+            return Err(CryptoError::InvalidSignature);
+        }
+        Ok(Address::from_public_key(&self.public_key, like.address_type()))
+    }
+
+    pub fn to_bytes(&self) -> [u8; MESSAGE_SIGNATURE_LEN] {
+        let mut bytes = [0u8; MESSAGE_SIGNATURE_LEN];
+        bytes[..32].copy_from_slice(&self.public_key.to_bytes());
+        bytes[32..].copy_from_slice(self.signature.as_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != MESSAGE_SIGNATURE_LEN {
+            return Err(CryptoError::SerializationError(
+                format!("message signature must be {} bytes, got {}", MESSAGE_SIGNATURE_LEN, bytes.len())
+            ));
+        }
+        Ok(Self {
+            public_key: PublicKey::from_bytes(&bytes[..32])?,
+            signature: Signature::from_slice(&bytes[32..])?,
+        })
+    }
+}
+
+impl fmt::Display for MessageSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", bs58::encode(self.to_bytes()).into_string())
+    }
+}
+
+impl FromStr for MessageSignature {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let bytes = bs58::decode(s.trim()).into_vec()
+            .map_err(|e| CryptoError::SerializationError(format!("invalid base58: {}", e)))?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+
+/// Check that `signature` (as produced by [`MessageSignature`]'s `Display`)
+/// signs `message` with the key behind `address`. Needs no wallet.
+pub fn verify_message(address: &Address, message: &[u8], signature: &str) -> bool {
+    signature.parse::<MessageSignature>()
+        .and_then(|signature| signature.recover_address(message, address))
+        .is_ok_and(|recovered| recovered.data() == address.data())
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::AddressType;
+
+    #[test]
+    fn test_sign_and_verify_message() {
+        let keypair = Keypair::generate();
+        let address = Address::from_public_key(&keypair.public_key(), AddressType::Base58);
+
+        let signature = MessageSignature::sign(&keypair, b"I own this address").to_string();
+        assert!(verify_message(&address, b"I own this address", &signature));
+        assert!(!verify_message(&address, b"I own that address", &signature));
+
+        let other = Address::from_public_key(&Keypair::generate().public_key(), AddressType::Base58);
+        assert!(!verify_message(&other, b"I own this address", &signature));
+        assert!(!verify_message(&address, b"I own this address", "not a signature"));
+    }
+
+    #[test]
+    fn test_message_signature_is_not_a_transaction_signature() {
+        let keypair = Keypair::generate();
+        let signature = MessageSignature::sign(&keypair, b"payload");
+
+        // the raw bytes alone don't verify; only the domain-separated hash does
+        assert!(!keypair.verify(b"payload", &signature.signature));
+        assert!(!keypair.verify(sha256(b"payload").as_bytes(), &signature.signature));
+        assert_eq!(signature.to_string().parse::<MessageSignature>().unwrap(), signature);
+    }
+}
@@ -1,8 +1,12 @@
+mod cache;
 mod keypair;
+mod message;
 mod signature;
 mod types;
 
+pub use cache::{SignatureCache, SignatureCacheConfig, SignatureCacheStats, SigCacheMode};
 pub use keypair::Keypair;
+pub use message::{message_hash, verify_message, MessageSignature, MESSAGE_DOMAIN, MESSAGE_SIGNATURE_LEN};
 pub use signature::Signature;
 pub use types::{Publickey, Privatekey};
 
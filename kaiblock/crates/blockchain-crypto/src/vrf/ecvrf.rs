@@ -0,0 +1,233 @@
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT as B;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::{clamp_integer, Scalar};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha512};
+use crate::{CryptoError, Result};
+use super::hash_to_curve::{encode_to_curve, SUITE};
+
+
+/// Bytes of the challenge in a proof
+const CHALLENGE_LEN: usize = 16;
+
+/// Length of an encoded proof: Gamma, the challenge and the response
+pub const PROOF_LEN: usize = 32 + CHALLENGE_LEN + 32;
+
+
+/// Challenge c = SHA-512(suite || 0x02 || Y || H || Gamma || U || V || 0x00), truncated
+fn challenge(points: [&EdwardsPoint; 5]) -> [u8; CHALLENGE_LEN] {
+	let mut hasher = Sha512::new().chain_update([SUITE, 0x02]);
+	for point in points {
+		hasher.update(point.compress().as_bytes());
+	}
+	let hash = hasher.chain_update([0x00]).finalize();
+	let mut c = [0u8; CHALLENGE_LEN];
+	c.copy_from_slice(&hash[..CHALLENGE_LEN]);
+	c
+}
+
+fn challenge_scalar(c: &[u8; CHALLENGE_LEN]) -> Scalar {
+	let mut bytes = [0u8; 32];
+	bytes[..CHALLENGE_LEN].copy_from_slice(c);
+	Scalar::from_bytes_mod_order(bytes)
+}
+
+
+///VRF public key: a compressed edwards25519 point, as an Ed25519 public key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VrfPublicKey([u8; 32]);
+
+impl VrfPublicKey {
+	///parse a public key, refusing points of small order, which would let
+	///one proof verify for several outputs
+	pub fn from_bytes(bytes: [u8; 32]) -> Result<Self> {
+		Self::decode(&bytes)?;
+		Ok(Self(bytes))
+	}
+
+	fn decode(bytes: &[u8; 32]) -> Result<EdwardsPoint> {
+		CompressedEdwardsY(*bytes).decompress()
+			.filter(|point| !point.is_small_order())
+			.ok_or_else(|| CryptoError::InvalidKey("Invalid VRF public key".to_string()))
+	}
+
+	pub fn as_bytes(&self) -> &[u8; 32] {
+		&self.0
+	}
+
+	///verify `proof` was made over `seed` by this key's holder, and return
+	///its output
+	pub fn verify(&self, seed: &[u8], proof: &VrfProof) -> Result<VrfOutput> {
+		let y = Self::decode(&self.0)?;
+		let gamma = CompressedEdwardsY(proof.gamma).decompress().ok_or(CryptoError::InvalidSignature)?;
+		let s = Option::<Scalar>::from(Scalar::from_canonical_bytes(proof.response)).ok_or(CryptoError::InvalidSignature)?;
+		let c = challenge_scalar(&proof.challenge);
+
+		let h = encode_to_curve(&self.0, seed);
+		let u = B * s - y * c;
+		let v = h * s - gamma * c;
+		if challenge([&y, &h, &gamma, &u, &v]) != proof.challenge {
+			return Err(CryptoError::InvalidSignature);
+		}
+		Ok(VrfOutput::from_gamma(&gamma))
+	}
+}
+
+
+///VRF key pair (ECVRF-EDWARDS25519-SHA512-TAI of RFC 9381). The secret is
+///an Ed25519 secret key, expanded the same way
+#[derive(Clone)]
+pub struct VrfKeypair {
+	secret: [u8; 32],
+	scalar: Scalar,
+	//second half of the expanded secret, keying nonces
+	nonce_key: [u8; 32],
+	public: VrfPublicKey,
+}
+
+impl VrfKeypair {
+	///generate a new random key pair
+	pub fn generate() -> Self {
+		let mut secret = [0u8; 32];
+		OsRng.fill_bytes(&mut secret);
+		Self::from_secret_bytes(secret)
+	}
+
+	///create a key pair from a 32-byte secret key
+	pub fn from_secret_bytes(secret: [u8; 32]) -> Self {
+		let expanded = Sha512::digest(secret);
+		let mut scalar_bytes = [0u8; 32];
+		let mut nonce_key = [0u8; 32];
+		scalar_bytes.copy_from_slice(&expanded[..32]);
+		nonce_key.copy_from_slice(&expanded[32..]);
+
+		let scalar = Scalar::from_bytes_mod_order(clamp_integer(scalar_bytes));
+		let public = VrfPublicKey((B * scalar).compress().to_bytes());
+		Self { secret, scalar, nonce_key, public }
+	}
+
+	pub fn public_key(&self) -> &VrfPublicKey {
+		&self.public
+	}
+
+	pub fn secret_bytes(&self) -> [u8; 32] {
+		self.secret
+	}
+
+	///evaluate the VRF on `seed`: the output, and a proof anyone with the
+	///public key can check it with. proving is deterministic, so a key has
+	///one output per seed
+	pub fn prove(&self, seed: &[u8]) -> (VrfOutput, VrfProof) {
+		let y = B * self.scalar;
+		let h = encode_to_curve(&self.public.0, seed);
+		let gamma = h * self.scalar;
+
+		let nonce_hash = Sha512::new()
+			.chain_update(self.nonce_key)
+			.chain_update(h.compress().as_bytes())
+			.finalize();
+		let k = Scalar::from_bytes_mod_order_wide(&nonce_hash.into());
+		let c = challenge([&y, &h, &gamma, &(B * k), &(h * k)]);
+		let s = k + challenge_scalar(&c) * self.scalar;
+
+		let proof = VrfProof {
+			gamma: gamma.compress().to_bytes(),
+			challenge: c,
+			response: s.to_bytes(),
+		};
+		(VrfOutput::from_gamma(&gamma), proof)
+	}
+}
+
+impl std::fmt::Debug for VrfKeypair {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("VrfKeypair").field("public", &self.public).finish()
+	}
+}
+
+
+///Proof that a VRF output was computed with a key's secret over a seed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VrfProof {
+	gamma: [u8; 32],
+	challenge: [u8; CHALLENGE_LEN],
+	response: [u8; 32],
+}
+
+impl VrfProof {
+	///the RFC 9381 encoding: Gamma, then c and s, little-endian
+	pub fn to_bytes(&self) -> [u8; PROOF_LEN] {
+		let mut bytes = [0u8; PROOF_LEN];
+		bytes[..32].copy_from_slice(&self.gamma);
+		bytes[32..32 + CHALLENGE_LEN].copy_from_slice(&self.challenge);
+		bytes[32 + CHALLENGE_LEN..].copy_from_slice(&self.response);
+		bytes
+	}
+
+	pub fn from_bytes(bytes: &[u8; PROOF_LEN]) -> Self {
+		let mut proof = Self { gamma: [0u8; 32], challenge: [0u8; CHALLENGE_LEN], response: [0u8; 32] };
+		proof.gamma.copy_from_slice(&bytes[..32]);
+		proof.challenge.copy_from_slice(&bytes[32..32 + CHALLENGE_LEN]);
+		proof.response.copy_from_slice(&bytes[32 + CHALLENGE_LEN..]);
+		proof
+	}
+
+	pub fn from_slice(slice: &[u8]) -> Result<Self> {
+		let bytes: &[u8; PROOF_LEN] = slice.try_into().map_err(|_| CryptoError::InvalidSignature)?;
+		Ok(Self::from_bytes(bytes))
+	}
+}
+
+
+///VRF output (beta): 64 bytes, unpredictable without the secret key, and
+///unique for a key and seed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VrfOutput([u8; 64]);
+
+impl VrfOutput {
+	//beta = SHA-512(suite || 0x03 || cofactor * Gamma || 0x00)
+	fn from_gamma(gamma: &EdwardsPoint) -> Self {
+		let hash = Sha512::new()
+			.chain_update([SUITE, 0x03])
+			.chain_update(gamma.mul_by_cofactor().compress().as_bytes())
+			.chain_update([0x00])
+			.finalize();
+		Self(hash.into())
+	}
+
+	pub fn as_bytes(&self) -> &[u8; 64] {
+		&self.0
+	}
+
+	///the first 32 bytes, as a randomness seed (e.g. to pick a slot leader)
+	pub fn randomness(&self) -> [u8; 32] {
+		let mut seed = [0u8; 32];
+		seed.copy_from_slice(&self.0[..32]);
+		seed
+	}
+}
+
+//serde derives stop at 32-byte arrays
+impl Serialize for VrfOutput {
+	fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+		serializer.serialize_bytes(&self.0)
+	}
+}
+
+impl<'de> Deserialize<'de> for VrfOutput {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+		let bytes = Vec::<u8>::deserialize(deserializer)?;
+		let bytes: [u8; 64] = bytes.try_into()
+			.map_err(|bytes: Vec<u8>| D::Error::invalid_length(bytes.len(), &"64 bytes"))?;
+		Ok(Self(bytes))
+	}
+}
+
+
+///Verify a VRF proof over `seed` under `public_key`, returning its output
+pub fn verify(public_key: &VrfPublicKey, seed: &[u8], proof: &VrfProof) -> Result<VrfOutput> {
+	public_key.verify(seed, proof)
+}
@@ -0,0 +1,39 @@
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use sha2::{Digest, Sha512};
+
+
+/// Suite string of ECVRF-EDWARDS25519-SHA512-TAI (RFC 9381)
+pub(crate) const SUITE: u8 = 0x03;
+
+
+/// Hash `data` to a point of the prime-order subgroup of edwards25519, by
+/// try-and-increment as RFC 9381 does: the first counter whose
+/// SHA-512(suite || 0x01 || salt || data || counter || 0x00) starts with a
+/// valid point encoding wins, and that point is multiplied by the cofactor.
+/// Nobody learns the point's discrete log, which is what a VRF needs of it.
+///
+/// Try-and-increment takes time depending on `data`; fine for public inputs
+/// like VRF seeds, not for secrets
+pub(crate) fn encode_to_curve(salt: &[u8], data: &[u8]) -> EdwardsPoint {
+	for counter in 0..=u8::MAX {
+		let hash = Sha512::new()
+			.chain_update([SUITE, 0x01])
+			.chain_update(salt)
+			.chain_update(data)
+			.chain_update([counter, 0x00])
+			.finalize();
+		let mut candidate = [0u8; 32];
+		candidate.copy_from_slice(&hash[..32]);
+		if let Some(point) = CompressedEdwardsY(candidate).decompress() {
+			return point.mul_by_cofactor();
+		}
+	}
+	//each try succeeds about half the time: 256 failures in a row don't happen
+	unreachable!("no counter hashed to a point")
+}
+
+///Hash `data`, under `salt` (a VRF hashes under the public key), to a
+///compressed edwards25519 point in the prime-order subgroup
+pub fn hash_to_curve(salt: &[u8], data: &[u8]) -> [u8; 32] {
+	encode_to_curve(salt, data).compress().to_bytes()
+}
@@ -0,0 +1,93 @@
+//! Verifiable random function (ECVRF-EDWARDS25519-SHA512-TAI, RFC 9381).
+//!
+//! The holder of a [`VrfKeypair`] maps any seed to a pseudorandom
+//! [`VrfOutput`] and a [`VrfProof`] of it. Anyone with the public key can
+//! check the output is the one for that seed, while no one else can predict
+//! it, so a proof-of-stake engine can draw slot leaders from it without a
+//! validator being able to grind for a better draw.
+
+mod ecvrf;
+mod hash_to_curve;
+
+pub use ecvrf::{verify, VrfKeypair, VrfOutput, VrfProof, VrfPublicKey, PROOF_LEN};
+pub use hash_to_curve::hash_to_curve;
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex32(s: &str) -> [u8; 32] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_rfc9381_vector() {
+        // Example 16 of RFC 9381 (ECVRF-EDWARDS25519-SHA512-TAI, empty seed)
+        let keypair = VrfKeypair::from_secret_bytes(hex32("9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60"));
+        assert_eq!(hex::encode(keypair.public_key().as_bytes()), "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a");
+
+        let (output, proof) = keypair.prove(b"");
+        assert_eq!(
+            hex::encode(proof.to_bytes()),
+            "8657106690b5526245a92b003bb079ccd1a92130477671f6fc01ad16f26f723f26f8a57ccaed74ee1b190bed1f479d9727d2d0f9b005a6e456a35d4fb0daab1268a1b0db10836d9826a528ca76567805"
+        );
+        assert_eq!(
+            hex::encode(output.as_bytes()),
+            "90cf1df3b703cce59e2a35b925d411164068269d7b2d29f3301c03dd757876ff66b71dda49d2de59d03450451af026798e8f81cd2e333de5cdf4f3e140fdd8ae"
+        );
+        assert_eq!(verify(keypair.public_key(), b"", &proof).unwrap(), output);
+    }
+
+    #[test]
+    fn test_prove_and_verify() {
+        let keypair = VrfKeypair::generate();
+        let (output, proof) = keypair.prove(b"epoch 7 slot 3");
+
+        assert_eq!(keypair.public_key().verify(b"epoch 7 slot 3", &proof).unwrap(), output);
+        // one output per seed: proving again gives the same one
+        assert_eq!(keypair.prove(b"epoch 7 slot 3"), (output, proof));
+        assert_ne!(keypair.prove(b"epoch 7 slot 4").0, output);
+
+        assert!(verify(keypair.public_key(), b"epoch 7 slot 4", &proof).is_err());
+        assert!(verify(VrfKeypair::generate().public_key(), b"epoch 7 slot 3", &proof).is_err());
+
+        let mut tampered = proof.to_bytes();
+        tampered[40] ^= 1;
+        assert!(verify(keypair.public_key(), b"epoch 7 slot 3", &VrfProof::from_bytes(&tampered)).is_err());
+        assert_eq!(VrfProof::from_slice(&proof.to_bytes()).unwrap(), proof);
+        assert!(VrfProof::from_slice(&[0u8; 79]).is_err());
+    }
+
+    #[test]
+    fn test_small_order_keys_are_refused() {
+        // the identity, and a point of order 2
+        let mut identity = [0u8; 32];
+        identity[0] = 1;
+        assert!(VrfPublicKey::from_bytes(identity).is_err());
+        let mut order_two = [0xffu8; 32];
+        order_two[0] = 0xec;
+        order_two[31] = 0x7f;
+        assert!(VrfPublicKey::from_bytes(order_two).is_err());
+    }
+
+    #[test]
+    fn test_hash_to_curve_is_deterministic_and_salted() {
+        let point = hash_to_curve(b"salt", b"data");
+        assert_eq!(hash_to_curve(b"salt", b"data"), point);
+        assert_ne!(hash_to_curve(b"other salt", b"data"), point);
+        assert_ne!(hash_to_curve(b"salt", b"other data"), point);
+    }
+
+    #[test]
+    fn test_serde_roundtrip() {
+        let keypair = VrfKeypair::generate();
+        let (output, proof) = keypair.prove(b"seed");
+
+        let json = serde_json::to_string(&(keypair.public_key(), output, proof)).unwrap();
+        let decoded: (VrfPublicKey, VrfOutput, VrfProof) = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, (*keypair.public_key(), output, proof));
+        assert!(serde_json::from_str::<VrfOutput>("[1, 2, 3]").is_err());
+    }
+}
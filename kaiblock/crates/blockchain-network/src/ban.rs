@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use blockchain_core::BlockchainError;
+use crate::bandwidth::TokenBucket;
+
+
+/// When misbehaving peers get banned
+#[derive(Debug, Clone)]
+pub struct BanConfig {
+    /// score at which a peer is banned
+    pub threshold: u32,
+    pub ban_duration: Duration,
+    /// one point of a peer's score is forgiven each interval, so occasional
+    /// honest mistakes don't add up to a ban
+    pub score_decay: Duration,
+    /// messages accepted from one peer per second, in bursts of up to a
+    /// second's worth; the rest are dropped (`None`: unlimited)
+    pub max_messages_per_second: Option<u64>,
+}
+
+impl Default for BanConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 100,
+            ban_duration: Duration::from_secs(24 * 60 * 60),
+            score_decay: Duration::from_secs(60),
+            max_messages_per_second: Some(200),
+        }
+    }
+}
+
+
+/// Something a peer did wrong, weighted by how unlikely an honest peer is to do it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Misbehavior {
+    /// relayed a block whose header proof (PoW target or PoS signature) is invalid;
+    /// costs the sender nothing to make, so one is enough for a ban
+    InvalidBlockProof,
+    /// relayed a block with a valid proof whose body doesn't match the header
+    MismatchedBlockBody,
+    /// sent bytes that don't decode as the message they claim to be
+    MalformedMessage,
+    /// relayed a transaction that can never be valid, e.g. a bad signature
+    InvalidTransaction,
+    /// relayed a double-spend proof that doesn't check out
+    InvalidDoubleSpendProof,
+    /// sent a message beyond its rate limit
+    MessageFlood,
+    /// sent something other than Version or VerAck before the handshake completed
+    MessageBeforeHandshake,
+    /// answered a sync request with headers that don't connect to our chain,
+    /// miss their proof of work or contradict a checkpoint
+    InvalidHeaders,
+    /// served a sync block, matching the header we asked for, that the chain rejected
+    InvalidBlock,
+}
+
+impl Misbehavior {
+    pub fn score(&self) -> u32 {
+        match self {
+            Misbehavior::InvalidBlockProof => 100,
+            Misbehavior::MismatchedBlockBody => 20,
+            Misbehavior::MalformedMessage => 10,
+            Misbehavior::InvalidTransaction => 10,
+            Misbehavior::InvalidDoubleSpendProof => 10,
+            Misbehavior::MessageFlood => 5,
+            Misbehavior::MessageBeforeHandshake => 10,
+            Misbehavior::InvalidHeaders => 50,
+            Misbehavior::InvalidBlock => 100,
+        }
+    }
+
+    /// Misbehavior of relaying a transaction the chain rejected with `error`.
+    /// Rejections an honest peer can run into, like a spend that lost a race
+    /// or a full mempool, don't count
+    pub fn for_rejected_transaction(error: &BlockchainError) -> Option<Self> {
+        match error {
+            BlockchainError::InvalidTransaction(_)
+            | BlockchainError::CryptoError(_)
+            | BlockchainError::ValidationError(_)
+            | BlockchainError::SerializationError(_) => Some(Misbehavior::InvalidTransaction),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+struct Score {
+    points: u32,
+    /// when decay was last applied
+    updated: Instant,
+}
+
+impl Score {
+    // forgive a point per `interval` since the last update
+    fn decay(&mut self, interval: Duration) {
+        let now = Instant::now();
+        let intervals = match now.duration_since(self.updated).as_nanos().checked_div(interval.as_nanos()) {
+            Some(0) => return,
+            Some(intervals) => u32::try_from(intervals).unwrap_or(u32::MAX),
+            None => u32::MAX, // no decay interval: scores don't stick at all
+        };
+        if intervals >= self.points {
+            self.points = 0;
+            self.updated = now;
+        } else {
+            self.points -= intervals;
+            // keep the partial interval, so frequent checks don't stall decay
+            self.updated += interval * intervals;
+        }
+    }
+}
+
+
+#[derive(Debug, Default)]
+struct BanState {
+    scores: HashMap<IpAddr, Score>,
+    banned_until: HashMap<IpAddr, Instant>,
+    /// message allowance per peer, when rate limited
+    messages: HashMap<IpAddr, TokenBucket>,
+}
+
+
+/// Misbehavior scores per peer address; a peer reaching the threshold is
+/// disconnected and refused until its ban expires. Scores decay over time,
+/// and each peer's message rate is capped (`allow_message`).
+///
+/// Keyed by IP rather than socket address, so reconnecting from another port
+/// doesn't reset the score.
+#[derive(Debug, Default)]
+pub struct BanManager {
+    config: BanConfig,
+    state: Mutex<BanState>,
+}
+
+impl BanManager {
+    pub fn new(config: BanConfig) -> Self {
+        Self { config, state: Mutex::new(BanState::default()) }
+    }
+
+    /// Add `misbehavior` to the peer's score; returns true if that bans it
+    pub fn penalize(&self, peer: IpAddr, misbehavior: Misbehavior) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let score = state.scores.entry(peer).or_insert(Score { points: 0, updated: Instant::now() });
+        score.decay(self.config.score_decay);
+        score.points = score.points.saturating_add(misbehavior.score());
+        if score.points < self.config.threshold {
+            return false;
+        }
+
+        state.scores.remove(&peer);
+        state.messages.remove(&peer);
+        state.banned_until.insert(peer, Instant::now() + self.config.ban_duration);
+        true
+    }
+
+    /// Take one message from the peer's allowance; false when it is sending
+    /// faster than `max_messages_per_second` and the message should be dropped
+    pub fn allow_message(&self, peer: IpAddr) -> bool {
+        let Some(rate) = self.config.max_messages_per_second else { return true };
+        let mut state = self.state.lock().unwrap();
+        state.messages.entry(peer)
+            .or_insert_with(|| TokenBucket::new(rate))
+            .consume(1)
+            .is_zero()
+    }
+
+    pub fn is_banned(&self, peer: IpAddr) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.banned_until.get(&peer) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                state.banned_until.remove(&peer);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn score(&self, peer: IpAddr) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        match state.scores.get_mut(&peer) {
+            Some(score) => {
+                score.decay(self.config.score_decay);
+                score.points
+            }
+            None => 0,
+        }
+    }
+
+    /// Peers currently banned
+    pub fn banned(&self) -> Vec<IpAddr> {
+        let now = Instant::now();
+        self.state.lock().unwrap().banned_until.iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+
+    pub fn unban(&self, peer: IpAddr) {
+        self.state.lock().unwrap().banned_until.remove(&peer);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const OTHER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    fn config(ban_duration: Duration) -> BanConfig {
+        BanConfig { ban_duration, score_decay: Duration::from_secs(3600), ..BanConfig::default() }
+    }
+
+    #[test]
+    fn test_score_adds_up_to_a_ban() {
+        let bans = BanManager::new(config(Duration::from_secs(60)));
+        for _ in 0..9 {
+            assert!(!bans.penalize(PEER, Misbehavior::MalformedMessage));
+        }
+        assert_eq!(bans.score(PEER), 90);
+        assert!(!bans.is_banned(PEER));
+
+        assert!(bans.penalize(PEER, Misbehavior::MalformedMessage));
+        assert!(bans.is_banned(PEER));
+        assert!(!bans.is_banned(OTHER));
+        assert_eq!(bans.banned(), vec![PEER]);
+        // the score starts over once the ban is in place
+        assert_eq!(bans.score(PEER), 0);
+    }
+
+    #[test]
+    fn test_ban_expires() {
+        let bans = BanManager::new(config(Duration::from_millis(50)));
+        assert!(bans.penalize(PEER, Misbehavior::InvalidBlockProof));
+        assert!(bans.is_banned(PEER));
+
+        std::thread::sleep(Duration::from_millis(80));
+        assert!(bans.banned().is_empty());
+        assert!(!bans.is_banned(PEER));
+    }
+
+    #[test]
+    fn test_unban_lifts_a_ban_early() {
+        let bans = BanManager::new(config(Duration::from_secs(60)));
+        bans.penalize(PEER, Misbehavior::InvalidBlockProof);
+        bans.unban(PEER);
+        assert!(!bans.is_banned(PEER));
+    }
+
+    #[test]
+    fn test_score_decays() {
+        let bans = BanManager::new(BanConfig { score_decay: Duration::from_millis(20), ..BanConfig::default() });
+        bans.penalize(PEER, Misbehavior::MessageFlood);
+        assert!(bans.score(PEER) <= 5);
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert_eq!(bans.score(PEER), 0);
+    }
+
+    #[test]
+    fn test_message_rate_limit() {
+        let bans = BanManager::new(BanConfig { max_messages_per_second: Some(3), ..BanConfig::default() });
+        assert!((0..3).all(|_| bans.allow_message(PEER)));
+        assert!(!bans.allow_message(PEER));
+        // each peer has its own allowance
+        assert!(bans.allow_message(OTHER));
+
+        let unlimited = BanManager::new(BanConfig { max_messages_per_second: None, ..BanConfig::default() });
+        assert!((0..1000).all(|_| unlimited.allow_message(PEER)));
+    }
+
+    #[test]
+    fn test_honest_rejections_are_not_misbehavior() {
+        let invalid = BlockchainError::InvalidTransaction("bad signature".to_string());
+        assert_eq!(Misbehavior::for_rejected_transaction(&invalid), Some(Misbehavior::InvalidTransaction));
+
+        let missing = BlockchainError::BlockNotFound("parent".to_string());
+        assert_eq!(Misbehavior::for_rejected_transaction(&missing), None);
+    }
+}
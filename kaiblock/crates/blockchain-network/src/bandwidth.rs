@@ -0,0 +1,253 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::MessageType;
+
+
+/// Bandwidth caps in bytes per second. `None` means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct BandwidthConfig {
+    /// upload cap for each peer
+    pub peer_upload: Option<u64>,
+    /// download cap for each peer
+    pub peer_download: Option<u64>,
+    /// upload cap across all peers
+    pub global_upload: Option<u64>,
+    /// download cap across all peers
+    pub global_download: Option<u64>,
+}
+
+
+/// Token bucket holding up to one second of traffic at `rate` bytes per second
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(rate: u64) -> Self {
+        Self { rate, tokens: rate as f64, last_refill: Instant::now() }
+    }
+
+    /// Take `bytes` from the bucket and return how long the caller must wait
+    /// before sending them. The bucket may go into debt, so a message larger
+    /// than the bucket is delayed instead of stuck forever.
+    pub fn consume(&mut self, bytes: u64) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 || self.rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.rate as f64)
+    }
+}
+
+
+/// Traffic counters for one direction, split by message class
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TrafficCounters {
+    pub total: u64,
+    pub by_class: BTreeMap<String, u64>,
+}
+
+impl TrafficCounters {
+    fn record(&mut self, class: MessageType, bytes: u64) {
+        self.total += bytes;
+        *self.by_class.entry(class.class_name().to_string()).or_insert(0) += bytes;
+    }
+}
+
+
+/// Bandwidth used by one peer
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PeerBandwidth {
+    pub sent: TrafficCounters,
+    pub received: TrafficCounters,
+}
+
+
+#[derive(Clone, Copy)]
+enum Direction {
+    Upload,
+    Download,
+}
+
+
+#[derive(Default)]
+struct PeerState {
+    usage: PeerBandwidth,
+    upload: Option<TokenBucket>,
+    download: Option<TokenBucket>,
+}
+
+
+/// Per-peer byte accounting plus per-peer and global throttling
+pub struct BandwidthManager {
+    config: BandwidthConfig,
+    global_upload: Option<Mutex<TokenBucket>>,
+    global_download: Option<Mutex<TokenBucket>>,
+    peers: Mutex<HashMap<String, PeerState>>,
+}
+
+impl BandwidthManager {
+    pub fn new(config: BandwidthConfig) -> Self {
+        Self {
+            global_upload: config.global_upload.map(|rate| Mutex::new(TokenBucket::new(rate))),
+            global_download: config.global_download.map(|rate| Mutex::new(TokenBucket::new(rate))),
+            peers: Mutex::new(HashMap::new()),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> &BandwidthConfig {
+        &self.config
+    }
+
+    /// Count bytes about to be sent to `peer` and wait until the caps allow it
+    pub async fn throttle_upload(&self, peer: &str, class: MessageType, bytes: usize) {
+        let delay = self.account(peer, class, bytes as u64, Direction::Upload);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Count bytes just read from `peer` and wait before reading more if over the caps.
+    /// Not reading is what pushes back on the sender.
+    pub async fn throttle_download(&self, peer: &str, class: MessageType, bytes: usize) {
+        let delay = self.account(peer, class, bytes as u64, Direction::Download);
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // Record the traffic and return the longer of the per-peer and global delays
+    fn account(&self, peer: &str, class: MessageType, bytes: u64, direction: Direction) -> Duration {
+        let mut peers = self.peers.lock().expect("bandwidth lock poisoned");
+        let state = peers.entry(peer.to_string()).or_default();
+
+        let (counters, bucket, peer_rate, global) = match direction {
+            Direction::Upload => (&mut state.usage.sent, &mut state.upload, self.config.peer_upload, &self.global_upload),
+            Direction::Download => (&mut state.usage.received, &mut state.download, self.config.peer_download, &self.global_download),
+        };
+        counters.record(class, bytes);
+
+        let peer_delay = match peer_rate {
+            Some(rate) => bucket.get_or_insert_with(|| TokenBucket::new(rate)).consume(bytes),
+            None => Duration::ZERO,
+        };
+        let global_delay = global.as_ref()
+            .map(|bucket| bucket.lock().expect("bandwidth lock poisoned").consume(bytes))
+            .unwrap_or_default();
+
+        peer_delay.max(global_delay)
+    }
+
+    /// Bandwidth used by `peer` so far
+    pub fn peer_usage(&self, peer: &str) -> PeerBandwidth {
+        self.peers.lock().expect("bandwidth lock poisoned")
+            .get(peer)
+            .map(|state| state.usage.clone())
+            .unwrap_or_default()
+    }
+
+    /// Drop the counters for a disconnected peer
+    pub fn remove_peer(&self, peer: &str) {
+        self.peers.lock().expect("bandwidth lock poisoned").remove(peer);
+    }
+}
+
+impl Default for BandwidthManager {
+    fn default() -> Self {
+        Self::new(BandwidthConfig::default())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close_to(delay: Duration, expected_ms: u64) -> bool{
+        delay.abs_diff(Duration::from_millis(expected_ms)) < Duration::from_millis(50)
+    }
+
+    #[test]
+    fn test_bucket_starts_full_then_goes_into_debt() {
+        let mut bucket = TokenBucket::new(1000);
+        assert_eq!(bucket.consume(1000), Duration::ZERO);
+
+        // half a second's worth past empty waits about half a second
+        assert!(close_to(bucket.consume(500), 500));
+        // and the debt adds up
+        assert!(close_to(bucket.consume(500), 1000));
+    }
+
+    #[test]
+    fn test_bucket_refills_at_rate_up_to_capacity() {
+        let mut bucket = TokenBucket::new(1000);
+        assert_eq!(bucket.consume(1000), Duration::ZERO);
+
+        std::thread::sleep(Duration::from_millis(300));
+        // about 300 bytes came back
+        assert_eq!(bucket.consume(200), Duration::ZERO);
+        assert!(close_to(bucket.consume(500), 400));
+
+        // refilling stops at one second of traffic
+        let mut bucket = TokenBucket::new(1000);
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(close_to(bucket.consume(1500), 500));
+    }
+
+    #[test]
+    fn test_peer_and_global_caps_throttle() {
+        let manager = BandwidthManager::new(BandwidthConfig {
+            peer_upload: Some(1000),
+            global_upload: Some(1500),
+            ..BandwidthConfig::default()
+        });
+
+        assert_eq!(manager.account("a", MessageType::Block, 1000, Direction::Upload), Duration::ZERO);
+        // b is within its own cap but the global bucket is short
+        assert!(close_to(manager.account("b", MessageType::Block, 1000, Direction::Upload), 333));
+        // a owes 500ms on its own cap and 667ms globally; the longer wait wins
+        assert!(close_to(manager.account("a", MessageType::Block, 500, Direction::Upload), 667));
+
+        // downloads are uncapped here
+        assert_eq!(manager.account("a", MessageType::Block, 1_000_000, Direction::Download), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_usage_counted_by_peer_and_class() {
+        let manager = BandwidthManager::default();
+        manager.account("a", MessageType::Block, 100, Direction::Upload);
+        manager.account("a", MessageType::Transaction, 20, Direction::Upload);
+        manager.account("a", MessageType::Transaction, 5, Direction::Download);
+
+        let usage = manager.peer_usage("a");
+        assert_eq!(usage.sent.total, 120);
+        assert_eq!(usage.sent.by_class["block"], 100);
+        assert_eq!(usage.sent.by_class["transaction"], 20);
+        assert_eq!(usage.received.total, 5);
+
+        manager.remove_peer("a");
+        assert_eq!(manager.peer_usage("a").sent.total, 0);
+    }
+
+    #[tokio::test]
+    async fn test_throttle_upload_waits_out_the_debt() {
+        let manager = BandwidthManager::new(BandwidthConfig { peer_upload: Some(1000), ..BandwidthConfig::default() });
+        manager.throttle_upload("a", MessageType::Block, 1000).await;
+
+        let start = Instant::now();
+        manager.throttle_upload("a", MessageType::Block, 200).await;
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use blockchain_core::{ConflictProof, Hash256};
+
+
+/// Limits on double-spend alert relay
+#[derive(Debug, Clone)]
+pub struct DoubleSpendRelayConfig {
+    /// alerts accepted from one peer per window; the rest are dropped unread
+    pub max_per_peer: u32,
+    /// alerts relayed in total per window, including our own
+    pub max_relayed: u32,
+    pub window: Duration,
+    /// proofs remembered so each conflict is relayed once
+    pub seen_capacity: usize,
+}
+
+impl Default for DoubleSpendRelayConfig {
+    fn default() -> Self {
+        Self {
+            max_per_peer: 10,
+            max_relayed: 50,
+            window: Duration::from_secs(60),
+            seen_capacity: 10_000,
+        }
+    }
+}
+
+
+/// Alerts counted in the current window
+#[derive(Debug, Clone, Copy)]
+struct WindowCount {
+    started: Instant,
+    count: u32,
+}
+
+impl WindowCount {
+    fn new() -> Self {
+        Self { started: Instant::now(), count: 0 }
+    }
+
+    /// Count one more if under `limit`, starting a new window when the old one is over
+    fn try_take(&mut self, limit: u32, window: Duration) -> bool {
+        if self.started.elapsed() >= window {
+            *self = Self::new();
+        }
+        if self.count >= limit {
+            return false;
+        }
+        self.count += 1;
+        true
+    }
+}
+
+
+#[derive(Debug)]
+struct RelayState {
+    seen: HashSet<Hash256>,
+    seen_order: VecDeque<Hash256>,
+    per_peer: HashMap<String, WindowCount>,
+    relayed: WindowCount,
+}
+
+
+/// Decides which double-spend proofs get relayed.
+///
+/// A proof is only forwarded once, after it verified, and within per-peer and
+/// global rate limits, so a peer can't use alerts to make us flood the network:
+/// every alert costs a signature check and is sent to all peers.
+#[derive(Debug)]
+pub struct DoubleSpendRelay {
+    config: DoubleSpendRelayConfig,
+    state: Mutex<RelayState>,
+}
+
+impl DoubleSpendRelay {
+    pub fn new(config: DoubleSpendRelayConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(RelayState {
+                seen: HashSet::new(),
+                seen_order: VecDeque::new(),
+                per_peer: HashMap::new(),
+                relayed: WindowCount::new(),
+            }),
+        }
+    }
+
+    /// Whether to look at a proof `peer` sent: it is new and the peer is
+    /// within its allowance. Call before verifying the proof.
+    pub fn accept_from(&self, peer: &str, proof: &ConflictProof) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.seen.contains(&proof.id()) {
+            return false;
+        }
+        state.per_peer.entry(peer.to_string())
+            .or_insert_with(WindowCount::new)
+            .try_take(self.config.max_per_peer, self.config.window)
+    }
+
+    /// Whether to relay a verified proof; marks it seen either way
+    pub fn should_relay(&self, proof: &ConflictProof) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let id = proof.id();
+        if !state.seen.insert(id) {
+            return false;
+        }
+        state.seen_order.push_back(id);
+        while state.seen_order.len() > self.config.seen_capacity {
+            if let Some(oldest) = state.seen_order.pop_front() {
+                state.seen.remove(&oldest);
+            }
+        }
+
+        let (limit, window) = (self.config.max_relayed, self.config.window);
+        state.relayed.try_take(limit, window)
+    }
+
+    pub fn remove_peer(&self, peer: &str) {
+        self.state.lock().unwrap().per_peer.remove(peer);
+    }
+}
+
+impl Default for DoubleSpendRelay {
+    fn default() -> Self {
+        Self::new(DoubleSpendRelayConfig::default())
+    }
+}
@@ -8,4 +8,16 @@ pub enum NetworkError{
     IoError(String),
     #[error("Peer Not Found")]
     PeerNotFound,
+    #[error("Peer Banned: {0}")]
+    PeerBanned(String),
+    #[error("Peer Limit Reached: {0}")]
+    PeerLimit(usize),
+    #[error("Sync Error: {0}")]
+    SyncError(String),
+    #[error("Connection Closed")]
+    ConnectionClosed,
+    #[error("Handshake Failed: {0}")]
+    Handshake(String),
+    #[error("Unknown Message Tag: {0}")]
+    UnknownMessage(u8),
 }
\ No newline at end of file
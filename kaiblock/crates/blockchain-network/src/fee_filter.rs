@@ -0,0 +1,55 @@
+use blockchain_core::mempool::{MempoolConfig, MempoolStats};
+use blockchain_core::transaction::Transaction;
+
+
+/// Fee-rate (fee per byte) of a transaction, computed the same way the mempool does
+pub fn transaction_fee_rate(tx: &Transaction) -> u64 {
+    let size = tx.size() as u64;
+    if size == 0 {
+        return 0;
+    }
+    tx.calculate_gas_fee() / size
+}
+
+
+/// Policy for the fee filter this node advertises to its peers.
+/// Below half of the mempool memory budget the filter stays at the relay floor;
+/// past that it rises towards the average fee-rate in the pool as it fills up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FeeFilterPolicy {
+    /// Relay floor (fee per byte) when the mempool is not under pressure
+    pub min_fee_per_byte: u64,
+    /// Mempool memory budget that pressure is measured against
+    pub max_memory: usize,
+}
+
+impl FeeFilterPolicy {
+    pub fn new(min_fee_per_byte: u64, max_memory: usize) -> Self {
+        Self { min_fee_per_byte, max_memory }
+    }
+
+    pub fn from_mempool_config(config: &MempoolConfig) -> Self {
+        Self::new(config.min_fee_per_byte, config.max_memory)
+    }
+
+    /// Fee filter derived from current mempool pressure
+    pub fn fee_filter(&self, stats: &MempoolStats) -> u64 {
+        let usage = stats.memory_usage.min(self.max_memory);
+        if self.max_memory == 0 || usage * 2 < self.max_memory {
+            return self.min_fee_per_byte;
+        }
+
+        // 0 at half full, max_memory when full
+        let pressure = (usage * 2 - self.max_memory) as u128;
+        let target = stats.avg_fee_per_byte.max(self.min_fee_per_byte);
+        let span = (target - self.min_fee_per_byte) as u128;
+
+        self.min_fee_per_byte + (span * pressure / self.max_memory as u128) as u64
+    }
+}
+
+impl Default for FeeFilterPolicy {
+    fn default() -> Self {
+        Self::from_mempool_config(&MempoolConfig::default())
+    }
+}
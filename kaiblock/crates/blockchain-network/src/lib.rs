@@ -1,14 +1,22 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+pub mod network;
+pub mod peer;
+pub mod message;
+pub mod errors;
+pub mod fee_filter;
+pub mod sync;
+pub mod bandwidth;
+pub mod double_spend;
+pub mod ban;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use network::Network;
+pub use peer::{Peer, PeerInfo};
+pub use message::{
+    negotiate_version, read_message, write_message, InventoryItem, MessageType, NetworkMessage, VersionMessage,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+pub use errors::NetworkError;
+pub use fee_filter::FeeFilterPolicy;
+pub use sync::{SyncConfig, SyncManager};
+pub use bandwidth::{BandwidthConfig, BandwidthManager, PeerBandwidth};
+pub use double_spend::{DoubleSpendRelay, DoubleSpendRelayConfig};
+pub use ban::{BanConfig, BanManager, Misbehavior};
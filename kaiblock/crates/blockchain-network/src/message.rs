@@ -1,32 +1,559 @@
+//! Messages nodes exchange, and how they go on the wire.
+//!
+//! Each message is a frame: a u32 little-endian length, then that many bytes
+//! holding the codec version, the message's tag and its payload in the
+//! canonical encoding of `blockchain_core::codec`. Tags are fixed: a new
+//! message gets a new tag and a protocol version, and a tag is never reused,
+//! so nodes of different versions agree on every message they both know. A
+//! frame with a tag the reader doesn't know is skipped, not treated as an error.
+//!
+//! Connections start with a handshake: each side sends `Version`, answers
+//! the other's with `VerAck`, and both then speak the lower of the two
+//! protocol versions (see [`negotiate_version`]).
+
 use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use blockchain_core::block::Block;
+use blockchain_core::codec::{self, Decode, Encode, Reader};
 use blockchain_core::transaction::Transaction;
+use blockchain_core::{BlockHeader, BlockId, ChainId, ConflictProof, TxId};
+use crate::sync::{HeadersRequest, HeadersResponse, LocatorRequest};
+use crate::NetworkError;
+
+
+/// Protocol version this node speaks
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest protocol version this node still talks to
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
 
+/// Largest frame accepted, so a bogus length can't make us allocate gigabytes
+pub const MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
 
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum MessageType{
+    /// First message of a connection (payload: VersionMessage)
+    Version,
+    /// Accepts the other side's Version
+    VerAck,
     Block,
     Transaction,
+    /// Minimum fee per byte the sender wants relayed to it (payload: u64)
+    FeeFilter,
+    /// Sender is closing the connection (payload: reason string)
+    Goodbye,
+    /// Ask for main chain headers by height range (payload: HeadersRequest)
+    GetHeaders,
+    /// Reply to GetHeaders (payload: HeadersResponse)
+    Headers,
+    /// Ask for block bodies by id (payload: Vec<BlockId>)
+    GetBlocks,
+    /// Reply to GetBlocks with the blocks the sender has (payload: Vec<Block>)
+    Blocks,
+    /// Two signed transactions spending one output (payload: ConflictProof)
+    DoubleSpendProof,
+    /// Liveness check (payload: nonce echoed by the Pong)
+    Ping,
+    Pong,
+    /// Announce blocks and transactions by id (payload: Vec<InventoryItem>)
+    Inv,
+    /// Ask for announced items; answered with Block and Transaction messages
+    GetData,
+    /// Ask for the main chain headers after the sender's fork point, given
+    /// as a block locator; answered with Headers (payload: LocatorRequest)
+    GetHeadersByLocator,
+
+}
+
+impl MessageType{
+    /// Label used for per-class traffic accounting
+    pub fn class_name(&self) -> &'static str{
+        match self {
+            MessageType::Version => "version",
+            MessageType::VerAck => "verack",
+            MessageType::Block => "block",
+            MessageType::Transaction => "transaction",
+            MessageType::FeeFilter => "fee_filter",
+            MessageType::Goodbye => "goodbye",
+            MessageType::GetHeaders => "get_headers",
+            MessageType::Headers => "headers",
+            MessageType::GetBlocks => "get_blocks",
+            MessageType::Blocks => "blocks",
+            MessageType::DoubleSpendProof => "double_spend_proof",
+            MessageType::Ping => "ping",
+            MessageType::Pong => "pong",
+            MessageType::Inv => "inv",
+            MessageType::GetData => "get_data",
+            MessageType::GetHeadersByLocator => "get_headers_by_locator",
+        }
+    }
+
+    /// Tag identifying the message on the wire
+    pub fn tag(&self) -> u8{
+        match self {
+            MessageType::Version => 0,
+            MessageType::VerAck => 1,
+            MessageType::Block => 2,
+            MessageType::Transaction => 3,
+            MessageType::FeeFilter => 4,
+            MessageType::Goodbye => 5,
+            MessageType::GetHeaders => 6,
+            MessageType::Headers => 7,
+            MessageType::GetBlocks => 8,
+            MessageType::Blocks => 9,
+            MessageType::DoubleSpendProof => 10,
+            MessageType::Ping => 11,
+            MessageType::Pong => 12,
+            MessageType::Inv => 13,
+            MessageType::GetData => 14,
+            MessageType::GetHeadersByLocator => 15,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Self>{
+        Some(match tag {
+            0 => MessageType::Version,
+            1 => MessageType::VerAck,
+            2 => MessageType::Block,
+            3 => MessageType::Transaction,
+            4 => MessageType::FeeFilter,
+            5 => MessageType::Goodbye,
+            6 => MessageType::GetHeaders,
+            7 => MessageType::Headers,
+            8 => MessageType::GetBlocks,
+            9 => MessageType::Blocks,
+            10 => MessageType::DoubleSpendProof,
+            11 => MessageType::Ping,
+            12 => MessageType::Pong,
+            13 => MessageType::Inv,
+            14 => MessageType::GetData,
+            15 => MessageType::GetHeadersByLocator,
+            _ => return None,
+        })
+    }
+
+    /// First protocol version with this message; peers below it never get it
+    pub fn min_protocol_version(&self) -> u32{
+        match self {
+            MessageType::Ping | MessageType::Pong | MessageType::Inv | MessageType::GetData => 2,
+            MessageType::GetHeadersByLocator => 3,
+            _ => 1,
+        }
+    }
+}
+
+
+/// What a node says about itself when a connection opens
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VersionMessage{
+    /// highest protocol version the sender speaks
+    pub protocol_version: u32,
+    /// lowest protocol version the sender still accepts
+    pub min_protocol_version: u32,
+    pub chain_id: ChainId,
+    /// height of the sender's main chain
+    pub best_height: u64,
+    /// random per node, so a node that dialed itself notices
+    pub nonce: u64,
+    /// software name and version, e.g. `kaiblock/0.1.0`
+    pub user_agent: String,
+    /// port the sender accepts connections on (None: it doesn't listen).
+    /// With the address the connection came from this is where the sender
+    /// can be dialed; it is not trusted to identify the connection
+    pub listen_port: Option<u16>,
+}
+
+impl VersionMessage{
+    pub fn new(chain_id: ChainId, best_height: u64, nonce: u64) -> Self{
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            chain_id,
+            best_height,
+            nonce,
+            user_agent: concat!("kaiblock/", env!("CARGO_PKG_VERSION")).to_string(),
+            listen_port: None,
+        }
+    }
+
+    pub fn with_listen_port(mut self, listen_port: u16) -> Self{
+        self.listen_port = Some(listen_port);
+        self
+    }
+}
+
+/// Protocol version two nodes speak after exchanging `local` and `remote`:
+/// the lower of their versions, as long as each accepts it
+pub fn negotiate_version(local: &VersionMessage, remote: &VersionMessage) -> Result<u32, NetworkError>{
+    if remote.nonce == local.nonce {
+        return Err(NetworkError::Handshake("connected to ourselves".to_string()));
+    }
+    if remote.chain_id != local.chain_id {
+        return Err(NetworkError::Handshake(format!("peer is on chain {}, not {}", remote.chain_id, local.chain_id)));
+    }
+    let version = local.protocol_version.min(remote.protocol_version);
+    if version < local.min_protocol_version || version < remote.min_protocol_version {
+        return Err(NetworkError::Handshake(format!(
+            "no common protocol version (ours {}..={}, theirs {}..={})",
+            local.min_protocol_version, local.protocol_version,
+            remote.min_protocol_version, remote.protocol_version
+        )));
+    }
+    Ok(version)
+}
 
+
+/// A block or transaction announced by id
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InventoryItem{
+    Block(BlockId),
+    Transaction(TxId),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct NetworkMessage{
-    pub msg_type: MessageType,
-    pub payload: Vec<u8>, // Serialized Block or Transaction
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkMessage{
+    Version(VersionMessage),
+    VerAck,
+    Block(Block),
+    Transaction(Transaction),
+    FeeFilter(u64),
+    Goodbye(String),
+    GetHeaders(HeadersRequest),
+    Headers(HeadersResponse),
+    GetBlocks(Vec<BlockId>),
+    Blocks(Vec<Block>),
+    DoubleSpendProof(ConflictProof),
+    Ping(u64),
+    Pong(u64),
+    Inv(Vec<InventoryItem>),
+    GetData(Vec<InventoryItem>),
+    GetHeadersByLocator(LocatorRequest),
 }
 
 impl NetworkMessage{
-    pub new_block(block: &Block)-> Self{
-        Self{
-            msg_type: MessageType::Block,
-            payload: bincode::serialize(block).unwrap(),
+    pub fn msg_type(&self) -> MessageType{
+        match self {
+            NetworkMessage::Version(_) => MessageType::Version,
+            NetworkMessage::VerAck => MessageType::VerAck,
+            NetworkMessage::Block(_) => MessageType::Block,
+            NetworkMessage::Transaction(_) => MessageType::Transaction,
+            NetworkMessage::FeeFilter(_) => MessageType::FeeFilter,
+            NetworkMessage::Goodbye(_) => MessageType::Goodbye,
+            NetworkMessage::GetHeaders(_) => MessageType::GetHeaders,
+            NetworkMessage::Headers(_) => MessageType::Headers,
+            NetworkMessage::GetBlocks(_) => MessageType::GetBlocks,
+            NetworkMessage::Blocks(_) => MessageType::Blocks,
+            NetworkMessage::DoubleSpendProof(_) => MessageType::DoubleSpendProof,
+            NetworkMessage::Ping(_) => MessageType::Ping,
+            NetworkMessage::Pong(_) => MessageType::Pong,
+            NetworkMessage::Inv(_) => MessageType::Inv,
+            NetworkMessage::GetData(_) => MessageType::GetData,
+            NetworkMessage::GetHeadersByLocator(_) => MessageType::GetHeadersByLocator,
+        }
+    }
+
+    pub fn new_block(block: &Block)-> Self{
+        NetworkMessage::Block(block.clone())
+    }
+
+    pub fn new_transaction(tx: &Transaction) -> Self{
+        NetworkMessage::Transaction(tx.clone())
+    }
+
+    pub fn new_fee_filter(min_fee_per_byte: u64) -> Self{
+        NetworkMessage::FeeFilter(min_fee_per_byte)
+    }
+
+    pub fn new_goodbye(reason: &str) -> Self{
+        NetworkMessage::Goodbye(reason.to_string())
+    }
+
+    pub fn new_get_headers(start_height: u64, max_count: u32) -> Self{
+        NetworkMessage::GetHeaders(HeadersRequest { start_height, max_count })
+    }
+
+    pub fn new_get_headers_by_locator(locator: Vec<BlockId>, max_count: u32) -> Self{
+        NetworkMessage::GetHeadersByLocator(LocatorRequest { locator, max_count })
+    }
+
+    pub fn new_headers(tip_height: u64, headers: Vec<BlockHeader>) -> Self{
+        NetworkMessage::Headers(HeadersResponse { tip_height, headers })
+    }
+
+    pub fn new_get_blocks(block_ids: &[BlockId]) -> Self{
+        NetworkMessage::GetBlocks(block_ids.to_vec())
+    }
+
+    pub fn new_blocks(blocks: &[Block]) -> Self{
+        NetworkMessage::Blocks(blocks.to_vec())
+    }
+
+    pub fn new_double_spend_proof(proof: &ConflictProof) -> Self{
+        NetworkMessage::DoubleSpendProof(proof.clone())
+    }
+
+    /// The message framed for the wire: length, then its versioned encoding
+    pub fn to_frame(&self) -> Vec<u8>{
+        let body = codec::encode(self);
+        let mut frame = Vec::with_capacity(4 + body.len());
+        frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&body);
+        frame
+    }
+
+    /// Decode a frame's body. A message newer than this node yields
+    /// `NetworkError::UnknownMessage`, which callers skip
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NetworkError>{
+        if let Some(&tag) = bytes.get(1) {
+            if MessageType::from_tag(tag).is_none() {
+                return Err(NetworkError::UnknownMessage(tag));
+            }
+        }
+        codec::decode(bytes).map_err(|e| NetworkError::DeserializationError(e.to_string()))
+    }
+}
+
+
+/// Write `msg` as one frame, returning how many bytes went out
+pub async fn write_message<W: AsyncWrite + Unpin>(writer: &mut W, msg: &NetworkMessage) -> Result<usize, NetworkError>{
+    let frame = msg.to_frame();
+    writer.write_all(&frame).await?;
+    Ok(frame.len())
+}
+
+/// Read the next frame's bytes; `None` once the other side has closed cleanly
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>, NetworkError>{
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(NetworkError::DeserializationError(format!("frame of {} bytes exceeds the limit", len)));
+    }
+    let mut body = vec![0u8; len];
+    reader.read_exact(&mut body).await?;
+    Ok(Some(body))
+}
+
+/// Read the next message, skipping any this node doesn't know; `None` once
+/// the other side has closed. Also returns the frame's size on the wire
+pub async fn read_message<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<(NetworkMessage, usize)>, NetworkError>{
+    while let Some(body) = read_frame(reader).await? {
+        match NetworkMessage::from_bytes(&body) {
+            Ok(msg) => return Ok(Some((msg, 4 + body.len()))),
+            Err(NetworkError::UnknownMessage(_)) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+
+impl Encode for VersionMessage {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.protocol_version.encode_to(out);
+        self.min_protocol_version.encode_to(out);
+        self.chain_id.encode_to(out);
+        self.best_height.encode_to(out);
+        self.nonce.encode_to(out);
+        self.user_agent.encode_to(out);
+        self.listen_port.encode_to(out);
+    }
+}
+
+impl Decode for VersionMessage {
+    fn decode_from(reader: &mut Reader<'_>) -> blockchain_core::Result<Self> {
+        Ok(Self {
+            protocol_version: u32::decode_from(reader)?,
+            min_protocol_version: u32::decode_from(reader)?,
+            chain_id: ChainId::decode_from(reader)?,
+            best_height: u64::decode_from(reader)?,
+            nonce: u64::decode_from(reader)?,
+            user_agent: String::decode_from(reader)?,
+            // older peers end the message after the user agent
+            listen_port: if reader.is_empty() { None } else { Option::<u16>::decode_from(reader)? },
+        })
+    }
+}
+
+impl Encode for InventoryItem {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            InventoryItem::Block(id) => {
+                out.push(0);
+                id.encode_to(out);
+            }
+            InventoryItem::Transaction(id) => {
+                out.push(1);
+                id.encode_to(out);
+            }
+        }
+    }
+}
+
+impl Decode for InventoryItem {
+    fn decode_from(reader: &mut Reader<'_>) -> blockchain_core::Result<Self> {
+        match u8::decode_from(reader)? {
+            0 => Ok(InventoryItem::Block(BlockId::decode_from(reader)?)),
+            1 => Ok(InventoryItem::Transaction(TxId::decode_from(reader)?)),
+            tag => Err(blockchain_core::BlockchainError::SerializationError(format!("unknown inventory tag {}", tag))),
+        }
+    }
+}
+
+impl Encode for NetworkMessage {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        out.push(self.msg_type().tag());
+        match self {
+            NetworkMessage::Version(version) => version.encode_to(out),
+            NetworkMessage::VerAck => {}
+            NetworkMessage::Block(block) => block.encode_to(out),
+            NetworkMessage::Transaction(tx) => tx.encode_to(out),
+            NetworkMessage::FeeFilter(fee_rate) => fee_rate.encode_to(out),
+            NetworkMessage::Goodbye(reason) => reason.encode_to(out),
+            NetworkMessage::GetHeaders(request) => request.encode_to(out),
+            NetworkMessage::Headers(response) => response.encode_to(out),
+            NetworkMessage::GetBlocks(block_ids) => block_ids.encode_to(out),
+            NetworkMessage::Blocks(blocks) => blocks.encode_to(out),
+            NetworkMessage::DoubleSpendProof(proof) => proof.encode_to(out),
+            NetworkMessage::Ping(nonce) | NetworkMessage::Pong(nonce) => nonce.encode_to(out),
+            NetworkMessage::Inv(items) | NetworkMessage::GetData(items) => items.encode_to(out),
+            NetworkMessage::GetHeadersByLocator(request) => request.encode_to(out),
         }
     }
+}
+
+impl Decode for NetworkMessage {
+    fn decode_from(reader: &mut Reader<'_>) -> blockchain_core::Result<Self> {
+        let tag = u8::decode_from(reader)?;
+        let msg_type = MessageType::from_tag(tag)
+            .ok_or_else(|| blockchain_core::BlockchainError::SerializationError(format!("unknown message tag {}", tag)))?;
+        Ok(match msg_type {
+            MessageType::Version => NetworkMessage::Version(VersionMessage::decode_from(reader)?),
+            MessageType::VerAck => NetworkMessage::VerAck,
+            MessageType::Block => NetworkMessage::Block(Block::decode_from(reader)?),
+            MessageType::Transaction => NetworkMessage::Transaction(Transaction::decode_from(reader)?),
+            MessageType::FeeFilter => NetworkMessage::FeeFilter(u64::decode_from(reader)?),
+            MessageType::Goodbye => NetworkMessage::Goodbye(String::decode_from(reader)?),
+            MessageType::GetHeaders => NetworkMessage::GetHeaders(HeadersRequest::decode_from(reader)?),
+            MessageType::Headers => NetworkMessage::Headers(HeadersResponse::decode_from(reader)?),
+            MessageType::GetBlocks => NetworkMessage::GetBlocks(Vec::decode_from(reader)?),
+            MessageType::Blocks => NetworkMessage::Blocks(Vec::decode_from(reader)?),
+            MessageType::DoubleSpendProof => NetworkMessage::DoubleSpendProof(ConflictProof::decode_from(reader)?),
+            MessageType::Ping => NetworkMessage::Ping(u64::decode_from(reader)?),
+            MessageType::Pong => NetworkMessage::Pong(u64::decode_from(reader)?),
+            MessageType::Inv => NetworkMessage::Inv(Vec::decode_from(reader)?),
+            MessageType::GetData => NetworkMessage::GetData(Vec::decode_from(reader)?),
+            MessageType::GetHeadersByLocator => NetworkMessage::GetHeadersByLocator(LocatorRequest::decode_from(reader)?),
+        })
+    }
+}
+
 
-    oub new_transaction(tx: &Transaction) -> Self{
-        Self{
-            msg_type: MessageType::Transaction,
-            payload: bincode::serialize(tx).unwrap(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::Hash256;
+
+    fn version(chain_id: ChainId, nonce: u64) -> VersionMessage{
+        VersionMessage::new(chain_id, 10, nonce)
+    }
+
+    #[tokio::test]
+    async fn test_frame_round_trip() {
+        let messages = vec![
+            NetworkMessage::Version(version(1, 7).with_listen_port(8333)),
+            NetworkMessage::VerAck,
+            NetworkMessage::new_fee_filter(5),
+            NetworkMessage::new_goodbye("shutting down"),
+            NetworkMessage::new_get_headers(3, 100),
+            NetworkMessage::new_get_headers_by_locator(vec![BlockId::new(Hash256::from_bytes([3u8; 32]))], 100),
+            NetworkMessage::Ping(42),
+            NetworkMessage::Inv(vec![InventoryItem::Block(BlockId::new(Hash256::from_bytes([1u8; 32]))), InventoryItem::Transaction(TxId::new(Hash256::from_bytes([2u8; 32])))]),
+        ];
+        let mut wire = Vec::new();
+        for msg in &messages {
+            assert_eq!(write_message(&mut wire, msg).await.unwrap(), msg.to_frame().len());
         }
+
+        let mut reader = wire.as_slice();
+        for msg in &messages {
+            let (read, size) = read_message(&mut reader).await.unwrap().unwrap();
+            assert_eq!(&read, msg);
+            assert_eq!(size, msg.to_frame().len());
+        }
+        assert!(read_message(&mut reader).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_frame_with_unknown_tag_is_skipped() {
+        let mut unknown = codec::encode(&NetworkMessage::Ping(1));
+        unknown[1] = 200;
+        assert!(matches!(NetworkMessage::from_bytes(&unknown), Err(NetworkError::UnknownMessage(200))));
+
+        let mut wire = (unknown.len() as u32).to_le_bytes().to_vec();
+        wire.extend_from_slice(&unknown);
+        wire.extend_from_slice(&NetworkMessage::Pong(1).to_frame());
+
+        let (read, _) = read_message(&mut wire.as_slice()).await.unwrap().unwrap();
+        assert_eq!(read, NetworkMessage::Pong(1));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_and_truncated_frames_are_errors() {
+        let oversized = ((MAX_MESSAGE_SIZE + 1) as u32).to_le_bytes();
+        assert!(read_frame(&mut oversized.as_slice()).await.is_err());
+
+        let frame = NetworkMessage::Ping(1).to_frame();
+        assert!(read_frame(&mut &frame[..frame.len() - 1]).await.is_err());
+
+        // a clean close between frames is not an error
+        assert!(read_frame(&mut &[][..]).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_version_from_older_peer_has_no_listen_port() {
+        let current = version(1, 7).with_listen_port(8333);
+        let mut older = Vec::new();
+        current.protocol_version.encode_to(&mut older);
+        current.min_protocol_version.encode_to(&mut older);
+        current.chain_id.encode_to(&mut older);
+        current.best_height.encode_to(&mut older);
+        current.nonce.encode_to(&mut older);
+        current.user_agent.encode_to(&mut older);
+
+        let decoded = VersionMessage::decode_from(&mut Reader::new(&older)).unwrap();
+        assert_eq!(decoded, VersionMessage { listen_port: None, ..current });
+    }
+
+    #[test]
+    fn test_negotiate_lower_common_version() {
+        let local = version(1, 1);
+        let remote = VersionMessage { protocol_version: MIN_PROTOCOL_VERSION, ..version(1, 2) };
+        assert_eq!(negotiate_version(&local, &remote).unwrap(), MIN_PROTOCOL_VERSION);
+        assert_eq!(negotiate_version(&remote, &local).unwrap(), MIN_PROTOCOL_VERSION);
+        assert_eq!(negotiate_version(&local, &version(1, 2)).unwrap(), PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_negotiate_refuses_bad_peers() {
+        let local = version(1, 1);
+
+        // dialed ourselves
+        assert!(matches!(negotiate_version(&local, &version(1, 1)), Err(NetworkError::Handshake(_))));
+        // another chain
+        assert!(matches!(negotiate_version(&local, &version(2, 2)), Err(NetworkError::Handshake(_))));
+        // a peer that no longer speaks our version
+        let newer = VersionMessage {
+            protocol_version: PROTOCOL_VERSION + 2,
+            min_protocol_version: PROTOCOL_VERSION + 1,
+            ..version(1, 2)
+        };
+        assert!(matches!(negotiate_version(&local, &newer), Err(NetworkError::Handshake(_))));
     }
 }
@@ -1,9 +0,0 @@
-pub mod network;
-pub mod peer;
-pub mod message;
-pub mod errors;
-
-pub use network::Network;
-pub use peer::Peer;
-pub use message::{NetworkMessage, MessageType};
-pub use errors::NetworkError;
\ No newline at end of file
@@ -1,19 +1,74 @@
 use tokio::net::{TcpListener, TcpStream};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use crate::{Peer, NetworkMessage, NetworkError};
+use crate::{Peer, PeerInfo, NetworkMessage, NetworkError};
+use crate::message::{negotiate_version, read_message, write_message, InventoryItem, VersionMessage};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use tokio::sync::RwLock;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::{mpsc, RwLock};
 
 use crate::mempool::Mempool;
+use crate::fee_filter::{transaction_fee_rate, FeeFilterPolicy};
+use crate::bandwidth::{BandwidthConfig, BandwidthManager};
+use crate::double_spend::{DoubleSpendRelay, DoubleSpendRelayConfig};
+use crate::ban::{BanConfig, BanManager, Misbehavior};
+use blockchain_core::transaction::Transaction;
+use blockchain_core::mempool::MempoolStats;
+use blockchain_core::{Block, Blockchain, ChainConfig, ConflictProof, HeaderVerifier, check_body_commitment};
+use blockchain_core::sync::{MAX_BLOCKS_PER_REQUEST, MAX_HEADERS_PER_REQUEST};
 
 
 use rand::seq::IteratorRandom;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+
+/// Messages queued for one peer's connection; sends beyond this are dropped
+/// rather than letting a slow peer hold up relay to the rest
+const PEER_SEND_QUEUE: usize = 1024;
 
 
 pub struct Network{
     peers: Arc<RwLock<HashMap<String, Peer>>>,
     pub mempool: Mempool,
+    /// local chain served to syncing peers (None: header/block requests are ignored)
+    chain: Option<Arc<RwLock<Blockchain>>>,
+    /// per-peer traffic counters and upload/download caps
+    bandwidth: Arc<BandwidthManager>,
+    /// dedup and rate limits for double-spend alerts
+    double_spends: Arc<DoubleSpendRelay>,
+    /// misbehavior scores and bans per peer address
+    bans: Arc<BanManager>,
+    /// where relayed blocks go once their proof checks out
+    blocks: BlockIntake,
+    /// sent in our handshakes, to notice connections to ourselves
+    nonce: u64,
+    /// connections beyond this many are refused (None: no limit)
+    max_peers: Option<usize>,
+    /// port our listener is bound to, advertised in handshakes
+    listen_port: OnceLock<u16>,
+}
+
+
+/// What the task serving each connection shares with the network
+#[derive(Clone)]
+struct ConnectionContext {
+    peers: Arc<RwLock<HashMap<String, Peer>>>,
+    chain: Option<Arc<RwLock<Blockchain>>>,
+    bandwidth: Arc<BandwidthManager>,
+    double_spends: Arc<DoubleSpendRelay>,
+    bans: Arc<BanManager>,
+    blocks: BlockIntake,
+}
+
+
+/// Pre-validation of relayed blocks and the queue to full validation
+#[derive(Clone, Default)]
+struct BlockIntake {
+    /// checks header proofs; without one the chain's own PoW rules are used
+    verifier: Option<Arc<dyn HeaderVerifier>>,
+    /// bounded, so a flood of blocks is dropped instead of piling up
+    queue: Option<mpsc::Sender<Block>>,
 }
 
 
@@ -22,68 +77,621 @@ impl Network{
         Self {
             peers: Arc::new(HashMap::new()),
             mempool: Mempool::new(),
+            chain: None,
+            bandwidth: Arc::new(BandwidthManager::default()),
+            double_spends: Arc::new(DoubleSpendRelay::default()),
+            bans: Arc::new(BanManager::default()),
+            blocks: BlockIntake::default(),
+            nonce: rand::random(),
+            max_peers: None,
+            listen_port: OnceLock::new(),
+        }
+    }
+
+    /// Refuse connections, in and out, once `max_peers` peers are connected
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self{
+        self.max_peers = Some(max_peers);
+        self
+    }
+
+    async fn at_peer_limit(&self) -> bool{
+        match self.max_peers {
+            Some(max_peers) => self.peers.read().await.len() >= max_peers,
+            None => false,
+        }
+    }
+
+    /// Ban peers once their misbehavior score reaches the threshold
+    pub fn with_bans(mut self, config: BanConfig) -> Self{
+        self.bans = Arc::new(BanManager::new(config));
+        self
+    }
+
+    pub fn bans(&self) -> Arc<BanManager>{
+        self.bans.clone()
+    }
+
+    /// Check relayed block headers with `verifier` (e.g. PoS producer
+    /// signatures) instead of the chain's proof-of-work rules
+    pub fn with_header_verifier(mut self, verifier: Arc<dyn HeaderVerifier>) -> Self{
+        self.blocks.verifier = Some(verifier);
+        self
+    }
+
+    /// Send relayed blocks that pass the header pre-check to `queue` for full validation
+    pub fn with_block_queue(mut self, queue: mpsc::Sender<Block>) -> Self{
+        self.blocks.queue = Some(queue);
+        self
+    }
+
+    /// Limit how many double-spend alerts are accepted and relayed
+    pub fn with_double_spend_relay(mut self, config: DoubleSpendRelayConfig) -> Self{
+        self.double_spends = Arc::new(DoubleSpendRelay::new(config));
+        self
+    }
+
+    /// Cap upload and download rates per peer and globally
+    pub fn with_bandwidth(mut self, config: BandwidthConfig) -> Self{
+        self.bandwidth = Arc::new(BandwidthManager::new(config));
+        self
+    }
+
+    pub fn bandwidth(&self) -> Arc<BandwidthManager>{
+        self.bandwidth.clone()
+    }
+
+    /// Connected peers with their fee filters and bandwidth usage
+    pub async fn peer_info(&self) -> Vec<PeerInfo>{
+        self.peers.read().await.iter()
+            .map(|(addr, peer)| PeerInfo {
+                addr: addr.clone(),
+                fee_filter: peer.fee_filter,
+                protocol_version: peer.protocol_version,
+                user_agent: peer.user_agent.clone(),
+                bandwidth: self.bandwidth.peer_usage(addr),
+            })
+            .collect()
+    }
+
+    /// Serve headers and blocks from this chain to peers that are syncing
+    pub fn with_chain(mut self, chain: Arc<RwLock<Blockchain>>) -> Self{
+        self.chain = Some(chain);
+        self
+    }
+
+    /// What we tell peers about ourselves in a handshake
+    pub async fn version_message(&self) -> VersionMessage{
+        let (chain_id, best_height) = match &self.chain {
+            Some(chain) => {
+                let chain = chain.read().await;
+                (chain.config().chain_id, chain.height())
+            }
+            None => (ChainConfig::default().chain_id, 0),
+        };
+        let version = VersionMessage::new(chain_id, best_height, self.nonce);
+        match self.listen_port() {
+            Some(port) => version.with_listen_port(port),
+            None => version,
+        }
+    }
+
+    /// Port peers can reach us on, once the listener is bound
+    pub fn listen_port(&self) -> Option<u16>{
+        self.listen_port.get().copied()
+    }
+
+    /// Addresses of the connections to our peers
+    pub async fn peer_addrs(&self) -> Vec<String>{
+        self.peers.read().await.keys().cloned().collect()
+    }
+
+    /// Addresses our peers accept connections on, for requests that open
+    /// their own connection (peers that don't listen are left out)
+    pub async fn listen_addrs(&self) -> Vec<String>{
+        self.peers.read().await.values()
+            .filter_map(|peer| peer.listen_addr)
+            .map(|addr| addr.to_string())
+            .collect()
+    }
+
+    fn connection_context(&self) -> ConnectionContext{
+        ConnectionContext {
+            peers: self.peers.clone(),
+            chain: self.chain.clone(),
+            bandwidth: self.bandwidth.clone(),
+            double_spends: self.double_spends.clone(),
+            bans: self.bans.clone(),
+            blocks: self.blocks.clone(),
         }
     }
 
     pub async fn start_listener(&self, addr: &str) ->Result<(), NetworkError>{
         let listener = TcpListener::bind(addr).await?;
-        println!("Listening on {}", addr);
+        let _ = self.listen_port.set(listener.local_addr()?.port());
+        info!(%addr, "listening for peers");
         loop{
             let (socket, peer_addr) = listener.accept().await?;
-            println!("Accepted connection from {}", peer_addr);
+            if self.bans.is_banned(peer_addr.ip()) || self.at_peer_limit().await {
+                continue; // dropping the socket closes it
+            }
+            info!(peer = %peer_addr, "accepted connection");
 
-            let peers = self.peers.clone();
+            let context = self.connection_context();
+            let local = self.version_message().await;
             tokio::spawn(async move{
-                if let Err(e) = Slt::handle_connection(socket, peers).await?{
-                    eprintln!("Error handling connection from {}: {}", peer_addr, e);
+                if let Err(e) = Slt::handle_connection(socket, context, local).await?{
+                    warn!(error = %e, "connection failed");
                 }
-            });
+            }.instrument(info_span!("peer", peer = %peer_addr)));
         }
     }
 
 
-    pub async fn handle_connection(mut socket: TcpStream, peers: Arc<RwLock<HashMap<String, Peer>>>) ->Result<(), NetworkError>{
-        let mut buffer = vec![0; 1024];
+    // Serve a connection a peer opened to us. Each connection is its own
+    // peer, keyed by the address it came from: the listen port a peer
+    // advertises says where to dial it, not which connection is whose
+    async fn handle_connection(socket: TcpStream, context: ConnectionContext, local: VersionMessage) ->Result<(), NetworkError>{
+        let peer_addr = socket.peer_addr()?;
+        let (mut reader, writer) = socket.into_split();
+        let outbound = Self::spawn_writer(writer, context.bandwidth.clone(), peer_addr.to_string());
+
+        let (protocol_version, remote) = Self::accept_handshake(&mut reader, &outbound, &local, &context.bans, peer_addr).await?;
+
+        let mut peer = Peer::new(peer_addr);
+        peer.protocol_version = protocol_version;
+        peer.user_agent = Some(remote.user_agent);
+        peer.listen_addr = remote.listen_port.map(|port| SocketAddr::new(peer_addr.ip(), port));
+        peer.outbound = Some(outbound.clone());
+        context.peers.write().await.insert(peer_addr.to_string(), peer);
+
+        Self::serve_peer(reader, outbound, peer_addr, context).await
+    }
+
+
+    // Listener's side of the handshake: take the dialer's Version, answer with
+    // ours and a VerAck, and wait for its VerAck. Any other message first
+    // counts against the peer. Returns the agreed protocol version
+    async fn accept_handshake(
+        reader: &mut OwnedReadHalf,
+        outbound: &mpsc::Sender<NetworkMessage>,
+        local: &VersionMessage,
+        bans: &BanManager,
+        peer_addr: SocketAddr,
+    ) ->Result<(u32, VersionMessage), NetworkError>{
+        let unexpected = |msg: NetworkMessage, expected: &str| {
+            bans.penalize(peer_addr.ip(), Misbehavior::MessageBeforeHandshake);
+            NetworkError::Handshake(format!("expected {}, got {:?}", expected, msg.msg_type()))
+        };
+        let remote = match read_message(reader).await? {
+            Some((NetworkMessage::Version(remote), _)) => remote,
+            Some((msg, _)) => return Err(unexpected(msg, "a version message")),
+            None => return Err(NetworkError::ConnectionClosed),
+        };
+        let protocol_version = match negotiate_version(local, &remote) {
+            Ok(version) => version,
+            Err(e) => {
+                Self::reply(outbound, NetworkMessage::new_goodbye(&e.to_string())).await?;
+                return Err(e);
+            }
+        };
+        Self::reply(outbound, NetworkMessage::Version(local.clone())).await?;
+        Self::reply(outbound, NetworkMessage::VerAck).await?;
+        match read_message(reader).await? {
+            Some((NetworkMessage::VerAck, _)) => Ok((protocol_version, remote)),
+            Some((msg, _)) => Err(unexpected(msg, "a verack")),
+            None => Err(NetworkError::ConnectionClosed),
+        }
+    }
+
+
+    // Handle a handshaken peer's messages until it leaves, is banned or the
+    // connection drops, then forget it
+    async fn serve_peer(
+        mut reader: OwnedReadHalf,
+        outbound: mpsc::Sender<NetworkMessage>,
+        peer_addr: SocketAddr,
+        context: ConnectionContext,
+    ) ->Result<(), NetworkError>{
+        let result = Self::handle_messages(&mut reader, &outbound, peer_addr, &context).await;
+        let ConnectionContext { peers, bandwidth, double_spends, .. } = &context;
+        Self::forget_peer(peers, bandwidth, double_spends, &peer_addr.to_string()).await;
+        result
+    }
+
+
+    async fn handle_messages(
+        reader: &mut OwnedReadHalf,
+        outbound: &mpsc::Sender<NetworkMessage>,
+        peer_addr: SocketAddr,
+        context: &ConnectionContext,
+    ) ->Result<(), NetworkError>{
+        let ConnectionContext { peers, chain, bandwidth, double_spends, bans, blocks } = context;
+        let peer_key = peer_addr.to_string();
         loop{
-            let n = socket.read(&mut buffer).await?;
-            if n == 0{
-                break; // Connection closed
+            let (msg, n) = match read_message(reader).await {
+                Ok(Some(read)) => read,
+                Ok(None) => break, // Connection closed
+                Err(NetworkError::DeserializationError(e)) => {
+                    warn!(error = %e, "malformed message");
+                    if bans.penalize(peer_addr.ip(), Misbehavior::MalformedMessage) {
+                        warn!("banning peer");
+                    }
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            debug!(kind = ?msg.msg_type(), bytes = n, "received message");
+            bandwidth.throttle_download(&peer_key, msg.msg_type(), n).await;
+
+            // over its message rate: drop the message and count it against the peer
+            if !bans.allow_message(peer_addr.ip()) {
+                if bans.penalize(peer_addr.ip(), Misbehavior::MessageFlood) {
+                    warn!("banning peer for flooding");
+                    break;
+                }
+                continue;
             }
 
-            let msg: NetworkMessage = bincode::deserialize(&buffer[..n]).map_err(|e| NetworkError::DeserializationError(e.to_string()))?;
-            println!("Received message: {:?}", msg);
-            // Handle the message (e.g., broadcast to other peers)
+            match msg {
+                // the handshake is done; a repeat of it changes nothing
+                NetworkMessage::Version(_) | NetworkMessage::VerAck | NetworkMessage::Pong(_) => {}
+
+                NetworkMessage::Ping(nonce) => {
+                    Self::reply(outbound, NetworkMessage::Pong(nonce)).await?;
+                }
+
+                NetworkMessage::FeeFilter(fee_filter) => {
+                    if let Some(peer) = peers.write().await.get_mut(&peer_key) {
+                        peer.fee_filter = fee_filter;
+                    }
+                }
+
+                NetworkMessage::Goodbye(reason) => {
+                    info!(%reason, "peer disconnected");
+                    break;
+                }
+
+                // cheap proof check before a block takes a slot in the validation queue
+                NetworkMessage::Block(block) => {
+                    debug!(block = %block.id(), height = block.header.height, "received block");
+                    match Self::precheck_relayed_block(block, chain, blocks).await {
+                        Ok(block) => {
+                            if let Some(queue) = &blocks.queue {
+                                if queue.try_send(block).is_err() {
+                                    warn!("block validation queue full, dropping relayed block");
+                                }
+                            }
+                        }
+                        Err(misbehavior) => {
+                            warn!(?misbehavior, "invalid block relayed");
+                            if bans.penalize(peer_addr.ip(), misbehavior) {
+                                warn!("banning peer");
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                // a verified proof comes back out of the chain as an event, and the
+                // node relays it from there like one we detected ourselves
+                NetworkMessage::DoubleSpendProof(proof) => {
+                    if let Some(chain) = chain {
+                        if double_spends.accept_from(&peer_key, &proof) {
+                            if let Err(e) = chain.read().await.report_double_spend(proof) {
+                                warn!(error = %e, "invalid double-spend proof");
+                                if bans.penalize(peer_addr.ip(), Misbehavior::InvalidDoubleSpendProof) {
+                                    warn!("banning peer");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // ask for announced items we don't have yet
+                NetworkMessage::Inv(items) => {
+                    if let Some(chain) = chain {
+                        let wanted: Vec<_> = {
+                            let chain = chain.read().await;
+                            items.into_iter().filter(|item| !Self::has_item(&chain, item)).collect()
+                        };
+                        if !wanted.is_empty() {
+                            Self::reply(outbound, NetworkMessage::GetData(wanted)).await?;
+                        }
+                    }
+                }
+
+                NetworkMessage::GetData(items) => {
+                    if let Some(chain) = chain {
+                        let found = Self::find_items(&chain.read().await, &items);
+                        for msg in found {
+                            Self::reply(outbound, msg).await?;
+                        }
+                    }
+                }
+
+                // serving IBD is the bulk of upload traffic; the writer keeps it within the caps
+                msg @ (NetworkMessage::GetHeaders(_) | NetworkMessage::GetHeadersByLocator(_) | NetworkMessage::GetBlocks(_)) => {
+                    if let Some(chain) = chain {
+                        if let Some(reply) = Self::answer_sync_request(&msg, chain).await {
+                            Self::reply(outbound, reply).await?;
+                        }
+                    }
+                }
+
+                // relayed transactions go to our mempool; ones that can never be
+                // valid count against the peer that sent them
+                NetworkMessage::Transaction(tx) => {
+                    if let Some(chain) = chain {
+                        let mut chain = chain.write().await;
+                        let tx_id = tx.id();
+                        if chain.mempool().contains_transaction(&tx_id) || chain.transaction_exists(&tx_id) {
+                            continue;
+                        }
+                        if let Err(e) = chain.add_transaction(tx) {
+                            drop(chain);
+                            if let Some(misbehavior) = Misbehavior::for_rejected_transaction(&e) {
+                                warn!(tx = %tx_id, error = %e, "invalid transaction relayed");
+                                if bans.penalize(peer_addr.ip(), misbehavior) {
+                                    warn!("banning peer");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+
+                // Handle the message (e.g., broadcast to other peers)
+                NetworkMessage::Headers(_) | NetworkMessage::Blocks(_) => {}
+            }
         }
         Ok(())
     }
 
 
+    // Drop a peer that left or was banned, with its per-peer accounting.
+    // Dropping its queue lets the writer close the connection
+    async fn forget_peer(
+        peers: &RwLock<HashMap<String, Peer>>,
+        bandwidth: &BandwidthManager,
+        double_spends: &DoubleSpendRelay,
+        peer_key: &str,
+    ){
+        peers.write().await.remove(peer_key);
+        bandwidth.remove_peer(peer_key);
+        double_spends.remove_peer(peer_key);
+    }
+
+
+    // Write a connection's queued messages in order, within the upload caps,
+    // until every sender is dropped or the peer stops reading
+    fn spawn_writer(mut writer: OwnedWriteHalf, bandwidth: Arc<BandwidthManager>, peer_key: String) -> mpsc::Sender<NetworkMessage>{
+        let (outbound, mut queue) = mpsc::channel::<NetworkMessage>(PEER_SEND_QUEUE);
+        tokio::spawn(async move{
+            while let Some(msg) = queue.recv().await {
+                let data = msg.to_frame();
+                bandwidth.throttle_upload(&peer_key, msg.msg_type(), data.len()).await;
+                if let Err(e) = writer.write_all(&data).await {
+                    warn!(peer = %peer_key, error = %e, "failed to send message");
+                    break;
+                }
+                debug!(peer = %peer_key, kind = ?msg.msg_type(), "sent message");
+            }
+            let _ = writer.shutdown().await;
+        }.in_current_span());
+        outbound
+    }
+
+
+    // Queue a reply on the connection it answers
+    async fn reply(outbound: &mpsc::Sender<NetworkMessage>, msg: NetworkMessage) ->Result<(), NetworkError>{
+        outbound.send(msg).await.map_err(|_| NetworkError::ConnectionClosed)
+    }
+
+
+    fn has_item(chain: &Blockchain, item: &InventoryItem) -> bool{
+        match item {
+            InventoryItem::Block(id) => chain.get_block(id).is_some(),
+            InventoryItem::Transaction(id) => chain.mempool().contains_transaction(id) || chain.get_transaction(id).is_some(),
+        }
+    }
+
+
+    // Block and Transaction messages for the requested items we have
+    fn find_items(chain: &Blockchain, items: &[InventoryItem]) -> Vec<NetworkMessage>{
+        items.iter()
+            .filter_map(|item| match item {
+                InventoryItem::Block(id) => chain.get_block(id).map(NetworkMessage::new_block),
+                InventoryItem::Transaction(id) => chain.mempool().get_transaction(id)
+                    .or_else(|| chain.get_transaction(id))
+                    .map(NetworkMessage::new_transaction),
+            })
+            .collect()
+    }
+
+
+    /// Check a relayed block's header proof and body commitment.
+    /// With neither a verifier nor a chain there is nothing to check against.
+    async fn precheck_relayed_block(
+        block: Block,
+        chain: &Option<Arc<RwLock<Blockchain>>>,
+        blocks: &BlockIntake,
+    ) ->Result<Block, Misbehavior>{
+
+        let proof = match (&blocks.verifier, chain) {
+            (Some(verifier), _) => verifier.verify_header(&block.header),
+            (None, Some(chain)) => chain.read().await.verify_header(&block.header),
+            (None, None) => Ok(()),
+        };
+        proof.map_err(|_| Misbehavior::InvalidBlockProof)?;
+        check_body_commitment(&block).map_err(|_| Misbehavior::MismatchedBlockBody)?;
+        Ok(block)
+    }
+
+
+    async fn answer_sync_request(msg: &NetworkMessage, chain: &RwLock<Blockchain>) -> Option<NetworkMessage>{
+        let chain = chain.read().await;
+
+        match msg {
+            NetworkMessage::GetHeaders(request) => {
+                let max_count = (request.max_count as usize).min(MAX_HEADERS_PER_REQUEST);
+                let headers = chain.get_headers(request.start_height, max_count);
+                Some(NetworkMessage::new_headers(chain.height(), headers))
+            }
+            NetworkMessage::GetHeadersByLocator(request) => {
+                let max_count = (request.max_count as usize).min(MAX_HEADERS_PER_REQUEST);
+                let headers = chain.get_headers_after(&request.locator, max_count);
+                Some(NetworkMessage::new_headers(chain.height(), headers))
+            }
+            NetworkMessage::GetBlocks(block_ids) => {
+                let blocks: Vec<_> = block_ids.iter()
+                    .take(MAX_BLOCKS_PER_REQUEST)
+                    .filter_map(|id| chain.get_block(id).cloned())
+                    .collect();
+                Some(NetworkMessage::new_blocks(&blocks))
+            }
+            _ => None,
+        }
+    }
+
+
     pub async fn connect_to_peer(&self, addr: &str) ->Result<(), NetworkError>{
-        let socket = TcpStream::connect(addr).await?;
-        let peer = Peer::new(socket.peer_addr()?);
-        self.peers.write().await.insert(addr.to_string(), peer);
-        println!("Connected to peer {}", addr);
+        if let (Some(max_peers), true) = (self.max_peers, self.at_peer_limit().await) {
+            return Err(NetworkError::PeerLimit(max_peers));
+        }
+        let mut socket = TcpStream::connect(addr).await?;
+        let remote_addr = socket.peer_addr()?;
+        if self.bans.is_banned(remote_addr.ip()) {
+            return Err(NetworkError::PeerBanned(addr.to_string()));
+        }
+        let (protocol_version, remote) = Self::handshake(&mut socket, &self.version_message().await).await?;
+
+        // the connection stays open: later messages to the peer go over it
+        let (reader, writer) = socket.into_split();
+        let outbound = Self::spawn_writer(writer, self.bandwidth.clone(), remote_addr.to_string());
+        let mut peer = Peer::new(remote_addr);
+        peer.protocol_version = protocol_version;
+        peer.user_agent = Some(remote.user_agent);
+        peer.listen_addr = Some(remote_addr);
+        peer.outbound = Some(outbound.clone());
+        self.peers.write().await.insert(remote_addr.to_string(), peer);
+        info!(peer = %addr, protocol_version, "connected to peer");
+
+        let context = self.connection_context();
+        tokio::spawn(async move{
+            if let Err(e) = Self::serve_peer(reader, outbound, remote_addr, context).await {
+                warn!(error = %e, "connection failed");
+            }
+        }.instrument(info_span!("peer", peer = %remote_addr)));
         Ok(())
     }
 
 
+    // Dialer's side of the handshake: send our Version, take the peer's and its
+    // VerAck, and acknowledge. Returns the agreed protocol version
+    pub(crate) async fn handshake(socket: &mut TcpStream, local: &VersionMessage) ->Result<(u32, VersionMessage), NetworkError>{
+        write_message(socket, &NetworkMessage::Version(local.clone())).await?;
+        let remote = match read_message(socket).await? {
+            Some((NetworkMessage::Version(remote), _)) => remote,
+            Some((NetworkMessage::Goodbye(reason), _)) => return Err(NetworkError::Handshake(format!("peer refused: {}", reason))),
+            _ => return Err(NetworkError::Handshake("expected a version message".to_string())),
+        };
+        let protocol_version = negotiate_version(local, &remote)?;
+        match read_message(socket).await? {
+            Some((NetworkMessage::VerAck, _)) => {}
+            _ => return Err(NetworkError::Handshake("expected a verack".to_string())),
+        }
+        write_message(socket, &NetworkMessage::VerAck).await?;
+        Ok((protocol_version, remote))
+    }
+
+
     pub async fn broadcast_message(&self, msg: &NetworkMessage) ->Result<(), NetworkError>{
         let peers = self.peers.read().await?;
         for (addr, peer) in peers.iter(){
-            let mut socket = TcpStream::connect(addr).await?;
-            let data = bincode::serialize(msg).map_err(|e| NetworkError::SerializationError(e.to_string()))?;
-            socket.write_all(&data).await?;
-            println!("Sent message to {}", addr);
+            if !peer.supports(msg.msg_type()) {
+                continue;
+            }
+            // queued on the connection the handshake opened; a peer that
+            // can't take more misses this one rather than stalling the rest
+            if !peer.send(msg.clone()) {
+                warn!(peer = %addr, kind = ?msg.msg_type(), "failed to queue message");
+            }
         }
         Ok(())
     }
 
 
+    /// Announce a block we produced to every peer
+    pub async fn broadcast_block(&self, block: &Block) ->Result<(), NetworkError>{
+        self.broadcast_message(&NetworkMessage::new_block(block)).await
+    }
+
+
     pub async fn broadcast_transaction(&self, tx: &Transaction){
         if self.mempool.add_tx(tx.clone()).await {
-            let msg = NetworkMessage::new_transaction(tx.clone());
-            self.broadcast(&msg).await.unwrap();
+            let msg = NetworkMessage::new_transaction(tx);
+            let relayed = self.relay_to_accepting_peers(&msg, transaction_fee_rate(tx)).await;
+            debug!(tx = %tx.id(), peers = relayed, "relayed transaction");
+        }
+    }
+
+
+    // Announce only to peers whose fee filter the transaction clears; returns
+    // how many peers it reached
+    async fn relay_to_accepting_peers(&self, msg: &NetworkMessage, fee_per_byte: u64) -> usize{
+        let peers = self.peers.read().await;
+        let mut relayed = 0;
+        for (addr, peer) in peers.iter(){
+            if !peer.accepts_fee_rate(fee_per_byte) || !peer.supports(msg.msg_type()) {
+                continue;
+            }
+            // best effort: a peer whose connection is gone doesn't stop the relay to the rest
+            if peer.send(msg.clone()) {
+                relayed += 1;
+            } else {
+                warn!(peer = %addr, "failed to relay message");
+            }
+        }
+        relayed
+    }
+
+
+    /// Relay a verified double-spend proof to every peer, unless it was
+    /// already relayed or the alert rate limit is reached; returns whether it was sent
+    pub async fn relay_double_spend(&self, proof: &ConflictProof) ->Result<bool, NetworkError>{
+        if !self.double_spends.should_relay(proof) {
+            return Ok(false);
+        }
+        self.broadcast_message(&NetworkMessage::new_double_spend_proof(proof)).await?;
+        Ok(true)
+    }
+
+
+    // Say goodbye to every peer and forget them; returns how many were notified
+    pub async fn disconnect_all(&self, reason: &str) ->Result<usize, NetworkError>{
+        let mut peers = self.peers.write().await;
+        let goodbye = NetworkMessage::new_goodbye(reason);
+
+        let mut notified = 0;
+        for (addr, peer) in peers.drain(){
+            // best effort: a peer that is already gone doesn't block shutdown
+            if peer.send(goodbye.clone()) {
+                notified += 1;
+            } else {
+                warn!(peer = %addr, "failed to send goodbye");
+            }
+            self.bandwidth.remove_peer(&addr);
         }
+        Ok(notified)
+    }
+
+
+    // Advertise our own fee filter, derived from mempool pressure
+    pub async fn send_fee_filter(&self, policy: &FeeFilterPolicy, stats: &MempoolStats) ->Result<u64, NetworkError>{
+        let fee_filter = policy.fee_filter(stats);
+        self.broadcast_message(&NetworkMessage::new_fee_filter(fee_filter)).await?;
+        Ok(fee_filter)
     }
 
 
@@ -105,13 +713,168 @@ impl Network{
         let peers = self.peers.read().await;
         let mut rng = rand::thread_rng();
         let selected_peers = peers.values().choose_multiple(&mut rng, max_peers);
-        for peer in selected_peers {
-            if let Ok(mut socket) = TcpStream::connect(peer.addr).await {
-                let data = bincode::serialize(msg).unwrap();
-                if let Err(e) = socket.write_all(&data).await {
-                    eprintln!("Failed to send message to {}: {}", peer.addr, e);
-                }
+        for peer in selected_peers.into_iter().filter(|peer| peer.supports(msg.msg_type())) {
+            if !peer.send(msg.clone()) {
+                warn!(peer = %peer.addr, "failed to send message");
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PROTOCOL_VERSION;
+    use std::time::Duration;
+
+    // A network listening on a free local port, and the address it listens on
+    async fn listening() -> (Arc<Network>, String){
+        let network = Arc::new(Network::new());
+        let listener = network.clone();
+        tokio::spawn(async move { listener.start_listener("127.0.0.1:0").await });
+        for _ in 0..100 {
+            if let Some(port) = network.listen_port() {
+                return (network, format!("127.0.0.1:{}", port));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("listener did not start");
+    }
+
+    // Wait for `network` to have `count` peers and return their addresses
+    async fn wait_for_peers(network: &Network, count: usize) -> Vec<String>{
+        for _ in 0..100 {
+            let addrs = network.peer_addrs().await;
+            if addrs.len() >= count {
+                return addrs;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("expected {} peers, have {:?}", count, network.peer_addrs().await);
+    }
+
+    #[tokio::test]
+    async fn test_inbound_peer_keyed_by_its_connection() {
+        let (a, a_addr) = listening().await;
+        let (b, b_addr) = listening().await;
+
+        b.connect_to_peer(&a_addr).await.unwrap();
+        assert_eq!(b.peer_addrs().await, vec![a_addr.clone()]);
+
+        // a knows b by the connection b dialed from, and can dial b back on its listen address
+        let keys = wait_for_peers(&a, 1).await;
+        assert_ne!(keys, vec![b_addr.clone()]);
+        assert_eq!(a.listen_addrs().await, vec![b_addr]);
+    }
+
+    #[tokio::test]
+    async fn test_connection_claiming_a_listen_port_does_not_take_over_a_peer() {
+        let (a, a_addr) = listening().await;
+        let (b, b_addr) = listening().await;
+        b.connect_to_peer(&a_addr).await.unwrap();
+        wait_for_peers(&a, 1).await;
+
+        // another connection from the same host advertising b's listen port
+        let port = b_addr.rsplit(':').next().unwrap().parse().unwrap();
+        let local = VersionMessage::new(ChainConfig::default().chain_id, 0, b.nonce.wrapping_add(1)).with_listen_port(port);
+        let mut socket = TcpStream::connect(&a_addr).await.unwrap();
+        Network::handshake(&mut socket, &local).await.unwrap();
+        write_message(&mut socket, &NetworkMessage::new_fee_filter(1_000)).await.unwrap();
+
+        assert_eq!(wait_for_peers(&a, 2).await.len(), 2);
+        b.broadcast_message(&NetworkMessage::new_fee_filter(50)).await.unwrap();
+        for _ in 0..100 {
+            let mut fee_filters: Vec<_> = a.peer_info().await.iter().map(|info| info.fee_filter).collect();
+            fee_filters.sort();
+            if fee_filters == vec![50, 1_000] {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("fee filters were mixed up: {:?}", a.peer_info().await);
+    }
+
+    #[tokio::test]
+    async fn test_fee_filter_from_inbound_peer_is_applied() {
+        let (a, a_addr) = listening().await;
+        let (b, _) = listening().await;
+        b.connect_to_peer(&a_addr).await.unwrap();
+
+        // sent on the connection the handshake opened, not a new one
+        b.broadcast_message(&NetworkMessage::new_fee_filter(50)).await.unwrap();
+        b.broadcast_message(&NetworkMessage::Ping(1)).await.unwrap();
+
+        for _ in 0..100 {
+            let fee_filters: Vec<_> = a.peer_info().await.iter().map(|info| info.fee_filter).collect();
+            if fee_filters == vec![50] {
+                return;
             }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("fee filter was not applied: {:?}", a.peer_info().await);
+    }
+
+    #[tokio::test]
+    async fn test_messages_before_handshake_are_refused() {
+        let (a, a_addr) = listening().await;
+        let mut socket = TcpStream::connect(&a_addr).await.unwrap();
+        write_message(&mut socket, &NetworkMessage::new_fee_filter(0)).await.unwrap();
+
+        // the connection is closed without a peer being registered
+        assert!(matches!(read_message(&mut socket).await, Ok(None) | Err(_)));
+        assert!(a.peer_addrs().await.is_empty());
+        assert!(a.bans().score(socket.local_addr().unwrap().ip()) > 0);
+    }
+
+    #[tokio::test]
+    async fn test_relay_skips_unreachable_peers() {
+        let (_a, a_addr) = listening().await;
+        let b = Network::new();
+        b.connect_to_peer(&a_addr).await.unwrap();
+
+        // a peer whose connection is gone
+        let gone = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut peer = Peer::new(gone);
+        peer.protocol_version = PROTOCOL_VERSION;
+        b.peers.write().await.insert(gone.to_string(), peer);
+
+        let relayed = b.relay_to_accepting_peers(&NetworkMessage::Pong(7), 0).await;
+        assert_eq!(relayed, 1);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_negotiates_with_listener() {
+        let (a, a_addr) = listening().await;
+        let local = VersionMessage::new(ChainConfig::default().chain_id, 0, a.nonce.wrapping_add(1));
+
+        let mut socket = TcpStream::connect(&a_addr).await.unwrap();
+        let (version, remote) = Network::handshake(&mut socket, &local).await.unwrap();
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert_eq!(remote.nonce, a.nonce);
+        assert_eq!(remote.listen_port, a.listen_port());
+
+        // the connection carries messages once the handshake is done
+        write_message(&mut socket, &NetworkMessage::Ping(1)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_refused_on_another_chain() {
+        let (a, a_addr) = listening().await;
+        let local = VersionMessage::new(ChainConfig::default().chain_id.wrapping_add(1), 0, a.nonce.wrapping_add(1));
+
+        let mut socket = TcpStream::connect(&a_addr).await.unwrap();
+        match Network::handshake(&mut socket, &local).await {
+            Err(NetworkError::Handshake(reason)) => assert!(reason.contains("refused"), "{}", reason),
+            other => panic!("expected a refused handshake, got {:?}", other.map(|(version, _)| version)),
         }
     }
+
+    #[tokio::test]
+    async fn test_handshake_detects_dialing_ourselves() {
+        let (a, a_addr) = listening().await;
+        let local = a.version_message().await;
+        let mut socket = TcpStream::connect(&a_addr).await.unwrap();
+        assert!(matches!(Network::handshake(&mut socket, &local).await, Err(NetworkError::Handshake(_))));
+    }
 }
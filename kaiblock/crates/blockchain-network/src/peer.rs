@@ -1,8 +1,23 @@
 use std::net::SocketAddr;
+use serde::Serialize;
+use crate::bandwidth::PeerBandwidth;
+use tokio::sync::mpsc;
+use crate::message::{MessageType, NetworkMessage, MIN_PROTOCOL_VERSION};
 
 #[derive(Clone, Debug)]
 pub struct Peer{
     pub add: SocketAddr,
+    /// Minimum fee per byte this peer asked us to relay (0 = no filter)
+    pub fee_filter: u64,
+    /// Protocol version agreed in the handshake
+    pub protocol_version: u32,
+    /// What the peer said it runs, once the handshake is done
+    pub user_agent: Option<String>,
+    /// Where the peer accepts connections: the address we dialed, or the
+    /// listen port an inbound peer advertised (None: it doesn't listen)
+    pub listen_addr: Option<SocketAddr>,
+    /// Queue to the task writing to the peer's connection (None: not connected)
+    pub outbound: Option<mpsc::Sender<NetworkMessage>>,
 }
 
 
@@ -10,6 +25,38 @@ impl Peer{
     pub fn new(addr: SocketAddr) -Self{
         Self{
             add: addr,
+            fee_filter: 0,
+            protocol_version: MIN_PROTOCOL_VERSION,
+            user_agent: None,
+            listen_addr: None,
+            outbound: None,
         }
     }
-}
\ No newline at end of file
+
+    /// Queue a message on the peer's connection without waiting; false if
+    /// the connection is gone or too far behind to take more
+    pub fn send(&self, msg: NetworkMessage) -> bool{
+        self.outbound.as_ref().is_some_and(|outbound| outbound.try_send(msg).is_ok())
+    }
+
+    /// Whether the peer's protocol version has this kind of message
+    pub fn supports(&self, msg_type: MessageType) -> bool{
+        self.protocol_version >= msg_type.min_protocol_version()
+    }
+
+    /// Whether a transaction with this fee-rate should be announced to the peer
+    pub fn accepts_fee_rate(&self, fee_per_byte: u64) -> bool{
+        fee_per_byte >= self.fee_filter
+    }
+}
+
+
+/// Peer details reported over RPC
+#[derive(Clone, Debug, Serialize)]
+pub struct PeerInfo{
+    pub addr: String,
+    pub fee_filter: u64,
+    pub protocol_version: u32,
+    pub user_agent: Option<String>,
+    pub bandwidth: PeerBandwidth,
+}
@@ -0,0 +1,498 @@
+use serde::{Serialize, Deserialize};
+use tokio::net::TcpStream;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{BandwidthManager, MessageType, Misbehavior, Network, NetworkMessage, NetworkError, VersionMessage};
+use crate::message::read_message;
+use blockchain_core::block::{Block, BlockHeader};
+use blockchain_core::codec::{Decode, Encode, Reader};
+use blockchain_core::sync::{check_checkpoints, validate_header_chain, SyncStage, SyncStatus, MAX_BLOCKS_PER_REQUEST, MAX_HEADERS_PER_REQUEST};
+use blockchain_core::{check_body_commitment, BlockId, Blockchain, BlockchainError};
+use tracing::{info_span, warn, Instrument};
+
+
+/// Payload of a GetHeaders message
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HeadersRequest {
+    pub start_height: u64,
+    pub max_count: u32,
+}
+
+/// Payload of a GetHeadersByLocator message
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LocatorRequest {
+    /// the sender's main chain ids, newest first (see `Blockchain::block_locator`)
+    pub locator: Vec<BlockId>,
+    pub max_count: u32,
+}
+
+/// Payload of a Headers message
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HeadersResponse {
+    /// height of the responder's main chain
+    pub tip_height: u64,
+    pub headers: Vec<BlockHeader>,
+}
+
+impl Encode for HeadersRequest {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.start_height.encode_to(out);
+        self.max_count.encode_to(out);
+    }
+}
+
+impl Decode for HeadersRequest {
+    fn decode_from(reader: &mut Reader<'_>) -> blockchain_core::Result<Self> {
+        Ok(Self {
+            start_height: u64::decode_from(reader)?,
+            max_count: u32::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for LocatorRequest {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.locator.encode_to(out);
+        self.max_count.encode_to(out);
+    }
+}
+
+impl Decode for LocatorRequest {
+    fn decode_from(reader: &mut Reader<'_>) -> blockchain_core::Result<Self> {
+        Ok(Self {
+            locator: Vec::decode_from(reader)?,
+            max_count: u32::decode_from(reader)?,
+        })
+    }
+}
+
+impl Encode for HeadersResponse {
+    fn encode_to(&self, out: &mut Vec<u8>) {
+        self.tip_height.encode_to(out);
+        self.headers.encode_to(out);
+    }
+}
+
+impl Decode for HeadersResponse {
+    fn decode_from(reader: &mut Reader<'_>) -> blockchain_core::Result<Self> {
+        Ok(Self {
+            tip_height: u64::decode_from(reader)?,
+            headers: Vec::decode_from(reader)?,
+        })
+    }
+}
+
+
+/// Tuning for initial block download
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    /// block body requests in flight at once, spread across peers
+    pub max_parallel_requests: usize,
+    /// how long to wait for a peer to answer one request
+    pub request_timeout: Duration,
+    /// pause between sync rounds once caught up
+    pub poll_interval: Duration,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            max_parallel_requests: 8,
+            request_timeout: Duration::from_secs(30),
+            poll_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+
+/// Headers-first initial block download.
+///
+/// Each round sends every peer our block locator and follows the peer
+/// reporting the highest chain, falling back to the next highest when its
+/// headers don't check out. Headers are checked for linkage and proof of
+/// work before any body is fetched; bodies are then downloaded from all peers
+/// in parallel and appended in height order through `Blockchain::add_block`,
+/// which does the full validation. Peers that serve invalid headers or blocks
+/// are penalized, and skipped once banned.
+pub struct SyncManager {
+    network: Arc<Network>,
+    chain: Arc<RwLock<Blockchain>>,
+    status: SyncStatus,
+    config: SyncConfig,
+}
+
+impl SyncManager {
+    pub fn new(network: Arc<Network>, chain: Arc<RwLock<Blockchain>>, status: SyncStatus, config: SyncConfig) -> Self {
+        Self { network, chain, status, config }
+    }
+
+    /// Shared progress handle (hand this to RPC)
+    pub fn status(&self) -> SyncStatus {
+        self.status.clone()
+    }
+
+    /// Keep syncing until the task is dropped, pausing between rounds
+    pub async fn run(&self) {
+        loop {
+            if let Err(e) = self.sync().instrument(info_span!("sync")).await {
+                warn!(error = %e, "sync round failed");
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Download until caught up with the best peer; returns the number of blocks added
+    pub async fn sync(&self) -> Result<u64, NetworkError> {
+        let mut peers = self.sync_peers().await;
+        let current_height = self.chain.read().await.height();
+        self.status.update(|p| {
+            p.peers = peers.len();
+            p.current_height = current_height;
+        });
+
+        if peers.is_empty() {
+            self.status.update(|p| p.stage = SyncStage::Idle);
+            return Ok(0);
+        }
+
+        let mut added = 0;
+        loop {
+            self.status.update(|p| p.stage = SyncStage::Headers);
+            let (best_peer, tip_height, headers) = match self.best_headers(&peers).await {
+                Some(best) => best,
+                None => break,
+            };
+            self.status.update(|p| p.target_height = p.target_height.max(tip_height));
+            if headers.is_empty() {
+                break;
+            }
+            let header_count = headers.len() as u64;
+            self.status.update(|p| p.headers_downloaded += header_count);
+            // lets the chain tell which of these blocks lead to the assume-valid block
+            self.chain.write().await.note_headers(&headers);
+
+            self.status.update(|p| p.stage = SyncStage::Blocks);
+            added += self.download_blocks(&peers, &best_peer, &headers).await?;
+
+            if headers.len() < MAX_HEADERS_PER_REQUEST {
+                break;
+            }
+
+            // peers banned during the round aren't asked again
+            peers = self.sync_peers().await;
+            if peers.is_empty() {
+                break;
+            }
+        }
+
+        let current_height = self.chain.read().await.height();
+        self.status.update(|p| {
+            p.current_height = current_height;
+            p.stage = if current_height >= p.target_height { SyncStage::Synced } else { SyncStage::Idle };
+        });
+        Ok(added)
+    }
+
+
+    // Peers to sync from: the ones we can dial (requests go over connections
+    // of their own) that aren't banned
+    async fn sync_peers(&self) -> Vec<String> {
+        let bans = self.network.bans();
+        self.network.listen_addrs().await.into_iter()
+            .filter(|peer| peer.parse::<SocketAddr>().is_ok_and(|addr| !bans.is_banned(addr.ip())))
+            .collect()
+    }
+
+
+    // Count `misbehavior` against the host behind a sync peer's address
+    fn penalize(&self, peer: &str, misbehavior: Misbehavior) {
+        if let Ok(addr) = peer.parse::<SocketAddr>() {
+            if self.network.bans().penalize(addr.ip(), misbehavior) {
+                warn!(%peer, ?misbehavior, "banning sync peer");
+            }
+        }
+    }
+
+
+    // Send every peer our locator and take the headers of the highest chain
+    // that check out, falling back peer by peer. Returns the peer, the tip
+    // height it reported and its headers we don't have yet
+    async fn best_headers(&self, peers: &[String]) -> Option<(String, u64, Vec<BlockHeader>)> {
+        let (locator, start_height) = {
+            let chain = self.chain.read().await;
+            (chain.block_locator(), chain.height() + 1)
+        };
+        let local = self.network.version_message().await;
+
+        let mut responses = Vec::new();
+        for peer in peers {
+            // peers from before locators get the headers after our tip by height
+            let request = |version: u32| {
+                if version >= MessageType::GetHeadersByLocator.min_protocol_version() {
+                    NetworkMessage::new_get_headers_by_locator(locator.clone(), MAX_HEADERS_PER_REQUEST as u32)
+                } else {
+                    NetworkMessage::new_get_headers(start_height, MAX_HEADERS_PER_REQUEST as u32)
+                }
+            };
+            match request_from(peer, &local, request, &self.network.bandwidth(), self.config.request_timeout).await {
+                Ok(NetworkMessage::Headers(response)) => responses.push((peer.clone(), response)),
+                Ok(_) => {}
+                Err(e) => warn!(%peer, error = %e, "header request failed"),
+            }
+        }
+        responses.sort_by(|(_, a), (_, b)| b.tip_height.cmp(&a.tip_height));
+
+        for (peer, response) in responses {
+            if response.headers.is_empty() {
+                continue;
+            }
+            let chain = self.chain.read().await;
+            match new_headers(&chain, response.headers) {
+                Ok(headers) => return Some((peer, response.tip_height, headers)),
+                Err(e) => {
+                    drop(chain);
+                    warn!(%peer, error = %e, "bad headers");
+                    self.penalize(&peer, Misbehavior::InvalidHeaders);
+                }
+            }
+        }
+        None
+    }
+
+
+    // Fetch bodies for `headers` in chunks spread over all peers and append
+    // them in order. A body that doesn't match its header is asked again from
+    // the next peer; a matching block the chain rejects means the headers
+    // from `headers_peer` were for an invalid chain
+    async fn download_blocks(&self, peers: &[String], headers_peer: &str, headers: &[BlockHeader]) -> Result<u64, NetworkError> {
+        let first_height = headers[0].height;
+        let expected: Vec<BlockId> = headers.iter().map(BlockHeader::id).collect();
+        let local = self.network.version_message().await;
+
+        // (chunk start index, attempts so far)
+        let mut queue: VecDeque<(usize, usize)> = (0..expected.len())
+            .step_by(MAX_BLOCKS_PER_REQUEST)
+            .map(|start| (start, 0))
+            .collect();
+        let mut downloads = JoinSet::new();
+        let mut received: BTreeMap<u64, Block> = BTreeMap::new();
+        let mut next_height = first_height;
+        let mut added = 0;
+
+        while !queue.is_empty() || !downloads.is_empty() {
+            while downloads.len() < self.config.max_parallel_requests.max(1) {
+                let (start, attempts) = match queue.pop_front() {
+                    Some(chunk) => chunk,
+                    None => break,
+                };
+                if attempts >= peers.len() {
+                    return Err(NetworkError::SyncError(format!(
+                        "no peer served blocks from height {}", first_height + start as u64
+                    )));
+                }
+
+                let peer = peers[(start / MAX_BLOCKS_PER_REQUEST + attempts) % peers.len()].clone();
+                let ids = expected[start..(start + MAX_BLOCKS_PER_REQUEST).min(expected.len())].to_vec();
+                let timeout = self.config.request_timeout;
+                let bandwidth = self.network.bandwidth();
+                let local = local.clone();
+                downloads.spawn(async move {
+                    let request = NetworkMessage::new_get_blocks(&ids);
+                    let blocks = match request_from(&peer, &local, |_| request, &bandwidth, timeout).await {
+                        Ok(NetworkMessage::Blocks(blocks)) => Some(blocks),
+                        _ => None,
+                    };
+                    let blocks = blocks.filter(|blocks| blocks.iter().map(Block::id).eq(ids.iter().copied()));
+                    (start, attempts, peer, blocks)
+                });
+            }
+
+            let (start, attempts, peer, blocks) = match downloads.join_next().await {
+                Some(Ok(result)) => result,
+                Some(Err(e)) => return Err(NetworkError::SyncError(e.to_string())),
+                None => break,
+            };
+
+            match blocks {
+                Some(blocks) if blocks.iter().all(|block| check_body_commitment(block).is_ok()) => {
+                    for block in blocks {
+                        received.insert(block.header.height, block);
+                    }
+                }
+                // the ids match, so the peer sent bodies that aren't the blocks'
+                Some(_) => {
+                    warn!(%peer, "block bodies don't match their headers");
+                    self.penalize(&peer, Misbehavior::MismatchedBlockBody);
+                    queue.push_back((start, attempts + 1));
+                }
+                // missing, partial or mismatched reply: try the next peer
+                None => queue.push_back((start, attempts + 1)),
+            }
+
+            // append whatever is now contiguous
+            if received.contains_key(&next_height) {
+                let mut chain = self.chain.write().await;
+                let mut appended = 0;
+                while let Some(block) = received.remove(&next_height) {
+                    if let Err(e) = chain.add_block(block) {
+                        drop(chain);
+                        self.penalize(headers_peer, Misbehavior::InvalidBlock);
+                        return Err(NetworkError::SyncError(format!("block {} from {} rejected: {}", next_height, headers_peer, e)));
+                    }
+                    next_height += 1;
+                    appended += 1;
+                }
+                let current_height = chain.height();
+                drop(chain);
+
+                added += appended;
+                self.status.update(|p| {
+                    p.current_height = current_height;
+                    p.blocks_downloaded += appended;
+                });
+            }
+        }
+
+        Ok(added)
+    }
+}
+
+
+// Check that `headers` connect to a block we have, link up with valid proof
+// of work and respect our checkpoints; returns the ones we don't have yet
+fn new_headers(chain: &Blockchain, headers: Vec<BlockHeader>) -> blockchain_core::Result<Vec<BlockHeader>> {
+    let parent = chain.get_block(&headers[0].prev_block_hash)
+        .ok_or_else(|| BlockchainError::InvalidChain("headers don't connect to our chain".to_string()))?;
+    validate_header_chain(parent.id(), parent.header.height, &headers, chain.validation_rules().block_hash)?;
+    check_checkpoints(chain.checkpoints(), &headers)?;
+    Ok(headers.into_iter()
+        .skip_while(|header| chain.get_block(&header.id()).is_some())
+        .collect())
+}
+
+
+// One request/response exchange on a connection of its own, which like any
+// other starts with a handshake and is closed once the reply is in. The
+// request is built for the protocol version the handshake agreed on
+async fn request_from<F: FnOnce(u32) -> NetworkMessage>(
+    addr: &str,
+    local: &VersionMessage,
+    request: F,
+    bandwidth: &BandwidthManager,
+    timeout: Duration,
+) -> Result<NetworkMessage, NetworkError> {
+    let exchange = async {
+        let mut socket = TcpStream::connect(addr).await?;
+        let (version, _) = Network::handshake(&mut socket, local).await?;
+        let msg = request(version);
+        let data = msg.to_frame();
+        bandwidth.throttle_upload(addr, msg.msg_type(), data.len()).await;
+        socket.write_all(&data).await?;
+
+        // the peer now treats us like any other, so relays may come before the reply
+        loop {
+            let (reply_msg, n) = read_message(&mut socket).await?
+                .ok_or_else(|| NetworkError::SyncError(format!("{} closed without replying", addr)))?;
+            bandwidth.throttle_download(addr, reply_msg.msg_type(), n).await;
+            if matches!(reply_msg, NetworkMessage::Headers(_) | NetworkMessage::Blocks(_)) {
+                return Ok(reply_msg);
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout, exchange).await
+        .map_err(|_| NetworkError::SyncError(format!("request to {} timed out", addr)))?
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::write_message;
+    use blockchain_core::{Address, AddressType, ChainConfig, Hash256};
+    use tokio::net::TcpListener;
+
+    // Chains that share a genesis block
+    fn chain() -> Arc<RwLock<Blockchain>> {
+        let mut config = ChainConfig::default();
+        config.genesis.timestamp = Some(1_700_000_000);
+        Arc::new(RwLock::new(Blockchain::new(config).unwrap()))
+    }
+
+    // A network serving `chain` on a free local port, and the address it listens on
+    async fn listening(chain: Arc<RwLock<Blockchain>>) -> (Arc<Network>, String) {
+        let network = Arc::new(Network::new().with_chain(chain));
+        let listener = network.clone();
+        tokio::spawn(async move { listener.start_listener("127.0.0.1:0").await });
+        for _ in 0..100 {
+            if let Some(port) = network.listen_port() {
+                return (network, format!("127.0.0.1:{}", port));
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("listener did not start");
+    }
+
+    // A peer claiming a far longer chain that answers header requests with `header`
+    async fn lying_peer(header: BlockHeader) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let header = header.clone();
+                tokio::spawn(async move {
+                    let local = VersionMessage::new(ChainConfig::default().chain_id, 1_000, rand::random()).with_listen_port(addr.port());
+                    while let Ok(Some((msg, _))) = read_message(&mut socket).await {
+                        let reply = match msg {
+                            NetworkMessage::Version(_) => {
+                                let _ = write_message(&mut socket, &NetworkMessage::Version(local.clone())).await;
+                                NetworkMessage::VerAck
+                            }
+                            NetworkMessage::GetHeaders(_) | NetworkMessage::GetHeadersByLocator(_) => {
+                                NetworkMessage::new_headers(1_000, vec![header.clone()])
+                            }
+                            _ => continue,
+                        };
+                        if write_message(&mut socket, &reply).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        addr.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_sync_falls_back_when_the_best_peer_sends_bad_headers() {
+        let honest_chain = chain();
+        let miner = Address::from_hash(Hash256::from_bytes([9; 32]), AddressType::Base58);
+        for _ in 0..3 {
+            honest_chain.write().await.mine_block(miner.clone()).unwrap();
+        }
+        let mut header = honest_chain.read().await.get_headers(1, 1).remove(0);
+        header.prev_block_hash = BlockId::new(Hash256::from_bytes([7; 32]));
+        let (_honest, honest_addr) = listening(honest_chain).await;
+        let liar_addr = lying_peer(header).await;
+
+        let local_chain = chain();
+        let network = Arc::new(Network::new().with_chain(local_chain.clone()));
+        network.connect_to_peer(&liar_addr).await.unwrap();
+        network.connect_to_peer(&honest_addr).await.unwrap();
+
+        // the liar reports the higher tip, but its headers don't connect to anything
+        let sync = SyncManager::new(network.clone(), local_chain.clone(), SyncStatus::new(), SyncConfig::default());
+        assert_eq!(sync.sync().await.unwrap(), 3);
+        assert_eq!(local_chain.read().await.height(), 3);
+
+        // both peers are on localhost, so the penalty shows up on its address
+        let localhost = "127.0.0.1".parse().unwrap();
+        assert_eq!(network.bans().score(localhost), Misbehavior::InvalidHeaders.score());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::PrimitivesError;
+use core::fmt;
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+
+#[cfg(feature = "alloc")]
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+
+/// 256-bit hash value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Hash256([u8; 32]);
+
+impl Hash256 {
+    /// Create a new hash from 32 bytes
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Create a hash from a byte slice
+    pub fn from_slice(slice: &[u8]) -> Result<Self, PrimitivesError> {
+        let bytes: [u8; 32] = slice.try_into()
+            .map_err(|_| PrimitivesError::InvalidLength { expected: 32, got: slice.len() })?;
+        Ok(Self(bytes))
+    }
+
+    /// Create a hash from hex string, with or without 0x
+    pub fn from_hex(hex_str: &str) -> Result<Self, PrimitivesError> {
+        let hex_str = hex_str.strip_prefix("0x").unwrap_or(hex_str).as_bytes();
+        if hex_str.len() != 64 {
+            return Err(PrimitivesError::InvalidLength { expected: 32, got: hex_str.len() / 2 });
+        }
+
+        let mut bytes = [0u8; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex_str.chunks_exact(2)) {
+            *byte = (hex_value(pair[0])? << 4) | hex_value(pair[1])?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// Get the underlying bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Convert to byte slice
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Convert to hex string
+    #[cfg(feature = "alloc")]
+    pub fn to_hex(&self) -> String {
+        let mut hex = String::with_capacity(64);
+        for byte in self.0 {
+            hex.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+            hex.push(HEX_DIGITS[(byte & 0x0f) as usize] as char);
+        }
+        hex
+    }
+
+    /// Convert to hex string with 0x prefix
+    #[cfg(feature = "alloc")]
+    pub fn to_hex_prefixed(&self) -> String {
+        alloc::format!("0x{}", self.to_hex())
+    }
+
+    /// Create a zero hash
+    pub fn zero() -> Self {
+        Self([0u8; 32])
+    }
+
+    /// Check if hash is zero
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+}
+
+impl Default for Hash256 {
+    fn default() -> Self {
+        Self::zero()
+    }
+}
+
+impl fmt::Display for Hash256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<[u8; 32]> for Hash256 {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Hash256 {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+fn hex_value(digit: u8) -> Result<u8, PrimitivesError> {
+    match digit {
+        b'0'..=b'9' => Ok(digit - b'0'),
+        b'a'..=b'f' => Ok(digit - b'a' + 10),
+        b'A'..=b'F' => Ok(digit - b'A' + 10),
+        _ => Err(PrimitivesError::InvalidHex),
+    }
+}
+
+
+/// SHA-256 hash function wrapper
+pub fn sha256(data: &[u8]) -> Hash256 {
+    Hash256::from_bytes(Sha256::digest(data).into())
+}
+
+/// Double SHA-256 hash (commonly used in Bitcoin)
+pub fn double_sha256(data: &[u8]) -> Hash256 {
+    sha256(sha256(data).as_bytes())
+}
+
+/// Hash multiple data pieces together
+pub fn hash_combine(data: &[&[u8]]) -> Hash256 {
+    let mut hasher = Sha256::new();
+    for chunk in data {
+        hasher.update(chunk);
+    }
+    Hash256::from_bytes(hasher.finalize().into())
+}
+
+/// Difficulty of a hash: its number of leading zero bits
+pub fn hash_difficulty(hash: &Hash256) -> u32 {
+    let mut difficulty = 0;
+    for &byte in hash.as_bytes() {
+        difficulty += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    difficulty
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_known_value() {
+        let expected = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        assert_eq!(sha256(b"hello world").to_hex(), expected);
+        assert_eq!(hash_combine(&[b"hello ", b"world"]), sha256(b"hello world"));
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let hash = sha256(b"round trip");
+        assert_eq!(Hash256::from_hex(&hash.to_hex()).unwrap(), hash);
+        assert_eq!(Hash256::from_hex(&hash.to_hex_prefixed()).unwrap(), hash);
+        assert_eq!(Hash256::from_hex(&hash.to_hex().to_uppercase()).unwrap(), hash);
+        assert_eq!(alloc::format!("{}", hash), hash.to_hex());
+
+        assert_eq!(Hash256::from_hex("00"), Err(PrimitivesError::InvalidLength { expected: 32, got: 1 }));
+        assert_eq!(Hash256::from_hex(&"zz".repeat(32)), Err(PrimitivesError::InvalidHex));
+        assert!(Hash256::from_slice(&[0u8; 31]).is_err());
+    }
+
+    #[test]
+    fn test_hash_difficulty_counts_leading_zero_bits() {
+        let mut bytes = [0xffu8; 32];
+        assert_eq!(hash_difficulty(&Hash256::from_bytes(bytes)), 0);
+        bytes[0] = 0;
+        bytes[1] = 0x0f;
+        assert_eq!(hash_difficulty(&Hash256::from_bytes(bytes)), 12);
+        assert_eq!(hash_difficulty(&Hash256::zero()), 256);
+    }
+}
@@ -0,0 +1,415 @@
+//! Block headers as the node encodes and hashes them.
+//!
+//! Headers use the node's canonical codec (`blockchain_core::codec`): the
+//! [`CODEC_VERSION`] byte, then the fields in order with integers
+//! little-endian, the timestamp as i64 seconds and u32 nanoseconds, the bloom
+//! as its raw bytes and the consensus data with a u32 length prefix.
+
+use crate::hash::{hash_difficulty, Hash256};
+use crate::{PrimitivesError, CODEC_VERSION};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use sha2::{Digest, Sha256};
+
+
+/// Size of a header's log bloom filter
+pub const BLOOM_BYTES: usize = 256;
+
+const SECS_PER_DAY: i64 = 86_400;
+//years either side of 0 the node's clock (chrono) can hold
+const MAX_YEAR: i64 = 262_143;
+
+
+/// UTC time since the Unix epoch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    pub secs: i64,
+    /// below 1_000_000_000
+    pub nanos: u32,
+}
+
+impl Timestamp {
+    pub fn from_unix(secs: i64, nanos: u32) -> Self {
+        Self { secs, nanos }
+    }
+
+    /// Parse RFC 3339 text as the node displays it, e.g. `2024-05-01T12:00:00.250Z`
+    pub fn parse(text: &str) -> Result<Self, PrimitivesError> {
+        let invalid = PrimitivesError::InvalidHeader("malformed timestamp");
+        let text = text.strip_suffix('Z').ok_or(invalid.clone())?;
+        let (date, time) = text.split_once('T').ok_or(invalid.clone())?;
+
+        let (negative, date) = match date.as_bytes().first() {
+            Some(b'-') => (true, &date[1..]),
+            Some(b'+') => (false, &date[1..]),
+            _ => (false, date),
+        };
+        let mut date_parts = date.splitn(3, '-');
+        let next_number = |parts: &mut core::str::SplitN<'_, char>| -> Result<i64, PrimitivesError> {
+            let part = parts.next().ok_or(invalid.clone())?;
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid.clone());
+            }
+            part.parse::<i64>().map_err(|_| invalid.clone())
+        };
+        let year = next_number(&mut date_parts)?;
+        let month = next_number(&mut date_parts)?;
+        let day = next_number(&mut date_parts)?;
+        let year = if negative { -year } else { year };
+
+        let (clock, fraction) = time.split_once('.').unwrap_or((time, ""));
+        let mut clock_parts = clock.splitn(3, ':');
+        let hour = next_number(&mut clock_parts)?;
+        let minute = next_number(&mut clock_parts)?;
+        let second = next_number(&mut clock_parts)?;
+        if year.abs() > MAX_YEAR || !(1..=12).contains(&month) || !(1..=31).contains(&day)
+            || hour > 23 || minute > 59 || second > 59 {
+            return Err(invalid);
+        }
+
+        if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid);
+        }
+        let nanos = fraction.bytes()
+            .chain(core::iter::repeat(b'0'))
+            .take(9)
+            .fold(0u32, |nanos, digit| nanos * 10 + u32::from(digit - b'0'));
+
+        let days = days_from_civil(year, month as u32, day as u32);
+        Ok(Self { secs: days * SECS_PER_DAY + hour * 3600 + minute * 60 + second, nanos })
+    }
+}
+
+/// RFC 3339 in UTC with as many fractional digits as needed (none, 3, 6 or 9),
+/// the same text chrono writes for a `DateTime<Utc>`
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (year, month, day) = civil_from_days(self.secs.div_euclid(SECS_PER_DAY));
+        let second_of_day = self.secs.rem_euclid(SECS_PER_DAY);
+
+        if (0..=9999).contains(&year) {
+            write!(f, "{:04}", year)?;
+        } else {
+            write!(f, "{:+05}", year)?;
+        }
+        write!(
+            f, "-{:02}-{:02}T{:02}:{:02}:{:02}",
+            month, day, second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60
+        )?;
+
+        let nanos = self.nanos;
+        if nanos == 0 {
+        } else if nanos.is_multiple_of(1_000_000) {
+            write!(f, ".{:03}", nanos / 1_000_000)?;
+        } else if nanos.is_multiple_of(1_000) {
+            write!(f, ".{:06}", nanos / 1_000)?;
+        } else {
+            write!(f, ".{:09}", nanos)?;
+        }
+        f.write_char('Z')
+    }
+}
+
+
+/// Header of a block, as hashed by the node
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    /// id (hash) of the previous block's header
+    pub prev_block_hash: Hash256,
+    pub merkle_root: Hash256,
+    pub timestamp: Timestamp,
+    /// leading zero bits the header hash must have
+    pub difficulty: u64,
+    pub nonce: u64,
+    pub height: u64,
+    pub tx_count: u32,
+    pub size: u32,
+    pub chain_id: u32,
+    pub logs_bloom: [u8; BLOOM_BYTES],
+    /// engine-specific proof, not covered by the signing hash
+    pub consensus_data: Vec<u8>,
+}
+
+impl BlockHeader {
+    /// Bytes the node hashes for this header
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(1 + 4 + 32 + 32 + 12 + 8 * 3 + 4 * 3 + BLOOM_BYTES + 4 + self.consensus_data.len());
+        self.write(true, &mut |chunk| bytes.extend_from_slice(chunk));
+        bytes
+    }
+
+    /// Read a header from the node's encoding, which must be used up exactly
+    pub fn decode(bytes: &[u8]) -> Result<Self, PrimitivesError> {
+        let mut reader = Reader(bytes);
+        if reader.array::<1>()?[0] != CODEC_VERSION {
+            return Err(PrimitivesError::InvalidHeader("unsupported codec version"));
+        }
+        let header = Self {
+            version: u32::from_le_bytes(reader.array()?),
+            prev_block_hash: Hash256::from_bytes(reader.array()?),
+            merkle_root: Hash256::from_bytes(reader.array()?),
+            timestamp: {
+                let secs = i64::from_le_bytes(reader.array()?);
+                let nanos = u32::from_le_bytes(reader.array()?);
+                if nanos >= 1_000_000_000 {
+                    return Err(PrimitivesError::InvalidHeader("timestamp nanoseconds out of range"));
+                }
+                Timestamp::from_unix(secs, nanos)
+            },
+            difficulty: u64::from_le_bytes(reader.array()?),
+            nonce: u64::from_le_bytes(reader.array()?),
+            height: u64::from_le_bytes(reader.array()?),
+            tx_count: u32::from_le_bytes(reader.array()?),
+            size: u32::from_le_bytes(reader.array()?),
+            chain_id: u32::from_le_bytes(reader.array()?),
+            logs_bloom: reader.array()?,
+            consensus_data: reader.prefixed()?.to_vec(),
+        };
+        if !reader.0.is_empty() {
+            return Err(PrimitivesError::InvalidHeader("trailing bytes"));
+        }
+        Ok(header)
+    }
+
+    /// Header hash, which is also the block id
+    pub fn hash(&self) -> Hash256 {
+        self.hash_with(true)
+    }
+
+    /// Hash a block producer signs: the header without its consensus data
+    pub fn signing_hash(&self) -> Hash256 {
+        self.hash_with(false)
+    }
+
+    /// Whether the header hash has the leading zero bits its difficulty asks for
+    pub fn meets_difficulty(&self) -> bool {
+        u64::from(hash_difficulty(&self.hash())) >= self.difficulty
+    }
+
+    /// Check `self` can follow `parent`: it links to the parent's hash, is
+    /// one higher, is on the same chain and carries its proof of work.
+    /// Difficulty retargets need more than two headers and aren't checked.
+    pub fn verify_extends(&self, parent: &BlockHeader) -> Result<(), PrimitivesError> {
+        if self.prev_block_hash != parent.hash() {
+            return Err(PrimitivesError::InvalidHeader("does not link to the parent hash"));
+        }
+        if parent.height.checked_add(1) != Some(self.height) {
+            return Err(PrimitivesError::InvalidHeader("height is not one above the parent"));
+        }
+        if self.chain_id != parent.chain_id {
+            return Err(PrimitivesError::InvalidHeader("chain id differs from the parent"));
+        }
+        if !self.meets_difficulty() {
+            return Err(PrimitivesError::InvalidHeader("does not meet its difficulty target"));
+        }
+        Ok(())
+    }
+
+    fn hash_with(&self, consensus_data: bool) -> Hash256 {
+        let mut hasher = Sha256::new();
+        self.write(consensus_data, &mut |chunk| hasher.update(chunk));
+        Hash256::from_bytes(hasher.finalize().into())
+    }
+
+    //stream the encoding into `out`, optionally with the consensus data left empty
+    fn write(&self, consensus_data: bool, out: &mut dyn FnMut(&[u8])) {
+        out(&[CODEC_VERSION]);
+        out(&self.version.to_le_bytes());
+        out(self.prev_block_hash.as_bytes());
+        out(self.merkle_root.as_bytes());
+        out(&self.timestamp.secs.to_le_bytes());
+        out(&self.timestamp.nanos.to_le_bytes());
+        out(&self.difficulty.to_le_bytes());
+        out(&self.nonce.to_le_bytes());
+        out(&self.height.to_le_bytes());
+        out(&self.tx_count.to_le_bytes());
+        out(&self.size.to_le_bytes());
+        out(&self.chain_id.to_le_bytes());
+        out(&self.logs_bloom);
+        write_prefixed(out, if consensus_data { &self.consensus_data } else { &[] });
+    }
+}
+
+
+fn write_prefixed(out: &mut dyn FnMut(&[u8]), bytes: &[u8]) {
+    out(&u32::try_from(bytes.len()).expect("consensus data fits a u32 length").to_le_bytes());
+    out(bytes);
+}
+
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], PrimitivesError> {
+        if self.0.len() < len {
+            return Err(PrimitivesError::InvalidLength { expected: len, got: self.0.len() });
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PrimitivesError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn prefixed(&mut self) -> Result<&'a [u8], PrimitivesError> {
+        let len = u32::from_le_bytes(self.array()?);
+        self.take(len as usize)
+    }
+}
+
+
+//(year, month, day) of a day count from 1970-01-01, proleptic Gregorian
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::sha256;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use proptest::prelude::*;
+
+    fn header(height: u64, prev_block_hash: Hash256) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            prev_block_hash,
+            merkle_root: sha256(b"transactions"),
+            timestamp: Timestamp::from_unix(1_714_564_800 + height as i64, 250_000_000),
+            difficulty: 0,
+            nonce: 0,
+            height,
+            tx_count: 1,
+            size: 0,
+            chain_id: 1,
+            logs_bloom: [0u8; BLOOM_BYTES],
+            consensus_data: vec![],
+        }
+    }
+
+    #[test]
+    fn test_timestamp_text_round_trips() {
+        let cases = [
+            (0, 0, "1970-01-01T00:00:00Z"),
+            (1_714_564_800, 250_000_000, "2024-05-01T12:00:00.250Z"),
+            (951_782_400, 1_500, "2000-02-29T00:00:00.000001500Z"),
+            (-1, 5_000, "1969-12-31T23:59:59.000005Z"),
+        ];
+        for (secs, nanos, text) in cases {
+            let timestamp = Timestamp::from_unix(secs, nanos);
+            assert_eq!(timestamp.to_string(), text);
+            assert_eq!(Timestamp::parse(text).unwrap(), timestamp);
+        }
+        assert!(Timestamp::parse("2024-13-01T00:00:00Z").is_err());
+        assert!(Timestamp::parse("2024-05-01 00:00:00").is_err());
+    }
+
+    #[test]
+    fn test_encoding_round_trips_and_signing_hash_skips_consensus_data() {
+        let mut header = header(7, sha256(b"parent"));
+        header.consensus_data = vec![1, 2, 3];
+        assert_eq!(BlockHeader::decode(&header.encode()).unwrap(), header);
+        assert_eq!(header.hash(), sha256(&header.encode()));
+
+        let mut bytes = header.encode();
+        bytes.push(0);
+        assert!(BlockHeader::decode(&bytes).is_err());
+
+        let signing_hash = header.signing_hash();
+        header.consensus_data.clear();
+        assert_eq!(signing_hash, header.hash());
+    }
+
+    #[test]
+    fn test_golden_encoding() {
+        let mut header = header(0, Hash256::zero());
+        header.merkle_root = Hash256::zero();
+        header.consensus_data = vec![0xab];
+        let bytes = header.encode();
+
+        assert_eq!(bytes.len(), 1 + 4 + 32 + 32 + 12 + 24 + 12 + BLOOM_BYTES + 4 + 1);
+        let fields: Vec<u8> = [
+            &[1u8, 1, 0, 0, 0][..],
+            &[0u8; 64],
+            &[0xc0, 0x2e, 0x32, 0x66, 0, 0, 0, 0, 0x80, 0xb2, 0xe6, 0x0e],
+            &[0u8; 24],
+            &[1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0],
+        ].concat();
+        assert_eq!(bytes[..fields.len()], fields[..]);
+        assert_eq!(bytes[bytes.len() - 5..], [1, 0, 0, 0, 0xab]);
+        assert_eq!(header.hash(), Hash256::from_hex("3ccf0d0842ba8ee2473d6dee4e9e9f8992e1cedab20e5e9e077aee1995d4b200").unwrap());
+    }
+
+    #[test]
+    fn test_timestamp_parse_refuses_years_out_of_range() {
+        assert!(Timestamp::parse("262143-12-31T23:59:59Z").is_ok());
+        assert!(Timestamp::parse("9223372036854775807-01-01T00:00:00Z").is_err());
+        assert!(Timestamp::parse("-9223372036854775807-01-01T00:00:00Z").is_err());
+    }
+
+    #[test]
+    fn test_verify_extends() {
+        let parent = header(1, Hash256::zero());
+        let child = header(2, parent.hash());
+        assert!(child.verify_extends(&parent).is_ok());
+
+        assert!(header(2, Hash256::zero()).verify_extends(&parent).is_err());
+        assert!(header(3, parent.hash()).verify_extends(&parent).is_err());
+
+        let mut hard = header(2, parent.hash());
+        hard.difficulty = 64;
+        assert!(hard.verify_extends(&parent).is_err());
+    }
+
+    proptest! {
+        // headers from untrusted peers decode or fail, and what decodes
+        // encodes back to the same bytes
+        #[test]
+        fn prop_decoding_arbitrary_bytes_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..512)) {
+            if let Ok(header) = BlockHeader::decode(&bytes) {
+                prop_assert_eq!(header.encode(), bytes);
+            }
+        }
+
+        #[test]
+        fn prop_corrupted_headers_stay_canonical(height: u64, index: usize, byte: u8) {
+            let mut bytes = header(height, Hash256::zero()).encode();
+            let index = index % bytes.len();
+            bytes[index] = byte;
+            if let Ok(header) = BlockHeader::decode(&bytes) {
+                prop_assert_eq!(header.encode(), bytes);
+            }
+        }
+
+        #[test]
+        fn prop_parsing_arbitrary_timestamps_never_panics(text in "[-+]?[0-9]{1,20}-[0-9]{1,3}-[0-9]{1,3}T[0-9:.]{0,30}Z|.{0,40}") {
+            if let Ok(timestamp) = Timestamp::parse(&text) {
+                prop_assert_eq!(Timestamp::parse(&timestamp.to_string()).unwrap(), timestamp);
+            }
+        }
+    }
+}
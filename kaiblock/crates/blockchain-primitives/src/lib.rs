@@ -0,0 +1,60 @@
+//! Primitive chain types without the node.
+//!
+//! Hashes, merkle proofs and block headers, with the same encodings and
+//! hashes as blockchain-crypto and blockchain-core, in a `no_std` crate with
+//! no dependency beyond sha2 (and serde, optionally). Light clients, hardware
+//! wallets and browser builds use it to check header chains, proof of work and
+//! merkle proofs without tokio, sled or the rest of the node.
+//!
+//! Features:
+//! - `alloc`: [`MerkleProof`], [`MerkleMultiproof`] and [`BlockHeader`]
+//! - `std` (default): implies `alloc`, adds `std::error::Error` impls
+//! - `serde`: serde derives, matching the node's own
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod hash;
+#[cfg(feature = "alloc")]
+pub mod merkle;
+#[cfg(feature = "alloc")]
+pub mod header;
+
+use core::fmt;
+
+
+/// Version of the node's canonical encoding, the first byte of an encoded header
+pub const CODEC_VERSION: u8 = 1;
+
+/// Errors parsing primitive types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrimitivesError {
+    InvalidLength { expected: usize, got: usize },
+    InvalidHex,
+    /// a header that doesn't extend the one before it
+    InvalidHeader(&'static str),
+}
+
+impl fmt::Display for PrimitivesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrimitivesError::InvalidLength { expected, got } => write!(f, "Expected {} bytes, got {}", expected, got),
+            PrimitivesError::InvalidHex => write!(f, "Invalid hex"),
+            PrimitivesError::InvalidHeader(reason) => write!(f, "Invalid header: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PrimitivesError {}
+
+
+pub use hash::{double_sha256, hash_combine, hash_difficulty, sha256, Hash256};
+#[cfg(feature = "alloc")]
+pub use merkle::{MerkleMultiproof, MerkleProof};
+#[cfg(feature = "alloc")]
+pub use header::{BlockHeader, Timestamp, BLOOM_BYTES};
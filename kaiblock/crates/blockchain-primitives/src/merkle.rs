@@ -0,0 +1,207 @@
+use crate::hash::{hash_combine, Hash256};
+use alloc::vec::Vec;
+
+
+/// Proof that a leaf exists in the merkle tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MerkleProof {
+    pub leaf_index: usize,
+    pub leaf_hash: Hash256,
+    pub siblings: Vec<Hash256>,
+    pub root: Hash256,
+}
+
+impl MerkleProof {
+    /// Check the siblings lead from the leaf to the root. Trees duplicate the
+    /// last node of an odd level, so a sibling may equal the node itself.
+    /// The index has to fit the tree the siblings describe; otherwise its
+    /// high bits would go unchecked and one proof would pass for many leaves.
+    pub fn verify(&self) -> bool {
+        let depth = u32::try_from(self.siblings.len()).unwrap_or(u32::MAX);
+        let fits = self.leaf_index.checked_shr(depth).is_none_or(|high| high == 0);
+        fits && self.compute_root() == self.root
+    }
+
+    /// Root the siblings lead to from the leaf
+    pub fn compute_root(&self) -> Hash256 {
+        let mut current_hash = self.leaf_hash;
+        let mut current_index = self.leaf_index;
+
+        for sibling in &self.siblings {
+            current_hash = if current_index.is_multiple_of(2) {
+                hash_combine(&[current_hash.as_bytes(), sibling.as_bytes()])
+            } else {
+                hash_combine(&[sibling.as_bytes(), current_hash.as_bytes()])
+            };
+            current_index /= 2;
+        }
+
+        current_hash
+    }
+}
+
+
+
+/// Proof that several leaves of one tree exist, sharing the sibling hashes
+/// their paths have in common: a node on the path of two proven leaves is
+/// computed rather than sent.
+///
+/// `hashes` holds the nodes that can't be computed, level by level from the
+/// leaves up and left to right within a level. The shape of the tree comes
+/// from `leaf_count`, which also decides where the last node of an odd level
+/// is paired with itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MerkleMultiproof {
+    pub leaf_count: usize,
+    /// proven leaves with their indexes, in ascending index order
+    pub leaves: Vec<(usize, Hash256)>,
+    pub hashes: Vec<Hash256>,
+    pub root: Hash256,
+}
+
+impl MerkleMultiproof {
+    /// Check the leaves and hashes lead to the root. Indexes have to be
+    /// ascending and inside the tree, and every hash has to be used.
+    pub fn verify(&self) -> bool {
+        self.compute_root() == Some(self.root)
+    }
+
+    /// Root the leaves and hashes lead to; None when the proof is malformed
+    pub fn compute_root(&self) -> Option<Hash256> {
+        let &(last, _) = self.leaves.last()?;
+        let ascending = self.leaves.windows(2).all(|pair| pair[0].0 < pair[1].0);
+        if !ascending || last >= self.leaf_count {
+            return None;
+        }
+
+        let mut level = self.leaves.clone();
+        let mut hashes = self.hashes.iter();
+        let mut width = self.leaf_count;
+        while width > 1 {
+            let mut parents = Vec::with_capacity(level.len());
+            let mut position = 0;
+            while position < level.len() {
+                let (index, node) = level[position];
+                let (left, right) = if index.is_multiple_of(2) {
+                    let right = match level.get(position + 1) {
+                        Some(&(next, right)) if next == index + 1 => {
+                            position += 1;
+                            right
+                        }
+                        _ if index + 1 < width => *hashes.next()?,
+                        // last node of an odd level
+                        _ => node,
+                    };
+                    (node, right)
+                } else {
+                    (*hashes.next()?, node)
+                };
+                parents.push((index / 2, hash_combine(&[left.as_bytes(), right.as_bytes()])));
+                position += 1;
+            }
+            level = parents;
+            width = width.div_ceil(2);
+        }
+
+        if hashes.next().is_some() {
+            return None;
+        }
+        Some(level[0].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::sha256;
+    use alloc::vec;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_verify_two_level_proof() {
+        let leaves: Vec<Hash256> = (0u8..3).map(|i| sha256(&[i])).collect();
+        let left = hash_combine(&[leaves[0].as_bytes(), leaves[1].as_bytes()]);
+        let right = hash_combine(&[leaves[2].as_bytes(), leaves[2].as_bytes()]);
+        let root = hash_combine(&[left.as_bytes(), right.as_bytes()]);
+
+        let proof = MerkleProof { leaf_index: 1, leaf_hash: leaves[1], siblings: vec![leaves[0], right], root };
+        assert!(proof.verify());
+
+        let odd = MerkleProof { leaf_index: 2, leaf_hash: leaves[2], siblings: vec![leaves[2], left], root };
+        assert!(odd.verify());
+
+        let wrong_index = MerkleProof { leaf_index: 0, ..proof.clone() };
+        assert!(!wrong_index.verify());
+
+        //same path bits, plus one the tree has no level for
+        let past_the_tree = MerkleProof { leaf_index: 1 + 4, ..proof };
+        assert_eq!(past_the_tree.compute_root(), root);
+        assert!(!past_the_tree.verify());
+    }
+
+    #[test]
+    fn test_verify_multiproof_shares_computed_nodes() {
+        let leaves: Vec<Hash256> = (0u8..3).map(|i| sha256(&[i])).collect();
+        let left = hash_combine(&[leaves[0].as_bytes(), leaves[1].as_bytes()]);
+        let right = hash_combine(&[leaves[2].as_bytes(), leaves[2].as_bytes()]);
+        let root = hash_combine(&[left.as_bytes(), right.as_bytes()]);
+
+        //leaf 1 is the only node neither proven leaf's path computes
+        let proof = MerkleMultiproof { leaf_count: 3, leaves: vec![(0, leaves[0]), (2, leaves[2])], hashes: vec![leaves[1]], root };
+        assert!(proof.verify());
+
+        let all = MerkleMultiproof { leaves: leaves.iter().copied().enumerate().collect(), hashes: vec![], ..proof.clone() };
+        assert!(all.verify());
+
+        let unsorted = MerkleMultiproof { leaves: vec![(2, leaves[2]), (0, leaves[0])], ..proof.clone() };
+        assert!(!unsorted.verify());
+
+        let extra_hash = MerkleMultiproof { hashes: vec![leaves[1], leaves[1]], ..proof.clone() };
+        assert!(!extra_hash.verify());
+
+        let outside = MerkleMultiproof { leaves: vec![(0, leaves[0]), (3, leaves[2])], ..proof.clone() };
+        assert!(!outside.verify());
+
+        let empty = MerkleMultiproof { leaves: vec![], hashes: vec![], ..proof };
+        assert!(!empty.verify());
+    }
+
+    fn arbitrary_hash() -> impl Strategy<Value = Hash256> {
+        any::<[u8; 32]>().prop_map(Hash256::from_bytes)
+    }
+
+    proptest! {
+        // proofs come from untrusted peers: any of them is checked without
+        // panicking, however deep or far out its index
+        #[test]
+        fn prop_arbitrary_proofs_never_panic(
+            leaf_index: usize,
+            leaf_hash in arbitrary_hash(),
+            siblings in proptest::collection::vec(arbitrary_hash(), 0..80),
+            root in arbitrary_hash(),
+        ) {
+            let proof = MerkleProof { leaf_index, leaf_hash, siblings, root };
+            if proof.verify() {
+                prop_assert!(proof.siblings.len() >= 64 || leaf_index >> proof.siblings.len() == 0);
+            }
+        }
+
+        #[test]
+        fn prop_proofs_bind_their_leaf(
+            leaf_hash in arbitrary_hash(),
+            other in arbitrary_hash(),
+            siblings in proptest::collection::vec(arbitrary_hash(), 0..16),
+            path: u16,
+        ) {
+            let leaf_index = usize::from(path) & ((1 << siblings.len()) - 1);
+            let mut proof = MerkleProof { leaf_index, leaf_hash, siblings, root: Hash256::zero() };
+            proof.root = proof.compute_root();
+            prop_assert!(proof.verify());
+
+            proof.leaf_hash = other;
+            prop_assert_eq!(proof.verify(), other == leaf_hash);
+        }
+    }
+}
@@ -6,4 +6,39 @@ pub enum RpcError {
     TransactionNotFound,
     #[error("Internal server error")]
     InternalServerError,
-}
\ No newline at end of file
+    #[error("Parse error: {0}")]
+    ParseError(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Method not found: {0}")]
+    MethodNotFound(String),
+    #[error("Invalid params: {0}")]
+    InvalidParams(String),
+    #[error("Transaction rejected: {0}")]
+    TransactionRejected(String),
+    #[error("Indexer is disabled on this node")]
+    IndexerDisabled,
+    #[error("Block data up to height {0} is pruned on this node")]
+    Pruned(u64),
+    #[error("Block rejected: {0}")]
+    BlockRejected(String),
+}
+
+impl RpcError {
+    /// JSON-RPC 2.0 error code; -32000 and below are application errors
+    pub fn code(&self) -> i64 {
+        match self {
+            RpcError::ParseError(_) => -32700,
+            RpcError::InvalidRequest(_) => -32600,
+            RpcError::MethodNotFound(_) => -32601,
+            RpcError::InvalidParams(_) => -32602,
+            RpcError::InternalServerError => -32603,
+            RpcError::BlockNotFound => -32001,
+            RpcError::TransactionNotFound => -32002,
+            RpcError::TransactionRejected(_) => -32003,
+            RpcError::IndexerDisabled => -32004,
+            RpcError::Pruned(_) => -32005,
+            RpcError::BlockRejected(_) => -32006,
+        }
+    }
+}
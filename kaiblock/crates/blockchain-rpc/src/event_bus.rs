@@ -0,0 +1,388 @@
+use crate::events::{NodeEvent, Topic};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+
+/// What happens when a subscriber's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// drop the subscriber's oldest queued event; it is told how many it missed
+    DropOldest,
+    /// disconnect the subscriber
+    Disconnect,
+}
+
+
+/// Event bus settings
+#[derive(Debug, Clone)]
+pub struct EventBusConfig {
+    /// events queued per subscriber before the lag policy applies
+    pub queue_capacity: usize,
+    pub lag_policy: LagPolicy,
+    /// how often batched events (mempool and peer churn) are delivered
+    pub batch_interval: Duration,
+}
+
+impl Default for EventBusConfig {
+    fn default() -> Self {
+        Self {
+            queue_capacity: 256,
+            lag_policy: LagPolicy::DropOldest,
+            batch_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+
+/// Delivery counters across all subscribers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EventBusStats {
+    pub subscribers: usize,
+    pub published: u64,
+    /// events dropped from full queues
+    pub dropped: u64,
+    /// subscribers disconnected for lagging
+    pub disconnected: u64,
+}
+
+
+#[derive(Debug, Default)]
+struct Subscriber {
+    queue: Mutex<VecDeque<NodeEvent>>,
+    /// events dropped since the subscriber last received a batch
+    missed: AtomicU64,
+    topics: Mutex<HashSet<Topic>>,
+    closed: AtomicBool,
+    ready: Notify,
+}
+
+
+/// High-frequency events held until the next batch. Sizes and counts only
+/// keep their latest value; new and replaced transactions are kept in order.
+#[derive(Debug, Default)]
+struct Pending {
+    mempool_size: Option<usize>,
+    peer_count: Option<usize>,
+    transactions: Vec<NodeEvent>,
+}
+
+impl Pending {
+    fn take(&mut self) -> Vec<NodeEvent> {
+        let mut events: Vec<NodeEvent> = self.transactions.drain(..).collect();
+        if let Some(size) = self.mempool_size.take() {
+            events.push(NodeEvent::MempoolSize { size });
+        }
+        if let Some(count) = self.peer_count.take() {
+            events.push(NodeEvent::PeerCount { count });
+        }
+        events
+    }
+}
+
+
+/// Fan-out of node events to WebSocket and SSE subscribers.
+///
+/// Publishing never waits on a subscriber: each one has a bounded queue, and a
+/// subscriber that falls behind loses its oldest events or is disconnected,
+/// depending on [`LagPolicy`]. Subscribers only queue events of the topics
+/// they asked for. Mempool and peer churn is coalesced and delivered once per
+/// `batch_interval` by [`EventBus::run`].
+#[derive(Debug)]
+pub struct EventBus {
+    config: EventBusConfig,
+    subscribers: Mutex<Vec<Arc<Subscriber>>>,
+    pending: Mutex<Pending>,
+    published: AtomicU64,
+    dropped: AtomicU64,
+    disconnected: AtomicU64,
+}
+
+impl EventBus {
+    pub fn new(config: EventBusConfig) -> Self {
+        Self {
+            config,
+            subscribers: Mutex::new(Vec::new()),
+            pending: Mutex::new(Pending::default()),
+            published: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            disconnected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn config(&self) -> &EventBusConfig {
+        &self.config
+    }
+
+    /// Subscribe to what `GET /events` streams ([`Topic::EVENTS`])
+    pub fn subscribe(&self) -> Subscription {
+        self.subscribe_to(Topic::EVENTS)
+    }
+
+    pub fn subscribe_to(&self, topics: impl IntoIterator<Item = Topic>) -> Subscription {
+        let subscriber = Arc::new(Subscriber::default());
+        *lock(&subscriber.topics) = topics.into_iter().collect();
+        lock(&self.subscribers).push(subscriber.clone());
+        Subscription { subscriber }
+    }
+
+    /// Publish an event without blocking. New blocks and logs go out
+    /// immediately; mempool size, peer count, new pending transactions and
+    /// replacements wait for the next batch.
+    pub fn publish(&self, event: NodeEvent) {
+        match event {
+            NodeEvent::MempoolSize { size } => lock(&self.pending).mempool_size = Some(size),
+            NodeEvent::PeerCount { count } => lock(&self.pending).peer_count = Some(count),
+            event @ (NodeEvent::TransactionReplaced { .. } | NodeEvent::PendingTransaction { .. }) => {
+                lock(&self.pending).transactions.push(event)
+            }
+            event => self.deliver(vec![event]),
+        }
+    }
+
+    /// Deliver batched events now
+    pub fn flush(&self) {
+        let events = lock(&self.pending).take();
+        if !events.is_empty() {
+            self.deliver(events);
+        }
+    }
+
+    /// Deliver batched events every `batch_interval`, forever
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.config.batch_interval);
+        loop {
+            interval.tick().await;
+            self.flush();
+        }
+    }
+
+    pub fn stats(&self) -> EventBusStats {
+        EventBusStats {
+            subscribers: lock(&self.subscribers).len(),
+            published: self.published.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            disconnected: self.disconnected.load(Ordering::Relaxed),
+        }
+    }
+
+    fn deliver(&self, events: Vec<NodeEvent>) {
+        self.published.fetch_add(events.len() as u64, Ordering::Relaxed);
+        let capacity = self.config.queue_capacity.max(1);
+
+        let mut subscribers = lock(&self.subscribers);
+        subscribers.retain(|subscriber| !subscriber.closed.load(Ordering::SeqCst));
+
+        for subscriber in subscribers.iter() {
+            let topics = lock(&subscriber.topics).clone();
+            let mut wanted = events.iter()
+                .filter(|event| event.topic().is_none_or(|topic| topics.contains(&topic)))
+                .peekable();
+            if wanted.peek().is_none() {
+                continue;
+            }
+
+            let mut queue = lock(&subscriber.queue);
+            for event in wanted {
+                if queue.len() >= capacity {
+                    match self.config.lag_policy {
+                        LagPolicy::DropOldest => {
+                            queue.pop_front();
+                            subscriber.missed.fetch_add(1, Ordering::Relaxed);
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        LagPolicy::Disconnect => {
+                            queue.clear();
+                            subscriber.closed.store(true, Ordering::SeqCst);
+                            self.disconnected.fetch_add(1, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+                queue.push_back(event.clone());
+            }
+            drop(queue);
+            subscriber.ready.notify_one();
+        }
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(EventBusConfig::default())
+    }
+}
+
+
+/// One subscriber's end of the bus. Dropping it unsubscribes.
+#[derive(Debug)]
+pub struct Subscription {
+    subscriber: Arc<Subscriber>,
+}
+
+impl Subscription {
+    /// Change which topics this subscriber receives from now on
+    pub fn set_topics(&self, topics: impl IntoIterator<Item = Topic>) {
+        *lock(&self.subscriber.topics) = topics.into_iter().collect();
+    }
+
+    /// Wait for events and take everything queued, oldest first. A
+    /// `Lagged` event leads the batch if events were dropped. Returns None
+    /// once the bus has disconnected this subscriber.
+    pub async fn next_batch(&mut self) -> Option<Vec<NodeEvent>> {
+        loop {
+            if self.subscriber.closed.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            let notified = self.subscriber.ready.notified();
+            {
+                let mut queue = lock(&self.subscriber.queue);
+                let missed = self.subscriber.missed.swap(0, Ordering::Relaxed);
+                if !queue.is_empty() || missed > 0 {
+                    let mut batch = Vec::with_capacity(queue.len() + 1);
+                    if missed > 0 {
+                        batch.push(NodeEvent::Lagged { missed });
+                    }
+                    batch.extend(queue.drain(..));
+                    return Some(batch);
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.subscriber.closed.store(true, Ordering::SeqCst);
+    }
+}
+
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bus(queue_capacity: usize, lag_policy: LagPolicy) -> EventBus {
+        EventBus::new(EventBusConfig { queue_capacity, lag_policy, ..EventBusConfig::default() })
+    }
+
+    fn pending(txid: &str) -> NodeEvent {
+        NodeEvent::PendingTransaction { txid: txid.to_string() }
+    }
+
+    fn double_spend(n: u64) -> NodeEvent {
+        NodeEvent::DoubleSpend { outpoint: n.to_string(), first: String::new(), second: String::new() }
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_get_only_their_topics() {
+        let bus = EventBus::default();
+        let mut node = bus.subscribe_to([Topic::Node]);
+        let mut pending_txs = bus.subscribe_to([Topic::PendingTransactions]);
+
+        bus.publish(double_spend(1));
+        bus.publish(pending("a"));
+        bus.flush();
+
+        assert_eq!(node.next_batch().await, Some(vec![double_spend(1)]));
+        assert_eq!(pending_txs.next_batch().await, Some(vec![pending("a")]));
+
+        // topics can change on the fly
+        pending_txs.set_topics([Topic::Node]);
+        bus.publish(double_spend(2));
+        assert_eq!(pending_txs.next_batch().await, Some(vec![double_spend(2)]));
+    }
+
+    #[tokio::test]
+    async fn test_churn_is_batched_and_coalesced() {
+        let bus = EventBus::default();
+        let mut subscription = bus.subscribe_to([Topic::Node, Topic::PendingTransactions]);
+
+        bus.publish(NodeEvent::MempoolSize { size: 1 });
+        bus.publish(pending("a"));
+        bus.publish(NodeEvent::MempoolSize { size: 2 });
+        bus.publish(NodeEvent::PeerCount { count: 3 });
+        assert_eq!(bus.stats().published, 0);
+
+        bus.flush();
+        assert_eq!(subscription.next_batch().await, Some(vec![
+            pending("a"),
+            NodeEvent::MempoolSize { size: 2 },
+            NodeEvent::PeerCount { count: 3 },
+        ]));
+        assert_eq!(bus.stats().published, 3);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_loses_oldest_events() {
+        let bus = bus(2, LagPolicy::DropOldest);
+        let mut subscription = bus.subscribe_to([Topic::Node]);
+
+        for n in 0..5 {
+            bus.publish(double_spend(n));
+        }
+        assert_eq!(subscription.next_batch().await, Some(vec![
+            NodeEvent::Lagged { missed: 3 },
+            double_spend(3),
+            double_spend(4),
+        ]));
+        assert_eq!(bus.stats().dropped, 3);
+
+        // the count starts over once reported
+        bus.publish(double_spend(5));
+        assert_eq!(subscription.next_batch().await, Some(vec![double_spend(5)]));
+    }
+
+    #[tokio::test]
+    async fn test_lagging_subscriber_disconnected() {
+        let bus = bus(2, LagPolicy::Disconnect);
+        let mut slow = bus.subscribe_to([Topic::Node]);
+        let mut other = bus.subscribe_to([Topic::NewHeads]);
+
+        for n in 0..3 {
+            bus.publish(double_spend(n));
+        }
+        assert_eq!(slow.next_batch().await, None);
+        assert_eq!(bus.stats().disconnected, 1);
+
+        // the rest of the bus carries on
+        bus.publish(double_spend(3));
+        bus.publish(NodeEvent::Lagged { missed: 0 });
+        assert_eq!(other.next_batch().await, Some(vec![NodeEvent::Lagged { missed: 0 }]));
+        assert_eq!(bus.stats().subscribers, 1);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_subscription_unsubscribes() {
+        let bus = EventBus::default();
+        let subscription = bus.subscribe();
+        assert_eq!(bus.stats().subscribers, 1);
+
+        drop(subscription);
+        bus.publish(double_spend(0));
+        assert_eq!(bus.stats().subscribers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_next_batch_waits_for_events() {
+        let bus = Arc::new(EventBus::default());
+        let mut subscription = bus.subscribe_to([Topic::Node]);
+
+        let publisher = bus.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            publisher.publish(double_spend(0));
+        });
+        let batch = tokio::time::timeout(Duration::from_secs(5), subscription.next_batch()).await.unwrap();
+        assert_eq!(batch, Some(vec![double_spend(0)]));
+    }
+}
@@ -0,0 +1,140 @@
+use blockchain_core::block::Block;
+use blockchain_core::logs::block_logs;
+use blockchain_core::{ChainEvent, LogEntry, LogFilter, TxDropReason};
+use serde::{Serialize, Deserialize};
+
+
+/// Summary of a block pushed to subscribers
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockSummary {
+    pub height: u64,
+    pub hash: String,
+    pub tx_count: usize,
+    pub total_fees: u64,
+    pub timestamp: i64,
+    /// addresses sending or receiving in this block
+    pub addresses: Vec<String>,
+}
+
+impl BlockSummary {
+    pub fn from_block(block: &Block) -> Self {
+        let mut addresses: Vec<String> = block.transactions().iter()
+            .flat_map(|tx| {
+                let outputs = tx.outputs.iter().map(|output| output.address.to_string());
+                tx.from.iter().chain(tx.to.iter())
+                    .map(|address| address.to_string())
+                    .chain(outputs)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+
+        Self {
+            height: block.height(),
+            hash: block.id().to_string(),
+            tx_count: block.transaction_count(),
+            total_fees: block.transactions().iter().map(|tx| tx.fee).sum(),
+            timestamp: block.timestamp().to_unix_timestamp(),
+            addresses,
+        }
+    }
+
+    pub fn touches(&self, address: &str) -> bool {
+        self.addresses.iter().any(|a| a == address)
+    }
+}
+
+
+/// Events streamed to WebSocket and SSE subscribers.
+/// Serialized as `{"event": "...", "data": {...}}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum NodeEvent {
+    NewBlock(BlockSummary),
+    MempoolSize { size: usize },
+    PeerCount { count: usize },
+    /// a pooled transaction was evicted by a conflicting one paying more
+    TransactionReplaced {
+        replaced: String,
+        replacement: String,
+        old_fee_per_byte: u64,
+        new_fee_per_byte: u64,
+    },
+    /// two signed transactions spend the same output; a payment relying on
+    /// either one unconfirmed may never confirm
+    DoubleSpend {
+        outpoint: String,
+        first: String,
+        second: String,
+    },
+    /// a transaction entered the mempool
+    PendingTransaction { txid: String },
+    /// logs emitted by the transactions of a newly connected block
+    Logs { entries: Vec<LogEntry> },
+    /// the subscriber fell behind and this many events were dropped
+    Lagged { missed: u64 },
+}
+
+
+/// Kinds of events a bus subscriber can ask for
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Topic {
+    NewHeads,
+    PendingTransactions,
+    Logs,
+    /// mempool size, peer count, replacements and double spends
+    Node,
+}
+
+impl Topic {
+    /// What `GET /events` streams: everything but per-transaction churn
+    pub const EVENTS: [Topic; 2] = [Topic::NewHeads, Topic::Node];
+}
+
+impl NodeEvent {
+    /// The subscriber-facing events for a chain event, if there are any
+    pub fn from_chain_event(event: &ChainEvent) -> Vec<Self> {
+        match event {
+            ChainEvent::BlockConnected(block) => {
+                let mut events = vec![NodeEvent::NewBlock(BlockSummary::from_block(block))];
+                //contract logs come with the receipts, which the event doesn't
+                //carry; getLogs returns them
+                let entries = block_logs(block, &[], &LogFilter::default());
+                if !entries.is_empty() {
+                    events.push(NodeEvent::Logs { entries });
+                }
+                events
+            }
+            ChainEvent::TxAdded(tx) => vec![NodeEvent::PendingTransaction { txid: tx.id().to_string() }],
+            ChainEvent::TxDropped {
+                tx_id,
+                reason: TxDropReason::Replaced { by, old_fee_per_byte, new_fee_per_byte },
+            } => vec![NodeEvent::TransactionReplaced {
+                replaced: tx_id.to_string(),
+                replacement: by.to_string(),
+                old_fee_per_byte: *old_fee_per_byte,
+                new_fee_per_byte: *new_fee_per_byte,
+            }],
+            ChainEvent::DoubleSpend(proof) => vec![NodeEvent::DoubleSpend {
+                outpoint: proof.outpoint.to_string(),
+                first: proof.first.id().to_string(),
+                second: proof.second.id().to_string(),
+            }],
+            _ => Vec::new(),
+        }
+    }
+
+    /// The topic a subscriber asks for to receive this event; None for
+    /// `Lagged`, which goes to every subscriber
+    pub fn topic(&self) -> Option<Topic> {
+        match self {
+            NodeEvent::NewBlock(_) => Some(Topic::NewHeads),
+            NodeEvent::PendingTransaction { .. } => Some(Topic::PendingTransactions),
+            NodeEvent::Logs { .. } => Some(Topic::Logs),
+            NodeEvent::Lagged { .. } => None,
+            _ => Some(Topic::Node),
+        }
+    }
+}
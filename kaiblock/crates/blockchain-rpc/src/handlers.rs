@@ -1,48 +1,1131 @@
-use blockchain_storage::SledBlockStore;
+use blockchain_core::{codec, Address, Block, Blockchain, BlockId, Difficulty, ExtraNonceJob, Hash256, LogEntry, LogFilter, SyncStatus, Transaction, TxId};
+use blockchain_core::chain::MAX_REORG_DEPTH;
+use blockchain_core::transaction::EXTRA_NONCE_SIZE;
+use blockchain_crypto::signature::verify_message;
+use blockchain_storage::ChainIndexer;
 use blockchain_network::Network;
-use blockchain_core::{block: Block, transaction: Transaction};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::RwLock;
 use crate::errors::RpcError;
+use crate::event_bus::EventBus;
+use crate::idempotency::{IdempotencyCache, MAX_IDEMPOTENCY_KEY_LEN};
+use crate::metrics::{MetricsWriter, NodeMetrics};
+use crate::status::{NodeStatus, RECENT_ERROR_WINDOW};
+use crate::jsonrpc::{JsonRpcRequest, JsonRpcResponse, JSONRPC_VERSION, param, required_param};
 
 
+/// Heights returned by getChainTree when no depth is given
+const DEFAULT_CHAIN_TREE_DEPTH: u64 = 10;
+
+/// Addresses returned by getRichestAddresses when no limit is given, and the most it returns
+pub const DEFAULT_RICHEST_LIMIT: usize = 20;
+pub const MAX_RICHEST_LIMIT: usize = 100;
+
+/// Blocks averaged by getAverageBlockTime when no count is given, and the most it averages
+pub const DEFAULT_BLOCK_TIME_WINDOW: u64 = 100;
+pub const MAX_BLOCK_TIME_WINDOW: u64 = 10_000;
+
+/// Most blocks one getBlockStats call covers
+pub const MAX_BLOCK_STATS_RANGE: u64 = 1_000;
+
+
+/// Where the handler sends state-changing calls when it runs inside a node,
+/// so they count as in-flight work during shutdown and reach peers like the
+/// node's own writes. Errors are the reason the write was rejected
+#[async_trait]
+pub trait NodeWrites: Send + Sync {
+    async fn submit_transaction(&self, tx: Transaction) -> Result<TxId, String>;
+    /// Add the block to the chain and announce it to peers
+    async fn submit_block(&self, block: Block) -> Result<BlockId, String>;
+}
+
 
 #[derive(Clone)]
-pub struct Rcpandler{
-    pub store: Arc<RwLock<SledBlockStore>>,
-    pub network: Arc<Network>,
+pub struct RpcHandler{
+    pub blockchain: Arc<RwLock<Blockchain>>,
+    /// cleared by the node on shutdown to reject state-changing calls
+    pub accepting_writes: Arc<AtomicBool>,
+    /// progress of initial block download, updated by the node's sync task
+    pub sync_status: SyncStatus,
+    /// P2P layer for peer queries (None when running without networking)
+    pub network: Option<Arc<Network>>,
+    /// idempotency keys of recent transaction submissions
+    pub idempotency: Arc<IdempotencyCache>,
+    /// uptime, miner progress and recent errors, shared with the node
+    pub status: NodeStatus,
+    /// node events served on `GET /events`
+    pub events: Arc<EventBus>,
+    /// secondary indexes, when the node keeps them
+    pub indexer: Option<Arc<ChainIndexer>>,
+    /// block validation and reorg counters served on `GET /metrics`
+    pub metrics: NodeMetrics,
+    /// the node's write path (None: writes go straight to the chain)
+    pub writes: Option<Arc<dyn NodeWrites>>,
 }
 
-imp Rcpandler{
-    pub new(store: Arc<RwLock<SledBlockStore>>, network: Arc<Network>) -> Self {
-        Self { store, network }
+impl RpcHandler{
+    pub fn new(blockchain: Arc<RwLock<Blockchain>>) -> Self {
+        Self {
+            blockchain,
+            accepting_writes: Arc::new(AtomicBool::new(true)),
+            sync_status: SyncStatus::new(),
+            network: None,
+            idempotency: Arc::new(IdempotencyCache::default()),
+            status: NodeStatus::new(),
+            events: Arc::new(EventBus::default()),
+            indexer: None,
+            metrics: NodeMetrics::new(),
+            writes: None,
+        }
+    }
+
+    /// Share a write gate with the node so writes stop when it shuts down
+    pub fn with_write_gate(mut self, accepting_writes: Arc<AtomicBool>) -> Self {
+        self.accepting_writes = accepting_writes;
+        self
+    }
+
+    /// Send transactions and blocks through the node instead of straight to the chain
+    pub fn with_node_writes(mut self, writes: Arc<dyn NodeWrites>) -> Self {
+        self.writes = Some(writes);
+        self
+    }
+
+    /// Answer peer queries from the node's P2P layer
+    pub fn with_network(mut self, network: Arc<Network>) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Report sync progress from the node's sync task
+    pub fn with_sync_status(mut self, sync_status: SyncStatus) -> Self {
+        self.sync_status = sync_status;
+        self
+    }
+
+    /// Report uptime, mining and error counts kept by the node
+    pub fn with_status(mut self, status: NodeStatus) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Event bus shared with the node, which publishes to it
+    pub fn with_event_bus(mut self, events: Arc<EventBus>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Serve address and time queries from the node's indexer
+    pub fn with_indexer(mut self, indexer: Arc<ChainIndexer>) -> Self {
+        self.indexer = Some(indexer);
+        self
+    }
+
+    /// Counters kept by the node, exported with the gauges on `GET /metrics`
+    pub fn with_metrics(mut self, metrics: NodeMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+
+    /// Handle one JSON-RPC request. Returns None for notifications.
+    pub async fn handle(&self, request: JsonRpcRequest) -> Option<JsonRpcResponse> {
+        let id = request.id.clone();
+
+        let result = if request.jsonrpc != JSONRPC_VERSION {
+            Err(RpcError::InvalidRequest(format!("unsupported jsonrpc version {}", request.jsonrpc)))
+        } else {
+            self.dispatch(&request.method, &request.params).await
+        };
+
+        if let Err(RpcError::InternalServerError) = &result {
+            self.status.record_error("rpc");
+        }
+
+        let id = id?;
+        Some(match result {
+            Ok(value) => JsonRpcResponse::success(id, value),
+            Err(e) => JsonRpcResponse::failure(id, e),
+        })
+    }
+
+
+    /// Handle raw request bytes, answering malformed JSON with a parse error
+    pub async fn handle_raw(&self, body: &[u8]) -> Option<Value> {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(body) => self.handle_body(body).await,
+            Err(e) => Some(json!(JsonRpcResponse::failure(Value::Null, RpcError::ParseError(e.to_string())))),
+        }
+    }
+
+
+    /// Handle a parsed request body: a single request or a batch
+    pub async fn handle_body(&self, body: Value) -> Option<Value> {
+        match body {
+            Value::Array(requests) if requests.is_empty() => {
+                Some(json!(JsonRpcResponse::failure(Value::Null, RpcError::InvalidRequest("empty batch".to_string()))))
+            }
+            Value::Array(requests) => {
+                let mut responses = Vec::with_capacity(requests.len());
+                for request in requests {
+                    if let Some(response) = self.handle_value(request).await {
+                        responses.push(response);
+                    }
+                }
+                // a batch of only notifications gets no response at all
+                if responses.is_empty() { None } else { Some(json!(responses)) }
+            }
+            request => self.handle_value(request).await.map(|response| json!(response)),
+        }
+    }
+
+
+    async fn handle_value(&self, request: Value) -> Option<JsonRpcResponse> {
+        match serde_json::from_value::<JsonRpcRequest>(request) {
+            Ok(request) => self.handle(request).await,
+            Err(e) => Some(JsonRpcResponse::failure(Value::Null, RpcError::InvalidRequest(e.to_string()))),
+        }
+    }
+
+
+    async fn dispatch(&self, method: &str, params: &Value) -> Result<Value, RpcError> {
+        match method {
+            "getBlockHeight" => self.get_block_height().await,
+            "getFinalizedBlock" => self.get_finalized_block().await,
+            "getBlockByHeight" => self.get_block_by_height(required_param(params, 0, "height")?).await,
+            "getBlockByHash" => self.get_block_by_hash(&required_param::<String>(params, 0, "hash")?).await,
+            "getTransaction" => self.get_transaction(&required_param::<String>(params, 0, "txid")?).await,
+            "getTransactionReceipt" => self.get_transaction_receipt(&required_param::<String>(params, 0, "txid")?).await,
+            "sendRawTransaction" => {
+                let idempotency_key = match param(params, 1, "idempotencyKey") {
+                    None | Some(Value::Null) => None,
+                    Some(_) => Some(required_param::<String>(params, 1, "idempotencyKey")?),
+                };
+                self.send_raw_transaction(&required_param::<String>(params, 0, "data")?, idempotency_key).await
+            }
+            "getBlockTemplate" => self.get_block_template(&required_param::<String>(params, 0, "minerAddress")?).await,
+            "submitBlock" => self.submit_block(&required_param::<String>(params, 0, "data")?).await,
+            "getBalance" => self.get_balance(&required_param::<String>(params, 0, "address")?).await,
+            "getAccount" => self.get_account(&required_param::<String>(params, 0, "address")?).await,
+            "getUtxos" => self.get_utxos(&required_param::<String>(params, 0, "address")?).await,
+            "getTransactionsByAddress" => {
+                let page = match param(params, 1, "page") {
+                    None | Some(Value::Null) => 0,
+                    Some(_) => required_param(params, 1, "page")?,
+                };
+                self.get_transactions_by_address(&required_param::<String>(params, 0, "address")?, page)
+            }
+            "getBlocksByTime" => self.get_blocks_by_time(required_param(params, 0, "from")?, required_param(params, 1, "to")?),
+            "getBlockStats" => self.get_block_stats(required_param(params, 0, "fromHeight")?, required_param(params, 1, "toHeight")?),
+            "getDailyTransactionCounts" => {
+                self.get_daily_transaction_counts(required_param(params, 0, "from")?, required_param(params, 1, "to")?)
+            }
+            "getAverageBlockTime" => {
+                let blocks = match param(params, 0, "blocks") {
+                    None | Some(Value::Null) => DEFAULT_BLOCK_TIME_WINDOW,
+                    Some(_) => required_param(params, 0, "blocks")?,
+                };
+                self.get_average_block_time(blocks)
+            }
+            "getRichestAddresses" => {
+                let limit = match param(params, 0, "limit") {
+                    None | Some(Value::Null) => DEFAULT_RICHEST_LIMIT,
+                    Some(_) => required_param(params, 0, "limit")?,
+                };
+                self.get_richest_addresses(limit).await
+            }
+            "getCirculatingSupply" => self.get_circulating_supply().await,
+            "getMempoolInfo" => self.get_mempool_info().await,
+            "getBlockLimits" => self.get_block_limits().await,
+            "getChainId" => self.get_chain_id().await,
+            "getSignatureCacheInfo" => self.get_signature_cache_info().await,
+            "getSyncStatus" => self.get_sync_status(),
+            "getNodeStatus" => self.get_node_status().await,
+            "getPeerInfo" => self.get_peer_info().await,
+            "getDevAccounts" => self.get_dev_accounts().await,
+            "getLogs" => self.get_logs(required_param(params, 0, "filter")?).await,
+            "verifyMessage" => {
+                self.verify_message(
+                    &required_param::<String>(params, 0, "address")?,
+                    &required_param::<String>(params, 1, "signature")?,
+                    &required_param::<String>(params, 2, "message")?,
+                )
+            }
+            "getTransactionProof" => self.get_transaction_proof(&required_param::<String>(params, 0, "txid")?).await,
+            "getDifficultyProof" => {
+                self.get_difficulty_proof(required_param(params, 0, "fromHeight")?, required_param(params, 1, "toHeight")?).await
+            }
+            "getChainTree" => {
+                let depth = match param(params, 0, "depth") {
+                    None | Some(Value::Null) => DEFAULT_CHAIN_TREE_DEPTH,
+                    Some(_) => required_param(params, 0, "depth")?,
+                };
+                self.get_chain_tree(depth).await
+            }
+            _ => Err(RpcError::MethodNotFound(method.to_string())),
+        }
+    }
+
+
+    pub async fn get_block_height(&self) -> Result<Value, RpcError> {
+        Ok(json!(self.blockchain.read().await.height()))
+    }
+
+
+    /// Height and hash of the highest finalized block; null if nothing is final yet
+    pub async fn get_finalized_block(&self) -> Result<Value, RpcError> {
+        let blockchain = self.blockchain.read().await;
+        let finalized = blockchain.finalized_height()
+            .and_then(|height| blockchain.get_block_by_height(&height))
+            .map(|block| json!({
+                "height": block.height(),
+                "hash": block.id().to_hex(),
+                "tipHeight": blockchain.height(),
+            }));
+        Ok(finalized.unwrap_or(Value::Null))
+    }
+
+
+    pub async fn get_block_by_height(&self, height: u64) -> Result<Value, RpcError> {
+        let blockchain = self.blockchain.read().await;
+        let block = blockchain.get_block_by_height(&height)
+            .ok_or(RpcError::BlockNotFound)?;
+        check_not_pruned(&blockchain, block.height())?;
+        to_value(block)
+    }
+
+
+    pub async fn get_block_by_hash(&self, hash: &str) -> Result<Value, RpcError> {
+        let block_id = BlockId::from_hex(hash)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid block hash: {}", e)))?;
+
+        let blockchain = self.blockchain.read().await;
+        let block = blockchain.get_block(&block_id)
+            .ok_or(RpcError::BlockNotFound)?;
+        check_not_pruned(&blockchain, block.height())?;
+        to_value(block)
+    }
+
+
+    pub async fn get_transaction(&self, txid: &str) -> Result<Value, RpcError> {
+        let tx_id = TxId::from_hex(txid)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid transaction id: {}", e)))?;
+
+        let blockchain = self.blockchain.read().await;
+        let tx = blockchain.get_transaction(&tx_id)
+            .or_else(|| blockchain.mempool().get_transaction(&tx_id))
+            .ok_or_else(|| not_found_or_pruned(&blockchain, RpcError::TransactionNotFound))?;
+        to_value(tx)
+    }
+
+
+    /// Outcome of a confirmed transaction: its block, status and fee
+    pub async fn get_transaction_receipt(&self, txid: &str) -> Result<Value, RpcError> {
+        let tx_id = TxId::from_hex(txid)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid transaction id: {}", e)))?;
+
+        let receipt = self.blockchain.read().await.get_receipt(&tx_id)
+            .map_err(|_| RpcError::InternalServerError)?
+            .ok_or(RpcError::TransactionNotFound)?;
+        to_value(&receipt)
+    }
+
+
+    /// Submit a hex-encoded transaction (see `blockchain_core::codec`); returns its id.
+    /// Resubmitting with the same idempotency key returns the original id
+    /// instead of submitting again.
+    pub async fn send_raw_transaction(&self, data: &str, idempotency_key: Option<String>) -> Result<Value, RpcError> {
+        if !self.accepting_writes.load(Ordering::SeqCst) {
+            return Err(RpcError::TransactionRejected("node is shutting down".to_string()));
+        }
+
+        let bytes = hex::decode(data.trim_start_matches("0x"))
+            .map_err(|e| RpcError::InvalidParams(format!("invalid hex: {}", e)))?;
+        let tx: Transaction = codec::decode(&bytes)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid transaction encoding: {}", e)))?;
+
+        let key = match idempotency_key {
+            Some(key) => key,
+            None => {
+                let tx_id = self.add_transaction(tx).await?;
+                return Ok(json!(tx_id.to_hex()));
+            }
+        };
+
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+            return Err(RpcError::InvalidParams(
+                format!("idempotency key must be 1 to {} characters", MAX_IDEMPOTENCY_KEY_LEN)
+            ));
+        }
+
+        let mut cache = self.idempotency.lock().await;
+        if let Some(previous) = cache.get(&key) {
+            if previous.tx_id != tx.id() {
+                return Err(RpcError::InvalidParams("idempotency key was used for a different transaction".to_string()));
+            }
+            return Ok(json!(previous.tx_id.to_hex()));
+        }
+
+        let tx_id = self.add_transaction(tx).await?;
+        cache.record(key, tx_id);
+        Ok(json!(tx_id.to_hex()))
+    }
+
+
+    // Through the node when there is one, which relays the transaction to peers
+    async fn add_transaction(&self, tx: Transaction) -> Result<TxId, RpcError> {
+        match &self.writes {
+            Some(writes) => writes.submit_transaction(tx).await,
+            None => self.blockchain.write().await.add_transaction(tx).map_err(|e| e.to_string()),
+        }.map_err(RpcError::TransactionRejected)
+    }
+
+
+    /// Work for an external miner: an unmined block on the current tip paying
+    /// `miner_address`, with the best mempool transactions. The miner searches
+    /// `header` for a nonce whose hash is at or below `target` and hands the
+    /// block back to submitBlock.
+    ///
+    /// The coinbase carries an extra nonce region (set to 0). A miner that runs
+    /// through the header nonce rolls the extra nonce instead of asking for a
+    /// new template: the merkle root for extra nonce `n` is the merkle branch
+    /// folded over sha256(coinbasePrefix || n as 8 bytes LE || coinbaseSuffix).
+    /// It submits the block with `Block::set_extra_nonce(n)` and its nonce set.
+    pub async fn get_block_template(&self, miner_address: &str) -> Result<Value, RpcError> {
+        let miner_address = Address::from_string(miner_address)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid miner address: {}", e)))?;
+
+        let blockchain = self.blockchain.read().await;
+        if blockchain.config().staking.is_some() {
+            return Err(RpcError::InvalidRequest("blocks on this chain are proposed by validators, not mined".to_string()));
+        }
+        let mut block = blockchain.create_block_template(miner_address)
+            .map_err(|_| RpcError::InternalServerError)?;
+        block.set_extra_nonce(0)
+            .map_err(|_| RpcError::InternalServerError)?;
+        let job = ExtraNonceJob::from_block(&block)
+            .map_err(|_| RpcError::InternalServerError)?;
+        let header = &block.header;
+
+        let transactions: Vec<Value> = block.transactions().iter().skip(1)
+            .map(|tx| json!({
+                "txid": tx.id().to_hex(),
+                "wtxid": tx.wtxid().to_hex(),
+                "data": hex::encode(codec::encode(tx)),
+                "fee": tx.calculate_gas_fee(),
+            }))
+            .collect();
+        Ok(json!({
+            "height": header.height,
+            "previousBlockHash": header.prev_block_hash.to_hex(),
+            "merkleRoot": header.merkle_root.to_hex(),
+            "timestamp": header.timestamp.to_unix_timestamp(),
+            "difficulty": header.difficulty,
+            "target": hex::encode(difficulty_target(header.difficulty)),
+            "chainId": header.chain_id,
+            "coinbase": hex::encode(codec::encode(&block.transactions()[0])),
+            "transactions": transactions,
+            "extraNonce": {
+                "size": EXTRA_NONCE_SIZE,
+                "coinbasePrefix": hex::encode(&job.coinbase_prefix),
+                "coinbaseSuffix": hex::encode(&job.coinbase_suffix),
+                "merkleBranch": job.merkle_branch.iter().map(|hash| hash.to_hex()).collect::<Vec<_>>(),
+            },
+            "header": hex::encode(codec::encode(header)),
+            "block": hex::encode(codec::encode(&block)),
+        }))
+    }
+
+
+    /// Submit a hex-encoded block (see `blockchain_core::codec`), e.g. a
+    /// template from getBlockTemplate with its nonce found. The block is
+    /// announced to peers once it is on the chain; returns its hash.
+    pub async fn submit_block(&self, data: &str) -> Result<Value, RpcError> {
+        if !self.accepting_writes.load(Ordering::SeqCst) {
+            return Err(RpcError::BlockRejected("node is shutting down".to_string()));
+        }
+
+        let bytes = hex::decode(data.trim_start_matches("0x"))
+            .map_err(|e| RpcError::InvalidParams(format!("invalid hex: {}", e)))?;
+        let block: Block = codec::decode(&bytes)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid block encoding: {}", e)))?;
+
+        if let Some(writes) = &self.writes {
+            let block_id = writes.submit_block(block).await
+                .map_err(RpcError::BlockRejected)?;
+            return Ok(json!(block_id.to_hex()));
+        }
+
+        let block_id = self.blockchain.write().await.add_block(block.clone())
+            .map_err(|e| RpcError::BlockRejected(e.to_string()))?;
+        if let Some(network) = &self.network {
+            // peers the announcement doesn't reach catch up through sync
+            let _ = network.broadcast_block(&block).await;
+        }
+        Ok(json!(block_id.to_hex()))
+    }
+
+
+    pub async fn get_balance(&self, address: &str) -> Result<Value, RpcError> {
+        let address = Address::from_string(address)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid address: {}", e)))?;
+        Ok(json!(self.blockchain.read().await.get_balance(&address)))
+    }
+
+
+    /// Whether `signature` is a message signature over `message` by the key
+    /// behind `address`
+    pub fn verify_message(&self, address: &str, signature: &str, message: &str) -> Result<Value, RpcError> {
+        let address = Address::from_string(address)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid address: {}", e)))?;
+        Ok(json!(verify_message(&address, message.as_bytes(), signature)))
+    }
+
+
+    /// Balance and next nonce of an account
+    pub async fn get_account(&self, address: &str) -> Result<Value, RpcError> {
+        let address = Address::from_string(address)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid address: {}", e)))?;
+        let account = self.blockchain.read().await.world_state().get_account(&address);
+        Ok(json!({
+            "balance": account.balance,
+            "nonce": account.nonce,
+        }))
+    }
+
+
+    /// Unspent outputs paying `address`, with the tip height and coinbase
+    /// maturity needed to tell which of them can be spent yet
+    pub async fn get_utxos(&self, address: &str) -> Result<Value, RpcError> {
+        let address = Address::from_string(address)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid address: {}", e)))?;
+        let blockchain = self.blockchain.read().await;
+        let utxos: Vec<_> = match &self.indexer {
+            Some(indexer) => indexer.utxos_by_address(&address)
+                .map_err(|_| RpcError::InternalServerError)?,
+            None => blockchain.world_state().utxo_set()
+                .get_utxos_by_address(&address)
+                .into_iter()
+                .map(|(_, utxo)| utxo.clone())
+                .collect(),
+        };
+
+        Ok(json!({
+            "height": blockchain.height(),
+            "coinbaseMaturity": blockchain.validation_rules().coinbase_maturity,
+            "utxos": to_value(&utxos)?,
+        }))
+    }
+
+
+    /// Confirmed transactions touching `address`, newest first, one page at a time
+    pub fn get_transactions_by_address(&self, address: &str, page: usize) -> Result<Value, RpcError> {
+        let address = Address::from_string(address)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid address: {}", e)))?;
+        let indexer = self.indexer.as_ref().ok_or(RpcError::IndexerDisabled)?;
+        let transactions = indexer.transactions_by_address(&address, page)
+            .map_err(|_| RpcError::InternalServerError)?;
+
+        Ok(json!({
+            "page": page,
+            "transactions": transactions.iter()
+                .map(|tx| json!({
+                    "txid": tx.tx_id.to_hex(),
+                    "blockHeight": tx.block_height,
+                    "txIndex": tx.index,
+                }))
+                .collect::<Vec<_>>(),
+        }))
+    }
+
+
+    /// Main chain blocks with timestamps between `from` and `to` (unix seconds, inclusive)
+    pub fn get_blocks_by_time(&self, from: i64, to: i64) -> Result<Value, RpcError> {
+        let indexer = self.indexer.as_ref().ok_or(RpcError::IndexerDisabled)?;
+        let blocks = indexer.blocks_by_time(from, to)
+            .map_err(|_| RpcError::InternalServerError)?;
+
+        Ok(Value::Array(blocks.iter()
+            .map(|(height, block_id)| json!({ "height": height, "hash": block_id.to_hex() }))
+            .collect()))
+    }
+
+
+    /// Size, transaction count and fee figures of main chain blocks at
+    /// heights `from..=to`
+    pub fn get_block_stats(&self, from: u64, to: u64) -> Result<Value, RpcError> {
+        if from > to || to - from >= MAX_BLOCK_STATS_RANGE {
+            return Err(RpcError::InvalidParams(format!("range must cover 1 to {} blocks", MAX_BLOCK_STATS_RANGE)));
+        }
+        let indexer = self.indexer.as_ref().ok_or(RpcError::IndexerDisabled)?;
+        let stats = indexer.block_stats(from, to)
+            .map_err(|_| RpcError::InternalServerError)?;
+
+        Ok(Value::Array(stats.iter()
+            .map(|stats| json!({
+                "height": stats.height,
+                "hash": stats.block_id.to_hex(),
+                "timestamp": stats.timestamp,
+                "txCount": stats.tx_count,
+                "size": stats.size,
+                "totalFees": stats.total_fees,
+                "minFee": stats.min_fee,
+                "medianFee": stats.median_fee,
+                "maxFee": stats.max_fee,
+            }))
+            .collect()))
+    }
+
+
+    /// Transactions confirmed per UTC day between `from` and `to` (unix
+    /// seconds); days without any are left out
+    pub fn get_daily_transaction_counts(&self, from: i64, to: i64) -> Result<Value, RpcError> {
+        let indexer = self.indexer.as_ref().ok_or(RpcError::IndexerDisabled)?;
+        let days = indexer.daily_transactions(from, to)
+            .map_err(|_| RpcError::InternalServerError)?;
+
+        Ok(Value::Array(days.iter()
+            .map(|(day, count)| json!({ "day": day, "transactions": count }))
+            .collect()))
     }
 
 
-    pub async get_block_by_height(&self, height: u64) -> Result<Block, RpcError> {
-        let block = self.store.read().await.get_block_by_height(height).await
-        .map_err(|_| RpcError::InternalServerError)?;
-        block.ok_or(RpcError::BlockNotFound)
+    /// Mean seconds between the last `blocks` blocks
+    pub fn get_average_block_time(&self, blocks: u64) -> Result<Value, RpcError> {
+        if !(2..=MAX_BLOCK_TIME_WINDOW).contains(&blocks) {
+            return Err(RpcError::InvalidParams(format!("blocks must be between 2 and {}", MAX_BLOCK_TIME_WINDOW)));
+        }
+        let indexer = self.indexer.as_ref().ok_or(RpcError::IndexerDisabled)?;
+        let seconds = indexer.average_block_time(blocks)
+            .map_err(|_| RpcError::InternalServerError)?;
+        Ok(json!({ "blocks": blocks, "seconds": seconds }))
+    }
+
+
+    /// Largest balances, largest first. Read from the current state, so it
+    /// works without the indexer.
+    pub async fn get_richest_addresses(&self, limit: usize) -> Result<Value, RpcError> {
+        if limit == 0 || limit > MAX_RICHEST_LIMIT {
+            return Err(RpcError::InvalidParams(format!("limit must be 1 to {}", MAX_RICHEST_LIMIT)));
+        }
+        let richest = self.blockchain.read().await.world_state().richest_addresses(limit);
+
+        Ok(Value::Array(richest.iter()
+            .map(|(address, balance)| json!({ "address": address.to_string(), "balance": balance }))
+            .collect()))
+    }
+
 
+    /// Every coin in account balances and unspent outputs
+    pub async fn get_circulating_supply(&self) -> Result<Value, RpcError> {
+        Ok(json!(self.blockchain.read().await.world_state().total_supply()))
     }
 
 
-    pub async fn get_latest_block(&self) ->Result<Block, RpcError> {
-        let block = self.store.read().await.get_latest_block().await
-        .map_err(|_| RpcError::InternalServerError)?;
-        block.ok_or(RpcError::BlockNotFound)
+    /// Boundary headers a light client needs to check difficulty evolution
+    /// between two heights; `fromHeight` must be a retarget boundary
+    pub async fn get_difficulty_proof(&self, from: u64, to: u64) -> Result<Value, RpcError> {
+        let proof = self.blockchain.read().await.prove_difficulty(from, to)
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+        Ok(json!({
+            "fromHeight": from,
+            "toHeight": to,
+            "headers": to_value(&proof.headers)?,
+        }))
     }
 
-    pub async fn get_transaction(&self, tx: Transaction) -> Result<(), RpcError>{
-        self.network.send_transaction(tx).await
-        Ok(())
+
+    /// Merkle proof that a confirmed transaction is in its block, with the
+    /// block header, for light clients
+    pub async fn get_transaction_proof(&self, txid: &str) -> Result<Value, RpcError> {
+        let tx_id = TxId::from_hex(txid)
+            .map_err(|e| RpcError::InvalidParams(format!("invalid transaction id: {}", e)))?;
+
+        let blockchain = self.blockchain.read().await;
+        for height in (0..=blockchain.height()).rev() {
+            let Some(block) = blockchain.get_block_by_height(&height) else { continue };
+            if let Some(proof) = block.transaction_proof(&tx_id).map_err(|_| RpcError::InternalServerError)? {
+                return Ok(json!({
+                    "blockId": block.id().to_hex(),
+                    "header": to_value(&block.header)?,
+                    "proof": to_value(&proof)?,
+                }));
+            }
+        }
+        Err(not_found_or_pruned(&blockchain, RpcError::TransactionNotFound))
     }
 
-    pub async fn get_mempool(&self) ->Vec<Transaction>{
-        self.network.get_all_txs().await
+
+    pub async fn get_mempool_info(&self) -> Result<Value, RpcError> {
+        let stats = self.blockchain.read().await.mempool().get_stats();
+        to_value(&stats)
+    }
+
+
+    /// Consensus limits a block must fit in: per-resource caps and the combined weight
+    pub async fn get_block_limits(&self) -> Result<Value, RpcError> {
+        let blockchain = self.blockchain.read().await;
+        let rules = blockchain.validation_rules();
+        Ok(json!({
+            "maxBlockSize": rules.max_block_size,
+            "maxTransactionsPerBlock": rules.max_transactions_per_block,
+            "maxTransactionSize": rules.max_transaction_size,
+            "maxBlockComputeUnits": rules.max_block_compute_units,
+            "maxBlockSigOps": rules.block_weight.max_block_sig_ops,
+            "maxBlockWeight": rules.block_weight.max_block_weight,
+            "weights": {
+                "byte": rules.block_weight.byte_weight,
+                "sigOp": rules.block_weight.sig_op_weight,
+                "computeUnitsPerWeight": rules.block_weight.compute_units_per_weight,
+            },
+        }))
+    }
+
+
+    /// Chain and fork id that transaction signatures are bound to
+    pub async fn get_chain_id(&self) -> Result<Value, RpcError> {
+        let domain = self.blockchain.read().await.config().signing_domain();
+        Ok(json!({
+            "chainId": domain.chain_id,
+            "forkId": domain.fork_id,
+        }))
     }
 
 
+    /// Occupancy and hit rate of the signature cache shared by mempool and block validation
+    pub async fn get_signature_cache_info(&self) -> Result<Value, RpcError> {
+        let stats = self.blockchain.read().await.signature_cache_stats();
+        Ok(json!({
+            "entries": stats.entries,
+            "capacity": stats.capacity,
+            "hits": stats.hits,
+            "misses": stats.misses,
+            "insertions": stats.insertions,
+            "evictions": stats.evictions,
+            "hitRate": stats.hit_rate(),
+        }))
+    }
+
+
+    /// Deterministic dev accounts from the chain spec (empty outside Devnet/Local)
+    pub async fn get_dev_accounts(&self) -> Result<Value, RpcError> {
+        let accounts = self.blockchain.read().await.dev_accounts()
+            .map_err(|_| RpcError::InternalServerError)?;
+        to_value(&accounts)
+    }
+
+
+    /// Logs on the main chain matching a filter. Block blooms are checked first,
+    /// so sparse queries over long ranges stay cheap.
+    pub async fn get_logs(&self, query: LogQuery) -> Result<Value, RpcError> {
+        let blockchain = self.blockchain.read().await;
+        let height = blockchain.height();
+
+        let filter = query.into_filter(height)?;
+        check_not_pruned(&blockchain, filter.from_height)?;
+
+        let entries = blockchain.get_logs(&filter)
+            .map_err(|e| RpcError::InvalidParams(e.to_string()))?;
+
+        Ok(Value::Array(entries.iter().map(log_entry_json).collect()))
+    }
+
+
+    /// Blocks in the last `depth` heights, including competing branches and
+    /// orphans, for fork diagrams. Work is a decimal string since it can
+    /// exceed what JSON numbers hold exactly.
+    pub async fn get_chain_tree(&self, depth: u64) -> Result<Value, RpcError> {
+        if depth == 0 || depth > MAX_REORG_DEPTH {
+            return Err(RpcError::InvalidParams(format!("depth must be between 1 and {}", MAX_REORG_DEPTH)));
+        }
+
+        let tree = self.blockchain.read().await.get_chain_tree(depth);
+        let blocks: Vec<Value> = tree.nodes.iter()
+            .map(|node| json!({
+                "hash": node.block_id.to_string(),
+                "prevHash": node.prev_block_id.to_string(),
+                "height": node.height,
+                "timestamp": node.timestamp,
+                "difficulty": node.difficulty,
+                "txCount": node.tx_count,
+                "status": node.status,
+                "chainWork": node.chain_work.map(|work| work.to_string()),
+                "isTip": node.is_tip,
+                "branchLength": node.branch_length,
+            }))
+            .collect();
+
+        Ok(json!({
+            "tip": tree.tip.map(|tip| tip.to_string()),
+            "height": tree.height,
+            "startHeight": tree.start_height,
+            "blocks": blocks,
+        }))
+    }
+
+
+    /// Connected peers with bytes sent/received per message class
+    pub async fn get_peer_info(&self) -> Result<Value, RpcError> {
+        let peers = match &self.network {
+            Some(network) => network.peer_info().await,
+            None => Vec::new(),
+        };
+        to_value(&peers)
+    }
+
+
+    /// Chain, mempool, peer and mining gauges plus the node's counters, in
+    /// the Prometheus text format
+    pub async fn render_metrics(&self) -> String {
+        let (height, mempool) = {
+            let blockchain = self.blockchain.read().await;
+            (blockchain.height(), blockchain.mempool().get_stats())
+        };
+        let peers = match &self.network {
+            Some(network) => network.peer_info().await.len(),
+            None => 0,
+        };
+        let mining = self.status.mining();
+
+        let mut out = MetricsWriter::new();
+        out.gauge("kaiblock_chain_height", "Height of the main chain tip", height as f64);
+        out.gauge("kaiblock_mempool_transactions", "Transactions in the mempool", mempool.transaction_count as f64);
+        out.gauge("kaiblock_mempool_bytes", "Memory used by mempool transactions", mempool.memory_usage as f64);
+        out.gauge("kaiblock_peers_connected", "Connected peers", peers as f64);
+        out.gauge("kaiblock_hash_rate", "Average miner hashes per second (0 when not mining)", mining.as_ref().map_or(0.0, |mining| mining.hash_rate));
+        out.counter("kaiblock_blocks_mined_total", "Blocks found by the local miner", mining.as_ref().map_or(0, |mining| mining.blocks_found) as f64);
+        out.gauge("kaiblock_uptime_seconds", "Seconds since the node started", self.status.uptime().as_secs_f64());
+        self.metrics.write_to(&mut out);
+        out.finish()
+    }
+
+
+    /// Everything an operator dashboard shows, in one call: version, uptime,
+    /// sync, tip, peers, mempool, mining, storage and recent errors
+    pub async fn get_node_status(&self) -> Result<Value, RpcError> {
+        let (tip, mempool) = {
+            let blockchain = self.blockchain.read().await;
+            let tip = blockchain.get_chain_head().map(|head| json!({
+                "hash": head.id().to_string(),
+                "height": head.height(),
+                "timestamp": head.timestamp().to_unix_timestamp(),
+                "difficulty": head.header.difficulty,
+                "txCount": head.transaction_count(),
+                "chainWork": blockchain.chain_work(&head.id()).map(|work| work.to_string()),
+            }));
+            (tip, blockchain.mempool().get_stats())
+        };
+
+        let peers = match &self.network {
+            Some(network) => network.peer_info().await,
+            None => Vec::new(),
+        };
+
+        let progress = self.sync_status.progress();
+        let errors = self.status.recent_errors();
+        let events = self.events.stats();
+
+        Ok(json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime": self.status.uptime().as_secs(),
+            "acceptingWrites": self.accepting_writes.load(Ordering::SeqCst),
+            "sync": {
+                "stage": progress.stage,
+                "syncing": progress.is_syncing(),
+                "currentHeight": progress.current_height,
+                "targetHeight": progress.target_height,
+                "percent": progress.percent(),
+            },
+            "tip": tip,
+            "peers": {
+                "networking": self.network.is_some(),
+                "count": peers.len(),
+                "bytesSent": peers.iter().map(|peer| peer.bandwidth.sent.total).sum::<u64>(),
+                "bytesReceived": peers.iter().map(|peer| peer.bandwidth.received.total).sum::<u64>(),
+            },
+            "mempool": {
+                "size": mempool.transaction_count,
+                "bytes": mempool.memory_usage,
+                "totalFees": mempool.total_fees,
+                "avgFeePerByte": mempool.avg_fee_per_byte,
+            },
+            "mining": self.status.mining(),
+            "storage": {
+                "dataDir": self.status.data_dir().map(|dir| dir.display().to_string()),
+                "bytes": self.status.storage_usage(),
+            },
+            "errors": {
+                "windowSecs": RECENT_ERROR_WINDOW.as_secs(),
+                "total": errors.values().sum::<usize>(),
+                "byComponent": errors,
+            },
+            "events": {
+                "subscribers": events.subscribers,
+                "published": events.published,
+                "dropped": events.dropped,
+                "disconnected": events.disconnected,
+            },
+        }))
+    }
+
+
+    pub fn get_sync_status(&self) -> Result<Value, RpcError> {
+        let progress = self.sync_status.progress();
+        Ok(json!({
+            "stage": progress.stage,
+            "syncing": progress.is_syncing(),
+            "currentHeight": progress.current_height,
+            "targetHeight": progress.target_height,
+            "headersDownloaded": progress.headers_downloaded,
+            "blocksDownloaded": progress.blocks_downloaded,
+            "peers": progress.peers,
+            "percent": progress.percent(),
+        }))
+    }
+
+
+}
+
+
+// 256-bit target for a difficulty of that many leading zero bits: a
+// header hash meets it if it is numerically at or below the target
+fn difficulty_target(difficulty: Difficulty) -> [u8; 32] {
+    let mut target = [0xff; 32];
+    for (index, byte) in target.iter_mut().enumerate() {
+        let zero_bits = difficulty.saturating_sub(index as u64 * 8).min(8);
+        *byte = (0xffu16 >> zero_bits) as u8;
+    }
+    target
 }
 
+fn to_value<T: serde::Serialize>(value: &T) -> Result<Value, RpcError> {
+    serde_json::to_value(value).map_err(|_| RpcError::InternalServerError)
+}
 
+// refuse to answer from a block this node has cut down to its header
+fn check_not_pruned(blockchain: &Blockchain, height: u64) -> Result<(), RpcError> {
+    match blockchain.pruned_height() {
+        Some(pruned) if height <= pruned => Err(RpcError::Pruned(pruned)),
+        _ => Ok(()),
+    }
+}
+
+// a search that came up empty may have missed data in pruned blocks
+fn not_found_or_pruned(blockchain: &Blockchain, not_found: RpcError) -> RpcError {
+    match blockchain.pruned_height() {
+        Some(pruned) => RpcError::Pruned(pruned),
+        None => not_found,
+    }
+}
+
+
+/// Parameter of getLogs. Heights default to the current tip; topics are per
+/// position, with null matching anything.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogQuery {
+    pub from_block: Option<u64>,
+    pub to_block: Option<u64>,
+    #[serde(default)]
+    pub address: OneOrMany<String>,
+    #[serde(default)]
+    pub topics: Vec<Option<OneOrMany<String>>>,
+}
+
+impl LogQuery {
+    /// The filter this query describes; missing heights default to `tip`
+    pub fn into_filter(self, tip: u64) -> Result<LogFilter, RpcError> {
+        Ok(LogFilter {
+            from_height: self.from_block.unwrap_or(tip),
+            to_height: self.to_block.unwrap_or(tip),
+            addresses: self.address.into_vec().iter()
+                .map(|address| Address::from_string(address)
+                    .map_err(|e| RpcError::InvalidParams(format!("invalid address: {}", e))))
+                .collect::<Result<_, _>>()?,
+            topics: self.topics.into_iter()
+                .map(|position| position.map(OneOrMany::into_vec).unwrap_or_default().iter()
+                    .map(|topic| Hash256::from_hex(topic)
+                        .map_err(|e| RpcError::InvalidParams(format!("invalid topic: {}", e))))
+                    .collect::<Result<_, _>>())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+
+/// A log as getLogs and log subscriptions return it
+pub fn log_entry_json(entry: &LogEntry) -> Value {
+    json!({
+        "address": entry.log.address.to_string(),
+        "topics": entry.log.topics.iter().map(|topic| topic.to_hex()).collect::<Vec<_>>(),
+        "data": hex::encode(&entry.log.data),
+        "blockHash": entry.block_id.to_string(),
+        "blockHeight": entry.block_height,
+        "txid": entry.tx_id.to_string(),
+        "txIndex": entry.tx_index,
+        "logIndex": entry.log_index,
+    })
+}
+
+
+/// A single value or a list of alternatives
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany<T> {
+    One(T),
+    Many(Vec<T>),
+}
+
+impl<T> OneOrMany<T> {
+    fn into_vec(self) -> Vec<T> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+impl<T> Default for OneOrMany<T> {
+    fn default() -> Self {
+        OneOrMany::Many(Vec::new())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{signature::Keypair, AddressType};
+    use std::sync::Mutex;
+
+    /// Node write path that records what it was given
+    #[derive(Default)]
+    struct RecordingWrites {
+        transactions: Mutex<Vec<TxId>>,
+        reject: AtomicBool,
+    }
+
+    #[async_trait]
+    impl NodeWrites for RecordingWrites {
+        async fn submit_transaction(&self, tx: Transaction) -> Result<TxId, String> {
+            if self.reject.load(Ordering::SeqCst) {
+                return Err("mempool full".to_string());
+            }
+            self.transactions.lock().unwrap().push(tx.id());
+            Ok(tx.id())
+        }
+
+        async fn submit_block(&self, block: Block) -> Result<BlockId, String> {
+            Ok(block.id())
+        }
+    }
+
+    fn handler() -> RpcHandler {
+        RpcHandler::new(Arc::new(RwLock::new(Blockchain::default())))
+    }
+
+    fn address() -> Address {
+        Address::from_public_key(&Keypair::generate().public_key(), AddressType::Base58)
+    }
+
+    fn raw_transaction(nonce: u64) -> (String, TxId) {
+        let tx = Transaction::new_account(address(), address(), 100, nonce, 21000, 10, vec![]);
+        (hex::encode(codec::encode(&tx)), tx.id())
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_submits_once() {
+        let writes = Arc::new(RecordingWrites::default());
+        let handler = handler().with_node_writes(writes.clone());
+        let (data, tx_id) = raw_transaction(0);
+
+        let first = handler.send_raw_transaction(&data, Some("retry-1".to_string())).await.unwrap();
+        let second = handler.send_raw_transaction(&data, Some("retry-1".to_string())).await.unwrap();
+        assert_eq!(first, json!(tx_id.to_hex()));
+        assert_eq!(second, first);
+        assert_eq!(*writes.transactions.lock().unwrap(), vec![tx_id]);
+
+        // the key is bound to the first transaction
+        let (other, _) = raw_transaction(1);
+        let err = handler.send_raw_transaction(&other, Some("retry-1".to_string())).await.unwrap_err();
+        assert!(matches!(err, RpcError::InvalidParams(_)));
+    }
+
+    #[tokio::test]
+    async fn test_rejected_submission_can_be_retried_with_its_key() {
+        let writes = Arc::new(RecordingWrites::default());
+        let handler = handler().with_node_writes(writes.clone());
+        let (data, tx_id) = raw_transaction(0);
+
+        writes.reject.store(true, Ordering::SeqCst);
+        let err = handler.send_raw_transaction(&data, Some("retry-1".to_string())).await.unwrap_err();
+        assert!(matches!(err, RpcError::TransactionRejected(_)));
+
+        writes.reject.store(false, Ordering::SeqCst);
+        handler.send_raw_transaction(&data, Some("retry-1".to_string())).await.unwrap();
+        assert_eq!(*writes.transactions.lock().unwrap(), vec![tx_id]);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_length_is_checked() {
+        let handler = handler().with_node_writes(Arc::new(RecordingWrites::default()));
+        let (data, _) = raw_transaction(0);
+
+        for key in [String::new(), "k".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1)] {
+            let err = handler.send_raw_transaction(&data, Some(key)).await.unwrap_err();
+            assert!(matches!(err, RpcError::InvalidParams(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mined_template_is_accepted() {
+        let handler = handler();
+        let genesis = handler.blockchain.read().await.get_block_by_height(&0).unwrap().id();
+
+        let template = handler.get_block_template(&address().to_string()).await.unwrap();
+        assert_eq!(template["height"], 1);
+        assert_eq!(template["previousBlockHash"], json!(genesis.to_hex()));
+
+        let block_hash = handler.blockchain.read().await.config().validation_rules.block_hash;
+        let mut block: Block = codec::decode(&hex::decode(template["block"].as_str().unwrap()).unwrap()).unwrap();
+        // a miner that ran through the header nonce rolls the extra nonce
+        block.set_extra_nonce(5).unwrap();
+        assert!(block.mine_with(block_hash, None).unwrap());
+
+        let submitted = handler.submit_block(&hex::encode(codec::encode(&block))).await.unwrap();
+        assert_eq!(submitted, json!(block.id().to_hex()));
+        assert_eq!(handler.get_block_height().await.unwrap(), json!(1));
+
+        // the next template builds on the submitted block
+        let template = handler.get_block_template(&address().to_string()).await.unwrap();
+        assert_eq!(template["height"], 2);
+        assert_eq!(template["previousBlockHash"], json!(block.id().to_hex()));
+    }
+
+    #[tokio::test]
+    async fn test_submit_block_rejections() {
+        let handler = handler();
+        assert!(matches!(handler.submit_block("not hex").await, Err(RpcError::InvalidParams(_))));
+        assert!(matches!(handler.submit_block("00").await, Err(RpcError::InvalidParams(_))));
+        assert!(matches!(handler.get_block_template("not an address").await, Err(RpcError::InvalidParams(_))));
+
+        let template = handler.get_block_template(&address().to_string()).await.unwrap();
+        handler.accepting_writes.store(false, Ordering::SeqCst);
+        let err = handler.submit_block(template["block"].as_str().unwrap()).await.unwrap_err();
+        assert!(matches!(err, RpcError::BlockRejected(_)));
+    }
+
+    #[test]
+    fn test_difficulty_target() {
+        assert_eq!(difficulty_target(0), [0xff; 32]);
+
+        let target = difficulty_target(12);
+        assert_eq!(target[0], 0x00);
+        assert_eq!(target[1], 0x0f);
+        assert_eq!(target[2], 0xff);
+    }
+}
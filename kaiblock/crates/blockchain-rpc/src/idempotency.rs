@@ -0,0 +1,103 @@
+use blockchain_core::TxId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, MutexGuard};
+
+
+/// How long a key is remembered by default
+pub const DEFAULT_IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Longest key a client may send
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 128;
+
+
+/// What a key was first used for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdempotentSubmission {
+    /// id of the transaction submitted with the key
+    pub tx_id: TxId,
+    recorded_at: Instant,
+}
+
+
+/// Recent (idempotency key -> txid) mappings for transaction submission.
+///
+/// A client that times out can resend the same request with the same key and
+/// gets the original txid back instead of submitting twice. Only accepted
+/// submissions are recorded, so a rejected one can be retried with the same key.
+pub struct IdempotencyCache {
+    window: Duration,
+    entries: Mutex<HashMap<String, IdempotentSubmission>>,
+}
+
+impl IdempotencyCache {
+    pub fn new(window: Duration) -> Self {
+        Self { window, entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// Lock the cache for one submission. Holding the guard across the submit
+    /// keeps two concurrent retries with the same key from both going through.
+    pub async fn lock(&self) -> IdempotencyGuard<'_> {
+        let mut entries = self.entries.lock().await;
+        let window = self.window;
+        entries.retain(|_, entry| entry.recorded_at.elapsed() < window);
+        IdempotencyGuard { entries }
+    }
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_IDEMPOTENCY_WINDOW)
+    }
+}
+
+
+pub struct IdempotencyGuard<'a> {
+    entries: MutexGuard<'a, HashMap<String, IdempotentSubmission>>,
+}
+
+impl IdempotencyGuard<'_> {
+    /// Earlier submission recorded under `key`, if still in the window
+    pub fn get(&self, key: &str) -> Option<IdempotentSubmission> {
+        self.entries.get(key).copied()
+    }
+
+    /// Remember that `key` submitted `tx_id`
+    pub fn record(&mut self, key: String, tx_id: TxId) {
+        self.entries.insert(key, IdempotentSubmission { tx_id, recorded_at: Instant::now() });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::Hash256;
+
+    fn tx_id(byte: u8) -> TxId {
+        TxId::new(Hash256::from_bytes([byte; 32]))
+    }
+
+    #[tokio::test]
+    async fn test_recorded_key_returns_first_txid() {
+        let cache = IdempotencyCache::default();
+        {
+            let mut guard = cache.lock().await;
+            assert!(guard.get("key").is_none());
+            guard.record("key".to_string(), tx_id(1));
+        }
+
+        let guard = cache.lock().await;
+        assert_eq!(guard.get("key").map(|entry| entry.tx_id), Some(tx_id(1)));
+        assert!(guard.get("other").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_keys_are_forgotten_after_the_window() {
+        let cache = IdempotencyCache::new(Duration::from_millis(20));
+        cache.lock().await.record("key".to_string(), tx_id(1));
+
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert!(cache.lock().await.get("key").is_none());
+    }
+}
@@ -0,0 +1,118 @@
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use crate::errors::RpcError;
+
+
+pub const JSONRPC_VERSION: &str = "2.0";
+
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct JsonRpcRequest {
+    pub jsonrpc: String,
+    pub method: String,
+    #[serde(default)]
+    pub params: Value,
+    /// absent for notifications, which get no response. An explicit
+    /// `"id": null` is a request like any other and is answered
+    #[serde(default, deserialize_with = "present")]
+    pub id: Option<Value>,
+}
+
+
+// Wrap whatever value the member has, null included; only a missing member
+// falls back to the default of None
+fn present<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonRpcErrorObject {
+    pub code: i64,
+    pub message: String,
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonRpcResponse {
+    pub jsonrpc: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcErrorObject>,
+    pub id: Value,
+}
+
+impl JsonRpcResponse {
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            result: Some(result),
+            error: None,
+            id,
+        }
+    }
+
+    pub fn failure(id: Value, error: RpcError) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            result: None,
+            error: Some(JsonRpcErrorObject {
+                code: error.code(),
+                message: error.to_string(),
+            }),
+            id,
+        }
+    }
+}
+
+
+/// Positional (`[a, b]`) or named (`{"name": a}`) parameter lookup
+pub fn param(params: &Value, index: usize, name: &str) -> Option<Value> {
+    match params {
+        Value::Array(values) => values.get(index).cloned(),
+        Value::Object(map) => map.get(name).cloned(),
+        _ => None,
+    }
+}
+
+pub fn required_param<T: serde::de::DeserializeOwned>(params: &Value, index: usize, name: &str) -> Result<T, RpcError> {
+    let value = param(params, index, name)
+        .ok_or_else(|| RpcError::InvalidParams(format!("missing parameter `{}`", name)))?;
+    serde_json::from_value(value)
+        .map_err(|e| RpcError::InvalidParams(format!("parameter `{}`: {}", name, e)))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(body: Value) -> JsonRpcRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_null_id_is_not_a_notification() {
+        let with_null = request(json!({"jsonrpc": "2.0", "method": "getBlockHeight", "id": null}));
+        assert_eq!(with_null.id, Some(Value::Null));
+
+        let without = request(json!({"jsonrpc": "2.0", "method": "getBlockHeight"}));
+        assert_eq!(without.id, None);
+
+        let numbered = request(json!({"jsonrpc": "2.0", "method": "getBlockHeight", "id": 7}));
+        assert_eq!(numbered.id, Some(json!(7)));
+    }
+
+    #[test]
+    fn test_param_lookup() {
+        assert_eq!(param(&json!([1, 2]), 1, "b"), Some(json!(2)));
+        assert_eq!(param(&json!({"b": 2}), 1, "b"), Some(json!(2)));
+        assert_eq!(param(&Value::Null, 0, "a"), None);
+
+        let err = required_param::<u64>(&json!(["x"]), 0, "height").unwrap_err();
+        assert_eq!(err.code(), -32602);
+        assert!(required_param::<u64>(&json!([]), 0, "height").is_err());
+    }
+}
@@ -1,14 +1,22 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+pub mod server;
+pub mod handlers;
+pub mod errors;
+pub mod events;
+pub mod event_bus;
+pub mod jsonrpc;
+pub mod idempotency;
+pub mod status;
+pub mod metrics;
+pub mod rest;
+pub mod subscriptions;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use server::RpcServer;
+pub use handlers::{NodeWrites, RpcHandler};
+pub use errors::RpcError;
+pub use events::{NodeEvent, BlockSummary, Topic};
+pub use event_bus::{EventBus, EventBusConfig, EventBusStats, LagPolicy, Subscription};
+pub use jsonrpc::{JsonRpcRequest, JsonRpcResponse};
+pub use idempotency::IdempotencyCache;
+pub use status::{MiningStatus, NodeStatus};
+pub use metrics::{MetricsWriter, NodeMetrics, METRICS_CONTENT_TYPE};
+pub use subscriptions::{ConnectionSubscriptions, SubscriptionKind};
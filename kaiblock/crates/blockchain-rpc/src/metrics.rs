@@ -0,0 +1,213 @@
+use blockchain_core::ChainEvent;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+
+/// Content type of the Prometheus text exposition format served on `GET /metrics`
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Upper bounds, in seconds, of the block validation time buckets
+const VALIDATION_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// blocks validated per bucket of `VALIDATION_BUCKETS`, plus one past the last
+    validation_buckets: [u64; VALIDATION_BUCKETS.len() + 1],
+    validation_seconds: f64,
+    blocks_accepted: u64,
+    blocks_rejected: u64,
+    reorgs: u64,
+    /// the last chain event disconnected a block; the reorg is already counted
+    disconnecting: bool,
+}
+
+
+/// Counters the node bumps as things happen. Gauges such as chain height
+/// or peer count are read from their source when metrics are scraped
+/// instead. Clones share the same counters.
+#[derive(Debug, Clone, Default)]
+pub struct NodeMetrics {
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl NodeMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Time taken to validate and apply (or reject) a block
+    pub fn observe_block_validation(&self, elapsed: Duration, accepted: bool) {
+        let seconds = elapsed.as_secs_f64();
+        let bucket = VALIDATION_BUCKETS.iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(VALIDATION_BUCKETS.len());
+
+        let mut counters = self.counters();
+        counters.validation_buckets[bucket] += 1;
+        counters.validation_seconds += seconds;
+        if accepted {
+            counters.blocks_accepted += 1;
+        } else {
+            counters.blocks_rejected += 1;
+        }
+    }
+
+    /// Follow chain events to count reorgs: a run of disconnected blocks is one reorg
+    pub fn record_chain_event(&self, event: &ChainEvent) {
+        let mut counters = self.counters();
+        match event {
+            ChainEvent::BlockDisconnected(_) => {
+                if !counters.disconnecting {
+                    counters.reorgs += 1;
+                }
+                counters.disconnecting = true;
+            }
+            ChainEvent::BlockConnected(_) => counters.disconnecting = false,
+            _ => {}
+        }
+    }
+
+    pub fn reorgs(&self) -> u64 {
+        self.counters().reorgs
+    }
+
+    /// Append the counters to `out`
+    pub fn write_to(&self, out: &mut MetricsWriter) {
+        let counters = self.counters();
+        out.counter("kaiblock_blocks_accepted_total", "Blocks validated and added to the chain", counters.blocks_accepted as f64);
+        out.counter("kaiblock_blocks_rejected_total", "Blocks that failed validation", counters.blocks_rejected as f64);
+        out.counter("kaiblock_reorgs_total", "Chain reorganizations", counters.reorgs as f64);
+
+        let name = "kaiblock_block_validation_seconds";
+        out.header(name, "Time to validate and apply a block", "histogram");
+        let mut cumulative = 0;
+        for (bound, count) in VALIDATION_BUCKETS.iter().zip(&counters.validation_buckets) {
+            cumulative += count;
+            let _ = writeln!(out.text, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let total: u64 = counters.validation_buckets.iter().sum();
+        let _ = writeln!(out.text, "{}_bucket{{le=\"+Inf\"}} {}", name, total);
+        let _ = writeln!(out.text, "{}_sum {}", name, counters.validation_seconds);
+        let _ = writeln!(out.text, "{}_count {}", name, total);
+    }
+
+    fn counters(&self) -> std::sync::MutexGuard<'_, Counters> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+
+/// Builds a Prometheus text exposition page
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    text: String,
+}
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.sample(name, help, "gauge", value);
+    }
+
+    pub fn counter(&mut self, name: &str, help: &str, value: f64) {
+        self.sample(name, help, "counter", value);
+    }
+
+    pub fn finish(self) -> String {
+        self.text
+    }
+
+    fn sample(&mut self, name: &str, help: &str, kind: &str, value: f64) {
+        self.header(name, help, kind);
+        let _ = writeln!(self.text, "{} {}", name, value);
+    }
+
+    fn header(&mut self, name: &str, help: &str, kind: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::{Blockchain, Hash256, TxDropReason, TxId};
+
+    fn genesis() -> Arc<blockchain_core::Block> {
+        Arc::new(Blockchain::default().get_block_by_height(&0).unwrap().clone())
+    }
+
+    fn sample<'a>(page: &'a str, name: &str) -> &'a str {
+        page.lines()
+            .find_map(|line| line.strip_prefix(name).and_then(|rest| rest.strip_prefix(' ')))
+            .unwrap_or_else(|| panic!("{} missing from\n{}", name, page))
+    }
+
+    #[test]
+    fn test_a_run_of_disconnects_is_one_reorg() {
+        let metrics = NodeMetrics::new();
+        let block = genesis();
+
+        metrics.record_chain_event(&ChainEvent::BlockConnected(block.clone()));
+        assert_eq!(metrics.reorgs(), 0);
+
+        for _ in 0..3 {
+            metrics.record_chain_event(&ChainEvent::BlockDisconnected(block.clone()));
+        }
+        // other events don't end the run
+        metrics.record_chain_event(&ChainEvent::TxDropped {
+            tx_id: TxId::new(Hash256::zero()),
+            reason: TxDropReason::Expired,
+        });
+        metrics.record_chain_event(&ChainEvent::BlockDisconnected(block.clone()));
+        assert_eq!(metrics.reorgs(), 1);
+
+        metrics.record_chain_event(&ChainEvent::BlockConnected(block.clone()));
+        metrics.record_chain_event(&ChainEvent::BlockDisconnected(block));
+        assert_eq!(metrics.reorgs(), 2);
+    }
+
+    #[test]
+    fn test_validation_histogram_is_cumulative() {
+        let metrics = NodeMetrics::new();
+        metrics.observe_block_validation(Duration::from_micros(500), true);
+        metrics.observe_block_validation(Duration::from_millis(20), true);
+        metrics.observe_block_validation(Duration::from_secs(10), false);
+
+        let mut out = MetricsWriter::new();
+        metrics.write_to(&mut out);
+        let page = out.finish();
+
+        assert_eq!(sample(&page, "kaiblock_blocks_accepted_total"), "2");
+        assert_eq!(sample(&page, "kaiblock_blocks_rejected_total"), "1");
+        assert_eq!(sample(&page, "kaiblock_block_validation_seconds_bucket{le=\"0.001\"}"), "1");
+        assert_eq!(sample(&page, "kaiblock_block_validation_seconds_bucket{le=\"0.025\"}"), "2");
+        assert_eq!(sample(&page, "kaiblock_block_validation_seconds_bucket{le=\"5\"}"), "2");
+        assert_eq!(sample(&page, "kaiblock_block_validation_seconds_bucket{le=\"+Inf\"}"), "3");
+        assert_eq!(sample(&page, "kaiblock_block_validation_seconds_count"), "3");
+        assert!(page.contains("# TYPE kaiblock_block_validation_seconds histogram"));
+    }
+
+    #[test]
+    fn test_writer_emits_help_and_type() {
+        let mut out = MetricsWriter::new();
+        out.gauge("kaiblock_chain_height", "Height of the main chain tip", 7.0);
+        assert_eq!(out.finish(), "# HELP kaiblock_chain_height Height of the main chain tip\n\
+                                  # TYPE kaiblock_chain_height gauge\n\
+                                  kaiblock_chain_height 7\n");
+    }
+
+    #[test]
+    fn test_clones_share_counters() {
+        let metrics = NodeMetrics::new();
+        let node_side = metrics.clone();
+        node_side.record_chain_event(&ChainEvent::BlockDisconnected(genesis()));
+        assert_eq!(metrics.reorgs(), 1);
+    }
+}
@@ -1,9 +0,0 @@
-pub mod server;
-pub mod handlers;
-pub mod errors;
-
-pub use server::RcpServer;
-pub use handlers::RpcHandler;
-pub use errors::RpcError;
-
-
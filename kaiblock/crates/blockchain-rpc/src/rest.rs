@@ -0,0 +1,481 @@
+//! REST gateway for explorers and web apps.
+//!
+//! Routes answer from the same handlers as JSON-RPC, so both agree on what
+//! they return; errors come back as `{"error": {"code", "message"}}` with the
+//! JSON-RPC error code and a matching HTTP status. The OpenAPI document on
+//! `GET /openapi.json` is generated from `ROUTES`, which lists every route.
+
+use crate::errors::RpcError;
+use crate::events::BlockSummary;
+use crate::handlers::{RpcHandler, DEFAULT_BLOCK_TIME_WINDOW, DEFAULT_RICHEST_LIMIT};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+
+/// Blocks per page of `GET /blocks` unless `limit` says otherwise
+pub const DEFAULT_PAGE_LIMIT: u64 = 20;
+
+/// Most blocks one page of `GET /blocks` returns
+pub const MAX_PAGE_LIMIT: u64 = 100;
+
+
+/// A documented route
+pub struct RouteDoc {
+    pub method: &'static str,
+    /// OpenAPI path template
+    pub path: &'static str,
+    pub summary: &'static str,
+    /// (name, location, description) of each parameter
+    pub params: &'static [(&'static str, &'static str, &'static str)],
+}
+
+/// Every REST route, in the order they are matched
+pub const ROUTES: &[RouteDoc] = &[
+    RouteDoc {
+        method: "get",
+        path: "/blocks",
+        summary: "Main chain block summaries, newest first",
+        params: &[
+            ("before", "query", "list blocks below this height (defaults to the tip + 1)"),
+            ("limit", "query", "blocks per page, at most 100"),
+        ],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/blocks/{id}",
+        summary: "A block by height or hash",
+        params: &[("id", "path", "block height, or block hash in hex")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/tx/{id}",
+        summary: "A confirmed or pending transaction",
+        params: &[("id", "path", "transaction id in hex")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/tx/{id}/receipt",
+        summary: "Block, status and fee of a confirmed transaction",
+        params: &[("id", "path", "transaction id in hex")],
+    },
+    RouteDoc {
+        method: "post",
+        path: "/tx",
+        summary: "Submit a transaction: {\"data\": hex codec encoding}; returns its id",
+        params: &[("Idempotency-Key", "header", "resubmitting with the same key returns the first id")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/address/{address}/balance",
+        summary: "Balance of an address",
+        params: &[("address", "path", "address")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/address/{address}/utxos",
+        summary: "Unspent outputs paying an address",
+        params: &[("address", "path", "address")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/address/{address}/transactions",
+        summary: "Confirmed transactions touching an address, newest first (needs the indexer)",
+        params: &[("address", "path", "address"), ("page", "query", "page number, from 0")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/stats/blocks",
+        summary: "Size, transaction count and fees of each block in a height range, at most 1000 (needs the indexer)",
+        params: &[("from", "query", "first height"), ("to", "query", "last height")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/stats/daily-transactions",
+        summary: "Transactions confirmed per UTC day (needs the indexer)",
+        params: &[("from", "query", "unix seconds"), ("to", "query", "unix seconds")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/stats/block-time",
+        summary: "Mean seconds between recent blocks (needs the indexer)",
+        params: &[("blocks", "query", "blocks to average over, 2 to 10000, default 100")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/stats/richest",
+        summary: "Largest balances, largest first",
+        params: &[("limit", "query", "addresses to return, at most 100, default 20")],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/stats/supply",
+        summary: "Circulating supply",
+        params: &[],
+    },
+    RouteDoc {
+        method: "get",
+        path: "/openapi.json",
+        summary: "This document",
+        params: &[],
+    },
+];
+
+
+#[derive(Debug, Deserialize)]
+struct BlockPage {
+    before: Option<u64>,
+    limit: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AddressPage {
+    #[serde(default)]
+    page: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct Range<T> {
+    from: T,
+    to: T,
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockTimeWindow {
+    blocks: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Limit {
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SubmitTransaction {
+    data: String,
+}
+
+
+/// The REST routes, ready to be combined with the server's other filters
+pub fn routes(handler: Arc<RpcHandler>) -> BoxedFilter<(Box<dyn Reply>,)> {
+    let handler = warp::any().map(move || handler.clone());
+
+    let blocks = warp::path!("blocks")
+        .and(warp::get())
+        .and(warp::query::<BlockPage>())
+        .and(handler.clone())
+        .and_then(|page: BlockPage, handler: Arc<RpcHandler>| async move {
+            reply(list_blocks(&handler, page).await)
+        });
+
+    let block = warp::path!("blocks" / String)
+        .and(warp::get())
+        .and(handler.clone())
+        .and_then(|id: String, handler: Arc<RpcHandler>| async move {
+            reply(match id.parse::<u64>() {
+                Ok(height) => handler.get_block_by_height(height).await,
+                Err(_) => handler.get_block_by_hash(&id).await,
+            })
+        });
+
+    let transaction = warp::path!("tx" / String)
+        .and(warp::get())
+        .and(handler.clone())
+        .and_then(|id: String, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_transaction(&id).await)
+        });
+
+    let receipt = warp::path!("tx" / String / "receipt")
+        .and(warp::get())
+        .and(handler.clone())
+        .and_then(|id: String, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_transaction_receipt(&id).await)
+        });
+
+    let submit = warp::path!("tx")
+        .and(warp::post())
+        .and(warp::body::json::<SubmitTransaction>())
+        .and(warp::header::optional::<String>("idempotency-key"))
+        .and(handler.clone())
+        .and_then(|body: SubmitTransaction, key: Option<String>, handler: Arc<RpcHandler>| async move {
+            let submitted = handler.send_raw_transaction(&body.data, key).await
+                .map(|tx_id| json!({ "txid": tx_id }));
+            reply_with(submitted, StatusCode::ACCEPTED)
+        });
+
+    let balance = warp::path!("address" / String / "balance")
+        .and(warp::get())
+        .and(handler.clone())
+        .and_then(|address: String, handler: Arc<RpcHandler>| async move {
+            let balance = handler.get_balance(&address).await
+                .map(|balance| json!({ "address": address, "balance": balance }));
+            reply(balance)
+        });
+
+    let utxos = warp::path!("address" / String / "utxos")
+        .and(warp::get())
+        .and(handler.clone())
+        .and_then(|address: String, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_utxos(&address).await)
+        });
+
+    let address_transactions = warp::path!("address" / String / "transactions")
+        .and(warp::get())
+        .and(warp::query::<AddressPage>())
+        .and(handler.clone())
+        .and_then(|address: String, page: AddressPage, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_transactions_by_address(&address, page.page))
+        });
+
+    let block_stats = warp::path!("stats" / "blocks")
+        .and(warp::get())
+        .and(warp::query::<Range<u64>>())
+        .and(handler.clone())
+        .and_then(|range: Range<u64>, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_block_stats(range.from, range.to))
+        });
+
+    let daily_transactions = warp::path!("stats" / "daily-transactions")
+        .and(warp::get())
+        .and(warp::query::<Range<i64>>())
+        .and(handler.clone())
+        .and_then(|range: Range<i64>, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_daily_transaction_counts(range.from, range.to))
+        });
+
+    let block_time = warp::path!("stats" / "block-time")
+        .and(warp::get())
+        .and(warp::query::<BlockTimeWindow>())
+        .and(handler.clone())
+        .and_then(|window: BlockTimeWindow, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_average_block_time(window.blocks.unwrap_or(DEFAULT_BLOCK_TIME_WINDOW)))
+        });
+
+    let richest = warp::path!("stats" / "richest")
+        .and(warp::get())
+        .and(warp::query::<Limit>())
+        .and(handler.clone())
+        .and_then(|limit: Limit, handler: Arc<RpcHandler>| async move {
+            reply(handler.get_richest_addresses(limit.limit.unwrap_or(DEFAULT_RICHEST_LIMIT)).await)
+        });
+
+    let supply = warp::path!("stats" / "supply")
+        .and(warp::get())
+        .and(handler)
+        .and_then(|handler: Arc<RpcHandler>| async move {
+            reply(handler.get_circulating_supply().await)
+        });
+
+    let openapi = warp::path!("openapi.json")
+        .and(warp::get())
+        .map(|| Box::new(warp::reply::json(&openapi_spec())) as Box<dyn Reply>);
+
+    blocks
+        .or(block).unify()
+        .or(transaction).unify()
+        .or(receipt).unify()
+        .or(submit).unify()
+        .or(balance).unify()
+        .or(utxos).unify()
+        .or(address_transactions).unify()
+        .or(block_stats).unify()
+        .or(daily_transactions).unify()
+        .or(block_time).unify()
+        .or(richest).unify()
+        .or(supply).unify()
+        .or(openapi).unify()
+        .boxed()
+}
+
+
+/// OpenAPI 3 document describing `ROUTES`
+pub fn openapi_spec() -> Value {
+    let mut paths = Map::new();
+    for route in ROUTES {
+        let parameters: Vec<Value> = route.params.iter()
+            .map(|(name, location, description)| json!({
+                "name": name,
+                "in": location,
+                "required": *location == "path",
+                "description": description,
+                "schema": { "type": "string" },
+            }))
+            .collect();
+        let operation = json!({
+            "summary": route.summary,
+            "parameters": parameters,
+            "responses": {
+                "200": { "description": "success", "content": { "application/json": {} } },
+                "default": { "description": "error", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+            },
+        });
+
+        let methods = paths.entry(route.path).or_insert_with(|| json!({}));
+        methods[route.method] = operation;
+    }
+
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": "kaiblock node REST API", "version": env!("CARGO_PKG_VERSION") },
+        "paths": paths,
+        "components": {
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "properties": {
+                        "error": {
+                            "type": "object",
+                            "properties": {
+                                "code": { "type": "integer" },
+                                "message": { "type": "string" },
+                            },
+                        },
+                    },
+                },
+            },
+        },
+    })
+}
+
+
+// one page of block summaries below `before`, newest first, with the cursor of the next page
+async fn list_blocks(handler: &RpcHandler, page: BlockPage) -> Result<Value, RpcError> {
+    let limit = page.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if limit == 0 || limit > MAX_PAGE_LIMIT {
+        return Err(RpcError::InvalidParams(format!("limit must be 1 to {}", MAX_PAGE_LIMIT)));
+    }
+
+    let blockchain = handler.blockchain.read().await;
+    let end = page.before.unwrap_or(blockchain.height() + 1).min(blockchain.height() + 1);
+    let start = end.saturating_sub(limit);
+    let mut blocks = Vec::new();
+    for height in (start..end).rev() {
+        if blockchain.is_pruned(height) {
+            break;
+        }
+        if let Some(block) = blockchain.get_block_by_height(&height) {
+            blocks.push(BlockSummary::from_block(block));
+        }
+    }
+
+    let next = blocks.last().map(|block| block.height).filter(|height| *height > 0);
+    Ok(json!({ "blocks": blocks, "next": next }))
+}
+
+
+fn reply(result: Result<Value, RpcError>) -> Result<Box<dyn Reply>, Infallible> {
+    reply_with(result, StatusCode::OK)
+}
+
+fn reply_with(result: Result<Value, RpcError>, status: StatusCode) -> Result<Box<dyn Reply>, Infallible> {
+    Ok(match result {
+        Ok(value) => Box::new(warp::reply::with_status(warp::reply::json(&value), status)),
+        Err(error) => {
+            let body = json!({ "error": { "code": error.code(), "message": error.to_string() } });
+            Box::new(warp::reply::with_status(warp::reply::json(&body), http_status(&error)))
+        }
+    })
+}
+
+fn http_status(error: &RpcError) -> StatusCode {
+    match error {
+        RpcError::BlockNotFound | RpcError::TransactionNotFound => StatusCode::NOT_FOUND,
+        RpcError::ParseError(_) | RpcError::InvalidRequest(_) | RpcError::InvalidParams(_) => StatusCode::BAD_REQUEST,
+        RpcError::MethodNotFound(_) => StatusCode::NOT_FOUND,
+        RpcError::TransactionRejected(_) | RpcError::BlockRejected(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        RpcError::IndexerDisabled => StatusCode::NOT_IMPLEMENTED,
+        RpcError::Pruned(_) => StatusCode::GONE,
+        RpcError::InternalServerError => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::Blockchain;
+    use std::collections::HashSet;
+    use tokio::sync::RwLock;
+
+    fn handler() -> Arc<RpcHandler> {
+        Arc::new(RpcHandler::new(Arc::new(RwLock::new(Blockchain::default()))))
+    }
+
+    async fn get(handler: &Arc<RpcHandler>, path: &str) -> (StatusCode, Value) {
+        let response = warp::test::request().method("GET").path(path).reply(&routes(handler.clone())).await;
+        (response.status(), serde_json::from_slice(response.body()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_blocks_by_height_and_hash() {
+        let handler = handler();
+        let genesis = handler.blockchain.read().await.get_block_by_height(&0).unwrap().id();
+
+        let (status, by_height) = get(&handler, "/blocks/0").await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, by_hash) = get(&handler, &format!("/blocks/{}", genesis.to_hex())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(by_height, by_hash);
+    }
+
+    #[tokio::test]
+    async fn test_block_pages() {
+        let handler = handler();
+        let (status, page) = get(&handler, "/blocks").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["blocks"].as_array().unwrap().len(), 1);
+        assert_eq!(page["blocks"][0]["height"], 0);
+        // nothing below genesis
+        assert_eq!(page["next"], Value::Null);
+
+        let (status, page) = get(&handler, "/blocks?limit=0").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(page["error"]["code"], -32602);
+    }
+
+    #[tokio::test]
+    async fn test_errors_map_to_http_status() {
+        let handler = handler();
+
+        let (status, body) = get(&handler, "/blocks/999").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"]["code"], RpcError::BlockNotFound.code());
+
+        let (status, _) = get(&handler, "/tx/nothex").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, body) = get(&handler, "/stats/blocks?from=0&to=1").await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body["error"]["code"], RpcError::IndexerDisabled.code());
+    }
+
+    #[tokio::test]
+    async fn test_submit_rejects_bad_encoding() {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/tx")
+            .json(&json!({ "data": "zz" }))
+            .reply(&routes(handler()))
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_documents_every_route() {
+        let (status, spec) = get(&handler(), "/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let paths = spec["paths"].as_object().unwrap();
+        let documented: HashSet<&str> = ROUTES.iter().map(|route| route.path).collect();
+        assert_eq!(paths.len(), documented.len());
+        for route in ROUTES {
+            let operation = &paths[route.path][route.method];
+            assert_eq!(operation["summary"], route.summary);
+            assert_eq!(operation["parameters"].as_array().unwrap().len(), route.params.len());
+        }
+    }
+}
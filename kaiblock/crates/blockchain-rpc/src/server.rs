@@ -1,66 +1,321 @@
 use warp::Filter;
-use crate::handler::RpcHandler
-use blockchain_core::transaction::Transaction;
+use warp::ws::{Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use crate::event_bus::{EventBus, Subscription};
+use crate::handlers::RpcHandler;
+use crate::metrics::METRICS_CONTENT_TYPE;
+use crate::rest;
+use crate::subscriptions::ConnectionSubscriptions;
+use serde_json::Value;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use tracing::info;
 
 
-pub RpcServer{
+pub struct RpcServer{
     pub handler: Arc<RpcHandler>,
-    pub port: u16,
+    pub addr: SocketAddr,
 }
 
 
 
-impl RcpServer{
-    pub fn new(handler: Arc<RpcHandler>, port: u16) -> Self {
-        Self { handler, port }
+impl RpcServer{
+    pub fn new(handler: Arc<RpcHandler>, addr: SocketAddr) -> Self {
+        Self { handler, addr }
     }
 
+    /// Serve JSON-RPC 2.0 over HTTP (`POST /`) and WebSocket (`GET /ws`, which
+    /// also takes subscriptions, see `subscriptions`),
+    /// node events over WebSocket or SSE (`GET /events`), Prometheus
+    /// metrics (`GET /metrics`), and the REST gateway (see `rest`)
     pub async fn start(&self) {
+        self.start_until(std::future::pending()).await
+    }
+
+    /// Serve until `shutdown` resolves, then stop accepting connections
+    pub async fn start_until(&self, shutdown: impl Future<Output = ()> + Send + 'static) {
         let handler_filter = warp::any().map({
             let handler = self.handler.clone();
             move || handler.clone()
         });
 
 
-    // GET /block/ latest
+    let http_rpc = http_rpc(handler_filter.clone());
 
-    let latest_block = warp::path!("block" / "latest")
+
+    // GET /ws: one JSON-RPC request or batch per text frame, plus subscriptions
+    let ws_rpc = warp::path("ws")
+    .and(warp::ws())
     .and(handler_filter.clone())
-    and_then(|handler: Arc<Rpcandler>|async move {
-        match handler.get_latest_block().await {
-            Ok(block) => Ok(warp::reply::json(&block)),
-            Err(_) => Err(warp::reject::not_found()),
-        }
+    .map(|ws: warp::ws::Ws, handler: Arc<RpcHandler>| {
+        ws.on_upgrade(move |socket| serve_websocket(socket, handler))
+    });
+
+
+    let events_filter = warp::any().map({
+        let events = self.handler.events.clone();
+        move || events.clone()
+    });
 
+    // GET /events: one JSON event per text frame, or SSE without an upgrade
+    let ws_events = warp::path("events")
+    .and(warp::ws())
+    .and(events_filter.clone())
+    .map(|ws: warp::ws::Ws, events: Arc<EventBus>| {
+        ws.on_upgrade(move |socket| stream_events(socket, events.subscribe()))
     });
 
-    let block_by_height = warp::path!("block" / u64)
+    let sse_events = warp::path("events")
+    .and(warp::get())
+    .and(events_filter)
+    .map(|events: Arc<EventBus>| {
+        warp::sse::reply(warp::sse::keep_alive().stream(sse_stream(events.subscribe())))
+    });
+
+
+    // GET /metrics: Prometheus scrape target
+    let metrics = warp::path("metrics")
+    .and(warp::get())
     .and(handler_filter.clone())
-    .and_then(|height: u64, handler: Arc<Rpcandler>| async move {
-        match handler.get_block_by_height(height).await {
-            Ok(block) => Ok(warp::reply::json(&block)),
-            Err(_) => Err(warp::reject::not_found()),
-        });
+    .and_then(|handler: Arc<RpcHandler>| async move {
+        let page = handler.render_metrics().await;
+        Ok::<_, warp::Rejection>(warp::reply::with_header(page, "content-type", METRICS_CONTENT_TYPE))
+    });
+
+
+    // REST gateway: /blocks, /tx, /address and /openapi.json
+    let rest = rest::routes(self.handler.clone());
+
 
+    let routes = http_rpc.or(ws_rpc).or(ws_events).or(sse_events).or(metrics).or(rest);
+    info!("RPC server listening on {}", self.addr);
+    let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(self.addr, shutdown);
+    server.await;
 
-    // POST /transaction
-    let submit_tx = warp::path("transaction")
+
+}
+
+}
+
+
+// POST / (single request or batch)
+fn http_rpc(
+    handler_filter: impl Filter<Extract = (Arc<RpcHandler>,), Error = Infallible> + Clone + Send + Sync + 'static,
+) -> impl Filter<Extract = (Box<dyn warp::Reply>,), Error = warp::Rejection> + Clone {
+    warp::path::end()
     .and(warp::post())
-    .and(warp::body::json())
-    .and(handler_filter.clone())
-    .and_then(|tx: Transaction, handler: Arc<Rpcandler>| async move {
-       handler.submit_tx(tx).await.map_err(|_| warp::reject())
-       .map(|_| warp::reply::with_status("Transaction submitted", warp::http::StatusCode::OK))
+    .and(warp::body::bytes())
+    .and(handler_filter)
+    .and_then(|body: bytes::Bytes, handler: Arc<RpcHandler>| async move {
+        let reply: Box<dyn warp::Reply> = match handler.handle_raw(&body).await {
+            Some(response) => Box::new(warp::reply::json(&response)),
+            // notifications only: nothing to say
+            None => Box::new(warp::http::StatusCode::NO_CONTENT),
+        };
+        Ok::<_, warp::Rejection>(reply)
+    })
+}
 
-    });
+
+// Requests are answered in order; notifications for the connection's
+// subscriptions are interleaved between them as bus batches arrive
+async fn serve_websocket(socket: WebSocket, handler: Arc<RpcHandler>) {
+    let (mut tx, mut rx) = socket.split();
+    let mut subscriptions = ConnectionSubscriptions::default();
+    let mut events = handler.events.subscribe_to([]);
+
+    loop {
+        tokio::select! {
+            message = rx.next() => {
+                let message = match message {
+                    Some(Ok(message)) if !message.is_close() => message,
+                    _ => break,
+                };
+                let text = match message.to_str() {
+                    Ok(text) => text,
+                    Err(_) => continue,
+                };
+
+                let subscription_reply = serde_json::from_str::<Value>(text).ok()
+                    .and_then(|body| subscriptions.handle(&body));
+                let response = match subscription_reply {
+                    Some(response) => {
+                        events.set_topics(subscriptions.topics());
+                        Some(response)
+                    }
+                    None => handler.handle_raw(text.as_bytes()).await,
+                };
+                if let Some(response) = response {
+                    if tx.send(Message::text(response.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            batch = events.next_batch() => {
+                let batch = match batch {
+                    Some(batch) => batch,
+                    // disconnected for lagging
+                    None => break,
+                };
+                for notification in batch.iter().flat_map(|event| subscriptions.notifications(event)) {
+                    if tx.feed(Message::text(notification.to_string())).await.is_err() {
+                        return;
+                    }
+                }
+                if tx.flush().await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+    let _ = tx.close().await;
+}
 
 
-    let routes = latest_block.or(block_by_height).or(submit_tx);
-    println!("RPC server listening on port {}", self.port);
-    warp::serve(routes).run(([127,0,0,1], self.port)).await;
+// Each batch is written with a single flush, so a burst of events costs one
+// write instead of one per event
+async fn stream_events(socket: WebSocket, mut subscription: Subscription) {
+    let (mut tx, mut rx) = socket.split();
+
+    loop {
+        tokio::select! {
+            batch = subscription.next_batch() => {
+                let batch = match batch {
+                    Some(batch) => batch,
+                    // disconnected for lagging
+                    None => break,
+                };
+                for event in batch {
+                    let text = serde_json::to_string(&event).expect("events serialize");
+                    if tx.feed(Message::text(text)).await.is_err() {
+                        return;
+                    }
+                }
+                if tx.flush().await.is_err() {
+                    return;
+                }
+            }
+            message = rx.next() => match message {
+                Some(Ok(message)) if !message.is_close() => {}
+                _ => break,
+            },
+        }
+    }
+    let _ = tx.close().await;
+}
 
 
+fn sse_stream(subscription: Subscription) -> impl futures_util::Stream<Item = Result<warp::sse::Event, Infallible>> {
+    futures_util::stream::unfold(subscription, |mut subscription| async move {
+        subscription.next_batch().await.map(|batch| (batch, subscription))
+    })
+    .flat_map(futures_util::stream::iter)
+    .map(|event| {
+        let data = serde_json::to_string(&event).expect("events serialize");
+        Ok(warp::sse::Event::default().data(data))
+    })
 }
 
-}
\ No newline at end of file
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::Blockchain;
+    use serde_json::json;
+    use tokio::sync::RwLock;
+
+    fn handler() -> Arc<RpcHandler> {
+        Arc::new(RpcHandler::new(Arc::new(RwLock::new(Blockchain::default()))))
+    }
+
+    async fn call(body: &str) -> Option<Value> {
+        handler().handle_raw(body.as_bytes()).await
+    }
+
+    fn error_code(response: &Value) -> i64 {
+        response["error"]["code"].as_i64().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_single_request() {
+        let response = call(r#"{"jsonrpc": "2.0", "method": "getBlockHeight", "id": 1}"#).await.unwrap();
+        assert_eq!(response, json!({"jsonrpc": "2.0", "result": 0, "id": 1}));
+    }
+
+    #[tokio::test]
+    async fn test_batch_answers_each_request_in_order() {
+        let response = call(r#"[
+            {"jsonrpc": "2.0", "method": "getBlockHeight", "id": "a"},
+            {"jsonrpc": "2.0", "method": "getBlockHeight"},
+            {"jsonrpc": "2.0", "method": "noSuchMethod", "id": "b"}
+        ]"#).await.unwrap();
+
+        let responses = response.as_array().unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], "a");
+        assert_eq!(responses[0]["result"], 0);
+        assert_eq!(responses[1]["id"], "b");
+        assert_eq!(error_code(&responses[1]), -32601);
+    }
+
+    #[tokio::test]
+    async fn test_notifications_get_no_response() {
+        assert_eq!(call(r#"{"jsonrpc": "2.0", "method": "getBlockHeight"}"#).await, None);
+        assert_eq!(call(r#"[{"jsonrpc": "2.0", "method": "getBlockHeight"}]"#).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_null_id_is_answered() {
+        let response = call(r#"{"jsonrpc": "2.0", "method": "getBlockHeight", "id": null}"#).await.unwrap();
+        assert_eq!(response, json!({"jsonrpc": "2.0", "result": 0, "id": null}));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_is_a_parse_error() {
+        let response = call(r#"{"jsonrpc": "2.0", "method": "#).await.unwrap();
+        assert_eq!(error_code(&response), -32700);
+        assert_eq!(response["id"], Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_requests() {
+        let response = call(r#"{"jsonrpc": "2.0", "method": "noSuchMethod", "id": 1}"#).await.unwrap();
+        assert_eq!(error_code(&response), -32601);
+
+        let response = call(r#"{"jsonrpc": "2.0", "method": "getBlockByHeight", "params": ["ten"], "id": 2}"#).await.unwrap();
+        assert_eq!(error_code(&response), -32602);
+        let response = call(r#"{"jsonrpc": "2.0", "method": "getBlockByHeight", "params": [], "id": 3}"#).await.unwrap();
+        assert_eq!(error_code(&response), -32602);
+
+        let response = call(r#"{"jsonrpc": "1.0", "method": "getBlockHeight", "id": 4}"#).await.unwrap();
+        assert_eq!(error_code(&response), -32600);
+        let response = call("[]").await.unwrap();
+        assert_eq!(error_code(&response), -32600);
+    }
+
+    #[tokio::test]
+    async fn test_http_replies() {
+        let handler = handler();
+        let route = http_rpc(warp::any().map(move || handler.clone()));
+
+        let reply = warp::test::request()
+            .method("POST")
+            .path("/")
+            .body(r#"{"jsonrpc": "2.0", "method": "getBlockHeight", "id": 1}"#)
+            .reply(&route)
+            .await;
+        assert_eq!(reply.status(), 200);
+        let body: Value = serde_json::from_slice(reply.body()).unwrap();
+        assert_eq!(body["result"], 0);
+
+        let reply = warp::test::request()
+            .method("POST")
+            .path("/")
+            .body(r#"{"jsonrpc": "2.0", "method": "getBlockHeight"}"#)
+            .reply(&route)
+            .await;
+        assert_eq!(reply.status(), 204);
+    }
+}
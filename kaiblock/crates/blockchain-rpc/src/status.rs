@@ -0,0 +1,194 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+
+/// Errors older than this don't count as recent
+pub const RECENT_ERROR_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Errors remembered at most; older ones are dropped first
+const MAX_TRACKED_ERRORS: usize = 4096;
+
+
+/// Miner activity, as last reported by the miner
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MiningStatus {
+    pub threads: usize,
+    pub hashes: u64,
+    pub blocks_found: u64,
+    /// average hashes per second since the miner started
+    pub hash_rate: f64,
+}
+
+
+#[derive(Debug, Default)]
+struct Activity {
+    mining: Option<MiningStatus>,
+    /// (when, component) of each recorded error, oldest first
+    errors: VecDeque<(Instant, &'static str)>,
+}
+
+
+/// Node activity that neither the chain nor the network keeps track of:
+/// uptime, miner progress, where data lives and recent errors per component.
+/// Clones share the same state, so the node and its RPC handler can each hold one.
+#[derive(Debug, Clone)]
+pub struct NodeStatus {
+    started: Instant,
+    data_dir: Option<PathBuf>,
+    activity: Arc<Mutex<Activity>>,
+}
+
+impl NodeStatus {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            data_dir: None,
+            activity: Arc::new(Mutex::new(Activity::default())),
+        }
+    }
+
+    /// Directory whose size is reported as storage usage
+    pub fn with_data_dir(mut self, data_dir: Option<PathBuf>) -> Self {
+        self.data_dir = data_dir;
+        self
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn data_dir(&self) -> Option<&Path> {
+        self.data_dir.as_deref()
+    }
+
+    /// Bytes used under the data directory (None for in-memory nodes)
+    pub fn storage_usage(&self) -> Option<u64> {
+        self.data_dir.as_deref().map(dir_size)
+    }
+
+    pub fn set_mining(&self, status: Option<MiningStatus>) {
+        self.activity().mining = status;
+    }
+
+    pub fn mining(&self) -> Option<MiningStatus> {
+        self.activity().mining.clone()
+    }
+
+    /// Count an error in `component` (e.g. "network", "sync", "storage")
+    pub fn record_error(&self, component: &'static str) {
+        let mut activity = self.activity();
+        if activity.errors.len() >= MAX_TRACKED_ERRORS {
+            activity.errors.pop_front();
+        }
+        activity.errors.push_back((Instant::now(), component));
+    }
+
+    /// Errors per component within the last [`RECENT_ERROR_WINDOW`]
+    pub fn recent_errors(&self) -> BTreeMap<&'static str, usize> {
+        let mut activity = self.activity();
+        let now = Instant::now();
+        while activity.errors.front().is_some_and(|(at, _)| now.duration_since(*at) > RECENT_ERROR_WINDOW) {
+            activity.errors.pop_front();
+        }
+
+        let mut counts = BTreeMap::new();
+        for (_, component) in &activity.errors {
+            *counts.entry(*component).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    fn activity(&self) -> std::sync::MutexGuard<'_, Activity> {
+        self.activity.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for NodeStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+// Total size of the files under `path`; unreadable entries count as empty
+fn dir_size(path: &Path) -> u64 {
+    let entries = match std::fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+
+    entries.flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_errors_counted_per_component() {
+        let status = NodeStatus::new();
+        status.record_error("network");
+        status.record_error("network");
+        status.record_error("storage");
+
+        let errors = status.recent_errors();
+        assert_eq!(errors.get("network"), Some(&2));
+        assert_eq!(errors.get("storage"), Some(&1));
+        assert_eq!(errors.get("sync"), None);
+    }
+
+    #[test]
+    fn test_tracked_errors_are_capped() {
+        let status = NodeStatus::new();
+        for _ in 0..MAX_TRACKED_ERRORS {
+            status.record_error("network");
+        }
+        status.record_error("sync");
+
+        let errors = status.recent_errors();
+        assert_eq!(errors.values().sum::<usize>(), MAX_TRACKED_ERRORS);
+        assert_eq!(errors.get("sync"), Some(&1));
+    }
+
+    #[test]
+    fn test_clones_share_activity() {
+        let status = NodeStatus::new();
+        let node_side = status.clone();
+        node_side.set_mining(Some(MiningStatus { threads: 2, hashes: 100, blocks_found: 1, hash_rate: 50.0 }));
+        node_side.record_error("miner");
+
+        assert_eq!(status.mining().map(|mining| mining.blocks_found), Some(1));
+        assert_eq!(status.recent_errors().get("miner"), Some(&1));
+
+        node_side.set_mining(None);
+        assert!(status.mining().is_none());
+    }
+
+    #[test]
+    fn test_storage_usage_sums_the_data_dir() {
+        assert_eq!(NodeStatus::new().storage_usage(), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a"), [0u8; 100]).unwrap();
+        std::fs::create_dir(dir.path().join("blocks")).unwrap();
+        std::fs::write(dir.path().join("blocks").join("b"), [0u8; 50]).unwrap();
+
+        let status = NodeStatus::new().with_data_dir(Some(dir.path().to_path_buf()));
+        assert_eq!(status.storage_usage(), Some(150));
+
+        // a missing directory counts as empty
+        let missing = NodeStatus::new().with_data_dir(Some(dir.path().join("missing")));
+        assert_eq!(missing.storage_usage(), Some(0));
+    }
+}
@@ -0,0 +1,262 @@
+//! Subscriptions on `GET /ws`.
+//!
+//! `subscribe` with `["newHeads"]`, `["pendingTransactions"]` or
+//! `["logs", filter]` (a getLogs filter; heights are ignored) returns a
+//! subscription id, and `unsubscribe` with `[id]` cancels it. Matching events
+//! arrive as notifications:
+//!
+//! `{"jsonrpc": "2.0", "method": "subscription", "params": {"subscription": id, "result": ...}}`
+//!
+//! Each connection has one event bus subscription covering the topics its
+//! subscriptions need, so a slow reader only fills its own bounded queue. What
+//! happens then follows the bus's [`LagPolicy`](crate::LagPolicy): dropped
+//! events are reported with a `subscriptionLagged` notification carrying
+//! `{"missed": n}`, and a disconnected reader has its socket closed.
+
+use crate::errors::RpcError;
+use crate::events::{NodeEvent, Topic};
+use crate::handlers::{log_entry_json, LogQuery};
+use crate::jsonrpc::{param, required_param, JsonRpcRequest, JsonRpcResponse, JSONRPC_VERSION};
+use blockchain_core::LogFilter;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+
+
+/// Most subscriptions one connection can hold at once
+pub const MAX_SUBSCRIPTIONS_PER_CONNECTION: usize = 32;
+
+
+/// What a subscription delivers
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubscriptionKind {
+    /// a summary of every block joining the main chain
+    NewHeads,
+    /// the id of every transaction entering the mempool
+    PendingTransactions,
+    /// each log matching the filter, as blocks join the main chain
+    Logs(LogFilter),
+}
+
+impl SubscriptionKind {
+    /// Parse the params of `subscribe`
+    pub fn from_params(params: &Value) -> Result<Self, RpcError> {
+        match required_param::<String>(params, 0, "kind")?.as_str() {
+            "newHeads" => Ok(SubscriptionKind::NewHeads),
+            "pendingTransactions" => Ok(SubscriptionKind::PendingTransactions),
+            "logs" => match param(params, 1, "filter") {
+                None | Some(Value::Null) => Ok(SubscriptionKind::Logs(LogFilter::default())),
+                Some(_) => Ok(SubscriptionKind::Logs(required_param::<LogQuery>(params, 1, "filter")?.into_filter(0)?)),
+            },
+            kind => Err(RpcError::InvalidParams(format!("unknown subscription `{}`", kind))),
+        }
+    }
+
+    pub fn topic(&self) -> Topic {
+        match self {
+            SubscriptionKind::NewHeads => Topic::NewHeads,
+            SubscriptionKind::PendingTransactions => Topic::PendingTransactions,
+            SubscriptionKind::Logs(_) => Topic::Logs,
+        }
+    }
+
+    // notification results for an event
+    fn results(&self, event: &NodeEvent) -> Vec<Value> {
+        match (self, event) {
+            (SubscriptionKind::NewHeads, NodeEvent::NewBlock(summary)) => vec![json!(summary)],
+            (SubscriptionKind::PendingTransactions, NodeEvent::PendingTransaction { txid }) => vec![json!(txid)],
+            (SubscriptionKind::Logs(filter), NodeEvent::Logs { entries }) => entries.iter()
+                .filter(|entry| filter.matches(&entry.log))
+                .map(log_entry_json)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+
+/// The subscriptions of one WebSocket connection
+#[derive(Debug, Default)]
+pub struct ConnectionSubscriptions {
+    next_id: u64,
+    active: BTreeMap<String, SubscriptionKind>,
+}
+
+impl ConnectionSubscriptions {
+    /// Start a subscription; returns its id
+    pub fn subscribe(&mut self, kind: SubscriptionKind) -> Result<String, RpcError> {
+        if self.active.len() >= MAX_SUBSCRIPTIONS_PER_CONNECTION {
+            return Err(RpcError::InvalidRequest(
+                format!("at most {} subscriptions per connection", MAX_SUBSCRIPTIONS_PER_CONNECTION)
+            ));
+        }
+        self.next_id += 1;
+        let id = format!("0x{:x}", self.next_id);
+        self.active.insert(id.clone(), kind);
+        Ok(id)
+    }
+
+    /// Cancel a subscription; false if there was none with this id
+    pub fn unsubscribe(&mut self, id: &str) -> bool {
+        self.active.remove(id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    /// Bus topics the active subscriptions need
+    pub fn topics(&self) -> HashSet<Topic> {
+        self.active.values().map(SubscriptionKind::topic).collect()
+    }
+
+    /// Answer `subscribe` and `unsubscribe` requests. Returns None for
+    /// anything else, which is left to the ordinary JSON-RPC handler.
+    pub fn handle(&mut self, body: &Value) -> Option<Value> {
+        let method = body.get("method").and_then(Value::as_str)?;
+        if method != "subscribe" && method != "unsubscribe" {
+            return None;
+        }
+
+        let request = match serde_json::from_value::<JsonRpcRequest>(body.clone()) {
+            Ok(request) => request,
+            Err(e) => return Some(json!(JsonRpcResponse::failure(Value::Null, RpcError::InvalidRequest(e.to_string())))),
+        };
+        let result = if request.jsonrpc != JSONRPC_VERSION {
+            Err(RpcError::InvalidRequest(format!("unsupported jsonrpc version {}", request.jsonrpc)))
+        } else if method == "subscribe" {
+            SubscriptionKind::from_params(&request.params)
+                .and_then(|kind| self.subscribe(kind))
+                .map(Value::String)
+        } else {
+            required_param::<String>(&request.params, 0, "subscription")
+                .map(|id| Value::Bool(self.unsubscribe(&id)))
+        };
+
+        let id = request.id?;
+        Some(json!(match result {
+            Ok(value) => JsonRpcResponse::success(id, value),
+            Err(e) => JsonRpcResponse::failure(id, e),
+        }))
+    }
+
+    /// Notifications to send for an event from the bus
+    pub fn notifications(&self, event: &NodeEvent) -> Vec<Value> {
+        if let NodeEvent::Lagged { missed } = event {
+            if self.active.is_empty() {
+                return Vec::new();
+            }
+            return vec![json!({
+                "jsonrpc": JSONRPC_VERSION,
+                "method": "subscriptionLagged",
+                "params": { "missed": missed },
+            })];
+        }
+
+        self.active.iter()
+            .flat_map(|(id, kind)| kind.results(event).into_iter().map(move |result| json!({
+                "jsonrpc": JSONRPC_VERSION,
+                "method": "subscription",
+                "params": { "subscription": id, "result": result },
+            })))
+            .collect()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::BlockSummary;
+
+    fn request(method: &str, params: Value) -> Value {
+        json!({"jsonrpc": "2.0", "method": method, "params": params, "id": 1})
+    }
+
+    fn summary() -> BlockSummary {
+        BlockSummary { height: 1, hash: "ab".to_string(), tx_count: 1, total_fees: 0, timestamp: 0, addresses: Vec::new() }
+    }
+
+    #[test]
+    fn test_subscribe_and_unsubscribe() {
+        let mut subscriptions = ConnectionSubscriptions::default();
+
+        let response = subscriptions.handle(&request("subscribe", json!(["newHeads"]))).unwrap();
+        let id = response["result"].as_str().unwrap().to_string();
+        assert_eq!(subscriptions.topics(), HashSet::from([Topic::NewHeads]));
+
+        let response = subscriptions.handle(&request("unsubscribe", json!([id]))).unwrap();
+        assert_eq!(response["result"], true);
+        assert!(subscriptions.is_empty());
+
+        // cancelling twice is not an error, just a no-op
+        let response = subscriptions.handle(&request("unsubscribe", json!([id]))).unwrap();
+        assert_eq!(response["result"], false);
+    }
+
+    #[test]
+    fn test_other_methods_are_left_to_the_handler() {
+        let mut subscriptions = ConnectionSubscriptions::default();
+        assert!(subscriptions.handle(&request("getBlockHeight", json!([]))).is_none());
+    }
+
+    #[test]
+    fn test_bad_subscriptions_are_refused() {
+        let mut subscriptions = ConnectionSubscriptions::default();
+        let response = subscriptions.handle(&request("subscribe", json!(["everything"]))).unwrap();
+        assert_eq!(response["error"]["code"], -32602);
+
+        for _ in 0..MAX_SUBSCRIPTIONS_PER_CONNECTION {
+            subscriptions.subscribe(SubscriptionKind::NewHeads).unwrap();
+        }
+        let response = subscriptions.handle(&request("subscribe", json!(["newHeads"]))).unwrap();
+        assert_eq!(response["error"]["code"], -32600);
+    }
+
+    #[test]
+    fn test_logs_subscription_without_filter_matches_everything() {
+        let kind = SubscriptionKind::from_params(&json!(["logs"])).unwrap();
+        assert_eq!(kind, SubscriptionKind::Logs(LogFilter::default()));
+        assert_eq!(kind.topic(), Topic::Logs);
+    }
+
+    #[test]
+    fn test_events_go_to_matching_subscriptions() {
+        let mut subscriptions = ConnectionSubscriptions::default();
+        let heads = subscriptions.subscribe(SubscriptionKind::NewHeads).unwrap();
+        let pending = subscriptions.subscribe(SubscriptionKind::PendingTransactions).unwrap();
+        assert_ne!(heads, pending);
+
+        let notifications = subscriptions.notifications(&NodeEvent::NewBlock(summary()));
+        assert_eq!(notifications, vec![json!({
+            "jsonrpc": "2.0",
+            "method": "subscription",
+            "params": { "subscription": heads, "result": summary() },
+        })]);
+
+        let notifications = subscriptions.notifications(&NodeEvent::PendingTransaction { txid: "cd".to_string() });
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(notifications[0]["params"]["subscription"], pending);
+        assert_eq!(notifications[0]["params"]["result"], "cd");
+
+        assert!(subscriptions.notifications(&NodeEvent::MempoolSize { size: 1 }).is_empty());
+    }
+
+    #[test]
+    fn test_lag_is_reported_once_per_connection() {
+        let mut subscriptions = ConnectionSubscriptions::default();
+        assert!(subscriptions.notifications(&NodeEvent::Lagged { missed: 3 }).is_empty());
+
+        subscriptions.subscribe(SubscriptionKind::NewHeads).unwrap();
+        subscriptions.subscribe(SubscriptionKind::PendingTransactions).unwrap();
+        let notifications = subscriptions.notifications(&NodeEvent::Lagged { missed: 3 });
+        assert_eq!(notifications, vec![json!({
+            "jsonrpc": "2.0",
+            "method": "subscriptionLagged",
+            "params": { "missed": 3 },
+        })]);
+    }
+}
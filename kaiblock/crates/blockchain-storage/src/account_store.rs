@@ -0,0 +1,48 @@
+use runtime::{AccountStore, Pubkey, RuntimeError, StoredAccount};
+use sled::{Db, Tree};
+use crate::errors::StorageError;
+
+const ACCOUNTS_TREE: &str = "runtime_accounts";
+
+
+fn runtime_error(e: impl ToString) -> RuntimeError {
+    RuntimeError::Storage(e.to_string())
+}
+
+
+/// Runtime program accounts in sled, keyed by pubkey. A transaction's
+/// accounts are written in one batch, so they land together or not at all.
+#[derive(Debug)]
+pub struct SledAccountStore {
+    accounts: Tree,
+}
+
+impl SledAccountStore {
+    pub fn new(path: &str) -> Result<Self, StorageError> {
+        Self::from_db(&sled::open(path)?)
+    }
+
+    /// Account store inside an already open database
+    pub fn from_db(db: &Db) -> Result<Self, StorageError> {
+        Ok(Self { accounts: db.open_tree(ACCOUNTS_TREE)? })
+    }
+}
+
+impl AccountStore for SledAccountStore {
+    fn get_account(&self, pubkey: &Pubkey) -> Result<Option<StoredAccount>, RuntimeError> {
+        match self.accounts.get(pubkey).map_err(runtime_error)? {
+            Some(data) => Ok(Some(StoredAccount::from_bytes(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn commit(&mut self, accounts: Vec<(Pubkey, StoredAccount)>) -> Result<(), RuntimeError> {
+        let mut batch = sled::Batch::default();
+        for (pubkey, account) in accounts {
+            batch.insert(pubkey.as_slice(), account.to_bytes()?);
+        }
+        self.accounts.apply_batch(batch).map_err(runtime_error)?;
+        self.accounts.flush().map_err(runtime_error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,349 @@
+//! Chain backups with a signed merkle manifest.
+//!
+//! A backup is the main chain cut into chunks of consecutive blocks plus a
+//! manifest listing every chunk's height range and hash, the merkle root over
+//! those hashes, and the exporting node's signature on the root. A restore
+//! checks the manifest is signed by a trusted key and covers the whole chain
+//! from genesis to its tip, then checks each chunk against it before any block
+//! is imported, naming the chunks that are missing or corrupt so only those
+//! have to be fetched again.
+
+use std::fs;
+use std::path::Path;
+
+use blockchain_core::block::Block;
+use blockchain_core::{BlockHeight, BlockId, Blockchain, ChainConfig, ChainId, Hash256, PublicKey, Signature};
+use blockchain_crypto::hash::sha256;
+use blockchain_crypto::signature::Keypair;
+use blockchain_crypto::MerkleTree;
+use serde::{Deserialize, Serialize};
+
+use crate::chain_store::open_blockchain;
+use crate::errors::StorageError;
+
+
+/// Blocks per chunk unless the caller picks another size
+pub const DEFAULT_BLOCKS_PER_CHUNK: u64 = 1000;
+
+const MANIFEST_FILE: &str = "manifest.bin";
+
+/// Domain tag for manifest signatures, so they can't be replayed as anything else
+const MANIFEST_DOMAIN: &[u8] = b"kaiblock-backup-manifest";
+
+
+/// One chunk as listed in the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkEntry {
+    pub first_height: BlockHeight,
+    pub last_height: BlockHeight,
+    /// sha256 of the chunk file
+    pub hash: Hash256,
+}
+
+
+/// Signed index of a backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub chain_id: ChainId,
+    pub tip: BlockId,
+    pub tip_height: BlockHeight,
+    pub chunks: Vec<ChunkEntry>,
+    /// merkle root over the chunk hashes, in order
+    pub root: Hash256,
+    pub signer: PublicKey,
+    pub signature: Signature,
+}
+
+impl BackupManifest {
+    /// Hash the signature covers: the root and what the backup claims to hold
+    pub fn signing_hash(&self) -> Hash256 {
+        let mut data = Vec::with_capacity(MANIFEST_DOMAIN.len() + 8 + 32 + 8 + 32);
+        data.extend_from_slice(MANIFEST_DOMAIN);
+        data.extend_from_slice(&(self.chain_id as u64).to_le_bytes());
+        data.extend_from_slice(self.tip.hash().as_bytes());
+        data.extend_from_slice(&self.tip_height.to_le_bytes());
+        data.extend_from_slice(self.root.as_bytes());
+        sha256(&data)
+    }
+
+    /// Check the manifest is signed by one of `trusted`, its root matches its
+    /// chunk list, and the chunks cover every height from genesis to the tip
+    pub fn verify(&self, trusted: &[PublicKey]) -> Result<(), StorageError> {
+        if !trusted.contains(&self.signer) {
+            return Err(StorageError::Backup("manifest signed by an untrusted key".to_string()));
+        }
+        if !self.signer.verify(self.signing_hash().as_bytes(), &self.signature) {
+            return Err(StorageError::Backup("invalid manifest signature".to_string()));
+        }
+        if chunk_root(&self.chunks)? != self.root {
+            return Err(StorageError::Backup("chunk list does not match the manifest root".to_string()));
+        }
+
+        let mut next_height = 0;
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if chunk.first_height != next_height || chunk.last_height < chunk.first_height {
+                return Err(StorageError::Backup(format!(
+                    "chunk {} covers {}..={}, expected it to start at {}",
+                    index, chunk.first_height, chunk.last_height, next_height
+                )));
+            }
+            next_height = chunk.last_height + 1;
+        }
+        if next_height != self.tip_height + 1 {
+            return Err(StorageError::Backup(format!(
+                "chunks end at height {}, the tip is at {}", next_height.saturating_sub(1), self.tip_height
+            )));
+        }
+        Ok(())
+    }
+}
+
+
+/// Result of checking chunk files against a manifest
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkReport {
+    pub missing: Vec<usize>,
+    /// present, but not matching the hash in the manifest
+    pub corrupt: Vec<usize>,
+}
+
+impl ChunkReport {
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+
+    /// Chunks to fetch again, in order
+    pub fn damaged(&self) -> Vec<usize> {
+        let mut damaged: Vec<usize> = self.missing.iter().chain(&self.corrupt).copied().collect();
+        damaged.sort_unstable();
+        damaged
+    }
+}
+
+
+/// Write the main chain to `dir` as chunks of `blocks_per_chunk` blocks plus a
+/// manifest signed with `keypair`. Pruned chains can't be backed up.
+pub fn export_backup(
+    chain: &Blockchain,
+    dir: &Path,
+    blocks_per_chunk: u64,
+    keypair: &Keypair,
+) -> Result<BackupManifest, StorageError> {
+    if let Some(pruned) = chain.pruned_height() {
+        return Err(StorageError::Backup(format!("blocks up to height {} are pruned", pruned)));
+    }
+    let tip = chain.get_chain_head().ok_or(StorageError::NotFound)?;
+    let (tip, tip_height) = (tip.id(), chain.height());
+    fs::create_dir_all(dir)?;
+
+    let mut chunks = Vec::new();
+    let mut first_height = 0;
+    while first_height <= tip_height {
+        let last_height = (first_height + blocks_per_chunk.max(1) - 1).min(tip_height);
+        let blocks: Vec<&Block> = chain.get_block_range(first_height, last_height);
+        if blocks.len() as u64 != last_height - first_height + 1 {
+            return Err(StorageError::Backup(format!("main chain has gaps in {}..={}", first_height, last_height)));
+        }
+
+        let data = bincode::serialize(&blocks)?;
+        fs::write(dir.join(chunk_file(chunks.len())), &data)?;
+        chunks.push(ChunkEntry { first_height, last_height, hash: sha256(&data) });
+        first_height = last_height + 1;
+    }
+
+    let mut manifest = BackupManifest {
+        chain_id: chain.config().chain_id,
+        tip,
+        tip_height,
+        root: chunk_root(&chunks)?,
+        chunks,
+        signer: keypair.public_key(),
+        signature: Signature::from_bytes([0u8; 64]),
+    };
+    manifest.signature = keypair.sign(manifest.signing_hash().as_bytes());
+
+    fs::write(dir.join(MANIFEST_FILE), bincode::serialize(&manifest)?)?;
+    Ok(manifest)
+}
+
+
+/// Read the manifest of the backup in `dir`, checking it against `trusted` signers
+pub fn read_manifest(dir: &Path, trusted: &[PublicKey]) -> Result<BackupManifest, StorageError> {
+    let manifest: BackupManifest = bincode::deserialize(&fs::read(dir.join(MANIFEST_FILE))?)?;
+    manifest.verify(trusted)?;
+    Ok(manifest)
+}
+
+
+/// Hash every chunk file in `dir` against the manifest
+pub fn check_chunks(manifest: &BackupManifest, dir: &Path) -> ChunkReport {
+    let mut report = ChunkReport::default();
+    for (index, entry) in manifest.chunks.iter().enumerate() {
+        match fs::read(dir.join(chunk_file(index))) {
+            Ok(data) if sha256(&data) == entry.hash => {}
+            Ok(_) => report.corrupt.push(index),
+            Err(_) => report.missing.push(index),
+        }
+    }
+    report
+}
+
+
+/// Rebuild a chain from the backup in `dir`.
+///
+/// Nothing is imported until the manifest and every chunk check out; a
+/// damaged backup fails with the chunks to fetch again. The backup must
+/// start from the same genesis block as `config`.
+pub fn restore_backup(dir: &Path, config: ChainConfig, trusted: &[PublicKey]) -> Result<Blockchain, StorageError> {
+    let manifest = read_manifest(dir, trusted)?;
+    if manifest.chain_id != config.chain_id {
+        return Err(StorageError::Backup(format!(
+            "backup is of chain {}, not {}", manifest.chain_id, config.chain_id
+        )));
+    }
+
+    let report = check_chunks(&manifest, dir);
+    if !report.is_intact() {
+        return Err(StorageError::DamagedBackup(report.damaged()));
+    }
+
+    let mut chain = open_blockchain(config)?;
+    for (index, entry) in manifest.chunks.iter().enumerate() {
+        let blocks: Vec<Block> = bincode::deserialize(&fs::read(dir.join(chunk_file(index)))?)?;
+        if blocks.len() as u64 != entry.last_height - entry.first_height + 1 {
+            return Err(StorageError::DamagedBackup(vec![index]));
+        }
+
+        for block in blocks {
+            if block.height() == 0 {
+                if chain.get_block_by_height(&0).map(Block::id) != Some(block.id()) {
+                    return Err(StorageError::Backup("backup has a different genesis block".to_string()));
+                }
+                continue;
+            }
+            chain.add_block(block)?;
+        }
+    }
+
+    if chain.get_chain_head().map(Block::id) != Some(manifest.tip) {
+        return Err(StorageError::Backup("restored chain does not end at the manifest tip".to_string()));
+    }
+    Ok(chain)
+}
+
+
+fn chunk_file(index: usize) -> String {
+    format!("chunk-{:06}.bin", index)
+}
+
+fn chunk_root(chunks: &[ChunkEntry]) -> Result<Hash256, StorageError> {
+    let tree = MerkleTree::new(chunks.iter().map(|chunk| chunk.hash).collect())
+        .map_err(|e| StorageError::Backup(format!("manifest merkle tree: {}", e)))?;
+    Ok(tree.root())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::AddressType;
+    use blockchain_crypto::{address::public_key_to_address, signature::generate_keypair};
+
+    fn config() -> ChainConfig {
+        let mut config = ChainConfig::default();
+        config.genesis.timestamp = Some(1_700_000_000);
+        config
+    }
+
+    // genesis plus four mined blocks
+    fn chain() -> Blockchain {
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let mut chain = Blockchain::new(config()).unwrap();
+        for _ in 0..4 {
+            chain.mine_block(miner.clone()).unwrap();
+        }
+        chain
+    }
+
+    #[test]
+    fn test_export_verify_restore_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = generate_keypair();
+        let trusted = [keypair.public_key().clone()];
+        let chain = chain();
+
+        let manifest = export_backup(&chain, dir.path(), 2, &keypair).unwrap();
+        let ranges: Vec<_> = manifest.chunks.iter().map(|chunk| (chunk.first_height, chunk.last_height)).collect();
+        assert_eq!(ranges, vec![(0, 1), (2, 3), (4, 4)]);
+        assert_eq!(read_manifest(dir.path(), &trusted).unwrap().root, manifest.root);
+        assert!(check_chunks(&manifest, dir.path()).is_intact());
+
+        let restored = restore_backup(dir.path(), config(), &trusted).unwrap();
+        assert_eq!(restored.height(), 4);
+        assert_eq!(restored.get_chain_head().map(Block::id), chain.get_chain_head().map(Block::id));
+    }
+
+    #[test]
+    fn test_tampered_and_missing_chunks_are_named() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = generate_keypair();
+        let trusted = [keypair.public_key().clone()];
+        let manifest = export_backup(&chain(), dir.path(), 2, &keypair).unwrap();
+
+        let path = dir.path().join(chunk_file(1));
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&path, data).unwrap();
+        fs::remove_file(dir.path().join(chunk_file(2))).unwrap();
+
+        let report = check_chunks(&manifest, dir.path());
+        assert_eq!(report, ChunkReport { missing: vec![2], corrupt: vec![1] });
+        assert!(matches!(
+            restore_backup(dir.path(), config(), &trusted),
+            Err(StorageError::DamagedBackup(damaged)) if damaged == vec![1, 2]
+        ));
+    }
+
+    #[test]
+    fn test_manifest_must_be_signed_trusted_and_complete() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = generate_keypair();
+        let trusted = [keypair.public_key().clone()];
+        let manifest = export_backup(&chain(), dir.path(), 2, &keypair).unwrap();
+
+        assert!(manifest.verify(&trusted).is_ok());
+        assert!(manifest.verify(&[generate_keypair().public_key().clone()]).is_err());
+        assert!(restore_backup(dir.path(), config(), &[]).is_err());
+
+        // dropping the last chunk changes the root
+        let mut truncated = manifest.clone();
+        truncated.chunks.pop();
+        assert!(truncated.verify(&trusted).is_err());
+
+        // re-signing a consistent but incomplete list still fails the coverage check
+        truncated.root = chunk_root(&truncated.chunks).unwrap();
+        truncated.signature = keypair.sign(truncated.signing_hash().as_bytes());
+        assert!(truncated.verify(&trusted).is_err());
+
+        // a claimed tip the signature doesn't cover
+        let mut moved = manifest.clone();
+        moved.tip_height += 1;
+        assert!(moved.verify(&trusted).is_err());
+    }
+
+    #[test]
+    fn test_restore_needs_the_same_chain() {
+        let dir = tempfile::tempdir().unwrap();
+        let keypair = generate_keypair();
+        let trusted = [keypair.public_key().clone()];
+        export_backup(&chain(), dir.path(), DEFAULT_BLOCKS_PER_CHUNK, &keypair).unwrap();
+
+        let other_chain = ChainConfig { chain_id: config().chain_id + 1, ..config() };
+        assert!(restore_backup(dir.path(), other_chain, &trusted).is_err());
+
+        let mut other_genesis = config();
+        other_genesis.genesis.timestamp = Some(1_600_000_000);
+        assert!(restore_backup(dir.path(), other_genesis, &trusted).is_err());
+    }
+}
@@ -1,16 +1,26 @@
 use sled::Db;
 use crate::errors::StorageError;
+use crate::state_store::StateStore;
 use blockchain_core::block::Block; // <-- Correct import path
+use blockchain_core::{BlockchainError, BlockId, Hash256};
 use bincode;
 
+#[derive(Debug)]
 pub struct SledBlockStore {
-    db: Db,
+    pub(crate) db: Db,
+    pub(crate) state: StateStore,
 }
 
 impl SledBlockStore {
     pub fn new(path: &str) -> Result<Self, StorageError> {
         let db = sled::open(path)?;
-        Ok(Self { db })
+        let state = StateStore::from_db(db.clone())?;
+        Ok(Self { db, state })
+    }
+
+    /// State trie nodes and committed roots, stored alongside the blocks
+    pub fn state(&self) -> &StateStore {
+        &self.state
     }
 
     pub fn hash_key(hash: &[u8]) -> Vec<u8> {
@@ -19,6 +29,18 @@ impl SledBlockStore {
         key
     }
 
+    pub fn undo_key(hash: &[u8]) -> Vec<u8> {
+        let mut key = b"undo:".to_vec();
+        key.extend_from_slice(hash);
+        key
+    }
+
+    pub fn receipt_key(tx_id: &[u8]) -> Vec<u8> {
+        let mut key = b"receipt:".to_vec();
+        key.extend_from_slice(tx_id);
+        key
+    }
+
     pub fn height_key(height: u64) -> Vec<u8> {
         let mut key = b"height:".to_vec();
         key.extend_from_slice(&height.to_be_bytes());
@@ -34,7 +56,7 @@ impl SledBlockStore {
     }
 
 
-    pub async get_block_by_hash(&self, hash: &[u8]
+    pub async fn get_block_by_hash(&self, hash: &[u8]
     ) -> Result<Option<Block>, StorageError>{
         match self.db.get(Self::hash_key(hash))? {
             Some(data) => Ok(Some(Self::deserialize_block(&data)?)),
@@ -42,28 +64,41 @@ impl SledBlockStore {
         }
     }
 
-    pub async get_block_by_height(&self, height: u64
-    ) -> Result<Option<Block>, StorageError>{
+    /// Id of the main chain block at a height
+    pub fn main_chain_id(&self, height: u64) -> Result<Option<BlockId>, StorageError> {
         match self.db.get(Self::height_key(height))? {
-            Some(data) => Ok(Some(Self::deserialize_block(&data)?)),
+            Some(data) => Ok(Some(Self::decode_height_entry(&data)?)),
             None => Ok(None),
         }
     }
 
-    pub async get_latest_block(&self) -> Result<Option<Block>, StorageError>{
-        let mut latest: Option<u64> = None;
-        for entry in self.db.scan_prefix(b"height:") {
-            let (_, value) = entry?;
-            let block = Self::deserialize_block(&value)?;
-
-            if latest.is_none() || block.height > latest.as_ref().unwrap().height {
-                latest = Some(block);
-            }
+    /// Height keys hold the block id. Stores written before the index kept
+    /// the whole block there, which is still read
+    pub(crate) fn decode_height_entry(data: &[u8]) -> Result<BlockId, StorageError> {
+        if data.len() == 32 {
+            let hash = Hash256::from_slice(data).map_err(BlockchainError::from)?;
+            return Ok(BlockId::new(hash));
+        }
+        Ok(Self::deserialize_block(data)?.id())
+    }
 
+    pub async fn get_block_by_height(&self, height: u64
+    ) -> Result<Option<Block>, StorageError>{
+        match self.main_chain_id(height)? {
+            Some(block_id) => self.get_block_by_hash(block_id.hash().as_bytes()).await,
+            None => Ok(None),
         }
+    }
 
-        Ok(latest)
+    pub async fn get_latest_block(&self) -> Result<Option<Block>, StorageError>{
+        // heights are big-endian, so the last key is the highest
+        match self.db.scan_prefix(b"height:").last() {
+            Some(entry) => {
+                let (_, value) = entry?;
+                let block_id = Self::decode_height_entry(&value)?;
+                self.get_block_by_hash(block_id.hash().as_bytes()).await
+            }
+            None => Ok(None),
+        }
     }
 }
-
-
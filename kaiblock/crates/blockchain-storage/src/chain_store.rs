@@ -0,0 +1,218 @@
+use blockchain_core::block::Block;
+use blockchain_core::state::{BlockUndo, WorldStateSnapshot};
+use blockchain_core::receipt::Receipt;
+use blockchain_core::store::ChainStore;
+use blockchain_core::{BlockchainError, BlockHeight, BlockId, Blockchain, ChainConfig, Hash256, TxId};
+use crate::block_store::SledBlockStore;
+use crate::errors::StorageError;
+
+
+const CHAIN_HEAD_KEY: &[u8] = b"tip";
+const STATE_BASE_KEY: &[u8] = b"state_base";
+const PRUNED_HEIGHT_KEY: &[u8] = b"pruned_height";
+
+
+fn storage_error(e: impl ToString) -> BlockchainError {
+    BlockchainError::StorageError(e.to_string())
+}
+
+
+impl ChainStore for SledBlockStore {
+    fn put_block(&self, block: &Block) -> blockchain_core::Result<()> {
+        let data = Self::serialize_block(block).map_err(storage_error)?;
+        self.db.insert(Self::hash_key(block.hash().as_bytes()), data)
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn get_block(&self, block_id: &BlockId) -> blockchain_core::Result<Option<Block>> {
+        match self.db.get(Self::hash_key(block_id.hash().as_bytes())).map_err(storage_error)? {
+            Some(data) => Ok(Some(Self::deserialize_block(&data).map_err(storage_error)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put_block_undo(&self, undo: &BlockUndo) -> blockchain_core::Result<()> {
+        let data = bincode::serialize(undo).map_err(storage_error)?;
+        self.db.insert(Self::undo_key(undo.block_id.hash().as_bytes()), data)
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn get_block_undo(&self, block_id: &BlockId) -> blockchain_core::Result<Option<BlockUndo>> {
+        match self.db.get(Self::undo_key(block_id.hash().as_bytes())).map_err(storage_error)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data).map_err(storage_error)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put_receipts(&self, receipts: &[Receipt]) -> blockchain_core::Result<()> {
+        let mut batch = sled::Batch::default();
+        for receipt in receipts {
+            let data = bincode::serialize(receipt).map_err(storage_error)?;
+            batch.insert(Self::receipt_key(receipt.tx_id.hash().as_bytes()), data);
+        }
+        self.db.apply_batch(batch).map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn get_receipt(&self, tx_id: &TxId) -> blockchain_core::Result<Option<Receipt>> {
+        match self.db.get(Self::receipt_key(tx_id.hash().as_bytes())).map_err(storage_error)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data).map_err(storage_error)?)),
+            None => Ok(None),
+        }
+    }
+
+    // height keys hold just the block id, the block itself lives under its hash
+    fn put_main_chain(&self, height: BlockHeight, block_id: &BlockId) -> blockchain_core::Result<()> {
+        self.db.insert(Self::height_key(height), block_id.hash().as_bytes().to_vec())
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn remove_main_chain(&self, height: BlockHeight) -> blockchain_core::Result<()> {
+        self.db.remove(Self::height_key(height))
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn main_chain(&self) -> blockchain_core::Result<Vec<(BlockHeight, BlockId)>> {
+        let mut index = Vec::new();
+        for entry in self.db.scan_prefix(b"height:") {
+            let (key, value) = entry.map_err(storage_error)?;
+
+            let mut height_bytes = [0u8; 8];
+            height_bytes.copy_from_slice(&key[b"height:".len()..]);
+
+            let block_id = Self::decode_height_entry(&value).map_err(storage_error)?;
+            index.push((u64::from_be_bytes(height_bytes), block_id));
+        }
+
+        Ok(index)
+    }
+
+    fn put_chain_head(&self, block_id: &BlockId) -> blockchain_core::Result<()> {
+        self.db.insert(CHAIN_HEAD_KEY, block_id.hash().as_bytes().to_vec())
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn chain_head(&self) -> blockchain_core::Result<Option<BlockId>> {
+        match self.db.get(CHAIN_HEAD_KEY).map_err(storage_error)? {
+            Some(data) => {
+                let hash = blockchain_core::Hash256::from_slice(&data)?;
+                Ok(Some(BlockId::new(hash)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn put_state_root(&self, height: BlockHeight, state_root: &Hash256, nodes: &[(Hash256, Vec<u8>)]) -> blockchain_core::Result<()> {
+        // the block's staged key-value state lands with its root
+        self.state.commit().map_err(storage_error)?;
+        self.state.put_nodes(nodes).map_err(storage_error)?;
+        self.state.put_root(height, state_root).map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn put_state_base(&self, state: &WorldStateSnapshot) -> blockchain_core::Result<()> {
+        let data = bincode::serialize(state).map_err(storage_error)?;
+        self.db.insert(STATE_BASE_KEY, data).map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn state_base(&self) -> blockchain_core::Result<Option<WorldStateSnapshot>> {
+        match self.db.get(STATE_BASE_KEY).map_err(storage_error)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data).map_err(storage_error)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn prune_block(&self, height: BlockHeight, block_id: &BlockId) -> blockchain_core::Result<()> {
+        let Some(block) = self.get_block(block_id)? else {
+            return Ok(());
+        };
+        let data = Self::serialize_block(&block.pruned()).map_err(storage_error)?;
+
+        let mut batch = sled::Batch::default();
+        batch.insert(Self::hash_key(block_id.hash().as_bytes()), data);
+        batch.insert(Self::height_key(height), block_id.hash().as_bytes().to_vec());
+        batch.remove(Self::undo_key(block_id.hash().as_bytes()));
+        batch.insert(PRUNED_HEIGHT_KEY, height.to_be_bytes().to_vec());
+        self.db.apply_batch(batch).map_err(storage_error)?;
+        Ok(())
+    }
+
+    fn pruned_height(&self) -> blockchain_core::Result<Option<BlockHeight>> {
+        match self.db.get(PRUNED_HEIGHT_KEY).map_err(storage_error)? {
+            Some(data) => {
+                let bytes: [u8; 8] = data.as_ref().try_into()
+                    .map_err(|_| storage_error("corrupt pruned height"))?;
+                Ok(Some(BlockHeight::from_be_bytes(bytes)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn flush(&self) -> blockchain_core::Result<()> {
+        self.db.flush().map_err(storage_error)?;
+        Ok(())
+    }
+}
+
+
+/// Open a blockchain persisted under `config.storage_path`, on a sled store
+/// there. Without a storage path the chain is kept in memory.
+pub fn open_blockchain(config: ChainConfig) -> Result<Blockchain, StorageError> {
+    match config.storage_path.clone() {
+        Some(path) => {
+            let store = SledBlockStore::new(&path.to_string_lossy())?;
+            Ok(Blockchain::with_store(config, Box::new(store))?)
+        }
+        None => Ok(Blockchain::new(config)?),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::{AddressType, Block};
+    use blockchain_crypto::{address::public_key_to_address, signature::generate_keypair};
+
+    #[test]
+    fn test_open_blockchain_persists_to_storage_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ChainConfig { storage_path: Some(dir.path().to_path_buf()), ..ChainConfig::default() };
+        let miner = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+
+        let tip = {
+            let mut chain = open_blockchain(config.clone()).unwrap();
+            for _ in 0..3 {
+                chain.mine_block(miner).unwrap();
+            }
+            chain.get_chain_head().map(Block::id).unwrap()
+        };
+
+        let chain = open_blockchain(config.clone()).unwrap();
+        assert_eq!(chain.height(), 3);
+
+        // core doesn't open stores on its own
+        assert!(Blockchain::new(config).is_err());
+        assert_eq!(chain.get_chain_head().map(Block::id), Some(tip));
+    }
+
+    #[test]
+    fn test_height_index_holds_block_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SledBlockStore::new(&dir.path().to_string_lossy()).unwrap();
+        let chain = Blockchain::with_store(ChainConfig::default(), Box::new(store)).unwrap();
+        let genesis = chain.get_block_by_height(&0).unwrap().id();
+        drop(chain);
+
+        let store = SledBlockStore::new(&dir.path().to_string_lossy()).unwrap();
+        assert_eq!(store.main_chain().unwrap(), vec![(0, genesis)]);
+        assert_eq!(store.db.get(SledBlockStore::height_key(0)).unwrap().unwrap().len(), 32);
+        assert_eq!(store.main_chain_id(0).unwrap(), Some(genesis));
+    }
+}
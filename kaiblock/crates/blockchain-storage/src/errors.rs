@@ -6,4 +6,16 @@ pub enum StorageError {
     Serialization(#[from] bincode::Error),
     #[error("database error")]
     Database(#[from] sled::Error),
+    #[error("chain error: {0}")]
+    Chain(#[from] blockchain_core::BlockchainError),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("backup error: {0}")]
+    Backup(String),
+    #[error("backup chunks missing or corrupt: {0:?}")]
+    DamagedBackup(Vec<usize>),
+    #[error("snapshot error: {0}")]
+    Snapshot(String),
+    #[error("snapshot chunks missing or corrupt: {0:?}")]
+    DamagedSnapshot(Vec<usize>),
 }
@@ -0,0 +1,555 @@
+use sled::{Db, Tree};
+use crate::errors::StorageError;
+use blockchain_core::block::Block;
+use blockchain_core::transaction::{Transaction, UTXO};
+use blockchain_core::{Address, Amount, Blockchain, BlockHeight, BlockId, Hash256, OutPoint, TxId};
+use blockchain_core::chain::MAX_REORG_DEPTH;
+use blockchain_crypto::hash::sha256;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+const TXS_TREE: &str = "index_txs_by_address";
+const BLOCKS_TREE: &str = "index_blocks_by_time";
+const UTXOS_TREE: &str = "index_utxos";
+const ADDRESS_UTXOS_TREE: &str = "index_utxos_by_address";
+const SPENT_TREE: &str = "index_spent_utxos";
+const BLOCK_STATS_TREE: &str = "index_block_stats";
+const DAILY_TXS_TREE: &str = "index_daily_txs";
+const META_TREE: &str = "index_meta";
+const TIP_KEY: &[u8] = b"tip";
+//value of set-like trees, where the key is the entry
+const PRESENT: &[u8] = &[];
+
+/// Largest page size `transactions_by_address` allows
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Seconds in a day of `daily_transactions`, which are UTC days
+pub const SECONDS_PER_DAY: i64 = 86_400;
+
+
+/// Whether the node maintains secondary indexes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexerConfig {
+    pub enabled: bool,
+    /// transactions per page of `transactions_by_address`
+    pub page_size: usize,
+}
+
+impl Default for IndexerConfig {
+    fn default() -> Self {
+        Self { enabled: false, page_size: 25 }
+    }
+}
+
+
+/// A transaction touching an address, as indexed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedTransaction {
+    pub tx_id: TxId,
+    pub block_height: BlockHeight,
+    /// position in the block
+    pub index: u32,
+}
+
+
+/// Per-block figures for explorers. Fees are those of the block's
+/// non-coinbase transactions; all zero when there are none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockStats {
+    pub height: BlockHeight,
+    pub block_id: BlockId,
+    /// unix seconds
+    pub timestamp: i64,
+    pub tx_count: usize,
+    /// encoded size in bytes
+    pub size: usize,
+    pub total_fees: Amount,
+    pub min_fee: Amount,
+    pub median_fee: Amount,
+    pub max_fee: Amount,
+}
+
+impl BlockStats {
+    pub fn from_block(block: &Block) -> Self {
+        let mut fees: Vec<Amount> = block.transactions().iter()
+            .filter(|tx| !tx.is_coinbase())
+            .map(|tx| tx.fee)
+            .collect();
+        fees.sort_unstable();
+
+        Self {
+            height: block.height(),
+            block_id: block.id(),
+            timestamp: block.timestamp().to_unix_timestamp(),
+            tx_count: block.transaction_count(),
+            size: block.size(),
+            total_fees: fees.iter().sum(),
+            min_fee: fees.first().copied().unwrap_or(0),
+            median_fee: fees.get(fees.len() / 2).copied().unwrap_or(0),
+            max_fee: fees.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+
+/// Secondary indexes over the main chain: transactions by address, blocks by
+/// timestamp, unspent outputs by address, and per-block and per-day figures
+/// for explorers. Figures only cover blocks indexed since they were added;
+/// delete the index directory to rebuild it from the chain.
+///
+/// The indexer follows the chain block by block and records the tip it has
+/// indexed, so after a restart or a missed event [`ChainIndexer::sync`]
+/// rewinds whatever left the main chain and indexes what it hasn't seen.
+/// Spent outputs are kept for `MAX_REORG_DEPTH` blocks so a disconnected block
+/// can give them back.
+#[derive(Debug)]
+pub struct ChainIndexer {
+    // address hash, height, index -> tx id
+    txs: Tree,
+    // timestamp, height -> block id
+    blocks: Tree,
+    // outpoint -> utxo
+    utxos: Tree,
+    // address hash, outpoint -> ()
+    address_utxos: Tree,
+    // height spent, outpoint -> utxo
+    spent: Tree,
+    // height -> block stats
+    block_stats: Tree,
+    // day -> transactions confirmed that day
+    daily_txs: Tree,
+    meta: Tree,
+    page_size: usize,
+}
+
+impl ChainIndexer {
+    pub fn new(path: &str, config: &IndexerConfig) -> Result<Self, StorageError> {
+        Self::from_db(&sled::open(path)?, config)
+    }
+
+    /// Indexes kept in memory only, rebuilt by `sync` on every start
+    pub fn temporary(config: &IndexerConfig) -> Result<Self, StorageError> {
+        Self::from_db(&sled::Config::new().temporary(true).open()?, config)
+    }
+
+    /// Indexer inside an already open database
+    pub fn from_db(db: &Db, config: &IndexerConfig) -> Result<Self, StorageError> {
+        Ok(Self {
+            txs: db.open_tree(TXS_TREE)?,
+            blocks: db.open_tree(BLOCKS_TREE)?,
+            utxos: db.open_tree(UTXOS_TREE)?,
+            address_utxos: db.open_tree(ADDRESS_UTXOS_TREE)?,
+            spent: db.open_tree(SPENT_TREE)?,
+            block_stats: db.open_tree(BLOCK_STATS_TREE)?,
+            daily_txs: db.open_tree(DAILY_TXS_TREE)?,
+            meta: db.open_tree(META_TREE)?,
+            page_size: config.page_size.clamp(1, MAX_PAGE_SIZE),
+        })
+    }
+
+    /// Last block indexed
+    pub fn tip(&self) -> Result<Option<(BlockHeight, BlockId)>, StorageError> {
+        match self.meta.get(TIP_KEY)? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Bring the indexes in line with the chain's main chain
+    pub fn sync(&self, blockchain: &Blockchain) -> Result<(), StorageError> {
+        //rewind blocks the chain no longer has on its main chain
+        while let Some((height, block_id)) = self.tip()? {
+            let on_main_chain = blockchain.get_block_by_height(&height)
+                .is_some_and(|block| block.id() == block_id);
+            if on_main_chain {
+                break;
+            }
+            let block = blockchain.get_block(&block_id)
+                .ok_or_else(|| StorageError::Chain(blockchain_core::BlockchainError::BlockNotFound(block_id.to_string())))?;
+            self.disconnect_block(block)?;
+        }
+
+        let start = match self.tip()? {
+            Some((height, _)) => height + 1,
+            None => 0,
+        };
+        if start <= blockchain.height() && blockchain.is_pruned(start) {
+            return Err(StorageError::Chain(blockchain_core::BlockchainError::BlockNotFound(
+                format!("blocks from height {} are pruned, too old to index", start)
+            )));
+        }
+        for height in start..=blockchain.height() {
+            if let Some(block) = blockchain.get_block_by_height(&height) {
+                self.connect_block(block)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Index a block joining the main chain on top of the indexed tip
+    pub fn connect_block(&self, block: &Block) -> Result<(), StorageError> {
+        let height = block.height();
+        let mut txs = sled::Batch::default();
+        let mut spent = sled::Batch::default();
+
+        for (index, tx) in block.transactions().iter().enumerate() {
+            let tx_id = tx.id();
+            let mut addresses = self.addresses(tx)?;
+
+            for input in &tx.inputs {
+                let key = outpoint_key(&input.prev_output);
+                if let Some(data) = self.utxos.remove(&key)? {
+                    let utxo: UTXO = bincode::deserialize(&data)?;
+                    self.address_utxos.remove(address_utxo_key(&utxo.output.address, &input.prev_output))?;
+                    spent.insert(spent_key(height, &input.prev_output), bincode::serialize(&utxo)?);
+                }
+            }
+
+            for (output_index, output) in tx.outputs.iter().enumerate() {
+                let outpoint = OutPoint::new(tx_id, output_index as u32);
+                let utxo = UTXO::new(output.clone(), height, tx_id, output_index as u32, tx.is_coinbase());
+                self.utxos.insert(outpoint_key(&outpoint), bincode::serialize(&utxo)?)?;
+                self.address_utxos.insert(address_utxo_key(&output.address, &outpoint), PRESENT)?;
+                addresses.insert(address_hash(&output.address));
+            }
+
+            for address in addresses {
+                txs.insert(tx_key(&address, height, index as u32), tx_id.hash().as_bytes().as_slice());
+            }
+        }
+
+        self.txs.apply_batch(txs)?;
+        self.spent.apply_batch(spent)?;
+        self.blocks.insert(block_key(block), block.id().hash().as_bytes().as_slice())?;
+        self.block_stats.insert(height.to_be_bytes(), bincode::serialize(&BlockStats::from_block(block))?)?;
+        self.count_daily_txs(block, true)?;
+        self.prune_spent(height)?;
+        self.meta.insert(TIP_KEY, bincode::serialize(&(height, block.id()))?)?;
+        Ok(())
+    }
+
+    /// Remove the indexed tip block, restoring the outputs it spent
+    pub fn disconnect_block(&self, block: &Block) -> Result<(), StorageError> {
+        let height = block.height();
+
+        for (index, tx) in block.transactions().iter().enumerate().rev() {
+            let tx_id = tx.id();
+            for (output_index, output) in tx.outputs.iter().enumerate() {
+                let outpoint = OutPoint::new(tx_id, output_index as u32);
+                self.utxos.remove(outpoint_key(&outpoint))?;
+                self.address_utxos.remove(address_utxo_key(&output.address, &outpoint))?;
+                self.txs.remove(tx_key(&address_hash(&output.address), height, index as u32))?;
+            }
+
+            for input in &tx.inputs {
+                if let Some(data) = self.spent.remove(spent_key(height, &input.prev_output))? {
+                    let utxo: UTXO = bincode::deserialize(&data)?;
+                    self.address_utxos.insert(address_utxo_key(&utxo.output.address, &input.prev_output), PRESENT)?;
+                    self.utxos.insert(outpoint_key(&input.prev_output), data)?;
+                }
+            }
+
+            for address in self.addresses(tx)? {
+                self.txs.remove(tx_key(&address, height, index as u32))?;
+            }
+        }
+
+        self.blocks.remove(block_key(block))?;
+        self.block_stats.remove(height.to_be_bytes())?;
+        self.count_daily_txs(block, false)?;
+        let parent = height.checked_sub(1).map(|parent| (parent, block.prev_hash()));
+        match parent {
+            Some(parent) => self.meta.insert(TIP_KEY, bincode::serialize(&parent)?)?,
+            None => self.meta.remove(TIP_KEY)?,
+        };
+        Ok(())
+    }
+
+    /// Transactions touching `address`, newest first, a page at a time
+    pub fn transactions_by_address(&self, address: &Address, page: usize) -> Result<Vec<IndexedTransaction>, StorageError> {
+        let page_size = self.page_size;
+        let prefix = address_hash(address);
+
+        let mut transactions = Vec::with_capacity(page_size);
+        for entry in self.txs.scan_prefix(prefix.as_bytes()).rev().skip(page.saturating_mul(page_size)).take(page_size) {
+            let (key, value) = entry?;
+            let block_height = u64::from_be_bytes(key[32..40].try_into().expect("tx key layout"));
+            let index = u32::from_be_bytes(key[40..44].try_into().expect("tx key layout"));
+            let hash = Hash256::from_slice(&value).map_err(blockchain_core::BlockchainError::from)?;
+            transactions.push(IndexedTransaction { tx_id: TxId::new(hash), block_height, index });
+        }
+        Ok(transactions)
+    }
+
+    /// Main chain blocks with timestamps in `from..=to` (unix seconds), oldest first
+    pub fn blocks_by_time(&self, from: i64, to: i64) -> Result<Vec<(BlockHeight, BlockId)>, StorageError> {
+        let mut blocks = Vec::new();
+        if from > to {
+            return Ok(blocks);
+        }
+
+        let start = time_key(from, 0);
+        let end = time_key(to, BlockHeight::MAX);
+        for entry in self.blocks.range(start..=end) {
+            let (key, value) = entry?;
+            let height = u64::from_be_bytes(key[8..16].try_into().expect("block key layout"));
+            let hash = Hash256::from_slice(&value).map_err(blockchain_core::BlockchainError::from)?;
+            blocks.push((height, BlockId::new(hash)));
+        }
+        Ok(blocks)
+    }
+
+    /// Unspent outputs paying `address`
+    pub fn utxos_by_address(&self, address: &Address) -> Result<Vec<UTXO>, StorageError> {
+        let mut utxos = Vec::new();
+        for entry in self.address_utxos.scan_prefix(address_hash(address).as_bytes()) {
+            let (key, _) = entry?;
+            if let Some(data) = self.utxos.get(&key[32..])? {
+                utxos.push(bincode::deserialize(&data)?);
+            }
+        }
+        Ok(utxos)
+    }
+
+    /// Stats of main chain blocks at heights `from..=to`, oldest first
+    pub fn block_stats(&self, from: BlockHeight, to: BlockHeight) -> Result<Vec<BlockStats>, StorageError> {
+        let mut stats = Vec::new();
+        if from > to {
+            return Ok(stats);
+        }
+        for entry in self.block_stats.range(from.to_be_bytes()..=to.to_be_bytes()) {
+            stats.push(bincode::deserialize(&entry?.1)?);
+        }
+        Ok(stats)
+    }
+
+    /// Mean seconds between the last `blocks` indexed blocks; None with
+    /// fewer than two of them
+    pub fn average_block_time(&self, blocks: u64) -> Result<Option<f64>, StorageError> {
+        let mut timestamps = Vec::new();
+        for entry in self.block_stats.iter().rev().take(blocks.max(2) as usize) {
+            let stats: BlockStats = bincode::deserialize(&entry?.1)?;
+            timestamps.push(stats.timestamp);
+        }
+        match (timestamps.first(), timestamps.last()) {
+            (Some(newest), Some(oldest)) if timestamps.len() >= 2 => {
+                Ok(Some((newest - oldest) as f64 / (timestamps.len() - 1) as f64))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Transactions confirmed per UTC day, for days with any between the
+    /// days holding `from` and `to` (unix seconds). Days are given by the
+    /// unix time they start at.
+    pub fn daily_transactions(&self, from: i64, to: i64) -> Result<Vec<(i64, u64)>, StorageError> {
+        let mut days = Vec::new();
+        if from > to {
+            return Ok(days);
+        }
+        for entry in self.daily_txs.range(day_key(from)..=day_key(to)) {
+            let (key, value) = entry?;
+            let day = u64::from_be_bytes(key.as_ref().try_into().expect("day key layout"));
+            let count = u64::from_be_bytes(value.as_ref().try_into().expect("day count layout"));
+            days.push((day as i64 * SECONDS_PER_DAY, count));
+        }
+        Ok(days)
+    }
+
+    //count a block's transactions in its day, or take them out when disconnected
+    fn count_daily_txs(&self, block: &Block, connected: bool) -> Result<(), StorageError> {
+        let key = day_key(block.timestamp().to_unix_timestamp());
+        let change = block.transaction_count() as u64;
+        self.daily_txs.fetch_and_update(key, |count| {
+            let count = count.map_or(0, |bytes| u64::from_be_bytes(bytes.try_into().expect("day count layout")));
+            let count = if connected { count + change } else { count.saturating_sub(change) };
+            (count > 0).then(|| count.to_be_bytes().to_vec())
+        })?;
+        Ok(())
+    }
+
+    //account-model participants of a transaction and the owners of what it
+    //spends, while its inputs are still unspent
+    fn addresses(&self, tx: &Transaction) -> Result<BTreeSet<Hash256>, StorageError> {
+        let mut addresses: BTreeSet<Hash256> = tx.from.iter().chain(tx.to.iter()).map(address_hash).collect();
+        for input in &tx.inputs {
+            if let Some(data) = self.utxos.get(outpoint_key(&input.prev_output))? {
+                let utxo: UTXO = bincode::deserialize(&data)?;
+                addresses.insert(address_hash(&utxo.output.address));
+            }
+        }
+        Ok(addresses)
+    }
+
+    //outputs spent deeper than any reorg can't come back
+    fn prune_spent(&self, height: BlockHeight) -> Result<(), StorageError> {
+        let Some(cutoff) = height.checked_sub(MAX_REORG_DEPTH) else { return Ok(()) };
+        let mut batch = sled::Batch::default();
+        for entry in self.spent.range(..cutoff.to_be_bytes().to_vec()) {
+            batch.remove(entry?.0);
+        }
+        self.spent.apply_batch(batch)?;
+        Ok(())
+    }
+}
+
+
+//fixed-size key prefix for an address, whatever its encoding
+fn address_hash(address: &Address) -> Hash256 {
+    sha256(address.data())
+}
+
+fn outpoint_key(outpoint: &OutPoint) -> Vec<u8> {
+    let mut key = outpoint.tx_id.hash().as_bytes().to_vec();
+    key.extend_from_slice(&outpoint.output_index.to_be_bytes());
+    key
+}
+
+fn spent_key(height: BlockHeight, outpoint: &OutPoint) -> Vec<u8> {
+    let mut key = height.to_be_bytes().to_vec();
+    key.extend_from_slice(&outpoint_key(outpoint));
+    key
+}
+
+fn address_utxo_key(address: &Address, outpoint: &OutPoint) -> Vec<u8> {
+    let mut key = address_hash(address).as_bytes().to_vec();
+    key.extend_from_slice(&outpoint_key(outpoint));
+    key
+}
+
+fn tx_key(address: &Hash256, height: BlockHeight, index: u32) -> Vec<u8> {
+    let mut key = address.as_bytes().to_vec();
+    key.extend_from_slice(&height.to_be_bytes());
+    key.extend_from_slice(&index.to_be_bytes());
+    key
+}
+
+//timestamps before 1970 sort as 0
+fn time_key(timestamp: i64, height: BlockHeight) -> Vec<u8> {
+    let mut key = (timestamp.max(0) as u64).to_be_bytes().to_vec();
+    key.extend_from_slice(&height.to_be_bytes());
+    key
+}
+
+//days before 1970 count as day 0
+fn day_key(timestamp: i64) -> [u8; 8] {
+    ((timestamp.max(0) / SECONDS_PER_DAY) as u64).to_be_bytes()
+}
+
+fn block_key(block: &Block) -> Vec<u8> {
+    time_key(block.header.timestamp.to_unix_timestamp(), block.height())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::transaction::{TransactionInput, TransactionOutput};
+    use blockchain_core::{AddressType, ChainConfig, Signature, Timestamp};
+    use blockchain_crypto::signature::Keypair;
+
+    fn address(keypair: &Keypair) -> Address {
+        Address::from_public_key(keypair.public_key(), AddressType::Base58)
+    }
+
+    // block at `height` on `prev` mined at `timestamp`
+    fn block(prev: BlockId, height: BlockHeight, timestamp: i64, transactions: Vec<Transaction>) -> Block {
+        let mut block = Block::new(prev, transactions, 1, height, 1).unwrap();
+        block.header.timestamp = Timestamp::from_unix_timestamp(timestamp);
+        block
+    }
+
+    fn indexer(page_size: usize) -> ChainIndexer {
+        ChainIndexer::temporary(&IndexerConfig { enabled: true, page_size }).unwrap()
+    }
+
+    #[test]
+    fn test_spends_move_utxos_and_disconnect_restores_them() {
+        let indexer = indexer(25);
+        let alice = Keypair::generate();
+        let bob = address(&Keypair::generate());
+        let coinbase = Transaction::new_coinbase(address(&alice), 50, 0);
+        let genesis = block(BlockId::genesis(), 0, 1_000, vec![coinbase.clone()]);
+
+        let input = TransactionInput::new(OutPoint::new(coinbase.id(), 0), Signature::from_bytes([0u8; 64]), *alice.public_key());
+        let outputs = vec![TransactionOutput::new(30, bob.clone()), TransactionOutput::new(15, address(&alice))];
+        let payment = Transaction::new_utxo(vec![input], outputs, 5);
+        let miner = Transaction::new_coinbase(address(&Keypair::generate()), 55, 1);
+        let block1 = block(genesis.id(), 1, 1_060, vec![miner, payment.clone()]);
+
+        indexer.connect_block(&genesis).unwrap();
+        indexer.connect_block(&block1).unwrap();
+        assert_eq!(indexer.tip().unwrap(), Some((1, block1.id())));
+
+        // the spender is found through the output it spent, newest first
+        let alice_txs: Vec<_> = indexer.transactions_by_address(&address(&alice), 0).unwrap()
+            .into_iter().map(|tx| (tx.tx_id, tx.block_height, tx.index)).collect();
+        assert_eq!(alice_txs, vec![(payment.id(), 1, 1), (coinbase.id(), 0, 0)]);
+        let amounts = |owner: &Address| -> Vec<Amount> {
+            indexer.utxos_by_address(owner).unwrap().iter().map(|utxo| utxo.output.amount).collect()
+        };
+        assert_eq!(amounts(&address(&alice)), vec![15]);
+        assert_eq!(amounts(&bob), vec![30]);
+
+        let stats = indexer.block_stats(0, 1).unwrap();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[1].tx_count, stats[1].total_fees, stats[1].median_fee), (2, 5, 5));
+        assert_eq!(indexer.average_block_time(10).unwrap(), Some(60.0));
+
+        indexer.disconnect_block(&block1).unwrap();
+        assert_eq!(indexer.tip().unwrap(), Some((0, genesis.id())));
+        assert_eq!(amounts(&address(&alice)), vec![50]);
+        assert!(amounts(&bob).is_empty());
+        assert!(indexer.transactions_by_address(&bob, 0).unwrap().is_empty());
+        assert_eq!(indexer.transactions_by_address(&address(&alice), 0).unwrap().len(), 1);
+        assert_eq!(indexer.block_stats(0, 1).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_blocks_and_days_by_time() {
+        let indexer = indexer(25);
+        let miner = address(&Keypair::generate());
+        let times = [SECONDS_PER_DAY - 10, SECONDS_PER_DAY + 5, SECONDS_PER_DAY + 20, 3 * SECONDS_PER_DAY];
+        let mut prev = BlockId::genesis();
+        let mut ids = Vec::new();
+        for (height, timestamp) in times.iter().enumerate() {
+            let coinbase = Transaction::new_coinbase(miner.clone(), 50, height as BlockHeight);
+            let block = block(prev, height as BlockHeight, *timestamp, vec![coinbase]);
+            indexer.connect_block(&block).unwrap();
+            prev = block.id();
+            ids.push(block.id());
+        }
+
+        assert_eq!(indexer.blocks_by_time(SECONDS_PER_DAY, SECONDS_PER_DAY + 20).unwrap(), vec![(1, ids[1]), (2, ids[2])]);
+        assert!(indexer.blocks_by_time(10, 0).unwrap().is_empty());
+        assert_eq!(
+            indexer.daily_transactions(0, 3 * SECONDS_PER_DAY).unwrap(),
+            vec![(0, 1), (SECONDS_PER_DAY, 2), (3 * SECONDS_PER_DAY, 1)]
+        );
+    }
+
+    #[test]
+    fn test_sync_indexes_the_chain_a_page_at_a_time() {
+        let miner = address(&Keypair::generate());
+        let mut chain = Blockchain::new(ChainConfig::default()).unwrap();
+        for _ in 0..5 {
+            chain.mine_block(miner.clone()).unwrap();
+        }
+
+        let indexer = indexer(2);
+        indexer.sync(&chain).unwrap();
+        assert_eq!(indexer.tip().unwrap(), Some((5, chain.get_chain_head().unwrap().id())));
+
+        let heights = |page| -> Vec<BlockHeight> {
+            indexer.transactions_by_address(&miner, page).unwrap().iter().map(|tx| tx.block_height).collect()
+        };
+        assert_eq!(heights(0), vec![5, 4]);
+        assert_eq!(heights(1), vec![3, 2]);
+        assert_eq!(heights(2), vec![1]);
+        assert!(heights(3).is_empty());
+
+        // syncing again is a no-op
+        indexer.sync(&chain).unwrap();
+        assert_eq!(heights(0), vec![5, 4]);
+    }
+}
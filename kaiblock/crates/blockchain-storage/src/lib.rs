@@ -1,14 +1,19 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+pub mod storage;
+pub mod block_store;
+pub mod state_store;
+pub mod errors;
+pub mod chain_store;
+pub mod backup;
+pub mod snapshot;
+pub mod account_store;
+pub mod indexer;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use storage::Storage;
+pub use block_store::SledBlockStore;
+pub use state_store::{StateCacheConfig, StateCacheStats, StateStore};
+pub use errors::StorageError;
+pub use chain_store::open_blockchain;
+pub use account_store::SledAccountStore;
+pub use indexer::{BlockStats, ChainIndexer, IndexedTransaction, IndexerConfig};
+pub use backup::{BackupManifest, ChunkEntry, ChunkReport, export_backup, restore_backup};
+pub use snapshot::{check_snapshot_chunks, export_snapshot, import_snapshot, read_snapshot_manifest};
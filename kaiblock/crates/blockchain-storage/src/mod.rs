@@ -1,9 +0,0 @@
-pub mod storage;
-pub mod block_store;
-pub mod state_store;
-pub mod errors;
-
-pub use storage::Storage;
-pub use block_store::SledBlockStore;
-pub use state_store::StateStore;
-pub use errors::StorageError;
@@ -0,0 +1,122 @@
+//! World state snapshots for fast sync.
+//!
+//! A snapshot is the world state at one main chain block, cut into chunks of
+//! accounts and unspent outputs, plus a manifest with every chunk's hash, the
+//! state root the chunks rebuild to and the blocks leading up to the snapshot
+//! block. A new node imports it instead of replaying the chain from genesis.
+//!
+//! Block headers don't commit to the state root, so a snapshot is only as good
+//! as the block it claims to be taken at: the importing operator names that
+//! block (from a trusted node or a checkpoint) and the import refuses any
+//! other. Export is resumable: chunk files already written with the right
+//! hash are kept, and the manifest is written last so a half-written
+//! snapshot is never mistaken for a complete one.
+
+use std::fs;
+use std::path::Path;
+
+use blockchain_core::snapshot::{SnapshotChunk, SnapshotImport, SnapshotManifest};
+use blockchain_core::store::MemoryChainStore;
+use blockchain_core::{BlockHeight, BlockId, Blockchain, ChainConfig};
+use blockchain_crypto::hash::sha256;
+
+use crate::backup::ChunkReport;
+use crate::block_store::SledBlockStore;
+use crate::errors::StorageError;
+
+
+const MANIFEST_FILE: &str = "snapshot.bin";
+
+
+/// Write the world state at main chain `height` to `dir`. Chunks left by an
+/// interrupted export of the same state are reused.
+pub fn export_snapshot(
+    chain: &Blockchain,
+    dir: &Path,
+    height: BlockHeight,
+    entries_per_chunk: usize,
+) -> Result<SnapshotManifest, StorageError> {
+    let (manifest, chunks) = chain.export_state_snapshot(height, entries_per_chunk)?;
+    fs::create_dir_all(dir)?;
+
+    for (index, (chunk, hash)) in chunks.iter().zip(&manifest.chunk_hashes).enumerate() {
+        let path = dir.join(chunk_file(index));
+        if fs::read(&path).map(|data| sha256(&data) == *hash).unwrap_or(false) {
+            continue;
+        }
+        fs::write(path, chunk.to_bytes()?)?;
+    }
+
+    fs::write(dir.join(MANIFEST_FILE), bincode::serialize(&manifest)?)?;
+    Ok(manifest)
+}
+
+
+/// Read the manifest of the snapshot in `dir`
+pub fn read_snapshot_manifest(dir: &Path) -> Result<SnapshotManifest, StorageError> {
+    let manifest: SnapshotManifest = bincode::deserialize(&fs::read(dir.join(MANIFEST_FILE))?)?;
+    manifest.validate()?;
+    Ok(manifest)
+}
+
+
+/// Hash every chunk file in `dir` against the manifest
+pub fn check_snapshot_chunks(manifest: &SnapshotManifest, dir: &Path) -> ChunkReport {
+    let mut report = ChunkReport::default();
+    for (index, hash) in manifest.chunk_hashes.iter().enumerate() {
+        match fs::read(dir.join(chunk_file(index))) {
+            Ok(data) if sha256(&data) == *hash => {}
+            Ok(_) => report.corrupt.push(index),
+            Err(_) => report.missing.push(index),
+        }
+    }
+    report
+}
+
+
+/// Start a chain from the snapshot in `dir`, taken at block `trusted_block`.
+///
+/// The chain is persisted under `config.storage_path`, which must not hold a
+/// chain yet, or kept in memory without one. Nothing is written until every
+/// chunk checks out and the rebuilt state matches the manifest's state root;
+/// a damaged snapshot fails with the chunks to fetch again.
+pub fn import_snapshot(dir: &Path, config: ChainConfig, trusted_block: &BlockId) -> Result<Blockchain, StorageError> {
+    let manifest = read_snapshot_manifest(dir)?;
+    if manifest.chain_id != config.chain_id {
+        return Err(StorageError::Snapshot(format!(
+            "snapshot is of chain {}, not {}", manifest.chain_id, config.chain_id
+        )));
+    }
+    if manifest.block_id != *trusted_block {
+        return Err(StorageError::Snapshot(format!(
+            "snapshot is taken at block {}, not the trusted block {}", manifest.block_id, trusted_block
+        )));
+    }
+
+    let report = check_snapshot_chunks(&manifest, dir);
+    if !report.is_intact() {
+        return Err(StorageError::DamagedSnapshot(report.damaged()));
+    }
+
+    let mut import = SnapshotImport::new(manifest)?;
+    for index in 0..import.manifest().chunk_hashes.len() {
+        let chunk = SnapshotChunk::from_bytes(&fs::read(dir.join(chunk_file(index)))?)
+            .map_err(|_| StorageError::DamagedSnapshot(vec![index]))?;
+        import.add_chunk(index, chunk)?;
+    }
+    let (manifest, state) = import.into_state()?;
+
+    let chain = match config.storage_path.clone() {
+        Some(path) => {
+            let store = SledBlockStore::new(&path.to_string_lossy())?;
+            Blockchain::from_state_snapshot(config, Box::new(store), manifest, state)?
+        }
+        None => Blockchain::from_state_snapshot(config, Box::new(MemoryChainStore::new()), manifest, state)?,
+    };
+    Ok(chain)
+}
+
+
+fn chunk_file(index: usize) -> String {
+    format!("state-{:06}.bin", index)
+}
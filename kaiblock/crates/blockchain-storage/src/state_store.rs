@@ -1,22 +1,366 @@
-use sled::Db;
+use sled::{Db, Tree};
 use crate::errors::StorageError;
+use blockchain_core::smt::{self, NodeSource, SmtProof};
+use blockchain_core::state::account_key;
+use blockchain_core::{Address, BlockchainError, BlockHeight, Hash256};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 
+const NODES_TREE: &str = "state_nodes";
+const ROOTS_TREE: &str = "state_roots";
+
+/// State cache sizing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateCacheConfig {
+    /// Most entries kept in memory, counting lookups of absent keys; writes
+    /// waiting for the next commit are held on top of this
+    pub capacity: usize,
+}
+
+impl Default for StateCacheConfig {
+    fn default() -> Self {
+        Self { capacity: 65_536 }
+    }
+}
+
+/// Counters for the state cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StateCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// commits that wrote anything
+    pub commits: u64,
+    pub entries: usize,
+    /// keys written since the last commit
+    pub dirty: usize,
+    pub capacity: usize,
+}
+
+impl StateCacheStats {
+    /// Fraction of lookups answered from memory
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+// A cached value is None when the key is known to be absent
+type CachedValue = Option<Vec<u8>>;
+
+#[derive(Debug, Default)]
+struct StateCache {
+    // value and when it was last used
+    entries: HashMap<Vec<u8>, (CachedValue, u64)>,
+    // keys by last use, least recent first
+    order: BTreeMap<u64, Vec<u8>>,
+    clock: u64,
+    // newest value of every key written since the last commit; never evicted
+    dirty: HashMap<Vec<u8>, CachedValue>,
+}
+
+impl StateCache {
+    fn get(&mut self, key: &[u8]) -> Option<CachedValue> {
+        if let Some(value) = self.dirty.get(key) {
+            return Some(value.clone());
+        }
+        self.clock += 1;
+        let (value, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        *used = self.clock;
+        self.order.insert(self.clock, key.to_vec());
+        Some(value.clone())
+    }
+
+    // Returns how many entries were evicted to make room
+    fn insert(&mut self, key: Vec<u8>, value: CachedValue, capacity: usize) -> u64 {
+        if capacity == 0 {
+            return 0;
+        }
+        self.clock += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (value, self.clock)) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.clock, key);
+
+        let mut evicted = 0;
+        while self.entries.len() > capacity {
+            let Some((_, oldest)) = self.order.pop_first() else { break };
+            self.entries.remove(&oldest);
+            evicted += 1;
+        }
+        evicted
+    }
+}
+
+/// Key-value state plus the sparse Merkle state trie: encoded nodes keyed by
+/// hash, and the state root committed at each height.
+///
+/// Key-value state goes through a cache: recently used accounts and UTXOs are
+/// answered from an LRU, and writes are held in memory until [`commit`] writes
+/// them in one batch, so a block's state lands together or not at all.
+///
+/// [`commit`]: StateStore::commit
+#[derive(Debug)]
 pub struct StateStore {
     db: Db,
+    nodes: Tree,
+    roots: Tree,
+    cache: Mutex<StateCache>,
+    cache_config: StateCacheConfig,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    commits: AtomicU64,
 }
 
 impl StateStore {
     pub fn new(path: &str) -> Result<Self, StorageError> {
         let db = sled::open(path)?;
-        Ok(Self { db })
+        Self::from_db(db)
     }
 
+    /// State store inside an already open database
+    pub fn from_db(db: Db) -> Result<Self, StorageError> {
+        let nodes = db.open_tree(NODES_TREE)?;
+        let roots = db.open_tree(ROOTS_TREE)?;
+        Ok(Self {
+            db,
+            nodes,
+            roots,
+            cache: Mutex::new(StateCache::default()),
+            cache_config: StateCacheConfig::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            commits: AtomicU64::new(0),
+        })
+    }
+
+    pub fn with_cache(mut self, config: StateCacheConfig) -> Self {
+        self.cache = Mutex::new(StateCache::default());
+        self.cache_config = config;
+        self
+    }
+
+    /// Stage a write; it is read back at once but only stored by [`commit`](Self::commit)
     pub fn set(&self, key: &[u8], value: &[u8]) -> Result<(), StorageError> {
-        self.db.insert(key, value)?;
+        self.lock_cache().dirty.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(())
+    }
+
+    /// Stage a removal, stored by [`commit`](Self::commit) like a write
+    pub fn delete(&self, key: &[u8]) -> Result<(), StorageError> {
+        self.lock_cache().dirty.insert(key.to_vec(), None);
         Ok(())
     }
 
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
-        Ok(self.db.get(key)?.map(|v| v.to_vec()))
+        // Held through the read, so a commit can't slip in between and leave
+        // the older stored value cached
+        let mut cache = self.lock_cache();
+        if let Some(value) = cache.get(key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(value);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let value = self.db.get(key)?.map(|v| v.to_vec());
+        let evicted = cache.insert(key.to_vec(), value.clone(), self.cache_config.capacity);
+        self.evictions.fetch_add(evicted, Ordering::Relaxed);
+        Ok(value)
+    }
+
+    /// Write every staged change in one batch. Returns how many keys were
+    /// written; on error the changes stay staged.
+    pub fn commit(&self) -> Result<usize, StorageError> {
+        let mut cache = self.lock_cache();
+        if cache.dirty.is_empty() {
+            return Ok(0);
+        }
+
+        let mut batch = sled::Batch::default();
+        for (key, value) in &cache.dirty {
+            match value {
+                Some(value) => batch.insert(key.as_slice(), value.as_slice()),
+                None => batch.remove(key.as_slice()),
+            }
+        }
+        self.db.apply_batch(batch)?;
+
+        // what was just written is the hottest state there is
+        let written = std::mem::take(&mut cache.dirty);
+        let count = written.len();
+        let mut evicted = 0;
+        for (key, value) in written {
+            evicted += cache.insert(key, value, self.cache_config.capacity);
+        }
+        self.evictions.fetch_add(evicted, Ordering::Relaxed);
+        self.commits.fetch_add(1, Ordering::Relaxed);
+        Ok(count)
+    }
+
+    /// Drop the changes staged since the last commit, e.g. of a block that
+    /// failed validation
+    pub fn discard(&self) {
+        self.lock_cache().dirty.clear();
+    }
+
+    pub fn cache_stats(&self) -> StateCacheStats {
+        let cache = self.lock_cache();
+        StateCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            commits: self.commits.load(Ordering::Relaxed),
+            entries: cache.entries.len(),
+            dirty: cache.dirty.len(),
+            capacity: self.cache_config.capacity,
+        }
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, StateCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Store encoded trie nodes. Nodes are content-addressed, so writing one
+    /// that is already stored changes nothing.
+    pub fn put_nodes(&self, nodes: &[(Hash256, Vec<u8>)]) -> Result<(), StorageError> {
+        let mut batch = sled::Batch::default();
+        for (hash, data) in nodes {
+            batch.insert(hash.as_bytes().as_slice(), data.as_slice());
+        }
+        self.nodes.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Record the state root committed at a height (replacing it after a reorg)
+    pub fn put_root(&self, height: BlockHeight, root: &Hash256) -> Result<(), StorageError> {
+        self.roots.insert(height.to_be_bytes(), root.as_bytes().as_slice())?;
+        Ok(())
+    }
+
+    pub fn root_at(&self, height: BlockHeight) -> Result<Option<Hash256>, StorageError> {
+        match self.roots.get(height.to_be_bytes())? {
+            Some(data) => Ok(Some(Hash256::from_slice(&data).map_err(BlockchainError::from)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Proof for a trie key under a stored root
+    pub fn prove(&self, root: Hash256, key: &Hash256) -> Result<SmtProof, StorageError> {
+        Ok(smt::prove_from(self, root, key)?)
+    }
+
+    /// Proof for an account under the root committed at `height`, with that root
+    pub fn prove_account(&self, height: BlockHeight, address: &Address) -> Result<Option<(Hash256, SmtProof)>, StorageError> {
+        match self.root_at(height)? {
+            Some(root) => Ok(Some((root, self.prove(root, &account_key(address))?))),
+            None => Ok(None),
+        }
+    }
+}
+
+impl NodeSource for StateStore {
+    fn node(&self, hash: &Hash256) -> blockchain_core::Result<Option<Vec<u8>>> {
+        let node = self.nodes.get(hash.as_bytes())
+            .map_err(|e| BlockchainError::StorageError(e.to_string()))?;
+        Ok(node.map(|data| data.to_vec()))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(capacity: usize) -> StateStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        StateStore::from_db(db).unwrap().with_cache(StateCacheConfig { capacity })
+    }
+
+    #[test]
+    fn test_writes_are_staged_until_commit() {
+        let store = store(16);
+        store.set(b"alice", b"10").unwrap();
+        store.set(b"bob", b"20").unwrap();
+        assert_eq!(store.get(b"alice").unwrap(), Some(b"10".to_vec()));
+        assert_eq!(store.db.get(b"alice").unwrap(), None);
+        assert_eq!(store.cache_stats().dirty, 2);
+
+        assert_eq!(store.commit().unwrap(), 2);
+        assert_eq!(store.db.get(b"alice").unwrap().as_deref(), Some(b"10".as_slice()));
+        assert_eq!(store.commit().unwrap(), 0);
+        assert_eq!(store.cache_stats().commits, 1);
+
+        // a failed block's changes never reach the store
+        store.set(b"alice", b"0").unwrap();
+        store.delete(b"bob").unwrap();
+        assert_eq!(store.get(b"bob").unwrap(), None);
+        store.discard();
+        assert_eq!(store.get(b"alice").unwrap(), Some(b"10".to_vec()));
+        assert_eq!(store.get(b"bob").unwrap(), Some(b"20".to_vec()));
+
+        store.delete(b"bob").unwrap();
+        store.commit().unwrap();
+        assert_eq!(store.db.get(b"bob").unwrap(), None);
+        assert_eq!(store.get(b"bob").unwrap(), None);
+    }
+
+    #[test]
+    fn test_least_recently_used_entries_are_evicted() {
+        let store = store(2);
+        for key in [b"a", b"b", b"c"] {
+            store.db.insert(key, key.as_slice()).unwrap();
+        }
+
+        store.get(b"a").unwrap();
+        store.get(b"b").unwrap();
+        store.get(b"a").unwrap();
+        store.get(b"c").unwrap();
+        let stats = store.cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions, stats.entries), (1, 3, 1, 2));
+
+        // "b" was the least recently used
+        store.get(b"a").unwrap();
+        store.get(b"b").unwrap();
+        let stats = store.cache_stats();
+        assert_eq!((stats.hits, stats.misses), (2, 4));
+        assert_eq!(stats.hit_rate(), 2.0 / 6.0);
+    }
+
+    #[test]
+    fn test_absent_keys_are_cached_and_dirty_keys_never_evicted() {
+        let store = store(1);
+        assert_eq!(store.get(b"missing").unwrap(), None);
+        assert_eq!(store.get(b"missing").unwrap(), None);
+        assert_eq!(store.cache_stats().hits, 1);
+
+        for key in [b"x", b"y", b"z"] {
+            store.set(key, b"staged").unwrap();
+        }
+        store.db.insert(b"cold", b"1".as_slice()).unwrap();
+        store.get(b"cold").unwrap();
+        for key in [b"x", b"y", b"z"] {
+            assert_eq!(store.get(key).unwrap(), Some(b"staged".to_vec()));
+        }
+        assert_eq!(store.cache_stats().entries, 1);
+        assert_eq!(store.commit().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_zero_capacity_reads_through() {
+        let store = store(0);
+        store.db.insert(b"k", b"v".as_slice()).unwrap();
+        assert_eq!(store.get(b"k").unwrap(), Some(b"v".to_vec()));
+        assert_eq!(store.get(b"k").unwrap(), Some(b"v".to_vec()));
+        let stats = store.cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (0, 2, 0));
+        assert_eq!(StateCacheStats::default().hit_rate(), 0.0);
     }
 }
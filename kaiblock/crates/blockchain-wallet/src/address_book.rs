@@ -0,0 +1,253 @@
+use blockchain_core::NetworkType;
+use blockchain_crypto::{Address, AddressType};
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use crate::errors::WalletError;
+
+
+const ADDRESS_BOOK_VERSION: u32 = 1;
+
+/// Longest label the address book accepts
+pub const MAX_LABEL_LEN: usize = 64;
+
+
+/// One labelled address, with the network it was saved for
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct AddressBookEntry {
+    pub label: String,
+    pub address: String,
+    pub network: NetworkType,
+    pub address_type: AddressType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+
+/// On-disk address book layout (JSON)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AddressBookFile {
+    pub version: u32,
+    pub entries: Vec<AddressBookEntry>,
+}
+
+
+/// Human labels ("petnames") for addresses, kept in a JSON file.
+///
+/// Labels are local to this book: they mean whatever its owner saved them as
+/// and are never looked up on the network. Every entry records the network it
+/// was saved for, and resolving a label on another network fails, so a
+/// testnet contact can't be paid on mainnet by mistake.
+#[derive(Debug, Clone)]
+pub struct AddressBook {
+    path: PathBuf,
+    file: AddressBookFile,
+}
+
+impl AddressBook {
+    /// Load the address book at `path`; an empty one if the file doesn't exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, WalletError> {
+        let path = path.as_ref().to_path_buf();
+        if !path.exists() {
+            return Ok(Self { path, file: AddressBookFile { version: ADDRESS_BOOK_VERSION, entries: Vec::new() } });
+        }
+
+        let data = std::fs::read(&path).map_err(|e| WalletError::Io(e.to_string()))?;
+        let file: AddressBookFile = serde_json::from_slice(&data)
+            .map_err(|e| WalletError::AddressBook(format!("corrupt address book: {}", e)))?;
+        if file.version != ADDRESS_BOOK_VERSION {
+            return Err(WalletError::AddressBook(format!("unsupported version {}", file.version)));
+        }
+        Ok(Self { path, file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Entries in the order they were added
+    pub fn entries(&self) -> &[AddressBookEntry] {
+        &self.file.entries
+    }
+
+    pub fn get(&self, label: &str) -> Option<&AddressBookEntry> {
+        self.file.entries.iter().find(|entry| entry.label == label)
+    }
+
+    /// Label saved for `address` on `network`, if any
+    pub fn label_of(&self, address: &Address, network: NetworkType) -> Option<&str> {
+        self.file.entries.iter()
+            .find(|entry| entry.network == network && entry.address == address.encoded())
+            .map(|entry| entry.label.as_str())
+    }
+
+    /// Save `address` under `label` for `network`. The address must belong to
+    /// the network, and the label must be new
+    pub fn insert(&mut self, label: &str, address: &Address, network: NetworkType, note: Option<String>) -> Result<(), WalletError> {
+        check_label(label)?;
+        check_network(address, network)?;
+        if self.get(label).is_some() {
+            return Err(WalletError::AddressBook(format!("label {:?} is already taken", label)));
+        }
+
+        self.file.entries.push(AddressBookEntry {
+            label: label.to_string(),
+            address: address.encoded().to_string(),
+            network,
+            address_type: address.address_type(),
+            note,
+        });
+        self.save()
+    }
+
+    /// Forget `label`, returning what it pointed to
+    pub fn remove(&mut self, label: &str) -> Result<AddressBookEntry, WalletError> {
+        let index = self.file.entries.iter()
+            .position(|entry| entry.label == label)
+            .ok_or_else(|| WalletError::AddressBook(format!("no address labelled {:?}", label)))?;
+        let entry = self.file.entries.remove(index);
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// The address `name` stands for on `network`: a label of this book, or
+    /// else an address. Either way it must belong to `network`
+    pub fn resolve(&self, name: &str, network: NetworkType) -> Result<Address, WalletError> {
+        let address = match self.get(name) {
+            Some(entry) => {
+                if entry.network != network {
+                    return Err(WalletError::AddressBook(format!(
+                        "{:?} was saved for {:?}, not {:?}", name, entry.network, network
+                    )));
+                }
+                Address::from_string(&entry.address).map_err(|_| WalletError::InvalidAddress)?
+            }
+            None => Address::from_string(name).map_err(|_| WalletError::AddressBook(format!(
+                "{:?} is neither a label in the address book nor an address", name
+            )))?,
+        };
+        check_network(&address, network)?;
+        Ok(address)
+    }
+
+    // Write to a temp file then rename so a crash never leaves a half-written book
+    fn save(&self) -> Result<(), WalletError> {
+        let data = serde_json::to_vec_pretty(&self.file).map_err(|_| WalletError::SerializationError)?;
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, data).map_err(|e| WalletError::Io(e.to_string()))?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| WalletError::Io(e.to_string()))
+    }
+}
+
+
+/// Whether `address` can be used on `network`. Only Bech32 addresses name
+/// their network (in the human-readable prefix); other encodings fit any
+pub fn check_network(address: &Address, network: NetworkType) -> Result<(), WalletError> {
+    match address.hrp() {
+        Some(hrp) if hrp != network.bech32_hrp() => Err(WalletError::AddressBook(format!(
+            "{} is not a {:?} address", address, network
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Labels are short, printable and never parse as an address, so a label can't
+// shadow one
+fn check_label(label: &str) -> Result<(), WalletError> {
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ' ');
+    if label.trim().is_empty() || label.trim() != label || label.chars().count() > MAX_LABEL_LEN || !label.chars().all(allowed) {
+        return Err(WalletError::AddressBook(format!(
+            "invalid label {:?}: use up to {} letters, digits, spaces, '-', '_' or '.'", label, MAX_LABEL_LEN
+        )));
+    }
+    if Address::from_string(label).is_ok() {
+        return Err(WalletError::AddressBook(format!("label {:?} reads as an address", label)));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{hash::sha256, signature::Keypair};
+
+    fn bech32(network: NetworkType, seed: &[u8]) -> Address {
+        Address::bech32_from_hash(sha256(seed), network.bech32_hrp()).unwrap()
+    }
+
+    fn base58() -> Address {
+        Address::from_public_key(Keypair::generate().public_key(), AddressType::Base58)
+    }
+
+    #[test]
+    fn test_labels_resolve_only_on_their_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut book = AddressBook::open(dir.path().join("book.json")).unwrap();
+        let alice = bech32(NetworkType::Testnet, b"alice");
+        let bob = base58();
+        book.insert("alice", &alice, NetworkType::Testnet, None).unwrap();
+        book.insert("bob", &bob, NetworkType::Testnet, Some("faucet".to_string())).unwrap();
+
+        assert_eq!(book.resolve("alice", NetworkType::Testnet).unwrap(), alice);
+        assert_eq!(book.resolve("bob", NetworkType::Testnet).unwrap(), bob);
+        // even an address that fits any network stays with the network it was saved for
+        assert!(matches!(book.resolve("alice", NetworkType::Mainnet), Err(WalletError::AddressBook(_))));
+        assert!(matches!(book.resolve("bob", NetworkType::Mainnet), Err(WalletError::AddressBook(_))));
+        assert_eq!(book.label_of(&alice, NetworkType::Testnet), Some("alice"));
+        assert_eq!(book.label_of(&alice, NetworkType::Mainnet), None);
+    }
+
+    #[test]
+    fn test_addresses_of_other_networks_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut book = AddressBook::open(dir.path().join("book.json")).unwrap();
+        let mainnet = bech32(NetworkType::Mainnet, b"carol");
+
+        assert!(book.insert("carol", &mainnet, NetworkType::Testnet, None).is_err());
+        assert!(book.resolve(mainnet.encoded(), NetworkType::Testnet).is_err());
+        assert_eq!(book.resolve(mainnet.encoded(), NetworkType::Mainnet).unwrap(), mainnet);
+        assert!(book.resolve("nobody", NetworkType::Mainnet).is_err());
+    }
+
+    #[test]
+    fn test_bad_labels_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut book = AddressBook::open(dir.path().join("book.json")).unwrap();
+        let address = bech32(NetworkType::Devnet, b"dave");
+        book.insert("dave", &address, NetworkType::Devnet, None).unwrap();
+
+        let too_long = "x".repeat(MAX_LABEL_LEN + 1);
+        let address_like = bech32(NetworkType::Devnet, b"erin").encoded().to_string();
+        for label in ["dave", "", " padded", "semi;colon", too_long.as_str(), address_like.as_str()] {
+            assert!(
+                matches!(book.insert(label, &address, NetworkType::Devnet, None), Err(WalletError::AddressBook(_))),
+                "{:?} was accepted", label
+            );
+        }
+        assert_eq!(book.entries().len(), 1);
+    }
+
+    #[test]
+    fn test_book_persists_across_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.json");
+        let frank = bech32(NetworkType::Local, b"frank");
+        let grace = bech32(NetworkType::Local, b"grace");
+        {
+            let mut book = AddressBook::open(&path).unwrap();
+            book.insert("frank", &frank, NetworkType::Local, Some("cold wallet".to_string())).unwrap();
+            book.insert("grace", &grace, NetworkType::Local, None).unwrap();
+            assert_eq!(book.remove("frank").unwrap().address, frank.encoded());
+            assert!(book.remove("frank").is_err());
+        }
+
+        let book = AddressBook::open(&path).unwrap();
+        assert_eq!(book.entries().len(), 1);
+        assert_eq!(book.resolve("grace", NetworkType::Local).unwrap(), grace);
+        assert!(book.get("frank").is_none());
+        assert!(!path.with_extension("tmp").exists());
+
+        std::fs::write(&path, b"{not json").unwrap();
+        assert!(matches!(AddressBook::open(&path), Err(WalletError::AddressBook(_))));
+    }
+}
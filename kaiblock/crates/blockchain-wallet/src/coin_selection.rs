@@ -0,0 +1,423 @@
+use blockchain_core::{Amount, OutPoint, SigningDomain, Transaction, TransactionInput, TransactionOutput, TxId, UTXO};
+use blockchain_crypto::{signature::Keypair, Address, Hash256, Signature};
+use rand::seq::SliceRandom;
+use crate::errors::WalletError;
+
+
+/// Most search steps branch-and-bound takes before giving up
+pub const BNB_MAX_TRIES: usize = 100_000;
+
+/// Most inputs random-improve spends in one payment
+pub const MAX_RANDOM_IMPROVE_INPUTS: usize = 100;
+
+
+/// What a payment needs from coin selection. Sizes are in encoded bytes,
+/// fees are `fee_per_byte` for every byte.
+#[derive(Debug, Clone)]
+pub struct SelectionParams {
+    /// amount paid to the recipient
+    pub amount: Amount,
+    pub fee_per_byte: Amount,
+    /// size of the payment with no inputs and no change
+    pub base_size: usize,
+    /// size each input adds
+    pub input_size: usize,
+    /// size a change output adds
+    pub change_size: usize,
+    /// change below this is left to the fee instead of creating an output
+    /// that costs about as much to spend as it holds
+    pub dust_threshold: Amount,
+}
+
+impl SelectionParams {
+    /// Measure a payment of `amount` from `keypair` to `recipient`. The dust
+    /// threshold defaults to three times the cost of spending an input.
+    pub fn new(keypair: &Keypair, recipient: Address, amount: Amount, fee_per_byte: Amount) -> Self {
+        let payment = TransactionOutput::new(amount, recipient.clone());
+        let change = TransactionOutput::new(0, recipient);
+        let input = blank_inputs(keypair, [OutPoint::new(TxId::new(Hash256::zero()), 0)]);
+
+        let base_size = Transaction::new_utxo(Vec::new(), vec![payment.clone()], 0).size();
+        let input_size = Transaction::new_utxo(input, vec![payment.clone()], 0).size() - base_size;
+        let change_size = Transaction::new_utxo(Vec::new(), vec![payment, change], 0).size() - base_size;
+
+        Self {
+            amount,
+            fee_per_byte,
+            base_size,
+            input_size,
+            change_size,
+            dust_threshold: 3 * input_size as Amount * fee_per_byte,
+        }
+    }
+
+    pub fn with_dust_threshold(mut self, dust_threshold: Amount) -> Self {
+        self.dust_threshold = dust_threshold;
+        self
+    }
+
+    /// Fee of the payment spending `inputs` inputs, with or without change
+    pub fn fee(&self, inputs: usize, with_change: bool) -> Amount {
+        let change = if with_change { self.change_size } else { 0 };
+        (self.base_size + inputs * self.input_size + change) as Amount * self.fee_per_byte
+    }
+
+    /// What an output is worth once the fee to spend it is paid; None if
+    /// spending it costs more than it holds
+    pub fn effective_value(&self, utxo: &UTXO) -> Option<Amount> {
+        let cost = self.input_size as Amount * self.fee_per_byte;
+        utxo.output.amount.checked_sub(cost).filter(|value| *value > 0)
+    }
+
+    // amount plus the fee of everything but the inputs
+    fn target(&self) -> Amount {
+        self.amount + self.fee(0, false)
+    }
+
+    // fee of a change output plus the smallest change worth making
+    fn cost_of_change(&self) -> Amount {
+        self.change_size as Amount * self.fee_per_byte + self.dust_threshold
+    }
+}
+
+
+/// UTXOs picked for a payment and how their value splits between the
+/// recipient, the fee and change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    pub inputs: Vec<UTXO>,
+    pub amount: Amount,
+    pub fee: Amount,
+    /// None when what is left over is dust and goes to the fee instead
+    pub change: Option<Amount>,
+}
+
+impl Selection {
+    /// Split `inputs` for the payment; fails if they don't cover it and its fee
+    pub fn from_inputs(inputs: Vec<UTXO>, params: &SelectionParams) -> Result<Self, WalletError> {
+        let total: Amount = inputs.iter().map(|utxo| utxo.output.amount).sum();
+        let needed = params.amount + params.fee(inputs.len(), false);
+        if total < needed {
+            return Err(WalletError::InsufficientFunds { needed, available: total });
+        }
+
+        let fee_with_change = params.fee(inputs.len(), true);
+        let change = total.checked_sub(params.amount + fee_with_change)
+            .filter(|change| *change >= params.dust_threshold);
+        let fee = match change {
+            Some(_) => fee_with_change,
+            None => total - params.amount,
+        };
+
+        Ok(Self { inputs, amount: params.amount, fee, change })
+    }
+
+    pub fn total_input(&self) -> Amount {
+        self.inputs.iter().map(|utxo| utxo.output.amount).sum()
+    }
+
+    /// Sign the payment for the chain `domain` names: `amount` to `recipient`,
+    /// change (if any) to `change_address`
+    pub fn into_transaction(self, keypair: &Keypair, recipient: Address, change_address: Address, domain: SigningDomain) -> Result<Transaction, WalletError> {
+        let mut outputs = vec![TransactionOutput::new(self.amount, recipient)];
+        if let Some(change) = self.change {
+            outputs.push(TransactionOutput::new(change, change_address));
+        }
+
+        let mut tx = Transaction::new_utxo(blank_inputs(keypair, self.inputs.iter().map(UTXO::outpoint)), outputs, self.fee);
+        for index in 0..tx.inputs.len() {
+            tx.sign_input(keypair, index, domain).map_err(|_| WalletError::SigningError)?;
+        }
+        Ok(tx)
+    }
+}
+
+
+/// Picks the UTXOs that pay for a transaction
+pub trait CoinSelector {
+    fn select(&self, utxos: &[UTXO], params: &SelectionParams) -> Result<Selection, WalletError>;
+}
+
+
+/// Spend the largest outputs first. Uses few inputs, so fees stay low, but
+/// leaves small outputs to pile up.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestFirst;
+
+impl CoinSelector for LargestFirst {
+    fn select(&self, utxos: &[UTXO], params: &SelectionParams) -> Result<Selection, WalletError> {
+        let mut candidates = spendable(utxos, params);
+        candidates.sort_by(|a, b| b.output.amount.cmp(&a.output.amount));
+
+        let mut inputs = Vec::new();
+        for utxo in candidates {
+            inputs.push(utxo);
+            if let Ok(selection) = Selection::from_inputs(inputs.clone(), params) {
+                return Ok(selection);
+            }
+        }
+        Selection::from_inputs(inputs, params)
+    }
+}
+
+
+/// Search for a set of outputs that pays the amount and fee with so little
+/// left over that no change output is needed, preferring the least excess.
+/// Fails if there is no such set, or none is found within `max_tries` steps.
+#[derive(Debug, Clone, Copy)]
+pub struct BranchAndBound {
+    pub max_tries: usize,
+}
+
+impl Default for BranchAndBound {
+    fn default() -> Self {
+        Self { max_tries: BNB_MAX_TRIES }
+    }
+}
+
+impl CoinSelector for BranchAndBound {
+    fn select(&self, utxos: &[UTXO], params: &SelectionParams) -> Result<Selection, WalletError> {
+        let mut pool: Vec<(UTXO, Amount)> = utxos.iter()
+            .filter_map(|utxo| params.effective_value(utxo).map(|value| (utxo.clone(), value)))
+            .collect();
+        pool.sort_by(|a, b| b.1.cmp(&a.1));
+
+        let values: Vec<Amount> = pool.iter().map(|(_, value)| *value).collect();
+        let mut search = BnbSearch {
+            values: &values,
+            remaining: values.iter().rev()
+                .scan(0, |sum, value| { *sum += value; Some(*sum) })
+                .collect::<Vec<_>>().into_iter().rev().collect(),
+            target: params.target(),
+            upper: params.target() + params.cost_of_change(),
+            tries: self.max_tries,
+            selected: Vec::new(),
+            best: None,
+        };
+        search.explore(0, 0);
+
+        let (_, picked) = search.best
+            .ok_or_else(|| WalletError::CoinSelection("no selection pays the amount without change".to_string()))?;
+        Selection::from_inputs(picked.into_iter().map(|index| pool[index].0.clone()).collect(), params)
+    }
+}
+
+struct BnbSearch<'a> {
+    /// effective values, largest first
+    values: &'a [Amount],
+    /// sum of `values[i..]` at `i`
+    remaining: Vec<Amount>,
+    target: Amount,
+    upper: Amount,
+    tries: usize,
+    selected: Vec<usize>,
+    /// (excess over the target, indices) of the best match so far
+    best: Option<(Amount, Vec<usize>)>,
+}
+
+impl BnbSearch<'_> {
+    fn explore(&mut self, index: usize, value: Amount) {
+        if self.tries == 0 || value > self.upper || self.best.as_ref().is_some_and(|(excess, _)| *excess == 0) {
+            return;
+        }
+        self.tries -= 1;
+
+        if value >= self.target {
+            let excess = value - self.target;
+            if self.best.as_ref().is_none_or(|(best, _)| excess < *best) {
+                self.best = Some((excess, self.selected.clone()));
+            }
+            return;
+        }
+        if index == self.values.len() || value + self.remaining[index] < self.target {
+            return;
+        }
+
+        self.selected.push(index);
+        self.explore(index + 1, value + self.values[index]);
+        self.selected.pop();
+        self.explore(index + 1, value);
+    }
+}
+
+
+/// Pick outputs at random until the payment is covered, then keep adding
+/// random outputs while that brings the total closer to twice the payment,
+/// so change comes out about the size of the payment. Spreads spending over
+/// the wallet's outputs and keeps future payments easy to fund.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomImprove;
+
+impl CoinSelector for RandomImprove {
+    fn select(&self, utxos: &[UTXO], params: &SelectionParams) -> Result<Selection, WalletError> {
+        let mut candidates = spendable(utxos, params);
+        candidates.shuffle(&mut rand::thread_rng());
+
+        let mut inputs = Vec::new();
+        let mut total: Amount = 0;
+        while total < params.amount + params.fee(inputs.len(), true) {
+            let Some(utxo) = candidates.pop() else {
+                return Selection::from_inputs(inputs, params);
+            };
+            total += utxo.output.amount;
+            inputs.push(utxo);
+        }
+
+        let ideal = 2 * params.amount + params.fee(inputs.len(), true);
+        let limit = 3 * params.amount + params.fee(inputs.len(), true);
+        while inputs.len() < MAX_RANDOM_IMPROVE_INPUTS {
+            let Some(utxo) = candidates.pop() else { break };
+            let improved = total + utxo.output.amount;
+            let closer = improved.abs_diff(ideal) < total.abs_diff(ideal);
+            if closer && improved <= limit {
+                total = improved;
+                inputs.push(utxo);
+            }
+        }
+
+        Selection::from_inputs(inputs, params)
+    }
+}
+
+
+/// Try for a changeless payment, and fall back to random-improve
+pub fn select_coins(utxos: &[UTXO], params: &SelectionParams) -> Result<Selection, WalletError> {
+    BranchAndBound::default().select(utxos, params)
+        .or_else(|_| RandomImprove.select(utxos, params))
+}
+
+
+// outputs worth spending at this fee rate
+fn spendable(utxos: &[UTXO], params: &SelectionParams) -> Vec<UTXO> {
+    utxos.iter()
+        .filter(|utxo| params.effective_value(utxo).is_some())
+        .cloned()
+        .collect()
+}
+
+// inputs with a blank signature until signed, which has the same encoded size
+fn blank_inputs(keypair: &Keypair, outpoints: impl IntoIterator<Item = OutPoint>) -> Vec<TransactionInput> {
+    outpoints.into_iter()
+        .map(|outpoint| TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *keypair.public_key()))
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{hash::sha256, AddressType};
+
+    fn keypair_and_recipient() -> (Keypair, Address) {
+        let keypair = Keypair::generate();
+        let recipient = Address::from_public_key(Keypair::generate().public_key(), AddressType::Base58);
+        (keypair, recipient)
+    }
+
+    fn utxos(keypair: &Keypair, amounts: &[Amount]) -> Vec<UTXO> {
+        let address = Address::from_public_key(keypair.public_key(), AddressType::Base58);
+        amounts.iter().enumerate()
+            .map(|(index, amount)| {
+                let tx_id = TxId::new(sha256(&index.to_le_bytes()));
+                UTXO::new(TransactionOutput::new(*amount, address.clone()), 1, tx_id, 0, false)
+            })
+            .collect()
+    }
+
+    // inputs cover the payment, its fee at the final size, and change exactly
+    fn assert_pays(selection: &Selection, params: &SelectionParams) {
+        let change = selection.change.unwrap_or(0);
+        assert_eq!(selection.amount, params.amount);
+        assert_eq!(selection.total_input(), selection.amount + selection.fee + change);
+        assert!(selection.fee >= params.fee(selection.inputs.len(), selection.change.is_some()));
+        if let Some(change) = selection.change {
+            assert!(change >= params.dust_threshold);
+        }
+    }
+
+    #[test]
+    fn test_selectors_never_underpay() {
+        let (keypair, recipient) = keypair_and_recipient();
+        let wallet = utxos(&keypair, &[5_000, 12_000, 700, 40_000, 3_300, 25_000, 9_999, 150]);
+        let selectors: [&dyn CoinSelector; 3] = [&LargestFirst, &BranchAndBound::default(), &RandomImprove];
+
+        for amount in [1_000, 8_000, 20_000, 50_000, 80_000] {
+            for fee_per_byte in [1, 3] {
+                let params = SelectionParams::new(&keypair, recipient.clone(), amount, fee_per_byte);
+                for selector in selectors {
+                    if let Ok(selection) = selector.select(&wallet, &params) {
+                        assert_pays(&selection, &params);
+                    }
+                }
+                assert_pays(&select_coins(&wallet, &params).unwrap(), &params);
+            }
+        }
+    }
+
+    #[test]
+    fn test_signed_transaction_pays_its_own_size() {
+        let (keypair, recipient) = keypair_and_recipient();
+        let wallet = utxos(&keypair, &[5_000, 12_000, 40_000]);
+        let params = SelectionParams::new(&keypair, recipient.clone(), 30_000, 2);
+
+        let selection = LargestFirst.select(&wallet, &params).unwrap();
+        let fee = selection.fee;
+        let change_address = Address::from_public_key(keypair.public_key(), AddressType::Base58);
+        let tx = selection.into_transaction(&keypair, recipient, change_address, SigningDomain::default()).unwrap();
+        assert_eq!(tx.fee, fee);
+        assert!(tx.size() as Amount * params.fee_per_byte <= fee);
+    }
+
+    #[test]
+    fn test_insufficient_funds() {
+        let (keypair, recipient) = keypair_and_recipient();
+        let wallet = utxos(&keypair, &[1_000, 2_000]);
+        let params = SelectionParams::new(&keypair, recipient, 10_000, 1);
+
+        assert!(matches!(LargestFirst.select(&wallet, &params), Err(WalletError::InsufficientFunds { .. })));
+        assert!(select_coins(&wallet, &params).is_err());
+    }
+
+    #[test]
+    fn test_branch_and_bound_finds_changeless_payment() {
+        let (keypair, recipient) = keypair_and_recipient();
+        let params = SelectionParams::new(&keypair, recipient, 10_000, 1);
+        let input_cost = params.input_size as Amount;
+
+        // two outputs that cover the payment exactly once their inputs are paid for
+        let exact = params.amount + params.fee(0, false) + 2 * input_cost;
+        let wallet = utxos(&keypair, &[50_000, exact / 2, exact - exact / 2, 30_000]);
+
+        let selection = BranchAndBound::default().select(&wallet, &params).unwrap();
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.inputs.len(), 2);
+        assert_eq!(selection.fee, params.fee(2, false));
+    }
+
+    #[test]
+    fn test_dust_change_goes_to_the_fee() {
+        let (keypair, recipient) = keypair_and_recipient();
+        let params = SelectionParams::new(&keypair, recipient, 10_000, 1).with_dust_threshold(500);
+        let exact = params.amount + params.fee(1, true);
+
+        let wallet = utxos(&keypair, &[exact + 100]);
+        let selection = Selection::from_inputs(wallet, &params).unwrap();
+        assert_eq!(selection.change, None);
+        assert_eq!(selection.fee, params.fee(1, true) + 100);
+
+        let wallet = utxos(&keypair, &[exact + 500]);
+        assert_eq!(Selection::from_inputs(wallet, &params).unwrap().change, Some(500));
+    }
+
+    #[test]
+    fn test_outputs_not_worth_spending_are_skipped() {
+        let (keypair, recipient) = keypair_and_recipient();
+        let params = SelectionParams::new(&keypair, recipient, 1_000, 10);
+        let dust = params.input_size as Amount * params.fee_per_byte;
+
+        let wallet = utxos(&keypair, &[dust, 50_000]);
+        assert_eq!(params.effective_value(&wallet[0]), None);
+        let selection = LargestFirst.select(&wallet, &params).unwrap();
+        assert_eq!(selection.inputs, vec![wallet[1].clone()]);
+    }
+}
@@ -0,0 +1,221 @@
+use blockchain_crypto::adaptor::{
+    AdaptorPoint, AdaptorSecret, AdaptorSignature,
+    create_adaptor_signature, verify_adaptor_signature, complete_adaptor_signature, extract_adaptor_secret,
+};
+use blockchain_crypto::bip340::{Bip340Keypair, Bip340Signature, XOnlyPublicKey};
+use blockchain_core::{SigningDomain, Transaction};
+use crate::errors::WalletError;
+
+
+/// A payment signature that only becomes valid once the payee reveals a secret.
+///
+/// The payer signs `message` (e.g. a transaction hash) with an adaptor
+/// signature locked to the payee's adaptor point. The payee completes it with
+/// the secret to claim, and publishing the completed signature hands the
+/// secret to the payer.
+///
+/// On chain the payer's funds sit in a Schnorr key output
+/// (`TransactionOutput::schnorr_key`), and the locked message is the signing
+/// hash of the transaction spending it to the payee: the completed signature
+/// is what the validator checks for that input.
+#[derive(Debug, Clone)]
+pub struct ConditionalPayment {
+    pub message: Vec<u8>,
+    pub payer: XOnlyPublicKey,
+    pub adaptor_signature: AdaptorSignature,
+}
+
+
+impl ConditionalPayment {
+    /// Payer: lock a payment to `condition`
+    pub fn offer(payer: &Bip340Keypair, message: &[u8], condition: &AdaptorPoint) -> Result<Self, WalletError> {
+        let adaptor_signature = create_adaptor_signature(payer, message, condition)
+            .map_err(|e| WalletError::AdaptorSignature(e.to_string()))?;
+
+        Ok(Self {
+            message: message.to_vec(),
+            payer: *payer.public_key(),
+            adaptor_signature,
+        })
+    }
+
+    /// Payer: offer `tx`, which spends our Schnorr key output to the payee,
+    /// so that it only becomes valid once the payee knows the secret
+    pub fn offer_transaction(payer: &Bip340Keypair, tx: &Transaction, domain: SigningDomain, condition: &AdaptorPoint) -> Result<Self, WalletError> {
+        Self::offer(payer, tx.signing_hash(domain).as_bytes(), condition)
+    }
+
+    /// The point the payment is locked to
+    pub fn condition(&self) -> &AdaptorPoint {
+        &self.adaptor_signature.adaptor_point
+    }
+
+    /// Payee: check the offer turns into a valid payer signature once the secret is known
+    pub fn verify(&self) -> Result<(), WalletError> {
+        verify_adaptor_signature(&self.payer, &self.message, &self.adaptor_signature)
+            .map_err(|e| WalletError::AdaptorSignature(e.to_string()))
+    }
+
+    /// Payee: claim the payment. Publishing the result reveals the secret.
+    pub fn claim(&self, secret: &AdaptorSecret) -> Result<Bip340Signature, WalletError> {
+        complete_adaptor_signature(&self.adaptor_signature, secret)
+            .map_err(|e| WalletError::AdaptorSignature(e.to_string()))
+    }
+
+    /// Payer: recover the secret from the payee's published claim
+    pub fn learn_secret(&self, claim: &Bip340Signature) -> Result<AdaptorSecret, WalletError> {
+        extract_adaptor_secret(&self.adaptor_signature, claim)
+            .map_err(|e| WalletError::AdaptorSignature(e.to_string()))
+    }
+
+    /// Payee: claim an offered transaction by signing input `input_index`,
+    /// which spends the payer's Schnorr key output. Broadcasting `tx` reveals the secret
+    pub fn claim_transaction(&self, tx: &mut Transaction, input_index: usize, secret: &AdaptorSecret) -> Result<Bip340Signature, WalletError> {
+        let input = tx.inputs.get_mut(input_index)
+            .ok_or_else(|| WalletError::AdaptorSignature(format!("transaction has no input {}", input_index)))?;
+        let claim = self.claim(secret)?;
+        input.set_schnorr_signature(&claim);
+        Ok(claim)
+    }
+
+    /// Payer: recover the secret from the claiming transaction once it is published
+    pub fn learn_secret_from_transaction(&self, tx: &Transaction, input_index: usize) -> Result<AdaptorSecret, WalletError> {
+        let claim = tx.inputs.get(input_index)
+            .ok_or_else(|| WalletError::AdaptorSignature(format!("transaction has no input {}", input_index)))?
+            .schnorr_signature()
+            .map_err(|e| WalletError::AdaptorSignature(e.to_string()))?;
+        self.learn_secret(&claim)
+    }
+}
+
+
+/// Two conditional payments locked to the same point, one in each direction.
+///
+/// The initiator holds the secret. Claiming `incoming` reveals it, which lets
+/// the counterparty claim `outgoing`, so either both payments go through or neither does.
+#[derive(Debug, Clone)]
+pub struct AtomicSwap {
+    /// payment we make to the counterparty
+    pub outgoing: ConditionalPayment,
+    /// payment the counterparty makes to us
+    pub incoming: ConditionalPayment,
+}
+
+
+impl AtomicSwap {
+    /// Pair our offer with the counterparty's, checking both are locked to the same point
+    pub fn new(outgoing: ConditionalPayment, incoming: ConditionalPayment) -> Result<Self, WalletError> {
+        if outgoing.condition() != incoming.condition() {
+            return Err(WalletError::AdaptorSignature("swap legs use different conditions".to_string()));
+        }
+        incoming.verify()?;
+
+        Ok(Self { outgoing, incoming })
+    }
+
+    /// Initiator: claim the incoming payment with the secret
+    pub fn claim_with_secret(&self, secret: &AdaptorSecret) -> Result<Bip340Signature, WalletError> {
+        self.incoming.claim(secret)
+    }
+
+    /// Counterparty: once the initiator's claim on our outgoing payment is public,
+    /// extract the secret and claim the incoming payment with it
+    pub fn claim_after_reveal(&self, revealed: &Bip340Signature) -> Result<Bip340Signature, WalletError> {
+        let secret = self.outgoing.learn_secret(revealed)?;
+        self.incoming.claim(&secret)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::{OutPoint, TransactionInput, TransactionOutput, TxId, UTXOSet, UTXO};
+    use blockchain_crypto::{hash::sha256, signature::generate_keypair, Signature};
+
+    fn domain() -> SigningDomain {
+        SigningDomain::new(1, 0)
+    }
+
+    // The payer's Schnorr key output and a transaction paying it on to the payee
+    fn funded_spend(payer: &Bip340Keypair) -> (UTXOSet, Transaction) {
+        let output = TransactionOutput::schnorr_key(1000, *payer.public_key());
+        let outpoint = OutPoint::new(TxId::new(sha256(b"funding tx")), 0);
+        let mut utxo_set = UTXOSet::new();
+        utxo_set.add_utxo(outpoint, UTXO::new(output.clone(), 1, outpoint.tx_id, 0, false)).unwrap();
+
+        let payee = generate_keypair();
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *payee.public_key());
+        let tx = Transaction::new_utxo(vec![input], vec![TransactionOutput::new(900, output.address.clone())], 100);
+        (utxo_set, tx)
+    }
+
+    #[test]
+    fn test_offer_claim_learn_secret() {
+        let payer = Bip340Keypair::generate();
+        let secret = AdaptorSecret::generate();
+
+        let payment = ConditionalPayment::offer(&payer, b"invoice 7", &secret.adaptor_point()).unwrap();
+        payment.verify().unwrap();
+
+        let claim = payment.claim(&secret).unwrap();
+        assert!(payer.public_key().verify(b"invoice 7", &claim));
+        assert_eq!(payment.learn_secret(&claim).unwrap(), secret);
+
+        // the wrong secret doesn't complete the signature
+        assert!(payment.claim(&AdaptorSecret::generate()).is_err());
+    }
+
+    #[test]
+    fn test_claimed_transaction_spends_on_chain() {
+        let payer = Bip340Keypair::generate();
+        let secret = AdaptorSecret::generate();
+        let (utxo_set, mut tx) = funded_spend(&payer);
+
+        let payment = ConditionalPayment::offer_transaction(&payer, &tx, domain(), &secret.adaptor_point()).unwrap();
+        payment.verify().unwrap();
+
+        // the adaptor signature alone doesn't authorize the spend
+        let presignature = Bip340Signature { r: payment.adaptor_signature.r, s: payment.adaptor_signature.s };
+        tx.inputs[0].set_schnorr_signature(&presignature);
+        assert!(!tx.verify_signatures(&utxo_set, domain()).unwrap());
+
+        payment.claim_transaction(&mut tx, 0, &secret).unwrap();
+        assert!(tx.verify_signatures(&utxo_set, domain()).unwrap());
+
+        // the payer reads the secret off the published transaction
+        assert_eq!(payment.learn_secret_from_transaction(&tx, 0).unwrap(), secret);
+        assert!(payment.claim_transaction(&mut tx, 1, &secret).is_err());
+    }
+
+    #[test]
+    fn test_atomic_swap() {
+        let alice = Bip340Keypair::generate();
+        let bob = Bip340Keypair::generate();
+        let secret = AdaptorSecret::generate();
+        let condition = secret.adaptor_point();
+
+        let alice_pays = ConditionalPayment::offer(&alice, b"alice pays bob", &condition).unwrap();
+        let bob_pays = ConditionalPayment::offer(&bob, b"bob pays alice", &condition).unwrap();
+
+        // Alice holds the secret and claims first
+        let alice_swap = AtomicSwap::new(alice_pays.clone(), bob_pays.clone()).unwrap();
+        let alice_claim = alice_swap.claim_with_secret(&secret).unwrap();
+        assert!(bob.public_key().verify(b"bob pays alice", &alice_claim));
+
+        // which lets Bob claim his side without ever being told the secret
+        let bob_swap = AtomicSwap::new(bob_pays, alice_pays).unwrap();
+        let bob_claim = bob_swap.claim_after_reveal(&alice_claim).unwrap();
+        assert!(alice.public_key().verify(b"alice pays bob", &bob_claim));
+    }
+
+    #[test]
+    fn test_swap_legs_must_share_a_condition() {
+        let alice = Bip340Keypair::generate();
+        let bob = Bip340Keypair::generate();
+        let outgoing = ConditionalPayment::offer(&alice, b"out", &AdaptorSecret::generate().adaptor_point()).unwrap();
+        let incoming = ConditionalPayment::offer(&bob, b"in", &AdaptorSecret::generate().adaptor_point()).unwrap();
+
+        assert!(AtomicSwap::new(outgoing, incoming).is_err());
+    }
+}
@@ -8,4 +8,36 @@ pub enum WalletError{
     SigningError,
     #[error("serialization error")]
     SerializationError,
+    #[error("adaptor signature error: {0}")]
+    AdaptorSignature(String),
+    #[error("keystore already exists: {0}")]
+    KeystoreExists(String),
+    #[error("keystore is locked")]
+    KeystoreLocked,
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+    #[error("keystore corrupt: {0}")]
+    KeystoreCorrupt(String),
+    #[error("no key for public key {0}")]
+    KeyNotFound(String),
+    #[error("io error: {0}")]
+    Io(String),
+    #[error("unsupported key format: {0}")]
+    UnsupportedKeyFormat(String),
+    #[error("nothing to sweep from {0}")]
+    NothingToSweep(String),
+    #[error("sweep failed: {0}")]
+    Sweep(String),
+    #[error("multisig: {0}")]
+    Multisig(String),
+    #[error("time lock: {0}")]
+    TimeLock(String),
+    #[error("insufficient funds: need {needed}, have {available}")]
+    InsufficientFunds { needed: u64, available: u64 },
+    #[error("coin selection: {0}")]
+    CoinSelection(String),
+    #[error("nonce: {0}")]
+    Nonce(String),
+    #[error("address book: {0}")]
+    AddressBook(String),
 }
@@ -0,0 +1,406 @@
+use blockchain_core::{Amount, Block, BlockHeight, BlockId, ChainEvent, OutPoint, Transaction, TxId};
+use blockchain_crypto::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+
+/// Balance of one address. Pending amounts come from mempool transactions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub confirmed: Amount,
+    pub pending_incoming: Amount,
+    pub pending_outgoing: Amount,
+}
+
+impl Balance {
+    /// Balance once every pending transaction confirms
+    pub fn unconfirmed(&self) -> Amount {
+        (self.confirmed + self.pending_incoming).saturating_sub(self.pending_outgoing)
+    }
+}
+
+
+/// A wallet transaction as shown in its history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub tx_id: TxId,
+    /// block the transaction confirmed in; None while it is in the mempool
+    pub block: Option<(BlockHeight, BlockId)>,
+    /// 0 while pending, 1 once in the tip block, and so on
+    pub confirmations: u64,
+    /// paid to the wallet's addresses
+    pub received: Amount,
+    /// spent from the wallet's addresses, fees included
+    pub sent: Amount,
+}
+
+
+/// What changed for the wallet on a chain event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletEvent {
+    /// a transaction paying one of the wallet's addresses was seen for the
+    /// first time, in the mempool (height None) or in a block
+    PaymentReceived { tx_id: TxId, address: Address, amount: Amount, height: Option<BlockHeight> },
+    /// a wallet transaction made it into a block
+    Confirmed { tx_id: TxId, height: BlockHeight },
+    /// a wallet transaction's block was disconnected; it is pending again
+    Unconfirmed { tx_id: TxId },
+    /// a pending wallet transaction left the mempool without confirming
+    Dropped { tx_id: TxId },
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WalletTx {
+    block: Option<(BlockHeight, BlockId)>,
+    /// (address, received, sent) for each wallet address the transaction touches
+    effects: Vec<(Address, Amount, Amount)>,
+    /// wallet outputs the transaction creates
+    outputs: Vec<OutPoint>,
+}
+
+
+/// Transaction history and balances of a set of addresses.
+///
+/// Feed it the node's `ChainEvent`s with `apply_event`, or blocks fetched
+/// over RPC with `connect_block`, in chain order. A saved wallet (it is
+/// serde-serializable) resumes from its `tip`; a new one rescans from the
+/// height its addresses were created.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Wallet {
+    addresses: HashSet<Address>,
+    tip: Option<BlockHeight>,
+    transactions: HashMap<TxId, WalletTx>,
+    /// outputs paying the wallet, so spends of them can be recognized
+    owned: HashMap<OutPoint, (Address, Amount)>,
+}
+
+impl Wallet {
+    pub fn new(addresses: impl IntoIterator<Item = Address>) -> Self {
+        Self {
+            addresses: addresses.into_iter().collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Track another address. Only transactions seen from now on count.
+    pub fn watch(&mut self, address: Address) {
+        self.addresses.insert(address);
+    }
+
+    /// Height of the last block connected
+    pub fn tip(&self) -> Option<BlockHeight> {
+        self.tip
+    }
+
+    pub fn apply_event(&mut self, event: &ChainEvent) -> Vec<WalletEvent> {
+        match event {
+            ChainEvent::BlockConnected(block) => self.connect_block(block),
+            ChainEvent::BlockDisconnected(block) => self.disconnect_block(block),
+            ChainEvent::TxAdded(tx) => self.add_pending(tx),
+            ChainEvent::TxDropped { tx_id, .. } => self.drop_pending(tx_id),
+            ChainEvent::DoubleSpend(_) => Vec::new(),
+        }
+    }
+
+    /// Record the wallet transactions of a block joining the main chain
+    pub fn connect_block(&mut self, block: &Block) -> Vec<WalletEvent> {
+        let height = block.height();
+        let mut events = Vec::new();
+        for tx in block.transactions() {
+            let tx_id = tx.id();
+            if let Some(known) = self.transactions.get_mut(&tx_id) {
+                known.block = Some((height, block.id()));
+                events.push(WalletEvent::Confirmed { tx_id, height });
+            } else if self.track(tx, Some((height, block.id())), &mut events) {
+                events.push(WalletEvent::Confirmed { tx_id, height });
+            }
+        }
+        self.tip = Some(height);
+        events
+    }
+
+    /// Return the transactions of a disconnected block to pending. The chain
+    /// drops those that are no longer valid with `TxDropped` events.
+    pub fn disconnect_block(&mut self, block: &Block) -> Vec<WalletEvent> {
+        let mut events = Vec::new();
+        for tx in block.transactions() {
+            let tx_id = tx.id();
+            if tx.is_coinbase() {
+                // a coinbase can't go back to the mempool
+                events.extend(self.forget(&tx_id).map(|_| WalletEvent::Dropped { tx_id }));
+            } else if let Some(known) = self.transactions.get_mut(&tx_id) {
+                known.block = None;
+                events.push(WalletEvent::Unconfirmed { tx_id });
+            }
+        }
+        self.tip = block.height().checked_sub(1);
+        events
+    }
+
+    /// Record a wallet transaction entering the mempool
+    pub fn add_pending(&mut self, tx: &Transaction) -> Vec<WalletEvent> {
+        let mut events = Vec::new();
+        if !self.transactions.contains_key(&tx.id()) {
+            self.track(tx, None, &mut events);
+        }
+        events
+    }
+
+    /// Forget a pending transaction that left the mempool
+    pub fn drop_pending(&mut self, tx_id: &TxId) -> Vec<WalletEvent> {
+        if self.transactions.get(tx_id).is_some_and(|tx| tx.block.is_none()) {
+            self.forget(tx_id);
+            return vec![WalletEvent::Dropped { tx_id: *tx_id }];
+        }
+        Vec::new()
+    }
+
+    /// Balance of one address
+    pub fn balance(&self, address: &Address) -> Balance {
+        let mut balance = Balance::default();
+        let (mut confirmed_in, mut confirmed_out) = (0, 0);
+        for tx in self.transactions.values() {
+            for (_, received, sent) in tx.effects.iter().filter(|(effect_address, ..)| effect_address == address) {
+                match tx.block {
+                    Some(_) => {
+                        confirmed_in += received;
+                        confirmed_out += sent;
+                    }
+                    None => {
+                        balance.pending_incoming += received;
+                        balance.pending_outgoing += sent;
+                    }
+                }
+            }
+        }
+        balance.confirmed = confirmed_in.saturating_sub(confirmed_out);
+        balance
+    }
+
+    /// Balance of every tracked address
+    pub fn balances(&self) -> HashMap<Address, Balance> {
+        self.addresses.iter()
+            .map(|address| (address.clone(), self.balance(address)))
+            .collect()
+    }
+
+    /// Wallet transactions, pending first, then newest block first
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        let mut entries: Vec<HistoryEntry> = self.transactions.iter()
+            .map(|(tx_id, tx)| HistoryEntry {
+                tx_id: *tx_id,
+                block: tx.block,
+                confirmations: match (tx.block, self.tip) {
+                    (Some((height, _)), Some(tip)) => (tip + 1).saturating_sub(height),
+                    _ => 0,
+                },
+                received: tx.effects.iter().map(|(_, received, _)| received).sum(),
+                sent: tx.effects.iter().map(|(_, _, sent)| sent).sum(),
+            })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.block.map_or(BlockHeight::MAX, |(height, _)| height)));
+        entries
+    }
+
+    // Keep a transaction that touches the wallet; false if it doesn't
+    fn track(&mut self, tx: &Transaction, block: Option<(BlockHeight, BlockId)>, events: &mut Vec<WalletEvent>) -> bool {
+        let tx_id = tx.id();
+        let mut effects: Vec<(Address, Amount, Amount)> = Vec::new();
+        let mut add = |address: &Address, received: Amount, sent: Amount| {
+            match effects.iter_mut().find(|(effect_address, ..)| effect_address == address) {
+                Some(effect) => {
+                    effect.1 += received;
+                    effect.2 += sent;
+                }
+                None => effects.push((address.clone(), received, sent)),
+            }
+        };
+
+        for input in &tx.inputs {
+            if let Some((address, amount)) = self.owned.get(&input.prev_output) {
+                add(address, 0, *amount);
+            }
+        }
+        let mut outputs = Vec::new();
+        for (index, output) in tx.outputs.iter().enumerate() {
+            if self.addresses.contains(&output.address) {
+                add(&output.address, output.amount, 0);
+                outputs.push((OutPoint::new(tx_id, index as u32), output.address.clone(), output.amount));
+            }
+        }
+        if let Some(from) = tx.from.as_ref().filter(|from| self.addresses.contains(*from)) {
+            add(from, 0, tx.amount.unwrap_or(0) + tx.calculate_gas_fee());
+        }
+        if let (Some(to), Some(amount)) = (tx.to.as_ref().filter(|to| self.addresses.contains(*to)), tx.amount) {
+            add(to, amount, 0);
+        }
+
+        if effects.is_empty() {
+            return false;
+        }
+        for (address, received, _) in &effects {
+            if *received > 0 {
+                events.push(WalletEvent::PaymentReceived {
+                    tx_id,
+                    address: address.clone(),
+                    amount: *received,
+                    height: block.map(|(height, _)| height),
+                });
+            }
+        }
+        for (outpoint, address, amount) in &outputs {
+            self.owned.insert(*outpoint, (address.clone(), *amount));
+        }
+        self.transactions.insert(tx_id, WalletTx {
+            block,
+            effects,
+            outputs: outputs.into_iter().map(|(outpoint, ..)| outpoint).collect(),
+        });
+        true
+    }
+
+    fn forget(&mut self, tx_id: &TxId) -> Option<WalletTx> {
+        let tx = self.transactions.remove(tx_id)?;
+        for outpoint in &tx.outputs {
+            self.owned.remove(outpoint);
+        }
+        Some(tx)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::{TransactionInput, TransactionOutput, TxDropReason};
+    use blockchain_crypto::{signature::Keypair, AddressType, Signature};
+    use std::sync::Arc;
+
+    fn address(keypair: &Keypair) -> Address {
+        Address::from_public_key(keypair.public_key(), AddressType::Base58)
+    }
+
+    fn block(prev: &Block, transactions: Vec<Transaction>) -> Block {
+        Block::new(prev.id(), transactions, 1, prev.height() + 1, 1).unwrap()
+    }
+
+    // spend `outpoint` worth `total`: `amount` to `to`, `change` back to `from`, the rest as fee
+    fn spend(owner: &Keypair, outpoint: OutPoint, to: &Address, amount: Amount, change: Amount, fee: Amount) -> Transaction {
+        let input = TransactionInput::new(outpoint, Signature::from_bytes([0u8; 64]), *owner.public_key());
+        let outputs = vec![TransactionOutput::new(amount, to.clone()), TransactionOutput::new(change, address(owner))];
+        Transaction::new_utxo(vec![input], outputs, fee)
+    }
+
+    struct Fixture {
+        owner: Keypair,
+        wallet: Wallet,
+        genesis: Block,
+        block1: Block,
+        coinbase: TxId,
+    }
+
+    // a wallet whose address mined block 1
+    fn fixture() -> Fixture {
+        let owner = Keypair::generate();
+        let miner = address(&owner);
+        let genesis = Block::genesis(1, address(&Keypair::generate()), 50).unwrap();
+        let coinbase = Transaction::new_coinbase(miner.clone(), 50, 1);
+        let block1 = block(&genesis, vec![coinbase.clone()]);
+
+        let mut wallet = Wallet::new([miner]);
+        wallet.connect_block(&genesis);
+        wallet.connect_block(&block1);
+        Fixture { owner, wallet, genesis, block1, coinbase: coinbase.id() }
+    }
+
+    #[test]
+    fn test_received_payment_confirms() {
+        let owner = Keypair::generate();
+        let miner = address(&owner);
+        let genesis = Block::genesis(1, address(&Keypair::generate()), 50).unwrap();
+        let coinbase = Transaction::new_coinbase(miner.clone(), 50, 1);
+        let block1 = block(&genesis, vec![coinbase.clone()]);
+
+        let mut wallet = Wallet::new([miner.clone()]);
+        assert!(wallet.connect_block(&genesis).is_empty());
+        assert_eq!(wallet.connect_block(&block1), vec![
+            WalletEvent::PaymentReceived { tx_id: coinbase.id(), address: miner.clone(), amount: 50, height: Some(1) },
+            WalletEvent::Confirmed { tx_id: coinbase.id(), height: 1 },
+        ]);
+        assert_eq!(wallet.tip(), Some(1));
+        assert_eq!(wallet.balance(&miner), Balance { confirmed: 50, ..Balance::default() });
+    }
+
+    #[test]
+    fn test_pending_spend_and_its_confirmation() {
+        let Fixture { owner, mut wallet, block1, coinbase, .. } = fixture();
+        let me = address(&owner);
+        let payee = address(&Keypair::generate());
+
+        let tx = spend(&owner, OutPoint::new(coinbase, 0), &payee, 30, 15, 5);
+        assert_eq!(wallet.add_pending(&tx), vec![
+            WalletEvent::PaymentReceived { tx_id: tx.id(), address: me.clone(), amount: 15, height: None },
+        ]);
+        let balance = wallet.balance(&me);
+        assert_eq!(balance, Balance { confirmed: 50, pending_incoming: 15, pending_outgoing: 50 });
+        assert_eq!(balance.unconfirmed(), 15);
+
+        // seen again in a block: confirmed, not received twice
+        let block2 = block(&block1, vec![tx.clone()]);
+        assert_eq!(wallet.connect_block(&block2), vec![WalletEvent::Confirmed { tx_id: tx.id(), height: 2 }]);
+        assert_eq!(wallet.balance(&me), Balance { confirmed: 15, ..Balance::default() });
+
+        let history = wallet.history();
+        assert_eq!(history.iter().map(|entry| (entry.tx_id, entry.confirmations)).collect::<Vec<_>>(),
+            vec![(tx.id(), 1), (coinbase, 2)]);
+        assert_eq!((history[0].received, history[0].sent), (15, 50));
+    }
+
+    #[test]
+    fn test_reorg_returns_transactions_to_pending() {
+        let Fixture { owner, mut wallet, genesis, block1, coinbase } = fixture();
+        let me = address(&owner);
+        let tx = spend(&owner, OutPoint::new(coinbase, 0), &address(&Keypair::generate()), 30, 15, 5);
+        let block2 = block(&block1, vec![tx.clone()]);
+        wallet.connect_block(&block2);
+
+        assert_eq!(wallet.disconnect_block(&block2), vec![WalletEvent::Unconfirmed { tx_id: tx.id() }]);
+        assert_eq!(wallet.tip(), Some(1));
+        assert_eq!(wallet.history()[0].block, None);
+        assert_eq!(wallet.balance(&me).pending_outgoing, 50);
+
+        // the coinbase can't go back to the mempool
+        assert_eq!(wallet.disconnect_block(&block1), vec![WalletEvent::Dropped { tx_id: coinbase }]);
+        assert_eq!(wallet.tip(), Some(genesis.height()));
+        assert_eq!(wallet.balance(&me).confirmed, 0);
+    }
+
+    #[test]
+    fn test_dropped_pending_transaction_is_forgotten() {
+        let Fixture { owner, mut wallet, coinbase, .. } = fixture();
+        let tx = spend(&owner, OutPoint::new(coinbase, 0), &address(&Keypair::generate()), 30, 15, 5);
+        wallet.add_pending(&tx);
+
+        let dropped = ChainEvent::TxDropped { tx_id: tx.id(), reason: TxDropReason::Evicted };
+        assert_eq!(wallet.apply_event(&dropped), vec![WalletEvent::Dropped { tx_id: tx.id() }]);
+        assert_eq!(wallet.history().len(), 1);
+
+        // confirmed transactions aren't dropped
+        assert!(wallet.drop_pending(&coinbase).is_empty());
+    }
+
+    #[test]
+    fn test_account_transfer_counts_amount_and_gas() {
+        let sender = Keypair::generate();
+        let recipient = Keypair::generate();
+        let mut wallet = Wallet::new([address(&sender), address(&recipient)]);
+
+        let tx = Transaction::new_account(address(&sender), address(&recipient), 100, 0, 21000, 2, Vec::new());
+        wallet.apply_event(&ChainEvent::TxAdded(Arc::new(tx)));
+
+        assert_eq!(wallet.balance(&address(&sender)).pending_outgoing, 100 + 42_000);
+        assert_eq!(wallet.balance(&address(&recipient)).pending_incoming, 100);
+        assert_eq!(wallet.balances().len(), 2);
+    }
+}
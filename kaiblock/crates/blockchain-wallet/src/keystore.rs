@@ -0,0 +1,363 @@
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use blockchain_core::transaction::{SigningDomain, Transaction};
+use blockchain_crypto::{signature::{Keypair, MessageSignature}, Address, AddressType};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use serde::{Serialize, Deserialize};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+use crate::errors::WalletError;
+
+
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+/// Encrypted under the derived key so a wrong passphrase is caught even with no keys stored
+const CHECK_PLAINTEXT: &[u8] = b"kaiblock-keystore";
+
+
+/// Argon2id parameters, stored in the file so they can be raised later
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    pub salt: String,
+}
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self {
+            // OWASP minimum for argon2id
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+            salt: hex::encode(salt),
+        }
+    }
+
+    fn derive_key(&self, passphrase: &str) -> Result<Zeroizing<[u8; 32]>, WalletError> {
+        let salt = hex::decode(&self.salt).map_err(|_| WalletError::KeystoreCorrupt("bad salt".to_string()))?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| WalletError::KeystoreCorrupt(e.to_string()))?;
+
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, key.as_mut())
+            .map_err(|e| WalletError::KeystoreCorrupt(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+
+/// AES-256-GCM ciphertext with its nonce
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedData {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+
+/// One stored key. The address is bound to the ciphertext as associated data.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct KeystoreEntry {
+    pub address: String,
+    pub public_key: String,
+    pub secret: EncryptedData,
+}
+
+
+/// On-disk keystore layout (JSON)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeystoreFile {
+    pub version: u32,
+    pub kdf: KdfParams,
+    pub check: EncryptedData,
+    pub keys: Vec<KeystoreEntry>,
+}
+
+
+/// Passphrase-encrypted key storage.
+///
+/// Private keys are encrypted with AES-256-GCM under a key derived from the
+/// passphrase with Argon2id. Decrypted keys only live in memory while the
+/// keystore is unlocked.
+pub struct Keystore {
+    path: PathBuf,
+    file: KeystoreFile,
+    /// derived encryption key, present while unlocked
+    key: Option<Zeroizing<[u8; 32]>>,
+    /// decrypted keypairs, in file order, present while unlocked
+    keypairs: Vec<Keypair>,
+}
+
+impl Keystore {
+    /// Create an empty keystore at `path`. Fails if the file already exists.
+    pub fn create(path: impl AsRef<Path>, passphrase: &str) -> Result<Self, WalletError> {
+        let path = path.as_ref().to_path_buf();
+        if path.exists() {
+            return Err(WalletError::KeystoreExists(path.display().to_string()));
+        }
+
+        let kdf = KdfParams::generate();
+        let key = kdf.derive_key(passphrase)?;
+        let check = encrypt(&key, CHECK_PLAINTEXT, b"")?;
+
+        let keystore = Self {
+            path,
+            file: KeystoreFile { version: KEYSTORE_VERSION, kdf, check, keys: Vec::new() },
+            key: Some(key),
+            keypairs: Vec::new(),
+        };
+        keystore.save()?;
+        Ok(keystore)
+    }
+
+    /// Load a keystore without decrypting anything
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, WalletError> {
+        let path = path.as_ref().to_path_buf();
+        let data = std::fs::read(&path).map_err(|e| WalletError::Io(e.to_string()))?;
+        let file: KeystoreFile = serde_json::from_slice(&data)
+            .map_err(|e| WalletError::KeystoreCorrupt(e.to_string()))?;
+
+        if file.version != KEYSTORE_VERSION {
+            return Err(WalletError::KeystoreCorrupt(format!("unsupported version {}", file.version)));
+        }
+
+        Ok(Self { path, file, key: None, keypairs: Vec::new() })
+    }
+
+    /// Load a keystore and decrypt its keys with `passphrase`
+    pub fn unlock(path: impl AsRef<Path>, passphrase: &str) -> Result<Self, WalletError> {
+        let mut keystore = Self::open(path)?;
+        keystore.unlock_with(passphrase)?;
+        Ok(keystore)
+    }
+
+    /// Decrypt the keys of an opened keystore
+    pub fn unlock_with(&mut self, passphrase: &str) -> Result<(), WalletError> {
+        let key = self.file.kdf.derive_key(passphrase)?;
+        let check = decrypt(&key, &self.file.check, b"").map_err(|_| WalletError::WrongPassphrase)?;
+        if check.as_slice() != CHECK_PLAINTEXT {
+            return Err(WalletError::WrongPassphrase);
+        }
+
+        let mut keypairs = Vec::with_capacity(self.file.keys.len());
+        for entry in &self.file.keys {
+            let secret = decrypt(&key, &entry.secret, entry.address.as_bytes())?;
+            let keypair = Keypair::from_private_bytes(&secret).map_err(|_| WalletError::InvalidKey)?;
+            if keypair.export_public_key() != entry.public_key {
+                return Err(WalletError::KeystoreCorrupt(format!("key mismatch for {}", entry.address)));
+            }
+            keypairs.push(keypair);
+        }
+
+        self.key = Some(key);
+        self.keypairs = keypairs;
+        Ok(())
+    }
+
+    /// Forget the decrypted keys
+    pub fn lock(&mut self) {
+        self.key = None;
+        self.keypairs.clear();
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.is_some()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Addresses of the stored keys (available while locked)
+    pub fn addresses(&self) -> Vec<String> {
+        self.file.keys.iter().map(|entry| entry.address.clone()).collect()
+    }
+
+    /// Generate a new key, store it and return its address
+    pub fn generate_key(&mut self) -> Result<Address, WalletError> {
+        self.import_key(Keypair::generate())
+    }
+
+    /// Store an existing key and return its address
+    pub fn import_key(&mut self, keypair: Keypair) -> Result<Address, WalletError> {
+        let key = self.key.as_ref().ok_or(WalletError::KeystoreLocked)?;
+
+        let address = Address::from_public_key(&keypair.public_key(), AddressType::Base58);
+        let encoded = address.encoded().to_string();
+        if self.file.keys.iter().any(|entry| entry.address == encoded) {
+            return Ok(address);
+        }
+
+        let secret = Zeroizing::new(keypair.private_key_bytes());
+        let entry = KeystoreEntry {
+            address: encoded.clone(),
+            public_key: keypair.export_public_key(),
+            secret: encrypt(key, secret.as_ref(), encoded.as_bytes())?,
+        };
+
+        self.file.keys.push(entry);
+        self.keypairs.push(keypair);
+        if let Err(e) = self.save() {
+            self.file.keys.pop();
+            self.keypairs.pop();
+            return Err(e);
+        }
+        Ok(address)
+    }
+
+    /// Sign every input of `tx` with the matching stored key, for the chain
+    /// `domain` names. Fails without signing anything if an input's key isn't
+    /// in the keystore.
+    pub fn sign_transaction(&self, tx: &mut Transaction, domain: SigningDomain) -> Result<usize, WalletError> {
+        if !self.is_unlocked() {
+            return Err(WalletError::KeystoreLocked);
+        }
+
+        let signers = tx.inputs.iter()
+            .map(|input| {
+                self.keypairs.iter()
+                    .find(|keypair| keypair.public_key() == input.public_key)
+                    .ok_or(WalletError::KeyNotFound(input.public_key.to_hex()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (index, keypair) in signers.into_iter().enumerate() {
+            tx.sign_input(keypair, index, domain).map_err(|_| WalletError::SigningError)?;
+        }
+        Ok(tx.inputs.len())
+    }
+
+    /// Sign a message with the key behind `address`, proving ownership of it.
+    /// Message signatures are domain-separated and can't authorize a transaction.
+    pub fn sign_message(&self, address: &str, message: &[u8]) -> Result<MessageSignature, WalletError> {
+        Ok(MessageSignature::sign(self.keypair(address)?, message))
+    }
+
+    /// The unlocked key behind `address`, e.g. to build a payment from it
+    pub fn keypair(&self, address: &str) -> Result<&Keypair, WalletError> {
+        if !self.is_unlocked() {
+            return Err(WalletError::KeystoreLocked);
+        }
+
+        self.keypairs.iter()
+            .find(|keypair| Address::from_public_key(&keypair.public_key(), AddressType::Base58).encoded() == address)
+            .ok_or_else(|| WalletError::KeyNotFound(address.to_string()))
+    }
+
+    // Write to a temp file then rename so a crash never leaves a half-written keystore
+    fn save(&self) -> Result<(), WalletError> {
+        let data = serde_json::to_vec_pretty(&self.file).map_err(|_| WalletError::SerializationError)?;
+        let tmp = self.path.with_extension("tmp");
+
+        std::fs::write(&tmp, data).map_err(|e| WalletError::Io(e.to_string()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| WalletError::Io(e.to_string()))?;
+        }
+        std::fs::rename(&tmp, &self.path).map_err(|e| WalletError::Io(e.to_string()))
+    }
+}
+
+
+fn encrypt(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<EncryptedData, WalletError> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| WalletError::InvalidKey)?;
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| WalletError::KeystoreCorrupt("encryption failed".to_string()))?;
+
+    Ok(EncryptedData { nonce: hex::encode(nonce), ciphertext: hex::encode(ciphertext) })
+}
+
+fn decrypt(key: &[u8; 32], data: &EncryptedData, aad: &[u8]) -> Result<Zeroizing<Vec<u8>>, WalletError> {
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| WalletError::InvalidKey)?;
+    let nonce = hex::decode(&data.nonce).map_err(|_| WalletError::KeystoreCorrupt("bad nonce".to_string()))?;
+    let ciphertext = hex::decode(&data.ciphertext)
+        .map_err(|_| WalletError::KeystoreCorrupt("bad ciphertext".to_string()))?;
+    if nonce.len() != NONCE_LEN {
+        return Err(WalletError::KeystoreCorrupt("bad nonce".to_string()));
+    }
+
+    cipher.decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad })
+        .map(Zeroizing::new)
+        .map_err(|_| WalletError::KeystoreCorrupt("decryption failed".to_string()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_decrypt_round_trip() {
+        let key = [7u8; 32];
+        let data = encrypt(&key, b"secret", b"address").unwrap();
+        assert_eq!(decrypt(&key, &data, b"address").unwrap().as_slice(), b"secret");
+
+        // a fresh nonce per encryption
+        assert_ne!(encrypt(&key, b"secret", b"address").unwrap(), data);
+    }
+
+    #[test]
+    fn test_decrypt_needs_the_same_key_and_associated_data() {
+        let key = [7u8; 32];
+        let data = encrypt(&key, b"secret", b"address").unwrap();
+        assert!(decrypt(&[8u8; 32], &data, b"address").is_err());
+        assert!(decrypt(&key, &data, b"another address").is_err());
+
+        let mut tampered = data.clone();
+        tampered.ciphertext.replace_range(0..2, if &data.ciphertext[0..2] == "00" { "01" } else { "00" });
+        assert!(decrypt(&key, &tampered, b"address").is_err());
+    }
+
+    #[test]
+    fn test_keys_survive_lock_and_unlock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keystore.json");
+
+        let mut keystore = Keystore::create(&path, "correct horse").unwrap();
+        let address = keystore.generate_key().unwrap().encoded().to_string();
+        let public_key = keystore.keypair(&address).unwrap().export_public_key();
+
+        keystore.lock();
+        assert!(!keystore.is_unlocked());
+        assert!(matches!(keystore.keypair(&address), Err(WalletError::KeystoreLocked)));
+        assert!(matches!(keystore.generate_key(), Err(WalletError::KeystoreLocked)));
+
+        let reopened = Keystore::unlock(&path, "correct horse").unwrap();
+        assert_eq!(reopened.addresses(), vec![address.clone()]);
+        assert_eq!(reopened.keypair(&address).unwrap().export_public_key(), public_key);
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keystore.json");
+        Keystore::create(&path, "correct horse").unwrap().generate_key().unwrap();
+
+        assert!(matches!(Keystore::unlock(&path, "battery staple"), Err(WalletError::WrongPassphrase)));
+
+        // an empty keystore still checks the passphrase
+        let empty = dir.path().join("empty.json");
+        Keystore::create(&empty, "correct horse").unwrap();
+        assert!(matches!(Keystore::unlock(&empty, "battery staple"), Err(WalletError::WrongPassphrase)));
+    }
+
+    #[test]
+    fn test_create_does_not_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keystore.json");
+        Keystore::create(&path, "correct horse").unwrap();
+        assert!(matches!(Keystore::create(&path, "another"), Err(WalletError::KeystoreExists(_))));
+    }
+}
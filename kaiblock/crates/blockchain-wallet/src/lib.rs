@@ -1,14 +1,28 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+pub mod keypair;
+pub mod address;
+pub mod transaction;
+pub mod errors;
+pub mod conditional;
+pub mod keystore;
+pub mod sweep;
+pub mod multisig;
+pub mod timelock;
+pub mod coin_selection;
+pub mod history;
+pub mod nonce;
+pub mod address_book;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use keypair::WalletKeyPair;
+pub use address::Address;
+pub use transaction::WalletTransaction;
+pub use errors::WalletError;
+pub use conditional::{ConditionalPayment, AtomicSwap};
+pub use keystore::Keystore;
+pub use multisig::MultisigBuilder;
+pub use timelock::{locked_for, locked_until, spend_time_locked};
+pub use sweep::{SweepOptions, SweepPlan, SweepSource, parse_sweep_key, plan_sweep};
+pub use coin_selection::{BranchAndBound, CoinSelector, LargestFirst, RandomImprove, Selection, SelectionParams, select_coins};
+pub use history::{Balance, HistoryEntry, Wallet, WalletEvent};
+pub use nonce::{NonceManager, DEFAULT_FEE_BUMP_PERCENT};
+pub use address_book::{AddressBook, AddressBookEntry, check_network};
@@ -1,10 +0,0 @@
-pub mod keypair;
-pub mod address;
-pub mod transaction;
-pub mod errors;
-
-
-pub use keypair::Keypair;
-pub use address::Adress;
-pub use transaction::WalletTransaction;
-pub use errors::WalletError;
\ No newline at end of file
@@ -0,0 +1,156 @@
+use blockchain_core::{Amount, MultisigSignature, Script, SigningDomain, Transaction, TransactionInput, TransactionOutput, UTXO};
+use blockchain_crypto::{signature::Keypair, PublicKey, Signature};
+use serde::{Deserialize, Serialize};
+use crate::errors::WalletError;
+
+
+/// A multisig spend being signed, PSBT style.
+///
+/// One cosigner creates it from the multisig outputs to spend and passes it
+/// around (it serializes with serde); each adds its signatures with
+/// `add_signature`, or copies still missing ones over with `merge`. Once every
+/// input has its script's threshold, `finalize` gives the transaction to
+/// broadcast. Signatures are over the transaction's signing hash for the
+/// chain it was built for, which leaves them out, so they can be collected in
+/// any order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultisigBuilder {
+    tx: Transaction,
+    // threshold and keys of the output each input spends
+    scripts: Vec<(u8, Vec<PublicKey>)>,
+    // signatures collected for each input
+    signatures: Vec<Vec<MultisigSignature>>,
+    // redeem script revealed by each input spending a P2SH output
+    #[serde(default)]
+    redeem_scripts: Vec<Option<Script>>,
+    // chain the signatures are for
+    #[serde(default)]
+    domain: SigningDomain,
+}
+
+impl MultisigBuilder {
+    /// Spend multisig `utxos` to `outputs` on the chain `domain` names, paying `fee`
+    pub fn new(utxos: &[UTXO], outputs: Vec<TransactionOutput>, fee: Amount, domain: SigningDomain) -> Result<Self, WalletError> {
+        Self::build(utxos, None, outputs, fee, domain)
+    }
+
+    /// Spend `utxos` paying to the hash of the multisig `redeem_script` (P2SH);
+    /// outputs with a bare multisig script may be spent alongside
+    pub fn spend_script_hash(utxos: &[UTXO], redeem_script: &Script, outputs: Vec<TransactionOutput>, fee: Amount, domain: SigningDomain) -> Result<Self, WalletError> {
+        Self::build(utxos, Some(redeem_script), outputs, fee, domain)
+    }
+
+    fn build(utxos: &[UTXO], redeem_script: Option<&Script>, outputs: Vec<TransactionOutput>, fee: Amount, domain: SigningDomain) -> Result<Self, WalletError> {
+        let mut inputs = Vec::with_capacity(utxos.len());
+        let mut scripts = Vec::with_capacity(utxos.len());
+        let mut redeem_scripts = Vec::with_capacity(utxos.len());
+        for utxo in utxos {
+            let (script, redeem) = match (&utxo.output.script_pubkey, redeem_script) {
+                (Script::PayToScriptHash(hash), Some(redeem)) if *hash == redeem.hash() => (redeem, Some(redeem.clone())),
+                (script, _) => (script, None),
+            };
+            let Script::MultiSig { threshold, public_keys } = script else {
+                return Err(WalletError::Multisig(format!("{} is not a multisig output", utxo.outpoint())));
+            };
+            if !script.is_valid() {
+                return Err(WalletError::Multisig(format!("{} has an unspendable script", utxo.outpoint())));
+            }
+            // the input's own key is unused by multisig spends; any of the script's will do
+            inputs.push(TransactionInput::new(utxo.outpoint(), Signature::from_bytes([0u8; 64]), public_keys[0]));
+            scripts.push((*threshold, public_keys.clone()));
+            redeem_scripts.push(redeem);
+        }
+
+        Ok(Self {
+            tx: Transaction::new_utxo(inputs, outputs, fee),
+            signatures: vec![Vec::new(); scripts.len()],
+            scripts,
+            redeem_scripts,
+            domain,
+        })
+    }
+
+    /// The transaction being signed, without signatures
+    pub fn transaction(&self) -> &Transaction {
+        &self.tx
+    }
+
+    /// Sign every input whose script lists `keypair`'s key, returning how many it signed
+    pub fn add_signature(&mut self, keypair: &Keypair) -> Result<usize, WalletError> {
+        let tx_hash = self.tx.signing_hash(self.domain);
+        let mut signed = 0;
+        for (index, (_, public_keys)) in self.scripts.iter().enumerate() {
+            let Some(key_index) = public_keys.iter().position(|key| key == keypair.public_key()) else { continue };
+            let signature = MultisigSignature {
+                key_index: key_index as u8,
+                signature: keypair.sign(tx_hash.as_bytes()),
+            };
+            insert_signature(&mut self.signatures[index], signature);
+            signed += 1;
+        }
+
+        if signed == 0 {
+            return Err(WalletError::KeyNotFound(hex::encode(keypair.public_key_bytes())));
+        }
+        Ok(signed)
+    }
+
+    /// Take the signatures another cosigner collected for the same transaction
+    pub fn merge(&mut self, other: &MultisigBuilder) -> Result<(), WalletError> {
+        if other.tx.hash() != self.tx.hash() || other.scripts != self.scripts || other.domain != self.domain {
+            return Err(WalletError::Multisig("cannot merge signatures of a different transaction".to_string()));
+        }
+
+        let tx_hash = self.tx.signing_hash(self.domain);
+        for (index, signatures) in other.signatures.iter().enumerate() {
+            let public_keys = &self.scripts[index].1;
+            for signature in signatures {
+                let valid = public_keys.get(signature.key_index as usize)
+                    .is_some_and(|key| key.verify(tx_hash.as_bytes(), &signature.signature));
+                if !valid {
+                    return Err(WalletError::Multisig(format!("invalid signature for input {}", index)));
+                }
+                insert_signature(&mut self.signatures[index], *signature);
+            }
+        }
+        Ok(())
+    }
+
+    /// Signatures input `index` still needs
+    pub fn missing_signatures(&self, index: usize) -> usize {
+        match (self.scripts.get(index), self.signatures.get(index)) {
+            (Some((threshold, _)), Some(signatures)) => (*threshold as usize).saturating_sub(signatures.len()),
+            _ => 0,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        (0..self.scripts.len()).all(|index| self.missing_signatures(index) == 0)
+    }
+
+    /// The signed transaction, using the first `threshold` signatures of each input
+    pub fn finalize(self) -> Result<Transaction, WalletError> {
+        if !self.is_complete() {
+            return Err(WalletError::Multisig("not enough signatures".to_string()));
+        }
+
+        let mut tx = self.tx;
+        for (index, mut signatures) in self.signatures.into_iter().enumerate() {
+            signatures.truncate(self.scripts[index].0 as usize);
+            tx.inputs[index].set_multisig_signatures(signatures);
+            if let Some(Some(redeem_script)) = self.redeem_scripts.get(index) {
+                tx.inputs[index].set_redeem_script(redeem_script);
+            }
+        }
+        Ok(tx)
+    }
+}
+
+
+// keep signatures ordered by key index, one per key
+fn insert_signature(signatures: &mut Vec<MultisigSignature>, signature: MultisigSignature) {
+    match signatures.binary_search_by_key(&signature.key_index, |existing| existing.key_index) {
+        Ok(position) => signatures[position] = signature,
+        Err(position) => signatures.insert(position, signature),
+    }
+}
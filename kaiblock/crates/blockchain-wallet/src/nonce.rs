@@ -0,0 +1,313 @@
+use blockchain_core::{ChainEvent, Fee, Nonce, Timestamp, Transaction, TxId, TRANSFER_GAS};
+use blockchain_crypto::Address;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::errors::WalletError;
+
+
+/// Fee increase a replacement pays over the transaction it replaces, in
+/// percent; matches the node's default `min_replacement_fee_bump`
+pub const DEFAULT_FEE_BUMP_PERCENT: u64 = 10;
+
+
+#[derive(Debug, Clone, Default)]
+struct SenderNonces {
+    /// next nonce the chain expects
+    confirmed: Nonce,
+    /// sent transactions that haven't confirmed, by nonce
+    pending: BTreeMap<Nonce, Transaction>,
+    /// nonces handed out by `next_nonce` and not yet sent
+    reserved: BTreeSet<Nonce>,
+}
+
+impl SenderNonces {
+    fn in_use(&self, nonce: Nonce) -> bool {
+        self.pending.contains_key(&nonce) || self.reserved.contains(&nonce)
+    }
+
+    // forget what the chain has already confirmed
+    fn confirm(&mut self, confirmed: Nonce) {
+        self.confirmed = self.confirmed.max(confirmed);
+        self.pending = self.pending.split_off(&self.confirmed);
+        self.reserved = self.reserved.split_off(&self.confirmed);
+    }
+}
+
+
+/// Hands out account-model nonces for senders that send several
+/// transactions before any confirm.
+///
+/// Nonces come from the chain's confirmed nonce plus what is pending in the
+/// mempool, so concurrent sends don't collide. A nonce freed by a dropped
+/// transaction is a gap that stalls every later one; `next_nonce` fills gaps
+/// first. A pending transaction can be sped up or cancelled by sending a
+/// replacement with the same nonce and a higher fee.
+///
+/// Feed it the node's `ChainEvent`s with `apply_event`, or resynchronize with
+/// `sync` from `getAccount` and the mempool.
+#[derive(Debug, Clone)]
+pub struct NonceManager {
+    senders: HashMap<Address, SenderNonces>,
+    fee_bump_percent: u64,
+}
+
+impl NonceManager {
+    pub fn new() -> Self {
+        Self {
+            senders: HashMap::new(),
+            fee_bump_percent: DEFAULT_FEE_BUMP_PERCENT,
+        }
+    }
+
+    /// Bump replacements by this percent; use the node's `min_replacement_fee_bump`
+    pub fn with_fee_bump_percent(mut self, fee_bump_percent: u64) -> Self {
+        self.fee_bump_percent = fee_bump_percent;
+        self
+    }
+
+    /// Reset a sender to the chain's view: its confirmed nonce and its
+    /// transactions in the mempool. Reservations not yet sent are kept.
+    pub fn sync<'a>(&mut self, sender: &Address, confirmed: Nonce, mempool: impl IntoIterator<Item = &'a Transaction>) {
+        let nonces = self.senders.entry(sender.clone()).or_default();
+        nonces.confirmed = confirmed;
+        nonces.pending = mempool.into_iter()
+            .filter(|tx| tx.from.as_ref() == Some(sender))
+            .filter_map(|tx| tx.nonce.filter(|nonce| *nonce >= confirmed).map(|nonce| (nonce, tx.clone())))
+            .collect();
+        nonces.reserved = nonces.reserved.split_off(&confirmed);
+    }
+
+    /// Reserve the lowest nonce that is neither confirmed, pending nor
+    /// reserved, so a gap is filled before the sequence grows. Send a
+    /// transaction with it and `record_sent` it, or `release` it.
+    pub fn next_nonce(&mut self, sender: &Address) -> Nonce {
+        let nonces = self.senders.entry(sender.clone()).or_default();
+        let nonce = (nonces.confirmed..)
+            .find(|nonce| !nonces.in_use(*nonce))
+            .expect("nonces are unbounded");
+        nonces.reserved.insert(nonce);
+        nonce
+    }
+
+    /// Give back a reserved nonce that was never sent
+    pub fn release(&mut self, sender: &Address, nonce: Nonce) {
+        if let Some(nonces) = self.senders.get_mut(sender) {
+            nonces.reserved.remove(&nonce);
+        }
+    }
+
+    /// Track a transaction the node accepted. One already pending with the
+    /// same nonce is taken to be replaced by it.
+    pub fn record_sent(&mut self, tx: &Transaction) -> Result<(), WalletError> {
+        let (sender, nonce) = sender_and_nonce(tx)?;
+        let nonces = self.senders.entry(sender.clone()).or_default();
+        if nonce < nonces.confirmed {
+            return Err(WalletError::Nonce(format!("nonce {} is already confirmed for {}", nonce, sender)));
+        }
+        nonces.reserved.remove(&nonce);
+        nonces.pending.insert(nonce, tx.clone());
+        Ok(())
+    }
+
+    /// Next nonce the chain expects from a sender
+    pub fn confirmed(&self, sender: &Address) -> Nonce {
+        self.senders.get(sender).map_or(0, |nonces| nonces.confirmed)
+    }
+
+    /// Pending transaction holding a nonce
+    pub fn pending(&self, sender: &Address, nonce: Nonce) -> Option<&Transaction> {
+        self.senders.get(sender)?.pending.get(&nonce)
+    }
+
+    /// Nonces below the highest pending one that nothing pending holds.
+    /// Transactions above a gap can't confirm until it is filled.
+    pub fn gaps(&self, sender: &Address) -> Vec<Nonce> {
+        let Some(nonces) = self.senders.get(sender) else { return Vec::new() };
+        let Some(highest) = nonces.pending.keys().next_back() else { return Vec::new() };
+        (nonces.confirmed..*highest)
+            .filter(|nonce| !nonces.pending.contains_key(nonce))
+            .collect()
+    }
+
+    pub fn apply_event(&mut self, event: &ChainEvent) {
+        match event {
+            ChainEvent::BlockConnected(block) => {
+                for tx in block.transactions() {
+                    if let (Some(sender), Some(nonce)) = (&tx.from, tx.nonce) {
+                        if let Some(nonces) = self.senders.get_mut(sender) {
+                            nonces.confirm(nonce + 1);
+                        }
+                    }
+                }
+            }
+            // the chain re-adds still valid transactions with TxAdded
+            ChainEvent::BlockDisconnected(block) => {
+                for tx in block.transactions() {
+                    if let (Some(sender), Some(nonce)) = (&tx.from, tx.nonce) {
+                        if let Some(nonces) = self.senders.get_mut(sender) {
+                            nonces.confirmed = nonces.confirmed.min(nonce);
+                        }
+                    }
+                }
+            }
+            ChainEvent::TxAdded(tx) => {
+                let tracked = tx.from.as_ref().is_some_and(|sender| self.senders.contains_key(sender));
+                if tracked {
+                    let _ = self.record_sent(tx);
+                }
+            }
+            ChainEvent::TxDropped { tx_id, .. } => self.forget(tx_id),
+            ChainEvent::DoubleSpend(_) => {}
+        }
+    }
+
+    /// The pending transaction at `nonce` again, paying `fee_bump_percent`
+    /// more so the mempool replaces it. Send it, then `record_sent` it.
+    pub fn speed_up(&self, sender: &Address, nonce: Nonce) -> Result<Transaction, WalletError> {
+        let pending = self.pending(sender, nonce)
+            .ok_or_else(|| WalletError::Nonce(format!("nothing pending at nonce {} for {}", nonce, sender)))?;
+
+        let mut replacement = pending.clone();
+        let gas_limit = pending.gas_limit.unwrap_or(TRANSFER_GAS).max(1);
+        replacement.gas_limit = Some(gas_limit);
+        replacement.gas_price = Some(self.bumped_fee(pending.calculate_gas_fee()).div_ceil(gas_limit));
+        replacement.timestamp = Timestamp::now();
+        Ok(replacement)
+    }
+
+    /// A transfer of nothing to the sender itself at `nonce`, paying enough
+    /// to replace what is pending there. Once it confirms the original can't.
+    pub fn cancel(&self, sender: &Address, nonce: Nonce) -> Result<Transaction, WalletError> {
+        let pending = self.pending(sender, nonce)
+            .ok_or_else(|| WalletError::Nonce(format!("nothing pending at nonce {} for {}", nonce, sender)))?;
+
+        let gas_price = self.bumped_fee(pending.calculate_gas_fee()).div_ceil(TRANSFER_GAS);
+        Ok(Transaction::new_account(sender.clone(), sender.clone(), 0, nonce, TRANSFER_GAS, gas_price, Vec::new()))
+    }
+
+    // fee a replacement of a transaction paying `fee` has to pay
+    fn bumped_fee(&self, fee: Fee) -> Fee {
+        fee.saturating_mul(100 + self.fee_bump_percent).div_ceil(100).max(fee + 1)
+    }
+
+    fn forget(&mut self, tx_id: &TxId) {
+        for nonces in self.senders.values_mut() {
+            nonces.pending.retain(|_, tx| tx.id() != *tx_id);
+        }
+    }
+}
+
+impl Default for NonceManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+fn sender_and_nonce(tx: &Transaction) -> Result<(&Address, Nonce), WalletError> {
+    match (&tx.from, tx.nonce) {
+        (Some(sender), Some(nonce)) => Ok((sender, nonce)),
+        _ => Err(WalletError::Nonce("not an account transaction".to_string())),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::{Block, BlockId, TxDropReason};
+    use blockchain_crypto::{signature::Keypair, AddressType};
+    use std::sync::Arc;
+
+    fn address() -> Address {
+        Address::from_public_key(Keypair::generate().public_key(), AddressType::Base58)
+    }
+
+    fn transfer(sender: &Address, nonce: Nonce, gas_price: u64) -> Transaction {
+        Transaction::new_account(sender.clone(), address(), 10, nonce, TRANSFER_GAS, gas_price, Vec::new())
+    }
+
+    #[test]
+    fn test_nonces_follow_confirmed_and_pending() {
+        let sender = address();
+        let mut nonces = NonceManager::new();
+        nonces.sync(&sender, 3, [&transfer(&sender, 3, 1)]);
+
+        assert_eq!(nonces.next_nonce(&sender), 4);
+        assert_eq!(nonces.next_nonce(&sender), 5);
+
+        // a nonce given back is handed out again
+        nonces.release(&sender, 4);
+        assert_eq!(nonces.next_nonce(&sender), 4);
+    }
+
+    #[test]
+    fn test_dropped_transaction_gap_is_filled_first() {
+        let sender = address();
+        let mut nonces = NonceManager::new();
+        let sent: Vec<Transaction> = (0..3).map(|nonce| transfer(&sender, nonce, 1)).collect();
+        for tx in &sent {
+            assert_eq!(nonces.next_nonce(&sender), tx.nonce.unwrap());
+            nonces.record_sent(tx).unwrap();
+        }
+        assert!(nonces.gaps(&sender).is_empty());
+
+        nonces.apply_event(&ChainEvent::TxDropped { tx_id: sent[1].id(), reason: TxDropReason::Expired });
+        assert_eq!(nonces.gaps(&sender), vec![1]);
+        assert_eq!(nonces.next_nonce(&sender), 1);
+        assert_eq!(nonces.next_nonce(&sender), 3);
+    }
+
+    #[test]
+    fn test_confirmed_blocks_advance_the_sender() {
+        let sender = address();
+        let mut nonces = NonceManager::new();
+        let tx0 = transfer(&sender, 0, 1);
+        let tx1 = transfer(&sender, 1, 1);
+        nonces.record_sent(&tx0).unwrap();
+        nonces.record_sent(&tx1).unwrap();
+
+        let block = Arc::new(Block::new(BlockId::genesis(), vec![tx0.clone()], 1, 1, 1).unwrap());
+        nonces.apply_event(&ChainEvent::BlockConnected(block.clone()));
+        assert_eq!(nonces.confirmed(&sender), 1);
+        assert!(nonces.pending(&sender, 0).is_none());
+        assert!(matches!(nonces.record_sent(&tx0), Err(WalletError::Nonce(_))));
+
+        // a reorg takes the confirmation back
+        nonces.apply_event(&ChainEvent::BlockDisconnected(block));
+        assert_eq!(nonces.confirmed(&sender), 0);
+    }
+
+    #[test]
+    fn test_mempool_transactions_of_tracked_senders_are_recorded() {
+        let tracked = address();
+        let untracked = address();
+        let mut nonces = NonceManager::new();
+        nonces.sync(&tracked, 0, []);
+
+        nonces.apply_event(&ChainEvent::TxAdded(Arc::new(transfer(&tracked, 0, 1))));
+        nonces.apply_event(&ChainEvent::TxAdded(Arc::new(transfer(&untracked, 0, 1))));
+        assert!(nonces.pending(&tracked, 0).is_some());
+        assert!(nonces.pending(&untracked, 0).is_none());
+    }
+
+    #[test]
+    fn test_replacements_pay_the_bump() {
+        let sender = address();
+        let mut nonces = NonceManager::new().with_fee_bump_percent(25);
+        let pending = transfer(&sender, 0, 100);
+        nonces.record_sent(&pending).unwrap();
+
+        let faster = nonces.speed_up(&sender, 0).unwrap();
+        assert_eq!(faster.nonce, Some(0));
+        assert!(faster.calculate_gas_fee() >= pending.calculate_gas_fee() * 125 / 100);
+
+        let cancel = nonces.cancel(&sender, 0).unwrap();
+        assert_eq!((cancel.to.as_ref(), cancel.amount, cancel.nonce), (Some(&sender), Some(0), Some(0)));
+        assert!(cancel.calculate_gas_fee() >= pending.calculate_gas_fee() * 125 / 100);
+
+        // a replacement takes the place of what it replaces
+        nonces.record_sent(&faster).unwrap();
+        assert_eq!(nonces.pending(&sender, 0), Some(&faster));
+        assert!(matches!(nonces.speed_up(&sender, 1), Err(WalletError::Nonce(_))));
+    }
+}
@@ -0,0 +1,299 @@
+use blockchain_core::{Amount, BlockHeight, Gas, GasPrice, Nonce, SigningDomain, Transaction, TransactionInput, TransactionOutput, UTXO};
+use blockchain_crypto::{signature::Keypair, Address, AddressType, Signature};
+use crate::errors::WalletError;
+
+
+/// Gas limit of a plain account transfer
+pub const TRANSFER_GAS: Gas = 21000;
+
+
+/// Parse a key to sweep. Accepts the current hex private key export and the
+/// formats older wallets wrote: base58 private keys and 64-byte ed25519
+/// keypairs (secret followed by public key), in hex or base58.
+pub fn parse_sweep_key(key: &str) -> Result<Keypair, WalletError> {
+    let key = key.trim();
+    if key.split_whitespace().count() > 1 {
+        return Err(WalletError::UnsupportedKeyFormat("mnemonic phrases are not supported".to_string()));
+    }
+
+    let bytes = match hex::decode(key.trim_start_matches("0x")) {
+        Ok(bytes) => bytes,
+        Err(_) => bs58::decode(key).into_vec()
+            .map_err(|_| WalletError::UnsupportedKeyFormat("expected a hex or base58 private key".to_string()))?,
+    };
+
+    match bytes.len() {
+        32 => Keypair::from_private_bytes(&bytes).map_err(|_| WalletError::InvalidKey),
+        64 => {
+            let keypair = Keypair::from_private_bytes(&bytes[..32]).map_err(|_| WalletError::InvalidKey)?;
+            if keypair.public_key_bytes()[..] != bytes[32..] {
+                return Err(WalletError::InvalidKey);
+            }
+            Ok(keypair)
+        }
+        len => Err(WalletError::UnsupportedKeyFormat(format!("{}-byte key", len))),
+    }
+}
+
+
+/// Fee and size settings for a sweep
+#[derive(Debug, Clone)]
+pub struct SweepOptions {
+    pub fee_per_byte: Amount,
+    /// largest transaction the node accepts; inputs are split across as many
+    /// transactions as needed to stay under it
+    pub max_transaction_size: usize,
+    /// gas price of the account transfer
+    pub gas_price: GasPrice,
+    /// chain the inputs are signed for, as reported by `getChainId`
+    pub domain: SigningDomain,
+}
+
+impl Default for SweepOptions {
+    fn default() -> Self {
+        Self {
+            fee_per_byte: 1,
+            max_transaction_size: 100_000,
+            gas_price: 1,
+            domain: SigningDomain::default(),
+        }
+    }
+}
+
+
+/// What the sweeping key controls, as reported by a node
+#[derive(Debug, Clone, Default)]
+pub struct SweepSource {
+    pub utxos: Vec<UTXO>,
+    /// chain height the UTXOs were read at
+    pub height: BlockHeight,
+    pub coinbase_maturity: BlockHeight,
+    pub account_balance: Amount,
+    pub account_nonce: Nonce,
+}
+
+
+/// Signed transactions moving everything a key controls to one address
+#[derive(Debug, Clone)]
+pub struct SweepPlan {
+    pub source: Address,
+    pub destination: Address,
+    /// outputs spent by the plan
+    pub spent: Vec<UTXO>,
+    /// coinbase outputs that can't be spent yet
+    pub immature: Vec<UTXO>,
+    /// outputs worth less than the fee to spend them
+    pub dust: Vec<UTXO>,
+    pub account_balance: Amount,
+    pub transactions: Vec<Transaction>,
+}
+
+impl SweepPlan {
+    pub fn total_fees(&self) -> Amount {
+        self.transactions.iter().map(|tx| tx.calculate_gas_fee()).sum()
+    }
+
+    /// Total arriving at the destination
+    pub fn total_swept(&self) -> Amount {
+        self.transactions.iter()
+            .map(|tx| tx.amount.unwrap_or(0) + tx.outputs.iter().map(|output| output.amount).sum::<Amount>())
+            .sum()
+    }
+}
+
+
+/// Build and sign the transactions sweeping `source` to `destination`.
+///
+/// UTXOs go into as few transactions as the size limit allows, largest first,
+/// each paying `fee_per_byte` for its exact size. Outputs that cost more to
+/// spend than they hold are left behind. The account balance, if it covers
+/// the transfer gas, is moved with one account transfer.
+pub fn plan_sweep(keypair: &Keypair, destination: Address, source: SweepSource, options: &SweepOptions) -> Result<SweepPlan, WalletError> {
+    let from = Address::from_public_key(keypair.public_key(), AddressType::Base58);
+
+    let (mut spendable, immature): (Vec<UTXO>, Vec<UTXO>) = source.utxos.into_iter()
+        .partition(|utxo| !utxo.is_coinbase || source.height.saturating_sub(utxo.block_height) >= source.coinbase_maturity);
+    spendable.sort_by(|a, b| b.output.amount.cmp(&a.output.amount));
+
+    let base_size = sweep_transaction(keypair, &[], destination.clone(), 0).size();
+    let input_size = sweep_transaction(keypair, &spendable[..spendable.len().min(1)], destination.clone(), 0).size() - base_size;
+    let input_cost = input_size as Amount * options.fee_per_byte;
+
+    let (spendable, dust): (Vec<UTXO>, Vec<UTXO>) = spendable.into_iter()
+        .partition(|utxo| utxo.output.amount > input_cost);
+
+    let inputs_per_tx = options.max_transaction_size.saturating_sub(base_size) / input_size.max(1);
+    if inputs_per_tx == 0 && !spendable.is_empty() {
+        return Err(WalletError::Sweep("transaction size limit is too small for a single input".to_string()));
+    }
+
+    let mut transactions = Vec::new();
+    for chunk in spendable.chunks(inputs_per_tx.max(1)) {
+        let total: Amount = chunk.iter().map(|utxo| utxo.output.amount).sum();
+        let fee = sweep_transaction(keypair, chunk, destination.clone(), 0).size() as Amount * options.fee_per_byte;
+        // dust filtering leaves every chunk worth more than its fee; check anyway
+        if total <= fee {
+            continue;
+        }
+
+        let mut tx = sweep_transaction(keypair, chunk, destination.clone(), fee);
+        for index in 0..tx.inputs.len() {
+            tx.sign_input(keypair, index, options.domain).map_err(|_| WalletError::SigningError)?;
+        }
+        transactions.push(tx);
+    }
+
+    let transfer_fee = TRANSFER_GAS * options.gas_price;
+    if source.account_balance > transfer_fee {
+        transactions.push(Transaction::new_account(
+            from.clone(),
+            destination.clone(),
+            source.account_balance - transfer_fee,
+            source.account_nonce,
+            TRANSFER_GAS,
+            options.gas_price,
+            Vec::new(),
+        ));
+    }
+
+    if transactions.is_empty() {
+        return Err(WalletError::NothingToSweep(from.to_string()));
+    }
+
+    Ok(SweepPlan {
+        source: from,
+        destination,
+        spent: spendable,
+        immature,
+        dust,
+        account_balance: source.account_balance,
+        transactions,
+    })
+}
+
+
+// One transaction spending `utxos` to a single output, less `fee`. Inputs
+// carry a blank signature until signed, which has the same encoded size.
+fn sweep_transaction(keypair: &Keypair, utxos: &[UTXO], destination: Address, fee: Amount) -> Transaction {
+    let inputs = utxos.iter()
+        .map(|utxo| TransactionInput::new(utxo.outpoint(), Signature::from_bytes([0u8; 64]), *keypair.public_key()))
+        .collect();
+    let total: Amount = utxos.iter().map(|utxo| utxo.output.amount).sum();
+
+    Transaction::new_utxo(inputs, vec![TransactionOutput::new(total.saturating_sub(fee), destination)], fee)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_core::TxId;
+    use blockchain_crypto::hash::sha256;
+
+    fn utxo(keypair: &Keypair, amount: Amount, index: u32, is_coinbase: bool, block_height: BlockHeight) -> UTXO {
+        let address = Address::from_public_key(keypair.public_key(), AddressType::Base58);
+        let tx_id = TxId::new(sha256(&index.to_le_bytes()));
+        UTXO::new(TransactionOutput::new(amount, address), block_height, tx_id, 0, is_coinbase)
+    }
+
+    fn destination() -> Address {
+        Address::from_public_key(Keypair::generate().public_key(), AddressType::Base58)
+    }
+
+    #[test]
+    fn test_parse_current_and_legacy_key_formats() {
+        let keypair = Keypair::generate();
+        let secret = keypair.private_key_bytes();
+        let mut legacy = secret.to_vec();
+        legacy.extend_from_slice(&keypair.public_key_bytes());
+
+        for encoded in [
+            hex::encode(secret),
+            format!("0x{}", hex::encode(secret)),
+            bs58::encode(secret).into_string(),
+            hex::encode(&legacy),
+            bs58::encode(&legacy).into_string(),
+        ] {
+            let parsed = parse_sweep_key(&encoded).unwrap();
+            assert_eq!(parsed.public_key_bytes(), keypair.public_key_bytes(), "{}", encoded);
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_unsupported_keys() {
+        assert!(matches!(parse_sweep_key("abandon ability able"), Err(WalletError::UnsupportedKeyFormat(_))));
+        assert!(matches!(parse_sweep_key(&hex::encode([1u8; 16])), Err(WalletError::UnsupportedKeyFormat(_))));
+        assert!(matches!(parse_sweep_key("not a key!"), Err(WalletError::UnsupportedKeyFormat(_))));
+
+        // a 64-byte keypair whose halves don't belong together
+        let mut mismatched = Keypair::generate().private_key_bytes().to_vec();
+        mismatched.extend_from_slice(&Keypair::generate().public_key_bytes());
+        assert!(matches!(parse_sweep_key(&hex::encode(mismatched)), Err(WalletError::InvalidKey)));
+    }
+
+    #[test]
+    fn test_sweep_sorts_outputs_and_pays_exact_fees() {
+        let keypair = Keypair::generate();
+        let source = SweepSource {
+            utxos: vec![
+                utxo(&keypair, 10_000, 0, false, 5),
+                utxo(&keypair, 50_000, 1, true, 90), // coinbase, not yet mature
+                utxo(&keypair, 50, 2, false, 5), // costs more to spend than it holds
+                utxo(&keypair, 30_000, 3, true, 10),
+            ],
+            height: 100,
+            coinbase_maturity: 20,
+            account_balance: 100_000,
+            account_nonce: 4,
+        };
+        let options = SweepOptions { fee_per_byte: 2, ..SweepOptions::default() };
+        let plan = plan_sweep(&keypair, destination(), source, &options).unwrap();
+
+        assert_eq!(plan.spent.iter().map(|utxo| utxo.output.amount).collect::<Vec<_>>(), vec![30_000, 10_000]);
+        assert_eq!(plan.immature.len(), 1);
+        assert_eq!(plan.dust.len(), 1);
+
+        assert_eq!(plan.transactions.len(), 2);
+        let utxo_sweep = &plan.transactions[0];
+        assert_eq!(utxo_sweep.fee, utxo_sweep.size() as Amount * options.fee_per_byte);
+        let transfer = &plan.transactions[1];
+        assert_eq!(transfer.nonce, Some(4));
+        assert_eq!(transfer.amount, Some(100_000 - TRANSFER_GAS * options.gas_price));
+
+        // everything spent arrives, less the fees
+        assert_eq!(plan.total_swept() + plan.total_fees(), 40_000 + 100_000);
+    }
+
+    #[test]
+    fn test_sweep_splits_inputs_under_the_size_limit() {
+        let keypair = Keypair::generate();
+        let to = destination();
+        let source = |count: u32| SweepSource {
+            utxos: (0..count).map(|index| utxo(&keypair, 10_000, index, false, 1)).collect(),
+            ..SweepSource::default()
+        };
+
+        let single = plan_sweep(&keypair, to.clone(), source(1), &SweepOptions::default()).unwrap().transactions[0].size();
+        let four = plan_sweep(&keypair, to.clone(), source(4), &SweepOptions::default()).unwrap().transactions[0].size();
+        let input_size = (four - single) / 3;
+
+        let options = SweepOptions { max_transaction_size: single + input_size, ..SweepOptions::default() };
+        let plan = plan_sweep(&keypair, to, source(5), &options).unwrap();
+        assert_eq!(plan.transactions.iter().map(|tx| tx.inputs.len()).collect::<Vec<_>>(), vec![2, 2, 1]);
+        assert!(plan.transactions.iter().all(|tx| tx.size() <= options.max_transaction_size));
+    }
+
+    #[test]
+    fn test_nothing_to_sweep() {
+        let keypair = Keypair::generate();
+        let source = SweepSource {
+            utxos: vec![utxo(&keypair, 10, 0, false, 1)],
+            account_balance: TRANSFER_GAS,
+            ..SweepSource::default()
+        };
+        assert!(matches!(
+            plan_sweep(&keypair, destination(), source, &SweepOptions::default()),
+            Err(WalletError::NothingToSweep(_))
+        ));
+    }
+}
@@ -0,0 +1,77 @@
+use blockchain_core::timelock::{relative_lock_sequence, SEQUENCE_FINAL};
+use blockchain_core::{Amount, LockTime, Script, SigningDomain, Transaction, TransactionInput, TransactionOutput, UTXO};
+use blockchain_crypto::{hash::sha256, signature::Keypair, Address, Signature};
+use crate::errors::WalletError;
+
+
+/// Output to `address` that can't be spent before `lock` (CLTV)
+pub fn locked_until(amount: Amount, address: Address, lock: LockTime) -> TransactionOutput {
+    let owner = Script::pay_to_pubkey_hash(sha256(address.data()));
+    TransactionOutput {
+        amount,
+        script_pubkey: Script::check_lock_time(lock.to_consensus(), owner),
+        address,
+    }
+}
+
+/// Output to `address` that can't be spent until it is `blocks` deep (CSV)
+pub fn locked_for(amount: Amount, address: Address, blocks: u16) -> TransactionOutput {
+    let owner = Script::pay_to_pubkey_hash(sha256(address.data()));
+    TransactionOutput {
+        amount,
+        script_pubkey: Script::check_sequence(blocks, owner),
+        address,
+    }
+}
+
+
+/// Spend time-locked `utxos` owned by `keypair` to `destination`, less `fee`.
+///
+/// The transaction's lock time and each input's sequence are set to what
+/// the scripts ask for, so it is valid from the first block they allow; a
+/// node refuses it until then. Outputs without a time lock spend as usual.
+/// Inputs are signed for the chain `domain` names.
+pub fn spend_time_locked(keypair: &Keypair, utxos: &[UTXO], destination: Address, fee: Amount, domain: SigningDomain) -> Result<Transaction, WalletError> {
+    let mut lock_time: Option<LockTime> = None;
+    let mut inputs = Vec::with_capacity(utxos.len());
+    for utxo in utxos {
+        let mut input = TransactionInput::new(utxo.outpoint(), Signature::from_bytes([0u8; 64]), *keypair.public_key());
+        input.sequence = SEQUENCE_FINAL;
+
+        let mut script = &utxo.output.script_pubkey;
+        loop {
+            match script {
+                Script::CheckLockTime { lock_time: required, script: inner } => {
+                    let required = LockTime::from_consensus(*required)
+                        .ok_or_else(|| WalletError::TimeLock(format!("{} has an empty lock time", utxo.outpoint())))?;
+                    lock_time = match lock_time {
+                        None => Some(required),
+                        Some(current) if current.is_reached_by(required) => Some(required),
+                        Some(current) if required.is_reached_by(current) => Some(current),
+                        // one transaction can't wait for both a height and a time
+                        Some(_) => return Err(WalletError::TimeLock("outputs mix height and time locks".to_string())),
+                    };
+                    script = inner;
+                }
+                Script::CheckSequence { blocks, script: inner } => {
+                    input.sequence = relative_lock_sequence(*blocks);
+                    script = inner;
+                }
+                _ => break,
+            }
+        }
+        inputs.push(input);
+    }
+
+    let total: Amount = utxos.iter().map(|utxo| utxo.output.amount).sum();
+    if total <= fee {
+        return Err(WalletError::TimeLock(format!("outputs worth {} don't cover the fee of {}", total, fee)));
+    }
+
+    let mut tx = Transaction::new_utxo(inputs, vec![TransactionOutput::new(total - fee, destination)], fee);
+    tx.lock_time = lock_time.map_or(0, LockTime::to_consensus);
+    for index in 0..tx.inputs.len() {
+        tx.sign_input(keypair, index, domain).map_err(|_| WalletError::SigningError)?;
+    }
+    Ok(tx)
+}
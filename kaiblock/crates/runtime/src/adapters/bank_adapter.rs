@@ -66,9 +66,11 @@ impl Program for BankProgramAdapter{
         // accounts store, instruction data, and signers.
 
         let program_id = BANK_PROGRAM_ID;
+        let keys: Vec<BankPubkey> = accounts.iter().map(|acct| acct.pubkey).collect();
 
         processor::process_instruction(
         	&program_id,
+        	&keys,
         	&mut store,
         	&data,
         	&signers,
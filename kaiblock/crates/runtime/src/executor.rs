@@ -1,20 +1,38 @@
 0use crate::types::*;
 use crate::program::{Program, ProgramError};
-use std::collections::HashMap;
+use crate::randomness::{self, RandomnessSource};
+use crate::params::{ParamsSchedule, PARAMS_ACCOUNT, PARAMS_PROGRAM_ID};
+use crate::gas::{GasReceipt, GasSchedule, HostCall};
+use crate::wasm::WasmProgram;
+use crate::store::{AccountStore, MemoryAccountStore, StoredAccount};
+use crate::loader::{LoaderProgram, ProgramAccount, LOADER_PROGRAM_ID};
+use crate::logs::{LogKind, ProgramLog};
+use crate::system::{SystemProgram, SYSTEM_PROGRAM_ID};
+use crate::scheduler::schedule;
+use borsh::{BorshDeserialize, BorshSerialize};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 use std::sync::Arc;
-use log::info;
+use tracing::{debug, debug_span, info};
 
 
-/// Configuration for the runtime (budgets, etc.)
-#[derive(Clone)]
+/// Tracing target of messages programs log, so they can be filtered apart
+/// from the runtime's own events (e.g. `runtime::program=off`)
+pub const PROGRAM_LOG_TARGET: &str = "runtime::program";
+
+/// Configuration for the runtime (budgets, etc.).
+/// Genesis values; later values come from the on-chain schedule, see crate::params
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
 pub struct RuntimeConfig {
-	//initial compute units provided by the transaction
+	//most compute units a transaction may use (and its limit when it sets none)
 	pub max_compute_units: u64,
-	//cost per instruction byte(simple model)
-	pub byte_cost: u64,
-	// cost per instruction (flat)
-	pub instr_cost: u64,
+	//compute charged per operation, see crate::gas
+	pub gas: GasSchedule,
+	//price per compute unit every transaction pays on top of its priority fee; burned
+	pub base_fee: u64,
+	//balance an account must keep per byte of data, see crate::system; 0 turns deposits off
+	pub storage_deposit_per_byte: u64,
 }
 
 
@@ -22,8 +40,19 @@ impl Defaulf for RuntimeConfig{
 	fn default() ->Self{
 		Self{
 			max_compute_units: 1_000_000,
-			byte_cost: 10,
-			instr_cost: 500,
+			gas: GasSchedule::default(),
+			base_fee: 1,
+			storage_deposit_per_byte: 0,
+		}
+	}
+}
+
+impl RuntimeConfig {
+	/// Compute units reserved for `tx`: its own limit, capped at the maximum
+	pub fn compute_limit(&self, tx: &Transaction) -> u64 {
+		match tx.compute_limit {
+			0 => self.max_compute_units,
+			limit => limit.min(self.max_compute_units),
 		}
 	}
 }
@@ -32,8 +61,25 @@ impl Defaulf for RuntimeConfig{
 pub struct RuntimeContext{
 	//Remaining compute units available for the transaction
 	pub remaining_compute: u64,
-	//Access to logs via log::info; additional host functions can be added.
+	//Access to logs via ctx.log (traced under PROGRAM_LOG_TARGET); additional host functions can be added.
 	pub clock: u64; //slot/timestamp;runtime sets this.
+	//seed of the instruction being executed, see crate::randomness
+	seed: [u8; 32],
+	//random draws made so far by this instruction
+	pub(crate) draws: u64,
+	//compute charged per host call
+	pub(crate) gas: GasSchedule,
+	//programs `invoke` can dispatch to
+	programs: Arc<HashMap<Pubkey, Arc<dyn Program>>>,
+	//programs currently executing, outermost first
+	call_stack: Vec<Pubkey>,
+	//index of the top-level instruction being executed
+	pub(crate) instruction: u32,
+	//logs recorded so far, see crate::logs
+	pub(crate) logs: Vec<ProgramLog>,
+	//native balances of the transaction's accounts, committed if it succeeds
+	balances: HashMap<Pubkey, u64>,
+	storage_deposit_per_byte: u64,
 }
 
 
@@ -47,13 +93,142 @@ impl RuntimeContext{
 		Ok(())
 	}
 
-	pub fn log(&sekf, msg: &str){
-        // delegated to log crate; programs should use ctx.log for deterministic logging
-		info!("{}", msg)
+	/// Deterministic seed of the current instruction, derived from the block
+	/// (or VRF output), the transaction and the instruction index.
+	/// Predictable and grindable by block producers: see crate::randomness
+	/// before relying on it.
+	pub fn random_seed(&self) -> [u8; 32] {
+		self.seed
+	}
+
+	/// Next deterministic random value of this instruction. Each call returns
+	/// a different value and costs `gas.random_draw` compute units.
+	pub fn next_random(&mut self) -> Result<[u8; 32], RuntimeError> {
+		self.consume(self.gas.host_call_cost(HostCall::RandomDraw))?;
+		let value = randomness::draw(&self.seed, self.draws);
+		self.draws += 1;
+		Ok(value)
+	}
+
+	/// Log a message, charged per byte so logs can't be used to bloat nodes for free.
+	/// It goes into the transaction's receipt and is traced as well.
+	pub fn log(&mut self, msg: &str) -> Result<(), RuntimeError> {
+		self.consume(self.gas.host_call_cost(HostCall::Log { bytes: msg.len() }))?;
+		info!(target: PROGRAM_LOG_TARGET, program = ?self.call_stack.last(), "{}", msg);
+		self.record(LogKind::Message(msg.to_string()));
+		Ok(())
+	}
+
+	/// Emit a structured event into the transaction's receipt, charged like
+	/// a log of the topic and data
+	pub fn emit(&mut self, topic: [u8; 32], data: &[u8]) -> Result<(), RuntimeError> {
+		self.consume(self.gas.host_call_cost(HostCall::Log { bytes: topic.len() + data.len() }))?;
+		self.record(LogKind::Event { topic, data: data.to_vec() });
+		Ok(())
+	}
+
+	/// Native balance of one of the transaction's accounts, as of this point
+	/// in the transaction
+	pub fn balance(&self, pubkey: &Pubkey) -> u64 {
+		self.balances.get(pubkey).copied().unwrap_or(0)
+	}
+
+	/// Balance an account holding `bytes` of data must keep, see crate::system
+	pub fn storage_deposit(&self, bytes: usize) -> u64 {
+		(bytes as u64).saturating_mul(self.storage_deposit_per_byte)
+	}
+
+	//move native balance between the transaction's accounts; only the system program may
+	pub(crate) fn move_lamports(&mut self, from: &Pubkey, to: &Pubkey, amount: u64) -> Result<(), RuntimeError> {
+		let available = self.balance(from);
+		if available < amount {
+			return Err(RuntimeError::InsufficientFunds { required: amount, available });
+		}
+		self.balances.insert(*from, available - amount);
+		let balance = self.balances.entry(*to).or_insert(0);
+		*balance = balance.saturating_add(amount);
+		Ok(())
+	}
+
+	//keep a log of the running program, already paid for
+	pub(crate) fn record(&mut self, kind: LogKind) {
+		self.logs.push(ProgramLog {
+			instruction: self.instruction,
+			program: self.call_stack.last().copied().unwrap_or_default(),
+			kind,
+		});
+	}
+
+	/// Call another program from inside a program (cross-program invocation).
+	///
+	/// `instruction.accounts` indexes into `accounts`, the caller's own
+	/// accounts, so the callee can only see what the caller was given, with the
+	/// same signer and writable flags. The call is charged like a top-level
+	/// instruction plus `gas.invoke`, out of the same budget. The callee's
+	/// changes are checked like the executor checks an instruction's (only the
+	/// owner may change data, read-only accounts keep theirs) and copied back
+	/// into `accounts` when it returns.
+	///
+	/// Calls nest at most MAX_INVOKE_DEPTH programs deep, and a program can't
+	/// be re-entered while it is still running. The logs and balance changes
+	/// of a call that fails are dropped with its writes. Accounts can't change
+	/// owner through a call, see crate::system.
+	pub fn invoke(&mut self, instruction: &Instruction, accounts: &mut [AccountInfo]) -> Result<(), RuntimeError> {
+		if self.call_stack.len() >= MAX_INVOKE_DEPTH {
+			return Err(RuntimeError::InvokeDepthExceeded);
+		}
+		if self.call_stack.contains(&instruction.program_id) {
+			return Err(RuntimeError::Reentrancy);
+		}
+		self.consume(self.gas.instruction_cost(instruction).saturating_add(self.gas.host_call_cost(HostCall::Invoke)))?;
+
+		let program = self.programs.get(&instruction.program_id).cloned()
+			.ok_or(RuntimeError::ProgramNotFound)?;
+		let mut callee_accounts = Vec::with_capacity(instruction.accounts.len());
+		for &idx in &instruction.accounts {
+			let acct = accounts.get(idx as usize).ok_or(RuntimeError::AccountIndexOOB)?;
+			callee_accounts.push(acct.clone());
+		}
+
+		let (logged, balances) = (self.logs.len(), self.balances.clone());
+		self.call_stack.push(instruction.program_id);
+		let result = program.process(&mut callee_accounts, &instruction.data, self);
+		self.call_stack.pop();
+		let result = result
+			.map_err(|e| RuntimeError::ProgramError(format!("{:?}", e)))
+			.and_then(|()| {
+				for (&idx, acct) in instruction.accounts.iter().zip(&callee_accounts) {
+					let target = &accounts[idx as usize];
+					if acct.data != target.data && target.owner != instruction.program_id {
+						return Err(RuntimeError::AccountNotOwned);
+					}
+					if acct.owner != target.owner {
+						return Err(RuntimeError::OwnerChangedByInvoke);
+					}
+				}
+				Ok(())
+			});
+		if result.is_err() {
+			self.logs.truncate(logged);
+			self.balances = balances;
+			return result;
+		}
+
+		for (&idx, acct) in instruction.accounts.iter().zip(callee_accounts) {
+			let target = &mut accounts[idx as usize];
+			if target.is_writable {
+				target.data = acct.data;
+			}
+		}
+		Ok(())
 	}
 }
 
 
+/// Deepest nesting of programs, counting the one the transaction calls
+pub const MAX_INVOKE_DEPTH: usize = 4;
+
+
 #[derive(Error, Debug)]
 pub enum RuntimeError{
 	#[error("program not found")]
@@ -68,47 +243,330 @@ pub enum RuntimeError{
     SignatureVerificationFailed,
     #[error("invalid instruction data: {0}")]
     InvalidInstructionData(String),
+    #[error("fee payer can't cover the fee: requires {required}, has {available}")]
+    InsufficientFunds { required: u64, available: u64 },
+    #[error("instruction changed an account its program does not own")]
+    AccountNotOwned,
+    #[error("account store: {0}")]
+    Storage(String),
+    #[error("cross-program invocation nested deeper than {}", MAX_INVOKE_DEPTH)]
+    InvokeDepthExceeded,
+    #[error("cross-program invocation re-entered a running program")]
+    Reentrancy,
+    #[error("accounts can only change owner when the transaction calls the system program")]
+    OwnerChangedByInvoke,
+    #[error("account holds {available}, its data needs a storage deposit of {required}")]
+    InsufficientDeposit { required: u64, available: u64 },
 }
 
 
-/// The runtime holds a registry of programs (native adapters or WASM shims).
+/// The runtime holds a registry of programs (native adapters or WASM modules).
+/// Transactions only read it while they run and write their results back
+/// afterwards, so a block's independent transactions can run in parallel.
 pub struct Runtime {
-	programs: HashMap<Pubkey, Arc<dyn Program>>,
-	config: RuntimeConfig,
+	// shared with RuntimeContext so programs can invoke each other
+	programs: Arc<HashMap<Pubkey, Arc<dyn Program>>>,
+	// parameters by height, mirrored from the params account
+	params: ParamsSchedule,
 	// for tests/dev only: simulated clock(slot/timestamp)
 
 	pub clock: u64;
+	// seeds program randomness; the node sets it per block
+	pub randomness: RandomnessSource,
+	// receives priority fees; the node sets it to the block producer (None burns them)
+	pub fee_collector: Option<Pubkey>,
+	// native balances paying for compute
+	balances: HashMap<Pubkey, u64>,
+	// program accounts, loaded at transaction start and committed on success
+	accounts: Box<dyn AccountStore>,
+	// ids in `programs` compiled from loader accounts, see crate::loader
+	deployed: HashSet<Pubkey>,
+}
+
+//what a transaction did, applied to the runtime's state in block order
+struct Executed {
+	receipt: GasReceipt,
+	changes: Result<Changes, RuntimeError>,
+}
+
+//writes of a successful transaction
+struct Changes {
+	accounts: Vec<(Pubkey, StoredAccount)>,
+	params: Option<ParamsSchedule>,
+	//balances it changed, fee payer's included
+	balances: Vec<(Pubkey, u64)>,
 }
 
 impl Runtime {
 	pub fn new(config: RuntimeConfig)->Self{
-		Self{
-			programs: HashMap::new(),
-			config,
+		Self::with_params(ParamsSchedule::new(config))
+	}
+
+	// resume with the parameter schedule read from chain state
+	pub fn with_params(params: ParamsSchedule)->Self{
+		let mut runtime = Self{
+			programs: Arc::new(HashMap::new()),
+			params,
 			clock: 0,
-		}
+			randomness: RandomnessSource::default(),
+			fee_collector: None,
+			balances: HashMap::new(),
+			accounts: Box::new(MemoryAccountStore::new()),
+			deployed: HashSet::new(),
+		};
+		runtime.register_program(LOADER_PROGRAM_ID, LoaderProgram);
+		runtime.register_program(SYSTEM_PROGRAM_ID, SystemProgram);
+		runtime
+	}
+
+	// keep program accounts in `store` instead of memory
+	pub fn with_account_store(mut self, store: Box<dyn AccountStore>) -> Self {
+		self.accounts = store;
+		self
+	}
+
+	/// Persisted state of `pubkey`, as of the last successful transaction
+	pub fn account(&self, pubkey: &Pubkey) -> Result<Option<StoredAccount>, RuntimeError> {
+		self.accounts.get_account(pubkey)
+	}
+
+	pub fn balance(&self, account: &Pubkey) -> u64 {
+		self.balances.get(account).copied().unwrap_or(0)
+	}
+
+	pub fn credit(&mut self, account: Pubkey, amount: u64) {
+		let balance = self.balances.entry(account).or_insert(0);
+		*balance = balance.saturating_add(amount);
+	}
+
+	/// Parameters in effect at the current clock
+	pub fn config(&self) -> &RuntimeConfig {
+		self.params.at(self.clock)
+	}
+
+	/// Parameters in effect at `height`, for replaying historical blocks
+	pub fn config_at(&self, height: u64) -> &RuntimeConfig {
+		self.params.at(height)
+	}
+
+	pub fn params(&self) -> &ParamsSchedule {
+		&self.params
 	}
 
 	//register a native program
 	pub fn register_program<P: Program+ 'static >(&mut self, program_id: Pubkey, program: P){
-		self.programs.insert(program_id, Arc::new(program));
+		Arc::make_mut(&mut self.programs).insert(program_id, Arc::new(program));
+		self.deployed.remove(&program_id);
+	}
+
+	//compile a WASM module and register it like a native program; see crate::wasm for the ABI
+	pub fn deploy_wasm(&mut self, program_id: Pubkey, code: &[u8]) -> Result<(), RuntimeError>{
+		let program = WasmProgram::new(code)
+			.map_err(|e| RuntimeError::ProgramError(e.to_string()))?;
+		self.register_program(program_id, program);
+		Ok(())
+	}
+
+	//compile the deployed programs `tx` refers to that aren't loaded yet
+	fn load_deployed(&mut self, tx: &Transaction) -> Result<(), RuntimeError>{
+		let referenced: Vec<Pubkey> = tx.instructions.iter().map(|instr| instr.program_id)
+			.chain(tx.accounts.iter().map(|meta| meta.pubkey))
+			.collect();
+		for program_id in referenced {
+			if self.programs.contains_key(&program_id) {
+				continue;
+			}
+			if let Some(stored) = self.accounts.get_account(&program_id)? {
+				self.install_deployed(program_id, &stored);
+			}
+		}
+		Ok(())
+	}
+
+	//(re)load the program in a loader account; registered programs are never replaced
+	fn install_deployed(&mut self, program_id: Pubkey, stored: &StoredAccount){
+		if stored.owner != LOADER_PROGRAM_ID
+			|| (self.programs.contains_key(&program_id) && !self.deployed.contains(&program_id)) {
+			return;
+		}
+		match ProgramAccount::from_account_data(&stored.data).and_then(|account| account.load()) {
+			Ok(program) => {
+				Arc::make_mut(&mut self.programs).insert(program_id, Arc::new(program));
+				self.deployed.insert(program_id);
+			}
+			//the loader checked the code compiles, so this is a corrupt store
+			Err(e) => info!("deployed program {:?} can't be loaded: {}", program_id, e),
+		}
 	}
 
 
 // execute a transaction. `signers` are pubkeys included as signers for this tx (runtime is expected to verify signatures)
 // before calling this; tests will use this param to simulate signature presence.
 
+	//
+	// The fee for the whole compute limit is reserved from the fee payer first, and the
+	// unused part refunded afterwards. A transaction that fails still pays for the
+	// compute it used, so failing transactions can't be used to load the network for free.
 	pub fn execute_transaction(
 		&mut self,
 		tx: &Transaction,
 		signers: &[Pubkey],
-		) ->Result<(), RuntimeError>{
+		) ->Result<GasReceipt, RuntimeError>{
+	        self.load_deployed(tx)?;
+	        let executed = self.run_transaction(tx, signers)?;
+	        self.apply(tx, executed)
+	}
+
+	/// Execute a block's transactions, each with its signers, and return their
+	/// results in order. Transactions that don't touch each other's accounts run
+	/// in parallel (see crate::scheduler); state and results are the same as
+	/// calling execute_transaction on each in turn.
+	pub fn execute_transactions(
+		&mut self,
+		txs: &[(Transaction, Vec<Pubkey>)],
+		) ->Vec<Result<GasReceipt, RuntimeError>>{
+	        let mut results: Vec<Option<Result<GasReceipt, RuntimeError>>> = txs.iter().map(|_| None).collect();
+
+	        for batch in schedule(txs.iter().map(|(tx, _)| tx), self.fee_collector) {
+	        	let loaded: Vec<Result<(), RuntimeError>> = batch.iter()
+	        		.map(|&index| self.load_deployed(&txs[index].0))
+	        		.collect();
+	        	let runtime = &*self;
+	        	let executed: Vec<Result<Executed, RuntimeError>> = batch.par_iter()
+	        		.zip(loaded)
+	        		.map(|(&index, loaded)| {
+	        			let (tx, signers) = &txs[index];
+	        			loaded.and_then(|()| runtime.run_transaction(tx, signers))
+	        		})
+	        		.collect();
+
+	        	//in block order, so the batch ends where running one by one would
+	        	for (&index, executed) in batch.iter().zip(executed) {
+	        		results[index] = Some(executed.and_then(|executed| self.apply(&txs[index].0, executed)));
+	        	}
+	        }
+
+	        results.into_iter()
+	        	.map(|result| result.expect("the schedule places every transaction"))
+	        	.collect()
+	}
+
+
+	//run `tx` against the current state without changing it; an error means it
+	//can't be charged at all
+	fn run_transaction(
+		&self,
+		tx: &Transaction,
+		signers: &[Pubkey],
+		) ->Result<Executed, RuntimeError>{
 	        // Here we allow caller to simulate that signers have been validated.
 	        // In production: verify signatures, check fee payer balance, nonce/recent-blockhash, etc.
 	        // For now, sample check: require fee_payer to be present in signers.
 	        if !signers.iter().any(|s| s==&tx.fee_payer) {
 	        	return Err(RuntimeError::SignatureVerificationFailed);
 	        }
+	        let _span = debug_span!("execute", fee_payer = ?tx.fee_payer).entered();
+
+	        //reserve the fee for the whole limit at the prices in effect at this height
+	        let config = self.config().clone();
+	        let compute_limit = config.compute_limit(tx);
+	        let price = config.base_fee.saturating_add(tx.priority_fee);
+	        let reserved = compute_limit.saturating_mul(price);
+	        let available = self.balance(&tx.fee_payer);
+	        if available < reserved {
+	        	return Err(RuntimeError::InsufficientFunds { required: reserved, available });
+	        }
+
+	        let mut before: HashMap<Pubkey, u64> = tx.accounts.iter()
+	        	.map(|meta| (meta.pubkey, self.balance(&meta.pubkey)))
+	        	.collect();
+	        before.insert(tx.fee_payer, available);
+	        let mut balances = before.clone();
+	        balances.insert(tx.fee_payer, available - reserved);
+
+	        let tx_seed = self.randomness.transaction_seed(tx);
+	        let mut ctx = RuntimeContext{
+	        	remaining_compute: compute_limit,
+	        	clock: self.clock,
+	        	seed: [0u8; 32],
+	        	draws: 0,
+	        	gas: config.gas.clone(),
+	        	programs: Arc::clone(&self.programs),
+	        	call_stack: Vec::new(),
+	        	instruction: 0,
+	        	logs: Vec::new(),
+	        	balances,
+	        	storage_deposit_per_byte: config.storage_deposit_per_byte,
+	        };
+	        let changes = self.run_instructions(tx, &tx_seed, &mut ctx);
+
+	        //bill what was used, refund the rest
+	        let compute_used = compute_limit - ctx.remaining_compute;
+	        let receipt = GasReceipt{
+	        	compute_limit,
+	        	compute_used,
+	        	base_fee_paid: compute_used.saturating_mul(config.base_fee),
+	        	priority_fee_paid: compute_used.saturating_mul(tx.priority_fee),
+	        	refunded: ctx.remaining_compute.saturating_mul(price),
+	        	logs: std::mem::take(&mut ctx.logs),
+	        };
+	        let changes = changes.map(|mut changes| {
+	        	let payer = ctx.balances.entry(tx.fee_payer).or_insert(0);
+	        	*payer = payer.saturating_add(receipt.refunded);
+	        	changes.balances = ctx.balances.into_iter()
+	        		.filter(|(pubkey, balance)| before.get(pubkey) != Some(balance))
+	        		.collect();
+	        	changes
+	        });
+
+	        debug!(compute_used, ok = changes.is_ok(), "executed transaction");
+	        Ok(Executed{ receipt, changes })
+	}
+
+	//write what a transaction did into the runtime's state and pay its fees
+	fn apply(&mut self, tx: &Transaction, executed: Executed) ->Result<GasReceipt, RuntimeError>{
+	        let Executed{ receipt, changes } = executed;
+	        let result = changes.and_then(|changes| self.commit(changes));
+	        if result.is_err() {
+	        	//a failed transaction changes nothing but pays for what it used
+	        	let charged = receipt.base_fee_paid.saturating_add(receipt.priority_fee_paid);
+	        	let balance = self.balance(&tx.fee_payer).saturating_sub(charged);
+	        	self.balances.insert(tx.fee_payer, balance);
+	        }
+	        if let Some(collector) = self.fee_collector {
+	        	self.credit(collector, receipt.priority_fee_paid);
+	        }
+	        result.map(|()| receipt)
+	}
+
+	//only a successful transaction changes accounts, all of them at once
+	fn commit(&mut self, changes: Changes) ->Result<(), RuntimeError>{
+	        let Changes{ accounts, params, balances } = changes;
+	        //deployments and upgrades run from the next transaction on
+	        let programs: Vec<(Pubkey, StoredAccount)> = accounts.iter()
+	        	.filter(|(_, acct)| acct.owner == LOADER_PROGRAM_ID)
+	        	.cloned()
+	        	.collect();
+	        if !accounts.is_empty() {
+	        	self.accounts.commit(accounts)?;
+	        }
+	        for (program_id, stored) in &programs {
+	        	self.install_deployed(*program_id, stored);
+	        }
+	        if let Some(params) = params {
+	        	self.params = params;
+	        }
+	        self.balances.extend(balances);
+	        Ok(())
+	}
+
+
+	fn run_instructions(
+		&self,
+		tx: &Transaction,
+		tx_seed: &[u8; 32],
+		ctx: &mut RuntimeContext,
+		) ->Result<Changes, RuntimeError>{
 
 	        // Build account infos map (pubkey -> AccountInfo). We'll clone metadata into AccountInfo
 	        // The transaction's AccountMeta list is the authoritative ordering of accounts for programs.
@@ -116,31 +574,38 @@ impl Runtime {
 	        let mut account_map: HashMap<Pubkey, AccountInfo> = HashMap::new();
 
 	        for meta in &tx.accounts {
-	        	//initialize empty data for account unless it already has data in map(test harness may pre-populate)
+	        	//stored accounts keep their owner; a new one starts empty, owned as the transaction says
+	        	let stored = if meta.pubkey == PARAMS_ACCOUNT {
+	        		//the params account is runtime state, so its contents are always known
+	        		StoredAccount{ owner: PARAMS_PROGRAM_ID, data: self.params.try_to_vec().unwrap_or_default() }
+	        	} else {
+	        		self.accounts.get_account(&meta.pubkey)?
+	        			.unwrap_or(StoredAccount{ owner: meta.owner, data: vec![] })
+	        	};
 	        	let ai = AccountInfo{
 	        		pubkey: meta.pubkey,
-	        		owner: meta.owner,
+	        		owner: stored.owner,
 	        		is_signer: meta.is_signer,
 	        		is_writable: meta.is_writable,
-	        		data: vec![], //this would be the accounts persisted bytes in real node.
+	        		data: stored.data,
 	        	};
 
 	        	account_map.insert(meta.pubkey, ai)
 	        }
+	        //as loaded, to find what the transaction changed
+	        let loaded = account_map.clone();
+	        let staged = ctx.balances.clone();
 
 
-	        //prepare runtime context
-	        let mut ctx = RuntimeContext{
-	        	remaining_compute: self.config.max_compute_units,
-	        	clock: self.clock,
-	        };
-
+	        for (index, instr) in tx.instruction.iter().enumerate() {
+	        	let _span = debug_span!("instruction", index, program = ?instr.program_id).entered();
+	        	ctx.seed = randomness::instruction_seed(tx_seed, index as u32);
+	        	ctx.draws = 0;
+	        	ctx.call_stack = vec![instr.program_id];
+	        	ctx.instruction = index as u32;
 
-	        for instr in &tx.instruction {
-	        	//compute cost estimation: instr_cost + byte_cost * data_len
-	        	let data_cost = (instr.data.len() as u64).saturating_mul(self.config.byte_cost);
-	        	let total_cost = self.config.instr_cost.saturating_add(data_cost);
-	        	ctx.consume(total_cost)?;
+	        	//dispatch cost: flat + per data byte + per account
+	        	ctx.consume(ctx.gas.instruction_cost(instr))?;
 
 
 	        	// find program
@@ -167,12 +632,23 @@ impl Runtime {
 
 	        	}
 
-	        	match program.process(&mut accounts_for_instr, &instr.data, &mut ctx){
+	        	match program.process(&mut accounts_for_instr, &instr.data, ctx){
 	        		Ok(()) =>{
-	        			//commit account changes back into account_map for writable accounts
-	        			for acct in accounts_for_instr.into_iter() {
+	        			//carry account changes over to the next instruction for writable accounts
+	        			for mut acct in accounts_for_instr.into_iter() {
+	        				let current = &account_map[&acct.pubkey];
+	        				//programs can't reassign accounts, and only the owner may change the data
+	        				let assigned = acct.owner;
+	        				acct.owner = current.owner;
+	        				if acct.data != current.data && acct.owner != instr.program_id {
+	        					return Err(RuntimeError::AccountNotOwned);
+	        				}
+	        				//but the system program hands over the accounts it owns
+	        				if instr.program_id == SYSTEM_PROGRAM_ID && acct.owner == SYSTEM_PROGRAM_ID {
+	        					acct.owner = assigned;
+	        				}
 	        				//only update if writable(conservative)
-	        				if acct.is_writable{
+	        				if acct.is_writable {
 	        					account_map/insert(acct.pubkey, acct);
 	        				}
 	        			}
@@ -183,7 +659,33 @@ impl Runtime {
 	        	}
 	        }	
 
-	        Ok(())
+	        //changed accounts have to keep the deposit for their data
+	        for acct in account_map.values().filter(|acct| acct.pubkey != PARAMS_ACCOUNT) {
+	        	let changed = acct.data != loaded[&acct.pubkey].data
+	        		|| ctx.balance(&acct.pubkey) < staged.get(&acct.pubkey).copied().unwrap_or(0);
+	        	let required = ctx.storage_deposit(acct.data.len());
+	        	if changed && ctx.balance(&acct.pubkey) < required {
+	        		return Err(RuntimeError::InsufficientDeposit { required, available: ctx.balance(&acct.pubkey) });
+	        	}
+	        }
+
+	        //only a successful transaction changes the parameters
+	        let params = match account_map.remove(&PARAMS_ACCOUNT) {
+	        	Some(params) => Some(ParamsSchedule::try_from_slice(&params.data)
+	        		.map_err(|e| RuntimeError::InvalidInstructionData(format!("params account: {:?}", e)))?),
+	        	None => None,
+	        };
+
+	        //the changed accounts, committed if the transaction succeeds
+	        let accounts: Vec<(Pubkey, StoredAccount)> = account_map.into_values()
+	        	.filter(|acct| {
+	        		let before = &loaded[&acct.pubkey];
+	        		acct.is_writable && (acct.data != before.data || acct.owner != before.owner)
+	        	})
+	        	.map(|acct| (acct.pubkey, StoredAccount{ owner: acct.owner, data: acct.data }))
+	        	.collect();
+
+	        Ok(Changes{ accounts, params, balances: Vec::new() })
 	}
 
 }
@@ -193,8 +695,10 @@ impl Runtime {
 
 // execute_transaction requires the caller to have verified signatures; for the test harness we simulate signers.
 
-// Account data persistence is modeled by the account_map. In a real node this map should come from your on-disk or DB-backed account store and AccountInfo.data should be a mutable reference to persistent storage to avoid copies.
+// Account data comes from the runtime's AccountStore (crate::store) and is staged in account_map for the transaction; AccountInfo.data is a copy, so a failing transaction never touches the store.
+
+// The compute model is a gas schedule (crate::gas): per-instruction, per-byte, per-account and per-host-call costs. This protects against extremely-large instruction payloads and allows programs to monitor ctx.remaining_compute.
 
-// The compute model is intentionally simple: a flat per-instruction cost plus per-byte cost. This protects against extremely-large instruction payloads and allows programs to monitor ctx.remaining_compute.
+// execute_transactions runs a block's non-conflicting transactions in parallel: a transaction runs against the state as of its batch and returns its writes, which are applied in block order, see crate::scheduler.
 
-// There are clear extension points: before instruction execution you should check fees, nonce/recent-blockhash, and payer balance, and after execution apply fee transfers and rent accounting.
\ No newline at end of file
+// There are clear extension points: before instruction execution you should check fees, nonce/recent-blockhash, and payer balance. Storage deposits are checked after execution, see crate::system.
\ No newline at end of file
@@ -0,0 +1,102 @@
+//! Gas schedule and fees.
+//!
+//! Every operation a transaction makes the node do costs compute units: a
+//! flat amount per instruction, per byte of instruction data and per account
+//! passed in, plus per host call (logging, random draws, account writes,
+//! calls into other programs, which also pay their own instruction cost) and,
+//! for WASM programs, one unit per unit of fuel. A transaction sets
+//! its own compute limit and pays `base_fee + priority_fee` per unit: the whole
+//! limit is reserved from the fee payer up front and the unused part refunded.
+//! The base fee is burned; the priority fee goes to the block producer.
+
+use crate::logs::ProgramLog;
+use crate::types::Instruction;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+
+/// Compute units charged per operation
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct GasSchedule {
+    /// flat cost of dispatching an instruction
+    pub instruction: u64,
+    /// per byte of instruction data
+    pub instruction_byte: u64,
+    /// per account passed to an instruction
+    pub account: u64,
+    /// per random draw
+    pub random_draw: u64,
+    /// flat cost of a log call
+    pub log: u64,
+    /// per byte logged
+    pub log_byte: u64,
+    /// per byte a WASM program writes to an account
+    pub account_write_byte: u64,
+    /// flat cost of a cross-program invocation, on top of its instruction cost
+    pub invoke: u64,
+}
+
+impl Default for GasSchedule {
+    fn default() -> Self {
+        Self {
+            instruction: 500,
+            instruction_byte: 10,
+            account: 100,
+            random_draw: 100,
+            log: 100,
+            log_byte: 1,
+            account_write_byte: 1,
+            invoke: 1000,
+        }
+    }
+}
+
+
+/// Host functions programs call through the runtime context
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostCall {
+    Log { bytes: usize },
+    RandomDraw,
+    AccountWrite { bytes: usize },
+    Invoke,
+}
+
+impl GasSchedule {
+    /// Cost of dispatching `instr`, charged before the program runs
+    pub fn instruction_cost(&self, instr: &Instruction) -> u64 {
+        let data = (instr.data.len() as u64).saturating_mul(self.instruction_byte);
+        let accounts = (instr.accounts.len() as u64).saturating_mul(self.account);
+        self.instruction.saturating_add(data).saturating_add(accounts)
+    }
+
+    pub fn host_call_cost(&self, call: HostCall) -> u64 {
+        match call {
+            HostCall::Log { bytes } => self.log.saturating_add((bytes as u64).saturating_mul(self.log_byte)),
+            HostCall::RandomDraw => self.random_draw,
+            HostCall::AccountWrite { bytes } => (bytes as u64).saturating_mul(self.account_write_byte),
+            HostCall::Invoke => self.invoke,
+        }
+    }
+}
+
+
+/// What a transaction was billed, and what its programs logged
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GasReceipt {
+    /// compute units reserved (the transaction's limit)
+    pub compute_limit: u64,
+    pub compute_used: u64,
+    /// burned
+    pub base_fee_paid: u64,
+    /// paid to the block producer
+    pub priority_fee_paid: u64,
+    /// returned to the fee payer for unused compute
+    pub refunded: u64,
+    /// logs and events, in the order they were recorded; see crate::logs
+    pub logs: Vec<ProgramLog>,
+}
+
+impl GasReceipt {
+    pub fn total_fee(&self) -> u64 {
+        self.base_fee_paid.saturating_add(self.priority_fee_paid)
+    }
+}
@@ -2,8 +2,26 @@ pub mod types;
 pub mod program;
 pub mod executor;
 pub mod adapters;
+pub mod randomness;
+pub mod params;
+pub mod gas;
+pub mod wasm;
+pub mod store;
+pub mod loader;
+pub mod logs;
+pub mod system;
+pub mod scheduler;
 
 pub use types::*;
 pub use program::{Program, ProgramError};
-pub use executor::{Runtime, RuntimeError, RuntimeConfig, RuntimeContext};
+pub use executor::{Runtime, RuntimeError, RuntimeConfig, RuntimeContext, MAX_INVOKE_DEPTH, PROGRAM_LOG_TARGET};
+pub use randomness::RandomnessSource;
+pub use gas::{GasReceipt, GasSchedule, HostCall};
+pub use logs::{LogKind, ProgramLog};
+pub use scheduler::{schedule, AccessSet};
+pub use system::{SystemInstruction, SystemProgram, MAX_ACCOUNT_DATA, SYSTEM_PROGRAM_ID};
+pub use params::{ParamsInstruction, ParamsProgram, ParamsSchedule, PARAMS_ACCOUNT, PARAMS_PROGRAM_ID};
+pub use wasm::WasmProgram;
+pub use store::{AccountStore, MemoryAccountStore, StoredAccount};
+pub use loader::{LoaderInstruction, LoaderProgram, ProgramAccount, LOADER_PROGRAM_ID};
 pub use adapters::bank_adapter::BankProgramAdapter;
\ No newline at end of file
@@ -0,0 +1,135 @@
+//! On-chain program deployment.
+//!
+//! A deployed program lives in an account owned by the loader: its pubkey is
+//! the program id, and its data is a borsh [`ProgramAccount`] holding the WASM
+//! code and the key allowed to upgrade it. The runtime compiles a deployed
+//! program the first time a transaction refers to it and recompiles it when an
+//! upgrade commits, so the new code runs from the next transaction on.
+//!
+//! Code is compiled when it is deployed or upgraded, so a program that would
+//! never load can't be stored, and the compile is paid for per byte like any
+//! other account write.
+
+use crate::executor::RuntimeContext;
+use crate::gas::HostCall;
+use crate::program::{Program, ProgramError};
+use crate::types::{AccountInfo, Pubkey};
+use crate::wasm::WasmProgram;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+
+/// Program that deploys and upgrades programs
+pub const LOADER_PROGRAM_ID: Pubkey = [10u8; 32];
+
+
+/// Data of a program account
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ProgramAccount {
+    /// key allowed to upgrade the program; None once it is made immutable
+    pub authority: Option<Pubkey>,
+    /// WASM module, see crate::wasm for the ABI
+    pub code: Vec<u8>,
+}
+
+impl ProgramAccount {
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::try_from_slice(data)
+            .map_err(|e| ProgramError::Custom(format!("corrupt program account: {:?}", e)))
+    }
+
+    /// Compile the code
+    pub fn load(&self) -> Result<WasmProgram, ProgramError> {
+        WasmProgram::new(&self.code)
+    }
+}
+
+
+/// Instructions of the loader program
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub enum LoaderInstruction {
+    /// Deploy `code` to a new program account, upgradable by `authority`.
+    /// The program account signs, so nobody can deploy to a key they don't hold.
+    /// Accounts: [program account (writable, signer), authority (signer)]
+    DeployProgram { code: Vec<u8> },
+    /// Replace the program's code.
+    /// Accounts: [program account (writable), authority (signer)]
+    Upgrade { code: Vec<u8> },
+    /// Hand upgrades over to `new_authority`, or make the program immutable with None.
+    /// Accounts: [program account (writable), authority (signer)]
+    SetAuthority { new_authority: Option<Pubkey> },
+}
+
+
+/// Native program managing program accounts; the runtime registers it itself
+pub struct LoaderProgram;
+
+impl Program for LoaderProgram {
+    fn process(
+        &self,
+        accounts: &mut [AccountInfo],
+        data: &[u8],
+        ctx: &mut RuntimeContext,
+    ) -> Result<(), ProgramError> {
+        let instruction = LoaderInstruction::try_from_slice(data)
+            .map_err(|e| ProgramError::Custom(format!("borsh decode: {:?}", e)))?;
+
+        let [program, authority] = accounts else {
+            return Err(ProgramError::Custom("expected program and authority accounts".into()));
+        };
+        if !program.is_writable {
+            return Err(ProgramError::Custom("program account must be passed writable".into()));
+        }
+        if !authority.is_signer {
+            return Err(ProgramError::Custom("missing authority signature".into()));
+        }
+
+        match instruction {
+            LoaderInstruction::DeployProgram { code } => {
+                if !program.data.is_empty() {
+                    return Err(ProgramError::Custom("program account is already in use".into()));
+                }
+                if !program.is_signer {
+                    return Err(ProgramError::Custom("program account must sign its deployment".into()));
+                }
+                let account = ProgramAccount { authority: Some(authority.pubkey), code };
+                write_code(program, &account, ctx)
+            }
+            LoaderInstruction::Upgrade { code } => {
+                let mut account = authorized(program, authority)?;
+                account.code = code;
+                write_code(program, &account, ctx)
+            }
+            LoaderInstruction::SetAuthority { new_authority } => {
+                let mut account = authorized(program, authority)?;
+                account.authority = new_authority;
+                program.data = encode(&account)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+
+//the deployed program, if `authority` may change it
+fn authorized(program: &AccountInfo, authority: &AccountInfo) -> Result<ProgramAccount, ProgramError> {
+    let account = ProgramAccount::from_account_data(&program.data)?;
+    match account.authority {
+        Some(key) if key == authority.pubkey => Ok(account),
+        Some(_) => Err(ProgramError::Custom("not the program's upgrade authority".into())),
+        None => Err(ProgramError::Custom("program is immutable".into())),
+    }
+}
+
+//charge for and check the code compiles before storing it
+fn write_code(program: &mut AccountInfo, account: &ProgramAccount, ctx: &mut RuntimeContext) -> Result<(), ProgramError> {
+    ctx.consume(ctx.gas.host_call_cost(HostCall::AccountWrite { bytes: account.code.len() }))
+        .map_err(|e| ProgramError::Custom(e.to_string()))?;
+    account.load()?;
+    program.data = encode(account)?;
+    Ok(())
+}
+
+fn encode(account: &ProgramAccount) -> Result<Vec<u8>, ProgramError> {
+    account.try_to_vec()
+        .map_err(|e| ProgramError::Custom(format!("borsh encode: {:?}", e)))
+}
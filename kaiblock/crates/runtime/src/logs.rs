@@ -0,0 +1,32 @@
+//! Program logs.
+//!
+//! Programs record what they did through the runtime context: free-form
+//! messages with `ctx.log` and structured events, a 32-byte topic plus data,
+//! with `ctx.emit`. Both are charged like any other host call and end up in
+//! the transaction's receipt in the order they were recorded, tagged with the
+//! instruction and program that recorded them. Logs go with the writes they
+//! describe: a call that fails drops its logs, and a failed transaction has
+//! no receipt to carry any.
+
+use crate::types::Pubkey;
+use borsh::{BorshDeserialize, BorshSerialize};
+
+
+/// What a program recorded
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum LogKind {
+	/// a message from `ctx.log`
+	Message(String),
+	/// a structured event from `ctx.emit`
+	Event { topic: [u8; 32], data: Vec<u8> },
+}
+
+/// A log recorded while a transaction ran
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ProgramLog {
+	/// index of the top-level instruction in the transaction
+	pub instruction: u32,
+	/// the program that recorded it, which may have been invoked by another
+	pub program: Pubkey,
+	pub kind: LogKind,
+}
@@ -0,0 +1,126 @@
+//! On-chain runtime parameters.
+//!
+//! The compute budget and instruction costs live in the params account
+//! instead of node configuration, so every node charges the same costs and
+//! they can be changed without a restart. Changes are scheduled for a future
+//! height by the params authority; earlier entries are never rewritten, so
+//! replaying an old block charges what was charged at the time.
+
+use crate::executor::{RuntimeConfig, RuntimeContext};
+use crate::program::{Program, ProgramError};
+use crate::types::{AccountInfo, Pubkey};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+
+/// Program that schedules parameter changes
+pub const PARAMS_PROGRAM_ID: Pubkey = [8u8; 32];
+
+/// Account holding the borsh-encoded [`ParamsSchedule`]
+pub const PARAMS_ACCOUNT: Pubkey = [9u8; 32];
+
+
+/// Runtime parameters with the height each set takes effect at
+#[derive(Debug, Clone, PartialEq, BorshSerialize, BorshDeserialize)]
+pub struct ParamsSchedule {
+    /// (activation height, parameters), sorted by height; the first is at 0
+    entries: Vec<(u64, RuntimeConfig)>,
+}
+
+impl ParamsSchedule {
+    /// Schedule starting with `genesis` at height 0
+    pub fn new(genesis: RuntimeConfig) -> Self {
+        Self { entries: vec![(0, genesis)] }
+    }
+
+    /// Parameters in effect at `height`
+    pub fn at(&self, height: u64) -> &RuntimeConfig {
+        let index = self.entries.partition_point(|(activation, _)| *activation <= height);
+        &self.entries[index.saturating_sub(1)].1
+    }
+
+    /// Every (activation height, parameters) pair, oldest first
+    pub fn history(&self) -> &[(u64, RuntimeConfig)] {
+        &self.entries
+    }
+
+    /// Add `config` from `activation_height` on. It must come after every
+    /// scheduled change, so history is only ever appended to.
+    pub fn schedule(&mut self, activation_height: u64, config: RuntimeConfig) -> Result<(), ProgramError> {
+        let latest = self.entries.last().map(|(height, _)| *height).unwrap_or(0);
+        if activation_height <= latest {
+            return Err(ProgramError::Custom(format!(
+                "activation height {} must be after the latest scheduled change at {}",
+                activation_height, latest
+            )));
+        }
+        if config.max_compute_units == 0 {
+            return Err(ProgramError::Custom("compute budget can't be zero".into()));
+        }
+        self.entries.push((activation_height, config));
+        Ok(())
+    }
+}
+
+impl Default for ParamsSchedule {
+    fn default() -> Self {
+        Self::new(RuntimeConfig::default())
+    }
+}
+
+
+/// Instructions of the params program
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub enum ParamsInstruction {
+    /// Use `config` from `activation_height` on.
+    /// Accounts: [params account (writable), authority (signer)]
+    Update { activation_height: u64, config: RuntimeConfig },
+}
+
+
+/// Native program letting `authority` (a governance or upgrade key) change
+/// the runtime parameters
+pub struct ParamsProgram {
+    authority: Pubkey,
+}
+
+impl ParamsProgram {
+    pub fn new(authority: Pubkey) -> Self {
+        Self { authority }
+    }
+}
+
+impl Program for ParamsProgram {
+    fn process(
+        &self,
+        accounts: &mut [AccountInfo],
+        data: &[u8],
+        ctx: &mut RuntimeContext,
+    ) -> Result<(), ProgramError> {
+        let ParamsInstruction::Update { activation_height, config } = ParamsInstruction::try_from_slice(data)
+            .map_err(|e| ProgramError::Custom(format!("borsh decode: {:?}", e)))?;
+
+        let [params, authority] = accounts else {
+            return Err(ProgramError::Custom("expected params and authority accounts".into()));
+        };
+        if params.pubkey != PARAMS_ACCOUNT || !params.is_writable {
+            return Err(ProgramError::Custom("params account must be passed writable".into()));
+        }
+        if authority.pubkey != self.authority || !authority.is_signer {
+            return Err(ProgramError::Custom("missing params authority signature".into()));
+        }
+        // a change can't reach back into blocks already executed
+        if activation_height <= ctx.clock {
+            return Err(ProgramError::Custom(format!(
+                "activation height {} is not after the current height {}",
+                activation_height, ctx.clock
+            )));
+        }
+
+        let mut schedule = ParamsSchedule::try_from_slice(&params.data)
+            .map_err(|e| ProgramError::Custom(format!("corrupt params account: {:?}", e)))?;
+        schedule.schedule(activation_height, config)?;
+        params.data = schedule.try_to_vec()
+            .map_err(|e| ProgramError::Custom(format!("borsh encode: {:?}", e)))?;
+        Ok(())
+    }
+}
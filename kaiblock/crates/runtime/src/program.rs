@@ -2,8 +2,7 @@ use crate::types::AccountInfo;
 use thiserror::Error;
 
 /// Program trait: implement this for any native program you want to register
-/// with the runtime. For WASM-backed programs you will implement a small
-/// shim that loads the module and provides the same `process` signature.
+/// with the runtime. WASM modules run behind the same trait, see crate::wasm.
 
 #[derive(Error, Debug)]
 pubb enum ProgramError {
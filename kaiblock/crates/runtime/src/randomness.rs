@@ -0,0 +1,76 @@
+//! Deterministic randomness for programs.
+//!
+//! Every node executing the same transaction in the same block derives the same
+//! seeds, so programs can draw "random" values without breaking consensus.
+//!
+//! NOT SAFE FOR ADVERSARIAL SETTINGS. Seeds are predictable to anyone who knows
+//! the inputs before the transaction executes, and a block producer can grind
+//! the block hash (or withhold a block) to steer outcomes. Use this for tests,
+//! games with nothing at stake and tie-breaking, never for lotteries or anything
+//! whose outcome is worth more than the cost of producing a block.
+
+use crate::types::Transaction;
+use borsh::BorshSerialize;
+use sha2::{Digest, Sha256};
+
+
+/// Domain tag mixed into every seed so they can't collide with other hashes
+const SEED_DOMAIN: &[u8] = b"kaiblock-runtime-seed";
+
+
+/// Where per-transaction seeds come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomnessSource {
+    /// hash of the block the transaction is executed in (proof of work)
+    BlockHash([u8; 32]),
+    /// VRF output of the slot leader (proof of stake)
+    Vrf([u8; 32]),
+}
+
+impl Default for RandomnessSource {
+    fn default() -> Self {
+        RandomnessSource::BlockHash([0u8; 32])
+    }
+}
+
+impl RandomnessSource {
+    /// Seed of one transaction: H(domain, source, transaction id)
+    pub fn transaction_seed(&self, tx: &Transaction) -> [u8; 32] {
+        let (tag, bytes): (u8, &[u8; 32]) = match self {
+            RandomnessSource::BlockHash(hash) => (0, hash),
+            RandomnessSource::Vrf(output) => (1, output),
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(SEED_DOMAIN);
+        hasher.update([tag]);
+        hasher.update(bytes);
+        hasher.update(transaction_id(tx));
+        hasher.finalize().into()
+    }
+}
+
+
+/// Seed of one instruction within a transaction
+pub fn instruction_seed(tx_seed: &[u8; 32], instruction_index: u32) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(tx_seed);
+    hasher.update(instruction_index.to_le_bytes());
+    hasher.finalize().into()
+}
+
+
+/// Draw `counter` of an instruction seed; successive draws are independent
+pub fn draw(instruction_seed: &[u8; 32], counter: u64) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(instruction_seed);
+    hasher.update(counter.to_le_bytes());
+    hasher.finalize().into()
+}
+
+
+/// Hash of the borsh-encoded transaction
+pub fn transaction_id(tx: &Transaction) -> [u8; 32] {
+    let encoded = tx.try_to_vec().unwrap_or_default();
+    Sha256::digest(encoded).into()
+}
@@ -0,0 +1,96 @@
+//! Parallel transaction scheduling.
+//!
+//! A transaction can only touch the accounts it lists, so the listed accounts
+//! tell which transactions can run side by side: two conflict when one writes
+//! an account the other reads or writes. The fee payer's balance is written
+//! by every transaction, instruction programs are read, and the fee collector
+//! counts as read since every transaction credits it.
+//!
+//! [`schedule`] splits a block's transactions into batches run one after the
+//! other. Transactions in a batch don't conflict, so running them in parallel
+//! and applying their results in block order gives the same state as running
+//! the whole block in order; a transaction goes in the batch after the last
+//! one holding a transaction it conflicts with. Writing the params account
+//! changes the rules for every later transaction, so such a transaction runs
+//! in a batch of its own.
+
+use crate::params::PARAMS_ACCOUNT;
+use crate::types::{Pubkey, Transaction};
+use std::collections::{HashMap, HashSet};
+
+
+/// Accounts a transaction reads and writes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccessSet {
+	pub reads: HashSet<Pubkey>,
+	pub writes: HashSet<Pubkey>,
+}
+
+impl AccessSet {
+	pub fn of(tx: &Transaction, fee_collector: Option<Pubkey>) -> Self {
+		let mut access = Self::default();
+		access.writes.insert(tx.fee_payer);
+		for meta in &tx.accounts {
+			if meta.is_writable {
+				access.writes.insert(meta.pubkey);
+			}
+		}
+		let reads = tx.accounts.iter().map(|meta| meta.pubkey)
+			.chain(tx.instructions.iter().map(|instr| instr.program_id))
+			.chain(fee_collector);
+		access.reads = reads.filter(|pubkey| !access.writes.contains(pubkey)).collect();
+		access
+	}
+
+	/// Whether running both in either order could give different results
+	pub fn conflicts_with(&self, other: &AccessSet) -> bool {
+		self.writes.iter().any(|pubkey| other.writes.contains(pubkey) || other.reads.contains(pubkey))
+			|| other.writes.iter().any(|pubkey| self.reads.contains(pubkey))
+	}
+
+	//whether the transaction has to run on its own
+	fn is_exclusive(&self) -> bool {
+		self.writes.contains(&PARAMS_ACCOUNT)
+	}
+}
+
+
+/// Indexes of `txs` in batches to run one after the other; a batch holds no
+/// conflicting transactions and lists them in block order
+pub fn schedule<'a>(txs: impl IntoIterator<Item = &'a Transaction>, fee_collector: Option<Pubkey>) -> Vec<Vec<usize>> {
+	let mut batches: Vec<Vec<usize>> = Vec::new();
+	//batch of the last transaction writing / reading each account
+	let mut last_write: HashMap<Pubkey, usize> = HashMap::new();
+	let mut last_read: HashMap<Pubkey, usize> = HashMap::new();
+	//no transaction may run before this batch
+	let mut floor = 0;
+
+	for (index, tx) in txs.into_iter().enumerate() {
+		let access = AccessSet::of(tx, fee_collector);
+		let after_writes = access.reads.iter().chain(&access.writes)
+			.filter_map(|pubkey| last_write.get(pubkey))
+			.map(|batch| batch + 1);
+		let after_reads = access.writes.iter()
+			.filter_map(|pubkey| last_read.get(pubkey))
+			.map(|batch| batch + 1);
+		let mut batch = after_writes.chain(after_reads).fold(floor, usize::max);
+
+		if access.is_exclusive() {
+			batch = batch.max(batches.len());
+			floor = batch + 1;
+		}
+
+		if batch == batches.len() {
+			batches.push(Vec::new());
+		}
+		batches[batch].push(index);
+		for pubkey in access.writes {
+			last_write.insert(pubkey, batch);
+		}
+		for pubkey in access.reads {
+			let last = last_read.entry(pubkey).or_insert(batch);
+			*last = (*last).max(batch);
+		}
+	}
+	batches
+}
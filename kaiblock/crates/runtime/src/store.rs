@@ -0,0 +1,65 @@
+//! Where program accounts live between transactions.
+//!
+//! The runtime reads every account a transaction lists before the first
+//! instruction and writes the changed ones back in one commit after the last,
+//! so a failing transaction leaves no trace and a crash mid-commit can't leave
+//! half a transaction behind.
+
+use crate::executor::RuntimeError;
+use crate::types::Pubkey;
+use borsh::{BorshDeserialize, BorshSerialize};
+use std::collections::HashMap;
+
+
+/// An account as persisted
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct StoredAccount {
+	/// the only program allowed to change `data`
+	pub owner: Pubkey,
+	pub data: Vec<u8>,
+}
+
+impl StoredAccount {
+	pub fn to_bytes(&self) -> Result<Vec<u8>, RuntimeError> {
+		self.try_to_vec()
+			.map_err(|e| RuntimeError::Storage(format!("encode account: {:?}", e)))
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, RuntimeError> {
+		Self::try_from_slice(bytes)
+			.map_err(|e| RuntimeError::Storage(format!("decode account: {:?}", e)))
+	}
+}
+
+
+/// Account persistence backend
+pub trait AccountStore: Send + Sync {
+	fn get_account(&self, pubkey: &Pubkey) -> Result<Option<StoredAccount>, RuntimeError>;
+
+	/// Write every account in `accounts` or, on error, none of them
+	fn commit(&mut self, accounts: Vec<(Pubkey, StoredAccount)>) -> Result<(), RuntimeError>;
+}
+
+
+/// Accounts kept in memory, for tests and ephemeral runtimes
+#[derive(Debug, Default)]
+pub struct MemoryAccountStore {
+	accounts: HashMap<Pubkey, StoredAccount>,
+}
+
+impl MemoryAccountStore {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+impl AccountStore for MemoryAccountStore {
+	fn get_account(&self, pubkey: &Pubkey) -> Result<Option<StoredAccount>, RuntimeError> {
+		Ok(self.accounts.get(pubkey).cloned())
+	}
+
+	fn commit(&mut self, accounts: Vec<(Pubkey, StoredAccount)>) -> Result<(), RuntimeError> {
+		self.accounts.extend(accounts);
+		Ok(())
+	}
+}
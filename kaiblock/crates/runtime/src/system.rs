@@ -0,0 +1,139 @@
+//! Account creation and native balances.
+//!
+//! The system program owns every account no other program has claimed. It
+//! allocates an account's data, hands the account over to the program that
+//! will own it, and moves native balances between accounts; it is the only
+//! program that can do either.
+//!
+//! Account data is paid for with a storage deposit: an account whose data a
+//! transaction changes, or whose balance it lowers, must be left holding at
+//! least `storage_deposit_per_byte` for every byte of its data, or the
+//! transaction fails. The deposit stays in the account's balance rather than
+//! being burned, so it is locked for as long as the data is stored.
+//!
+//! Accounts change owner only when the transaction calls the system program
+//! itself; an owner change made through a cross-program invocation is refused.
+
+use crate::executor::RuntimeContext;
+use crate::gas::HostCall;
+use crate::program::{Program, ProgramError};
+use crate::types::{AccountInfo, Pubkey};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+
+/// Program that creates accounts and moves native balances
+pub const SYSTEM_PROGRAM_ID: Pubkey = [11u8; 32];
+
+/// Largest account the system program allocates
+pub const MAX_ACCOUNT_DATA: u64 = 10 * 1024 * 1024;
+
+
+/// Instructions of the system program
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub enum SystemInstruction {
+    /// Fund a new account with `lamports`, give it `space` zeroed bytes and
+    /// assign it to `owner`. The new account signs, so nobody can create an
+    /// account at a key they don't hold, and has to be listed as owned by the
+    /// system program with no data.
+    /// Accounts: [funder (writable, signer), new account (writable, signer)]
+    CreateAccount { lamports: u64, space: u64, owner: Pubkey },
+    /// Move `lamports` of native balance.
+    /// Accounts: [from (writable, signer), to (writable)]
+    Transfer { lamports: u64 },
+    /// Give an unused system account `space` zeroed bytes.
+    /// Accounts: [account (writable, signer)]
+    Allocate { space: u64 },
+    /// Hand a system account over to `owner`.
+    /// Accounts: [account (writable, signer)]
+    Assign { owner: Pubkey },
+}
+
+
+/// Native program creating accounts; the runtime registers it itself
+pub struct SystemProgram;
+
+impl Program for SystemProgram {
+    fn process(
+        &self,
+        accounts: &mut [AccountInfo],
+        data: &[u8],
+        ctx: &mut RuntimeContext,
+    ) -> Result<(), ProgramError> {
+        let instruction = SystemInstruction::try_from_slice(data)
+            .map_err(|e| ProgramError::Custom(format!("borsh decode: {:?}", e)))?;
+
+        match instruction {
+            SystemInstruction::CreateAccount { lamports, space, owner } => {
+                let [funder, account] = accounts else {
+                    return Err(ProgramError::Custom("expected funder and new account".into()));
+                };
+                writable_signer(funder, "funder")?;
+                allocate(account, space, ctx)?;
+                transfer(funder, account, lamports, ctx)?;
+                account.owner = owner;
+                Ok(())
+            }
+            SystemInstruction::Transfer { lamports } => {
+                let [from, to] = accounts else {
+                    return Err(ProgramError::Custom("expected from and to accounts".into()));
+                };
+                writable_signer(from, "sender")?;
+                transfer(from, to, lamports, ctx)
+            }
+            SystemInstruction::Allocate { space } => {
+                let [account] = accounts else {
+                    return Err(ProgramError::Custom("expected one account".into()));
+                };
+                allocate(account, space, ctx)
+            }
+            SystemInstruction::Assign { owner } => {
+                let [account] = accounts else {
+                    return Err(ProgramError::Custom("expected one account".into()));
+                };
+                unclaimed(account)?;
+                account.owner = owner;
+                Ok(())
+            }
+        }
+    }
+}
+
+
+fn writable_signer(account: &AccountInfo, role: &str) -> Result<(), ProgramError> {
+    if !account.is_writable || !account.is_signer {
+        return Err(ProgramError::Custom(format!("{} must be passed writable and signing", role)));
+    }
+    Ok(())
+}
+
+//a signing account the system program still owns
+fn unclaimed(account: &AccountInfo) -> Result<(), ProgramError> {
+    writable_signer(account, "account")?;
+    if account.owner != SYSTEM_PROGRAM_ID {
+        return Err(ProgramError::Custom("account is owned by another program".into()));
+    }
+    Ok(())
+}
+
+//give an unused system account `space` zeroed bytes, charged like a write
+fn allocate(account: &mut AccountInfo, space: u64, ctx: &mut RuntimeContext) -> Result<(), ProgramError> {
+    unclaimed(account)?;
+    if !account.data.is_empty() {
+        return Err(ProgramError::Custom("account is already in use".into()));
+    }
+    if space > MAX_ACCOUNT_DATA {
+        return Err(ProgramError::Custom(format!("{} bytes is more than the {} an account may hold", space, MAX_ACCOUNT_DATA)));
+    }
+    ctx.consume(ctx.gas.host_call_cost(HostCall::AccountWrite { bytes: space as usize }))
+        .map_err(|e| ProgramError::Custom(e.to_string()))?;
+    account.data = vec![0u8; space as usize];
+    Ok(())
+}
+
+fn transfer(from: &AccountInfo, to: &AccountInfo, lamports: u64, ctx: &mut RuntimeContext) -> Result<(), ProgramError> {
+    if !to.is_writable {
+        return Err(ProgramError::Custom("recipient must be passed writable".into()));
+    }
+    ctx.move_lamports(&from.pubkey, &to.pubkey, lamports)
+        .map_err(|e| ProgramError::Custom(e.to_string()))
+}
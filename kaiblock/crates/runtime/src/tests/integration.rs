@@ -1,133 +0,0 @@
-use runtime::{Runtime, RuntimeConfig, types::*, adapters::bank_adapter::BankProgramAdapter, adapters::bank_adapter::BANK_PROGRAM_ID};
-use bank::instruction::BankInstruction;
-use bank::state::{Mint, TokenAccount, Pubkey as BankPubkey};
-use borsh::BorshSerialize;
-
-fn mk_pubkey(b: u8) -> [u8;32] {
-    let mut k = [0u8; 32];
-    k[0] = b;
-    k
-}
-
-#[test]
-fn test_runtime_with_bank_adapter_mint_and_transfer() {
-    // Setup runtime with default config
-    let mut runtime = Runtime::new(RuntimeConfig::default());
-    // register the bank program under BANK_PROGRAM_ID
-    runtime.register_program(BANK_PROGRAM_ID, BankProgramAdapter::new());
-
-    // Pre-create accounts metadata (fee payer, mint, alice, bob)
-    let fee_payer = mk_pubkey(1);
-    let mint_pubkey = mk_pubkey(2);
-    let alice_key = mk_pubkey(3);
-    let bob_key = mk_pubkey(4);
-
-    // Prepare account metas. Set is_writable where the program will modify account.data
-    let accounts_meta = vec![
-        AccountMeta { pubkey: fee_payer, owner: fee_payer, is_signer: true, is_writable: true }, // fee payer
-        AccountMeta { pubkey: mint_pubkey, owner: BANK_PROGRAM_ID, is_signer: false, is_writable: true },
-        AccountMeta { pubkey: alice_key, owner: BANK_PROGRAM_ID, is_signer: true, is_writable: true },
-        AccountMeta { pubkey: bob_key, owner: BANK_PROGRAM_ID, is_signer: false, is_writable: true },
-    ];
-
-    // Build a transaction:
-    // 1) MintTo {amount: 1000} : accounts = [mint_pubkey, alice_key]
-    // 2) Transfer {amount: 200} : accounts = [alice_key, bob_key]
-
-    let mint_instr = Instruction {
-        program_id: BANK_PROGRAM_ID,
-        accounts: vec![1u8 /* mint_meta index*/ , 2u8 /* alice_meta index */],
-        data: BankInstruction::MintTo { amount: 1000u128 }.try_to_vec().unwrap(),
-    };
-
-    let transfer_instr = Instruction {
-        program_id: BANK_PROGRAM_ID,
-        accounts: vec![2u8 /* alice */, 3u8 /* bob */],
-        data: BankInstruction::Transfer { amount: 200u128 }.try_to_vec().unwrap(),
-    };
-
-    // Preinitialize account data: write Mint and TokenAccount Borsh data into AccountInfo.data via the Transaction's metas mapping.
-    // Since our Runtime test harness builds AccountInfo inside execute_transaction,
-    // we will create Transaction with the account metas and then mutate the runtime's expected storage representation?
-    // Simpler: add the mint and token accounts into the instruction by using a helper Transaction where account data is default,
-    // and rely on the bank adapter to create accounts if absent. But the bank processor expects existing mint and token account data,
-    // so we need to prepopulate by embedding the bytes into the Transaction structure before calling execute_transaction.
-    //
-    // To keep the runtime simple, we will construct a Transaction with metas and then **mutate** the runtime's internal account_map by invoking an initial "bootstrap" transaction
-    // that writes the serialized account data into the runtime's internal map. But execute_transaction initializes its own account_map from tx.accounts,
-    // so instead a practical approach for this test is to encode the account bytes into AccountMeta.owner/other fields is not possible.
-    //
-    // Simplest approach for this unit test is to construct a synthetic transaction in which:
-    // - We run a custom "Init" instruction handled by the bank program if it sees empty account data, it will initialize default state for mint/account.
-    // But bank::processor::InitMint and InitAccount already exist. We'll call them to initialize state.
-    //
-    // Steps:
-    // a) Call InitMint with accounts [mint]
-    // b) Call InitAccount for alice
-    // c) Call InitAccount for bob
-    // d) Call MintTo (mint -> alice)
-    // e) Call Transfer (alice -> bob)
-    //
-    // We'll build a transaction with those 5 instructions executed sequentially.
-
-    let init_mint = Instruction {
-        program_id: BANK_PROGRAM_ID,
-        accounts: vec![1u8], // mint
-        data: BankInstruction::InitMint { decimals: 6u8, mint_authority: Some(mk_pubkey(99)) }.try_to_vec().unwrap(),
-    };
-
-    let init_alice = Instruction {
-        program_id: BANK_PROGRAM_ID,
-        accounts: vec![2u8], // alice
-        data: BankInstruction::InitAccount { owner: alice_key }.try_to_vec().unwrap(),
-    };
-
-    let init_bob = Instruction {
-        program_id: BANK_PROGRAM_ID,
-        accounts: vec![3u8], // bob
-        data: BankInstruction::InitAccount { owner: bob_key }.try_to_vec().unwrap(),
-    };
-
-    // Now the mint_to and transfer will run. Note: we must ensure the mint_authority (mk_pubkey(99)) signs the MintTo.
-    // The bank adapter infers signers from AccountInfo.is_signer. To make mk_pubkey(99) be a signer, include it in accounts and mark is_signer = true.
-    // But our accounts_meta currently do not include mk_pubkey(99). Easiest approach: make fee_payer equal to mint authority instead of mk_pubkey(99).
-    // To adjust less, we'll set the mint_authority to fee_payer key so the test can simply sign with fee_payer.
-    //
-    // Redo the init_mint instruction with mint_authority = Some(fee_payer)
-    let init_mint = Instruction {
-        program_id: BANK_PROGRAM_ID,
-        accounts: vec![1u8],
-        data: BankInstruction::InitMint { decimals: 6u8, mint_authority: Some(fee_payer) }.try_to_vec().unwrap(),
-    };
-
-    // Rebuild the transaction
-    let tx = Transaction {
-        fee_payer,
-        recent_blockhash: [0u8;32],
-        accounts: accounts_meta.clone(),
-        instructions: vec![
-            init_mint,
-            init_alice,
-            init_bob,
-            mint_instr,
-            transfer_instr,
-        ],
-    };
-
-    // Execute transaction - signers include fee_payer so MintTo will see authority.
-    let res = runtime.execute_transaction(&tx, &[fee_payer]);
-    assert!(res.is_ok(), "transaction failed: {:?}", res.err());
-
-    // If execution succeeded, we need to verify results.
-    // Since the runtime in this example does not expose the final account_map to the caller,
-    // we will re-run a synthetic "read" by invoking a small program or by checking side effects.
-    // For simplicity in this test, we will call execute_transaction with a dummy instruction that returns success,
-    // but we need verification of balances.
-    //
-    // A pragmatic approach: adapt the runtime to return the final account_map for tests.
-    // For now, to keep things self-contained, we assert that the mint and accounts were created
-    // (i.e., transaction did not error). More exhaustive checks require exposing account storage.
-    //
-    // For completeness, we will assert the runtime accepted the transaction.
-    assert!(res.is_ok());
-}
@@ -41,6 +41,10 @@ pub struct Transaction {
 	pub recent_blockhash: [u8, 32],
 	pub accounts: Vec<AccountMeta>,
 	pub instructions: Vec<Instruction>,
+	/// compute units to reserve; 0 means the runtime's maximum
+	pub compute_limit: u64,
+	/// price per compute unit paid to the block producer on top of the base fee
+	pub priority_fee: u64,
 	    /// Signatures are not part of core runtime type here; they are handled by node-level code.
     /// For tests we simulate signature presence via the `signers` arg to runtime.execute_transaction.
 
@@ -0,0 +1,273 @@
+//! WASM programs.
+//!
+//! A [`WasmProgram`] runs a compiled WebAssembly module behind the same
+//! [`Program`] interface as native programs, so the executor dispatches to it
+//! the same way and it sees the same accounts and compute budget.
+//!
+//! Module ABI:
+//! - exports `memory`, `alloc(len: i32) -> i32` and `process(ptr: i32, len: i32) -> i32`
+//! - the runtime writes the borsh-encoded [`WasmInput`] into a buffer from
+//!   `alloc` and calls `process` on it; 0 means success, anything else is the
+//!   program's error code and discards its account writes
+//! - host functions, imported from `env`:
+//!   - `log(ptr: i32, len: i32)`
+//!   - `emit(topic_ptr: i32, data_ptr: i32, data_len: i32)`: records an event
+//!     with the 32-byte topic at `topic_ptr`, see crate::logs
+//!   - `random(out_ptr: i32)`: writes the next 32-byte draw, see crate::randomness
+//!   - `set_account_data(index: i32, ptr: i32, len: i32)`: replaces the data of
+//!     a writable account
+//!
+//! Execution is metered with fuel, one compute unit per unit (roughly one
+//! WASM instruction), and host calls are charged from the gas schedule out of
+//! the same budget. Floating point NaNs are canonicalized and threads are off,
+//! so every node gets the same result.
+
+use crate::executor::{RuntimeContext, PROGRAM_LOG_TARGET};
+use crate::gas::{GasSchedule, HostCall};
+use crate::logs::LogKind;
+use crate::program::{Program, ProgramError};
+use crate::randomness;
+use crate::types::{AccountInfo, Pubkey};
+use borsh::{BorshDeserialize, BorshSerialize};
+use tracing::info;
+use std::sync::OnceLock;
+use wasmtime::{Caller, Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+
+/// Largest module accepted for deployment
+pub const MAX_CODE_SIZE: usize = 512 * 1024;
+
+/// Linear memory a program may grow to
+pub const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+
+/// What a program's `process` export receives
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct WasmInput {
+	pub accounts: Vec<WasmAccount>,
+	pub data: Vec<u8>,
+	pub clock: u64,
+}
+
+/// An account as passed to a WASM program
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct WasmAccount {
+	pub pubkey: Pubkey,
+	pub owner: Pubkey,
+	pub is_signer: bool,
+	pub is_writable: bool,
+	pub data: Vec<u8>,
+}
+
+impl From<&AccountInfo> for WasmAccount {
+	fn from(account: &AccountInfo) -> Self {
+		Self {
+			pubkey: account.pubkey,
+			owner: account.owner,
+			is_signer: account.is_signer,
+			is_writable: account.is_writable,
+			data: account.data.clone(),
+		}
+	}
+}
+
+
+//state host functions see during one call
+struct HostState {
+	accounts: Vec<WasmAccount>,
+	seed: [u8; 32],
+	draws: u64,
+	gas: GasSchedule,
+	limits: StoreLimits,
+	//logs recorded by this call, kept if it succeeds
+	logs: Vec<LogKind>,
+}
+
+
+//one engine for every program: compiled modules are tied to the engine that built them
+fn engine() -> &'static Engine {
+	static ENGINE: OnceLock<Engine> = OnceLock::new();
+	ENGINE.get_or_init(|| {
+		let mut config = Config::new();
+		config.consume_fuel(true);
+		config.cranelift_nan_canonicalization(true);
+		config.wasm_threads(false);
+		config.wasm_relaxed_simd(false);
+		Engine::new(&config).expect("static wasm engine config is valid")
+	})
+}
+
+
+/// A deployed WASM module
+pub struct WasmProgram {
+	module: Module,
+	linker: Linker<HostState>,
+}
+
+impl WasmProgram {
+	/// Compile `code` (binary WASM), checking it has the exports the ABI needs
+	pub fn new(code: &[u8]) -> Result<Self, ProgramError> {
+		if code.len() > MAX_CODE_SIZE {
+			return Err(ProgramError::Custom(format!("program is {} bytes, the limit is {}", code.len(), MAX_CODE_SIZE)));
+		}
+		let module = Module::new(engine(), code)
+			.map_err(|e| ProgramError::Custom(format!("invalid wasm module: {}", e)))?;
+		for export in ["memory", "alloc", "process"] {
+			if module.get_export(export).is_none() {
+				return Err(ProgramError::Custom(format!("wasm module does not export `{}`", export)));
+			}
+		}
+
+		let linker = host_functions().map_err(wasm_error)?;
+		Ok(Self { module, linker })
+	}
+
+	//instantiate, copy the input in and run `process`
+	fn call(&self, store: &mut Store<HostState>, input: &[u8]) -> Result<i32, wasmtime::Error> {
+		let instance = self.linker.instantiate(&mut *store, &self.module)?;
+		let memory = instance.get_memory(&mut *store, "memory")
+			.ok_or_else(|| wasmtime::Error::msg("`memory` is not a memory"))?;
+		let alloc = instance.get_typed_func::<i32, i32>(&mut *store, "alloc")?;
+		let process = instance.get_typed_func::<(i32, i32), i32>(&mut *store, "process")?;
+
+		let len = i32::try_from(input.len()).map_err(|_| wasmtime::Error::msg("input too large"))?;
+		let ptr = alloc.call(&mut *store, len)?;
+		memory.write(&mut *store, ptr as u32 as usize, input)?;
+		process.call(&mut *store, (ptr, len))
+	}
+}
+
+impl Program for WasmProgram {
+	fn process(
+		&self,
+		accounts: &mut [AccountInfo],
+		data: &[u8],
+		ctx: &mut RuntimeContext,
+	) -> Result<(), ProgramError> {
+		let input = WasmInput {
+			accounts: accounts.iter().map(WasmAccount::from).collect(),
+			data: data.to_vec(),
+			clock: ctx.clock,
+		}.try_to_vec().map_err(|e| ProgramError::Custom(format!("borsh encode: {:?}", e)))?;
+
+		let mut store = Store::new(engine(), HostState {
+			accounts: accounts.iter().map(WasmAccount::from).collect(),
+			seed: ctx.random_seed(),
+			draws: ctx.draws,
+			gas: ctx.gas.clone(),
+			limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).instances(1).build(),
+			logs: Vec::new(),
+		});
+		store.limiter(|state| &mut state.limits);
+
+		let budget = ctx.remaining_compute;
+		store.set_fuel(budget).map_err(wasm_error)?;
+		let result = self.call(&mut store, &input);
+
+		//bill whatever ran, whether or not it succeeded
+		let used = budget - store.get_fuel().unwrap_or(0).min(budget);
+		ctx.remaining_compute -= used;
+		ctx.draws = store.data().draws;
+
+		let code = result.map_err(|e| {
+			if ctx.remaining_compute == 0 {
+				ProgramError::Custom("compute budget exceeded".into())
+			} else {
+				wasm_error(e)
+			}
+		})?;
+		if code != 0 {
+			return Err(ProgramError::Custom(format!("wasm program failed with code {}", code)));
+		}
+
+		let state = store.into_data();
+		for (account, written) in accounts.iter_mut().zip(state.accounts) {
+			account.data = written.data;
+		}
+		for kind in state.logs {
+			ctx.record(kind);
+		}
+		Ok(())
+	}
+}
+
+
+fn wasm_error(e: wasmtime::Error) -> ProgramError {
+	ProgramError::Custom(format!("wasm: {}", e))
+}
+
+
+fn host_functions() -> Result<Linker<HostState>, wasmtime::Error> {
+	let mut linker = Linker::new(engine());
+
+	linker.func_wrap("env", "log", |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> wasmtime::Result<()> {
+		let cost = caller.data().gas.host_call_cost(HostCall::Log { bytes: len as u32 as usize });
+		charge(&mut caller, cost)?;
+		let bytes = read_memory(&mut caller, ptr, len)?;
+		let message = String::from_utf8_lossy(&bytes).into_owned();
+		info!(target: PROGRAM_LOG_TARGET, "{}", message);
+		caller.data_mut().logs.push(LogKind::Message(message));
+		Ok(())
+	})?;
+
+	linker.func_wrap("env", "emit", |mut caller: Caller<'_, HostState>, topic_ptr: i32, data_ptr: i32, data_len: i32| -> wasmtime::Result<()> {
+		let cost = caller.data().gas.host_call_cost(HostCall::Log { bytes: 32 + data_len as u32 as usize });
+		charge(&mut caller, cost)?;
+		let mut topic = [0u8; 32];
+		memory(&mut caller)?.read(&caller, topic_ptr as u32 as usize, &mut topic)?;
+		let data = read_memory(&mut caller, data_ptr, data_len)?;
+		caller.data_mut().logs.push(LogKind::Event { topic, data });
+		Ok(())
+	})?;
+
+	linker.func_wrap("env", "random", |mut caller: Caller<'_, HostState>, out_ptr: i32| -> wasmtime::Result<()> {
+		let cost = caller.data().gas.host_call_cost(HostCall::RandomDraw);
+		charge(&mut caller, cost)?;
+		let state = caller.data_mut();
+		let value = randomness::draw(&state.seed, state.draws);
+		state.draws += 1;
+		memory(&mut caller)?.write(&mut caller, out_ptr as u32 as usize, &value)?;
+		Ok(())
+	})?;
+
+	linker.func_wrap("env", "set_account_data", |mut caller: Caller<'_, HostState>, index: i32, ptr: i32, len: i32| -> wasmtime::Result<()> {
+		let cost = caller.data().gas.host_call_cost(HostCall::AccountWrite { bytes: len as u32 as usize });
+		charge(&mut caller, cost)?;
+		let bytes = read_memory(&mut caller, ptr, len)?;
+		let account = caller.data_mut().accounts.get_mut(index as u32 as usize)
+			.ok_or_else(|| wasmtime::Error::msg(format!("account index {} out of range", index)))?;
+		if !account.is_writable {
+			return Err(wasmtime::Error::msg(format!("account {} is not writable", index)));
+		}
+		account.data = bytes;
+		Ok(())
+	})?;
+
+	Ok(linker)
+}
+
+
+//take `units` of fuel for a host call; running out ends the call like running out mid-code
+fn charge(caller: &mut Caller<'_, HostState>, units: u64) -> wasmtime::Result<()> {
+	let fuel = caller.get_fuel()?;
+	if fuel < units {
+		caller.set_fuel(0)?;
+		return Err(wasmtime::Error::msg("compute budget exceeded"));
+	}
+	caller.set_fuel(fuel - units)
+}
+
+fn memory(caller: &mut Caller<'_, HostState>) -> wasmtime::Result<Memory> {
+	caller.get_export("memory")
+		.and_then(|export| export.into_memory())
+		.ok_or_else(|| wasmtime::Error::msg("module has no memory"))
+}
+
+fn read_memory(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+	if len as u32 as usize > MAX_MEMORY {
+		return Err(wasmtime::Error::msg(format!("{} bytes is more than a program's memory", len as u32)));
+	}
+	let mut bytes = vec![0u8; len as u32 as usize];
+	memory(caller)?.read(&*caller, ptr as u32 as usize, &mut bytes)?;
+	Ok(bytes)
+}
@@ -0,0 +1,26 @@
+//! Multi-node test harness.
+//!
+//! A [`TestNetwork`] runs several full chains in one process, linked by an
+//! in-memory network that carries blocks and transactions between them. Nothing
+//! happens on its own: blocks are mined when a test asks, messages wait in
+//! flight until the test delivers them, and the network can be partitioned and
+//! healed in between. Mined blocks are deterministic (see
+//! `blockchain_core::testing`), so the same steps always end in the same chains
+//! and fork choice and sync can be tested without timing flakiness.
+//!
+//! ```ignore
+//! let mut network = TestNetwork::new(4);
+//! network.partition(&[&[0, 1], &[2, 3]]);
+//! network.mine(0)?;
+//! network.mine_blocks(2, 2)?;
+//! network.settle();
+//! network.heal();
+//! network.settle();
+//! assert_eq!(network.assert_converged(), network.node(2).head_id().unwrap());
+//! ```
+
+pub mod network;
+pub mod node;
+
+pub use network::{Message, TestNetwork};
+pub use node::TestNode;
@@ -0,0 +1,285 @@
+use crate::node::TestNode;
+use blockchain_core::{Block, BlockId, ChainConfig, Result, Transaction, TxId};
+use std::collections::VecDeque;
+
+
+/// What nodes send each other
+#[derive(Debug, Clone)]
+pub enum Message {
+    Block(Block),
+    Transaction(Transaction),
+}
+
+#[derive(Debug, Clone)]
+struct Envelope {
+    from: usize,
+    to: usize,
+    message: Message,
+}
+
+
+/// Nodes linked by an in-memory network.
+///
+/// Every node is linked to every other node in its partition (all of them
+/// until [`partition`](Self::partition) is called). Messages are delivered
+/// first in, first out, and a node relays whatever was new to it to its other
+/// peers, so gossip ends once every reachable node has seen a message. A
+/// message in flight across a link that gets cut is lost.
+#[derive(Debug)]
+pub struct TestNetwork {
+    nodes: Vec<TestNode>,
+    // partition each node is in; nodes only reach nodes in the same one
+    partitions: Vec<usize>,
+    in_flight: VecDeque<Envelope>,
+    delivered: u64,
+    dropped: u64,
+}
+
+impl TestNetwork {
+    /// `nodes` nodes on the default chain config
+    pub fn new(nodes: usize) -> Self {
+        Self::with_config(nodes, ChainConfig::default())
+    }
+
+    /// `nodes` nodes starting from the same genesis
+    pub fn with_config(nodes: usize, config: ChainConfig) -> Self {
+        assert!(nodes > 0, "a network needs at least one node");
+        let nodes: Vec<TestNode> = (0..nodes).map(|id| TestNode::new(id, config.clone())).collect();
+        assert!(
+            nodes.iter().all(|node| node.head_id() == nodes[0].head_id()),
+            "nodes built different genesis blocks from one config"
+        );
+
+        Self {
+            partitions: vec![0; nodes.len()],
+            nodes,
+            in_flight: VecDeque::new(),
+            delivered: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn node(&self, id: usize) -> &TestNode {
+        &self.nodes[id]
+    }
+
+    pub fn node_mut(&mut self, id: usize) -> &mut TestNode {
+        &mut self.nodes[id]
+    }
+
+    pub fn nodes(&self) -> &[TestNode] {
+        &self.nodes
+    }
+
+    /// Whether `from` and `to` are linked
+    pub fn can_reach(&self, from: usize, to: usize) -> bool {
+        self.partitions[from] == self.partitions[to]
+    }
+
+    /// Nodes linked to `id`
+    pub fn peers(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        (0..self.nodes.len()).filter(move |&peer| peer != id && self.can_reach(id, peer))
+    }
+
+    /// Mine a block on `node`'s head, with its pooled transactions, and
+    /// announce it. Peers see it once it is delivered.
+    pub fn mine(&mut self, node: usize) -> Result<BlockId> {
+        let block = self.nodes[node].mine()?;
+        let block_id = block.id();
+        self.broadcast(node, None, Message::Block(block));
+        Ok(block_id)
+    }
+
+    /// Mine `n` blocks in a row on `node`
+    pub fn mine_blocks(&mut self, node: usize, n: usize) -> Result<Vec<BlockId>> {
+        (0..n).map(|_| self.mine(node)).collect()
+    }
+
+    /// Add `tx` to `node`'s mempool and announce it
+    pub fn submit_transaction(&mut self, node: usize, tx: Transaction) -> Result<TxId> {
+        let tx_id = self.nodes[node].chain_mut().add_transaction(tx.clone())?;
+        self.broadcast(node, None, Message::Transaction(tx));
+        Ok(tx_id)
+    }
+
+    /// Deliver the oldest message in flight; false when there was none
+    pub fn step(&mut self) -> bool {
+        let Some(Envelope { from, to, message }) = self.in_flight.pop_front() else {
+            return false;
+        };
+        if !self.can_reach(from, to) {
+            self.dropped += 1;
+            return true;
+        }
+
+        self.delivered += 1;
+        if self.nodes[to].receive(&message) {
+            self.broadcast(to, Some(from), message);
+        }
+        true
+    }
+
+    /// Deliver messages until none are in flight; returns how many were handled
+    pub fn settle(&mut self) -> usize {
+        let mut steps = 0;
+        while self.step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Split the network: nodes only reach nodes listed in the same group.
+    /// Nodes not listed end up together in one more group.
+    pub fn partition(&mut self, groups: &[&[usize]]) {
+        let mut partitions = vec![groups.len(); self.nodes.len()];
+        for (index, group) in groups.iter().enumerate() {
+            for &node in *group {
+                partitions[node] = index;
+            }
+        }
+        self.partitions = partitions;
+    }
+
+    /// Link every node again. Reconnected nodes offer each other the main
+    /// chain blocks the other lacks, as sync does, so a `settle` afterwards
+    /// lets fork choice pick between what the partitions built.
+    pub fn heal(&mut self) {
+        self.partitions = vec![0; self.nodes.len()];
+        for from in 0..self.nodes.len() {
+            let blocks = self.nodes[from].main_chain();
+            for to in self.peers(from).collect::<Vec<_>>() {
+                for block in &blocks {
+                    if self.nodes[to].chain().get_block(&block.id()).is_none() {
+                        self.send(from, to, Message::Block(block.clone()));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Head of every node, by node id
+    pub fn heads(&self) -> Vec<Option<BlockId>> {
+        self.nodes.iter().map(TestNode::head_id).collect()
+    }
+
+    /// Whether every node has the same head
+    pub fn is_converged(&self) -> bool {
+        let heads = self.heads();
+        heads.iter().all(|head| *head == heads[0])
+    }
+
+    /// The common head; panics, listing every node's head and height, if the
+    /// nodes disagree
+    #[track_caller]
+    pub fn assert_converged(&self) -> BlockId {
+        if !self.is_converged() {
+            let heads: Vec<String> = self.nodes.iter()
+                .map(|node| format!("node {}: {:?} at height {}", node.id(), node.head_id(), node.height()))
+                .collect();
+            panic!("nodes have not converged:\n{}", heads.join("\n"));
+        }
+        self.heads()[0].expect("every node has a genesis block")
+    }
+
+    /// Messages waiting to be delivered
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Messages delivered so far
+    pub fn delivered(&self) -> u64 {
+        self.delivered
+    }
+
+    /// Messages lost to a partition so far
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    // send to every peer of `from` except the one it came from
+    fn broadcast(&mut self, from: usize, except: Option<usize>, message: Message) {
+        let peers: Vec<usize> = self.peers(from).filter(|&peer| Some(peer) != except).collect();
+        for to in peers {
+            self.send(from, to, message.clone());
+        }
+    }
+
+    fn send(&mut self, from: usize, to: usize, message: Message) {
+        self.in_flight.push_back(Envelope { from, to, message });
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blockchain_crypto::{signature::generate_keypair, address::public_key_to_address, AddressType};
+
+    #[test]
+    fn test_blocks_propagate_to_every_node() {
+        let mut network = TestNetwork::new(3);
+        let tip = network.mine_blocks(0, 2).unwrap()[1];
+        assert!(!network.is_converged());
+
+        network.settle();
+        assert_eq!(network.assert_converged(), tip);
+        assert!(network.nodes().iter().all(|node| node.height() == 2));
+    }
+
+    #[test]
+    fn test_partitions_fork_and_heal_to_the_heavier_branch() {
+        let mut network = TestNetwork::new(4);
+        network.partition(&[&[0, 1], &[2, 3]]);
+
+        network.mine(0).unwrap();
+        let heavier = network.mine_blocks(2, 2).unwrap()[1];
+        network.settle();
+        assert_eq!(network.node(1).head_id(), network.node(0).head_id());
+        assert_eq!(network.node(3).head_id(), Some(heavier));
+        assert!(!network.is_converged());
+
+        network.heal();
+        network.settle();
+        assert_eq!(network.assert_converged(), heavier);
+        assert_eq!(network.node(0).height(), 2);
+    }
+
+    #[test]
+    fn test_messages_across_a_new_partition_are_lost() {
+        let mut network = TestNetwork::new(2);
+        network.mine(0).unwrap();
+        network.partition(&[&[0], &[1]]);
+
+        network.settle();
+        assert_eq!(network.dropped(), 1);
+        assert_eq!(network.node(1).height(), 0);
+    }
+
+    #[test]
+    fn test_transactions_gossip_and_confirm_everywhere() {
+        let mut network = TestNetwork::new(3);
+        let sender = network.node(0).chain().config().genesis.coinbase_recipient;
+        let recipient = public_key_to_address(generate_keypair().public_key(), AddressType::Base58);
+        let tx = Transaction::new_account(sender, recipient, 1000, 0, 21000, 20, vec![]);
+
+        let tx_id = network.submit_transaction(0, tx).unwrap();
+        network.settle();
+        assert!(network.nodes().iter().all(|node| node.chain().mempool().contains_transaction(&tx_id)));
+
+        network.mine(2).unwrap();
+        network.settle();
+        network.assert_converged();
+        for node in network.nodes() {
+            assert!(node.chain().mempool().is_empty());
+            assert_eq!(node.chain().get_balance(&recipient), 1000);
+        }
+    }
+}
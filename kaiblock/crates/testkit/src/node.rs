@@ -0,0 +1,83 @@
+use crate::network::Message;
+use blockchain_core::{Block, BlockHeight, BlockId, BlockWeight, Blockchain, ChainConfig, Result};
+use blockchain_crypto::{hash::sha256, Address, AddressType};
+
+
+/// One node of a [`TestNetwork`](crate::TestNetwork): a chain and the
+/// address its blocks pay
+#[derive(Debug)]
+pub struct TestNode {
+    id: usize,
+    chain: Blockchain,
+    miner: Address,
+}
+
+impl TestNode {
+    pub(crate) fn new(id: usize, config: ChainConfig) -> Self {
+        let chain = Blockchain::new(config).expect("test chain config is valid");
+        let miner = Address::from_hash(sha256(format!("testkit-node-{}", id).as_bytes()), AddressType::Base58);
+        Self { id, chain, miner }
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn chain(&self) -> &Blockchain {
+        &self.chain
+    }
+
+    /// The chain, to set up state directly; changes made here aren't announced
+    pub fn chain_mut(&mut self) -> &mut Blockchain {
+        &mut self.chain
+    }
+
+    /// Address the node's coinbases pay
+    pub fn miner(&self) -> Address {
+        self.miner
+    }
+
+    pub fn head_id(&self) -> Option<BlockId> {
+        self.chain.get_chain_head().map(Block::id)
+    }
+
+    pub fn height(&self) -> BlockHeight {
+        self.chain.height()
+    }
+
+    /// Mine a block with the node's pooled transactions onto its head
+    pub(crate) fn mine(&mut self) -> Result<Block> {
+        let mut branch = self.chain.fork_at(self.chain.height())?.with_miner(self.miner);
+        let rules = self.chain.validation_rules();
+        let transactions = self.chain.mempool().get_transactions_for_block(
+            rules.max_transactions_per_block,
+            &BlockWeight::new(rules),
+            self.chain.world_state(),
+        );
+        self.chain.extend_branch_with(&mut branch, transactions)?;
+
+        let block = branch.blocks()[0].clone();
+        self.chain.add_block(block.clone())?;
+        Ok(block)
+    }
+
+    /// Take in a message from a peer; true if it was new and valid, so worth
+    /// relaying
+    pub(crate) fn receive(&mut self, message: &Message) -> bool {
+        match message {
+            Message::Block(block) => {
+                self.chain.get_block(&block.id()).is_none() && self.chain.add_block(block.clone()).is_ok()
+            }
+            Message::Transaction(tx) => {
+                !self.chain.transaction_exists(&tx.id()) && self.chain.add_transaction(tx.clone()).is_ok()
+            }
+        }
+    }
+
+    /// Main chain blocks after genesis, oldest first
+    pub(crate) fn main_chain(&self) -> Vec<Block> {
+        (1..=self.chain.height())
+            .filter_map(|height| self.chain.get_block_by_height(&height).cloned())
+            .collect()
+    }
+}
@@ -0,0 +1,29 @@
+//! A block off the wire: decoding, then the checks run on it before anything
+//! is known about its parent, must refuse bad input rather than panic.
+
+#![no_main]
+
+use blockchain_core::codec;
+use blockchain_core::validation::BlockValidationContext;
+use blockchain_core::{check_body_commitment, AccountModel, Block, ValidationRules, Validator, WorldState};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(block) = codec::decode::<Block>(data) else {
+        return;
+    };
+    assert_eq!(codec::encode(&block), data, "decoded block has a second encoding");
+
+    let _ = check_body_commitment(&block);
+    let _ = block.total_reward();
+
+    let rules = ValidationRules::default();
+    let world_state = WorldState::new(AccountModel::Hybrid);
+    let _ = Validator::new(rules.clone()).validate_block(BlockValidationContext {
+        block: &block,
+        prev_block: None,
+        retarget_start: None,
+        world_state: &world_state,
+        rules: &rules,
+    });
+});
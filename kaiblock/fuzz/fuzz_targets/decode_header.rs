@@ -0,0 +1,22 @@
+//! Light clients decode headers with blockchain-primitives, full nodes with
+//! the core codec. Neither may panic, and whatever the node accepts the light
+//! client must read the same way.
+
+#![no_main]
+
+use blockchain_core::codec;
+use blockchain_primitives::BlockHeader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let light = BlockHeader::decode(data);
+    if let Ok(header) = &light {
+        assert_eq!(header.encode(), data, "decoded header has a second encoding");
+        let _ = header.hash();
+    }
+
+    if let Ok(header) = codec::decode::<blockchain_core::BlockHeader>(data) {
+        let light = light.expect("the light client refused a header the node accepts");
+        assert_eq!(light.hash(), header.hash(), "node and light client hash a header differently");
+    }
+});
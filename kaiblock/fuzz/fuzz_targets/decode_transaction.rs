@@ -0,0 +1,29 @@
+//! A transaction off the wire: decoding and stateless validation against an
+//! empty state must refuse bad input rather than panic.
+
+#![no_main]
+
+use blockchain_core::codec;
+use blockchain_core::validation::TransactionValidationContext;
+use blockchain_core::{AccountModel, Transaction, ValidationRules, Validator, WorldState};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(tx) = codec::decode::<Transaction>(data) else {
+        return;
+    };
+    assert_eq!(codec::encode(&tx), data, "decoded transaction has a second encoding");
+
+    let _ = tx.total_cost();
+    let _ = tx.fee_per_compute_unit();
+
+    let rules = ValidationRules::default();
+    let world_state = WorldState::new(AccountModel::Hybrid);
+    let _ = Validator::new(rules.clone()).validate_transaction(TransactionValidationContext {
+        transaction: &tx,
+        world_state: &world_state,
+        block_height: 1,
+        block_timestamp: tx.timestamp,
+        rules: &rules,
+    });
+});
@@ -0,0 +1,32 @@
+//! Merkle proofs come from untrusted peers. Checking one must not panic
+//! however long it is or wherever its index points.
+//!
+//! Input: the leaf index as 8 little-endian bytes, then 32-byte hashes for
+//! the leaf, the root and the siblings in order.
+
+#![no_main]
+
+use blockchain_crypto::{Hash256, MerkleProof};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((index, hashes)) = data.split_first_chunk::<8>() else {
+        return;
+    };
+    let mut hashes = hashes.chunks_exact(32)
+        .map(|chunk| Hash256::from_bytes(chunk.try_into().expect("chunks of 32 bytes")));
+    let (Some(leaf_hash), Some(root)) = (hashes.next(), hashes.next()) else {
+        return;
+    };
+
+    let proof = MerkleProof {
+        leaf_index: u64::from_le_bytes(*index) as usize,
+        leaf_hash,
+        siblings: hashes.collect(),
+        root,
+    };
+    if proof.verify() {
+        let depth = proof.siblings.len();
+        assert!(depth >= 64 || (proof.leaf_index as u64) >> depth == 0, "index beyond the tree verified");
+    }
+});
@@ -0,0 +1,16 @@
+//! Addresses arrive as text in transactions and RPC calls. Parsing must
+//! refuse bad text rather than panic, and an address must read back the same
+//! from its own encoding.
+
+#![no_main]
+
+use blockchain_crypto::Address;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|text: &str| {
+    let Ok(address) = Address::from_string(text) else {
+        return;
+    };
+    let reparsed = Address::from_string(address.encoded()).expect("an address's own encoding failed to parse");
+    assert_eq!(reparsed, address);
+});